@@ -0,0 +1,61 @@
+//! Prints a bencoded file, like a `.torrent` or a saved tracker response, to inspect it while debugging.
+//!
+//! ```bash
+//! $ cargo run -p bencoder --example bencode-dump -- ./torrents/file_name
+//! $ curl -s "$ANNOUNCE_URL" | cargo run -p bencoder --example bencode-dump -- --json
+//! ```
+//!
+//! It reads the standard input when no file is given. With `--json` it prints every byte as JSON, see
+//! `Bencode::to_json`, otherwise the pretty printed value.
+
+use std::{
+    env, fs,
+    io::{self, Read},
+    process::ExitCode,
+};
+
+use bencoder::bencode::Bencode;
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut path = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                eprintln!("Usage: bencode-dump [--json] [file]");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let data = match &path {
+        Some(path) => fs::read(path),
+        None => {
+            let mut data = vec![];
+            io::stdin().read_to_end(&mut data).map(|_| data)
+        }
+    };
+    let data = match data {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!(
+                "Could not read {}: {}",
+                path.as_deref().unwrap_or("the standard input"),
+                err
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match Bencode::decode(&data) {
+        Ok(bencode) if json => println!("{}", bencode.to_json()),
+        Ok(bencode) => println!("{}", bencode),
+        Err(err) => {
+            eprintln!("Invalid bencode: {:?}", err);
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
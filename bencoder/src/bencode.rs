@@ -1,4 +1,7 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Bencode {
@@ -8,6 +11,10 @@ pub enum Bencode {
     BDict(BTreeMap<Vec<u8>, Bencode>),
 }
 
+/// Maximum nesting of lists and dictionaries accepted when decoding, deeper data is rejected
+/// instead of risking a stack overflow.
+pub const MAX_DEPTH: usize = 64;
+
 #[derive(PartialEq, Eq, Debug)]
 pub enum BencodeError {
     InvalidBencode,
@@ -16,12 +23,24 @@ pub enum BencodeError {
     InvalidBencodeString,
     InvalidBencodeList,
     InvalidBencodeDict,
+    MaxDepthExceeded,
+    NonCanonicalNumber,
+    NonCanonicalString,
+    UnsortedKeys,
+    DuplicateKey,
+    TrailingData,
 }
 
 pub trait ToBencode {
     fn to_bencode(&self) -> Bencode;
 }
 
+impl ToBencode for Bencode {
+    fn to_bencode(&self) -> Bencode {
+        self.clone()
+    }
+}
+
 impl ToBencode for String {
     fn to_bencode(&self) -> Bencode {
         Bencode::BString(self.as_bytes().to_vec())
@@ -77,6 +96,85 @@ impl<T: ToBencode> ToBencode for Vec<T> {
 }
 
 impl Bencode {
+    /// Returns the number, if the bencode is one.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Bencode::BNumber(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns the bytes of the string, if the bencode is one.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Bencode::BString(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the string, if the bencode is one and it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_bytes().and_then(|s| std::str::from_utf8(s).ok())
+    }
+
+    /// Returns the elements of the list, if the bencode is one.
+    pub fn as_list(&self) -> Option<&[Bencode]> {
+        match self {
+            Bencode::BList(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    /// Returns the entries of the dictionary, if the bencode is one.
+    pub fn as_dict(&self) -> Option<&BTreeMap<Vec<u8>, Bencode>> {
+        match self {
+            Bencode::BDict(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// Returns the value stored under `key`, if the bencode is a dictionary that contains it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bencoder::bencode::Bencode;
+    ///
+    /// let bencode = Bencode::decode(b"d6:lengthi10e4:name4:spame").unwrap();
+    ///
+    /// assert_eq!(bencode.get_int("length"), Some(10));
+    /// assert_eq!(bencode.get_str("name"), Some("spam"));
+    /// assert_eq!(bencode.get_str("length"), None);
+    /// ```
+    pub fn get(&self, key: &str) -> Option<&Bencode> {
+        self.as_dict()?.get(key.as_bytes())
+    }
+
+    /// Returns the number stored under `key`, if present and a number.
+    pub fn get_int(&self, key: &str) -> Option<i64> {
+        self.get(key)?.as_int()
+    }
+
+    /// Returns the bytes of the string stored under `key`, if present and a string.
+    pub fn get_bytes(&self, key: &str) -> Option<&[u8]> {
+        self.get(key)?.as_bytes()
+    }
+
+    /// Returns the string stored under `key`, if present and a valid UTF-8 string.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key)?.as_str()
+    }
+
+    /// Returns the list stored under `key`, if present and a list.
+    pub fn get_list(&self, key: &str) -> Option<&[Bencode]> {
+        self.get(key)?.as_list()
+    }
+
+    /// Returns the dictionary stored under `key`, if present and a dictionary.
+    pub fn get_dict(&self, key: &str) -> Option<&BTreeMap<Vec<u8>, Bencode>> {
+        self.get(key)?.as_dict()
+    }
+
     /// Parses a bencoded vec of bytes into a Bencode enum.
     ///
     /// # Example
@@ -96,86 +194,221 @@ impl Bencode {
     ///
     /// assert_eq!(bencode, Bencode::BNumber(123));
     /// ```
+    ///
+    /// # Errors
+    ///
+    /// Truncated or malformed data returns one of the `InvalidBencode` errors, and lists or dictionaries
+    /// nested deeper than `MAX_DEPTH` return `MaxDepthExceeded`. Decoding never panics.
     pub fn decode(data: &[u8]) -> Result<Bencode, BencodeError> {
-        let (bencode, _) = Bencode::do_decode(&data[0..])?;
+        let (bencode, _) = Bencode::do_decode(data, 0, false)?;
         Ok(bencode)
     }
 
-    fn do_decode(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
-        if data.is_empty() {
-            return Err(BencodeError::InvalidBencode);
-        };
-        match data[0] {
-            b'i' => Bencode::decode_number(data),
-            b'l' => Bencode::decode_list(data),
-            b'd' => Bencode::decode_dict(data),
-            b'0'..=b'9' => Bencode::decode_string(data),
-            _ => Err(BencodeError::InvalidBencode),
+    /// Parses bencoded bytes like `decode`, but only accepts the canonical encoding of the value.
+    ///
+    /// Encoding the result with `encode` gives back exactly `data`, so it can be hashed or compared safely.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bencoder::bencode::{Bencode, BencodeError};
+    ///
+    /// assert!(Bencode::decode_strict(b"d1:ai1e1:bi2ee").is_ok());
+    /// assert_eq!(Bencode::decode_strict(b"d1:bi2e1:ai1ee"), Err(BencodeError::UnsortedKeys));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Besides the errors of `decode`:
+    /// - `NonCanonicalNumber` if a number has leading zeros, a plus sign or is a negative zero.
+    /// - `NonCanonicalString` if the length of a string has leading zeros or a plus sign.
+    /// - `UnsortedKeys` if the keys of a dictionary are not sorted by their raw bytes.
+    /// - `DuplicateKey` if a dictionary has the same key twice.
+    /// - `TrailingData` if there are bytes after the value.
+    pub fn decode_strict(data: &[u8]) -> Result<Bencode, BencodeError> {
+        let (bencode, size) = Bencode::do_decode(data, 0, true)?;
+        if size != data.len() {
+            return Err(BencodeError::TrailingData);
+        }
+        Ok(bencode)
+    }
+
+    /// Returns the exact bytes of the value stored under `key` in the dictionary at the start of `data`.
+    ///
+    /// Useful when the original encoding matters, like hashing the `info` dictionary of a torrent,
+    /// since decoding and encoding again drops non-canonical ordering and keys are kept in the tree only once.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bencoder::bencode::Bencode;
+    ///
+    /// let data = b"d4:infod1:bi1e1:ai2ee4:name3:fooe";
+    /// let raw_info = Bencode::raw_value(data, b"info").unwrap();
+    ///
+    /// assert_eq!(raw_info, Some(&b"d1:bi1e1:ai2ee"[..]));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidBencodeDict` if `data` does not start with a dictionary.
+    /// - Any decoding error of the entries of the dictionary, as returned by `decode`.
+    pub fn raw_value<'a>(data: &'a [u8], key: &[u8]) -> Result<Option<&'a [u8]>, BencodeError> {
+        if data.first() != Some(&b'd') {
+            return Err(BencodeError::InvalidBencodeDict);
+        }
+        let mut i = 1;
+        loop {
+            match data.get(i) {
+                Some(b'e') => return Ok(None),
+                Some(_) => {
+                    let (entry_key, size) = Bencode::do_decode(&data[i..], 1, false)?;
+                    i += size;
+                    let (_, size) = Bencode::do_decode(&data[i..], 1, false)?;
+                    if entry_key == Bencode::BString(key.to_vec()) {
+                        return Ok(Some(&data[i..i + size]));
+                    }
+                    i += size;
+                }
+                None => return Err(BencodeError::InvalidBencodeDict),
+            }
         }
     }
 
-    fn decode_string(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
-        let mut i = 0;
-        while data[i] != b':' {
-            i += 1;
+    fn do_decode(
+        data: &[u8],
+        depth: usize,
+        strict: bool,
+    ) -> Result<(Bencode, usize), BencodeError> {
+        match data.first() {
+            Some(b'i') => Bencode::decode_number(data, strict),
+            Some(b'l') => Bencode::decode_list(data, depth + 1, strict),
+            Some(b'd') => Bencode::decode_dict(data, depth + 1, strict),
+            Some(b'0'..=b'9') => Bencode::decode_string(data, strict),
+            _ => Err(BencodeError::InvalidBencode),
         }
+    }
+
+    fn decode_string(data: &[u8], strict: bool) -> Result<(Bencode, usize), BencodeError> {
+        let colon = Bencode::find(data, b':').ok_or(BencodeError::InvalidBencodeString)?;
         let length =
-            std::str::from_utf8(&data[0..i]).map_err(|_| BencodeError::InvalidBencodeString)?;
+            std::str::from_utf8(&data[0..colon]).map_err(|_| BencodeError::InvalidBencodeString)?;
+        if strict && !Bencode::is_canonical_number(length, false) {
+            return Err(BencodeError::NonCanonicalString);
+        }
         let length: usize = length
             .parse()
             .map_err(|_| BencodeError::InvalidBencodeString)?;
 
-        i += 1;
-
-        let string: Vec<u8> = data.iter().skip(i).take(length).cloned().collect();
-        let len = string.len();
+        let start = colon + 1;
+        let end = start
+            .checked_add(length)
+            .ok_or(BencodeError::InvalidBencodeString)?;
+        let string = data
+            .get(start..end)
+            .ok_or(BencodeError::InvalidBencodeString)?;
 
-        Ok((Bencode::BString(string), i + len))
+        Ok((Bencode::BString(string.to_vec()), end))
     }
 
-    fn decode_number(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
-        let mut i = 1;
-
-        while data[i] != b'e' {
-            i += 1;
-        }
+    fn decode_number(data: &[u8], strict: bool) -> Result<(Bencode, usize), BencodeError> {
+        let end = Bencode::find(data, b'e').ok_or(BencodeError::InvalidBencodeNumber)?;
 
         let number =
-            std::str::from_utf8(&data[1..i]).map_err(|_| BencodeError::InvalidBencodeNumber)?;
+            std::str::from_utf8(&data[1..end]).map_err(|_| BencodeError::InvalidBencodeNumber)?;
+        if strict && !Bencode::is_canonical_number(number, true) {
+            return Err(BencodeError::NonCanonicalNumber);
+        }
         let number: i64 = number
             .parse()
             .map_err(|_| BencodeError::InvalidBencodeNumber)?;
 
-        Ok((Bencode::BNumber(number), i + 1))
+        Ok((Bencode::BNumber(number), end + 1))
     }
 
-    fn decode_list(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
+    fn decode_list(
+        data: &[u8],
+        depth: usize,
+        strict: bool,
+    ) -> Result<(Bencode, usize), BencodeError> {
+        if depth > MAX_DEPTH {
+            return Err(BencodeError::MaxDepthExceeded);
+        }
         let mut i = 1;
         let mut list = Vec::new();
-        while data[i] != b'e' {
-            let (value, size) = Bencode::do_decode(&data[i..])?;
-            list.push(value);
-            i += size;
+        loop {
+            match data.get(i) {
+                Some(b'e') => break,
+                Some(_) => {
+                    let (value, size) = Bencode::do_decode(&data[i..], depth, strict)?;
+                    list.push(value);
+                    i += size;
+                }
+                None => return Err(BencodeError::InvalidBencodeList),
+            }
         }
         Ok((Bencode::BList(list), i + 1))
     }
 
-    fn decode_dict(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
+    fn decode_dict(
+        data: &[u8],
+        depth: usize,
+        strict: bool,
+    ) -> Result<(Bencode, usize), BencodeError> {
+        if depth > MAX_DEPTH {
+            return Err(BencodeError::MaxDepthExceeded);
+        }
         let mut i = 1;
         let mut dict = BTreeMap::new();
-        while data[i] != b'e' {
-            let (key, size) = Bencode::do_decode(&data[i..])?;
-            i += size;
-            let (value, size) = Bencode::do_decode(&data[i..])?;
-            i += size;
-            match key {
-                Bencode::BString(key) => dict.insert(key, value),
-                _ => return Err(BencodeError::InvalidBencodeDict),
-            };
+        loop {
+            match data.get(i) {
+                Some(b'e') => break,
+                Some(_) => {
+                    let (key, size) = Bencode::do_decode(&data[i..], depth, strict)?;
+                    i += size;
+                    let (value, size) = Bencode::do_decode(&data[i..], depth, strict)?;
+                    i += size;
+                    let key = match key {
+                        Bencode::BString(key) => key,
+                        _ => return Err(BencodeError::InvalidBencodeDict),
+                    };
+                    if strict {
+                        match dict.keys().next_back() {
+                            Some(last) if *last == key => return Err(BencodeError::DuplicateKey),
+                            Some(last) if *last > key => return Err(BencodeError::UnsortedKeys),
+                            _ => (),
+                        }
+                    }
+                    dict.insert(key, value);
+                }
+                None => return Err(BencodeError::InvalidBencodeDict),
+            }
         }
         Ok((Bencode::BDict(dict), i + 1))
     }
 
+    /// Returns `true` if `number` is written the only way `encode` writes it: without a plus sign,
+    /// without leading zeros and, when `signed`, without a negative zero.
+    fn is_canonical_number(number: &str, signed: bool) -> bool {
+        let digits = match number.strip_prefix('-') {
+            Some(digits) if signed => {
+                if digits == "0" {
+                    return false;
+                }
+                digits
+            }
+            _ => number,
+        };
+        !digits.is_empty()
+            && digits.bytes().all(|b| b.is_ascii_digit())
+            && (digits == "0" || !digits.starts_with('0'))
+    }
+
+    /// Returns the position of the first `byte` in `data`, if any.
+    fn find(data: &[u8], byte: u8) -> Option<usize> {
+        data.iter().position(|b| *b == byte)
+    }
+
     /// Encodes a Bencode enum into a bencoded vec of bytes.
     ///
     /// # Example
@@ -196,51 +429,66 @@ impl Bencode {
     /// assert_eq!(encoded, b"i123e");
     /// ```
     pub fn encode(bencode: &dyn ToBencode) -> Vec<u8> {
-        let bencode = bencode.to_bencode();
-        Bencode::do_encode(&bencode)
-    }
-
-    fn do_encode(bencode: &Bencode) -> Vec<u8> {
-        match bencode {
-            Bencode::BNumber(n) => Bencode::encode_number(*n),
-            Bencode::BString(s) => Bencode::encode_string(s),
-            Bencode::BList(l) => Bencode::encode_list(l),
-            Bencode::BDict(d) => Bencode::encode_dict(d),
-        }
-    }
-
-    fn encode_number(n: i64) -> Vec<u8> {
-        let mut encoded = vec![b'i'];
-        encoded.extend(n.to_string().into_bytes());
-        encoded.push(b'e');
+        let mut encoded = Vec::new();
+        bencode.to_bencode().encode_into(&mut encoded);
         encoded
     }
 
-    fn encode_string(s: &[u8]) -> Vec<u8> {
-        let mut encoded = Vec::new();
-        encoded.extend(s.len().to_string().into_bytes());
-        encoded.push(b':');
-        encoded.extend(s);
-        encoded
+    /// Encodes the value at the end of `buffer`, the only allocations are the ones made by the buffer to grow.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bencoder::bencode::Bencode;
+    ///
+    /// let mut buffer = b"response: ".to_vec();
+    /// Bencode::BList(vec![Bencode::BNumber(1), Bencode::BString(b"spam".to_vec())])
+    ///     .encode_into(&mut buffer);
+    ///
+    /// assert_eq!(buffer, b"response: li1e4:spame");
+    /// ```
+    pub fn encode_into(&self, buffer: &mut Vec<u8>) {
+        // Writing to a Vec can not fail.
+        let _ = self.write_encoded(buffer);
     }
 
-    fn encode_list(l: &[Bencode]) -> Vec<u8> {
-        let mut encoded = vec![b'l'];
-        for bencode in l {
-            encoded.extend(Bencode::do_encode(bencode));
+    /// Encodes the value into `writer` as it goes, without building the encoded bytes first.
+    ///
+    /// Each element is a separate write, so an unbuffered writer like a `TcpStream` should be wrapped in a
+    /// `BufWriter`.
+    ///
+    /// # Errors
+    ///
+    /// - `io::Error` if the writer failed.
+    pub fn encode_to_writer<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.write_encoded(&mut writer)
+    }
+
+    fn write_encoded<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Bencode::BNumber(n) => write!(writer, "i{}e", n),
+            Bencode::BString(s) => Bencode::write_string(writer, s),
+            Bencode::BList(l) => {
+                writer.write_all(b"l")?;
+                for bencode in l {
+                    bencode.write_encoded(writer)?;
+                }
+                writer.write_all(b"e")
+            }
+            Bencode::BDict(d) => {
+                writer.write_all(b"d")?;
+                for (key, value) in d {
+                    Bencode::write_string(writer, key)?;
+                    value.write_encoded(writer)?;
+                }
+                writer.write_all(b"e")
+            }
         }
-        encoded.push(b'e');
-        encoded
     }
 
-    fn encode_dict(d: &BTreeMap<Vec<u8>, Bencode>) -> Vec<u8> {
-        let mut encoded = vec![b'd'];
-        for (key, value) in d {
-            encoded.extend(Bencode::do_encode(&key.to_bencode()));
-            encoded.extend(Bencode::do_encode(value));
-        }
-        encoded.push(b'e');
-        encoded
+    fn write_string<W: Write + ?Sized>(writer: &mut W, s: &[u8]) -> io::Result<()> {
+        write!(writer, "{}:", s.len())?;
+        writer.write_all(s)
     }
 }
 
@@ -388,6 +636,246 @@ mod tests {
         assert_eq!(Bencode::decode(data).unwrap(), Bencode::BDict(dict));
     }
 
+    #[test]
+    fn test_decode_truncated_string() {
+        assert_eq!(
+            Bencode::decode(b"4:sp"),
+            Err(BencodeError::InvalidBencodeString)
+        );
+        assert_eq!(
+            Bencode::decode(b"4"),
+            Err(BencodeError::InvalidBencodeString)
+        );
+    }
+
+    #[test]
+    fn test_decode_string_with_huge_length() {
+        assert_eq!(
+            Bencode::decode(b"18446744073709551615:a"),
+            Err(BencodeError::InvalidBencodeString)
+        );
+    }
+
+    #[test]
+    fn test_decode_truncated_number() {
+        assert_eq!(
+            Bencode::decode(b"i42"),
+            Err(BencodeError::InvalidBencodeNumber)
+        );
+        assert_eq!(
+            Bencode::decode(b"i"),
+            Err(BencodeError::InvalidBencodeNumber)
+        );
+    }
+
+    #[test]
+    fn test_decode_truncated_list() {
+        assert_eq!(Bencode::decode(b"l"), Err(BencodeError::InvalidBencodeList));
+        assert_eq!(
+            Bencode::decode(b"l4:spam"),
+            Err(BencodeError::InvalidBencodeList)
+        );
+    }
+
+    #[test]
+    fn test_decode_truncated_dict() {
+        assert_eq!(Bencode::decode(b"d"), Err(BencodeError::InvalidBencodeDict));
+        assert_eq!(
+            Bencode::decode(b"d3:foo"),
+            Err(BencodeError::InvalidBencode)
+        );
+        assert_eq!(
+            Bencode::decode(b"d3:fooi1e"),
+            Err(BencodeError::InvalidBencodeDict)
+        );
+    }
+
+    #[test]
+    fn test_decode_nesting_up_to_max_depth() {
+        let mut data = vec![b'l'; MAX_DEPTH];
+        data.extend(vec![b'e'; MAX_DEPTH]);
+        assert!(Bencode::decode(&data).is_ok());
+    }
+
+    #[test]
+    fn test_decode_nesting_deeper_than_max_depth() {
+        let mut data = vec![b'l'; MAX_DEPTH + 1];
+        data.extend(vec![b'e'; MAX_DEPTH + 1]);
+        assert_eq!(Bencode::decode(&data), Err(BencodeError::MaxDepthExceeded));
+
+        let data = vec![b'l'; 1_000_000];
+        assert_eq!(Bencode::decode(&data), Err(BencodeError::MaxDepthExceeded));
+    }
+
+    /// Small xorshift generator so the fuzz tests are deterministic without extra dependencies.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_fuzz_decode_random_bytes_does_not_panic() {
+        let alphabet = b"ilde0123456789:-x";
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..20_000 {
+            let len = (rng.next() % 32) as usize;
+            let data: Vec<u8> = (0..len)
+                .map(|_| alphabet[(rng.next() % alphabet.len() as u64) as usize])
+                .collect();
+            let _ = Bencode::decode(&data);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_decode_truncated_and_mutated_valid_data_does_not_panic() {
+        let mut dict = BTreeMap::new();
+        dict.insert(
+            b"announce".to_vec(),
+            Bencode::BString(b"http://a/b".to_vec()),
+        );
+        dict.insert(
+            b"list".to_vec(),
+            Bencode::BList(vec![
+                Bencode::BNumber(-42),
+                Bencode::BString(b"spam".to_vec()),
+            ]),
+        );
+        let valid = Bencode::encode(&dict);
+        assert!(Bencode::decode(&valid).is_ok());
+
+        for end in 0..valid.len() {
+            assert!(Bencode::decode(&valid[..end]).is_err());
+        }
+
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..20_000 {
+            let mut data = valid.clone();
+            let position = (rng.next() % data.len() as u64) as usize;
+            data[position] = rng.next() as u8;
+            let _ = Bencode::decode(&data);
+        }
+    }
+
+    #[test]
+    fn test_raw_value_keeps_original_bytes() {
+        let data = b"d8:announce3:url4:infod6:lengthi1e4:name1:a5:extra0:ee";
+
+        assert_eq!(
+            Bencode::raw_value(data, b"info").unwrap(),
+            Some(&b"d6:lengthi1e4:name1:a5:extra0:e"[..])
+        );
+        assert_eq!(
+            Bencode::raw_value(data, b"announce").unwrap(),
+            Some(&b"3:url"[..])
+        );
+    }
+
+    #[test]
+    fn test_raw_value_missing_key() {
+        assert_eq!(Bencode::raw_value(b"d1:ai1ee", b"info"), Ok(None));
+    }
+
+    #[test]
+    fn test_raw_value_invalid_data() {
+        assert_eq!(
+            Bencode::raw_value(b"i1e", b"info"),
+            Err(BencodeError::InvalidBencodeDict)
+        );
+        assert_eq!(
+            Bencode::raw_value(b"d1:a", b"info"),
+            Err(BencodeError::InvalidBencode)
+        );
+        assert_eq!(
+            Bencode::raw_value(b"d1:ai1e", b"info"),
+            Err(BencodeError::InvalidBencodeDict)
+        );
+    }
+
+    #[test]
+    fn test_typed_accessors() {
+        let bencode =
+            Bencode::decode(b"d4:dictd1:ai1ee3:int1:x4:listli1ee3:numi-5e3:raw1:\xff3:str4:spame")
+                .unwrap();
+
+        assert_eq!(bencode.get_int("num"), Some(-5));
+        assert_eq!(bencode.get_int("int"), None);
+        assert_eq!(bencode.get_str("str"), Some("spam"));
+        assert_eq!(bencode.get_str("raw"), None);
+        assert_eq!(bencode.get_bytes("raw"), Some(&[0xff][..]));
+        assert_eq!(bencode.get_list("list"), Some(&[Bencode::BNumber(1)][..]));
+        assert_eq!(
+            bencode.get("dict").and_then(|dict| dict.get_int("a")),
+            Some(1)
+        );
+        assert!(bencode.get_dict("dict").is_some());
+        assert_eq!(bencode.get("missing"), None);
+        assert_eq!(Bencode::BNumber(1).get("num"), None);
+    }
+
+    #[test]
+    fn test_decode_strict_accepts_canonical_data() {
+        let data = b"d4:listli-3ei0ee3:num0:5:zzzzzi10ee";
+
+        let bencode = Bencode::decode_strict(data).unwrap();
+
+        assert_eq!(Bencode::encode(&bencode), data);
+    }
+
+    #[test]
+    fn test_decode_strict_rejects_non_canonical_numbers() {
+        for data in [&b"i03e"[..], b"i-0e", b"i+3e", b"i-03e", b"i00e"] {
+            assert_eq!(
+                Bencode::decode_strict(data),
+                Err(BencodeError::NonCanonicalNumber)
+            );
+            assert!(Bencode::decode(data).is_ok());
+        }
+        assert_eq!(
+            Bencode::decode_strict(b"i-e"),
+            Err(BencodeError::NonCanonicalNumber)
+        );
+    }
+
+    #[test]
+    fn test_decode_strict_rejects_non_canonical_strings() {
+        assert_eq!(
+            Bencode::decode_strict(b"04:spam"),
+            Err(BencodeError::NonCanonicalString)
+        );
+        assert!(Bencode::decode_strict(b"0:").is_ok());
+    }
+
+    #[test]
+    fn test_decode_strict_rejects_unsorted_and_duplicate_keys() {
+        assert_eq!(
+            Bencode::decode_strict(b"d1:bi1e1:ai2ee"),
+            Err(BencodeError::UnsortedKeys)
+        );
+        assert_eq!(
+            Bencode::decode_strict(b"d1:ai1e1:ai2ee"),
+            Err(BencodeError::DuplicateKey)
+        );
+        assert_eq!(
+            Bencode::decode_strict(b"ld1:bi1e1:ai2eee"),
+            Err(BencodeError::UnsortedKeys)
+        );
+        assert!(Bencode::decode(b"d1:bi1e1:ai2ee").is_ok());
+    }
+
+    #[test]
+    fn test_decode_strict_rejects_trailing_data() {
+        assert_eq!(
+            Bencode::decode_strict(b"i1ei2e"),
+            Err(BencodeError::TrailingData)
+        );
+    }
+
     #[test]
     fn test_encode_string() {
         let data = String::from("spam");
@@ -441,4 +929,39 @@ mod tests {
         let data: Vec<String> = vec![];
         assert_eq!(Bencode::encode(&data), b"le");
     }
+
+    #[test]
+    fn test_encode_into_appends_to_the_buffer() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"cow".to_vec(), Bencode::BString(b"moo".to_vec()));
+        dict.insert(b"spam".to_vec(), Bencode::BList(vec![Bencode::BNumber(-3)]));
+        let bencode = Bencode::BDict(dict);
+        let mut buffer = b"xx".to_vec();
+
+        bencode.encode_into(&mut buffer);
+
+        assert_eq!(buffer, b"xxd3:cow3:moo4:spamli-3eee");
+        assert_eq!(&buffer[2..], Bencode::encode(&bencode));
+    }
+
+    #[test]
+    fn test_encode_to_writer() {
+        let bencode = Bencode::BList(vec![
+            Bencode::BString(b"spam".to_vec()),
+            Bencode::BNumber(42),
+        ]);
+        let mut writer = io::Cursor::new(Vec::new());
+
+        bencode.encode_to_writer(&mut writer).unwrap();
+
+        assert_eq!(writer.into_inner(), b"l4:spami42ee");
+    }
+
+    #[test]
+    fn test_encode_to_writer_returns_the_writer_error() {
+        let bencode = Bencode::BString(b"spam".to_vec());
+        let mut buffer = [0; 3];
+
+        assert!(bencode.encode_to_writer(&mut buffer[..]).is_err());
+    }
 }
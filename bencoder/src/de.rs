@@ -0,0 +1,593 @@
+use std::{collections::btree_map, fmt, vec};
+
+use serde::de::{
+    self, Deserialize, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+    SeqAccess, VariantAccess, Visitor,
+};
+
+use crate::{bencode::Bencode, error::SerdeError};
+
+/// Deserializes a value from bencoded bytes.
+///
+/// Missing `Option` fields deserialize to `None` and unknown keys are ignored.
+/// Strings deserialize into `String` only if they are valid UTF-8, binary data should use `Vec<u8>`.
+///
+/// # Example
+///
+/// ```rust
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize, Debug, PartialEq)]
+/// struct Response {
+///     interval: u32,
+///     #[serde(rename = "failure reason")]
+///     failure_reason: Option<String>,
+/// }
+///
+/// let response: Response = bencoder::from_bytes(b"d8:intervali1800e5:extrai0ee").unwrap();
+///
+/// assert_eq!(response, Response { interval: 1800, failure_reason: None });
+/// ```
+///
+/// # Errors
+///
+/// - `Decode` if the data is not valid bencode.
+/// - `InvalidType` if a value does not have the bencode type the target expects.
+/// - `NumberOutOfRange` if a number does not fit in the target integer type.
+/// - `Message` for missing fields and other errors raised by the `Deserialize` implementation.
+pub fn from_bytes<T: DeserializeOwned>(data: &[u8]) -> Result<T, SerdeError> {
+    let bencode = Bencode::decode(data).map_err(SerdeError::Decode)?;
+    from_bencode(bencode)
+}
+
+/// Deserializes a value from an already decoded `Bencode` tree.
+///
+/// # Errors
+///
+/// The same as `from_bytes`, except for `Decode`.
+pub fn from_bencode<T: DeserializeOwned>(bencode: Bencode) -> Result<T, SerdeError> {
+    T::deserialize(Deserializer(bencode))
+}
+
+/// Deserializer that hands out the values of a `Bencode` tree.
+struct Deserializer(Bencode);
+
+impl Deserializer {
+    fn invalid_type(&self, expected: &'static str) -> SerdeError {
+        let found = match self.0 {
+            Bencode::BNumber(_) => "number",
+            Bencode::BString(_) => "string",
+            Bencode::BList(_) => "list",
+            Bencode::BDict(_) => "dictionary",
+        };
+        SerdeError::InvalidType {
+            expected,
+            found: found.to_string(),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Bencode::BNumber(n) => visitor.visit_i64(n),
+            Bencode::BString(s) => match String::from_utf8(s) {
+                Ok(s) => visitor.visit_string(s),
+                Err(err) => visitor.visit_byte_buf(err.into_bytes()),
+            },
+            Bencode::BList(l) => visitor.visit_seq(ListAccess(l.into_iter())),
+            Bencode::BDict(d) => visitor.visit_map(DictAccess {
+                entries: d.into_iter(),
+                value: None,
+            }),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Bencode::BNumber(0) => visitor.visit_bool(false),
+            Bencode::BNumber(1) => visitor.visit_bool(true),
+            Bencode::BNumber(_) => Err(SerdeError::NumberOutOfRange),
+            _ => Err(self.invalid_type("a boolean")),
+        }
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Bencode::BNumber(n) => visitor.visit_i64(n),
+            _ => Err(self.invalid_type("a number")),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(SerdeError::UnsupportedType("floats"))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(SerdeError::UnsupportedType("floats"))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Bencode::BString(s) => match String::from_utf8(s) {
+                Ok(s) => visitor.visit_string(s),
+                Err(_) => Err(SerdeError::InvalidType {
+                    expected: "a UTF-8 string",
+                    found: "binary string".to_string(),
+                }),
+            },
+            _ => Err(self.invalid_type("a string")),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Bencode::BString(s) => visitor.visit_byte_buf(s),
+            _ => Err(self.invalid_type("a string")),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Lists deserialize element by element, strings deserialize byte by byte so `Vec<u8>` fields accept them.
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Bencode::BList(l) => visitor.visit_seq(ListAccess(l.into_iter())),
+            Bencode::BString(s) => visitor.visit_seq(s.into_deserializer()),
+            _ => Err(self.invalid_type("a list")),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Bencode::BDict(d) => visitor.visit_map(DictAccess {
+                entries: d.into_iter(),
+                value: None,
+            }),
+            _ => Err(self.invalid_type("a dictionary")),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    /// Unit variants are strings, the rest are dictionaries with the variant name as their only key.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Bencode::BString(variant) => visitor.visit_enum(Variant {
+                name: variant,
+                value: None,
+            }),
+            Bencode::BDict(d) if d.len() == 1 => {
+                let (name, value) = d.into_iter().next().ok_or(SerdeError::InvalidType {
+                    expected: "an enum",
+                    found: "empty dictionary".to_string(),
+                })?;
+                visitor.visit_enum(Variant {
+                    name,
+                    value: Some(value),
+                })
+            }
+            _ => Err(self.invalid_type("an enum")),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+}
+
+struct ListAccess(vec::IntoIter<Bencode>);
+
+impl<'de> SeqAccess<'de> for ListAccess {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.0
+            .next()
+            .map(|value| seed.deserialize(Deserializer(value)))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct DictAccess {
+    entries: btree_map::IntoIter<Vec<u8>, Bencode>,
+    value: Option<Bencode>,
+}
+
+impl<'de> MapAccess<'de> for DictAccess {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Deserializer(Bencode::BString(key)))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let value = self
+            .value
+            .take()
+            .ok_or_else(|| SerdeError::Message("value requested before its key".to_string()))?;
+        seed.deserialize(Deserializer(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct Variant {
+    name: Vec<u8>,
+    value: Option<Bencode>,
+}
+
+impl<'de> EnumAccess<'de> for Variant {
+    type Error = SerdeError;
+    type Variant = VariantValue;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant = seed.deserialize(Deserializer(Bencode::BString(self.name)))?;
+        Ok((variant, VariantValue(self.value)))
+    }
+}
+
+struct VariantValue(Option<Bencode>);
+
+impl VariantValue {
+    fn into_deserializer(self) -> Result<Deserializer, SerdeError> {
+        self.0.map(Deserializer).ok_or(SerdeError::InvalidType {
+            expected: "a variant with a value",
+            found: "unit variant".to_string(),
+        })
+    }
+}
+
+impl<'de> VariantAccess<'de> for VariantValue {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self.into_deserializer()?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        de::Deserializer::deserialize_seq(self.into_deserializer()?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        de::Deserializer::deserialize_map(self.into_deserializer()?, visitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Bencode {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(BencodeVisitor)
+    }
+}
+
+struct BencodeVisitor;
+
+impl<'de> Visitor<'de> for BencodeVisitor {
+    type Value = Bencode;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a bencode value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(Bencode::BNumber(v as i64))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Bencode::BNumber(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        i64::try_from(v)
+            .map(Bencode::BNumber)
+            .map_err(|_| E::custom("number out of range"))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Bencode::BString(v.as_bytes().to_vec()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(Bencode::BString(v.into_bytes()))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(Bencode::BString(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(Bencode::BString(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut list = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            list.push(value);
+        }
+        Ok(Bencode::BList(list))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut dict = std::collections::BTreeMap::new();
+        while let Some(key) = map.next_key::<Bencode>()? {
+            let key = match key {
+                Bencode::BString(key) => key,
+                _ => return Err(de::Error::custom("dictionary keys must be strings")),
+            };
+            dict.insert(key, map.next_value()?);
+        }
+        Ok(Bencode::BDict(dict))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Info {
+        name: String,
+        #[serde(rename = "piece length")]
+        piece_length: u32,
+        pieces: Vec<u8>,
+        private: Option<bool>,
+        #[serde(default)]
+        files: Vec<File>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct File {
+        length: u64,
+        path: Vec<String>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Event {
+        Started,
+        Stopped { reason: String },
+        Moved(u16),
+    }
+
+    #[test]
+    fn test_deserialize_struct() {
+        let data = b"d5:filesld6:lengthi3e4:pathl3:dir1:beee4:name1:a12:piece lengthi2e6:pieces2:\xff\x004:salti7ee";
+
+        let info: Info = from_bytes(data).unwrap();
+
+        assert_eq!(
+            info,
+            Info {
+                name: "a".to_string(),
+                piece_length: 2,
+                pieces: vec![0xff, 0x00],
+                private: None,
+                files: vec![File {
+                    length: 3,
+                    path: vec!["dir".to_string(), "b".to_string()],
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn test_deserialize_missing_field() {
+        let result: Result<Info, _> = from_bytes(b"d4:name1:ae");
+
+        assert!(matches!(result, Err(SerdeError::Message(_))));
+    }
+
+    #[test]
+    fn test_deserialize_invalid_type() {
+        let result: Result<File, _> = from_bytes(b"d6:length1:x4:pathlee");
+
+        assert_eq!(
+            result,
+            Err(SerdeError::InvalidType {
+                expected: "a number",
+                found: "string".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_deserialize_number_out_of_range() {
+        assert!(from_bytes::<u8>(b"i256e").is_err());
+        assert!(from_bytes::<u32>(b"i-1e").is_err());
+        assert_eq!(from_bytes::<u16>(b"i6881e").unwrap(), 6881);
+    }
+
+    #[test]
+    fn test_deserialize_invalid_utf8_string() {
+        assert!(from_bytes::<String>(b"1:\xff").is_err());
+        assert_eq!(from_bytes::<Vec<u8>>(b"1:\xff").unwrap(), vec![0xff]);
+    }
+
+    #[test]
+    fn test_deserialize_enum_variants() {
+        assert_eq!(from_bytes::<Event>(b"7:Started").unwrap(), Event::Started);
+        assert_eq!(
+            from_bytes::<Event>(b"d7:Stoppedd6:reason3:byeee").unwrap(),
+            Event::Stopped {
+                reason: "bye".to_string()
+            }
+        );
+        assert_eq!(
+            from_bytes::<Event>(b"d5:Movedi7ee").unwrap(),
+            Event::Moved(7)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_map() {
+        let map: HashMap<String, i64> = from_bytes(b"d1:ai1e1:bi2ee").unwrap();
+
+        assert_eq!(map.get("a"), Some(&1));
+        assert_eq!(map.get("b"), Some(&2));
+    }
+
+    #[test]
+    fn test_deserialize_invalid_bencode() {
+        assert_eq!(
+            from_bytes::<i64>(b"i42"),
+            Err(SerdeError::Decode(
+                crate::bencode::BencodeError::InvalidBencodeNumber
+            ))
+        );
+    }
+
+    #[test]
+    fn test_deserialize_bencode_value() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"num".to_vec(), Bencode::BNumber(-2));
+        dict.insert(
+            b"list".to_vec(),
+            Bencode::BList(vec![Bencode::BString(vec![0xff])]),
+        );
+
+        assert_eq!(
+            from_bytes::<Bencode>(b"d4:listl1:\xffe3:numi-2ee").unwrap(),
+            Bencode::BDict(dict)
+        );
+    }
+}
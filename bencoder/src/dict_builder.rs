@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+
+use crate::bencode::{Bencode, ToBencode};
+
+/// Builds a bencoded dictionary one entry at a time.
+///
+/// Entries are kept sorted by key, as bencode requires.
+///
+/// # Example
+///
+/// ```rust
+/// use bencoder::{bencode::Bencode, dict_builder::BencodeDictBuilder};
+///
+/// let warning: Option<String> = None;
+/// let dict = BencodeDictBuilder::new()
+///     .insert(b"interval", &1800)
+///     .insert_optional(b"warning message", warning.as_ref())
+///     .insert(b"complete", &2)
+///     .build();
+///
+/// assert_eq!(Bencode::encode(&dict), b"d8:completei2e8:intervali1800ee");
+/// ```
+#[derive(Debug, Default)]
+pub struct BencodeDictBuilder {
+    dict: BTreeMap<Vec<u8>, Bencode>,
+}
+
+impl BencodeDictBuilder {
+    /// Creates a builder for an empty dictionary.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the entry, replacing any previous value with the same key.
+    pub fn insert(mut self, key: &[u8], value: &dyn ToBencode) -> Self {
+        self.dict.insert(key.to_vec(), value.to_bencode());
+        self
+    }
+
+    /// Adds the entry only if the value is present.
+    pub fn insert_optional<T: ToBencode>(self, key: &[u8], value: Option<&T>) -> Self {
+        match value {
+            Some(value) => self.insert(key, value),
+            None => self,
+        }
+    }
+
+    /// Returns the dictionary built.
+    pub fn build(self) -> Bencode {
+        Bencode::BDict(self.dict)
+    }
+}
+
+impl ToBencode for BencodeDictBuilder {
+    fn to_bencode(&self) -> Bencode {
+        Bencode::BDict(self.dict.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_sorts_keys_and_skips_missing_values() {
+        let dict = BencodeDictBuilder::new()
+            .insert(b"port", &6881)
+            .insert(b"ip", &"127.0.0.1".to_string())
+            .insert_optional::<String>(b"peer id", None)
+            .build();
+
+        assert_eq!(Bencode::encode(&dict), b"d2:ip9:127.0.0.14:porti6881ee");
+    }
+
+    #[test]
+    fn test_insert_replaces_value() {
+        let dict = BencodeDictBuilder::new()
+            .insert(b"a", &1)
+            .insert(b"a", &2)
+            .build();
+
+        assert_eq!(dict.get_int("a"), Some(2));
+    }
+}
@@ -0,0 +1,49 @@
+use std::fmt::{self, Display};
+
+use crate::bencode::BencodeError;
+
+/// Posible errors when converting between Rust values and bencode with serde.
+#[derive(PartialEq, Eq, Debug)]
+pub enum SerdeError {
+    /// The data is not valid bencode.
+    Decode(BencodeError),
+    /// Bencode has no representation for the type, like floats or maps with non string keys.
+    UnsupportedType(&'static str),
+    /// The number does not fit in the integer type of bencode or of the target field.
+    NumberOutOfRange,
+    /// The bencode type does not match the expected one.
+    InvalidType {
+        expected: &'static str,
+        found: String,
+    },
+    /// A custom error raised by a `Serialize` or `Deserialize` implementation.
+    Message(String),
+}
+
+impl Display for SerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerdeError::Decode(err) => write!(f, "invalid bencode: {:?}", err),
+            SerdeError::UnsupportedType(ty) => write!(f, "bencode does not support {}", ty),
+            SerdeError::NumberOutOfRange => write!(f, "number out of range"),
+            SerdeError::InvalidType { expected, found } => {
+                write!(f, "invalid type: expected {}, found {}", expected, found)
+            }
+            SerdeError::Message(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SerdeError {}
+
+impl serde::ser::Error for SerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        SerdeError::Message(msg.to_string())
+    }
+}
+
+impl serde::de::Error for SerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        SerdeError::Message(msg.to_string())
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt::{self, Write};
+
+use crate::bencode::Bencode;
+
+/// Bytes of a binary string shown by the pretty printer, the rest are left out.
+const SHOWN_BINARY_BYTES: usize = 16;
+
+impl Bencode {
+    /// Converts the value to compact JSON, to inspect torrents and tracker responses with the usual JSON tools.
+    ///
+    /// Strings that are valid UTF-8 become JSON strings. Binary strings, like the `pieces` of a torrent or compact
+    /// peers, become an object with their bytes in hex: `{"hex":"0a1b"}`. Dictionary keys that are not valid UTF-8
+    /// are written as `hex:` followed by their bytes in hex.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bencoder::bencode::Bencode;
+    ///
+    /// let bencode = Bencode::decode(b"d3:bar2:\xff\x004:spaml1:ai42eee").unwrap();
+    ///
+    /// assert_eq!(bencode.to_json(), r#"{"bar":{"hex":"ff00"},"spam":["a",42]}"#);
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        // Writing to a String can not fail.
+        let _ = write_json(&mut json, self);
+        json
+    }
+}
+
+fn write_json(out: &mut String, bencode: &Bencode) -> fmt::Result {
+    match bencode {
+        Bencode::BNumber(n) => write!(out, "{}", n),
+        Bencode::BString(s) => match std::str::from_utf8(s) {
+            Ok(s) => write_json_string(out, s),
+            Err(_) => write!(out, "{{\"hex\":\"{}\"}}", hex(s)),
+        },
+        Bencode::BList(l) => {
+            out.push('[');
+            for (i, bencode) in l.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(out, bencode)?;
+            }
+            out.push(']');
+            Ok(())
+        }
+        Bencode::BDict(d) => {
+            out.push('{');
+            for (i, (key, value)) in d.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json_string(out, &key_string(key))?;
+                out.push(':');
+                write_json(out, value)?;
+            }
+            out.push('}');
+            Ok(())
+        }
+    }
+}
+
+/// Writes `s` quoted, escaping the characters JSON does not allow in a string.
+fn write_json_string<W: Write>(out: &mut W, s: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+/// Returns a dictionary key as text, see `Bencode::to_json`.
+fn key_string(key: &[u8]) -> String {
+    match std::str::from_utf8(key) {
+        Ok(key) => key.to_string(),
+        Err(_) => format!("hex:{}", hex(key)),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Pretty prints the value indented like JSON, one element per line.
+///
+/// Binary strings are shown as their length and their first bytes in hex, like `<60 bytes: 0a1b...>`, so the
+/// pieces of a torrent do not flood the output. Use `Bencode::to_json` to get every byte.
+impl fmt::Display for Bencode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_pretty(f, self, 0)
+    }
+}
+
+fn write_pretty(f: &mut fmt::Formatter<'_>, bencode: &Bencode, depth: usize) -> fmt::Result {
+    match bencode {
+        Bencode::BNumber(n) => write!(f, "{}", n),
+        Bencode::BString(s) => match std::str::from_utf8(s) {
+            Ok(s) => write_json_string(f, s),
+            Err(_) if s.len() <= SHOWN_BINARY_BYTES => {
+                write!(f, "<{} bytes: {}>", s.len(), hex(s))
+            }
+            Err(_) => write!(
+                f,
+                "<{} bytes: {}...>",
+                s.len(),
+                hex(&s[..SHOWN_BINARY_BYTES])
+            ),
+        },
+        Bencode::BList(l) if l.is_empty() => f.write_str("[]"),
+        Bencode::BList(l) => {
+            f.write_str("[\n")?;
+            for (i, bencode) in l.iter().enumerate() {
+                write_indent(f, depth + 1)?;
+                write_pretty(f, bencode, depth + 1)?;
+                f.write_str(if i + 1 < l.len() { ",\n" } else { "\n" })?;
+            }
+            write_indent(f, depth)?;
+            f.write_str("]")
+        }
+        Bencode::BDict(d) if d.is_empty() => f.write_str("{}"),
+        Bencode::BDict(d) => {
+            f.write_str("{\n")?;
+            for (i, (key, value)) in d.iter().enumerate() {
+                write_indent(f, depth + 1)?;
+                write_json_string(f, &key_string(key))?;
+                f.write_str(": ")?;
+                write_pretty(f, value, depth + 1)?;
+                f.write_str(if i + 1 < d.len() { ",\n" } else { "\n" })?;
+            }
+            write_indent(f, depth)?;
+            f.write_str("}")
+        }
+    }
+}
+
+fn write_indent(f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+    for _ in 0..depth {
+        f.write_str("  ")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json_escapes_strings() {
+        let bencode = Bencode::BList(vec![
+            Bencode::BString(b"say \"hi\"\n".to_vec()),
+            Bencode::BNumber(-3),
+            Bencode::BList(vec![]),
+        ]);
+
+        assert_eq!(bencode.to_json(), r#"["say \"hi\"\n",-3,[]]"#);
+    }
+
+    #[test]
+    fn test_to_json_non_utf8_key() {
+        let bencode = Bencode::decode(b"d2:\xff\xfei1ee").unwrap();
+
+        assert_eq!(bencode.to_json(), r#"{"hex:fffe":1}"#);
+    }
+
+    #[test]
+    fn test_pretty_print() {
+        let mut data = b"d8:announce4:http4:infod6:lengthi10e6:pieces20:".to_vec();
+        data.extend([0xff; 20]);
+        data.extend(b"e4:listle5:peers2:\xff\x00e");
+        let bencode = Bencode::decode(&data).unwrap();
+
+        assert_eq!(
+            bencode.to_string(),
+            r#"{
+  "announce": "http",
+  "info": {
+    "length": 10,
+    "pieces": <20 bytes: ffffffffffffffffffffffffffffffff...>
+  },
+  "list": [],
+  "peers": <2 bytes: ff00>
+}"#
+        );
+    }
+}
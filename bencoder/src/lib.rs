@@ -1 +1,11 @@
 pub mod bencode;
+pub mod bytes;
+pub mod de;
+pub mod dict_builder;
+pub mod error;
+pub mod json;
+pub mod ser;
+
+pub use de::{from_bencode, from_bytes};
+pub use error::SerdeError;
+pub use ser::{to_bencode, to_bytes};
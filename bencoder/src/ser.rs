@@ -0,0 +1,513 @@
+use std::collections::BTreeMap;
+
+use serde::ser::{self, Serialize};
+
+use crate::{bencode::Bencode, error::SerdeError};
+
+/// Serializes a value into bencoded bytes.
+///
+/// Struct fields and map entries that are `None` or `()` are left out, since bencode has no null value.
+/// Byte vectors are encoded as lists of numbers unless the field uses `#[serde(with = "bencoder::bytes")]`.
+///
+/// # Example
+///
+/// ```rust
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Peer {
+///     ip: String,
+///     port: u16,
+///     #[serde(rename = "peer id")]
+///     peer_id: Option<String>,
+/// }
+///
+/// let peer = Peer { ip: "127.0.0.1".to_string(), port: 6881, peer_id: None };
+///
+/// assert_eq!(bencoder::to_bytes(&peer).unwrap(), b"d2:ip9:127.0.0.14:porti6881ee");
+/// ```
+///
+/// # Errors
+///
+/// - `UnsupportedType` if the value contains floats, a `None` outside of a struct or map,
+///   or a map with keys that are not strings.
+/// - `NumberOutOfRange` if an unsigned number does not fit in an `i64`.
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, SerdeError> {
+    let bencode = to_bencode(value)?;
+    Ok(Bencode::encode(&bencode))
+}
+
+/// Serializes a value into a `Bencode` tree.
+///
+/// # Errors
+///
+/// The same as `to_bytes`.
+pub fn to_bencode<T: Serialize + ?Sized>(value: &T) -> Result<Bencode, SerdeError> {
+    value
+        .serialize(Serializer)?
+        .ok_or(SerdeError::UnsupportedType("null values"))
+}
+
+/// Serializer that builds a `Bencode` tree, returning `None` for values bencode can not represent on its own.
+struct Serializer;
+
+fn required(value: Option<Bencode>) -> Result<Bencode, SerdeError> {
+    value.ok_or(SerdeError::UnsupportedType("null values inside lists"))
+}
+
+fn single_entry_dict(key: &'static str, value: Bencode) -> Bencode {
+    let mut dict = BTreeMap::new();
+    dict.insert(key.as_bytes().to_vec(), value);
+    Bencode::BDict(dict)
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Option<Bencode>;
+    type Error = SerdeError;
+    type SerializeSeq = ListSerializer;
+    type SerializeTuple = ListSerializer;
+    type SerializeTupleStruct = ListSerializer;
+    type SerializeTupleVariant = ListSerializer;
+    type SerializeMap = DictSerializer;
+    type SerializeStruct = DictSerializer;
+    type SerializeStructVariant = DictSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Bencode::BNumber(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        let v = i64::try_from(v).map_err(|_| SerdeError::NumberOutOfRange)?;
+        self.serialize_i64(v)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
+        Err(SerdeError::UnsupportedType("floats"))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
+        Err(SerdeError::UnsupportedType("floats"))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Bencode::BString(v.to_vec())))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        let value = required(value.serialize(Serializer)?)?;
+        Ok(Some(single_entry_dict(variant, value)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(ListSerializer {
+            list: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Ok(ListSerializer {
+            list: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(DictSerializer {
+            dict: BTreeMap::new(),
+            next_key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Ok(DictSerializer {
+            dict: BTreeMap::new(),
+            next_key: None,
+            variant: Some(variant),
+        })
+    }
+}
+
+/// Builds a `BList`, wrapped in a dictionary keyed by the variant name for tuple variants.
+struct ListSerializer {
+    list: Vec<Bencode>,
+    variant: Option<&'static str>,
+}
+
+impl ListSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.list.push(required(value.serialize(Serializer)?)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Bencode>, SerdeError> {
+        let list = Bencode::BList(self.list);
+        Ok(Some(match self.variant {
+            Some(variant) => single_entry_dict(variant, list),
+            None => list,
+        }))
+    }
+}
+
+impl ser::SerializeSeq for ListSerializer {
+    type Ok = Option<Bencode>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for ListSerializer {
+    type Ok = Option<Bencode>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for ListSerializer {
+    type Ok = Option<Bencode>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for ListSerializer {
+    type Ok = Option<Bencode>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+/// Builds a `BDict`, wrapped in a dictionary keyed by the variant name for struct variants.
+///
+/// Keys are sorted by the `BTreeMap`, as bencode requires.
+struct DictSerializer {
+    dict: BTreeMap<Vec<u8>, Bencode>,
+    next_key: Option<Vec<u8>>,
+    variant: Option<&'static str>,
+}
+
+impl DictSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: Vec<u8>, value: &T) -> Result<(), SerdeError> {
+        if let Some(value) = value.serialize(Serializer)? {
+            self.dict.insert(key, value);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Bencode>, SerdeError> {
+        let dict = Bencode::BDict(self.dict);
+        Ok(Some(match self.variant {
+            Some(variant) => single_entry_dict(variant, dict),
+            None => dict,
+        }))
+    }
+}
+
+impl ser::SerializeMap for DictSerializer {
+    type Ok = Option<Bencode>;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+        match key.serialize(Serializer)? {
+            Some(Bencode::BString(key)) => {
+                self.next_key = Some(key);
+                Ok(())
+            }
+            _ => Err(SerdeError::UnsupportedType(
+                "dictionary keys that are not strings",
+            )),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .next_key
+            .take()
+            .ok_or_else(|| SerdeError::Message("value serialized before its key".to_string()))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for DictSerializer {
+    type Ok = Option<Bencode>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.insert(key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for DictSerializer {
+    type Ok = Option<Bencode>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.insert(key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl Serialize for Bencode {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Bencode::BNumber(n) => serializer.serialize_i64(*n),
+            Bencode::BString(s) => serializer.serialize_bytes(s),
+            Bencode::BList(l) => l.serialize(serializer),
+            Bencode::BDict(d) => {
+                use ser::SerializeMap;
+                let mut map = serializer.serialize_map(Some(d.len()))?;
+                for (key, value) in d {
+                    map.serialize_entry(&crate::bytes::Bytes(key), value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Serialize;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Info {
+        name: String,
+        #[serde(rename = "piece length")]
+        piece_length: i64,
+        #[serde(with = "crate::bytes")]
+        pieces: Vec<u8>,
+        private: Option<bool>,
+        files: Vec<File>,
+    }
+
+    #[derive(Serialize)]
+    struct File {
+        length: u64,
+        path: Vec<String>,
+    }
+
+    #[derive(Serialize)]
+    enum Event {
+        Started,
+        Stopped { reason: String },
+    }
+
+    #[test]
+    fn test_serialize_struct_sorts_keys_and_skips_none() {
+        let info = Info {
+            name: "a".to_string(),
+            piece_length: 2,
+            pieces: vec![0xff, 0x00],
+            private: None,
+            files: vec![File {
+                length: 3,
+                path: vec!["dir".to_string(), "b".to_string()],
+            }],
+        };
+
+        assert_eq!(
+            to_bytes(&info).unwrap(),
+            b"d5:filesld6:lengthi3e4:pathl3:dir1:beee4:name1:a12:piece lengthi2e6:pieces2:\xff\x00e"
+        );
+    }
+
+    #[test]
+    fn test_serialize_bool_and_some() {
+        assert_eq!(to_bytes(&Some(true)).unwrap(), b"i1e");
+    }
+
+    #[test]
+    fn test_serialize_enum_variants() {
+        assert_eq!(to_bytes(&Event::Started).unwrap(), b"7:Started");
+        assert_eq!(
+            to_bytes(&Event::Stopped {
+                reason: "bye".to_string()
+            })
+            .unwrap(),
+            b"d7:Stoppedd6:reason3:byeee"
+        );
+    }
+
+    #[test]
+    fn test_serialize_map() {
+        let mut map = HashMap::new();
+        map.insert("b", 2);
+        map.insert("a", 1);
+
+        assert_eq!(to_bytes(&map).unwrap(), b"d1:ai1e1:bi2ee");
+    }
+
+    #[test]
+    fn test_serialize_unsupported_values() {
+        assert_eq!(to_bytes(&1.5), Err(SerdeError::UnsupportedType("floats")));
+        assert_eq!(to_bytes(&u64::MAX), Err(SerdeError::NumberOutOfRange));
+        assert!(to_bytes(&vec![None, Some(1)]).is_err());
+
+        let mut map = HashMap::new();
+        map.insert(1, 1);
+        assert!(to_bytes(&map).is_err());
+    }
+
+    #[test]
+    fn test_serialize_bencode_value() {
+        let data = b"d4:listli1e3:fooe3:numi-2ee";
+        let bencode = Bencode::decode(data).unwrap();
+
+        assert_eq!(to_bytes(&bencode).unwrap(), data);
+    }
+}
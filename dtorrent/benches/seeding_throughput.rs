@@ -0,0 +1,103 @@
+//! Measures the 16 KiB blocks per second served to peers from a completed torrent by several threads at once,
+//! reading the file for each block and reading the blocks from the mapped file.
+//!
+//! Run it with `cargo bench --package dtorrent`.
+
+#[cfg(unix)]
+fn main() {
+    seeding::main();
+}
+
+#[cfg(not(unix))]
+fn main() {
+    println!("The mapped file storage is only available on Unix");
+}
+
+#[cfg(unix)]
+mod seeding {
+    use std::{
+        fs,
+        sync::Arc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use dtorrent::{
+        config::cfg::Cfg,
+        storage_manager::{file_storage::FileStorage, mmap_storage::MmapStorage, storage::Storage},
+        torrent_parser::{info::Info, torrent::Torrent},
+    };
+    use url_encoder::info_hash::InfoHash;
+
+    const DIRECTORY: &str = "./bench_seeding_throughput";
+    const THREADS: usize = 8;
+    const REQUESTS_PER_THREAD: usize = 50_000;
+    const BLOCK_LENGTH: usize = 16 * 1024;
+    const TORRENT_LENGTH: usize = 64 * 1024 * 1024;
+
+    pub fn main() {
+        let torrent = Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![],
+            http_seeds: vec![],
+            info_hash_v2: None,
+            piece_layer: vec![],
+            info: Info {
+                length: TORRENT_LENGTH as i64,
+                name: "data".to_string(),
+                piece_length: BLOCK_LENGTH as i64,
+                pieces: vec![],
+                private: None,
+                meta_version: None,
+                file_tree: Default::default(),
+            },
+            info_hash: InfoHash::new([0; 20]),
+        };
+        let config = Cfg {
+            download_directory: DIRECTORY.to_string(),
+            ..Cfg::default()
+        };
+
+        let file = FileStorage::new(&torrent, &config);
+        file.write_piece(0, &vec![1; TORRENT_LENGTH])
+            .expect("the bench file can be written");
+        file.complete().expect("the bench file can be completed");
+        let mmap = MmapStorage::new(&torrent, &config);
+        mmap.complete().expect("the bench file can be mapped");
+
+        let read = blocks_per_second(Arc::new(file));
+        let mapped = blocks_per_second(Arc::new(mmap));
+        fs::remove_dir_all(DIRECTORY).expect("the bench directory can be removed");
+
+        println!("File reads: {:.0} blocks/s", read);
+        println!("Mapped file: {:.0} blocks/s", mapped);
+        println!("Speedup: {:.2}x", mapped / read);
+    }
+
+    fn blocks_per_second(storage: Arc<dyn Storage>) -> f64 {
+        let blocks = TORRENT_LENGTH / BLOCK_LENGTH;
+
+        let start = Instant::now();
+        let handles: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let storage = storage.clone();
+                thread::spawn(move || {
+                    for request in 0..REQUESTS_PER_THREAD {
+                        // Spreads the requests over the torrent like the peers of a large swarm would.
+                        let block =
+                            (thread * REQUESTS_PER_THREAD + request).wrapping_mul(7919) % blocks;
+                        storage
+                            .read_block((block * BLOCK_LENGTH) as u64, BLOCK_LENGTH)
+                            .expect("the blocks of the bench file can be read");
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().expect("seeding thread panicked");
+        }
+
+        let elapsed: Duration = start.elapsed();
+        (THREADS * REQUESTS_PER_THREAD) as f64 / elapsed.as_secs_f64()
+    }
+}
@@ -0,0 +1,222 @@
+//! Simulates a swarm over loopback: a few seeders serve a synthetic torrent to several leechers, which also
+//! serve each other the pieces they already have. Reports the time each leecher took and the throughput of the swarm.
+//!
+//! Run it with `cargo bench --package dtorrent --bench swarm_throughput`.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddrV4, TcpListener, TcpStream},
+    path::Path,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use dtorrent::{
+    bt_server::server::BtServer,
+    config::cfg::Cfg,
+    peer::peer_message::Bitfield,
+    torrent_handler::{handler::TorrentHandler, status::AtomicTorrentStatus},
+    torrent_parser::{info::Info, torrent::Torrent},
+};
+use sha1::{Digest, Sha1};
+use url_encoder::info_hash::InfoHash;
+
+const SEEDERS: usize = 2;
+const LEECHERS: usize = 4;
+const TORRENT_LENGTH: usize = 256 * 1024 * 1024;
+const PIECE_LENGTH: usize = 256 * 1024;
+/// The handler announces again as soon as it ran out of peers to connect, later announces are delayed
+/// so the leechers do not spend the benchmark announcing.
+const REANNOUNCE_DELAY: Duration = Duration::from_millis(200);
+
+fn main() {
+    let directory = env::temp_dir().join("dtorrent_bench_swarm_throughput");
+    let _ = fs::remove_dir_all(&directory);
+
+    let (torrent, data) = generated_torrent();
+    let addresses: Vec<SocketAddrV4> = (0..SEEDERS + LEECHERS)
+        .map(|_| SocketAddrV4::new([127, 0, 0, 1].into(), free_port()))
+        .collect();
+    let announce_url = start_tracker(&addresses);
+    let torrent = Torrent {
+        announce_url,
+        ..torrent
+    };
+
+    for (seeder, address) in addresses.iter().take(SEEDERS).enumerate() {
+        let config = peer_config(&directory.join(format!("seeder_{}", seeder)), address);
+        let status = AtomicTorrentStatus::new(&torrent, config.clone());
+        complete(&status, &torrent, &data);
+        start_server(
+            &torrent,
+            Arc::new(status),
+            config,
+            peer_id("seeder", seeder),
+        );
+    }
+    println!(
+        "{} seeders, {} leechers, {} MiB torrent in {} KiB pieces",
+        SEEDERS,
+        LEECHERS,
+        TORRENT_LENGTH / (1024 * 1024),
+        PIECE_LENGTH / 1024
+    );
+
+    let start = Instant::now();
+    let leechers: Vec<_> = addresses
+        .iter()
+        .skip(SEEDERS)
+        .enumerate()
+        .map(|(leecher, address)| {
+            let config = peer_config(&directory.join(format!("leecher_{}", leecher)), address);
+            let mut handler =
+                TorrentHandler::new(torrent.clone(), config.clone(), peer_id("leecher", leecher));
+            handler.set_client_port(address.port());
+            start_server(
+                &torrent,
+                handler.status(),
+                config,
+                peer_id("leecher", leecher),
+            );
+            thread::spawn(move || {
+                handler.handle().expect("the leecher downloads the torrent");
+                start.elapsed()
+            })
+        })
+        .collect();
+    for (leecher, handle) in leechers.into_iter().enumerate() {
+        let elapsed = handle.join().expect("leecher thread panicked");
+        println!(
+            "Leecher {} finished in {:.2}s",
+            leecher,
+            elapsed.as_secs_f64()
+        );
+    }
+
+    let elapsed = start.elapsed();
+    let downloaded = (LEECHERS * TORRENT_LENGTH) as f64 / (1024.0 * 1024.0);
+    println!(
+        "Swarm throughput: {:.1} MiB/s",
+        downloaded / elapsed.as_secs_f64()
+    );
+    fs::remove_dir_all(&directory).expect("the bench directory can be removed");
+}
+
+/// Returns a torrent of generated bytes with its piece hashes, and the bytes.
+fn generated_torrent() -> (Torrent, Vec<u8>) {
+    let data: Vec<u8> = (0..TORRENT_LENGTH)
+        .map(|byte| (byte * 31 % 251) as u8)
+        .collect();
+    let pieces = data
+        .chunks(PIECE_LENGTH)
+        .flat_map(|piece| Sha1::digest(piece).to_vec())
+        .collect();
+
+    let torrent = Torrent {
+        announce_url: String::new(),
+        announce_list: vec![],
+        http_seeds: vec![],
+        info_hash_v2: None,
+        piece_layer: vec![],
+        info: Info {
+            length: TORRENT_LENGTH as i64,
+            name: "swarm_throughput".to_string(),
+            piece_length: PIECE_LENGTH as i64,
+            pieces,
+            private: None,
+            meta_version: None,
+            file_tree: Default::default(),
+        },
+        info_hash: InfoHash::new(Sha1::digest(b"swarm_throughput").into()),
+    };
+    (torrent, data)
+}
+
+fn peer_config(directory: &Path, address: &SocketAddrV4) -> Cfg {
+    Cfg {
+        tcp_port: address.port(),
+        tcp_port_max: address.port(),
+        download_directory: directory.display().to_string(),
+        ..Cfg::default()
+    }
+}
+
+fn peer_id(role: &str, peer: usize) -> String {
+    format!("-DT0001-{:0>12}", format!("{}{}", role, peer))
+}
+
+/// Marks every piece as downloaded with its part of `data`.
+fn complete(status: &AtomicTorrentStatus, torrent: &Torrent, data: &[u8]) {
+    let all_pieces = Bitfield::new(vec![0xff; (torrent.total_pieces() as usize).div_ceil(8)]);
+    while let Some(index) = status
+        .select_piece(&all_pieces)
+        .expect("the seeder status can select pieces")
+    {
+        let start = index as usize * PIECE_LENGTH;
+        let end = (start + PIECE_LENGTH).min(data.len());
+        status
+            .piece_downloaded(index, &data[start..end])
+            .expect("the seeder can write its pieces");
+    }
+}
+
+fn start_server(torrent: &Torrent, status: Arc<AtomicTorrentStatus>, config: Cfg, peer_id: String) {
+    let mut server = BtServer::new(
+        HashMap::from([(torrent.info_hash(), status)]),
+        config,
+        peer_id,
+    );
+    server.bind().expect("the peer can listen");
+    thread::spawn(move || server.init());
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("a free port can be found")
+        .port()
+}
+
+/// Starts an HTTP tracker that answers every announce with all the peers of the swarm and returns its announce url.
+fn start_tracker(peers: &[SocketAddrV4]) -> String {
+    let compact: Vec<u8> = peers
+        .iter()
+        .flat_map(|peer| [&peer.ip().octets()[..], &peer.port().to_be_bytes()].concat())
+        .collect();
+    let mut body = format!("d8:intervali60e5:peers{}:", compact.len()).into_bytes();
+    body.extend(compact);
+    body.push(b'e');
+    let body = Arc::new(body);
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("the tracker can listen");
+    let address = listener.local_addr().expect("the tracker has an address");
+    thread::spawn(move || {
+        for (announce, stream) in listener.incoming().flatten().enumerate() {
+            let body = body.clone();
+            thread::spawn(move || {
+                if announce >= LEECHERS {
+                    thread::sleep(REANNOUNCE_DELAY);
+                }
+                answer_announce(stream, &body);
+            });
+        }
+    });
+    format!("http://{}/announce", address)
+}
+
+fn answer_announce(mut stream: TcpStream, body: &[u8]) {
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+        line.clear();
+    }
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(body);
+}
@@ -0,0 +1,109 @@
+use clap::{Parser, Subcommand};
+use dtorrent::control::{command::ControlCommand, DEFAULT_CONTROL_SOCKET};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use url_encoder::info_hash::InfoHash;
+
+/// Manages a dtorrent running with `--daemon` through its control socket.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Path of the control socket of the daemon.
+    #[arg(long, default_value = DEFAULT_CONTROL_SOCKET)]
+    socket: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Starts the torrent of a `.torrent` file.
+    Add { file: PathBuf },
+    /// Lists the torrents with their state and progress.
+    List,
+    /// Pauses a torrent, given by its hex info hash.
+    Pause { info_hash: String },
+    /// Resumes a paused torrent, given by its hex info hash.
+    Resume { info_hash: String },
+    /// Removes a torrent, given by its hex info hash, announcing it stopped to its trackers.
+    Remove {
+        info_hash: String,
+        /// Also deletes the downloaded data of the torrent.
+        #[arg(long)]
+        delete_data: bool,
+    },
+    /// Prints the magnet link of a torrent, given by its hex info hash, to share it.
+    Magnet { info_hash: String },
+    /// Stops the daemon.
+    Quit,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let command = match control_command(args.command) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    send(&args.socket, &command)
+}
+
+/// Converts the command of the arguments, the path of `add` is made absolute as the daemon may run elsewhere.
+fn control_command(command: Command) -> Result<ControlCommand, String> {
+    let info_hash =
+        |hex: &str| InfoHash::from_hex(hex).map_err(|_| format!("Invalid info hash: {}", hex));
+    Ok(match command {
+        Command::Add { file } => ControlCommand::Add(
+            std::fs::canonicalize(&file)
+                .map_err(|err| format!("Could not find {}: {}", file.display(), err))?,
+        ),
+        Command::List => ControlCommand::List,
+        Command::Pause { info_hash: hex } => ControlCommand::Pause(info_hash(&hex)?),
+        Command::Resume { info_hash: hex } => ControlCommand::Resume(info_hash(&hex)?),
+        Command::Remove {
+            info_hash: hex,
+            delete_data,
+        } => ControlCommand::Remove {
+            info_hash: info_hash(&hex)?,
+            delete_data,
+        },
+        Command::Magnet { info_hash: hex } => ControlCommand::Magnet(info_hash(&hex)?),
+        Command::Quit => ControlCommand::Quit,
+    })
+}
+
+#[cfg(unix)]
+fn send(socket: &std::path::Path, command: &ControlCommand) -> ExitCode {
+    use dtorrent::control::{client::send_command, response::ControlResponse};
+
+    match send_command(socket, command) {
+        Ok(ControlResponse::Ok(lines)) => {
+            for line in lines {
+                println!("{}", line);
+            }
+            ExitCode::SUCCESS
+        }
+        Ok(ControlResponse::Error(message)) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!(
+                "Could not connect to the daemon at {}: {}",
+                socket.display(),
+                err
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn send(_: &std::path::Path, _: &ControlCommand) -> ExitCode {
+    eprintln!(
+        "The control socket needs Unix domain sockets, which are not supported on this platform"
+    );
+    ExitCode::FAILURE
+}
@@ -1,23 +1,35 @@
 use crate::config::cfg::Cfg;
 use crate::peer::bt_peer::{BtPeer, BtPeerError};
+use crate::peer::connection_direction::ConnectionDirection;
+use crate::peer::handshake::HANDSHAKE_LENGTH;
+use crate::peer::peer_message::{Message, MessageId};
 use crate::peer::peer_session::{PeerSession, PeerSessionError};
 use crate::torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError};
 use crate::torrent_parser::torrent::Torrent;
 use std::collections::HashMap;
-use std::net::{TcpListener, TcpStream};
-use std::sync::Arc;
+use std::io::Write;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
 use std::thread;
 use std::time::Duration;
-use tracing::{error, info, warn};
+use tracing::{error, info, info_span, warn};
+use url_encoder::info_hash::InfoHash;
+
+/// Torrents served by a `BtServer` by info hash, shared with the components that add torrents while it runs.
+pub type SharedTorrents = Arc<RwLock<HashMap<InfoHash, Arc<AtomicTorrentStatus>>>>;
 
 /// Struct for handling the server side.
 ///
-/// To create a new `BtServer`, use BtServer::new(torrent, config).
+/// To create a new `BtServer`, use BtServer::new(torrents_with_status, config, client_peer_id).
+///
+/// The torrents are identified by their info hash in bytes, as received in the handshake of the incoming peers.
+/// More torrents can be served while the server runs by adding them to the map returned by `torrents`.
 #[derive(Debug)]
 pub struct BtServer {
     config: Cfg,
-    torrents_with_status: HashMap<Torrent, Arc<AtomicTorrentStatus>>,
+    torrents_with_status: SharedTorrents,
     client_peer_id: String,
+    listener: Option<TcpListener>,
 }
 
 /// Posible BtServer errors.
@@ -31,29 +43,82 @@ pub enum BtServerError {
     TorrentNotFound(String),
     ErrorSettingStreamTimeout,
     MaxPeersConnectedReached(String),
+    MaxGlobalConnectionsReached,
+    PeerBanned(String),
+    PeerAlreadyConnected(String),
+    PeerIsOurself,
+    SeedingStopped(String),
+    TorrentPaused(String),
+    PoisonedTorrentsLock,
 }
 
 impl BtServer {
-    /// Creates a new `BtServer` from a `HashMap` containing a torrent with its `AtomicTorrentStatus` and `Config`.
+    /// Creates a new `BtServer` from a `HashMap` containing the info hash of each torrent with its `AtomicTorrentStatus` and `Config`.
     pub fn new(
-        torrents_with_status: HashMap<Torrent, Arc<AtomicTorrentStatus>>,
+        torrents_with_status: HashMap<InfoHash, Arc<AtomicTorrentStatus>>,
         config: Cfg,
         client_peer_id: String,
     ) -> Self {
         Self {
             config,
-            torrents_with_status,
+            torrents_with_status: Arc::new(RwLock::new(torrents_with_status)),
             client_peer_id,
+            listener: None,
         }
     }
 
+    /// Returns the torrents served, new torrents inserted in the map are served from the next connection.
+    pub fn torrents(&self) -> SharedTorrents {
+        self.torrents_with_status.clone()
+    }
+
+    /// Binds the server to the first free port of the range given by the `tcp_port` and `tcp_port_max` settings.
+    ///
+    /// Returns the bound port, which must be announced to the trackers.
+    ///
+    /// # Errors
+    /// - `OpeningListenerError` if none of the ports of the range could be bound.
+    pub fn bind(&mut self) -> Result<u16, BtServerError> {
+        let mut last_error = None;
+
+        for port in self.config.tcp_ports() {
+            match TcpListener::bind(format!("0.0.0.0:{}", port)) {
+                Ok(listener) => {
+                    let port = listener
+                        .local_addr()
+                        .map_err(BtServerError::OpeningListenerError)?
+                        .port();
+                    self.listener = Some(listener);
+                    return Ok(port);
+                }
+                Err(err) => {
+                    warn!("Couldn't bind port {}: {}", port, err);
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        Err(BtServerError::OpeningListenerError(
+            last_error.unwrap_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::AddrInUse, "No ports to bind")
+            }),
+        ))
+    }
+
     /// Starts the server and starts listening for connections.
     ///
+    /// If the server was not bound yet with `bind()`, it is bound first.
+    ///
     /// # Errors
     /// - `OpeningListenerError` if the TcpLister couldn't be opened.
     pub fn init(&mut self) -> Result<(), BtServerError> {
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", self.config.tcp_port))
-            .map_err(BtServerError::OpeningListenerError)?;
+        if self.listener.is_none() {
+            self.bind()?;
+        }
+        let listener = match self.listener.take() {
+            Some(listener) => listener,
+            None => return Ok(()),
+        };
 
         info!("Server started, listening for connections");
 
@@ -75,8 +140,8 @@ impl BtServer {
             .peer_addr()
             .map_err(BtServerError::HandleConnectionError)?;
 
-        // set timeouts
-        self.set_stream_timeouts(&mut stream)?;
+        // A peer that connects but never sends its handshake only holds the connection for the handshake timeout.
+        self.set_stream_timeouts(&mut stream, self.config.handshake_seconds_timeout)?;
 
         let mut peer = BtPeer::new(addr.ip().to_string(), addr.port() as i64);
 
@@ -85,23 +150,78 @@ impl BtServer {
             BtServerError::BtPeerError(err)
         })?;
 
+        self.set_stream_timeouts(&mut stream, self.config.read_write_seconds_timeout)?;
+
         // See if the torrent is in the list of torrents.
-        let (torrent, torrent_status) = match self.find_torrent_and_status(info_hash) {
-            Ok(value) => value,
-            Err(value) => return value,
-        };
+        let torrent_status = self.find_torrent_status(&info_hash)?;
+        let torrent = &torrent_status.torrent;
+        torrent_status.add_overhead_downloaded_bytes(HANDSHAKE_LENGTH as u64);
+
+        if torrent_status.is_seeding_stopped() {
+            return Err(BtServerError::SeedingStopped(torrent.name()));
+        }
+
+        if torrent_status.is_paused() {
+            return Err(BtServerError::TorrentPaused(torrent.name()));
+        }
+
+        if torrent_status
+            .is_banned(&peer)
+            .map_err(BtServerError::TorrentStatusError)?
+        {
+            return Err(BtServerError::PeerBanned(peer.ip));
+        }
+
+        if let Some(peer_id) = &peer.peer_id {
+            // We dialed ourselves, for example when the tracker returned our own address.
+            if peer_id == self.client_peer_id.as_bytes() {
+                return Err(BtServerError::PeerIsOurself);
+            }
+
+            // Both directions are served by a single connection, so a peer we are already connected to is refused,
+            // unless we are connecting to each other at the same time and this is the connection both sides keep.
+            if !torrent_status
+                .keeps_connection(
+                    peer_id,
+                    ConnectionDirection::Incoming,
+                    self.client_peer_id.as_bytes(),
+                )
+                .map_err(BtServerError::TorrentStatusError)?
+            {
+                return Err(BtServerError::PeerAlreadyConnected(peer.ip));
+            }
+        }
 
         let current_peers = torrent_status.all_current_peers();
         // if we reached the max number of peers, we can't accept any more connections.
-        if current_peers >= self.config.max_peers_per_torrent as usize {
+        if current_peers >= torrent_status.config().max_peers() as usize {
+            self.reject_peer(&mut stream, &mut peer, &torrent_status)?;
             return Err(BtServerError::MaxPeersConnectedReached(torrent.name()));
         }
 
-        let mut peer_session = self.create_peer_session(&peer, torrent, torrent_status)?;
+        // if we reached the max number of peers between all the torrents, the peer is refused until a slot is freed.
+        // The slowest peer is asked to disconnect to make room for the next one, unless the peers already asked to
+        // disconnect free enough slots.
+        let max_global_connections = self.config.runtime.max_global_connections() as usize;
+        let global_peers = self.global_current_peers()?;
+        if global_peers >= max_global_connections {
+            if global_peers.saturating_sub(self.global_pending_disconnects()?)
+                >= max_global_connections
+            {
+                match self.disconnect_slowest_peer() {
+                    Ok(()) | Err(BtServerError::MaxGlobalConnectionsReached) => (),
+                    Err(err) => return Err(err),
+                }
+            }
+            self.reject_peer(&mut stream, &mut peer, &torrent_status)?;
+            return Err(BtServerError::MaxGlobalConnectionsReached);
+        }
+
+        let mut peer_session = self.create_peer_session(&peer, torrent, &torrent_status)?;
 
-        match peer_session.handshake_incoming_leecher(&mut stream) {
+        match peer_session.handshake_incoming(&mut stream) {
             Ok(_) => {
-                self.unchoke_peer(peer_session, peer, stream, torrent.clone(), torrent_status)?;
+                self.unchoke_peer(peer_session, peer, stream, torrent.clone(), &torrent_status)?;
             }
             Err(err) => {
                 warn!("{:?}", err)
@@ -110,25 +230,112 @@ impl BtServer {
         Ok(())
     }
 
-    fn find_torrent_and_status(
+    /// Refuses a peer without room for it: the handshake is completed and the peer is choked before the
+    /// connection is closed, so it knows it was not dropped by a network error. The rejection is counted in the
+    /// status of the torrent.
+    ///
+    /// # Errors
+    /// - `BtPeerError` if the handshake could not be sent.
+    /// - `HandleConnectionError` if the choke message could not be sent.
+    fn reject_peer(
         &self,
-        info_hash: Vec<u8>,
-    ) -> Result<(&Torrent, &Arc<AtomicTorrentStatus>), Result<(), BtServerError>> {
-        let (torrent, torrent_status) =
-            match self.torrents_with_status.iter().find(|(torrent, _)| {
-                match torrent.get_info_hash_as_bytes() {
-                    Ok(info_hash_bytes) => info_hash_bytes == info_hash,
-                    Err(_) => false,
-                }
-            }) {
-                Some((torrent, torrent_status)) => (torrent, torrent_status),
-                None => {
-                    return Err(Err(BtServerError::TorrentNotFound(
-                        String::from_utf8_lossy(&info_hash).to_string(),
-                    )))
+        stream: &mut TcpStream,
+        peer: &mut BtPeer,
+        torrent_status: &AtomicTorrentStatus,
+    ) -> Result<(), BtServerError> {
+        torrent_status.add_rejected_connection();
+        info!(
+            "No room for peer {}:{}, rejecting the connection",
+            peer.ip, peer.port
+        );
+
+        peer.send_handshake(
+            stream,
+            torrent_status.torrent.info_hash(),
+            self.client_peer_id.clone(),
+        )
+        .map_err(BtServerError::BtPeerError)?;
+        let choke = Message::new(MessageId::Choke, vec![]);
+        stream
+            .write_all(&choke.as_bytes())
+            .map_err(BtServerError::HandleConnectionError)?;
+        torrent_status
+            .add_overhead_uploaded_bytes(HANDSHAKE_LENGTH as u64 + choke.overhead_bytes());
+        // The connection is closed when the stream is dropped anyway.
+        let _ = stream.shutdown(Shutdown::Both);
+        Ok(())
+    }
+
+    /// Returns the number of peers connected and connecting between all the torrents.
+    fn global_current_peers(&self) -> Result<usize, BtServerError> {
+        Ok(global_current_peers(&*self.lock_torrents()?))
+    }
+
+    /// Returns the number of peers asked to disconnect between all the torrents whose sessions did not close yet.
+    fn global_pending_disconnects(&self) -> Result<usize, BtServerError> {
+        let mut pending = 0;
+        for torrent_status in self.lock_torrents()?.values() {
+            pending += torrent_status
+                .pending_disconnects()
+                .map_err(BtServerError::TorrentStatusError)?;
+        }
+        Ok(pending)
+    }
+
+    /// Asks the slowest peer between all the torrents to disconnect, skipping the peers already asked to.
+    ///
+    /// # Errors
+    /// - `MaxGlobalConnectionsReached` if there is no connected peer that can be disconnected.
+    /// - `TorrentStatusError` if there was a problem using the `Torrent Status`.
+    /// - `PoisonedTorrentsLock` if the lock on the torrents is poisoned.
+    fn disconnect_slowest_peer(&self) -> Result<(), BtServerError> {
+        let torrents = self.lock_torrents()?;
+        let mut slowest: Option<(BtPeer, f64, &Arc<AtomicTorrentStatus>)> = None;
+
+        for torrent_status in torrents.values() {
+            let peer = torrent_status
+                .slowest_peer()
+                .map_err(BtServerError::TorrentStatusError)?;
+
+            if let Some((peer, speed)) = peer {
+                match &slowest {
+                    Some((_, slowest_speed, _)) if *slowest_speed <= speed => (),
+                    _ => slowest = Some((peer, speed, torrent_status)),
                 }
-            };
-        Ok((torrent, torrent_status))
+            }
+        }
+
+        match slowest {
+            Some((peer, _, torrent_status)) => {
+                info!(
+                    "Max global connections reached, disconnecting peer: {}:{}",
+                    peer.ip, peer.port
+                );
+                torrent_status
+                    .request_peer_disconnect(&peer)
+                    .map_err(BtServerError::TorrentStatusError)
+            }
+            None => Err(BtServerError::MaxGlobalConnectionsReached),
+        }
+    }
+
+    fn find_torrent_status(
+        &self,
+        info_hash: &InfoHash,
+    ) -> Result<Arc<AtomicTorrentStatus>, BtServerError> {
+        self.lock_torrents()?
+            .get(info_hash)
+            .cloned()
+            .ok_or_else(|| BtServerError::TorrentNotFound(info_hash.to_hex()))
+    }
+
+    fn lock_torrents(
+        &self,
+    ) -> Result<RwLockReadGuard<'_, HashMap<InfoHash, Arc<AtomicTorrentStatus>>>, BtServerError>
+    {
+        self.torrents_with_status
+            .read()
+            .map_err(|_| BtServerError::PoisonedTorrentsLock)
     }
 
     fn create_peer_session(
@@ -148,18 +355,18 @@ impl BtServer {
         Ok(peer_session)
     }
 
-    /// Sets read and write timeouts for the stream.
-    fn set_stream_timeouts(&self, stream: &mut TcpStream) -> Result<(), BtServerError> {
+    /// Sets read and write timeouts of `seconds` for the stream.
+    fn set_stream_timeouts(
+        &self,
+        stream: &mut TcpStream,
+        seconds: u64,
+    ) -> Result<(), BtServerError> {
         stream
-            .set_read_timeout(Some(Duration::from_secs(
-                self.config.read_write_seconds_timeout,
-            )))
+            .set_read_timeout(Some(Duration::from_secs(seconds)))
             .map_err(|_| BtServerError::ErrorSettingStreamTimeout)?;
 
         stream
-            .set_write_timeout(Some(Duration::from_secs(
-                self.config.read_write_seconds_timeout,
-            )))
+            .set_write_timeout(Some(Duration::from_secs(seconds)))
             .map_err(|_| BtServerError::ErrorSettingStreamTimeout)?;
         Ok(())
     }
@@ -180,15 +387,16 @@ impl BtServer {
             torrent.info.name, peer_name
         ));
 
-        let join =
-            builder.spawn(
-                move || match peer_session.unchoke_incoming_leecher(&mut stream) {
-                    Ok(_) => (),
-                    Err(err) => {
-                        warn!("{:?}", err);
-                    }
-                },
-            );
+        let span = info_span!("peer", torrent = %torrent.info.name, peer = %peer_name);
+        let join = builder.spawn(move || {
+            let _enter = span.enter();
+            match peer_session.run_incoming(&mut stream) {
+                Ok(_) => (),
+                Err(err) => {
+                    warn!("{:?}", err);
+                }
+            }
+        });
         match join {
             Ok(_) => (),
             Err(err) => {
@@ -198,3 +406,274 @@ impl BtServer {
         Ok(())
     }
 }
+
+/// Returns the number of peers connected and connecting between all the `torrents`.
+pub fn global_current_peers(torrents: &HashMap<InfoHash, Arc<AtomicTorrentStatus>>) -> usize {
+    torrents
+        .values()
+        .map(|torrent_status| torrent_status.all_current_peers())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::torrent_overrides::TorrentOverrides,
+        peer::peer_message::Bitfield,
+        storage_manager::memory_storage::MemoryStorage,
+        test_support::{
+            fixtures::test_config,
+            local_swarm::{add_pieces, generated_torrent, LEECHER_PEER_ID, SEEDER_PEER_ID},
+            temp_dir::TempDir,
+        },
+    };
+
+    #[test]
+    fn test_bind_tries_the_next_port_of_the_range() {
+        let taken = TcpListener::bind("0.0.0.0:0").unwrap();
+        let taken_port = taken.local_addr().unwrap().port();
+
+        let config = Cfg {
+            tcp_port: taken_port,
+            tcp_port_max: taken_port.saturating_add(20),
+            ..Cfg::default()
+        };
+        let mut server = BtServer::new(HashMap::new(), config, "client_peer_id".to_string());
+
+        let port = server.bind().unwrap();
+
+        assert!(port > taken_port);
+        assert!(port <= taken_port.saturating_add(20));
+    }
+
+    #[test]
+    fn test_bind_fails_if_the_only_port_is_taken() {
+        let taken = TcpListener::bind("0.0.0.0:0").unwrap();
+        let taken_port = taken.local_addr().unwrap().port();
+
+        let config = Cfg {
+            tcp_port: taken_port,
+            tcp_port_max: 0,
+            ..Cfg::default()
+        };
+        let mut server = BtServer::new(HashMap::new(), config, "client_peer_id".to_string());
+
+        assert!(matches!(
+            server.bind(),
+            Err(BtServerError::OpeningListenerError(_))
+        ));
+    }
+
+    #[test]
+    fn test_downloads_from_incoming_peer_with_missing_pieces() {
+        let dir = TempDir::new("server_downloads_from_incoming_peer");
+        let config = Cfg {
+            tcp_port: 0,
+            tcp_port_max: 0,
+            ..test_config(&dir)
+        };
+        let (torrent, data) =
+            generated_torrent("incoming", 4 * 16384, 16384, "http://127.0.0.1:1/announce");
+
+        let status = AtomicTorrentStatus::with_storage(
+            &torrent,
+            config.clone(),
+            Box::new(MemoryStorage::new()),
+        );
+        let status = Arc::new(status);
+        let mut server = BtServer::new(
+            HashMap::from([(torrent.info_hash(), status.clone())]),
+            config.clone(),
+            LEECHER_PEER_ID.to_string(),
+        );
+        let port = server.bind().unwrap();
+        thread::spawn(move || server.init());
+
+        let peer_status = AtomicTorrentStatus::with_storage(
+            &torrent,
+            config.clone(),
+            Box::new(MemoryStorage::new()),
+        );
+        add_pieces(&peer_status, &torrent, &data, &Bitfield::new(vec![0xff]));
+        let peer_status = Arc::new(peer_status);
+        peer_status.peer_connecting();
+
+        let mut peer_session = PeerSession::new(
+            BtPeer::new("127.0.0.1".to_string(), port as i64),
+            torrent,
+            peer_status,
+            config,
+            SEEDER_PEER_ID.to_string(),
+        )
+        .unwrap();
+
+        assert!(peer_session.start_outgoing().is_ok());
+        assert!(status.is_finished());
+    }
+
+    #[test]
+    fn test_drops_incoming_connection_from_ourself() {
+        let dir = TempDir::new("server_drops_incoming_connection_from_ourself");
+        let config = Cfg {
+            tcp_port: 0,
+            tcp_port_max: 0,
+            ..test_config(&dir)
+        };
+        let (torrent, _) =
+            generated_torrent("ourself", 4 * 16384, 16384, "http://127.0.0.1:1/announce");
+
+        let status = AtomicTorrentStatus::with_storage(
+            &torrent,
+            config.clone(),
+            Box::new(MemoryStorage::new()),
+        );
+        let status = Arc::new(status);
+        let mut server = BtServer::new(
+            HashMap::from([(torrent.info_hash(), status.clone())]),
+            config.clone(),
+            LEECHER_PEER_ID.to_string(),
+        );
+        let port = server.bind().unwrap();
+        thread::spawn(move || server.init());
+
+        // Dials the server with its own peer id, as when the tracker returns our own address.
+        status.peer_connecting();
+        let mut peer_session = PeerSession::new(
+            BtPeer::new("127.0.0.1".to_string(), port as i64),
+            torrent,
+            status.clone(),
+            config,
+            LEECHER_PEER_ID.to_string(),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            peer_session.start_outgoing(),
+            Err(PeerSessionError::BtPeerError(BtPeerError::HandshakeError))
+        ));
+        assert_eq!(status.current_peers(), 0);
+        assert_eq!(status.all_current_peers(), 0);
+    }
+
+    #[test]
+    fn test_closes_connection_without_handshake_after_the_handshake_timeout() {
+        let dir = TempDir::new("server_closes_connection_without_handshake");
+        let config = Cfg {
+            tcp_port: 0,
+            tcp_port_max: 0,
+            handshake_seconds_timeout: 1,
+            read_write_seconds_timeout: 60,
+            ..test_config(&dir)
+        };
+        let mut server = BtServer::new(HashMap::new(), config, LEECHER_PEER_ID.to_string());
+        let port = server.bind().unwrap();
+        thread::spawn(move || server.init());
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let started = std::time::Instant::now();
+
+        assert_eq!(std::io::Read::read(&mut stream, &mut [0; 1]).unwrap(), 0);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_rejects_peer_with_a_choke_when_max_peers_reached() {
+        let dir = TempDir::new("server_rejects_peer_when_max_peers_reached");
+        let config = Cfg {
+            tcp_port: 0,
+            tcp_port_max: 0,
+            ..test_config(&dir)
+        };
+        let (torrent, _) =
+            generated_torrent("rejected", 4 * 16384, 16384, "http://127.0.0.1:1/announce");
+
+        let status_config = Cfg {
+            overrides: TorrentOverrides {
+                max_peers_per_torrent: Some(1),
+                ..TorrentOverrides::default()
+            },
+            ..config.clone()
+        };
+        let status = AtomicTorrentStatus::with_storage(
+            &torrent,
+            status_config,
+            Box::new(MemoryStorage::new()),
+        );
+        let status = Arc::new(status);
+        status.peer_connecting();
+        let mut server = BtServer::new(
+            HashMap::from([(torrent.info_hash(), status.clone())]),
+            config,
+            LEECHER_PEER_ID.to_string(),
+        );
+        let port = server.bind().unwrap();
+        thread::spawn(move || server.init());
+
+        assert_rejected_with_a_choke(port, &torrent);
+        assert_eq!(status.rejected_connections(), 1);
+    }
+
+    #[test]
+    fn test_rejects_peer_and_disconnects_the_slowest_when_max_global_connections_reached() {
+        let dir = TempDir::new("server_rejects_peer_when_max_global_connections_reached");
+        let config = Cfg {
+            tcp_port: 0,
+            tcp_port_max: 0,
+            max_global_connections: 1,
+            ..test_config(&dir)
+        };
+        config.runtime.update(&config);
+        let (torrent, _) =
+            generated_torrent("global", 4 * 16384, 16384, "http://127.0.0.1:1/announce");
+
+        let status = AtomicTorrentStatus::with_storage(
+            &torrent,
+            config.clone(),
+            Box::new(MemoryStorage::new()),
+        );
+        let status = Arc::new(status);
+        let connected = BtPeer::new("10.0.0.1".to_string(), 6881);
+        status.peer_connecting();
+        status.peer_connected(&connected).unwrap();
+        let mut server = BtServer::new(
+            HashMap::from([(torrent.info_hash(), status.clone())]),
+            config,
+            LEECHER_PEER_ID.to_string(),
+        );
+        let port = server.bind().unwrap();
+        thread::spawn(move || server.init());
+
+        assert_rejected_with_a_choke(port, &torrent);
+        assert!(status.should_disconnect(&connected).unwrap());
+        assert_eq!(status.all_current_peers(), 1);
+
+        // The slot of the peer asked to disconnect is not freed yet, so the next peer is refused too.
+        assert_rejected_with_a_choke(port, &torrent);
+        assert_eq!(status.pending_disconnects().unwrap(), 1);
+        assert_eq!(status.all_current_peers(), 1);
+        assert_eq!(status.rejected_connections(), 2);
+    }
+
+    /// Connects to the server at `port` for `torrent`, checking it answers the handshake and closes with a choke.
+    fn assert_rejected_with_a_choke(port: u16, torrent: &Torrent) {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let mut peer = BtPeer::new("127.0.0.1".to_string(), port as i64);
+        peer.send_handshake(&mut stream, torrent.info_hash(), SEEDER_PEER_ID.to_string())
+            .unwrap();
+
+        assert_eq!(
+            peer.receive_handshake(&mut stream).unwrap(),
+            torrent.info_hash()
+        );
+        let mut choke = Vec::new();
+        std::io::Read::read_to_end(&mut stream, &mut choke).unwrap();
+        assert_eq!(choke, Message::new(MessageId::Choke, vec![]).as_bytes());
+    }
+}
@@ -1,20 +1,85 @@
-use std::fs::File;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
 use std::io;
-use std::io::BufRead;
-use std::io::BufReader;
+use std::ops::RangeInclusive;
 use std::str::FromStr;
+use std::sync::Arc;
 
 use super::constants;
+use super::runtime::RuntimeSettings;
+use super::torrent_overrides::TorrentOverrides;
+use crate::logger::{log_format::LogFormat, log_level::LogLevel};
+use crate::proxy::{Proxy, ProxyType};
+use crate::storage_manager::layout::DownloadLayout;
+use url_encoder::info_hash::InfoHash;
 
 /// `Cfg` struct containing the config file information, previusly created with `Cfg::new`.
 ///
-/// - `tcp_port`: port to listen for incoming connections,
-/// - `log_directory`: directory where the log files will be stored,
-/// - `download_directory`: directory where the downloaded files will be stored,
-/// - `pipelining_size`: number of request sent to a peer before waiting for the response,
-/// - `read_write_seconds_timeout`: timeout in seconds for the read and write operations to a peer,
-/// - `max_peers_per_torrent`: maximum number of simultaneous peers that a torrent can have,
-/// - `max_log_file_kb_size`: max file size in kilobytes the log can have,
+/// Every setting is optional, the ones missing from the config file take the value of `Cfg::default`.
+///
+/// - `tcp_port`: port to listen for incoming connections (defaults to 6881),
+/// - `log_directory`: directory where the log files will be stored, logs are written to the standard output if empty (defaults to ./dtorrent_logs),
+/// - `download_directory`: directory where the downloaded files will be stored (defaults to ./downloads),
+/// - `pipelining_size`: minimum number of block requests kept outstanding with a peer, it grows with the peer rate and latency (defaults to 5),
+/// - `read_write_seconds_timeout`: timeout in seconds for the read and write operations to a peer (defaults to 20),
+/// - `max_peers_per_torrent`: maximum number of simultaneous peers that a torrent can have (defaults to 20),
+/// - `max_log_file_kb_size`: max file size in kilobytes the log can have before it is rotated, 0 disables the rotation (defaults to 100000),
+/// - `super_seeding`: whether to reveal pieces one at a time to leechers when seeding (defaults to false),
+/// - `max_global_connections`: maximum number of simultaneous peers between all the torrents (defaults to 200),
+/// - `max_upload_slots`: maximum number of peers of a torrent that can be unchoked at the same time (defaults to 8),
+/// - `max_half_open_connections`: maximum number of outgoing connections of a torrent that can be waiting for the handshake (defaults to 8),
+/// - `max_corrupted_pieces`: number of pieces failing the hash check a peer can send before being banned (defaults to 3),
+/// - `max_request_length`: maximum length in bytes of a block a peer can request, bigger requests disconnect the peer (defaults to 16384),
+/// - `request_timeout_seconds`: seconds a peer has to deliver a requested block before it is considered snubbed (defaults to 30),
+/// - `tcp_port_max`: last port of the range to listen on, ports from `tcp_port` to `tcp_port_max` are tried in order (defaults to `tcp_port`),
+/// - `port_mapping`: whether to map the listen port in the gateway using NAT-PMP or UPnP (defaults to false),
+/// - `port_mapping_lifetime_seconds`: lifetime of the port mapping in seconds, it is renewed before it expires (defaults to 3600),
+/// - `proxy_type`: proxy for the tracker announces, one of none, socks5 or http (defaults to none),
+/// - `proxy_host`: host of the proxy,
+/// - `proxy_port`: port of the proxy (defaults to 1080),
+/// - `proxy_username`: username to authenticate with the proxy, no authentication is used if empty,
+/// - `proxy_password`: password to authenticate with the proxy,
+/// - `proxy_peer_connections`: whether to also connect to peers through the proxy (defaults to false),
+/// - `metrics_port`: port of the HTTP endpoint that serves the Prometheus metrics (defaults to 0, which disables it),
+/// - `max_log_files`: number of rotated log files kept besides the current one, older ones are deleted (defaults to 5),
+/// - `log_level`: minimum level of the events logged, one of error, warn, info, debug or trace, it can be changed at runtime with `logger::set_level` (defaults to info),
+/// - `log_format`: format of the log lines, one of text or json (defaults to text),
+/// - `per_torrent_logs`: whether to also write the events of each torrent to its own log file in the `torrents` folder of the `log_directory` (defaults to false),
+/// - `config_reload_seconds`: seconds between checks of the config file for changes, the settings that can change while running are reloaded when it changes, 0 disables it (defaults to 5),
+/// - `download_layout`: layout of the downloaded files in the `download_directory`, one of flat, info_hash (a folder named after the info hash of each torrent) or name_and_hash (a folder named after the name and the start of the info hash) (defaults to flat),
+/// - `part_files`: whether to write the downloads in progress to a `<name>.part` file that is renamed when the download finishes, so the file never appears half-written (defaults to false),
+/// - `completed_directory`: directory where the downloaded files are moved to when the download finishes, they are kept where they were downloaded if empty (defaults to empty),
+/// - `mmap_seeding`: whether to map the completed downloads in memory and serve the blocks to other peers from the map instead of reading the file each time, only on Unix, other processes must not modify the completed files while they are mapped (defaults to false),
+/// - `block_size`: size in bytes of the blocks requested to the peers, smaller blocks are only useful for testing (defaults to 16384),
+/// - `numwant`: number of peers asked to the tracker on each announce (defaults to 50),
+/// - `max_connections_per_ip`: how many connections can be open to the same IP among every torrent, 0 for no limit (defaults to 4),
+/// - `watch_directory`: directory checked for new `.torrent` files, which are loaded and started automatically, empty disables it (defaults to empty),
+/// - `watch_directory_seconds`: seconds between checks of the watch directory for new `.torrent` files (defaults to 5),
+/// - `move_loaded_torrents`: whether to move the `.torrent` files loaded from the watch directory to its `loaded` subdirectory, otherwise they are remembered and left in place (defaults to true),
+/// - `copy_completed`: whether to copy the downloaded files to the `completed_directory` instead of moving them, so they keep being seeded from the `download_directory` (defaults to false),
+/// - `on_complete_command`: command run by the shell when a download finishes, with the name, path and info hash of the torrent in the `DTORRENT_TORRENT_NAME`, `DTORRENT_TORRENT_PATH` and `DTORRENT_INFO_HASH` environment variables, empty disables it (defaults to empty),
+/// - `seed_ratio_limit`: share ratio, the uploaded bytes divided by the size of the torrent, at which a finished torrent stops seeding, 0 disables it (defaults to 0),
+/// - `seed_time_limit_seconds`: seconds a finished torrent is seeded before it stops seeding, 0 disables it (defaults to 0),
+/// - `handshake_seconds_timeout`: timeout in seconds for a peer to complete the handshake, before the read and write timeout applies (defaults to 5),
+/// - `hashing_threads`: threads of each torrent that check the hash of the downloaded pieces (defaults to 2),
+/// - `max_download_kb_per_second`: kilobytes per second each torrent can download, 0 is unlimited (defaults to 0),
+/// - `max_upload_kb_per_second`: kilobytes per second each torrent can upload, 0 is unlimited (defaults to 0),
+/// - `sequential_download`: whether to download the pieces in order, so the file can be played while it downloads (defaults to false),
+///
+/// The fields keep the values the config was loaded with. The settings that can change while running,
+/// `max_peers_per_torrent`, `max_global_connections`, `max_half_open_connections` and `max_corrupted_pieces`,
+/// are read by the running components from `runtime`, which is shared by every clone of the config.
+///
+/// The `download_directory`, `download_layout`, `max_peers_per_torrent`, `max_upload_slots`, `super_seeding`,
+/// `completed_directory`, `copy_completed`, `on_complete_command`, `seed_ratio_limit`, `seed_time_limit_seconds`,
+/// `max_download_kb_per_second`, `max_upload_kb_per_second` and `sequential_download` settings can be overridden for a single torrent in a `[torrent."<info hash>"]` section at the end of the config file.
+/// The sections are kept by info hash in `torrents`, and `Cfg::for_torrent` returns the config of a torrent
+/// with its `overrides` applied. A section can also set the `output_path` of the downloaded file of the torrent,
+/// relative to the `download_directory` unless it is absolute.
+///
+/// Unknown settings of the config file and unknown environment variables with the `DTORRENT_` prefix are skipped,
+/// and kept in `ignored_settings` so they can be logged once the logger is initialized.
 #[derive(Debug, Clone)]
 pub struct Cfg {
     pub tcp_port: u16,
@@ -24,65 +89,267 @@ pub struct Cfg {
     pub read_write_seconds_timeout: u64,
     pub max_peers_per_torrent: u32,
     pub max_log_file_kb_size: u32,
+    pub super_seeding: bool,
+    pub max_global_connections: u32,
+    pub max_upload_slots: u32,
+    pub max_half_open_connections: u32,
+    pub max_corrupted_pieces: u32,
+    pub max_request_length: u32,
+    pub request_timeout_seconds: u64,
+    pub tcp_port_max: u16,
+    pub port_mapping: bool,
+    pub port_mapping_lifetime_seconds: u32,
+    pub proxy_type: ProxyType,
+    pub proxy_host: String,
+    pub proxy_port: u16,
+    pub proxy_username: String,
+    pub proxy_password: String,
+    pub proxy_peer_connections: bool,
+    pub metrics_port: u16,
+    pub max_log_files: u32,
+    pub log_level: LogLevel,
+    pub log_format: LogFormat,
+    pub per_torrent_logs: bool,
+    pub config_reload_seconds: u64,
+    pub download_layout: DownloadLayout,
+    pub part_files: bool,
+    pub completed_directory: String,
+    pub mmap_seeding: bool,
+    pub block_size: u32,
+    pub numwant: u32,
+    pub max_connections_per_ip: u32,
+    pub watch_directory: String,
+    pub watch_directory_seconds: u64,
+    pub move_loaded_torrents: bool,
+    pub copy_completed: bool,
+    pub on_complete_command: String,
+    pub seed_ratio_limit: f64,
+    pub seed_time_limit_seconds: u64,
+    pub handshake_seconds_timeout: u64,
+    pub hashing_threads: u32,
+    pub max_download_kb_per_second: u32,
+    pub max_upload_kb_per_second: u32,
+    pub sequential_download: bool,
+    pub runtime: Arc<RuntimeSettings>,
+    pub torrents: HashMap<String, TorrentOverrides>,
+    pub overrides: TorrentOverrides,
+    pub ignored_settings: Vec<String>,
+}
+
+impl Default for Cfg {
+    fn default() -> Self {
+        let config = Self {
+            tcp_port: constants::DEFAULT_TCP_PORT,
+            log_directory: String::from(constants::DEFAULT_LOG_DIRECTORY),
+            download_directory: String::from(constants::DEFAULT_DOWNLOAD_DIRECTORY),
+            pipelining_size: constants::DEFAULT_PIPELINING_SIZE,
+            read_write_seconds_timeout: constants::DEFAULT_READ_WRITE_SECONDS_TIMEOUT,
+            max_peers_per_torrent: constants::DEFAULT_MAX_PEERS_PER_TORRENT,
+            max_log_file_kb_size: constants::DEFAULT_MAX_LOG_FILE_KB_SIZE,
+            super_seeding: constants::DEFAULT_SUPER_SEEDING,
+            max_global_connections: constants::DEFAULT_MAX_GLOBAL_CONNECTIONS,
+            max_upload_slots: constants::DEFAULT_MAX_UPLOAD_SLOTS,
+            max_half_open_connections: constants::DEFAULT_MAX_HALF_OPEN_CONNECTIONS,
+            max_corrupted_pieces: constants::DEFAULT_MAX_CORRUPTED_PIECES,
+            max_request_length: constants::DEFAULT_MAX_REQUEST_LENGTH,
+            request_timeout_seconds: constants::DEFAULT_REQUEST_TIMEOUT_SECONDS,
+            tcp_port_max: constants::DEFAULT_TCP_PORT_MAX,
+            port_mapping: constants::DEFAULT_PORT_MAPPING,
+            port_mapping_lifetime_seconds: constants::DEFAULT_PORT_MAPPING_LIFETIME_SECONDS,
+            proxy_type: constants::DEFAULT_PROXY_TYPE,
+            proxy_host: String::from(constants::DEFAULT_PROXY_HOST),
+            proxy_port: constants::DEFAULT_PROXY_PORT,
+            proxy_username: String::from(constants::DEFAULT_PROXY_USERNAME),
+            proxy_password: String::from(constants::DEFAULT_PROXY_PASSWORD),
+            proxy_peer_connections: constants::DEFAULT_PROXY_PEER_CONNECTIONS,
+            metrics_port: constants::DEFAULT_METRICS_PORT,
+            max_log_files: constants::DEFAULT_MAX_LOG_FILES,
+            log_level: constants::DEFAULT_LOG_LEVEL,
+            log_format: constants::DEFAULT_LOG_FORMAT,
+            per_torrent_logs: constants::DEFAULT_PER_TORRENT_LOGS,
+            config_reload_seconds: constants::DEFAULT_CONFIG_RELOAD_SECONDS,
+            download_layout: constants::DEFAULT_DOWNLOAD_LAYOUT,
+            part_files: constants::DEFAULT_PART_FILES,
+            completed_directory: String::from(constants::DEFAULT_COMPLETED_DIRECTORY),
+            mmap_seeding: constants::DEFAULT_MMAP_SEEDING,
+            block_size: constants::DEFAULT_BLOCK_SIZE,
+            numwant: constants::DEFAULT_NUMWANT,
+            max_connections_per_ip: constants::DEFAULT_MAX_CONNECTIONS_PER_IP,
+            watch_directory: String::from(constants::DEFAULT_WATCH_DIRECTORY),
+            watch_directory_seconds: constants::DEFAULT_WATCH_DIRECTORY_SECONDS,
+            move_loaded_torrents: constants::DEFAULT_MOVE_LOADED_TORRENTS,
+            copy_completed: constants::DEFAULT_COPY_COMPLETED,
+            on_complete_command: String::from(constants::DEFAULT_ON_COMPLETE_COMMAND),
+            seed_ratio_limit: constants::DEFAULT_SEED_RATIO_LIMIT,
+            seed_time_limit_seconds: constants::DEFAULT_SEED_TIME_LIMIT_SECONDS,
+            handshake_seconds_timeout: constants::DEFAULT_HANDSHAKE_SECONDS_TIMEOUT,
+            hashing_threads: constants::DEFAULT_HASHING_THREADS,
+            max_download_kb_per_second: constants::DEFAULT_MAX_DOWNLOAD_KB_PER_SECOND,
+            max_upload_kb_per_second: constants::DEFAULT_MAX_UPLOAD_KB_PER_SECOND,
+            sequential_download: constants::DEFAULT_SEQUENTIAL_DOWNLOAD,
+            runtime: Arc::default(),
+            torrents: HashMap::new(),
+            overrides: TorrentOverrides::default(),
+            ignored_settings: Vec::new(),
+        };
+        config.runtime.update(&config);
+        config
+    }
 }
 
 impl Cfg {
     /// Builds a Cfg struct containing the config file information by the given path.
-    /// The format of the config file must be: {config_name}={config_value} (without brackets).
+    ///
+    /// The config file is a TOML file with one `setting_name = value` per line, strings can be quoted,
+    /// and `#` starts a comment. The names are case insensitive, so the `SETTING_NAME=value` lines of older
+    /// config files are still accepted. Settings missing from the file keep their default value.
+    ///
+    /// Every setting can be overridden with an environment variable named after it with the `DTORRENT_` prefix,
+    /// e.g. `DTORRENT_TCP_PORT=6882`. Unknown settings are skipped and kept in `ignored_settings`.
+    ///
+    /// A `[torrent."<info hash>"]` line starts the section of a torrent, the settings after it only apply to that torrent.
+    ///
     /// In case of success it returns a Cfg struct.
     ///
     /// It returns an io::Error if:
     /// - The path to the config file does not exist or could not be open/readed.
-    /// - The confing file has wrong format.
-    /// - A wrong config_name was in the config file.
+    /// - The confing file has wrong format, the error includes the line number.
+    /// - A setting has a value that is not valid for it, in the config file or in a `DTORRENT_` environment variable.
+    /// - A section is not a `[torrent."<info hash>"]` section or sets a setting that can not be overridden per torrent.
     /// - tcp_port setting is not a valid number in the config file.
     /// - pipelining_size setting is not a valid number in the config file.
     /// - read_write_timeout setting is not a valid number in the config file.
     /// - max_peers_per_torrent  setting is not a valid number in the config file.
     /// - max_log_file_size setting is not a valid number in the config file.
-    /// - Minimum number of correct settings were not reached.
+    /// - super_seeding setting is not a valid boolean in the config file.
+    /// - max_global_connections setting is not a valid number in the config file.
+    /// - max_upload_slots setting is not a valid number in the config file.
+    /// - max_half_open_connections setting is not a valid number in the config file.
+    /// - max_corrupted_pieces setting is not a valid number in the config file.
+    /// - max_request_length setting is not a valid number in the config file.
+    /// - request_timeout_seconds setting is not a valid number in the config file.
+    /// - tcp_port_max setting is not a valid number in the config file.
+    /// - port_mapping setting is not a valid boolean in the config file.
+    /// - port_mapping_lifetime_seconds setting is not a valid number in the config file.
+    /// - proxy_type setting is not a valid proxy type in the config file.
+    /// - proxy_port setting is not a valid number in the config file.
+    /// - proxy_peer_connections setting is not a valid boolean in the config file.
+    /// - metrics_port setting is not a valid number in the config file.
+    /// - max_log_files setting is not a valid number in the config file.
+    /// - log_level setting is not a valid log level in the config file.
+    /// - log_format setting is not a valid log format in the config file.
+    /// - per_torrent_logs setting is not a valid boolean in the config file.
+    /// - config_reload_seconds setting is not a valid number in the config file.
+    /// - download_layout setting is not a valid download layout in the config file.
+    /// - part_files setting is not a valid boolean in the config file.
+    /// - mmap_seeding setting is not a valid boolean in the config file.
+    /// - block_size setting is not a valid number in the config file.
+    /// - numwant setting is not a valid number in the config file.
+    /// - max_connections_per_ip setting is not a valid number in the config file.
+    /// - watch_directory_seconds setting is not a valid number in the config file.
+    /// - move_loaded_torrents setting is not a valid boolean in the config file.
+    /// - copy_completed setting is not a valid boolean in the config file.
+    /// - seed_ratio_limit setting is not a valid number in the config file.
+    /// - seed_time_limit_seconds setting is not a valid number in the config file.
+    /// - handshake_seconds_timeout setting is not a valid number in the config file.
+    /// - hashing_threads setting is not a valid number in the config file.
+    /// - max_download_kb_per_second setting is not a valid number in the config file.
+    /// - max_upload_kb_per_second setting is not a valid number in the config file.
+    /// - sequential_download setting is not a valid boolean in the config file.
     pub fn new(path: &str) -> io::Result<Self> {
-        let mut cfg = Self {
-            tcp_port: 0,
-            log_directory: String::from(""),
-            download_directory: String::from(""),
-            pipelining_size: 0,
-            read_write_seconds_timeout: 0,
-            max_peers_per_torrent: 0,
-            max_log_file_kb_size: 0,
-        };
-
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
+        let contents = fs::read_to_string(path)?;
+        let config = Self::default()
+            .load_settings(&contents)?
+            .load_env_overrides(env::vars())?;
+        config.runtime.update(&config);
+        Ok(config)
+    }
 
-        let mut settings_loaded = 0;
+    /// Loads the settings of the contents of a config file.
+    fn load_settings(mut self, contents: &str) -> io::Result<Self> {
+        // Info hash of the torrent section the lines belong to, `None` before the first section.
+        let mut section: Option<String> = None;
 
-        for line in reader.lines() {
-            let current_line = line?;
-            let setting: Vec<&str> = current_line.split('=').collect();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                let info_hash = parse_torrent_section(line).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Invalid config section at line {}: {}", index + 1, line),
+                    )
+                })?;
+                self.torrents.entry(info_hash.clone()).or_default();
+                section = Some(info_hash);
+                continue;
+            }
 
-            if setting.len() != 2 {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    format!("Invalid config input: {}", current_line),
-                ));
+            let setting = line
+                .split_once('=')
+                .and_then(|(name, value)| Some((name.trim(), parse_toml_value(value)?)));
+            let (name, value) = match setting {
+                Some(setting) => setting,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Invalid config input at line {}: {}", index + 1, line),
+                    ))
+                }
+            };
+            let name = name.to_ascii_uppercase();
+            let loaded = match &section {
+                Some(info_hash) => self
+                    .torrents
+                    .entry(info_hash.clone())
+                    .or_default()
+                    .load_setting(&name, &value),
+                None => self.load_setting(&name, &value),
+            };
+            match loaded {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => self
+                    .ignored_settings
+                    .push(format!("{} at line {}", name, index + 1)),
+                loaded => loaded.map_err(|err| {
+                    io::Error::new(err.kind(), format!("{} at line {}", err, index + 1))
+                })?,
             }
-            cfg = Self::load_setting(cfg, setting[0], setting[1])?;
-            settings_loaded += 1;
         }
-        if settings_loaded < constants::MIN_SETTINGS {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                format!(
-                    "Minimum number of correct settings were not reached: {}",
-                    settings_loaded
-                ),
-            ));
+        Ok(self)
+    }
+
+    /// Loads the settings of the environment variables with the `DTORRENT_` prefix.
+    fn load_env_overrides(
+        mut self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> io::Result<Self> {
+        for (name, value) in vars {
+            if let Some(setting) = name.strip_prefix(constants::ENV_PREFIX) {
+                match self.load_setting(setting, &value) {
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => self
+                        .ignored_settings
+                        .push(format!("environment variable {}", name)),
+                    loaded => loaded.map_err(|err| {
+                        io::Error::new(
+                            err.kind(),
+                            format!("{} in environment variable {}", err, name),
+                        )
+                    })?,
+                }
+            }
         }
-        Ok(cfg)
+        Ok(self)
     }
 
-    fn load_setting(mut self, name: &str, value: &str) -> io::Result<Self> {
+    /// Loads a setting of the config.
+    ///
+    /// # Errors
+    ///
+    /// - `io::Error` of kind `NotFound` if there is no setting with the given name.
+    /// - `io::Error` of kind `InvalidInput` if the value is not valid for the setting.
+    fn load_setting(&mut self, name: &str, value: &str) -> io::Result<()> {
         match name {
             constants::TCP_PORT => {
                 self.tcp_port = self.parse_value(value, constants::TCP_PORT)?;
@@ -110,17 +377,233 @@ impl Cfg {
                     self.parse_value(value, constants::MAX_LOG_FILE_KB_SIZE)?;
             }
 
+            constants::SUPER_SEEDING => {
+                self.super_seeding = self.parse_value(value, constants::SUPER_SEEDING)?;
+            }
+
+            constants::MAX_GLOBAL_CONNECTIONS => {
+                self.max_global_connections =
+                    self.parse_value(value, constants::MAX_GLOBAL_CONNECTIONS)?;
+            }
+
+            constants::MAX_UPLOAD_SLOTS => {
+                self.max_upload_slots = self.parse_value(value, constants::MAX_UPLOAD_SLOTS)?;
+            }
+
+            constants::MAX_HALF_OPEN_CONNECTIONS => {
+                self.max_half_open_connections =
+                    self.parse_value(value, constants::MAX_HALF_OPEN_CONNECTIONS)?;
+            }
+
+            constants::MAX_CORRUPTED_PIECES => {
+                self.max_corrupted_pieces =
+                    self.parse_value(value, constants::MAX_CORRUPTED_PIECES)?;
+            }
+
+            constants::MAX_REQUEST_LENGTH => {
+                self.max_request_length = self.parse_value(value, constants::MAX_REQUEST_LENGTH)?;
+            }
+
+            constants::REQUEST_TIMEOUT_SECONDS => {
+                self.request_timeout_seconds =
+                    self.parse_value(value, constants::REQUEST_TIMEOUT_SECONDS)?;
+            }
+
+            constants::TCP_PORT_MAX => {
+                self.tcp_port_max = self.parse_value(value, constants::TCP_PORT_MAX)?;
+            }
+
+            constants::PORT_MAPPING => {
+                self.port_mapping = self.parse_value(value, constants::PORT_MAPPING)?;
+            }
+
+            constants::PORT_MAPPING_LIFETIME_SECONDS => {
+                self.port_mapping_lifetime_seconds =
+                    self.parse_value(value, constants::PORT_MAPPING_LIFETIME_SECONDS)?;
+            }
+
+            constants::PROXY_TYPE => {
+                self.proxy_type = self.parse_value(value, constants::PROXY_TYPE)?;
+            }
+
+            constants::PROXY_HOST => self.proxy_host = String::from(value),
+
+            constants::PROXY_PORT => {
+                self.proxy_port = self.parse_value(value, constants::PROXY_PORT)?;
+            }
+
+            constants::PROXY_USERNAME => self.proxy_username = String::from(value),
+
+            constants::PROXY_PASSWORD => self.proxy_password = String::from(value),
+
+            constants::PROXY_PEER_CONNECTIONS => {
+                self.proxy_peer_connections =
+                    self.parse_value(value, constants::PROXY_PEER_CONNECTIONS)?;
+            }
+
+            constants::METRICS_PORT => {
+                self.metrics_port = self.parse_value(value, constants::METRICS_PORT)?;
+            }
+
+            constants::MAX_LOG_FILES => {
+                self.max_log_files = self.parse_value(value, constants::MAX_LOG_FILES)?;
+            }
+
+            constants::LOG_LEVEL => {
+                self.log_level = self.parse_value(value, constants::LOG_LEVEL)?;
+            }
+
+            constants::LOG_FORMAT => {
+                self.log_format = self.parse_value(value, constants::LOG_FORMAT)?;
+            }
+
+            constants::PER_TORRENT_LOGS => {
+                self.per_torrent_logs = self.parse_value(value, constants::PER_TORRENT_LOGS)?;
+            }
+
+            constants::CONFIG_RELOAD_SECONDS => {
+                self.config_reload_seconds =
+                    self.parse_value(value, constants::CONFIG_RELOAD_SECONDS)?;
+            }
+
+            constants::DOWNLOAD_LAYOUT => {
+                self.download_layout = self.parse_value(value, constants::DOWNLOAD_LAYOUT)?;
+            }
+
+            constants::PART_FILES => {
+                self.part_files = self.parse_value(value, constants::PART_FILES)?;
+            }
+
+            constants::COMPLETED_DIRECTORY => self.completed_directory = String::from(value),
+
+            constants::MMAP_SEEDING => {
+                self.mmap_seeding = self.parse_value(value, constants::MMAP_SEEDING)?;
+            }
+
+            constants::BLOCK_SIZE => {
+                self.block_size = self.parse_value(value, constants::BLOCK_SIZE)?;
+            }
+
+            constants::NUMWANT => {
+                self.numwant = self.parse_value(value, constants::NUMWANT)?;
+            }
+
+            constants::MAX_CONNECTIONS_PER_IP => {
+                self.max_connections_per_ip =
+                    self.parse_value(value, constants::MAX_CONNECTIONS_PER_IP)?;
+            }
+
+            constants::WATCH_DIRECTORY => self.watch_directory = String::from(value),
+
+            constants::WATCH_DIRECTORY_SECONDS => {
+                self.watch_directory_seconds =
+                    self.parse_value(value, constants::WATCH_DIRECTORY_SECONDS)?;
+            }
+
+            constants::MOVE_LOADED_TORRENTS => {
+                self.move_loaded_torrents =
+                    self.parse_value(value, constants::MOVE_LOADED_TORRENTS)?;
+            }
+
+            constants::COPY_COMPLETED => {
+                self.copy_completed = self.parse_value(value, constants::COPY_COMPLETED)?;
+            }
+
+            constants::ON_COMPLETE_COMMAND => self.on_complete_command = String::from(value),
+
+            constants::SEED_RATIO_LIMIT => {
+                self.seed_ratio_limit = self.parse_value(value, constants::SEED_RATIO_LIMIT)?;
+            }
+
+            constants::SEED_TIME_LIMIT_SECONDS => {
+                self.seed_time_limit_seconds =
+                    self.parse_value(value, constants::SEED_TIME_LIMIT_SECONDS)?;
+            }
+
+            constants::HANDSHAKE_SECONDS_TIMEOUT => {
+                self.handshake_seconds_timeout =
+                    self.parse_value(value, constants::HANDSHAKE_SECONDS_TIMEOUT)?;
+            }
+
+            constants::HASHING_THREADS => {
+                self.hashing_threads = self.parse_value(value, constants::HASHING_THREADS)?;
+            }
+
+            constants::MAX_DOWNLOAD_KB_PER_SECOND => {
+                self.max_download_kb_per_second =
+                    self.parse_value(value, constants::MAX_DOWNLOAD_KB_PER_SECOND)?;
+            }
+
+            constants::MAX_UPLOAD_KB_PER_SECOND => {
+                self.max_upload_kb_per_second =
+                    self.parse_value(value, constants::MAX_UPLOAD_KB_PER_SECOND)?;
+            }
+
+            constants::SEQUENTIAL_DOWNLOAD => {
+                self.sequential_download =
+                    self.parse_value(value, constants::SEQUENTIAL_DOWNLOAD)?;
+            }
+
             _ => {
                 return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
+                    io::ErrorKind::NotFound,
                     format!("Invalid config setting name: {}", name),
                 ))
             }
         }
-        Ok(self)
+        Ok(())
+    }
+
+    /// Returns the config of the torrent with the given info hash, with the settings of its section if it has one.
+    pub fn for_torrent(&self, info_hash: &InfoHash) -> Self {
+        let mut config = self.clone();
+        if let Some(overrides) = self.torrents.get(&info_hash.to_hex()) {
+            overrides.apply(&mut config);
+        }
+        config
+    }
+
+    /// Returns the maximum number of simultaneous peers of the torrent.
+    ///
+    /// It is the value overridden for the torrent if there is one, or the current value of the runtime setting.
+    pub fn max_peers(&self) -> u32 {
+        self.overrides
+            .max_peers_per_torrent
+            .unwrap_or_else(|| self.runtime.max_peers_per_torrent())
+    }
+
+    /// Returns the range of ports to listen on, from `tcp_port` to `tcp_port_max`.
+    pub fn tcp_ports(&self) -> RangeInclusive<u16> {
+        self.tcp_port..=self.tcp_port_max.max(self.tcp_port)
+    }
+
+    /// Returns the proxy to connect through, or `None` if no proxy is configured.
+    pub fn proxy(&self) -> Option<Proxy> {
+        if self.proxy_type == ProxyType::None {
+            return None;
+        }
+        let credentials = if self.proxy_username.is_empty() {
+            None
+        } else {
+            Some((self.proxy_username.clone(), self.proxy_password.clone()))
+        };
+        Some(Proxy {
+            proxy_type: self.proxy_type,
+            host: self.proxy_host.clone(),
+            port: self.proxy_port,
+            credentials,
+        })
     }
 
     fn parse_value<F>(&self, value: &str, setting: &str) -> io::Result<F>
+    where
+        F: FromStr,
+    {
+        Self::parse_setting(value, setting)
+    }
+
+    /// Parses the value of a setting.
+    pub(super) fn parse_setting<F>(value: &str, setting: &str) -> io::Result<F>
     where
         F: FromStr,
     {
@@ -140,10 +623,68 @@ impl Cfg {
     }
 }
 
+/// Returns the value of a TOML `key = value` line without quotes and comments, or `None` if it has a wrong format.
+///
+/// Values that are not quoted are returned as they are, so numbers and booleans are parsed by the setting.
+fn parse_toml_value(value: &str) -> Option<String> {
+    let value = value.trim();
+    if let Some(literal) = value.strip_prefix('\'') {
+        let (literal, rest) = literal.split_once('\'')?;
+        return is_comment(rest).then(|| literal.to_string());
+    }
+    let basic = match value.strip_prefix('"') {
+        Some(basic) => basic,
+        None => {
+            let value = value.split_once('#').map_or(value, |(value, _)| value);
+            return Some(value.trim().to_string());
+        }
+    };
+
+    let mut unescaped = String::new();
+    let mut chars = basic.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return is_comment(chars.as_str()).then_some(unescaped),
+            '\\' => unescaped.push(match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '"' => '"',
+                '\\' => '\\',
+                _ => return None,
+            }),
+            c => unescaped.push(c),
+        }
+    }
+    None
+}
+
+/// Returns the info hash of a `[torrent."<info hash>"]` section line in lowercase hex, or `None` if it has a wrong format.
+fn parse_torrent_section(line: &str) -> Option<String> {
+    let key = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let info_hash = key.strip_prefix("torrent.")?.trim();
+    let info_hash = info_hash
+        .strip_prefix('"')
+        .and_then(|info_hash| info_hash.strip_suffix('"'))
+        .unwrap_or(info_hash);
+    InfoHash::from_hex(info_hash)
+        .ok()
+        .map(|info_hash| info_hash.to_hex())
+}
+
+/// Returns `true` if the rest of a line after a value is empty or a comment.
+fn is_comment(rest: &str) -> bool {
+    let rest = rest.trim();
+    rest.is_empty() || rest.starts_with('#')
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::{fs, io::Write};
+    use std::{
+        fs::{self, File},
+        io::Write,
+    };
 
     // tests:
     //  1- test todo ok
@@ -172,32 +713,180 @@ mod tests {
     }
 
     #[test]
-    fn test_empty_file() {
-        let path = "./test_empty_file.cfg";
+    fn test_empty_file_uses_defaults() {
+        let path = "./test_empty_file_uses_defaults.cfg";
         let contents = b"";
         create_and_write_file(path, contents);
 
-        create_and_assert_config_is_not_ok(path);
+        create_and_assert_config_is_ok(
+            path,
+            constants::DEFAULT_TCP_PORT,
+            constants::DEFAULT_LOG_DIRECTORY,
+            constants::DEFAULT_DOWNLOAD_DIRECTORY,
+            constants::DEFAULT_PIPELINING_SIZE,
+            constants::DEFAULT_READ_WRITE_SECONDS_TIMEOUT,
+            constants::DEFAULT_MAX_PEERS_PER_TORRENT,
+            constants::DEFAULT_MAX_LOG_FILE_KB_SIZE,
+        );
     }
 
     #[test]
     fn test_setting_doesnt_exist() {
-        let path = "./test_setting_doesnt_exist.cfg";
-        let contents = b"WRONG_SETTING=1000";
-        create_and_write_file(path, contents);
+        let config = Cfg::default()
+            .load_settings("tcp_port = 1000\nwrong_setting = 1000")
+            .unwrap();
 
-        create_and_assert_config_is_not_ok(path);
+        assert_eq!(config.tcp_port, 1000);
+        assert_eq!(config.ignored_settings, vec!["WRONG_SETTING at line 2"]);
     }
 
     #[test]
-    fn test_bad_number_of_settings() {
-        let path = "./test_bad_number_of_settings.cfg";
+    fn test_missing_settings_use_defaults() {
+        let path = "./test_missing_settings_use_defaults.cfg";
         let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log";
         create_and_write_file(path, contents);
 
+        create_and_assert_config_is_ok(
+            path,
+            1000,
+            "./log",
+            constants::DEFAULT_DOWNLOAD_DIRECTORY,
+            constants::DEFAULT_PIPELINING_SIZE,
+            constants::DEFAULT_READ_WRITE_SECONDS_TIMEOUT,
+            constants::DEFAULT_MAX_PEERS_PER_TORRENT,
+            constants::DEFAULT_MAX_LOG_FILE_KB_SIZE,
+        );
+    }
+
+    #[test]
+    fn test_toml_config() {
+        let path = "./test_toml_config.cfg";
+        let contents = b"# dtorrent config\ntcp_port = 1000\nlog_directory = \"./log # not a comment\"\n\ndownload_directory = './download' # comment\npipelining_size = 5\nread_write_seconds_timeout = 120\nmax_peers_per_torrent = 5\nmax_log_file_kb_size = 100\nsuper_seeding = true";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert!(config.super_seeding);
+        create_and_assert_config_is_ok(
+            path,
+            1000,
+            "./log # not a comment",
+            "./download",
+            5,
+            120,
+            5,
+            100,
+        );
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        let path = "./test_unterminated_string.cfg";
+        let contents = b"log_directory = \"./log";
+        create_and_write_file(path, contents);
+
         create_and_assert_config_is_not_ok(path);
     }
 
+    #[test]
+    fn test_error_includes_line_number() {
+        let config = Cfg::default().load_settings("tcp_port = 1000\npipelining_size = many");
+
+        assert!(config.unwrap_err().to_string().contains("at line 2"));
+    }
+
+    #[test]
+    fn test_torrent_overrides() {
+        let info_hash = InfoHash::new([0xab; 20]);
+        let contents = format!(
+            "max_peers_per_torrent = 20\nmax_upload_kb_per_second = 100\n\n[torrent.\"{}\"]\ndownload_directory = \"./movies\"\nmax_peers_per_torrent = 50\nsuper_seeding = true\ncompleted_directory = \"./seeding\"\non_complete_command = \"./notify.sh\"\nmax_download_kb_per_second = 500\nmax_upload_kb_per_second = 50\nsequential_download = true",
+            info_hash.to_hex().to_uppercase()
+        );
+
+        let config = Cfg::default().load_settings(&contents).unwrap();
+        let torrent_config = config.for_torrent(&info_hash);
+        let other_config = config.for_torrent(&InfoHash::new([0; 20]));
+
+        assert_eq!(config.max_peers_per_torrent, 20);
+        assert_eq!(torrent_config.download_directory, "./movies");
+        assert_eq!(torrent_config.max_peers(), 50);
+        assert!(torrent_config.super_seeding);
+        assert_eq!(torrent_config.completed_directory, "./seeding");
+        assert_eq!(torrent_config.on_complete_command, "./notify.sh");
+        assert_eq!(torrent_config.max_download_kb_per_second, 500);
+        assert_eq!(torrent_config.max_upload_kb_per_second, 50);
+        assert!(torrent_config.sequential_download);
+        assert!(other_config.on_complete_command.is_empty());
+        assert_eq!(other_config.max_download_kb_per_second, 0);
+        assert_eq!(other_config.max_upload_kb_per_second, 100);
+        assert!(!other_config.sequential_download);
+        assert_eq!(
+            other_config.download_directory,
+            constants::DEFAULT_DOWNLOAD_DIRECTORY
+        );
+        assert_eq!(
+            other_config.max_peers(),
+            config.runtime.max_peers_per_torrent()
+        );
+    }
+
+    #[test]
+    fn test_torrent_overrides_reject_global_settings() {
+        let contents = format!("[torrent.{}]\ntcp_port = 1000", "ab".repeat(20));
+
+        assert!(Cfg::default().load_settings(&contents).is_err());
+    }
+
+    #[test]
+    fn test_invalid_section() {
+        assert!(Cfg::default().load_settings("[peers]\nmax = 1").is_err());
+        assert!(Cfg::default()
+            .load_settings("[torrent.\"not a hash\"]")
+            .is_err());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = vec![
+            ("DTORRENT_TCP_PORT".to_string(), "2000".to_string()),
+            ("DTORRENT_SUPER_SEEDING".to_string(), "true".to_string()),
+            ("TCP_PORT".to_string(), "3000".to_string()),
+        ];
+
+        let config = Cfg::default()
+            .load_settings("tcp_port = 1000")
+            .unwrap()
+            .load_env_overrides(vars.into_iter())
+            .unwrap();
+
+        assert_eq!(config.tcp_port, 2000);
+        assert!(config.super_seeding);
+    }
+
+    #[test]
+    fn test_unknown_env_override_is_ignored() {
+        let vars = vec![
+            ("DTORRENT_TORRENT_NAME".to_string(), "file".to_string()),
+            ("DTORRENT_TCP_PORT".to_string(), "2000".to_string()),
+        ];
+
+        let config = Cfg::default().load_env_overrides(vars.into_iter()).unwrap();
+
+        assert_eq!(config.tcp_port, 2000);
+        assert_eq!(
+            config.ignored_settings,
+            vec!["environment variable DTORRENT_TORRENT_NAME"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_env_override() {
+        let vars = vec![("DTORRENT_TCP_PORT".to_string(), "abcd".to_string())];
+
+        let config = Cfg::default().load_env_overrides(vars.into_iter());
+
+        assert!(config.is_err());
+    }
+
     #[test]
     fn test_tcp_port_not_a_number() {
         let path = "./test_tcp_port_not_a_number.cfg";
@@ -252,6 +941,491 @@ mod tests {
         create_and_assert_config_is_ok(path, 2500, "./log2", "./download2", 10, 10, 1, 100);
     }
 
+    #[test]
+    fn test_super_seeding_defaults_to_false() {
+        let path = "./test_super_seeding_defaults_to_false.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert!(!config.super_seeding);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_super_seeding_enabled() {
+        let path = "./test_super_seeding_enabled.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nSUPER_SEEDING=true";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert!(config.super_seeding);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_super_seeding_not_a_boolean() {
+        let path = "./test_super_seeding_not_a_boolean.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nSUPER_SEEDING=yes";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_connection_limits() {
+        let path = "./test_connection_limits.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nMAX_GLOBAL_CONNECTIONS=50\nMAX_UPLOAD_SLOTS=3\nMAX_HALF_OPEN_CONNECTIONS=4";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.max_global_connections, 50);
+        assert_eq!(config.max_upload_slots, 3);
+        assert_eq!(config.max_half_open_connections, 4);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_max_upload_slots_not_a_number() {
+        let path = "./test_max_upload_slots_not_a_number.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nMAX_UPLOAD_SLOTS=many";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_max_corrupted_pieces() {
+        let path = "./test_max_corrupted_pieces.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nMAX_CORRUPTED_PIECES=1";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.max_corrupted_pieces, 1);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_max_request_length() {
+        let path = "./test_max_request_length.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nMAX_REQUEST_LENGTH=8192";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.max_request_length, 8192);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_request_timeout_seconds() {
+        let path = "./test_request_timeout_seconds.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nREQUEST_TIMEOUT_SECONDS=10";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.request_timeout_seconds, 10);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_tcp_port_max() {
+        let path = "./test_tcp_port_max.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nTCP_PORT_MAX=2000";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.tcp_port_max, 2000);
+        assert_eq!(config.tcp_ports(), 1000..=2000);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_tcp_ports_without_tcp_port_max() {
+        let path = "./test_tcp_ports_without_tcp_port_max.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.tcp_ports(), 1000..=1000);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_port_mapping() {
+        let path = "./test_port_mapping.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPORT_MAPPING=true";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert!(config.port_mapping);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_port_mapping_lifetime_seconds() {
+        let path = "./test_port_mapping_lifetime_seconds.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPORT_MAPPING_LIFETIME_SECONDS=600";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.port_mapping_lifetime_seconds, 600);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_proxy_type() {
+        let path = "./test_proxy_type.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPROXY_TYPE=socks5";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.proxy_type, ProxyType::Socks5);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_proxy_host() {
+        let path = "./test_proxy_host.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPROXY_HOST=proxy.example.com";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.proxy_host, "proxy.example.com");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_proxy_port() {
+        let path = "./test_proxy_port.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPROXY_PORT=3128";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.proxy_port, 3128);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_proxy_username() {
+        let path = "./test_proxy_username.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPROXY_USERNAME=user";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.proxy_username, "user");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_proxy_password() {
+        let path = "./test_proxy_password.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPROXY_PASSWORD=pass";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.proxy_password, "pass");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_proxy_peer_connections() {
+        let path = "./test_proxy_peer_connections.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPROXY_PEER_CONNECTIONS=true";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert!(config.proxy_peer_connections);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_proxy() {
+        let path = "./test_proxy.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPROXY_TYPE=http\nPROXY_HOST=proxy.example.com\nPROXY_PORT=3128\nPROXY_USERNAME=user\nPROXY_PASSWORD=pass";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(
+            config.proxy(),
+            Some(Proxy {
+                proxy_type: ProxyType::Http,
+                host: "proxy.example.com".to_string(),
+                port: 3128,
+                credentials: Some(("user".to_string(), "pass".to_string())),
+            })
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_no_proxy_by_default() {
+        let path = "./test_no_proxy_by_default.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.proxy(), None);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_metrics_port() {
+        let path = "./test_metrics_port.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nMETRICS_PORT=9100";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.metrics_port, 9100);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_max_log_files() {
+        let path = "./test_max_log_files.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nMAX_LOG_FILES=2";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.max_log_files, 2);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_log_level() {
+        let path = "./test_log_level.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nLOG_LEVEL=debug";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.log_level, LogLevel::Debug);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_log_format() {
+        let path = "./test_log_format.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nLOG_FORMAT=json";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.log_format, LogFormat::Json);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_per_torrent_logs() {
+        let path = "./test_per_torrent_logs.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPER_TORRENT_LOGS=true";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert!(config.per_torrent_logs);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_config_reload_seconds() {
+        let path = "./test_config_reload_seconds.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nCONFIG_RELOAD_SECONDS=30";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.config_reload_seconds, 30);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_download_layout() {
+        let path = "./test_download_layout.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nDOWNLOAD_LAYOUT=name_and_hash";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.download_layout, DownloadLayout::NameAndHash);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_part_files() {
+        let path = "./test_part_files.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPART_FILES=true";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert!(config.part_files);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_completed_directory() {
+        let path = "./test_completed_directory.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nCOMPLETED_DIRECTORY=./completed";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.completed_directory, "./completed");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_mmap_seeding() {
+        let path = "./test_mmap_seeding.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nMMAP_SEEDING=true";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert!(config.mmap_seeding);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_block_size() {
+        let path = "./test_block_size.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nBLOCK_SIZE=4096";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.block_size, 4096);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_numwant() {
+        let path = "./test_numwant.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nNUMWANT=20";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.numwant, 20);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_max_connections_per_ip() {
+        let path = "./test_max_connections_per_ip.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nMAX_CONNECTIONS_PER_IP=2";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.max_connections_per_ip, 2);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_watch_directory() {
+        let path = "./test_watch_directory.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nWATCH_DIRECTORY=./watch";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.watch_directory, "./watch");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_watch_directory_seconds() {
+        let path = "./test_watch_directory_seconds.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nWATCH_DIRECTORY_SECONDS=30";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.watch_directory_seconds, 30);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_move_loaded_torrents() {
+        let path = "./test_move_loaded_torrents.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nMOVE_LOADED_TORRENTS=false";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert!(!config.move_loaded_torrents);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_copy_completed() {
+        let path = "./test_copy_completed.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nCOPY_COMPLETED=true";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert!(config.copy_completed);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_on_complete_command() {
+        let path = "./test_on_complete_command.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nON_COMPLETE_COMMAND=./notify.sh";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.on_complete_command, "./notify.sh");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_seed_ratio_limit() {
+        let path = "./test_seed_ratio_limit.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nSEED_RATIO_LIMIT=1.5";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.seed_ratio_limit, 1.5);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_seed_time_limit_seconds() {
+        let path = "./test_seed_time_limit_seconds.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nSEED_TIME_LIMIT_SECONDS=3600";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.seed_time_limit_seconds, 3600);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_handshake_seconds_timeout() {
+        let path = "./test_handshake_seconds_timeout.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nHANDSHAKE_SECONDS_TIMEOUT=3";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.handshake_seconds_timeout, 3);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_hashing_threads() {
+        let path = "./test_hashing_threads.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nHASHING_THREADS=4";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        assert_eq!(config.hashing_threads, 4);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_rate_limits_and_sequential_download() {
+        let config = Cfg::default()
+            .load_settings(
+                "max_download_kb_per_second = 200\nmax_upload_kb_per_second = 40\nsequential_download = true",
+            )
+            .unwrap();
+
+        assert_eq!(config.max_download_kb_per_second, 200);
+        assert_eq!(config.max_upload_kb_per_second, 40);
+        assert!(config.sequential_download);
+        assert!(Cfg::default()
+            .load_settings("max_upload_kb_per_second = fast")
+            .is_err());
+    }
+
     #[test]
     fn test_bad_format() {
         let path = "./test_bad_format.cfg";
@@ -1,3 +1,7 @@
+use crate::logger::{log_format::LogFormat, log_level::LogLevel};
+use crate::proxy::ProxyType;
+use crate::storage_manager::layout::DownloadLayout;
+
 pub const TCP_PORT: &str = "TCP_PORT";
 pub const LOG_DIRECTORY: &str = "LOG_DIRECTORY";
 pub const DOWNLOAD_DIRECTORY: &str = "DOWNLOAD_DIRECTORY";
@@ -5,5 +9,100 @@ pub const PIPELINING_SIZE: &str = "PIPELINING_SIZE";
 pub const READ_WRITE_SECONDS_TIMEOUT: &str = "READ_WRITE_SECONDS_TIMEOUT";
 pub const MAX_PEERS_PER_TORRENT: &str = "MAX_PEERS_PER_TORRENT";
 pub const MAX_LOG_FILE_KB_SIZE: &str = "MAX_LOG_FILE_KB_SIZE";
+pub const SUPER_SEEDING: &str = "SUPER_SEEDING";
+pub const MAX_GLOBAL_CONNECTIONS: &str = "MAX_GLOBAL_CONNECTIONS";
+pub const MAX_UPLOAD_SLOTS: &str = "MAX_UPLOAD_SLOTS";
+pub const MAX_HALF_OPEN_CONNECTIONS: &str = "MAX_HALF_OPEN_CONNECTIONS";
+pub const MAX_CORRUPTED_PIECES: &str = "MAX_CORRUPTED_PIECES";
+pub const MAX_REQUEST_LENGTH: &str = "MAX_REQUEST_LENGTH";
+pub const REQUEST_TIMEOUT_SECONDS: &str = "REQUEST_TIMEOUT_SECONDS";
+pub const TCP_PORT_MAX: &str = "TCP_PORT_MAX";
+pub const PORT_MAPPING: &str = "PORT_MAPPING";
+pub const PORT_MAPPING_LIFETIME_SECONDS: &str = "PORT_MAPPING_LIFETIME_SECONDS";
+pub const PROXY_TYPE: &str = "PROXY_TYPE";
+pub const PROXY_HOST: &str = "PROXY_HOST";
+pub const PROXY_PORT: &str = "PROXY_PORT";
+pub const PROXY_USERNAME: &str = "PROXY_USERNAME";
+pub const PROXY_PASSWORD: &str = "PROXY_PASSWORD";
+pub const PROXY_PEER_CONNECTIONS: &str = "PROXY_PEER_CONNECTIONS";
+pub const METRICS_PORT: &str = "METRICS_PORT";
+pub const MAX_LOG_FILES: &str = "MAX_LOG_FILES";
+pub const LOG_LEVEL: &str = "LOG_LEVEL";
+pub const LOG_FORMAT: &str = "LOG_FORMAT";
+pub const PER_TORRENT_LOGS: &str = "PER_TORRENT_LOGS";
+pub const CONFIG_RELOAD_SECONDS: &str = "CONFIG_RELOAD_SECONDS";
+pub const DOWNLOAD_LAYOUT: &str = "DOWNLOAD_LAYOUT";
+
+/// Path of the downloaded file of a torrent, it can only be set in the section of the torrent.
+pub const OUTPUT_PATH: &str = "OUTPUT_PATH";
+pub const PART_FILES: &str = "PART_FILES";
+pub const COMPLETED_DIRECTORY: &str = "COMPLETED_DIRECTORY";
+pub const MMAP_SEEDING: &str = "MMAP_SEEDING";
+pub const BLOCK_SIZE: &str = "BLOCK_SIZE";
+pub const NUMWANT: &str = "NUMWANT";
+pub const MAX_CONNECTIONS_PER_IP: &str = "MAX_CONNECTIONS_PER_IP";
+pub const WATCH_DIRECTORY: &str = "WATCH_DIRECTORY";
+pub const WATCH_DIRECTORY_SECONDS: &str = "WATCH_DIRECTORY_SECONDS";
+pub const MOVE_LOADED_TORRENTS: &str = "MOVE_LOADED_TORRENTS";
+pub const COPY_COMPLETED: &str = "COPY_COMPLETED";
+pub const ON_COMPLETE_COMMAND: &str = "ON_COMPLETE_COMMAND";
+pub const SEED_RATIO_LIMIT: &str = "SEED_RATIO_LIMIT";
+pub const SEED_TIME_LIMIT_SECONDS: &str = "SEED_TIME_LIMIT_SECONDS";
+pub const HANDSHAKE_SECONDS_TIMEOUT: &str = "HANDSHAKE_SECONDS_TIMEOUT";
+pub const HASHING_THREADS: &str = "HASHING_THREADS";
+pub const MAX_DOWNLOAD_KB_PER_SECOND: &str = "MAX_DOWNLOAD_KB_PER_SECOND";
+pub const MAX_UPLOAD_KB_PER_SECOND: &str = "MAX_UPLOAD_KB_PER_SECOND";
+pub const SEQUENTIAL_DOWNLOAD: &str = "SEQUENTIAL_DOWNLOAD";
+
+/// Prefix of the environment variables that override the settings of the config file, e.g. `DTORRENT_TCP_PORT`.
+pub const ENV_PREFIX: &str = "DTORRENT_";
 
-pub const MIN_SETTINGS: i8 = 7;
+// Default values of the settings missing from the config file.
+pub const DEFAULT_TCP_PORT: u16 = 6881;
+pub const DEFAULT_LOG_DIRECTORY: &str = "./dtorrent_logs";
+pub const DEFAULT_DOWNLOAD_DIRECTORY: &str = "./downloads";
+pub const DEFAULT_PIPELINING_SIZE: u32 = 5;
+pub const DEFAULT_READ_WRITE_SECONDS_TIMEOUT: u64 = 20;
+pub const DEFAULT_MAX_PEERS_PER_TORRENT: u32 = 20;
+pub const DEFAULT_MAX_LOG_FILE_KB_SIZE: u32 = 100000;
+pub const DEFAULT_SUPER_SEEDING: bool = false;
+pub const DEFAULT_MAX_GLOBAL_CONNECTIONS: u32 = 200;
+pub const DEFAULT_MAX_UPLOAD_SLOTS: u32 = 8;
+pub const DEFAULT_MAX_HALF_OPEN_CONNECTIONS: u32 = 8;
+pub const DEFAULT_MAX_CORRUPTED_PIECES: u32 = 3;
+pub const DEFAULT_MAX_REQUEST_LENGTH: u32 = 16384;
+pub const DEFAULT_REQUEST_TIMEOUT_SECONDS: u64 = 30;
+pub const DEFAULT_TCP_PORT_MAX: u16 = 0;
+pub const DEFAULT_PORT_MAPPING: bool = false;
+pub const DEFAULT_PORT_MAPPING_LIFETIME_SECONDS: u32 = 3600;
+pub const DEFAULT_PROXY_TYPE: ProxyType = ProxyType::None;
+pub const DEFAULT_PROXY_HOST: &str = "";
+pub const DEFAULT_PROXY_PORT: u16 = 1080;
+pub const DEFAULT_PROXY_USERNAME: &str = "";
+pub const DEFAULT_PROXY_PASSWORD: &str = "";
+pub const DEFAULT_PROXY_PEER_CONNECTIONS: bool = false;
+pub const DEFAULT_METRICS_PORT: u16 = 0;
+pub const DEFAULT_MAX_LOG_FILES: u32 = 5;
+pub const DEFAULT_LOG_LEVEL: LogLevel = LogLevel::Info;
+pub const DEFAULT_LOG_FORMAT: LogFormat = LogFormat::Text;
+pub const DEFAULT_PER_TORRENT_LOGS: bool = false;
+pub const DEFAULT_CONFIG_RELOAD_SECONDS: u64 = 5;
+pub const DEFAULT_DOWNLOAD_LAYOUT: DownloadLayout = DownloadLayout::Flat;
+pub const DEFAULT_PART_FILES: bool = false;
+pub const DEFAULT_COMPLETED_DIRECTORY: &str = "";
+pub const DEFAULT_MMAP_SEEDING: bool = false;
+pub const DEFAULT_BLOCK_SIZE: u32 = 16384;
+pub const DEFAULT_NUMWANT: u32 = 50;
+pub const DEFAULT_MAX_CONNECTIONS_PER_IP: u32 = 4;
+pub const DEFAULT_WATCH_DIRECTORY: &str = "";
+pub const DEFAULT_WATCH_DIRECTORY_SECONDS: u64 = 5;
+pub const DEFAULT_MOVE_LOADED_TORRENTS: bool = true;
+pub const DEFAULT_COPY_COMPLETED: bool = false;
+pub const DEFAULT_ON_COMPLETE_COMMAND: &str = "";
+pub const DEFAULT_SEED_RATIO_LIMIT: f64 = 0.0;
+pub const DEFAULT_SEED_TIME_LIMIT_SECONDS: u64 = 0;
+pub const DEFAULT_HANDSHAKE_SECONDS_TIMEOUT: u64 = 5;
+pub const DEFAULT_HASHING_THREADS: u32 = 2;
+pub const DEFAULT_MAX_DOWNLOAD_KB_PER_SECOND: u32 = 0;
+pub const DEFAULT_MAX_UPLOAD_KB_PER_SECOND: u32 = 0;
+pub const DEFAULT_SEQUENTIAL_DOWNLOAD: bool = false;
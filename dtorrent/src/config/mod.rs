@@ -1,2 +1,5 @@
 pub mod cfg;
 pub mod constants;
+pub mod runtime;
+pub mod torrent_overrides;
+pub mod watcher;
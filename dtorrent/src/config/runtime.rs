@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::cfg::Cfg;
+
+/// Settings that can be changed while the client is running, shared by every clone of a `Cfg`.
+///
+/// Running components read them on every use, so the values stored by `update` take effect immediately
+/// without restarting the active downloads.
+#[derive(Debug, Default)]
+pub struct RuntimeSettings {
+    max_peers_per_torrent: AtomicU32,
+    max_global_connections: AtomicU32,
+    max_half_open_connections: AtomicU32,
+    max_corrupted_pieces: AtomicU32,
+}
+
+impl RuntimeSettings {
+    /// Stores the runtime settings of the given config.
+    pub fn update(&self, config: &Cfg) {
+        self.max_peers_per_torrent
+            .store(config.max_peers_per_torrent, Ordering::Relaxed);
+        self.max_global_connections
+            .store(config.max_global_connections, Ordering::Relaxed);
+        self.max_half_open_connections
+            .store(config.max_half_open_connections, Ordering::Relaxed);
+        self.max_corrupted_pieces
+            .store(config.max_corrupted_pieces, Ordering::Relaxed);
+    }
+
+    /// Returns the maximum number of simultaneous peers that a torrent can have.
+    pub fn max_peers_per_torrent(&self) -> u32 {
+        self.max_peers_per_torrent.load(Ordering::Relaxed)
+    }
+
+    /// Returns the maximum number of simultaneous peers between all the torrents.
+    pub fn max_global_connections(&self) -> u32 {
+        self.max_global_connections.load(Ordering::Relaxed)
+    }
+
+    /// Returns the maximum number of outgoing connections of a torrent that can be waiting for the handshake.
+    pub fn max_half_open_connections(&self) -> u32 {
+        self.max_half_open_connections.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of pieces failing the hash check a peer can send before being banned.
+    pub fn max_corrupted_pieces(&self) -> u32 {
+        self.max_corrupted_pieces.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,137 @@
+use std::io;
+
+use super::{cfg::Cfg, constants};
+use crate::storage_manager::layout::DownloadLayout;
+
+/// Settings of a single torrent that override the ones of the config.
+///
+/// They are read from the `[torrent."<info hash>"]` section of the torrent in the config file,
+/// see `Cfg::for_torrent`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TorrentOverrides {
+    pub download_directory: Option<String>,
+    pub download_layout: Option<DownloadLayout>,
+    pub output_path: Option<String>,
+    pub max_peers_per_torrent: Option<u32>,
+    pub max_upload_slots: Option<u32>,
+    pub super_seeding: Option<bool>,
+    pub completed_directory: Option<String>,
+    pub copy_completed: Option<bool>,
+    pub on_complete_command: Option<String>,
+    pub seed_ratio_limit: Option<f64>,
+    pub seed_time_limit_seconds: Option<u64>,
+    pub max_download_kb_per_second: Option<u32>,
+    pub max_upload_kb_per_second: Option<u32>,
+    pub sequential_download: Option<bool>,
+}
+
+impl TorrentOverrides {
+    /// Loads a setting of the section of the torrent.
+    ///
+    /// # Errors
+    ///
+    /// - `io::Error` if the setting can not be overridden per torrent or its value is not valid.
+    pub fn load_setting(&mut self, name: &str, value: &str) -> io::Result<()> {
+        match name {
+            constants::DOWNLOAD_DIRECTORY => self.download_directory = Some(String::from(value)),
+
+            constants::DOWNLOAD_LAYOUT => {
+                self.download_layout = Some(Cfg::parse_setting(value, name)?);
+            }
+
+            constants::OUTPUT_PATH => self.output_path = Some(String::from(value)),
+
+            constants::MAX_PEERS_PER_TORRENT => {
+                self.max_peers_per_torrent = Some(Cfg::parse_setting(value, name)?);
+            }
+
+            constants::MAX_UPLOAD_SLOTS => {
+                self.max_upload_slots = Some(Cfg::parse_setting(value, name)?);
+            }
+
+            constants::SUPER_SEEDING => {
+                self.super_seeding = Some(Cfg::parse_setting(value, name)?);
+            }
+
+            constants::COMPLETED_DIRECTORY => self.completed_directory = Some(String::from(value)),
+
+            constants::COPY_COMPLETED => {
+                self.copy_completed = Some(Cfg::parse_setting(value, name)?);
+            }
+
+            constants::ON_COMPLETE_COMMAND => self.on_complete_command = Some(String::from(value)),
+
+            constants::SEED_RATIO_LIMIT => {
+                self.seed_ratio_limit = Some(Cfg::parse_setting(value, name)?);
+            }
+
+            constants::SEED_TIME_LIMIT_SECONDS => {
+                self.seed_time_limit_seconds = Some(Cfg::parse_setting(value, name)?);
+            }
+
+            constants::MAX_DOWNLOAD_KB_PER_SECOND => {
+                self.max_download_kb_per_second = Some(Cfg::parse_setting(value, name)?);
+            }
+
+            constants::MAX_UPLOAD_KB_PER_SECOND => {
+                self.max_upload_kb_per_second = Some(Cfg::parse_setting(value, name)?);
+            }
+
+            constants::SEQUENTIAL_DOWNLOAD => {
+                self.sequential_download = Some(Cfg::parse_setting(value, name)?);
+            }
+
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Setting can not be overridden per torrent: {}", name),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the settings of the config with the overridden ones.
+    pub fn apply(&self, config: &mut Cfg) {
+        if let Some(download_directory) = &self.download_directory {
+            config.download_directory = download_directory.clone();
+        }
+        if let Some(download_layout) = self.download_layout {
+            config.download_layout = download_layout;
+        }
+        if let Some(max_peers_per_torrent) = self.max_peers_per_torrent {
+            config.max_peers_per_torrent = max_peers_per_torrent;
+        }
+        if let Some(max_upload_slots) = self.max_upload_slots {
+            config.max_upload_slots = max_upload_slots;
+        }
+        if let Some(super_seeding) = self.super_seeding {
+            config.super_seeding = super_seeding;
+        }
+        if let Some(completed_directory) = &self.completed_directory {
+            config.completed_directory = completed_directory.clone();
+        }
+        if let Some(copy_completed) = self.copy_completed {
+            config.copy_completed = copy_completed;
+        }
+        if let Some(on_complete_command) = &self.on_complete_command {
+            config.on_complete_command = on_complete_command.clone();
+        }
+        if let Some(seed_ratio_limit) = self.seed_ratio_limit {
+            config.seed_ratio_limit = seed_ratio_limit;
+        }
+        if let Some(seed_time_limit_seconds) = self.seed_time_limit_seconds {
+            config.seed_time_limit_seconds = seed_time_limit_seconds;
+        }
+        if let Some(max_download_kb_per_second) = self.max_download_kb_per_second {
+            config.max_download_kb_per_second = max_download_kb_per_second;
+        }
+        if let Some(max_upload_kb_per_second) = self.max_upload_kb_per_second {
+            config.max_upload_kb_per_second = max_upload_kb_per_second;
+        }
+        if let Some(sequential_download) = self.sequential_download {
+            config.sequential_download = sequential_download;
+        }
+        config.overrides = self.clone();
+    }
+}
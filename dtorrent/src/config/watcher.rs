@@ -0,0 +1,376 @@
+use std::{
+    fs, io,
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use tracing::{info, warn};
+
+use super::cfg::Cfg;
+use crate::logger;
+
+/// Re-reads the config file when it changes and applies the settings that can be changed while running.
+///
+/// The runtime settings of the config and the log level are updated in place, so the running components
+/// use the new values without restarting. Changes to the other settings are only applied on the next start.
+///
+/// To create a new `ConfigWatcher` use `ConfigWatcher::new`.
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    path: String,
+    config: Cfg,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    /// Creates a new `ConfigWatcher` of the config file at `path`, which was loaded into `config`.
+    pub fn new(path: &str, config: Cfg) -> Self {
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        Self {
+            path: path.to_string(),
+            config,
+            modified,
+        }
+    }
+
+    /// Spawns a thread that checks the config file for changes every `interval`.
+    pub fn spawn(mut self, interval: Duration) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            if let Err(err) = self.check() {
+                warn!("Could not reload the config file: {:?}", err);
+            }
+        })
+    }
+
+    /// Reloads the config file if it was modified since the last check.
+    ///
+    /// Returns `true` if the config was reloaded.
+    ///
+    /// # Errors
+    ///
+    /// - `io::Error` if the config file could not be read or has a wrong format, the current settings are kept.
+    pub fn check(&mut self) -> io::Result<bool> {
+        let modified = fs::metadata(&self.path)?.modified()?;
+        if self.modified == Some(modified) {
+            return Ok(false);
+        }
+        self.modified = Some(modified);
+
+        let reloaded = Cfg::new(&self.path)?;
+        self.apply(&reloaded);
+        Ok(true)
+    }
+
+    fn apply(&mut self, reloaded: &Cfg) {
+        self.config.runtime.update(reloaded);
+        logger::set_level(reloaded.log_level);
+        for setting in &reloaded.ignored_settings {
+            warn!("Ignoring unknown config setting: {}", setting);
+        }
+
+        let restart_settings = restart_settings(&self.config, reloaded);
+        if !restart_settings.is_empty() {
+            warn!(
+                "The changes to these config settings apply on the next start: {}",
+                restart_settings.join(", ")
+            );
+        }
+        info!(
+            max_peers_per_torrent = reloaded.max_peers_per_torrent,
+            max_global_connections = reloaded.max_global_connections,
+            max_half_open_connections = reloaded.max_half_open_connections,
+            max_corrupted_pieces = reloaded.max_corrupted_pieces,
+            log_level = %reloaded.log_level,
+            "Config reloaded"
+        );
+    }
+}
+
+/// Returns the names of the settings that changed in `reloaded` but are only applied on the next start, that is
+/// every setting except the runtime settings and the log level.
+fn restart_settings(current: &Cfg, reloaded: &Cfg) -> Vec<&'static str> {
+    // Destructured without `..`, so a new setting does not compile until it is listed here.
+    let Cfg {
+        tcp_port,
+        log_directory,
+        download_directory,
+        pipelining_size,
+        read_write_seconds_timeout,
+        max_peers_per_torrent: _,
+        max_log_file_kb_size,
+        super_seeding,
+        max_global_connections: _,
+        max_upload_slots,
+        max_half_open_connections: _,
+        max_corrupted_pieces: _,
+        max_request_length,
+        request_timeout_seconds,
+        tcp_port_max,
+        port_mapping,
+        port_mapping_lifetime_seconds,
+        proxy_type,
+        proxy_host,
+        proxy_port,
+        proxy_username,
+        proxy_password,
+        proxy_peer_connections,
+        metrics_port,
+        max_log_files,
+        log_level: _,
+        log_format,
+        per_torrent_logs,
+        config_reload_seconds,
+        download_layout,
+        part_files,
+        completed_directory,
+        mmap_seeding,
+        block_size,
+        numwant,
+        max_connections_per_ip,
+        watch_directory,
+        watch_directory_seconds,
+        move_loaded_torrents,
+        copy_completed,
+        on_complete_command,
+        seed_ratio_limit,
+        seed_time_limit_seconds,
+        handshake_seconds_timeout,
+        hashing_threads,
+        max_download_kb_per_second,
+        max_upload_kb_per_second,
+        sequential_download,
+        runtime: _,
+        torrents,
+        // Only set on the config of a single torrent, from its section in `torrents`.
+        overrides: _,
+        ignored_settings: _,
+    } = current;
+
+    [
+        ("tcp_port", *tcp_port != reloaded.tcp_port),
+        ("log_directory", *log_directory != reloaded.log_directory),
+        (
+            "download_directory",
+            *download_directory != reloaded.download_directory,
+        ),
+        (
+            "pipelining_size",
+            *pipelining_size != reloaded.pipelining_size,
+        ),
+        (
+            "read_write_seconds_timeout",
+            *read_write_seconds_timeout != reloaded.read_write_seconds_timeout,
+        ),
+        (
+            "max_log_file_kb_size",
+            *max_log_file_kb_size != reloaded.max_log_file_kb_size,
+        ),
+        ("super_seeding", *super_seeding != reloaded.super_seeding),
+        (
+            "max_upload_slots",
+            *max_upload_slots != reloaded.max_upload_slots,
+        ),
+        (
+            "max_request_length",
+            *max_request_length != reloaded.max_request_length,
+        ),
+        (
+            "request_timeout_seconds",
+            *request_timeout_seconds != reloaded.request_timeout_seconds,
+        ),
+        ("tcp_port_max", *tcp_port_max != reloaded.tcp_port_max),
+        ("port_mapping", *port_mapping != reloaded.port_mapping),
+        (
+            "port_mapping_lifetime_seconds",
+            *port_mapping_lifetime_seconds != reloaded.port_mapping_lifetime_seconds,
+        ),
+        ("proxy_type", *proxy_type != reloaded.proxy_type),
+        ("proxy_host", *proxy_host != reloaded.proxy_host),
+        ("proxy_port", *proxy_port != reloaded.proxy_port),
+        ("proxy_username", *proxy_username != reloaded.proxy_username),
+        ("proxy_password", *proxy_password != reloaded.proxy_password),
+        (
+            "proxy_peer_connections",
+            *proxy_peer_connections != reloaded.proxy_peer_connections,
+        ),
+        ("metrics_port", *metrics_port != reloaded.metrics_port),
+        ("max_log_files", *max_log_files != reloaded.max_log_files),
+        ("log_format", *log_format != reloaded.log_format),
+        (
+            "per_torrent_logs",
+            *per_torrent_logs != reloaded.per_torrent_logs,
+        ),
+        (
+            "config_reload_seconds",
+            *config_reload_seconds != reloaded.config_reload_seconds,
+        ),
+        (
+            "download_layout",
+            *download_layout != reloaded.download_layout,
+        ),
+        ("part_files", *part_files != reloaded.part_files),
+        (
+            "completed_directory",
+            *completed_directory != reloaded.completed_directory,
+        ),
+        ("mmap_seeding", *mmap_seeding != reloaded.mmap_seeding),
+        ("block_size", *block_size != reloaded.block_size),
+        ("numwant", *numwant != reloaded.numwant),
+        (
+            "max_connections_per_ip",
+            *max_connections_per_ip != reloaded.max_connections_per_ip,
+        ),
+        (
+            "watch_directory",
+            *watch_directory != reloaded.watch_directory,
+        ),
+        (
+            "watch_directory_seconds",
+            *watch_directory_seconds != reloaded.watch_directory_seconds,
+        ),
+        (
+            "move_loaded_torrents",
+            *move_loaded_torrents != reloaded.move_loaded_torrents,
+        ),
+        ("copy_completed", *copy_completed != reloaded.copy_completed),
+        (
+            "on_complete_command",
+            *on_complete_command != reloaded.on_complete_command,
+        ),
+        (
+            "seed_ratio_limit",
+            *seed_ratio_limit != reloaded.seed_ratio_limit,
+        ),
+        (
+            "seed_time_limit_seconds",
+            *seed_time_limit_seconds != reloaded.seed_time_limit_seconds,
+        ),
+        (
+            "handshake_seconds_timeout",
+            *handshake_seconds_timeout != reloaded.handshake_seconds_timeout,
+        ),
+        (
+            "hashing_threads",
+            *hashing_threads != reloaded.hashing_threads,
+        ),
+        (
+            "max_download_kb_per_second",
+            *max_download_kb_per_second != reloaded.max_download_kb_per_second,
+        ),
+        (
+            "max_upload_kb_per_second",
+            *max_upload_kb_per_second != reloaded.max_upload_kb_per_second,
+        ),
+        (
+            "sequential_download",
+            *sequential_download != reloaded.sequential_download,
+        ),
+        ("torrent sections", *torrents != reloaded.torrents),
+    ]
+    .into_iter()
+    .filter(|(_, changed)| *changed)
+    .map(|(name, _)| name)
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::{config::torrent_overrides::TorrentOverrides, logger::log_level::LogLevel};
+
+    #[test]
+    fn test_reloads_runtime_settings_when_modified() {
+        let path = "./test_reloads_runtime_settings.cfg";
+        fs::write(path, "max_peers_per_torrent = 5").unwrap();
+        let config = Cfg::new(path).unwrap();
+        let mut watcher = ConfigWatcher::new(path, config.clone());
+
+        assert!(!watcher.check().unwrap());
+
+        fs::write(path, "max_peers_per_torrent = 10").unwrap();
+        set_modified(path, 60);
+
+        assert!(watcher.check().unwrap());
+        assert_eq!(config.runtime.max_peers_per_torrent(), 10);
+        assert_eq!(config.max_peers_per_torrent, 5);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_keeps_settings_on_invalid_config() {
+        let path = "./test_keeps_settings_on_invalid_config.cfg";
+        fs::write(path, "max_peers_per_torrent = 5").unwrap();
+        let config = Cfg::new(path).unwrap();
+        let mut watcher = ConfigWatcher::new(path, config.clone());
+
+        fs::write(path, "max_peers_per_torrent = many").unwrap();
+        set_modified(path, 60);
+
+        assert!(watcher.check().is_err());
+        assert_eq!(config.runtime.max_peers_per_torrent(), 5);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_restart_settings_lists_every_setting_not_applied_at_runtime() {
+        let current = Cfg::default();
+        let mut reloaded = Cfg {
+            max_peers_per_torrent: 80,
+            log_level: LogLevel::Debug,
+            max_upload_slots: 9,
+            super_seeding: true,
+            tcp_port_max: 6999,
+            completed_directory: "./done".to_string(),
+            watch_directory: "./watch".to_string(),
+            ..Cfg::default()
+        };
+        reloaded.torrents.insert(
+            "c9e15763f722f23e98a29decdfae341b98d53056".to_string(),
+            TorrentOverrides {
+                max_peers_per_torrent: Some(50),
+                ..TorrentOverrides::default()
+            },
+        );
+
+        assert_eq!(
+            restart_settings(&current, &reloaded),
+            vec![
+                "super_seeding",
+                "max_upload_slots",
+                "tcp_port_max",
+                "completed_directory",
+                "watch_directory",
+                "torrent sections",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_restart_settings_when_only_runtime_settings_change() {
+        let current = Cfg::default();
+        let reloaded = Cfg {
+            max_global_connections: 10,
+            max_corrupted_pieces: 1,
+            log_level: LogLevel::Warn,
+            ..Cfg::default()
+        };
+
+        assert!(restart_settings(&current, &reloaded).is_empty());
+    }
+
+    /// Moves the modification time of the file forward, as writes close in time can keep the same one.
+    fn set_modified(path: &str, seconds: u64) {
+        let modified = SystemTime::now() + Duration::from_secs(seconds);
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+}
@@ -0,0 +1,23 @@
+use std::{
+    io::{self, Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    path::Path,
+};
+
+use super::{command::ControlCommand, response::ControlResponse};
+
+/// Sends `command` to the dtorrent listening on the control socket at `socket_path` and returns its response.
+///
+/// # Errors
+///
+/// - `io::Error` if the socket could not be connected to, or the command or response could not be sent.
+pub fn send_command(socket_path: &Path, command: &ControlCommand) -> io::Result<ControlResponse> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    stream.write_all(format!("{}\n", command).as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut text = String::new();
+    stream.read_to_string(&mut text)?;
+    Ok(ControlResponse::from_text(&text))
+}
@@ -0,0 +1,211 @@
+use std::{fmt, path::PathBuf};
+
+use url_encoder::info_hash::InfoHash;
+
+/// Command sent to a running dtorrent through its control socket, one per line.
+///
+/// - `add <path>`: starts the torrent of the `.torrent` file at `path`, which must be absolute,
+/// - `list`: lists the torrents with their state and progress,
+/// - `pause <info hash>`: pauses a torrent, disconnecting its peers,
+/// - `resume <info hash>`: resumes a paused torrent,
+/// - `remove <info hash> [--delete-data]`: removes a torrent, deleting its downloaded data with `--delete-data`,
+/// - `magnet <info hash>`: answers the magnet link of a torrent,
+/// - `quit`: stops dtorrent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlCommand {
+    Add(PathBuf),
+    List,
+    Pause(InfoHash),
+    Resume(InfoHash),
+    Remove {
+        info_hash: InfoHash,
+        delete_data: bool,
+    },
+    Magnet(InfoHash),
+    Quit,
+}
+
+/// Option of `remove` to delete the downloaded data of the torrent.
+const DELETE_DATA_OPTION: &str = "--delete-data";
+
+/// Posible `ControlCommand` parsing errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ControlCommandError {
+    EmptyCommand,
+    UnknownCommand(String),
+    MissingArgument(String),
+    InvalidInfoHash(String),
+    UnknownOption(String),
+}
+
+impl ControlCommand {
+    /// Parses a command line, like `pause 2c6b6858d61da9543d4231a71db4b1c9264b0685`.
+    ///
+    /// # Errors
+    ///
+    /// - `EmptyCommand` if the line is blank.
+    /// - `UnknownCommand` if the command is not one of the known ones.
+    /// - `MissingArgument` if the command needs an argument that is not in the line.
+    /// - `InvalidInfoHash` if the info hash is not 40 hex characters.
+    /// - `UnknownOption` if the option of `remove` is not `--delete-data`.
+    pub fn parse(line: &str) -> Result<Self, ControlCommandError> {
+        let line = line.trim();
+        let (name, argument) = match line.split_once(' ') {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (line, ""),
+        };
+
+        match name {
+            "" => Err(ControlCommandError::EmptyCommand),
+            "add" => Ok(ControlCommand::Add(PathBuf::from(Self::argument(
+                name, argument,
+            )?))),
+            "list" => Ok(ControlCommand::List),
+            "pause" => Ok(ControlCommand::Pause(Self::info_hash(name, argument)?)),
+            "resume" => Ok(ControlCommand::Resume(Self::info_hash(name, argument)?)),
+            "remove" => {
+                let (info_hash, option) = match argument.split_once(' ') {
+                    Some((info_hash, option)) => (info_hash, option.trim()),
+                    None => (argument, ""),
+                };
+                let delete_data = match option {
+                    "" => false,
+                    DELETE_DATA_OPTION => true,
+                    _ => return Err(ControlCommandError::UnknownOption(option.to_string())),
+                };
+                Ok(ControlCommand::Remove {
+                    info_hash: Self::info_hash(name, info_hash)?,
+                    delete_data,
+                })
+            }
+            "magnet" => Ok(ControlCommand::Magnet(Self::info_hash(name, argument)?)),
+            "quit" => Ok(ControlCommand::Quit),
+            _ => Err(ControlCommandError::UnknownCommand(name.to_string())),
+        }
+    }
+
+    fn argument<'a>(name: &str, argument: &'a str) -> Result<&'a str, ControlCommandError> {
+        if argument.is_empty() {
+            return Err(ControlCommandError::MissingArgument(name.to_string()));
+        }
+        Ok(argument)
+    }
+
+    fn info_hash(name: &str, argument: &str) -> Result<InfoHash, ControlCommandError> {
+        let argument = Self::argument(name, argument)?;
+        InfoHash::from_hex(argument)
+            .map_err(|_| ControlCommandError::InvalidInfoHash(argument.to_string()))
+    }
+}
+
+impl fmt::Display for ControlCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlCommand::Add(path) => write!(f, "add {}", path.display()),
+            ControlCommand::List => write!(f, "list"),
+            ControlCommand::Pause(info_hash) => write!(f, "pause {}", info_hash),
+            ControlCommand::Resume(info_hash) => write!(f, "resume {}", info_hash),
+            ControlCommand::Remove {
+                info_hash,
+                delete_data: false,
+            } => write!(f, "remove {}", info_hash),
+            ControlCommand::Remove {
+                info_hash,
+                delete_data: true,
+            } => write!(f, "remove {} {}", info_hash, DELETE_DATA_OPTION),
+            ControlCommand::Magnet(info_hash) => write!(f, "magnet {}", info_hash),
+            ControlCommand::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: &str = "2c6b6858d61da9543d4231a71db4b1c9264b0685";
+
+    #[test]
+    fn test_parse_commands() {
+        let info_hash = InfoHash::from_hex(HEX).unwrap();
+
+        assert_eq!(
+            ControlCommand::parse("add /torrents/my file.torrent\n"),
+            Ok(ControlCommand::Add(PathBuf::from(
+                "/torrents/my file.torrent"
+            )))
+        );
+        assert_eq!(ControlCommand::parse("list"), Ok(ControlCommand::List));
+        assert_eq!(
+            ControlCommand::parse(&format!("pause {}", HEX)),
+            Ok(ControlCommand::Pause(info_hash))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("resume {}", HEX)),
+            Ok(ControlCommand::Resume(info_hash))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("remove {}", HEX)),
+            Ok(ControlCommand::Remove {
+                info_hash,
+                delete_data: false
+            })
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("remove {} --delete-data", HEX)),
+            Ok(ControlCommand::Remove {
+                info_hash,
+                delete_data: true
+            })
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("magnet {}", HEX)),
+            Ok(ControlCommand::Magnet(info_hash))
+        );
+        assert_eq!(ControlCommand::parse("quit"), Ok(ControlCommand::Quit));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            ControlCommand::parse("  "),
+            Err(ControlCommandError::EmptyCommand)
+        );
+        assert_eq!(
+            ControlCommand::parse("start"),
+            Err(ControlCommandError::UnknownCommand("start".to_string()))
+        );
+        assert_eq!(
+            ControlCommand::parse("add"),
+            Err(ControlCommandError::MissingArgument("add".to_string()))
+        );
+        assert_eq!(
+            ControlCommand::parse("pause abc"),
+            Err(ControlCommandError::InvalidInfoHash("abc".to_string()))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("remove {} --all", HEX)),
+            Err(ControlCommandError::UnknownOption("--all".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_display_is_parsed_back() {
+        let commands = [
+            ControlCommand::Add(PathBuf::from("/torrents/file.torrent")),
+            ControlCommand::List,
+            ControlCommand::Pause(InfoHash::from_hex(HEX).unwrap()),
+            ControlCommand::Resume(InfoHash::from_hex(HEX).unwrap()),
+            ControlCommand::Remove {
+                info_hash: InfoHash::from_hex(HEX).unwrap(),
+                delete_data: true,
+            },
+            ControlCommand::Magnet(InfoHash::from_hex(HEX).unwrap()),
+            ControlCommand::Quit,
+        ];
+
+        for command in commands {
+            assert_eq!(ControlCommand::parse(&command.to_string()), Ok(command));
+        }
+    }
+}
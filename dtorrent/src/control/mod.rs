@@ -0,0 +1,9 @@
+#[cfg(unix)]
+pub mod client;
+pub mod command;
+pub mod response;
+#[cfg(unix)]
+pub mod server;
+
+/// Path of the control socket used when none is given.
+pub const DEFAULT_CONTROL_SOCKET: &str = "./dtorrent.sock";
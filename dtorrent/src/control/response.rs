@@ -0,0 +1,63 @@
+/// Response of dtorrent to a `ControlCommand`, sent back through the control socket.
+///
+/// The first line is `ok` or `error <message>`, an `ok` is followed by the lines of the result, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlResponse {
+    Ok(Vec<String>),
+    Error(String),
+}
+
+impl ControlResponse {
+    /// Returns the lines of the response, each one ending in a new line.
+    pub fn to_text(&self) -> String {
+        match self {
+            ControlResponse::Ok(lines) => {
+                let mut text = String::from("ok\n");
+                for line in lines {
+                    text.push_str(line);
+                    text.push('\n');
+                }
+                text
+            }
+            ControlResponse::Error(message) => format!("error {}\n", message),
+        }
+    }
+
+    /// Parses the text of a response, an unexpected first line is returned as an error.
+    pub fn from_text(text: &str) -> Self {
+        let mut lines = text.lines();
+        match lines.next() {
+            Some("ok") => ControlResponse::Ok(lines.map(String::from).collect()),
+            Some(line) => {
+                ControlResponse::Error(line.strip_prefix("error ").unwrap_or(line).to_string())
+            }
+            None => ControlResponse::Error("empty response".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_is_parsed_back() {
+        let responses = [
+            ControlResponse::Ok(vec![]),
+            ControlResponse::Ok(vec!["first".to_string(), "second".to_string()]),
+            ControlResponse::Error("unknown torrent".to_string()),
+        ];
+
+        for response in responses {
+            assert_eq!(ControlResponse::from_text(&response.to_text()), response);
+        }
+    }
+
+    #[test]
+    fn test_empty_response_is_an_error() {
+        assert_eq!(
+            ControlResponse::from_text(""),
+            ControlResponse::Error("empty response".to_string())
+        );
+    }
+}
@@ -0,0 +1,487 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
+
+use tracing::{error, info, warn};
+
+use super::{command::ControlCommand, response::ControlResponse};
+use crate::{
+    bt_server::server::SharedTorrents,
+    config::cfg::Cfg,
+    nat::{self, SharedPortMapping},
+    torrent_handler::{
+        connection_registry::PeerConnectionRegistry, handler::TorrentHandler,
+        status::AtomicTorrentStatus,
+    },
+    torrent_parser::parser::TorrentParser,
+    tracker::tracker_handler::TrackerHandler,
+};
+use url_encoder::info_hash::InfoHash;
+
+/// Server of the control socket of a running dtorrent, a Unix domain socket that accepts one `ControlCommand`
+/// per connection and answers with a `ControlResponse`, see `client::send_command`.
+///
+/// The torrents are the ones served by the `BtServer`, the added ones are started with a `TorrentHandler` and
+/// served by it too. The socket file is removed when the server is dropped.
+///
+/// To create a new `ControlServer` use `ControlServer::bind`.
+#[derive(Debug)]
+pub struct ControlServer {
+    listener: UnixListener,
+    path: PathBuf,
+    torrents: SharedTorrents,
+    config: Cfg,
+    client_peer_id: String,
+    client_port: u16,
+    port_mapping: SharedPortMapping,
+    connection_registry: Arc<PeerConnectionRegistry>,
+}
+
+impl ControlServer {
+    /// Binds the control socket at `path`, replacing the socket file a previous run did not remove.
+    ///
+    /// # Errors
+    ///
+    /// - `io::Error` if the socket could not be bound.
+    pub fn bind(
+        path: &Path,
+        torrents: SharedTorrents,
+        config: Cfg,
+        client_peer_id: String,
+    ) -> io::Result<Self> {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+            torrents,
+            client_port: config.tcp_port,
+            port_mapping: Arc::default(),
+            connection_registry: Arc::new(PeerConnectionRegistry::new(
+                config.max_connections_per_ip,
+            )),
+            config,
+            client_peer_id,
+        })
+    }
+
+    /// Sets the port announced to the tracker by the added torrents, it must be the port the `BtServer` is bound to.
+    pub fn set_client_port(&mut self, client_port: u16) {
+        self.client_port = client_port;
+    }
+
+    /// Sets the port mapping announced by the added torrents, see `TorrentHandler::set_port_mapping`.
+    pub fn set_port_mapping(&mut self, port_mapping: SharedPortMapping) {
+        self.port_mapping = port_mapping;
+    }
+
+    /// Shares the registry of outgoing peer connections with the added torrents.
+    pub fn set_connection_registry(&mut self, connection_registry: Arc<PeerConnectionRegistry>) {
+        self.connection_registry = connection_registry;
+    }
+
+    /// Serves the commands one connection at a time, until a `quit` command is received.
+    ///
+    /// # Errors
+    ///
+    /// - `io::Error` if a connection could not be accepted.
+    pub fn run(&self) -> io::Result<()> {
+        info!("Listening for commands on {}", self.path.display());
+        for stream in self.listener.incoming() {
+            match self.handle_connection(stream?) {
+                Ok(true) => return Ok(()),
+                Ok(false) => (),
+                Err(err) => warn!("Could not handle control connection: {:?}", err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers the command of a connection, returns `true` if it was `quit`.
+    fn handle_connection(&self, mut stream: UnixStream) -> io::Result<bool> {
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+
+        let (response, quit) = match ControlCommand::parse(&line) {
+            Ok(command) => {
+                info!("Control command: {}", command);
+                (self.execute(&command), command == ControlCommand::Quit)
+            }
+            Err(err) => (ControlResponse::Error(format!("{:?}", err)), false),
+        };
+        stream.write_all(response.to_text().as_bytes())?;
+        Ok(quit)
+    }
+
+    fn execute(&self, command: &ControlCommand) -> ControlResponse {
+        match command {
+            ControlCommand::Add(path) => self.add(path),
+            ControlCommand::List => self.list(),
+            ControlCommand::Pause(info_hash) => self.with_torrent(info_hash, |status| {
+                status.pause().map_err(|err| format!("{:?}", err))
+            }),
+            ControlCommand::Resume(info_hash) => self.with_torrent(info_hash, |status| {
+                status.resume();
+                Ok(())
+            }),
+            ControlCommand::Remove {
+                info_hash,
+                delete_data,
+            } => self.remove(info_hash, *delete_data),
+            ControlCommand::Magnet(info_hash) => self.magnet(info_hash),
+            ControlCommand::Quit => ControlResponse::Ok(vec![]),
+        }
+    }
+
+    /// Removes a torrent, so the `BtServer` stops serving it, and stops its peer sessions and its `TorrentHandler`,
+    /// see `AtomicTorrentStatus::remove`.
+    ///
+    /// The `stopped` event is announced to the trackers the torrent announced to, and its downloaded data is
+    /// deleted with `delete_data`.
+    fn remove(&self, info_hash: &InfoHash, delete_data: bool) -> ControlResponse {
+        let status = match self.torrents.write() {
+            Ok(mut torrents) => torrents.remove(info_hash),
+            Err(_) => return ControlResponse::Error("poisoned torrents lock".to_string()),
+        };
+        let status = match status {
+            Some(status) => status,
+            None => return ControlResponse::Error(format!("unknown torrent {}", info_hash)),
+        };
+        if let Err(err) = status.remove() {
+            return ControlResponse::Error(format!("{:?}", err));
+        }
+        self.announce_stopped(&status);
+
+        if delete_data {
+            if let Err(err) = status.delete_data() {
+                return ControlResponse::Error(format!("{:?}", err));
+            }
+        }
+        ControlResponse::Ok(vec![])
+    }
+
+    /// Announces the `stopped` event of a torrent to the trackers that answered its announces.
+    fn announce_stopped(&self, status: &AtomicTorrentStatus) {
+        let mut tracker_handler = match TrackerHandler::new(
+            status.torrent.clone(),
+            self.client_port.into(),
+            self.client_peer_id.clone(),
+        ) {
+            Ok(tracker_handler) => tracker_handler,
+            Err(err) => {
+                warn!("Could not announce the removed torrent: {:?}", err);
+                return;
+            }
+        };
+        tracker_handler.set_proxy(status.config().proxy());
+        tracker_handler.set_port_mapping(nat::current_mapping(&self.port_mapping));
+        match (status.trackers_health(), status.stats()) {
+            (Ok(health), Ok(stats)) => {
+                tracker_handler.set_health(health);
+                tracker_handler.announce_stopped(&stats);
+            }
+            (Err(err), _) | (_, Err(err)) => {
+                warn!("Could not announce the removed torrent: {:?}", err)
+            }
+        }
+    }
+
+    /// Starts the torrent of a `.torrent` file, answering with its info hash and name.
+    fn add(&self, path: &Path) -> ControlResponse {
+        let torrent = match TorrentParser::parse(path) {
+            Ok(torrent) => torrent,
+            Err(err) => {
+                return ControlResponse::Error(format!(
+                    "could not load {}: {:?}",
+                    path.display(),
+                    err
+                ))
+            }
+        };
+        let info_hash = torrent.info_hash();
+        let name = torrent.name();
+
+        let mut torrent_handler =
+            TorrentHandler::new(torrent, self.config.clone(), self.client_peer_id.clone());
+        torrent_handler.set_client_port(self.client_port);
+        torrent_handler.set_port_mapping(self.port_mapping.clone());
+        torrent_handler.set_connection_registry(self.connection_registry.clone());
+        torrent_handler.set_torrents(self.torrents.clone());
+
+        match self.torrents.write() {
+            Ok(mut torrents) => {
+                if torrents.contains_key(&info_hash) {
+                    return ControlResponse::Error(format!("torrent already added {}", info_hash));
+                }
+                torrents.insert(info_hash, torrent_handler.status());
+            }
+            Err(_) => return ControlResponse::Error("poisoned torrents lock".to_string()),
+        }
+
+        thread::spawn(move || {
+            if let Err(err) = torrent_handler.handle() {
+                error!("Torrent added through the control socket failed: {:?}", err);
+            }
+        });
+        ControlResponse::Ok(vec![format!("{} {}", info_hash, name)])
+    }
+
+    /// Answers a line per torrent with its info hash, state, progress and name, ordered by info hash.
+    fn list(&self) -> ControlResponse {
+        let torrents = match self.torrents.read() {
+            Ok(torrents) => torrents,
+            Err(_) => return ControlResponse::Error("poisoned torrents lock".to_string()),
+        };
+        let mut info_hashes: Vec<_> = torrents.keys().collect();
+        info_hashes.sort();
+
+        let mut lines = Vec::new();
+        for info_hash in info_hashes {
+            let status = &torrents[info_hash];
+            let progress = match status.progress() {
+                Ok(progress) => progress,
+                Err(err) => return ControlResponse::Error(format!("{:?}", err)),
+            };
+            lines.push(format!(
+                "{} {} {:.1}% {}",
+                info_hash,
+                state(status),
+                progress * 100.0,
+                status.torrent.name()
+            ));
+        }
+        ControlResponse::Ok(lines)
+    }
+
+    /// Answers the magnet link of a torrent, see `Torrent::to_magnet`.
+    fn magnet(&self, info_hash: &InfoHash) -> ControlResponse {
+        match self.torrents.read() {
+            Ok(torrents) => match torrents.get(info_hash) {
+                Some(status) => ControlResponse::Ok(vec![status.torrent.to_magnet()]),
+                None => ControlResponse::Error(format!("unknown torrent {}", info_hash)),
+            },
+            Err(_) => ControlResponse::Error("poisoned torrents lock".to_string()),
+        }
+    }
+
+    fn with_torrent<F>(&self, info_hash: &InfoHash, f: F) -> ControlResponse
+    where
+        F: FnOnce(&AtomicTorrentStatus) -> Result<(), String>,
+    {
+        let status = match self.torrents.read() {
+            Ok(torrents) => torrents.get(info_hash).cloned(),
+            Err(_) => return ControlResponse::Error("poisoned torrents lock".to_string()),
+        };
+        match status {
+            Some(status) => match f(&status) {
+                Ok(()) => ControlResponse::Ok(vec![]),
+                Err(err) => ControlResponse::Error(err),
+            },
+            None => ControlResponse::Error(format!("unknown torrent {}", info_hash)),
+        }
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Returns the state of a torrent shown by `list`.
+fn state(status: &AtomicTorrentStatus) -> &'static str {
+    if status.is_paused() {
+        "paused"
+    } else if status.is_seeding_stopped() {
+        "stopped"
+    } else if status.is_finished() {
+        "seeding"
+    } else {
+        "downloading"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{mpsc, RwLock},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{
+        control::client::send_command,
+        test_support::{
+            fixtures::{test_config, test_torrent},
+            local_swarm::generated_torrent,
+            mock_tracker::{peers_response, MockTracker},
+            temp_dir::TempDir,
+        },
+        torrent_handler::torrent_watcher::TorrentWatcher,
+    };
+
+    fn start_server(dir: &TempDir, torrents: SharedTorrents) -> (PathBuf, mpsc::Receiver<()>) {
+        let path = dir.join("dtorrent.sock");
+        let server = ControlServer::bind(
+            &path,
+            torrents,
+            test_config(dir),
+            "client_peer_id".to_string(),
+        )
+        .unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            server.run().unwrap();
+            drop(server);
+            let _ = sender.send(());
+        });
+        (path, receiver)
+    }
+
+    #[test]
+    fn test_list_pause_and_resume() {
+        let dir = TempDir::new("test_control_server_list_pause_and_resume");
+        let torrent = test_torrent("movie.mkv", "announce");
+        let info_hash = torrent.info_hash();
+        let status = Arc::new(AtomicTorrentStatus::new(&torrent, test_config(&dir)));
+        let torrents = Arc::new(RwLock::new(HashMap::from([(info_hash, status.clone())])));
+        let (path, _) = start_server(&dir, torrents);
+
+        assert_eq!(
+            send_command(&path, &ControlCommand::List).unwrap(),
+            ControlResponse::Ok(vec![format!("{} downloading 0.0% movie.mkv", info_hash)])
+        );
+
+        send_command(&path, &ControlCommand::Pause(info_hash)).unwrap();
+        assert!(status.is_paused());
+        assert_eq!(
+            send_command(&path, &ControlCommand::List).unwrap(),
+            ControlResponse::Ok(vec![format!("{} paused 0.0% movie.mkv", info_hash)])
+        );
+
+        send_command(&path, &ControlCommand::Resume(info_hash)).unwrap();
+        assert!(!status.is_paused());
+    }
+
+    #[test]
+    fn test_lists_the_torrents_started_by_the_watcher() {
+        let dir = TempDir::new("test_control_server_watcher_torrents");
+        let torrents = SharedTorrents::default();
+        let (path, _) = start_server(&dir, torrents.clone());
+        let watcher =
+            TorrentWatcher::new(test_config(&dir), "client_peer_id".to_string(), torrents);
+        let (torrent, _) =
+            generated_torrent("watched", 4 * 16384, 16384, "http://127.0.0.1:1/announce");
+
+        watcher.start(torrent.clone()).unwrap();
+
+        match send_command(&path, &ControlCommand::List).unwrap() {
+            ControlResponse::Ok(lines) => {
+                assert_eq!(lines.len(), 1);
+                assert!(lines[0].starts_with(&torrent.info_hash().to_string()));
+                assert!(lines[0].ends_with(" watched"));
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_magnet() {
+        let dir = TempDir::new("test_control_server_magnet");
+        let torrent = test_torrent("movie.mkv", "http://tracker.com/announce");
+        let info_hash = torrent.info_hash();
+        let status = Arc::new(AtomicTorrentStatus::new(&torrent, test_config(&dir)));
+        let torrents = Arc::new(RwLock::new(HashMap::from([(info_hash, status)])));
+        let (path, _) = start_server(&dir, torrents);
+
+        assert_eq!(
+            send_command(&path, &ControlCommand::Magnet(info_hash)).unwrap(),
+            ControlResponse::Ok(vec![format!(
+                "magnet:?xt=urn:btih:{}&dn=movie.mkv&tr=http%3A%2F%2Ftracker.com%2Fannounce",
+                info_hash
+            )])
+        );
+    }
+
+    #[test]
+    fn test_remove_deletes_the_data_and_announces_stopped() {
+        let dir = TempDir::new("test_control_server_remove");
+        let tracker = MockTracker::start(peers_response(60, &[]));
+        let torrent = test_torrent("movie.mkv", &tracker.announce_url());
+        let info_hash = torrent.info_hash();
+        let status = Arc::new(AtomicTorrentStatus::new(&torrent, test_config(&dir)));
+        status.allocate().unwrap();
+        let mut tracker_handler =
+            TrackerHandler::new(torrent, 6881, "client_peer_id".to_string()).unwrap();
+        tracker_handler
+            .get_peers_list(&status.stats().unwrap())
+            .unwrap();
+        status
+            .update_trackers_health(tracker_handler.health())
+            .unwrap();
+        let session_token = status.cancellation_token().unwrap();
+        let torrents = Arc::new(RwLock::new(HashMap::from([(info_hash, status.clone())])));
+        let (path, _) = start_server(&dir, torrents.clone());
+
+        let remove = ControlCommand::Remove {
+            info_hash,
+            delete_data: true,
+        };
+        assert_eq!(
+            send_command(&path, &remove).unwrap(),
+            ControlResponse::Ok(vec![])
+        );
+
+        assert!(torrents.read().unwrap().is_empty());
+        assert!(status.is_removed());
+        assert!(session_token.is_cancelled());
+        assert!(!status.output_path().unwrap().exists());
+        assert!(tracker
+            .requests()
+            .last()
+            .unwrap()
+            .contains("&event=stopped"));
+        assert_eq!(
+            send_command(&path, &remove).unwrap(),
+            ControlResponse::Error(format!("unknown torrent {}", info_hash))
+        );
+    }
+
+    #[test]
+    fn test_errors_are_answered() {
+        let dir = TempDir::new("test_control_server_errors");
+        let (path, _) = start_server(&dir, SharedTorrents::default());
+        let info_hash = InfoHash::new([1; 20]);
+
+        assert_eq!(
+            send_command(&path, &ControlCommand::Pause(info_hash)).unwrap(),
+            ControlResponse::Error(format!("unknown torrent {}", info_hash))
+        );
+        assert!(matches!(
+            send_command(&path, &ControlCommand::Add(dir.join("missing.torrent"))).unwrap(),
+            ControlResponse::Error(message) if message.starts_with("could not load")
+        ));
+    }
+
+    #[test]
+    fn test_quit_stops_the_server() {
+        let dir = TempDir::new("test_control_server_quit");
+        let (path, stopped) = start_server(&dir, SharedTorrents::default());
+
+        assert_eq!(
+            send_command(&path, &ControlCommand::Quit).unwrap(),
+            ControlResponse::Ok(vec![])
+        );
+        stopped.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(!path.exists());
+    }
+}
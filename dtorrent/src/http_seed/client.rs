@@ -0,0 +1,329 @@
+use native_tls::TlsConnector;
+use std::cell::Cell;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::ops::Range;
+use std::time::Duration;
+
+use url_encoder::info_hash::InfoHash;
+
+use crate::proxy::{Proxy, ProxyError};
+
+/// Max size in bytes of the status line and headers of a response.
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+/// Seconds to wait before asking a busy seed again when it does not say how long to wait.
+const DEFAULT_RETRY_SECS: u64 = 30;
+
+/// Client of an HTTP seed (BEP 17), a web server that serves the pieces of a torrent by their index.
+///
+/// A piece, or some byte ranges of it, is requested with
+/// `GET <url>?info_hash=<info hash>&piece=<index>&ranges=<start>-<end>,...`, where the ends of the ranges are
+/// inclusive. A seed too busy to serve the request answers `503 Service Unavailable` with the seconds to wait
+/// before asking again as the body.
+///
+/// The bytes sent and received by the last request are kept, see `traffic()`.
+///
+/// To create a new `HttpSeed` use `HttpSeed::new`.
+#[derive(Debug)]
+pub struct HttpSeed {
+    url: String,
+    https: bool,
+    host: String,
+    port: u16,
+    path: String,
+    proxy: Option<Proxy>,
+    traffic: Cell<(u64, u64)>,
+}
+
+/// Posible `HttpSeed` errors.
+#[derive(Debug)]
+pub enum HttpSeedError {
+    InvalidUrl(String),
+    ConnectError(io::Error),
+    ProxyConnectError(ProxyError),
+    CreateTlsConnectorError(native_tls::Error),
+    TlsHandshakeError,
+    ErrorWritingStream(io::Error),
+    ErrorReadingStream(io::Error),
+    InvalidResponse,
+    ResponseTooLarge,
+    UnexpectedStatus(u16),
+    /// The seed is busy, it must not be asked again before the duration elapses.
+    Busy(Duration),
+}
+
+impl HttpSeed {
+    /// Builds an `HttpSeed` from its url, like `http://example.com/seed.php`.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidUrl` if the url is not an `http` or `https` url with a valid port.
+    pub fn new(url: &str) -> Result<Self, HttpSeedError> {
+        let invalid_url = || HttpSeedError::InvalidUrl(url.to_string());
+        let (https, rest) = if let Some(rest) = url.strip_prefix("http://") {
+            (false, rest)
+        } else if let Some(rest) = url.strip_prefix("https://") {
+            (true, rest)
+        } else {
+            return Err(invalid_url());
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(start) => rest.split_at(start),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid_url())?),
+            None if https => (authority, 443),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(invalid_url());
+        }
+
+        Ok(Self {
+            url: url.to_string(),
+            https,
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            proxy: None,
+            traffic: Cell::new((0, 0)),
+        })
+    }
+
+    /// Makes the requests through `proxy`, if any.
+    pub fn with_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Returns the url of the seed.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Requests `ranges` of the piece at `index`, or the whole piece if there are none, and returns their bytes
+    /// one after the other.
+    ///
+    /// `max_length` is the number of bytes expected, a longer body is rejected.
+    ///
+    /// # Errors
+    ///
+    /// - `ConnectError`, `ProxyConnectError`, `CreateTlsConnectorError` or `TlsHandshakeError` if the seed could
+    ///   not be reached.
+    /// - `ErrorWritingStream` or `ErrorReadingStream` if the request could not be sent or the response read.
+    /// - `InvalidResponse` if the response has no valid status line.
+    /// - `ResponseTooLarge` if the response is longer than expected.
+    /// - `Busy` if the seed answered `503`, with the time to wait before asking again.
+    /// - `UnexpectedStatus` for any other status but `200`.
+    pub fn fetch(
+        &self,
+        info_hash: &InfoHash,
+        index: u32,
+        ranges: &[Range<u32>],
+        max_length: usize,
+    ) -> Result<Vec<u8>, HttpSeedError> {
+        let stream = self.connect_tcp_stream()?;
+        if !self.https {
+            return self.request(stream, info_hash, index, ranges, max_length);
+        }
+
+        let connector = TlsConnector::new().map_err(HttpSeedError::CreateTlsConnectorError)?;
+        let stream = connector
+            .connect(&self.host, stream)
+            .map_err(|_| HttpSeedError::TlsHandshakeError)?;
+        self.request(stream, info_hash, index, ranges, max_length)
+    }
+
+    /// Returns the bytes of the last request sent and of its response received, headers included.
+    pub fn traffic(&self) -> (u64, u64) {
+        self.traffic.get()
+    }
+
+    fn connect_tcp_stream(&self) -> Result<TcpStream, HttpSeedError> {
+        match &self.proxy {
+            Some(proxy) => proxy
+                .connect(&self.host, self.port)
+                .map_err(HttpSeedError::ProxyConnectError),
+            None => TcpStream::connect((self.host.as_str(), self.port))
+                .map_err(HttpSeedError::ConnectError),
+        }
+    }
+
+    fn request<S>(
+        &self,
+        mut stream: S,
+        info_hash: &InfoHash,
+        index: u32,
+        ranges: &[Range<u32>],
+        max_length: usize,
+    ) -> Result<Vec<u8>, HttpSeedError>
+    where
+        S: Read + Write,
+    {
+        // HTTP/1.0 so the body is never chunked.
+        let request = format!(
+            "GET {} HTTP/1.0\r\nHost: {}:{}\r\nUser-Agent: LDTorrent/0.1\r\nConnection: close\r\n\r\n",
+            self.target(info_hash, index, ranges),
+            self.host,
+            self.port
+        );
+        stream
+            .write_all(request.as_bytes())
+            .map_err(HttpSeedError::ErrorWritingStream)?;
+        let sent_bytes = request.len() as u64;
+        self.traffic.set((sent_bytes, 0));
+
+        let limit = MAX_HEAD_SIZE + max_length;
+        let mut response = vec![];
+        let read = (&mut stream)
+            .take(limit as u64 + 1)
+            .read_to_end(&mut response);
+        self.traffic.set((sent_bytes, response.len() as u64));
+        read.map_err(HttpSeedError::ErrorReadingStream)?;
+        if response.len() > limit {
+            return Err(HttpSeedError::ResponseTooLarge);
+        }
+
+        let (status, body) = parse_response(&response)?;
+        match status {
+            200 if body.len() > max_length => Err(HttpSeedError::ResponseTooLarge),
+            200 => Ok(body.to_vec()),
+            503 => {
+                let secs = String::from_utf8_lossy(body)
+                    .trim()
+                    .parse()
+                    .unwrap_or(DEFAULT_RETRY_SECS);
+                Err(HttpSeedError::Busy(Duration::from_secs(secs)))
+            }
+            status => Err(HttpSeedError::UnexpectedStatus(status)),
+        }
+    }
+
+    /// Returns the path and query of the request of `ranges` of the piece at `index`.
+    fn target(&self, info_hash: &InfoHash, index: u32, ranges: &[Range<u32>]) -> String {
+        let separator = if self.path.contains('?') { '&' } else { '?' };
+        let mut target = format!(
+            "{}{}info_hash={}&piece={}",
+            self.path,
+            separator,
+            info_hash.url_encoded(),
+            index
+        );
+        if !ranges.is_empty() {
+            let ranges: Vec<String> = ranges
+                .iter()
+                .map(|range| format!("{}-{}", range.start, range.end.saturating_sub(1)))
+                .collect();
+            target.push_str("&ranges=");
+            target.push_str(&ranges.join(","));
+        }
+        target
+    }
+}
+
+/// Returns the status code and the body of a response.
+///
+/// # Errors
+///
+/// - `InvalidResponse` if the response has no headers or its status line has no valid code.
+fn parse_response(response: &[u8]) -> Result<(u16, &[u8]), HttpSeedError> {
+    let end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or(HttpSeedError::InvalidResponse)?;
+    let head = String::from_utf8_lossy(&response[..end]);
+    let status = head
+        .lines()
+        .next()
+        .and_then(|status_line| status_line.split_whitespace().nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or(HttpSeedError::InvalidResponse)?;
+    let mut body = &response[end + 4..];
+
+    // A Content-Length shorter than the body is the end of the body.
+    let content_length = head.lines().skip(1).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("content-length")
+            .then(|| value.trim().parse::<usize>().ok())
+            .flatten()
+    });
+    if let Some(content_length) = content_length {
+        body = &body[..content_length.min(body.len())];
+    }
+    Ok((status, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::mock_http_seed::MockHttpSeed;
+
+    #[test]
+    fn test_parse_url() {
+        let seed = HttpSeed::new("http://example.com:8080/seed.php?user=1").unwrap();
+        assert!(!seed.https);
+        assert_eq!(seed.host, "example.com");
+        assert_eq!(seed.port, 8080);
+
+        assert_eq!(
+            seed.target(&InfoHash::new([0xaa; 20]), 3, &[0..16, 32..48]),
+            format!(
+                "/seed.php?user=1&info_hash={}&piece=3&ranges=0-15,32-47",
+                InfoHash::new([0xaa; 20]).url_encoded()
+            )
+        );
+        assert_eq!(HttpSeed::new("https://example.com").unwrap().port, 443);
+        assert!(matches!(
+            HttpSeed::new("ftp://example.com/seed"),
+            Err(HttpSeedError::InvalidUrl(_))
+        ));
+    }
+
+    #[test]
+    fn test_fetch_piece_and_ranges() {
+        let data: Vec<u8> = (0..100).collect();
+        let mock = MockHttpSeed::start(data.clone(), 40);
+        let seed = HttpSeed::new(&mock.url()).unwrap();
+        let info_hash = InfoHash::new([1; 20]);
+
+        assert_eq!(
+            seed.fetch(&info_hash, 2, &[], 40).unwrap(),
+            data[80..100].to_vec()
+        );
+        assert_eq!(
+            seed.fetch(&info_hash, 1, &[0..4, 10..12], 6).unwrap(),
+            [&data[40..44], &data[50..52]].concat()
+        );
+        let (sent, received) = seed.traffic();
+        assert!(sent > 0 && received > 6);
+        assert!(mock.requests()[0].contains(&format!("info_hash={}", info_hash.url_encoded())));
+    }
+
+    #[test]
+    fn test_busy_seed() {
+        let mock = MockHttpSeed::start(vec![0; 10], 10);
+        mock.set_busy(Some(7));
+        let seed = HttpSeed::new(&mock.url()).unwrap();
+
+        assert!(matches!(
+            seed.fetch(&InfoHash::new([1; 20]), 0, &[], 10),
+            Err(HttpSeedError::Busy(retry)) if retry == Duration::from_secs(7)
+        ));
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nabc").unwrap(),
+            (200, &b"ab"[..])
+        );
+        assert!(matches!(
+            parse_response(b"HTTP/1.1 OK\r\n\r\n"),
+            Err(HttpSeedError::InvalidResponse)
+        ));
+    }
+}
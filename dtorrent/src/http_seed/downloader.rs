@@ -0,0 +1,235 @@
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use tracing::{info, warn};
+
+use super::client::{HttpSeed, HttpSeedError};
+use crate::{
+    peer::peer_message::Bitfield,
+    torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError},
+    torrent_parser::torrent::Torrent,
+};
+
+/// Consecutive failed requests after which a seed is no longer used.
+const MAX_CONSECUTIVE_FAILURES: u32 = 5;
+
+/// Seconds to wait before asking the seed again after a failed request, multiplied by the consecutive failures.
+const FAILURE_RETRY_SECS: u64 = 5;
+
+/// Milliseconds between checks of a paused, finished or removed torrent while waiting.
+const WAIT_POLL_MILLIS: u64 = 100;
+
+/// Downloads the pieces of a torrent from an `HttpSeed`, one at a time, alongside its peers.
+///
+/// The pieces are selected from the torrent status like a peer that has all of them, checked against their hash
+/// and saved through the status, so the seed and the peers never download the same piece outside of the endgame.
+///
+/// To create a new `HttpSeedDownloader` use `HttpSeedDownloader::new`.
+#[derive(Debug)]
+pub struct HttpSeedDownloader {
+    seed: HttpSeed,
+    torrent: Torrent,
+    torrent_status: Arc<AtomicTorrentStatus>,
+}
+
+/// Posible `HttpSeedDownloader` errors.
+#[derive(Debug)]
+pub enum HttpSeedDownloaderError {
+    TorrentStatusError(AtomicTorrentStatusError),
+    SeedError(HttpSeedError),
+    PieceHashDoesNotMatch,
+}
+
+impl HttpSeedDownloader {
+    pub fn new(seed: HttpSeed, torrent: Torrent, torrent_status: Arc<AtomicTorrentStatus>) -> Self {
+        Self {
+            seed,
+            torrent,
+            torrent_status,
+        }
+    }
+
+    /// Downloads pieces until the torrent is finished or removed.
+    ///
+    /// A busy seed is asked again once the time it asked to wait elapses. Nothing is asked while the torrent is
+    /// paused.
+    ///
+    /// # Errors
+    ///
+    /// - `TorrentStatusError` if there was a problem using the `Torrent Status`.
+    /// - The error of the last request if `MAX_CONSECUTIVE_FAILURES` requests in a row failed, or returned a
+    ///   piece that did not match its hash.
+    pub fn run(&self) -> Result<(), HttpSeedDownloaderError> {
+        let all_pieces =
+            Bitfield::new(vec![0xff; self.torrent.total_pieces().div_ceil(8) as usize]);
+        let mut failures = 0;
+
+        while !self.is_done() {
+            if self.torrent_status.is_paused() {
+                self.wait(Duration::from_millis(WAIT_POLL_MILLIS));
+                continue;
+            }
+            let index = match self
+                .torrent_status
+                .select_piece(&all_pieces)
+                .map_err(HttpSeedDownloaderError::TorrentStatusError)?
+            {
+                Some(index) => index,
+                None => {
+                    self.wait(Duration::from_millis(WAIT_POLL_MILLIS));
+                    continue;
+                }
+            };
+
+            match self.download_piece(index) {
+                Ok(()) => failures = 0,
+                Err(HttpSeedDownloaderError::TorrentStatusError(
+                    AtomicTorrentStatusError::TorrentRemoved,
+                )) => return Ok(()),
+                Err(err @ HttpSeedDownloaderError::TorrentStatusError(_)) => return Err(err),
+                Err(HttpSeedDownloaderError::SeedError(HttpSeedError::Busy(retry))) => {
+                    self.abort_piece(index)?;
+                    info!(
+                        "HTTP seed {} is busy, waiting {:?}.",
+                        self.seed.url(),
+                        retry
+                    );
+                    self.wait(retry);
+                }
+                Err(err) => {
+                    self.abort_piece(index)?;
+                    failures += 1;
+                    if failures >= MAX_CONSECUTIVE_FAILURES {
+                        return Err(err);
+                    }
+                    warn!(
+                        "HTTP seed {} failed to send piece {}: {:?}",
+                        self.seed.url(),
+                        index,
+                        err
+                    );
+                    self.wait(Duration::from_secs(FAILURE_RETRY_SECS * failures as u64));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Requests a piece from the seed and saves it if it matches its hash.
+    fn download_piece(&self, index: u32) -> Result<(), HttpSeedDownloaderError> {
+        let piece_size = self.torrent.piece_size(index) as usize;
+        let result = self
+            .seed
+            .fetch(&self.torrent.info_hash(), index, &[], piece_size);
+        let (sent_bytes, received_bytes) = self.seed.traffic();
+        let piece = result.map_err(HttpSeedDownloaderError::SeedError)?;
+
+        // The request and the response headers are overhead, like the messages of a peer.
+        self.torrent_status.add_overhead_uploaded_bytes(sent_bytes);
+        self.torrent_status
+            .add_overhead_downloaded_bytes(received_bytes.saturating_sub(piece.len() as u64));
+        self.torrent_status
+            .add_downloaded_bytes(piece.len() as u64)
+            .map_err(HttpSeedDownloaderError::TorrentStatusError)?;
+        self.torrent_status.throttle_download(piece.len());
+
+        if piece.len() != piece_size || !self.torrent_status.piece_matches_hash(index, &piece) {
+            self.torrent_status.add_wasted_bytes(piece.len() as u64);
+            self.torrent_status.add_hash_failure();
+            return Err(HttpSeedDownloaderError::PieceHashDoesNotMatch);
+        }
+
+        self.torrent_status
+            .piece_downloaded(index, &piece)
+            .map_err(HttpSeedDownloaderError::TorrentStatusError)?;
+        info!(
+            piece = index,
+            downloaded_pieces = self.torrent_status.downloaded_pieces(),
+            total_pieces = self.torrent.total_pieces(),
+            "Piece downloaded from HTTP seed"
+        );
+        Ok(())
+    }
+
+    /// Frees a piece that could not be downloaded, unless a peer downloaded it meanwhile during the endgame.
+    fn abort_piece(&self, index: u32) -> Result<(), HttpSeedDownloaderError> {
+        match self.torrent_status.piece_aborted(index) {
+            Ok(()) | Err(AtomicTorrentStatusError::PieceWasNotDownloading) => Ok(()),
+            Err(err) => Err(HttpSeedDownloaderError::TorrentStatusError(err)),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.torrent_status.is_finished() || self.torrent_status.is_removed()
+    }
+
+    /// Blocks for `delay`, or until the torrent is finished or removed.
+    fn wait(&self, delay: Duration) {
+        let deadline = Instant::now() + delay;
+        while !self.is_done() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            thread::sleep(remaining.min(Duration::from_millis(WAIT_POLL_MILLIS)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_support::{
+        fixtures::test_config, local_swarm::generated_torrent, mock_http_seed::MockHttpSeed,
+        temp_dir::TempDir,
+    };
+
+    #[test]
+    fn test_downloads_every_piece() {
+        let dir = TempDir::new("test_http_seed_downloads_every_piece");
+        let (torrent, data) = generated_torrent("http_seed", 100_000, 32 * 1024, "");
+        let mock = MockHttpSeed::start(data.clone(), 32 * 1024);
+        let status = Arc::new(AtomicTorrentStatus::new(&torrent, test_config(&dir)));
+        status.allocate().unwrap();
+
+        let downloader =
+            HttpSeedDownloader::new(HttpSeed::new(&mock.url()).unwrap(), torrent, status.clone());
+        downloader.run().unwrap();
+
+        assert!(status.is_finished());
+        assert_eq!(fs::read(status.output_path().unwrap()).unwrap(), data);
+        let stats = status.stats().unwrap();
+        assert_eq!(stats.downloaded_bytes, 100_000);
+        assert!(stats.overhead_downloaded_bytes > 0);
+        assert_eq!(mock.requests().len(), 4);
+    }
+
+    #[test]
+    fn test_corrupted_piece_is_aborted() {
+        let dir = TempDir::new("test_http_seed_corrupted_piece_is_aborted");
+        let (torrent, _) = generated_torrent("corrupted_http_seed", 100, 100, "");
+        let mock = MockHttpSeed::start(vec![0; 100], 100);
+        let status = Arc::new(AtomicTorrentStatus::new(&torrent, test_config(&dir)));
+        status.allocate().unwrap();
+        let downloader =
+            HttpSeedDownloader::new(HttpSeed::new(&mock.url()).unwrap(), torrent, status.clone());
+
+        let run = thread::spawn(move || downloader.run());
+        // The downloader waits before asking again, until the torrent is removed.
+        while status.hash_failures() == 0 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        status.remove().unwrap();
+
+        assert!(run.join().unwrap().is_ok());
+        assert!(!status.is_finished());
+        assert_eq!(status.wasted_bytes(), 100);
+        assert_eq!(status.downloading_pieces(), 0);
+        assert_eq!(mock.requests().len(), 1);
+    }
+}
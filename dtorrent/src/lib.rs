@@ -1,7 +1,15 @@
 pub mod bt_server;
 pub mod config;
+pub mod control;
+pub mod http_seed;
+pub mod logger;
+pub mod metrics;
+pub mod nat;
 pub mod peer;
+pub mod proxy;
 pub mod storage_manager;
+#[cfg(test)]
+pub(crate) mod test_support;
 pub mod torrent_handler;
 pub mod torrent_parser;
 pub mod tracker;
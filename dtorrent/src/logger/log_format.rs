@@ -0,0 +1,43 @@
+use std::str::FromStr;
+
+/// Format of the lines written to the logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per line, for log aggregators.
+    Json,
+}
+
+/// Posible `LogFormat` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LogFormatError {
+    UnknownLogFormat(String),
+}
+
+impl FromStr for LogFormat {
+    type Err = LogFormatError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(LogFormatError::UnknownLogFormat(value.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("JSON".parse(), Ok(LogFormat::Json));
+        assert_eq!("text".parse(), Ok(LogFormat::Text));
+        assert_eq!(
+            "xml".parse::<LogFormat>(),
+            Err(LogFormatError::UnknownLogFormat("xml".to_string()))
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::{fmt, str::FromStr};
+
+use tracing::Level;
+
+/// Minimum severity of the events that are logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+/// Posible `LogLevel` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LogLevelError {
+    UnknownLogLevel(String),
+}
+
+impl LogLevel {
+    /// Returns `true` if events of the given `tracing` level are logged with this minimum level.
+    pub fn enables(&self, level: &Level) -> bool {
+        LogLevel::from(*level) <= *self
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            1 => LogLevel::Error,
+            2 => LogLevel::Warn,
+            3 => LogLevel::Info,
+            4 => LogLevel::Debug,
+            _ => LogLevel::Trace,
+        }
+    }
+}
+
+impl From<Level> for LogLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::ERROR => LogLevel::Error,
+            Level::WARN => LogLevel::Warn,
+            Level::INFO => LogLevel::Info,
+            Level::DEBUG => LogLevel::Debug,
+            Level::TRACE => LogLevel::Trace,
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = LogLevelError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(LogLevelError::UnknownLogLevel(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("WARN".parse(), Ok(LogLevel::Warn));
+        assert_eq!("trace".parse(), Ok(LogLevel::Trace));
+        assert_eq!(
+            "verbose".parse::<LogLevel>(),
+            Err(LogLevelError::UnknownLogLevel("verbose".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_enables_more_severe_levels() {
+        assert!(LogLevel::Warn.enables(&Level::ERROR));
+        assert!(LogLevel::Warn.enables(&Level::WARN));
+        assert!(!LogLevel::Warn.enables(&Level::INFO));
+        assert!(LogLevel::Trace.enables(&Level::DEBUG));
+    }
+
+    #[test]
+    fn test_from_u8_round_trip() {
+        for level in [
+            LogLevel::Error,
+            LogLevel::Warn,
+            LogLevel::Info,
+            LogLevel::Debug,
+            LogLevel::Trace,
+        ] {
+            assert_eq!(LogLevel::from_u8(level as u8), level);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+pub mod log_format;
+pub mod log_level;
+pub mod record;
+pub mod record_layer;
+pub mod rotating_file;
+
+use std::{
+    io,
+    path::Path,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Mutex,
+    },
+};
+
+use tracing_subscriber::{
+    filter::filter_fn, fmt, layer::Layered, prelude::*, registry::Registry, Layer,
+};
+
+use crate::config::cfg::Cfg;
+
+use self::{
+    log_format::LogFormat,
+    log_level::LogLevel,
+    record::SpanFieldsLayer,
+    record_layer::{RecordWriterLayer, TorrentFilesLayer},
+    rotating_file::RotatingFile,
+};
+
+/// Name of the log file inside the `log_directory`.
+pub const LOG_FILE_NAME: &str = "dtorrent.log";
+
+/// Folder inside the `log_directory` with the log files of each torrent.
+pub const TORRENT_LOGS_DIRECTORY: &str = "torrents";
+
+/// Minimum level of the logged events, read on every event so it can be changed while running.
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+type BoxedLayer = Box<dyn Layer<Layered<SpanFieldsLayer, Registry>> + Send + Sync>;
+
+/// Installs the global collector of `tracing` events.
+///
+/// Events are written to `dtorrent.log` in the `log_directory` of the config, rotated when it reaches `max_log_file_kb_size`.
+/// If no `log_directory` is configured they are written to the standard output.
+/// Lines are written in the `log_format` of the config, with the fields of the event and of the spans it happened in,
+/// and with `per_torrent_logs` the events of each torrent are also written to its own file.
+/// Only events at least as severe as the `log_level` of the config are logged, see `set_level`.
+///
+/// # Errors
+///
+/// - `io::Error` if the log file could not be opened.
+pub fn init(config: &Cfg) -> io::Result<()> {
+    set_level(config.log_level);
+
+    let mut layers = vec![main_layer(config)?];
+    if config.per_torrent_logs && !config.log_directory.is_empty() {
+        let torrent_files = TorrentFilesLayer::new(
+            Path::new(&config.log_directory).join(TORRENT_LOGS_DIRECTORY),
+            config.log_format,
+            max_log_file_size(config),
+            config.max_log_files,
+        );
+        layers.push(filtered(torrent_files));
+    }
+
+    tracing_subscriber::registry()
+        .with(SpanFieldsLayer)
+        .with(layers)
+        .init();
+    Ok(())
+}
+
+/// Returns the layer that writes every event to the standard output or to the log file.
+fn main_layer(config: &Cfg) -> io::Result<BoxedLayer> {
+    if config.log_directory.is_empty() {
+        return Ok(match config.log_format {
+            LogFormat::Text => filtered(fmt::layer()),
+            LogFormat::Json => filtered(RecordWriterLayer::new(io::stdout(), LogFormat::Json)),
+        });
+    }
+
+    let file = RotatingFile::open(
+        Path::new(&config.log_directory).join(LOG_FILE_NAME),
+        max_log_file_size(config),
+        config.max_log_files,
+    )?;
+    Ok(match config.log_format {
+        LogFormat::Text => filtered(fmt::layer().with_ansi(false).with_writer(Mutex::new(file))),
+        LogFormat::Json => filtered(RecordWriterLayer::new(file, LogFormat::Json)),
+    })
+}
+
+/// Filters the events of the layer by the current minimum level.
+fn filtered<L>(layer: L) -> BoxedLayer
+where
+    L: Layer<Layered<SpanFieldsLayer, Registry>> + Send + Sync + 'static,
+{
+    layer
+        .with_filter(filter_fn(|metadata| level().enables(metadata.level())))
+        .boxed()
+}
+
+fn max_log_file_size(config: &Cfg) -> u64 {
+    config.max_log_file_kb_size as u64 * 1024
+}
+
+/// Changes the minimum level of the logged events, taking effect immediately in every thread.
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Returns the current minimum level of the logged events.
+pub fn level() -> LogLevel {
+    LogLevel::from_u8(LEVEL.load(Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+
+    use super::*;
+
+    #[test]
+    fn test_set_level_changes_enabled_events() {
+        set_level(LogLevel::Warn);
+        assert_eq!(level(), LogLevel::Warn);
+        assert!(!level().enables(&Level::INFO));
+
+        set_level(LogLevel::Debug);
+        assert!(level().enables(&Level::INFO));
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+};
+
+use chrono::{SecondsFormat, Utc};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use super::log_format::LogFormat;
+
+/// Value of a field of an event or span.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldValue {
+    Str(String),
+    Int(i64),
+    UInt(u64),
+    Bool(bool),
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Str(value) => write!(f, "{}", value),
+            FieldValue::Int(value) => write!(f, "{}", value),
+            FieldValue::UInt(value) => write!(f, "{}", value),
+            FieldValue::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Key/value fields of an event or span, like the torrent name, the peer address or the piece index.
+#[derive(Debug, Default, Clone)]
+pub struct Fields(BTreeMap<String, FieldValue>);
+
+impl Fields {
+    fn insert(&mut self, field: &Field, value: FieldValue) {
+        self.0.insert(field.name().to_string(), value);
+    }
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, FieldValue::Str(format!("{:?}", value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, FieldValue::Str(value.to_string()));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, FieldValue::Int(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, FieldValue::UInt(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, FieldValue::Bool(value));
+    }
+}
+
+/// Stores the fields of every span so the records of the events inside it can include them.
+///
+/// It must be added to the registry before the layers that build `LogRecord`s.
+pub struct SpanFieldsLayer;
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanFieldsLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = Fields::default();
+            attrs.record(&mut fields);
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<Fields>() {
+                values.record(fields);
+            }
+        }
+    }
+}
+
+/// A logged event with the fields of the spans it happened in.
+#[derive(Debug)]
+pub struct LogRecord {
+    pub timestamp: String,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    pub fields: BTreeMap<String, FieldValue>,
+}
+
+impl LogRecord {
+    /// Builds the record of the event, the fields of inner spans and of the event take precedence.
+    pub fn from_event<S: Subscriber + for<'a> LookupSpan<'a>>(
+        event: &Event<'_>,
+        ctx: &Context<'_, S>,
+    ) -> Self {
+        let mut fields = BTreeMap::new();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(span_fields) = span.extensions().get::<Fields>() {
+                    fields.extend(span_fields.0.clone());
+                }
+            }
+        }
+        let mut event_fields = Fields::default();
+        event.record(&mut event_fields);
+        fields.extend(event_fields.0);
+
+        let message = fields
+            .remove("message")
+            .map(|message| message.to_string())
+            .unwrap_or_default();
+
+        Self {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_string(),
+            message,
+            fields,
+        }
+    }
+
+    /// Returns the value of the field, if present.
+    pub fn field(&self, name: &str) -> Option<&FieldValue> {
+        self.fields.get(name)
+    }
+
+    /// Renders the record as a line in the given format, without the line break.
+    pub fn format(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Text => self.to_text(),
+            LogFormat::Json => self.to_json(),
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let mut line = format!(
+            "{} {:>5} {}: {}",
+            self.timestamp, self.level, self.target, self.message
+        );
+        for (key, value) in &self.fields {
+            // Writing to a String can not fail.
+            let _ = write!(line, " {}={}", key, value);
+        }
+        line
+    }
+
+    fn to_json(&self) -> String {
+        let mut line = format!(
+            "{{\"timestamp\":{},\"level\":{},\"target\":{},\"message\":{},\"fields\":{{",
+            json_string(&self.timestamp),
+            json_string(self.level.as_str()),
+            json_string(&self.target),
+            json_string(&self.message)
+        );
+        for (i, (key, value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                line.push(',');
+            }
+            let value = match value {
+                FieldValue::Str(value) => json_string(value),
+                value => value.to_string(),
+            };
+            // Writing to a String can not fail.
+            let _ = write!(line, "{}:{}", json_string(key), value);
+        }
+        line.push_str("}}");
+        line
+    }
+}
+
+/// Quotes and escapes the value as a JSON string.
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                // Writing to a String can not fail.
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record() -> LogRecord {
+        let mut fields = BTreeMap::new();
+        fields.insert(
+            "torrent".to_string(),
+            FieldValue::Str("ubuntu.iso".to_string()),
+        );
+        fields.insert("piece".to_string(), FieldValue::UInt(7));
+        fields.insert("seeding".to_string(), FieldValue::Bool(false));
+        LogRecord {
+            timestamp: "2023-01-01T00:00:00.000Z".to_string(),
+            level: Level::INFO,
+            target: "dtorrent::peer".to_string(),
+            message: "Piece \"downloaded\"\n".to_string(),
+            fields,
+        }
+    }
+
+    #[test]
+    fn test_format_text() {
+        assert_eq!(
+            create_test_record().format(LogFormat::Text),
+            "2023-01-01T00:00:00.000Z  INFO dtorrent::peer: Piece \"downloaded\"\n piece=7 seeding=false torrent=ubuntu.iso"
+        );
+    }
+
+    #[test]
+    fn test_format_json() {
+        assert_eq!(
+            create_test_record().format(LogFormat::Json),
+            "{\"timestamp\":\"2023-01-01T00:00:00.000Z\",\"level\":\"INFO\",\"target\":\"dtorrent::peer\",\"message\":\"Piece \\\"downloaded\\\"\\n\",\"fields\":{\"piece\":7,\"seeding\":false,\"torrent\":\"ubuntu.iso\"}}"
+        );
+    }
+
+    #[test]
+    fn test_json_string_escapes_control_characters() {
+        assert_eq!(json_string("a\\b\u{1}"), "\"a\\\\b\\u0001\"");
+    }
+
+    #[test]
+    fn test_field() {
+        let record = create_test_record();
+
+        assert_eq!(
+            record.field("torrent"),
+            Some(&FieldValue::Str("ubuntu.iso".to_string()))
+        );
+        assert_eq!(record.field("peer"), None);
+    }
+}
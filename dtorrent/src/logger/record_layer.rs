@@ -0,0 +1,179 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+
+use tracing::{Event, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use super::{log_format::LogFormat, record::LogRecord, rotating_file::RotatingFile};
+
+/// Field of the spans that names the torrent the events belong to.
+pub const TORRENT_FIELD: &str = "torrent";
+
+/// Writes every event as a `LogRecord` line to a writer.
+pub struct RecordWriterLayer<W> {
+    writer: Mutex<W>,
+    format: LogFormat,
+}
+
+impl<W: Write> RecordWriterLayer<W> {
+    pub fn new(writer: W, format: LogFormat) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+            format,
+        }
+    }
+}
+
+impl<S, W> Layer<S> for RecordWriterLayer<W>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: Write + 'static,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let line = LogRecord::from_event(event, &ctx).format(self.format);
+        // A failed write can not be logged anywhere.
+        let _ = writeln!(lock(&self.writer), "{}", line);
+    }
+}
+
+/// Writes the events inside a span with a `torrent` field to a log file of that torrent.
+///
+/// Files are named after the torrent inside `directory` and rotated like the main log.
+pub struct TorrentFilesLayer {
+    directory: PathBuf,
+    format: LogFormat,
+    max_size: u64,
+    max_files: u32,
+    files: Mutex<HashMap<String, RotatingFile>>,
+}
+
+impl TorrentFilesLayer {
+    pub fn new(directory: PathBuf, format: LogFormat, max_size: u64, max_files: u32) -> Self {
+        Self {
+            directory,
+            format,
+            max_size,
+            max_files,
+            files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn write(&self, torrent: String, line: &str) {
+        let mut files = lock(&self.files);
+        if !files.contains_key(&torrent) {
+            let path = self.directory.join(format!("{}.log", file_name(&torrent)));
+            match RotatingFile::open(path, self.max_size, self.max_files) {
+                Ok(file) => {
+                    files.insert(torrent.clone(), file);
+                }
+                // The file can not be opened, the event is still in the main log.
+                Err(_) => return,
+            }
+        }
+        if let Some(file) = files.get_mut(&torrent) {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for TorrentFilesLayer {
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let record = LogRecord::from_event(event, &ctx);
+        if let Some(torrent) = record.field(TORRENT_FIELD) {
+            self.write(torrent.to_string(), &record.format(self.format));
+        }
+    }
+}
+
+/// Replaces the characters of the torrent name that are not safe in a file name.
+fn file_name(torrent: &str) -> String {
+    torrent
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A thread that panicked while writing a log line leaves the writer usable.
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use tracing::{info, info_span, subscriber};
+    use tracing_subscriber::prelude::*;
+
+    use crate::logger::record::SpanFieldsLayer;
+
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(
+            file_name("ubuntu 22.04/amd64.iso"),
+            "ubuntu_22.04_amd64.iso"
+        );
+    }
+
+    #[test]
+    fn test_events_are_written_per_torrent() {
+        let dir = "./test_events_are_written_per_torrent";
+        let subscriber =
+            tracing_subscriber::registry()
+                .with(SpanFieldsLayer)
+                .with(TorrentFilesLayer::new(
+                    PathBuf::from(dir),
+                    LogFormat::Json,
+                    0,
+                    0,
+                ));
+
+        subscriber::with_default(subscriber, || {
+            info!("Not inside a torrent");
+            let span = info_span!("peer", torrent = "first.iso", peer = "127.0.0.1:6881");
+            span.in_scope(|| info!(piece = 3, "Piece downloaded"));
+            info_span!("peer", torrent = "second.iso").in_scope(|| info!("Handshake successful"));
+        });
+
+        let first = fs::read_to_string(format!("{}/first.iso.log", dir)).unwrap();
+        assert_eq!(first.lines().count(), 1);
+        assert!(first.contains("\"message\":\"Piece downloaded\""));
+        assert!(first.contains(
+            "\"fields\":{\"peer\":\"127.0.0.1:6881\",\"piece\":3,\"torrent\":\"first.iso\"}"
+        ));
+
+        let second = fs::read_to_string(format!("{}/second.iso.log", dir)).unwrap();
+        assert!(second.contains("Handshake successful"));
+        assert!(!Path::new(&format!("{}/.log", dir)).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_writer_writes_lines() {
+        let path = "./test_record_writer_writes_lines.log";
+        let file = fs::File::create(path).unwrap();
+        let subscriber = tracing_subscriber::registry()
+            .with(SpanFieldsLayer)
+            .with(RecordWriterLayer::new(file, LogFormat::Text));
+
+        subscriber::with_default(subscriber, || {
+            info_span!("torrent", torrent = "first.iso")
+                .in_scope(|| info!("Connected to tracker."));
+        });
+
+        let contents = fs::read_to_string(path).unwrap();
+        assert!(contents.ends_with("Connected to tracker. torrent=first.iso\n"));
+        fs::remove_file(path).unwrap();
+    }
+}
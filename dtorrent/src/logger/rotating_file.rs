@@ -0,0 +1,159 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Log file that is rotated when it grows past a maximum size.
+///
+/// When a write would make the file bigger than `max_size`, `logfile` is renamed to `logfile.1`,
+/// `logfile.1` to `logfile.2` and so on, deleting the files past `max_files`, and a new `logfile` is started.
+///
+/// To create a new `RotatingFile` use `RotatingFile::open`.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: u32,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Opens the log file at `path` to append to it, creating it and its directory if needed.
+    ///
+    /// A `max_size` of 0 disables the rotation.
+    ///
+    /// # Errors
+    ///
+    /// - `io::Error` if the directory or the file could not be created or opened.
+    pub fn open(path: impl Into<PathBuf>, max_size: u64, max_files: u32) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            max_size,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    /// Moves every file one position back and starts a new, empty log file.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            self.file = File::create(&self.path)?;
+            self.size = 0;
+            return Ok(());
+        }
+
+        remove_if_exists(&self.rotated_path(self.max_files))?;
+        for index in (1..self.max_files).rev() {
+            rename_if_exists(&self.rotated_path(index), &self.rotated_path(index + 1))?;
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn rotated_path(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.max_size > 0 && self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotates_when_exceeding_max_size() {
+        let dir = "./test_rotates_when_exceeding_max_size";
+        let path = format!("{}/dtorrent.log", dir);
+        let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+
+        file.write_all(b"first\n").unwrap();
+        file.write_all(b"second\n").unwrap();
+        file.write_all(b"third\n").unwrap();
+        file.write_all(b"fourth\n").unwrap();
+        file.flush().unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"fourth\n");
+        assert_eq!(fs::read(format!("{}.1", path)).unwrap(), b"third\n");
+        assert_eq!(fs::read(format!("{}.2", path)).unwrap(), b"second\n");
+        assert!(!Path::new(&format!("{}.3", path)).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_appends_to_existing_file() {
+        let dir = "./test_appends_to_existing_log_file";
+        let path = format!("{}/dtorrent.log", dir);
+        RotatingFile::open(&path, 10, 1)
+            .unwrap()
+            .write_all(b"12345678")
+            .unwrap();
+
+        let mut file = RotatingFile::open(&path, 10, 1).unwrap();
+        file.write_all(b"abc").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"abc");
+        assert_eq!(fs::read(format!("{}.1", path)).unwrap(), b"12345678");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_truncates_without_retained_files() {
+        let dir = "./test_truncates_without_retained_log_files";
+        let path = format!("{}/dtorrent.log", dir);
+        let mut file = RotatingFile::open(&path, 4, 0).unwrap();
+
+        file.write_all(b"old").unwrap();
+        file.write_all(b"new").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(!Path::new(&format!("{}.1", path)).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,41 +1,283 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use dtorrent::{
-    bt_server::server::BtServer, config::cfg::Cfg, torrent_handler::status::AtomicTorrentStatus,
+    bt_server::server::BtServer,
+    config::{cfg::Cfg, watcher::ConfigWatcher},
+    control::DEFAULT_CONTROL_SOCKET,
+    logger,
+    metrics::server::MetricsServer,
+    nat::{self, SharedPortMapping},
+    storage_manager::verify::{verify, PieceCheck},
+    torrent_handler::{
+        connection_registry::PeerConnectionRegistry, status::AtomicTorrentStatus,
+        torrent_watcher::TorrentWatcher,
+    },
     torrent_parser::parser::TorrentParser,
 };
 use std::collections::HashMap;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tracing::info;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+#[cfg(unix)]
+use dtorrent::control::server::ControlServer;
 
 #[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Args {
-    #[arg(short, long)]
-    file: String,
-    #[arg(short, long)]
-    config: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(short, long, required_unless_present = "daemon")]
+    file: Option<String>,
+    #[arg(short, long, required = true)]
+    config: Option<String>,
+    /// Runs headless, managed through the control socket with `dtorrentctl`.
+    #[arg(long)]
+    daemon: bool,
+    /// Path of the control socket of the daemon.
+    #[arg(long, default_value = DEFAULT_CONTROL_SOCKET)]
+    socket: PathBuf,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Checks the data on disk against a `.torrent`, printing the result of each piece.
+    Verify {
+        /// Path of the `.torrent` file.
+        torrent: PathBuf,
+        /// Path of the downloaded file, or of the directory holding it.
+        path: PathBuf,
+    },
+    /// Prints the metadata of a `.torrent`: name, size, pieces, trackers, files and info hash.
+    Show {
+        /// Path of the `.torrent` file.
+        torrent: PathBuf,
+    },
 }
 
 #[tokio::main]
 async fn main() {
-
     // Reads the filepath from the command line argument (Check README)
     let args = Args::parse();
-    let file_path = PathBuf::from(args.file.trim());
-    let config_path = args.config.trim();
+    if let Some(Command::Verify { torrent, path }) = &args.command {
+        std::process::exit(verify_command(torrent, path));
+    }
+    if let Some(Command::Show { torrent }) = &args.command {
+        std::process::exit(show_command(torrent));
+    }
+    let config_path = args.config.as_deref().unwrap_or_default().trim();
+
+    let config = Cfg::new(&config_path).expect("Config file not found or incomplete");
 
-    // install global collector configured based on RUST_LOG env var.
-    tracing_subscriber::fmt::init();
+    // install global collector, writing to the rotated log file of the config.
+    logger::init(&config).expect("Could not open the log file");
+    for setting in &config.ignored_settings {
+        warn!("Ignoring unknown config setting: {}", setting);
+    }
+
+    // Applies the changes to the config file that do not need a restart.
+    if config.config_reload_seconds > 0 {
+        ConfigWatcher::new(config_path, config.clone())
+            .spawn(Duration::from_secs(config.config_reload_seconds));
+    }
 
     // Initializes the server
-    let parsed = TorrentParser::parse(&file_path).expect("parser could not find the file");
-    let config = Cfg::new(&config_path).expect("Config file not found or incomplete");
-    let (status, _status_reciever) = AtomicTorrentStatus::new(&parsed, config.clone());
     let mut torrent_with_status = HashMap::new();
-    torrent_with_status.insert(parsed, Arc::new(status));
+    if let Some(file) = &args.file {
+        let file_path = PathBuf::from(file.trim());
+        let parsed = TorrentParser::parse(&file_path).expect("parser could not find the file");
+        let status = AtomicTorrentStatus::new(&parsed, config.for_torrent(&parsed.info_hash()));
+        torrent_with_status.insert(parsed.info_hash(), Arc::new(status));
+    }
     let client_peer_id = "client_peer_id".to_string();
-    let mut server = BtServer::new(torrent_with_status, config, client_peer_id);
-    info!("Initializing server ...");
-    server.init().expect("Failed to initialize server");
+    let mut server = BtServer::new(torrent_with_status, config.clone(), client_peer_id.clone());
+    if config.metrics_port != 0 {
+        match MetricsServer::bind(config.metrics_port, server.torrents()) {
+            Ok(metrics_server) => {
+                metrics_server.spawn();
+            }
+            Err(err) => warn!("Could not start the metrics server: {:?}", err),
+        }
+    }
+    let port = server.bind().expect("Failed to bind server");
+    info!("Initializing server on port {} ...", port);
+
+    let port_mapping = SharedPortMapping::default();
+    if config.port_mapping {
+        match nat::map_port(port, config.port_mapping_lifetime_seconds) {
+            Ok(mapping) => {
+                info!(
+                    "Port mapped in the gateway: {}:{}",
+                    mapping.external_ip, mapping.external_port
+                );
+                if let Ok(mut port_mapping) = port_mapping.write() {
+                    *port_mapping = Some(mapping);
+                }
+                nat::spawn_renewal(
+                    port,
+                    config.port_mapping_lifetime_seconds,
+                    port_mapping.clone(),
+                );
+            }
+            Err(err) => warn!("Could not map port in the gateway: {:?}", err),
+        }
+    }
+
+    // Shared by every torrent started at runtime, so the limit of connections per IP applies between them.
+    let connection_registry = Arc::new(PeerConnectionRegistry::new(config.max_connections_per_ip));
+
+    // Starts the torrents dropped in the watch directory.
+    if !config.watch_directory.is_empty() {
+        let mut watcher =
+            TorrentWatcher::new(config.clone(), client_peer_id.clone(), server.torrents());
+        watcher.set_client_port(port);
+        watcher.set_port_mapping(port_mapping.clone());
+        watcher.set_connection_registry(connection_registry.clone());
+        watcher.spawn(Duration::from_secs(config.watch_directory_seconds));
+    }
+    if args.daemon {
+        run_daemon(
+            server,
+            &args.socket,
+            config,
+            client_peer_id,
+            port,
+            port_mapping,
+            connection_registry,
+        );
+    } else {
+        server.init().expect("Failed to initialize server");
+    }
+}
+
+/// Checks `path` against the `.torrent` at `torrent_path`, returning the exit code: 0 only if every piece matches.
+fn verify_command(torrent_path: &Path, path: &Path) -> i32 {
+    let torrent = match TorrentParser::parse(torrent_path) {
+        Ok(torrent) => torrent,
+        Err(err) => {
+            eprintln!("Could not load {}: {:?}", torrent_path.display(), err);
+            return 2;
+        }
+    };
+    let report = match verify(&torrent, path) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Could not read {}: {}", path.display(), err);
+            return 2;
+        }
+    };
+
+    for (index, check) in report.pieces.iter().enumerate() {
+        let result = match check {
+            PieceCheck::Valid => "ok",
+            PieceCheck::Invalid => "hash mismatch",
+            PieceCheck::Missing => "missing",
+        };
+        println!("piece {}: {}", index, result);
+    }
+    println!(
+        "{}: {} of {} pieces ok, {} mismatched, {} missing",
+        report.path.display(),
+        report.count(PieceCheck::Valid),
+        report.pieces.len(),
+        report.count(PieceCheck::Invalid),
+        report.count(PieceCheck::Missing)
+    );
+
+    if report.is_complete() {
+        0
+    } else {
+        1
+    }
+}
+
+/// Prints the metadata of the `.torrent` at `torrent_path`, returning the exit code: 0 if it could be parsed.
+fn show_command(torrent_path: &Path) -> i32 {
+    let torrent = match TorrentParser::parse(torrent_path) {
+        Ok(torrent) => torrent,
+        Err(err) => {
+            eprintln!("Could not load {}: {:?}", torrent_path.display(), err);
+            return 2;
+        }
+    };
+
+    println!("Name:        {}", torrent.name());
+    println!("Size:        {} bytes", torrent.length());
+    println!(
+        "Pieces:      {} of {} bytes",
+        torrent.total_pieces(),
+        torrent.piece_length()
+    );
+    println!(
+        "Private:     {}",
+        if torrent.is_private() { "yes" } else { "no" }
+    );
+    println!(
+        "Version:     {}",
+        if torrent.is_hybrid() {
+            "hybrid (v1 + v2)"
+        } else {
+            "v1"
+        }
+    );
+    println!("Info hash:   {}", torrent.info_hash().to_hex());
+    if let Some(info_hash_v2) = torrent.info_hash_v2_hex() {
+        println!("Info hash v2: {}", info_hash_v2);
+    }
+    println!("Magnet link: {}", torrent.to_magnet());
+    println!("Trackers:");
+    if torrent.announce_list.is_empty() {
+        println!("  {}", torrent.announce_url);
+    }
+    for (tier, urls) in torrent.announce_list.iter().enumerate() {
+        println!("  tier {}: {}", tier, urls.join(", "));
+    }
+    if !torrent.http_seeds.is_empty() {
+        println!("HTTP seeds:");
+        for url in &torrent.http_seeds {
+            println!("  {}", url);
+        }
+    }
+    println!("Files:");
+    println!("  {} ({} bytes)", torrent.name(), torrent.length());
+    0
+}
+
+/// Serves the torrents from another thread and the control socket until a `quit` command is received.
+#[cfg(unix)]
+fn run_daemon(
+    mut server: BtServer,
+    socket: &Path,
+    config: Cfg,
+    client_peer_id: String,
+    port: u16,
+    port_mapping: SharedPortMapping,
+    connection_registry: Arc<PeerConnectionRegistry>,
+) {
+    let mut control_server = ControlServer::bind(socket, server.torrents(), config, client_peer_id)
+        .expect("Failed to bind the control socket");
+    control_server.set_client_port(port);
+    control_server.set_port_mapping(port_mapping);
+    control_server.set_connection_registry(connection_registry);
+    thread::spawn(move || server.init().expect("Failed to initialize server"));
+
+    control_server
+        .run()
+        .expect("Failed to serve the control socket");
+    info!("Quit received through the control socket.");
+}
+
+#[cfg(not(unix))]
+fn run_daemon(
+    _: BtServer,
+    _: &Path,
+    _: Cfg,
+    _: String,
+    _: u16,
+    _: SharedPortMapping,
+    _: Arc<PeerConnectionRegistry>,
+) {
+    panic!("The daemon mode needs Unix domain sockets, which are not supported on this platform");
 }
@@ -0,0 +1,236 @@
+use std::{collections::HashMap, fmt::Write, sync::Arc};
+
+use url_encoder::info_hash::InfoHash;
+
+use crate::torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError};
+
+/// Type of a Prometheus metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MetricType {
+    Gauge,
+    Counter,
+}
+
+impl MetricType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricType::Gauge => "gauge",
+            MetricType::Counter => "counter",
+        }
+    }
+}
+
+/// A metric with its value for every torrent, in the same order as the torrents.
+struct Metric {
+    name: &'static str,
+    metric_type: MetricType,
+    help: &'static str,
+    values: Vec<f64>,
+}
+
+/// Renders the metrics of every torrent in the Prometheus text format.
+///
+/// Every sample is labeled with the `info_hash` and the `name` of its torrent.
+///
+/// # Errors
+/// - Any `AtomicTorrentStatusError` returned while reading the status of a torrent.
+pub fn render(
+    torrents: &HashMap<InfoHash, Arc<AtomicTorrentStatus>>,
+) -> Result<String, AtomicTorrentStatusError> {
+    let mut torrents: Vec<_> = torrents.iter().collect();
+    torrents.sort_by_key(|(info_hash, _)| **info_hash);
+
+    let mut metrics = vec![
+        Metric::new(
+            "dtorrent_progress_ratio",
+            MetricType::Gauge,
+            "Downloaded fraction of the torrent, from 0 to 1.",
+        ),
+        Metric::new("dtorrent_peers", MetricType::Gauge, "Peers connected."),
+        Metric::new(
+            "dtorrent_download_speed_kilobits",
+            MetricType::Gauge,
+            "Download speed in kilobits per second.",
+        ),
+        Metric::new(
+            "dtorrent_upload_speed_kilobits",
+            MetricType::Gauge,
+            "Upload speed in kilobits per second.",
+        ),
+        Metric::new(
+            "dtorrent_downloaded_bytes_total",
+            MetricType::Counter,
+            "Bytes of pieces received from peers.",
+        ),
+        Metric::new(
+            "dtorrent_uploaded_bytes_total",
+            MetricType::Counter,
+            "Bytes of pieces sent to peers.",
+        ),
+        Metric::new(
+            "dtorrent_overhead_downloaded_bytes_total",
+            MetricType::Counter,
+            "Bytes received that are not piece data, from peers and trackers.",
+        ),
+        Metric::new(
+            "dtorrent_overhead_uploaded_bytes_total",
+            MetricType::Counter,
+            "Bytes sent that are not piece data, to peers and trackers.",
+        ),
+        Metric::new(
+            "dtorrent_wasted_bytes_total",
+            MetricType::Counter,
+            "Bytes received that could not be used.",
+        ),
+        Metric::new(
+            "dtorrent_hash_failures_total",
+            MetricType::Counter,
+            "Pieces that failed the hash check.",
+        ),
+        Metric::new(
+            "dtorrent_tracker_errors_total",
+            MetricType::Counter,
+            "Failed requests to the tracker.",
+        ),
+        Metric::new(
+            "dtorrent_rejected_connections_total",
+            MetricType::Counter,
+            "Incoming connections refused because there were too many peers.",
+        ),
+    ];
+
+    for (_, status) in &torrents {
+        let stats = status.stats()?;
+        let values = [
+            status.progress()?,
+            status.current_peers() as f64,
+            status.torrent_download_speed()?,
+            status.torrent_upload_speed()?,
+            stats.downloaded_bytes as f64,
+            stats.uploaded_bytes as f64,
+            stats.overhead_downloaded_bytes as f64,
+            stats.overhead_uploaded_bytes as f64,
+            stats.wasted_bytes as f64,
+            status.hash_failures() as f64,
+            status.tracker_errors() as f64,
+            status.rejected_connections() as f64,
+        ];
+        for (metric, value) in metrics.iter_mut().zip(values) {
+            metric.values.push(value);
+        }
+    }
+
+    let labels: Vec<String> = torrents
+        .iter()
+        .map(|(info_hash, status)| {
+            format!(
+                "info_hash=\"{}\",name=\"{}\"",
+                info_hash,
+                escape_label_value(&status.torrent.name())
+            )
+        })
+        .collect();
+
+    let mut output = String::new();
+    for metric in metrics {
+        // Writing to a String can not fail.
+        let _ = writeln!(output, "# HELP {} {}", metric.name, metric.help);
+        let _ = writeln!(
+            output,
+            "# TYPE {} {}",
+            metric.name,
+            metric.metric_type.as_str()
+        );
+        for (labels, value) in labels.iter().zip(metric.values) {
+            let _ = writeln!(output, "{}{{{}}} {}", metric.name, labels, value);
+        }
+    }
+    Ok(output)
+}
+
+impl Metric {
+    fn new(name: &'static str, metric_type: MetricType, help: &'static str) -> Self {
+        Self {
+            name,
+            metric_type,
+            help,
+            values: Vec::new(),
+        }
+    }
+}
+
+/// Escapes a label value as required by the Prometheus text format.
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_support::{fixtures::test_config, temp_dir::TempDir},
+        torrent_parser::{info::Info, torrent::Torrent},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_render_torrent_metrics() {
+        let torrent = create_test_torrent("a \"test\" torrent");
+        let dir = TempDir::new("test_render_torrent_metrics");
+        let status = AtomicTorrentStatus::new(&torrent, test_config(&dir));
+        status.add_hash_failure();
+        status.add_tracker_error();
+        status.add_tracker_error();
+        status.add_rejected_connection();
+        let mut torrents = HashMap::new();
+        torrents.insert(torrent.info_hash(), Arc::new(status));
+
+        let output = render(&torrents).unwrap();
+
+        let labels = format!(
+            "info_hash=\"{}\",name=\"a \\\"test\\\" torrent\"",
+            InfoHash::new([1; 20])
+        );
+        assert!(output.contains("# TYPE dtorrent_progress_ratio gauge\n"));
+        assert!(output.contains(&format!("dtorrent_progress_ratio{{{}}} 0\n", labels)));
+        assert!(output.contains(&format!("dtorrent_hash_failures_total{{{}}} 1\n", labels)));
+        assert!(output.contains(&format!("dtorrent_tracker_errors_total{{{}}} 2\n", labels)));
+        assert!(output.contains(&format!(
+            "dtorrent_rejected_connections_total{{{}}} 1\n",
+            labels
+        )));
+    }
+
+    #[test]
+    fn test_render_without_torrents() {
+        let output = render(&HashMap::new()).unwrap();
+
+        assert!(output.contains("# TYPE dtorrent_peers gauge\n"));
+        assert!(!output.contains('{'));
+    }
+
+    fn create_test_torrent(name: &str) -> Torrent {
+        let info = Info {
+            length: 10,
+            name: name.to_string(),
+            piece_length: 1,
+            pieces: vec![],
+            private: None,
+            meta_version: None,
+            file_tree: Default::default(),
+        };
+
+        Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![],
+            http_seeds: vec![],
+            info_hash_v2: None,
+            piece_layer: vec![],
+            info,
+            info_hash: InfoHash::new([1; 20]),
+        }
+    }
+}
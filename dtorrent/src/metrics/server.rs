@@ -0,0 +1,180 @@
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use tracing::{error, info};
+
+use super::prometheus;
+use crate::bt_server::server::SharedTorrents;
+
+/// Seconds to wait for the request of a client, so a client that never sends it does not hold its thread forever.
+const READ_TIMEOUT_SECS: u64 = 5;
+
+/// HTTP server that serves the metrics of the torrents in the Prometheus text format on `/metrics`.
+///
+/// The torrents are the ones served by the `BtServer`, so the torrents added while it runs are reported too.
+///
+/// To create a new `MetricsServer`, use MetricsServer::bind(port, torrents).
+pub struct MetricsServer {
+    listener: TcpListener,
+    torrents: SharedTorrents,
+}
+
+impl MetricsServer {
+    /// Binds the metrics server to the given port on every interface.
+    ///
+    /// # Errors
+    /// - `io::Error` if the port could not be bound.
+    pub fn bind(port: u16, torrents: SharedTorrents) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        Ok(Self { listener, torrents })
+    }
+
+    /// Returns the port the server is bound to.
+    ///
+    /// # Errors
+    /// - `io::Error` if the local address of the listener could not be read.
+    pub fn port(&self) -> io::Result<u16> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    /// Serves the metrics from a new thread.
+    pub fn spawn(self) -> thread::JoinHandle<()> {
+        thread::spawn(move || self.serve())
+    }
+
+    /// Serves the metrics, each client from its own thread so a slow client does not delay the others.
+    pub fn serve(&self) {
+        if let Ok(port) = self.port() {
+            info!("Serving metrics on http://0.0.0.0:{}/metrics", port);
+        }
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let torrents = self.torrents.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = handle(stream, &torrents) {
+                            error!("Could not serve metrics: {:?}", err);
+                        }
+                    });
+                }
+                Err(err) => error!("Could not serve metrics: {:?}", err),
+            }
+        }
+    }
+}
+
+fn handle(mut stream: TcpStream, torrents: &SharedTorrents) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT_SECS)))?;
+    let mut buf = [0; 1024];
+    let bytes_read = stream.read(&mut buf)?;
+    let request = String::from_utf8_lossy(&buf[..bytes_read]);
+    let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+    let (status_line, body) = if !request.starts_with("GET ") {
+        ("405 Method Not Allowed", String::new())
+    } else if path == "/metrics" || path.starts_with("/metrics?") {
+        let rendered = match torrents.read() {
+            Ok(torrents) => prometheus::render(&torrents).map_err(|err| format!("{:?}", err)),
+            Err(_) => Err("poisoned torrents lock".to_string()),
+        };
+        match rendered {
+            Ok(body) => ("200 OK", body),
+            Err(err) => {
+                error!("Could not render metrics: {}", err);
+                ("500 Internal Server Error", String::new())
+            }
+        }
+    } else {
+        ("404 Not Found", String::new())
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status_line,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::Arc};
+
+    use super::*;
+    use crate::{
+        test_support::{fixtures::test_config, temp_dir::TempDir},
+        torrent_handler::status::AtomicTorrentStatus,
+        torrent_parser::parser::TorrentParser,
+    };
+
+    #[test]
+    fn test_serves_metrics() {
+        let server = MetricsServer::bind(0, Arc::default()).unwrap();
+        let port = server.port().unwrap();
+        server.spawn();
+
+        let response = request(port, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("# TYPE dtorrent_peers gauge\n"));
+    }
+
+    #[test]
+    fn test_unknown_path_is_not_found() {
+        let server = MetricsServer::bind(0, Arc::default()).unwrap();
+        let port = server.port().unwrap();
+        server.spawn();
+
+        let response = request(port, "GET /other HTTP/1.1\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn test_serves_torrents_added_after_bind() {
+        let dir = TempDir::new("metrics_server_added_torrent");
+        let torrents = SharedTorrents::default();
+        let server = MetricsServer::bind(0, torrents.clone()).unwrap();
+        let port = server.port().unwrap();
+        server.spawn();
+
+        let torrent = TorrentParser::parse(Path::new("../torrents/file1.torrent")).unwrap();
+        let status = AtomicTorrentStatus::new(&torrent, test_config(&dir));
+        torrents
+            .write()
+            .unwrap()
+            .insert(torrent.info_hash(), Arc::new(status));
+        let response = request(port, "GET /metrics HTTP/1.1\r\n\r\n");
+
+        assert!(response.contains(&format!("info_hash=\"{}\"", torrent.info_hash())));
+    }
+
+    #[test]
+    fn test_idle_client_does_not_block_other_clients() {
+        let server = MetricsServer::bind(0, Arc::default()).unwrap();
+        let port = server.port().unwrap();
+        server.spawn();
+
+        let _idle = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let response = request(port, "GET /metrics HTTP/1.1\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    fn request(port: u16, request: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT_SECS - 1)))
+            .unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+}
@@ -0,0 +1,80 @@
+pub mod natpmp;
+pub mod upnp;
+
+use std::io;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+/// A port mapped in the gateway of the local network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortMapping {
+    pub external_ip: Ipv4Addr,
+    pub external_port: u16,
+}
+
+/// Current mapping of the listen port, `None` while there is none, shared with the components that announce it.
+///
+/// It is updated by `spawn_renewal`, as the gateway can give a different address when the mapping is renewed.
+pub type SharedPortMapping = Arc<RwLock<Option<PortMapping>>>;
+
+/// Posible errors mapping a port.
+#[derive(Debug)]
+pub enum NatError {
+    Io(io::Error),
+    GatewayNotFound,
+    InvalidResponse,
+    MappingRefused(String),
+}
+
+/// Maps the TCP `port` in the gateway for `lifetime` seconds.
+///
+/// NAT-PMP is tried first, falling back to UPnP if the gateway does not support it.
+///
+/// # Errors
+/// - The error of UPnP if both protocols failed.
+pub fn map_port(port: u16, lifetime: u32) -> Result<PortMapping, NatError> {
+    match natpmp::map_port(port, lifetime) {
+        Ok(mapping) => Ok(mapping),
+        Err(err) => {
+            info!("NAT-PMP port mapping failed: {:?}, trying UPnP", err);
+            upnp::map_port(port, lifetime)
+        }
+    }
+}
+
+/// Returns the current mapping of `port_mapping`, if there is one.
+pub fn current_mapping(port_mapping: &SharedPortMapping) -> Option<PortMapping> {
+    match port_mapping.read() {
+        Ok(mapping) => *mapping,
+        Err(_) => {
+            warn!("Could not read the port mapping: poisoned lock");
+            None
+        }
+    }
+}
+
+/// Spawns a thread that renews the mapping of `port` at half its `lifetime`, so it never expires.
+///
+/// Each renewed mapping is stored in `mapping`. A failed renewal keeps the last mapping, which may still be valid.
+pub fn spawn_renewal(port: u16, lifetime: u32, mapping: SharedPortMapping) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(u64::from(lifetime / 2).max(1)));
+        match map_port(port, lifetime) {
+            Ok(renewed) => {
+                info!(
+                    "Port mapping renewed: {}:{}",
+                    renewed.external_ip, renewed.external_port
+                );
+                match mapping.write() {
+                    Ok(mut mapping) => *mapping = Some(renewed),
+                    Err(_) => warn!("Could not store the renewed port mapping: poisoned lock"),
+                }
+            }
+            Err(err) => warn!("Could not renew port mapping: {:?}", err),
+        }
+    })
+}
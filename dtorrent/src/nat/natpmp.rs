@@ -0,0 +1,180 @@
+use std::fs;
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::time::Duration;
+
+use super::{NatError, PortMapping};
+
+/// Port where the gateway listens for NAT-PMP requests.
+const NAT_PMP_PORT: u16 = 5351;
+
+const VERSION: u8 = 0;
+const OP_EXTERNAL_ADDRESS: u8 = 0;
+const OP_MAP_TCP: u8 = 2;
+/// Responses have the op code of the request plus 128.
+const RESPONSE_OFFSET: u8 = 128;
+
+const ROUTE_TABLE_PATH: &str = "/proc/net/route";
+const RESPONSE_TIMEOUT_MILLIS: u64 = 1000;
+
+/// Maps the TCP `port` in the gateway using NAT-PMP (RFC 6886).
+///
+/// The mapping lasts `lifetime` seconds, so it has to be renewed before it expires.
+///
+/// # Errors
+/// - `GatewayNotFound` if the default gateway could not be read from the route table.
+/// - `Io` if the gateway could not be reached.
+/// - `InvalidResponse` if the gateway sent an unexpected response.
+/// - `MappingRefused` if the gateway refused the mapping.
+pub fn map_port(port: u16, lifetime: u32) -> Result<PortMapping, NatError> {
+    let gateway = default_gateway()?;
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(NatError::Io)?;
+    socket
+        .set_read_timeout(Some(Duration::from_millis(RESPONSE_TIMEOUT_MILLIS)))
+        .map_err(NatError::Io)?;
+    socket
+        .connect(SocketAddrV4::new(gateway, NAT_PMP_PORT))
+        .map_err(NatError::Io)?;
+
+    let external_ip =
+        parse_external_address_response(&send(&socket, &external_address_request())?)?;
+    let external_port = parse_map_response(&send(&socket, &map_request(port, port, lifetime))?)?;
+
+    Ok(PortMapping {
+        external_ip,
+        external_port,
+    })
+}
+
+fn send(socket: &UdpSocket, request: &[u8]) -> Result<Vec<u8>, NatError> {
+    socket.send(request).map_err(NatError::Io)?;
+    let mut buffer = [0; 16];
+    let read = socket.recv(&mut buffer).map_err(NatError::Io)?;
+    Ok(buffer[..read].to_vec())
+}
+
+/// Reads the default gateway from the route table of the system.
+fn default_gateway() -> Result<Ipv4Addr, NatError> {
+    let route_table = fs::read_to_string(ROUTE_TABLE_PATH).map_err(NatError::Io)?;
+    parse_default_gateway(&route_table).ok_or(NatError::GatewayNotFound)
+}
+
+/// Parses the default gateway from the contents of `/proc/net/route`.
+///
+/// The gateway is the one of the route whose destination is 0.0.0.0, written in hexadecimal in little endian.
+fn parse_default_gateway(route_table: &str) -> Option<Ipv4Addr> {
+    route_table.lines().skip(1).find_map(|line| {
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        match columns.as_slice() {
+            [_, "00000000", gateway, ..] => {
+                let gateway = u32::from_str_radix(gateway, 16).ok()?;
+                Some(Ipv4Addr::from(gateway.to_le_bytes()))
+            }
+            _ => None,
+        }
+    })
+}
+
+fn external_address_request() -> Vec<u8> {
+    vec![VERSION, OP_EXTERNAL_ADDRESS]
+}
+
+fn map_request(internal_port: u16, external_port: u16, lifetime: u32) -> Vec<u8> {
+    let mut request = vec![VERSION, OP_MAP_TCP, 0, 0];
+    request.extend(internal_port.to_be_bytes());
+    request.extend(external_port.to_be_bytes());
+    request.extend(lifetime.to_be_bytes());
+    request
+}
+
+/// Checks the header of a response and returns its body.
+fn check_response(response: &[u8], op: u8, length: usize) -> Result<&[u8], NatError> {
+    if response.len() < length || response[0] != VERSION || response[1] != op + RESPONSE_OFFSET {
+        return Err(NatError::InvalidResponse);
+    }
+    let result_code = u16::from_be_bytes([response[2], response[3]]);
+    if result_code != 0 {
+        return Err(NatError::MappingRefused(result_code.to_string()));
+    }
+    // Skip the header and the seconds since the gateway started.
+    Ok(&response[8..length])
+}
+
+fn parse_external_address_response(response: &[u8]) -> Result<Ipv4Addr, NatError> {
+    let body = check_response(response, OP_EXTERNAL_ADDRESS, 12)?;
+    Ok(Ipv4Addr::new(body[0], body[1], body[2], body[3]))
+}
+
+fn parse_map_response(response: &[u8]) -> Result<u16, NatError> {
+    let body = check_response(response, OP_MAP_TCP, 16)?;
+    Ok(u16::from_be_bytes([body[2], body[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_default_gateway() {
+        let route_table =
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+            eth0\t0000A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0\n\
+            eth0\t00000000\t0100A8C0\t0003\t0\t0\t0\t00000000\t0\t0\t0\n";
+
+        assert_eq!(
+            parse_default_gateway(route_table),
+            Some(Ipv4Addr::new(192, 168, 0, 1))
+        );
+    }
+
+    #[test]
+    fn test_parse_default_gateway_without_default_route() {
+        let route_table = "Iface\tDestination\tGateway\n eth0\t0000A8C0\t00000000\n";
+
+        assert_eq!(parse_default_gateway(route_table), None);
+    }
+
+    #[test]
+    fn test_map_request() {
+        assert_eq!(
+            map_request(6881, 6882, 3600),
+            vec![0, 2, 0, 0, 0x1a, 0xe1, 0x1a, 0xe2, 0, 0, 0x0e, 0x10]
+        );
+    }
+
+    #[test]
+    fn test_parse_external_address_response() {
+        let response = [0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 7];
+
+        assert_eq!(
+            parse_external_address_response(&response).unwrap(),
+            Ipv4Addr::new(203, 0, 113, 7)
+        );
+    }
+
+    #[test]
+    fn test_parse_map_response() {
+        let response = [
+            0, 130, 0, 0, 0, 0, 0, 1, 0x1a, 0xe1, 0x1a, 0xe2, 0, 0, 0x0e, 0x10,
+        ];
+
+        assert_eq!(parse_map_response(&response).unwrap(), 6882);
+    }
+
+    #[test]
+    fn test_refused_mapping() {
+        let response = [0, 130, 0, 2, 0, 0, 0, 1, 0x1a, 0xe1, 0, 0, 0, 0, 0, 0];
+
+        assert!(matches!(
+            parse_map_response(&response),
+            Err(NatError::MappingRefused(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_response() {
+        assert!(matches!(
+            parse_map_response(&[0, 128, 0, 0]),
+            Err(NatError::InvalidResponse)
+        ));
+    }
+}
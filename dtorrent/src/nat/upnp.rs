@@ -0,0 +1,280 @@
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use super::{NatError, PortMapping};
+
+const SSDP_ADDRESS: &str = "239.255.255.250:1900";
+const GATEWAY_SEARCH_TARGET: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+const WAN_CONNECTION_SERVICES: [&str; 2] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+const MAPPING_DESCRIPTION: &str = "dtorrent";
+const RESPONSE_TIMEOUT_SECONDS: u64 = 3;
+
+/// Maps the TCP `port` in the Internet Gateway Device found in the local network using UPnP.
+///
+/// # Errors
+/// - `Io` if the gateway could not be found or reached.
+/// - `InvalidResponse` if the gateway sent an unexpected response.
+/// - `MappingRefused` if the gateway refused the mapping.
+pub fn map_port(port: u16, lifetime: u32) -> Result<PortMapping, NatError> {
+    let location = discover_gateway()?;
+    let (address, path) = split_url(&location).ok_or(NatError::InvalidResponse)?;
+    let description = http_request(&address, &format!("GET {} HTTP/1.1", path), "", "")?;
+    let (service, control_url) =
+        find_control_url(&description.body).ok_or(NatError::InvalidResponse)?;
+    let control_path = match split_url(&control_url) {
+        Some((_, path)) => path,
+        None => control_url,
+    };
+
+    let add_mapping = http_request(
+        &address,
+        &format!("POST {} HTTP/1.1", control_path),
+        &soap_action(service, "AddPortMapping"),
+        &add_port_mapping_body(service, port, description.local_ip, lifetime),
+    )?;
+    if !add_mapping.is_success() {
+        return Err(NatError::MappingRefused(
+            extract_tag(&add_mapping.body, "errorDescription").unwrap_or_default(),
+        ));
+    }
+
+    let external_ip = http_request(
+        &address,
+        &format!("POST {} HTTP/1.1", control_path),
+        &soap_action(service, "GetExternalIPAddress"),
+        &soap_envelope(service, "GetExternalIPAddress", ""),
+    )?;
+    let external_ip = extract_tag(&external_ip.body, "NewExternalIPAddress")
+        .and_then(|ip| ip.parse().ok())
+        .ok_or(NatError::InvalidResponse)?;
+
+    Ok(PortMapping {
+        external_ip,
+        external_port: port,
+    })
+}
+
+/// Sends a SSDP search and returns the location of the description of the first gateway that answers.
+fn discover_gateway() -> Result<String, NatError> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(NatError::Io)?;
+    socket
+        .set_read_timeout(Some(Duration::from_secs(RESPONSE_TIMEOUT_SECONDS)))
+        .map_err(NatError::Io)?;
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\n\r\n",
+        SSDP_ADDRESS, GATEWAY_SEARCH_TARGET
+    );
+    socket
+        .send_to(search.as_bytes(), SSDP_ADDRESS)
+        .map_err(NatError::Io)?;
+
+    let mut buffer = [0; 2048];
+    let read = socket.recv(&mut buffer).map_err(NatError::Io)?;
+    parse_location(&String::from_utf8_lossy(&buffer[..read])).ok_or(NatError::InvalidResponse)
+}
+
+struct HttpResponse {
+    status: u16,
+    body: String,
+    /// Address of the local end of the connection, it is the address the gateway sees us with.
+    local_ip: IpAddr,
+}
+
+impl HttpResponse {
+    fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+fn http_request(
+    address: &str,
+    request_line: &str,
+    soap_action: &str,
+    body: &str,
+) -> Result<HttpResponse, NatError> {
+    let socket_address: SocketAddr = address
+        .to_socket_addrs()
+        .map_err(NatError::Io)?
+        .next()
+        .ok_or(NatError::InvalidResponse)?;
+    let mut stream = TcpStream::connect_timeout(
+        &socket_address,
+        Duration::from_secs(RESPONSE_TIMEOUT_SECONDS),
+    )
+    .map_err(NatError::Io)?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(RESPONSE_TIMEOUT_SECONDS)))
+        .map_err(NatError::Io)?;
+    let local_ip = stream.local_addr().map_err(NatError::Io)?.ip();
+
+    let mut request = format!(
+        "{}\r\nHost: {}\r\nConnection: close\r\n",
+        request_line, address
+    );
+    if !soap_action.is_empty() {
+        request.push_str("Content-Type: text/xml; charset=\"utf-8\"\r\n");
+        request.push_str(&format!("SOAPAction: {}\r\n", soap_action));
+    }
+    request.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+    stream.write_all(request.as_bytes()).map_err(NatError::Io)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).map_err(NatError::Io)?;
+    let response = String::from_utf8_lossy(&response);
+
+    let status = response
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or(NatError::InvalidResponse)?;
+    let body = match response.split_once("\r\n\r\n") {
+        Some((_, body)) => body.to_string(),
+        None => String::new(),
+    };
+
+    Ok(HttpResponse {
+        status,
+        body,
+        local_ip,
+    })
+}
+
+/// Returns the value of the LOCATION header of a SSDP response.
+fn parse_location(response: &str) -> Option<String> {
+    response.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if name.trim().eq_ignore_ascii_case("location") {
+            Some(value.trim().to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// Splits an `http://host:port/path` url in its address and path.
+fn split_url(url: &str) -> Option<(String, String)> {
+    let url = url.strip_prefix("http://")?;
+    match url.split_once('/') {
+        Some((address, path)) => Some((address.to_string(), format!("/{}", path))),
+        None => Some((url.to_string(), "/".to_string())),
+    }
+}
+
+/// Finds the WAN connection service in a device description and returns its type and control url.
+fn find_control_url(description: &str) -> Option<(&'static str, String)> {
+    description.split("<service>").skip(1).find_map(|service| {
+        let service_type = extract_tag(service, "serviceType")?;
+        let service_type = WAN_CONNECTION_SERVICES
+            .into_iter()
+            .find(|wan_service| *wan_service == service_type)?;
+        Some((service_type, extract_tag(service, "controlURL")?))
+    })
+}
+
+/// Returns the text inside the first `tag` element of `xml`, ignoring namespace prefixes.
+fn extract_tag(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("{}>", tag))? + tag.len() + 1;
+    let end = start + xml[start..].find("</")?;
+    Some(xml[start..end].trim().to_string())
+}
+
+fn soap_action(service: &str, action: &str) -> String {
+    format!("\"{}#{}\"", service, action)
+}
+
+fn soap_envelope(service: &str, action: &str, arguments: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\r\n\
+        <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+        <s:Body><u:{action} xmlns:u=\"{service}\">{arguments}</u:{action}></s:Body></s:Envelope>",
+    )
+}
+
+fn add_port_mapping_body(service: &str, port: u16, local_ip: IpAddr, lifetime: u32) -> String {
+    let local_ip = match local_ip {
+        IpAddr::V4(ip) => ip,
+        IpAddr::V6(_) => Ipv4Addr::UNSPECIFIED,
+    };
+    let arguments = format!(
+        "<NewRemoteHost></NewRemoteHost>\
+        <NewExternalPort>{port}</NewExternalPort>\
+        <NewProtocol>TCP</NewProtocol>\
+        <NewInternalPort>{port}</NewInternalPort>\
+        <NewInternalClient>{local_ip}</NewInternalClient>\
+        <NewEnabled>1</NewEnabled>\
+        <NewPortMappingDescription>{MAPPING_DESCRIPTION}</NewPortMappingDescription>\
+        <NewLeaseDuration>{lifetime}</NewLeaseDuration>",
+    );
+    soap_envelope(service, "AddPortMapping", &arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_location() {
+        let response = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=120\r\nLocation: http://192.168.0.1:5000/rootDesc.xml\r\nST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\r\n";
+
+        assert_eq!(
+            parse_location(response),
+            Some("http://192.168.0.1:5000/rootDesc.xml".to_string())
+        );
+    }
+
+    #[test]
+    fn test_split_url() {
+        assert_eq!(
+            split_url("http://192.168.0.1:5000/ctl/IPConn"),
+            Some(("192.168.0.1:5000".to_string(), "/ctl/IPConn".to_string()))
+        );
+        assert_eq!(split_url("https://192.168.0.1/"), None);
+    }
+
+    #[test]
+    fn test_find_control_url() {
+        let description = "<root><device><serviceList>\
+            <service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType><controlURL>/ctl/L3F</controlURL></service>\
+            <service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType><controlURL>/ctl/IPConn</controlURL></service>\
+            </serviceList></device></root>";
+
+        assert_eq!(
+            find_control_url(description),
+            Some((WAN_CONNECTION_SERVICES[0], "/ctl/IPConn".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_extract_tag_with_namespace() {
+        let response = "<s:Envelope><s:Body><u:GetExternalIPAddressResponse xmlns:u=\"urn:schemas-upnp-org:service:WANIPConnection:1\">\
+            <NewExternalIPAddress>203.0.113.7</NewExternalIPAddress>\
+            </u:GetExternalIPAddressResponse></s:Body></s:Envelope>";
+
+        assert_eq!(
+            extract_tag(response, "NewExternalIPAddress"),
+            Some("203.0.113.7".to_string())
+        );
+        assert_eq!(extract_tag(response, "NewExternalPort"), None);
+    }
+
+    #[test]
+    fn test_add_port_mapping_body() {
+        let body = add_port_mapping_body(
+            WAN_CONNECTION_SERVICES[0],
+            6881,
+            IpAddr::V4(Ipv4Addr::new(192, 168, 0, 10)),
+            3600,
+        );
+
+        assert!(body.contains(
+            "<u:AddPortMapping xmlns:u=\"urn:schemas-upnp-org:service:WANIPConnection:1\">"
+        ));
+        assert!(body.contains("<NewExternalPort>6881</NewExternalPort>"));
+        assert!(body.contains("<NewInternalClient>192.168.0.10</NewInternalClient>"));
+        assert!(body.contains("<NewLeaseDuration>3600</NewLeaseDuration>"));
+    }
+}
@@ -0,0 +1,110 @@
+/// Size of the blocks requested to the peers, unless the `block_size` setting overrides it.
+pub const BLOCK_SIZE: u32 = 16384;
+
+/// Returns the number of blocks of `block_size` bytes a piece of `piece_size` bytes is split into.
+///
+/// A `block_size` of 0 is treated as 1.
+pub fn block_count(piece_size: u32, block_size: u32) -> u32 {
+    piece_size.div_ceil(block_size.max(1))
+}
+
+/// Returns the size of the last block of a piece of `piece_size` bytes, which may be shorter than `block_size`.
+///
+/// A `block_size` of 0 is treated as 1.
+pub fn last_block_size(piece_size: u32, block_size: u32) -> u32 {
+    let block_size = block_size.max(1);
+    match piece_size % block_size {
+        0 if piece_size == 0 => 0,
+        0 => block_size,
+        remainder => remainder,
+    }
+}
+
+/// Returns an iterator over the `(begin, length)` of the blocks of a piece of `piece_size` bytes.
+///
+/// Every block is `block_size` bytes long except the last one, which may be shorter. A piece smaller than one block
+/// has a single block with the whole piece. A `block_size` of 0 is treated as 1.
+pub fn blocks(piece_size: u32, block_size: u32) -> BlockIter {
+    BlockIter {
+        piece_size,
+        block_size: block_size.max(1),
+        begin: 0,
+    }
+}
+
+/// Iterator over the blocks of a piece, see `blocks`.
+#[derive(Debug, Clone)]
+pub struct BlockIter {
+    piece_size: u32,
+    block_size: u32,
+    begin: u32,
+}
+
+impl Iterator for BlockIter {
+    type Item = (u32, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.begin >= self.piece_size {
+            return None;
+        }
+        let begin = self.begin;
+        let length = self.block_size.min(self.piece_size - begin);
+        self.begin += length;
+        Some((begin, length))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = block_count(
+            self.piece_size - self.begin.min(self.piece_size),
+            self.block_size,
+        );
+        (remaining as usize, Some(remaining as usize))
+    }
+}
+
+impl ExactSizeIterator for BlockIter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_piece_multiple_of_block_size() {
+        assert_eq!(block_count(BLOCK_SIZE * 4, BLOCK_SIZE), 4);
+        assert_eq!(last_block_size(BLOCK_SIZE * 4, BLOCK_SIZE), BLOCK_SIZE);
+    }
+
+    #[test]
+    fn test_piece_with_shorter_last_block() {
+        assert_eq!(block_count(10_000, 4096), 3);
+        assert_eq!(last_block_size(10_000, 4096), 10_000 - 2 * 4096);
+        assert_eq!(block_count(100, BLOCK_SIZE), 1);
+        assert_eq!(last_block_size(100, BLOCK_SIZE), 100);
+    }
+
+    #[test]
+    fn test_empty_piece_and_zero_block_size() {
+        assert_eq!(block_count(0, BLOCK_SIZE), 0);
+        assert_eq!(last_block_size(0, BLOCK_SIZE), 0);
+        assert_eq!(block_count(3, 0), 3);
+        assert_eq!(last_block_size(3, 0), 1);
+    }
+
+    #[test]
+    fn test_blocks_of_a_piece() {
+        let blocks: Vec<(u32, u32)> = blocks(10_000, 4096).collect();
+
+        assert_eq!(blocks, vec![(0, 4096), (4096, 4096), (8192, 1808)]);
+        assert_eq!(blocks.len() as u32, block_count(10_000, 4096));
+    }
+
+    #[test]
+    fn test_piece_smaller_than_one_block() {
+        let mut iter = blocks(100, BLOCK_SIZE);
+
+        assert_eq!(iter.len(), 1);
+        assert_eq!(iter.next(), Some((0, 100)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(blocks(0, BLOCK_SIZE).next(), None);
+    }
+}
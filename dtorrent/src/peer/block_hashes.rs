@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+
+use crate::torrent_parser::{
+    merkle::{self, MerkleHash, MERKLE_BLOCK_SIZE},
+    torrent::Torrent,
+};
+
+use super::{
+    block,
+    peer_message::{HashRequest, Hashes},
+};
+
+/// Outcome of checking a received block against the hashes of its piece.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BlockCheck {
+    /// The hashes of the piece are not known yet, or the block does not cover whole merkle blocks.
+    Unknown,
+    Valid,
+    Corrupted,
+}
+
+/// Posible `BlockHashes` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BlockHashesError {
+    /// The hashes answer a request that was not sent, or was already answered.
+    UnexpectedHashes,
+    /// The hashes do not hash up to the hash of their piece in the piece layer.
+    InvalidHashes,
+}
+
+/// Hashes of the 16 KiB blocks of the pieces of a hybrid torrent (BEP 52) downloaded from a peer, so a corrupted
+/// block is found as soon as it is received instead of when its piece fails the hash check.
+///
+/// The hashes of a piece are asked to the peer with a Hash Request when the piece is selected, and checked against
+/// the hash of the piece in the piece layer of the torrent before they are used.
+///
+/// To create a new `BlockHashes` use `BlockHashes::new`.
+#[derive(Debug)]
+pub struct BlockHashes {
+    pieces_root: MerkleHash,
+    piece_layer: Vec<MerkleHash>,
+    piece_length: u64,
+    length: u64,
+    /// Leaves of the subtree of a piece, every piece but the only one of a single piece file has the same.
+    leaves_per_piece: u32,
+    unsent: Vec<HashRequest>,
+    sent: Vec<HashRequest>,
+    verified: HashMap<u32, Vec<MerkleHash>>,
+}
+
+impl BlockHashes {
+    /// Creates the `BlockHashes` of a hybrid torrent, or returns `None` if the torrent has no piece layer.
+    pub fn new(torrent: &Torrent) -> Option<Self> {
+        let pieces_root = torrent.pieces_root()?;
+        if torrent.piece_layer.is_empty() {
+            return None;
+        }
+        let leaves_per_piece = if torrent.total_pieces() == 1 {
+            block::block_count(torrent.length() as u32, MERKLE_BLOCK_SIZE).next_power_of_two()
+        } else {
+            merkle::blocks_per_piece(torrent.piece_length() as u64)?
+        };
+
+        Some(Self {
+            pieces_root,
+            piece_layer: torrent.piece_layer.clone(),
+            piece_length: torrent.piece_length() as u64,
+            length: torrent.length(),
+            leaves_per_piece,
+            unsent: vec![],
+            sent: vec![],
+            verified: HashMap::new(),
+        })
+    }
+
+    /// Queues the Hash Request of the hashes of the blocks of a piece, unless the piece is a single block.
+    pub fn piece_selected(&mut self, index: u32) {
+        if self.leaves_per_piece > 1 {
+            self.unsent.push(self.request(index));
+        }
+    }
+
+    /// Returns the queued Hash Requests, that are sent to the peer.
+    pub fn take_unsent(&mut self) -> Vec<HashRequest> {
+        let unsent = std::mem::take(&mut self.unsent);
+        self.sent.extend(&unsent);
+        unsent
+    }
+
+    /// Checks the hashes received for a piece against the piece layer, and keeps them to check its blocks.
+    ///
+    /// Returns the index of the piece.
+    ///
+    /// # Errors
+    ///
+    /// - `UnexpectedHashes` if the hashes do not answer a sent request.
+    /// - `InvalidHashes` if they do not hash up to the hash of the piece.
+    pub fn hashes_received(&mut self, hashes: &Hashes) -> Result<u32, BlockHashesError> {
+        let position = self
+            .sent
+            .iter()
+            .position(|request| *request == hashes.request)
+            .ok_or(BlockHashesError::UnexpectedHashes)?;
+        let request = self.sent.remove(position);
+        let index = request.index / self.leaves_per_piece;
+
+        let (leaves, _) = hashes.split().ok_or(BlockHashesError::InvalidHashes)?;
+        if merkle::root(leaves, leaves.len(), 0) != self.piece_layer[index as usize] {
+            return Err(BlockHashesError::InvalidHashes);
+        }
+        self.verified.insert(index, leaves.to_vec());
+        Ok(index)
+    }
+
+    /// Forgets a request the peer will not answer.
+    pub fn request_rejected(&mut self, request: &HashRequest) {
+        self.sent.retain(|sent| sent != request);
+    }
+
+    /// Checks a block of a piece against the hashes of the piece, if they were received.
+    pub fn check_block(&self, index: u32, begin: u32, block: &[u8]) -> BlockCheck {
+        let leaves = match self.verified.get(&index) {
+            Some(leaves) => leaves,
+            None => return BlockCheck::Unknown,
+        };
+        let ends_piece = begin as u64 + block.len() as u64 == self.piece_size(index);
+        if !begin.is_multiple_of(MERKLE_BLOCK_SIZE)
+            || (!(block.len() as u32).is_multiple_of(MERKLE_BLOCK_SIZE) && !ends_piece)
+        {
+            return BlockCheck::Unknown;
+        }
+
+        let first_leaf = (begin / MERKLE_BLOCK_SIZE) as usize;
+        let valid = merkle::block_hashes(block)
+            .iter()
+            .enumerate()
+            .all(|(i, hash)| leaves.get(first_leaf + i) == Some(hash));
+        if valid {
+            BlockCheck::Valid
+        } else {
+            BlockCheck::Corrupted
+        }
+    }
+
+    /// Forgets the hashes of a piece that is no longer downloaded from the peer.
+    pub fn forget(&mut self, index: u32) {
+        self.verified.remove(&index);
+        let leaves_per_piece = self.leaves_per_piece;
+        let of_piece = |request: &HashRequest| request.index / leaves_per_piece == index;
+        self.unsent.retain(|request| !of_piece(request));
+        self.sent.retain(|request| !of_piece(request));
+    }
+
+    /// Returns the answer to a Hash Request for the blocks of a piece we have, whose data is `piece`, or `None` if
+    /// it is not the request of the blocks of the piece.
+    ///
+    /// Only requests of the leaves of a whole piece, as sent by `piece_selected`, are answered. Their uncle hashes
+    /// are taken from the piece layer.
+    pub fn answer(&self, request: &HashRequest, piece: &[u8]) -> Option<Hashes> {
+        if request.pieces_root != self.pieces_root
+            || request.base_layer != 0
+            || request.length != self.leaves_per_piece
+            || !request.index.is_multiple_of(self.leaves_per_piece)
+        {
+            return None;
+        }
+        let index = request.index / self.leaves_per_piece;
+        if index as usize >= self.piece_layer.len() || piece.len() as u64 != self.piece_size(index)
+        {
+            return None;
+        }
+
+        let mut hashes = merkle::block_hashes(piece);
+        hashes.resize(self.leaves_per_piece as usize, merkle::pad_hash(0));
+        let piece_layer_index = self.leaves_per_piece.trailing_zeros();
+        hashes.extend(merkle::proof(
+            &self.piece_layer,
+            piece_layer_index,
+            index as usize,
+            request.proof_layers,
+        ));
+
+        Some(Hashes {
+            request: *request,
+            hashes,
+        })
+    }
+
+    /// Returns the index of the piece of a Hash Request, if it is a request of the blocks of a whole piece.
+    pub fn requested_piece(&self, request: &HashRequest) -> Option<u32> {
+        request
+            .index
+            .is_multiple_of(self.leaves_per_piece)
+            .then(|| request.index / self.leaves_per_piece)
+    }
+
+    fn request(&self, index: u32) -> HashRequest {
+        HashRequest {
+            pieces_root: self.pieces_root,
+            base_layer: 0,
+            index: index * self.leaves_per_piece,
+            length: self.leaves_per_piece,
+            proof_layers: 0,
+        }
+    }
+
+    fn piece_size(&self, index: u32) -> u64 {
+        let offset = index as u64 * self.piece_length;
+        self.piece_length.min(self.length.saturating_sub(offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::local_swarm::generated_hybrid_torrent;
+
+    const PIECE_LENGTH: usize = 4 * MERKLE_BLOCK_SIZE as usize;
+
+    #[test]
+    fn test_blocks_are_checked_with_the_hashes_of_the_peer() {
+        let (torrent, data) =
+            generated_hybrid_torrent("hybrid", 3 * PIECE_LENGTH - 100, PIECE_LENGTH, "");
+        let seeder = BlockHashes::new(&torrent).unwrap();
+        let mut leecher = BlockHashes::new(&torrent).unwrap();
+
+        leecher.piece_selected(2);
+        let requests = leecher.take_unsent();
+        assert_eq!(requests.len(), 1);
+        assert_eq!((requests[0].index, requests[0].length), (8, 4));
+
+        let piece = &data[2 * PIECE_LENGTH..];
+        assert_eq!(
+            leecher.check_block(2, 0, &piece[..16384]),
+            BlockCheck::Unknown
+        );
+        let hashes = seeder.answer(&requests[0], piece).unwrap();
+        assert_eq!(leecher.hashes_received(&hashes), Ok(2));
+
+        assert_eq!(
+            leecher.check_block(2, 0, &piece[..16384]),
+            BlockCheck::Valid
+        );
+        // The last block of the torrent is shorter.
+        assert_eq!(
+            leecher.check_block(2, 16384 * 3, &piece[16384 * 3..]),
+            BlockCheck::Valid
+        );
+        let mut corrupted = piece[16384..32768].to_vec();
+        corrupted[10] ^= 1;
+        assert_eq!(
+            leecher.check_block(2, 16384, &corrupted),
+            BlockCheck::Corrupted
+        );
+        assert_eq!(
+            leecher.check_block(2, 100, &piece[100..200]),
+            BlockCheck::Unknown
+        );
+        assert_eq!(
+            leecher.hashes_received(&hashes),
+            Err(BlockHashesError::UnexpectedHashes)
+        );
+    }
+
+    #[test]
+    fn test_hashes_not_matching_the_piece_layer_are_rejected() {
+        let (torrent, data) =
+            generated_hybrid_torrent("hybrid", 2 * PIECE_LENGTH, PIECE_LENGTH, "");
+        let seeder = BlockHashes::new(&torrent).unwrap();
+        let mut leecher = BlockHashes::new(&torrent).unwrap();
+
+        leecher.piece_selected(0);
+        let request = leecher.take_unsent()[0];
+        let mut hashes = seeder.answer(&request, &data[..PIECE_LENGTH]).unwrap();
+        hashes.hashes[1][0] ^= 1;
+
+        assert_eq!(
+            leecher.hashes_received(&hashes),
+            Err(BlockHashesError::InvalidHashes)
+        );
+        assert_eq!(
+            leecher.check_block(0, 0, &data[..16384]),
+            BlockCheck::Unknown
+        );
+    }
+
+    #[test]
+    fn test_answer_with_proof() {
+        let (torrent, data) =
+            generated_hybrid_torrent("hybrid", 3 * PIECE_LENGTH, PIECE_LENGTH, "");
+        let seeder = BlockHashes::new(&torrent).unwrap();
+        let request = HashRequest {
+            pieces_root: torrent.pieces_root().unwrap(),
+            base_layer: 0,
+            index: 4,
+            length: 4,
+            proof_layers: 2,
+        };
+
+        let hashes = seeder
+            .answer(&request, &data[PIECE_LENGTH..2 * PIECE_LENGTH])
+            .unwrap();
+        let (leaves, uncles) = hashes.split().unwrap();
+
+        assert_eq!(uncles.len(), 2);
+        assert!(merkle::verify_proof(
+            merkle::root(leaves, 4, 0),
+            1,
+            uncles,
+            &torrent.pieces_root().unwrap()
+        ));
+        assert!(seeder
+            .answer(
+                &HashRequest {
+                    index: 2,
+                    ..request
+                },
+                &data[..PIECE_LENGTH]
+            )
+            .is_none());
+    }
+}
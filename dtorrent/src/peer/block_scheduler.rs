@@ -0,0 +1,543 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::block::{self, BLOCK_SIZE};
+
+/// Max number of outstanding block requests to a single peer.
+const MAX_QUEUE_DEPTH: u32 = 250;
+
+/// Seconds worth of data we want to have requested to the peer, to keep the link busy while the requests travel.
+const REQUEST_QUEUE_SECONDS: f64 = 3.0;
+
+/// Weight of a new latency sample in the moving average.
+const LATENCY_SAMPLE_WEIGHT: f64 = 0.2;
+
+/// A block requested to the peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRequest {
+    pub index: u32,
+    pub begin: u32,
+    pub length: u32,
+}
+
+/// Posible errors when a block is received.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BlockSchedulerError {
+    /// The block was not requested, or its length does not match the request.
+    UnexpectedBlock,
+}
+
+/// A piece being downloaded from the peer.
+#[derive(Debug)]
+struct PieceInProgress {
+    index: u32,
+    data: Vec<u8>,
+    /// Blocks of the piece that were not requested yet.
+    pending_blocks: VecDeque<BlockRequest>,
+    missing_blocks: usize,
+}
+
+/// Schedules the block requests sent to a peer.
+///
+/// It keeps a target number of outstanding block requests, which may span several pieces, so the peer always has
+/// requests queued and the link does not sit idle waiting for a round trip. The target depth adapts to the measured
+/// latency of the requests and the download rate of the peer.
+#[derive(Debug)]
+pub struct BlockScheduler {
+    min_queue_depth: u32,
+    block_size: u32,
+    pieces: Vec<PieceInProgress>,
+    outstanding: VecDeque<(BlockRequest, Instant)>,
+    latency: Option<Duration>,
+    snubbed: bool,
+}
+
+impl BlockScheduler {
+    /// Creates a new `BlockScheduler` that keeps at least `min_queue_depth` requests outstanding.
+    ///
+    /// Pieces are requested in blocks of `BLOCK_SIZE` bytes, use `with_block_size` to change it.
+    pub fn new(min_queue_depth: u32) -> Self {
+        Self::with_block_size(min_queue_depth, BLOCK_SIZE)
+    }
+
+    /// Creates a new `BlockScheduler` that requests the pieces in blocks of `block_size` bytes.
+    ///
+    /// A `block_size` of 0 is treated as 1.
+    pub fn with_block_size(min_queue_depth: u32, block_size: u32) -> Self {
+        Self {
+            min_queue_depth: min_queue_depth.clamp(1, MAX_QUEUE_DEPTH),
+            block_size: block_size.max(1),
+            pieces: vec![],
+            outstanding: VecDeque::new(),
+            latency: None,
+            snubbed: false,
+        }
+    }
+
+    /// Starts downloading a piece of `size` bytes.
+    pub fn add_piece(&mut self, index: u32, size: u32) {
+        let pending_blocks: VecDeque<BlockRequest> = block::blocks(size, self.block_size)
+            .map(|(begin, length)| BlockRequest {
+                index,
+                begin,
+                length,
+            })
+            .collect();
+
+        self.pieces.push(PieceInProgress {
+            index,
+            data: vec![0; size as usize],
+            missing_blocks: pending_blocks.len(),
+            pending_blocks,
+        });
+    }
+
+    /// Returns true if all the blocks of the pieces in progress are requested and there is room for more requests,
+    /// so a new piece should be added.
+    pub fn wants_more_pieces(&self, bytes_per_second: f64) -> bool {
+        self.pieces
+            .iter()
+            .all(|piece| piece.pending_blocks.is_empty())
+            && (self.outstanding.len() as u32) < self.target_queue_depth(bytes_per_second)
+    }
+
+    /// Returns the blocks to request to fill the queue up to the target depth, and marks them as outstanding.
+    pub fn next_requests(&mut self, bytes_per_second: f64) -> Vec<BlockRequest> {
+        let target = self.target_queue_depth(bytes_per_second) as usize;
+        let mut requests = vec![];
+
+        for piece in self.pieces.iter_mut() {
+            while self.outstanding.len() < target {
+                match piece.pending_blocks.pop_front() {
+                    Some(request) => {
+                        self.outstanding.push_back((request, Instant::now()));
+                        requests.push(request);
+                    }
+                    None => break,
+                }
+            }
+        }
+        requests
+    }
+
+    /// Stores a received block.
+    ///
+    /// Blocks whose requests were dropped are still accepted if they were not requested again yet, since they may
+    /// have been in flight when the peer choked us.
+    ///
+    /// Returns the index and data of the piece if the block was the last one missing.
+    ///
+    /// # Errors
+    /// - `UnexpectedBlock` if the block was not requested.
+    pub fn block_received(
+        &mut self,
+        index: u32,
+        begin: u32,
+        block: &[u8],
+    ) -> Result<Option<(u32, Vec<u8>)>, BlockSchedulerError> {
+        let is_block = |request: &BlockRequest| {
+            request.index == index
+                && request.begin == begin
+                && request.length as usize == block.len()
+        };
+
+        let piece_position = self
+            .pieces
+            .iter()
+            .position(|piece| piece.index == index)
+            .ok_or(BlockSchedulerError::UnexpectedBlock)?;
+
+        match self
+            .outstanding
+            .iter()
+            .position(|(request, _)| is_block(request))
+        {
+            Some(position) => {
+                if let Some((_, requested_at)) = self.outstanding.remove(position) {
+                    self.add_latency_sample(requested_at.elapsed());
+                }
+            }
+            None => {
+                let pending_blocks = &mut self.pieces[piece_position].pending_blocks;
+                let position = pending_blocks
+                    .iter()
+                    .position(is_block)
+                    .ok_or(BlockSchedulerError::UnexpectedBlock)?;
+                pending_blocks.remove(position);
+            }
+        }
+
+        let piece = &mut self.pieces[piece_position];
+        let begin = begin as usize;
+        piece.data[begin..begin + block.len()].copy_from_slice(block);
+        piece.missing_blocks -= 1;
+
+        if piece.missing_blocks == 0 {
+            let piece = self.pieces.remove(piece_position);
+            return Ok(Some((piece.index, piece.data)));
+        }
+        Ok(None)
+    }
+
+    /// Requests again a received block that was found corrupted, before the rest of the blocks of its piece.
+    ///
+    /// Returns false if the block was not outstanding.
+    pub fn block_rejected(&mut self, index: u32, begin: u32, length: u32) -> bool {
+        let position = self.outstanding.iter().position(|(request, _)| {
+            request.index == index && request.begin == begin && request.length == length
+        });
+        let piece = self.pieces.iter_mut().find(|piece| piece.index == index);
+
+        match (position, piece) {
+            (Some(position), Some(piece)) => {
+                if let Some((request, _)) = self.outstanding.remove(position) {
+                    piece.pending_blocks.push_front(request);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forgets the outstanding requests, so they are requested again.
+    ///
+    /// It must be called when the peer chokes us, as it discards all our pending requests.
+    pub fn requests_dropped(&mut self) {
+        while let Some((request, _)) = self.outstanding.pop_back() {
+            if let Some(piece) = self
+                .pieces
+                .iter_mut()
+                .find(|piece| piece.index == request.index)
+            {
+                piece.pending_blocks.push_front(request);
+            }
+        }
+    }
+
+    /// Stops downloading a piece and returns its outstanding requests, so they can be cancelled.
+    pub fn cancel_piece(&mut self, index: u32) -> Vec<BlockRequest> {
+        self.pieces.retain(|piece| piece.index != index);
+
+        let (cancelled, outstanding) = self
+            .outstanding
+            .drain(..)
+            .partition(|(request, _)| request.index == index);
+        self.outstanding = outstanding;
+
+        cancelled
+            .into_iter()
+            .map(|(request, _): (BlockRequest, Instant)| request)
+            .collect()
+    }
+
+    /// Returns the indexes of the pieces in progress.
+    pub fn pieces_in_progress(&self) -> Vec<u32> {
+        self.pieces.iter().map(|piece| piece.index).collect()
+    }
+
+    /// Removes all the pieces in progress and returns their indexes.
+    pub fn take_pieces(&mut self) -> Vec<u32> {
+        self.outstanding.clear();
+        self.pieces.drain(..).map(|piece| piece.index).collect()
+    }
+
+    /// Returns true if there are no pieces being downloaded.
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    /// Returns the number of outstanding requests.
+    pub fn outstanding_requests(&self) -> usize {
+        self.outstanding.len()
+    }
+
+    /// Returns the time elapsed since the oldest outstanding request was sent.
+    pub fn oldest_request_age(&self) -> Option<Duration> {
+        self.outstanding
+            .iter()
+            .map(|(_, requested_at)| requested_at.elapsed())
+            .max()
+    }
+
+    /// Returns true if a request has been outstanding for longer than `timeout`.
+    pub fn has_timed_out(&self, timeout: Duration) -> bool {
+        self.oldest_request_age().is_some_and(|age| age >= timeout)
+    }
+
+    /// Marks the peer as snubbed, snubbed peers get a single outstanding request until they send a block.
+    pub fn set_snubbed(&mut self, snubbed: bool) {
+        self.snubbed = snubbed;
+    }
+
+    /// Returns the number of requests we want to have outstanding, given the download rate of the peer.
+    ///
+    /// It is enough blocks to cover `REQUEST_QUEUE_SECONDS` of download plus the latency of the requests, so links
+    /// with a high latency get a deeper queue.
+    pub fn target_queue_depth(&self, bytes_per_second: f64) -> u32 {
+        if self.snubbed {
+            return 1;
+        }
+
+        let latency = self.latency.map_or(0.0, |latency| latency.as_secs_f64());
+        let bytes_in_flight = bytes_per_second * (REQUEST_QUEUE_SECONDS + latency);
+        let depth = (bytes_in_flight / self.block_size as f64).ceil() as u32;
+
+        depth.clamp(self.min_queue_depth, MAX_QUEUE_DEPTH)
+    }
+
+    fn add_latency_sample(&mut self, sample: Duration) {
+        self.latency = Some(match self.latency {
+            Some(latency) => {
+                latency.mul_f64(1.0 - LATENCY_SAMPLE_WEIGHT) + sample.mul_f64(LATENCY_SAMPLE_WEIGHT)
+            }
+            None => sample,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_span_multiple_pieces() {
+        let mut scheduler = BlockScheduler::new(5);
+        scheduler.add_piece(0, BLOCK_SIZE * 2);
+        assert!(!scheduler.wants_more_pieces(0.0));
+
+        let requests = scheduler.next_requests(0.0);
+        assert_eq!(requests.len(), 2);
+        assert!(scheduler.wants_more_pieces(0.0));
+
+        scheduler.add_piece(1, BLOCK_SIZE * 4);
+        let requests = scheduler.next_requests(0.0);
+
+        assert_eq!(
+            requests,
+            vec![
+                BlockRequest {
+                    index: 1,
+                    begin: 0,
+                    length: BLOCK_SIZE
+                },
+                BlockRequest {
+                    index: 1,
+                    begin: BLOCK_SIZE,
+                    length: BLOCK_SIZE
+                },
+                BlockRequest {
+                    index: 1,
+                    begin: BLOCK_SIZE * 2,
+                    length: BLOCK_SIZE
+                },
+            ]
+        );
+        assert_eq!(scheduler.outstanding_requests(), 5);
+        assert!(!scheduler.wants_more_pieces(0.0));
+    }
+
+    #[test]
+    fn test_last_block_is_shorter() {
+        let mut scheduler = BlockScheduler::new(5);
+        scheduler.add_piece(0, BLOCK_SIZE + 100);
+
+        let requests = scheduler.next_requests(0.0);
+
+        assert_eq!(requests[1].begin, BLOCK_SIZE);
+        assert_eq!(requests[1].length, 100);
+    }
+
+    #[test]
+    fn test_smaller_block_size() {
+        let mut scheduler = BlockScheduler::with_block_size(5, 1000);
+        scheduler.add_piece(0, 2500);
+
+        let requests = scheduler.next_requests(0.0);
+
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2].begin, 2000);
+        assert_eq!(requests[2].length, 500);
+        assert_eq!(scheduler.block_received(0, 0, &[1; 1000]), Ok(None));
+        assert_eq!(scheduler.block_received(0, 2000, &[3; 500]), Ok(None));
+        let (_, data) = scheduler
+            .block_received(0, 1000, &[2; 1000])
+            .unwrap()
+            .unwrap();
+        assert_eq!(data.len(), 2500);
+        assert_eq!(data[1999], 2);
+    }
+
+    #[test]
+    fn test_piece_is_returned_when_all_blocks_are_received() {
+        let mut scheduler = BlockScheduler::new(5);
+        scheduler.add_piece(3, BLOCK_SIZE + 2);
+        scheduler.next_requests(0.0);
+
+        // Blocks can arrive in any order.
+        assert_eq!(scheduler.block_received(3, BLOCK_SIZE, &[7, 8]), Ok(None));
+        let (index, data) = scheduler
+            .block_received(3, 0, &[1; BLOCK_SIZE as usize])
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(index, 3);
+        assert_eq!(data.len(), BLOCK_SIZE as usize + 2);
+        assert_eq!(data[0], 1);
+        assert_eq!(data[BLOCK_SIZE as usize..], [7, 8]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_unexpected_block() {
+        let mut scheduler = BlockScheduler::new(5);
+        scheduler.add_piece(0, BLOCK_SIZE);
+        scheduler.next_requests(0.0);
+
+        assert_eq!(
+            scheduler.block_received(1, 0, &[0; BLOCK_SIZE as usize]),
+            Err(BlockSchedulerError::UnexpectedBlock)
+        );
+        assert_eq!(
+            scheduler.block_received(0, 0, &[0; 10]),
+            Err(BlockSchedulerError::UnexpectedBlock)
+        );
+    }
+
+    #[test]
+    fn test_dropped_requests_are_requested_again() {
+        let mut scheduler = BlockScheduler::new(5);
+        scheduler.add_piece(0, BLOCK_SIZE * 2);
+        let requests = scheduler.next_requests(0.0);
+
+        scheduler.requests_dropped();
+
+        assert_eq!(scheduler.outstanding_requests(), 0);
+        assert_eq!(scheduler.next_requests(0.0), requests);
+    }
+
+    #[test]
+    fn test_block_in_flight_when_choked_is_kept() {
+        let mut scheduler = BlockScheduler::new(5);
+        scheduler.add_piece(0, BLOCK_SIZE * 2);
+        scheduler.next_requests(0.0);
+
+        scheduler.requests_dropped();
+        assert_eq!(
+            scheduler.block_received(0, 0, &[1; BLOCK_SIZE as usize]),
+            Ok(None)
+        );
+
+        // Only the missing block is requested after the unchoke.
+        let requests = scheduler.next_requests(0.0);
+        assert_eq!(
+            requests,
+            vec![BlockRequest {
+                index: 0,
+                begin: BLOCK_SIZE,
+                length: BLOCK_SIZE
+            }]
+        );
+        assert!(scheduler
+            .block_received(0, BLOCK_SIZE, &[2; BLOCK_SIZE as usize])
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_block_received_twice_is_unexpected() {
+        let mut scheduler = BlockScheduler::new(5);
+        scheduler.add_piece(0, BLOCK_SIZE * 2);
+        scheduler.next_requests(0.0);
+
+        assert_eq!(
+            scheduler.block_received(0, 0, &[1; BLOCK_SIZE as usize]),
+            Ok(None)
+        );
+        assert_eq!(
+            scheduler.block_received(0, 0, &[1; BLOCK_SIZE as usize]),
+            Err(BlockSchedulerError::UnexpectedBlock)
+        );
+    }
+
+    #[test]
+    fn test_rejected_block_is_requested_again() {
+        let mut scheduler = BlockScheduler::new(5);
+        scheduler.add_piece(0, BLOCK_SIZE * 2);
+        scheduler.next_requests(0.0);
+
+        assert!(scheduler.block_rejected(0, BLOCK_SIZE, BLOCK_SIZE));
+        assert!(!scheduler.block_rejected(0, BLOCK_SIZE, BLOCK_SIZE));
+        assert_eq!(scheduler.outstanding_requests(), 1);
+        assert_eq!(
+            scheduler.next_requests(0.0),
+            vec![BlockRequest {
+                index: 0,
+                begin: BLOCK_SIZE,
+                length: BLOCK_SIZE,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_cancel_piece() {
+        let mut scheduler = BlockScheduler::new(5);
+        scheduler.add_piece(0, BLOCK_SIZE * 2);
+        scheduler.add_piece(1, BLOCK_SIZE);
+        scheduler.next_requests(0.0);
+
+        let cancelled = scheduler.cancel_piece(0);
+
+        assert_eq!(cancelled.len(), 2);
+        assert!(cancelled.iter().all(|request| request.index == 0));
+        assert_eq!(scheduler.pieces_in_progress(), vec![1]);
+        assert_eq!(scheduler.outstanding_requests(), 1);
+    }
+
+    #[test]
+    fn test_take_pieces() {
+        let mut scheduler = BlockScheduler::new(5);
+        scheduler.add_piece(0, BLOCK_SIZE);
+        scheduler.add_piece(1, BLOCK_SIZE);
+        scheduler.next_requests(0.0);
+
+        assert_eq!(scheduler.take_pieces(), vec![0, 1]);
+        assert!(scheduler.is_empty());
+        assert_eq!(scheduler.outstanding_requests(), 0);
+    }
+
+    #[test]
+    fn test_has_timed_out() {
+        let mut scheduler = BlockScheduler::new(5);
+        assert!(!scheduler.has_timed_out(Duration::ZERO));
+
+        scheduler.add_piece(0, BLOCK_SIZE);
+        scheduler.next_requests(0.0);
+
+        assert!(scheduler.has_timed_out(Duration::ZERO));
+        assert!(!scheduler.has_timed_out(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_snubbed_peer_gets_a_single_request() {
+        let mut scheduler = BlockScheduler::new(5);
+        scheduler.set_snubbed(true);
+        scheduler.add_piece(0, BLOCK_SIZE * 4);
+
+        assert_eq!(scheduler.next_requests(1024.0 * 1024.0).len(), 1);
+    }
+
+    #[test]
+    fn test_queue_depth_grows_with_rate_and_latency() {
+        let mut scheduler = BlockScheduler::new(5);
+        assert_eq!(scheduler.target_queue_depth(0.0), 5);
+
+        // 1 MiB/s during 3 seconds are 192 blocks.
+        let rate = 1024.0 * 1024.0;
+        assert_eq!(scheduler.target_queue_depth(rate), 192);
+
+        // With 1 second of latency we need 64 more blocks in flight, but the depth is capped.
+        scheduler.add_latency_sample(Duration::from_secs(1));
+        assert_eq!(scheduler.target_queue_depth(rate), MAX_QUEUE_DEPTH);
+        assert_eq!(scheduler.target_queue_depth(rate / 4.0), 64);
+    }
+}
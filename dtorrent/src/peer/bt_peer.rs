@@ -1,9 +1,15 @@
 use bencoder::bencode::Bencode;
 use std::io::Read;
 use std::io::Write;
-use std::net::TcpStream;
+use std::net::{IpAddr, SocketAddr, TcpStream};
 
-use super::handshake::Handshake;
+use url_encoder::info_hash::InfoHash;
+
+use super::{
+    client_id::client_name,
+    features::Features,
+    handshake::{Handshake, HANDSHAKE_LENGTH},
+};
 
 /// `BtPeer` struct containing individual BtPeer information.
 ///
@@ -11,9 +17,15 @@ use super::handshake::Handshake;
 #[derive(Debug, Clone)]
 pub struct BtPeer {
     pub peer_id: Option<Vec<u8>>,
+    /// Client of the peer decoded from its peer id, see `client_id::client_name`.
+    pub client: Option<String>,
     pub ip: String,
     pub port: i64,
-    pub info_hash: Option<Vec<u8>>,
+    pub info_hash: Option<InfoHash>,
+    /// Extensions announced by the peer in its handshake, empty until the handshake is received.
+    pub features: Features,
+    /// Port of the DHT node of the peer, sent in a Port message, `None` until it is received.
+    pub dht_port: Option<u16>,
 }
 
 impl PartialEq for BtPeer {
@@ -46,12 +58,22 @@ impl BtPeer {
     pub fn new(ip: String, port: i64) -> Self {
         Self {
             peer_id: None,
+            client: None,
             ip,
             port,
             info_hash: None,
+            features: Features::empty(),
+            dht_port: None,
         }
     }
 
+    /// Returns the socket address of the peer, or `None` if its ip is not an IP address or its port is invalid.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        let ip = self.ip.parse::<IpAddr>().ok()?;
+        let port = u16::try_from(self.port).ok()?;
+        Some(SocketAddr::new(ip, port))
+    }
+
     /// Builds a new `BtPeer` from a bencoded peer from the tracker response peer list.
     ///
     ///
@@ -61,78 +83,54 @@ impl BtPeer {
     /// - The peer Port is invalid.
     /// - The bencoded peer is not a Dict.
     pub fn from(bencode: Bencode) -> Result<BtPeer, BtPeerError> {
-        let mut peer_id: Vec<u8> = Vec::new();
-        let mut ip: String = String::new();
-        let mut port: i64 = 0;
+        if bencode.as_dict().is_none() {
+            return Err(BtPeerError::NotADict);
+        }
 
-        let d = match bencode {
-            Bencode::BDict(d) => d,
-            _ => return Err(BtPeerError::NotADict),
+        let peer_id = match bencode.get("peer id") {
+            Some(peer_id) => peer_id
+                .as_bytes()
+                .ok_or(BtPeerError::InvalidPeerId)?
+                .to_vec(),
+            None => Vec::new(),
+        };
+        let ip = match bencode.get("ip") {
+            Some(ip) => ip.as_str().ok_or(BtPeerError::InvalidIp)?.to_string(),
+            None => String::new(),
+        };
+        let port = match bencode.get("port") {
+            Some(port) => port.as_int().ok_or(BtPeerError::InvalidPort)?,
+            None => 0,
         };
-
-        for (k, v) in d.iter() {
-            if k == b"peer id" {
-                peer_id = Self::create_peer_id(v)?;
-            } else if k == b"ip" {
-                ip = Self::create_ip(v)?;
-            } else if k == b"port" {
-                port = Self::create_port(v)?;
-            }
-        }
 
         Ok(BtPeer {
+            client: client_name(&peer_id),
             peer_id: Some(peer_id),
             ip,
             port,
             info_hash: None,
+            features: Features::empty(),
+            dht_port: None,
         })
     }
 
-    fn create_peer_id(bencode: &Bencode) -> Result<Vec<u8>, BtPeerError> {
-        let peer_id = match bencode {
-            Bencode::BString(s) => s.clone(),
-            _ => return Err(BtPeerError::InvalidPeerId),
-        };
-
-        Ok(peer_id)
-    }
-
-    fn create_ip(bencode: &Bencode) -> Result<String, BtPeerError> {
-        let ip = match bencode {
-            Bencode::BString(s) => s,
-            _ => return Err(BtPeerError::InvalidIp),
-        };
-
-        let ip = match String::from_utf8(ip.to_vec()) {
-            Ok(s) => s,
-            Err(_) => return Err(BtPeerError::InvalidIp),
-        };
-
-        Ok(ip)
-    }
-
-    fn create_port(bencode: &Bencode) -> Result<i64, BtPeerError> {
-        let port = match bencode {
-            Bencode::BNumber(n) => *n,
-            _ => return Err(BtPeerError::InvalidPort),
-        };
-
-        Ok(port)
-    }
-
     /// Reads a handshake from the peer and returns the info hash.
     ///
+    /// The peer id and the features announced by the peer are kept.
+    ///
     /// It returns an error if the handshake could not be read or the handshake was not successful.
-    pub fn receive_handshake(&mut self, stream: &mut TcpStream) -> Result<Vec<u8>, BtPeerError> {
-        let mut buffer = [0; 68];
+    pub fn receive_handshake(&mut self, stream: &mut TcpStream) -> Result<InfoHash, BtPeerError> {
+        let mut buffer = [0; HANDSHAKE_LENGTH];
         stream
             .read_exact(&mut buffer)
             .map_err(|_| BtPeerError::HandshakeError)?;
 
         let handshake = Handshake::from_bytes(&buffer).map_err(|_| BtPeerError::HandshakeError)?;
 
-        self.info_hash = Some(handshake.info_hash.clone());
+        self.info_hash = Some(handshake.info_hash);
+        self.client = client_name(&handshake.peer_id);
         self.peer_id = Some(handshake.peer_id);
+        self.features = handshake.features;
 
         Ok(handshake.info_hash)
     }
@@ -143,7 +141,7 @@ impl BtPeer {
     pub fn send_handshake(
         &mut self,
         stream: &mut TcpStream,
-        info_hash: Vec<u8>,
+        info_hash: InfoHash,
         client_peer_id: String,
     ) -> Result<(), BtPeerError> {
         let handshake = Handshake::new(info_hash, client_peer_id.as_bytes().to_vec());
@@ -159,6 +157,23 @@ mod tests {
     use super::*;
     use std::collections::BTreeMap;
 
+    #[test]
+    fn test_socket_addr() {
+        assert_eq!(
+            BtPeer::new("10.0.0.1".to_string(), 6881).socket_addr(),
+            Some("10.0.0.1:6881".parse().unwrap())
+        );
+        assert_eq!(
+            BtPeer::new("::1".to_string(), 6881).socket_addr(),
+            Some("[::1]:6881".parse().unwrap())
+        );
+        assert_eq!(
+            BtPeer::new("localhost".to_string(), 6881).socket_addr(),
+            None
+        );
+        assert_eq!(BtPeer::new("10.0.0.1".to_string(), -1).socket_addr(), None);
+    }
+
     #[test]
     fn test_from_bt_peer() {
         let mut dict = BTreeMap::new();
@@ -175,12 +190,49 @@ mod tests {
         assert_eq!(bt_peer.port, 6868);
     }
 
+    #[test]
+    fn test_from_bt_peer_decodes_client() {
+        let mut dict = BTreeMap::new();
+        dict.insert(
+            b"peer id".to_vec(),
+            Bencode::BString(b"-TR3000-abcdefghijkl".to_vec()),
+        );
+        dict.insert(b"ip".to_vec(), Bencode::BString(b"127.0.0.1".to_vec()));
+        dict.insert(b"port".to_vec(), Bencode::BNumber(6868));
+
+        let bt_peer = BtPeer::from(Bencode::BDict(dict)).unwrap();
+
+        assert_eq!(bt_peer.client, Some("Transmission 3.0.0.0".to_string()));
+    }
+
     #[test]
     fn test_new_peer() {
         let bt_peer = BtPeer::new("127.0.0.1".to_string(), 6868);
 
         assert_eq!(bt_peer.peer_id, None);
+        assert!(bt_peer.features.is_empty());
+        assert_eq!(bt_peer.dht_port, None);
         assert_eq!(bt_peer.ip, "127.0.0.1");
         assert_eq!(bt_peer.port, 6868);
     }
+
+    #[test]
+    fn test_receive_handshake_keeps_features() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut receiver, _) = listener.accept().unwrap();
+        let handshake = Handshake {
+            features: Features::FAST_EXTENSION | Features::EXTENSION_PROTOCOL,
+            ..Handshake::new(InfoHash::new([7; 20]), vec![1; 20])
+        };
+        sender.write_all(&handshake.as_bytes()).unwrap();
+
+        let mut bt_peer = BtPeer::new("127.0.0.1".to_string(), 6868);
+        let info_hash = bt_peer.receive_handshake(&mut receiver).unwrap();
+
+        assert_eq!(info_hash, InfoHash::new([7; 20]));
+        assert!(bt_peer.features.contains(Features::EXTENSION_PROTOCOL));
+        assert!(bt_peer.features.contains(Features::FAST_EXTENSION));
+        assert!(!bt_peer.features.contains(Features::DHT));
+    }
 }
@@ -0,0 +1,123 @@
+/// Known client codes of Azureus-style peer ids, like `-qB4500-`.
+const AZUREUS_CLIENTS: [(&str, &str); 10] = [
+    ("AZ", "Vuze"),
+    ("BC", "BitComet"),
+    ("BI", "BiglyBT"),
+    ("DE", "Deluge"),
+    ("DT", "dtorrent"),
+    ("LT", "libtorrent"),
+    ("TR", "Transmission"),
+    ("UT", "µTorrent"),
+    ("lt", "libTorrent"),
+    ("qB", "qBittorrent"),
+];
+
+/// Known client letters of Shadow-style peer ids, like `S58B-----`.
+const SHADOW_CLIENTS: [(u8, &str); 7] = [
+    (b'A', "ABC"),
+    (b'O', "Osprey Permaseed"),
+    (b'Q', "BTQueue"),
+    (b'R', "Tribler"),
+    (b'S', "Shadow"),
+    (b'T', "BitTornado"),
+    (b'U', "UPnP NAT Bit Torrent"),
+];
+
+/// Decodes the client name and version from a peer id.
+///
+/// Two conventions are supported:
+/// - Azureus-style, like `-qB4500-` for qBittorrent 4.5.0.0. Unknown client codes are returned as they are.
+/// - Shadow-style, like `S58B-----` for Shadow 5.8.11, only for known clients.
+///
+/// Returns `None` if the peer id does not follow any of them.
+pub fn client_name(peer_id: &[u8]) -> Option<String> {
+    azureus_client(peer_id).or_else(|| shadow_client(peer_id))
+}
+
+fn azureus_client(peer_id: &[u8]) -> Option<String> {
+    if peer_id.len() < 8 || peer_id[0] != b'-' || peer_id[7] != b'-' {
+        return None;
+    }
+    let code = std::str::from_utf8(&peer_id[1..3]).ok()?;
+    let version = std::str::from_utf8(&peer_id[3..7]).ok()?;
+    if !code.chars().all(|c| c.is_ascii_alphanumeric())
+        || !version.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        return None;
+    }
+
+    let name = AZUREUS_CLIENTS
+        .iter()
+        .find(|(known, _)| *known == code)
+        .map_or(code, |(_, name)| name);
+    let version: Vec<String> = version.chars().map(String::from).collect();
+    Some(format!("{} {}", name, version.join(".")))
+}
+
+/// The version has up to five characters padded with `-`, followed by three more characters.
+fn shadow_client(peer_id: &[u8]) -> Option<String> {
+    if peer_id.len() < 9 || !peer_id[1].is_ascii_digit() {
+        return None;
+    }
+    let (_, name) = SHADOW_CLIENTS
+        .iter()
+        .find(|(letter, _)| *letter == peer_id[0])?;
+
+    let version: Vec<String> = peer_id[1..6]
+        .iter()
+        .take_while(|c| **c != b'-')
+        .map(|c| shadow_version_number(*c).map(|number| number.to_string()))
+        .collect::<Option<_>>()?;
+    Some(format!("{} {}", name, version.join(".")))
+}
+
+/// Each character of a Shadow-style version is a number from 0 to 63.
+fn shadow_version_number(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'A'..=b'Z' => Some(c - b'A' + 10),
+        b'a'..=b'z' => Some(c - b'a' + 36),
+        b'.' => Some(62),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_azureus_known_client() {
+        assert_eq!(
+            client_name(b"-qB4500-abcdefghijkl"),
+            Some("qBittorrent 4.5.0.0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_azureus_unknown_client() {
+        assert_eq!(
+            client_name(b"-XX0100-abcdefghijkl"),
+            Some("XX 0.1.0.0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_shadow_client() {
+        assert_eq!(
+            client_name(b"S58B-----abcdefghijk"),
+            Some("Shadow 5.8.11".to_string())
+        );
+        assert_eq!(
+            client_name(b"T03I--00abcdefghijkl"),
+            Some("BitTornado 0.3.18".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unknown_convention() {
+        assert_eq!(client_name(b"M7-2-2--abcdefghijkl"), None);
+        assert_eq!(client_name(b"Sabcdefghijklmnopqrs"), None);
+        assert_eq!(client_name(b"client_peer_id"), None);
+    }
+}
@@ -0,0 +1,42 @@
+/// Side that opened a connection with a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionDirection {
+    /// The peer connected to us.
+    Incoming,
+    /// We connected to the peer.
+    Outgoing,
+}
+
+impl ConnectionDirection {
+    /// Returns the direction of the connection kept when we and a peer connect to each other at the same time.
+    ///
+    /// The connection opened by the lower peer id is kept. Both sides compare the same two ids, so they agree on
+    /// the connection to close without exchanging any message.
+    pub fn kept(client_peer_id: &[u8], peer_id: &[u8]) -> Self {
+        if client_peer_id < peer_id {
+            ConnectionDirection::Outgoing
+        } else {
+            ConnectionDirection::Incoming
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_the_connection_of_the_lower_peer_id_is_kept() {
+        let lower = b"-DT0001-aaaaaaaaaaaa";
+        let higher = b"-DT0001-bbbbbbbbbbbb";
+
+        assert_eq!(
+            ConnectionDirection::kept(lower, higher),
+            ConnectionDirection::Outgoing
+        );
+        assert_eq!(
+            ConnectionDirection::kept(higher, lower),
+            ConnectionDirection::Incoming
+        );
+    }
+}
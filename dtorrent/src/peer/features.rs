@@ -0,0 +1,80 @@
+use bitflags::bitflags;
+
+bitflags! {
+    /// Extensions of the protocol supported by a peer, announced in the reserved bytes of the handshake.
+    ///
+    /// The reserved bytes are read as a big endian `u64`, so bit 0 is the last bit of the last byte.
+    /// Bits of unknown extensions are kept as received.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct Features: u64 {
+        /// DHT (BEP 5), `reserved[7] & 0x01`.
+        const DHT = 1;
+        /// Fast Extension (BEP 6), `reserved[7] & 0x04`.
+        const FAST_EXTENSION = 1 << 2;
+        /// BitTorrent v2 (BEP 52), `reserved[7] & 0x10`. The peer answers the hash requests of hybrid torrents.
+        const BITTORRENT_V2 = 1 << 4;
+        /// Extension Protocol (BEP 10), `reserved[5] & 0x10`.
+        const EXTENSION_PROTOCOL = 1 << 20;
+
+        const _ = !0;
+    }
+}
+
+impl Features {
+    /// Extensions implemented by this client, announced on the outgoing handshakes.
+    ///
+    /// A feature is only added here once it is implemented, peers use these bits to send the messages of the extension.
+    pub const SUPPORTED: Features = Features::BITTORRENT_V2;
+
+    /// Reads the features from the reserved bytes of a handshake.
+    pub fn from_reserved(reserved: [u8; 8]) -> Self {
+        Self::from_bits_retain(u64::from_be_bytes(reserved))
+    }
+
+    /// Returns the reserved bytes of a handshake announcing the features.
+    pub fn as_reserved(&self) -> [u8; 8] {
+        self.bits().to_be_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_reserved() {
+        let features = Features::from_reserved([0, 0, 0, 0, 0, 0x10, 0, 0x05]);
+
+        assert!(features.contains(Features::DHT));
+        assert!(features.contains(Features::FAST_EXTENSION));
+        assert!(features.contains(Features::EXTENSION_PROTOCOL));
+    }
+
+    #[test]
+    fn test_as_reserved() {
+        assert_eq!(Features::DHT.as_reserved(), [0, 0, 0, 0, 0, 0, 0, 0x01]);
+        assert_eq!(
+            Features::FAST_EXTENSION.as_reserved(),
+            [0, 0, 0, 0, 0, 0, 0, 0x04]
+        );
+        assert_eq!(
+            Features::EXTENSION_PROTOCOL.as_reserved(),
+            [0, 0, 0, 0, 0, 0x10, 0, 0]
+        );
+        assert_eq!(
+            Features::BITTORRENT_V2.as_reserved(),
+            [0, 0, 0, 0, 0, 0, 0, 0x10]
+        );
+    }
+
+    #[test]
+    fn test_unknown_bits_are_kept() {
+        let reserved = [0x80, 0, 0, 0, 0, 0x10, 0, 0];
+
+        let features = Features::from_reserved(reserved);
+
+        assert!(features.contains(Features::EXTENSION_PROTOCOL));
+        assert!(!features.contains(Features::DHT));
+        assert_eq!(features.as_reserved(), reserved);
+    }
+}
@@ -1,3 +1,7 @@
+use url_encoder::info_hash::InfoHash;
+
+use super::features::Features;
+
 #[derive(Debug)]
 pub enum FromHandshakeError {
     InvalidHandshake,
@@ -9,20 +13,23 @@ pub enum FromHandshakeError {
 pub struct Handshake {
     pub pstrlen: u8,
     pub pstr: String,
-    pub reserved: [u8; 8],
-    pub info_hash: Vec<u8>,
+    pub features: Features,
+    pub info_hash: InfoHash,
     pub peer_id: Vec<u8>,
 }
 
 const PSTR: &str = "BitTorrent protocol";
 
+/// Length in bytes of a handshake.
+pub const HANDSHAKE_LENGTH: usize = 68;
+
 impl Handshake {
-    /// Creates a new `Handshake` message.
-    pub fn new(info_hash: Vec<u8>, peer_id: Vec<u8>) -> Self {
+    /// Creates a new `Handshake` message announcing the features supported by the client, see `Features::SUPPORTED`.
+    pub fn new(info_hash: InfoHash, peer_id: Vec<u8>) -> Self {
         Self {
             pstrlen: 19,
             pstr: PSTR.to_string(),
-            reserved: [0; 8],
+            features: Features::SUPPORTED,
             info_hash,
             peer_id,
         }
@@ -32,15 +39,15 @@ impl Handshake {
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![self.pstrlen];
         bytes.extend(self.pstr.as_bytes());
-        bytes.extend(&self.reserved);
-        bytes.extend(&self.info_hash);
+        bytes.extend(self.features.as_reserved());
+        bytes.extend(self.info_hash.as_bytes());
         bytes.extend(&self.peer_id);
         bytes
     }
 
     /// Parses a byte array into a `Handshake` message.
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, FromHandshakeError> {
-        if bytes.len() != 68 {
+        if bytes.len() != HANDSHAKE_LENGTH {
             return Err(FromHandshakeError::InvalidHandshake);
         }
 
@@ -51,24 +58,17 @@ impl Handshake {
 
         let pstr = String::from_utf8(bytes[1..pstrlen as usize + 1].to_vec())
             .map_err(|_| FromHandshakeError::InvalidHandshake)?;
-        let reserved = &bytes[pstrlen as usize + 1..pstrlen as usize + 9];
-        let info_hash = &bytes[pstrlen as usize + 9..pstrlen as usize + 29];
+        let mut reserved = [0; 8];
+        reserved.copy_from_slice(&bytes[pstrlen as usize + 1..pstrlen as usize + 9]);
+        let info_hash = InfoHash::from_bytes(&bytes[pstrlen as usize + 9..pstrlen as usize + 29])
+            .map_err(|_| FromHandshakeError::InvalidHandshake)?;
         let peer_id = &bytes[pstrlen as usize + 29..];
 
         Ok(Self {
             pstrlen,
             pstr,
-            reserved: [
-                reserved[0],
-                reserved[1],
-                reserved[2],
-                reserved[3],
-                reserved[4],
-                reserved[5],
-                reserved[6],
-                reserved[7],
-            ],
-            info_hash: info_hash.to_vec(),
+            features: Features::from_reserved(reserved),
+            info_hash,
             peer_id: peer_id.to_vec(),
         })
     }
@@ -83,11 +83,11 @@ mod tests {
         let expected_handshake_len = 68;
         let expected_pstrlen = 19;
         let expected_pstr = b"BitTorrent protocol".to_vec();
-        let expected_reserved = [0; 8];
+        let expected_reserved = Features::SUPPORTED.as_reserved();
 
-        let info_hash: Vec<u8> = (1..=20).collect();
+        let info_hash = InfoHash::new([7; 20]);
         let peer_id: Vec<u8> = (21..=40).collect();
-        let handshake = Handshake::new(info_hash.clone(), peer_id.clone());
+        let handshake = Handshake::new(info_hash, peer_id.clone());
 
         let bytes = handshake.as_bytes();
 
@@ -95,7 +95,7 @@ mod tests {
         assert_eq!(bytes[0], expected_pstrlen);
         assert_eq!(bytes[1..20], expected_pstr);
         assert_eq!(bytes[20..28], expected_reserved);
-        assert_eq!(bytes[28..48], info_hash);
+        assert_eq!(bytes[28..48], info_hash.as_bytes()[..]);
         assert_eq!(bytes[48..], peer_id);
     }
 
@@ -103,19 +103,34 @@ mod tests {
     fn test_from_bytes() {
         let expected_pstrlen = 19;
         let expected_pstr = "BitTorrent protocol";
-        let expected_reserved = [0; 8];
+        let expected_reserved = Features::SUPPORTED.as_reserved();
 
-        let info_hash: Vec<u8> = (1..=20).collect();
+        let info_hash = InfoHash::new([7; 20]);
         let peer_id: Vec<u8> = (21..=40).collect();
-        let handshake = Handshake::new(info_hash.clone(), peer_id.clone());
+        let handshake = Handshake::new(info_hash, peer_id.clone());
         let bytes = handshake.as_bytes();
 
         let handshake = Handshake::from_bytes(&bytes).unwrap();
 
         assert_eq!(handshake.pstrlen, expected_pstrlen);
         assert_eq!(handshake.pstr, expected_pstr);
-        assert_eq!(handshake.reserved, expected_reserved);
+        assert_eq!(handshake.features.as_reserved(), expected_reserved);
         assert_eq!(handshake.info_hash, info_hash);
         assert_eq!(handshake.peer_id, peer_id);
     }
+
+    #[test]
+    fn test_features_round_trip() {
+        let features = Features::DHT | Features::EXTENSION_PROTOCOL;
+        let handshake = Handshake {
+            features,
+            ..Handshake::new(InfoHash::new([7; 20]), vec![1; 20])
+        };
+
+        let bytes = handshake.as_bytes();
+        let handshake = Handshake::from_bytes(&bytes).unwrap();
+
+        assert_eq!(bytes[20..28], [0, 0, 0, 0, 0, 0x10, 0, 0x01]);
+        assert_eq!(handshake.features, features);
+    }
 }
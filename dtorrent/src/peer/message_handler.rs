@@ -7,14 +7,15 @@ use crate::{
 };
 
 use super::{
-    handshake::Handshake,
-    peer_message::{Bitfield, Message, MessageId, Request},
+    handshake::{Handshake, HANDSHAKE_LENGTH},
+    peer_message::{Bitfield, HashRequest, Hashes, Message, MessageId, Request},
 };
 
 #[derive(Debug)]
 pub enum MessageHandlerError {
     ErrorGettingBitfield(AtomicTorrentStatusError),
     ErrorGettingPiece(AtomicTorrentStatusError),
+    ErrorUpdatingStats(AtomicTorrentStatusError),
     HandshakeError,
     MessageError(MessageId),
 }
@@ -49,10 +50,18 @@ impl MessageHandler {
         Bitfield::new(message.payload)
     }
 
-    /// Handles a piece message received from the peer.
-    pub fn handle_piece(&mut self, message: Message) -> Vec<u8> {
+    /// Handles a piece message received from the peer, returning the piece index, the offset and the block.
+    pub fn handle_piece(&mut self, message: Message) -> (u32, u32, Vec<u8>) {
+        let mut index: [u8; 4] = [0; 4];
+        let mut begin: [u8; 4] = [0; 4];
+        index.copy_from_slice(&message.payload[0..4]);
+        begin.copy_from_slice(&message.payload[4..8]);
         let block = &message.payload[8..];
-        block.to_vec()
+        (
+            u32::from_be_bytes(index),
+            u32::from_be_bytes(begin),
+            block.to_vec(),
+        )
     }
 
     // Returns the received piece index
@@ -62,6 +71,11 @@ impl MessageHandler {
         u32::from_be_bytes(index)
     }
 
+    // Returns the DHT port of the peer
+    pub fn handle_port(&mut self, message: Message) -> u16 {
+        u16::from_be_bytes([message.payload[0], message.payload[1]])
+    }
+
     /// ------------------------------------------------------------------------------------------------
     /// Sending messages
 
@@ -79,9 +93,13 @@ impl MessageHandler {
         payload.extend(block);
 
         let piece_msg = Message::new(MessageId::Piece, payload);
+        self.torrent_status.throttle_upload(block.len());
         self.send(stream, piece_msg)?;
+        self.torrent_status
+            .add_uploaded_bytes(block.len() as u64)
+            .map_err(MessageHandlerError::ErrorUpdatingStats)?;
 
-        info!("Sent piece: {} / Offset: {}", index, begin);
+        info!(piece = index, offset = begin, "Sent block");
 
         Ok(())
     }
@@ -93,6 +111,13 @@ impl MessageHandler {
         Ok(())
     }
 
+    /// Sends a choke message to the peer.
+    pub fn send_choked(&mut self, stream: &mut TcpStream) -> Result<(), MessageHandlerError> {
+        let choked_msg = Message::new(MessageId::Choke, vec![]);
+        self.send(stream, choked_msg)?;
+        Ok(())
+    }
+
     /// Sends a bitfield message to the peer.
     pub fn send_bitfield(&mut self, stream: &mut TcpStream) -> Result<(), MessageHandlerError> {
         let bitfield = self
@@ -105,6 +130,20 @@ impl MessageHandler {
         Ok(())
     }
 
+    /// Sends a bitfield message without any piece to the peer.
+    ///
+    /// Used when super-seeding, so the pieces can be revealed one at a time with `Have` messages.
+    pub fn send_empty_bitfield(
+        &mut self,
+        stream: &mut TcpStream,
+    ) -> Result<(), MessageHandlerError> {
+        let bytes_count = self.torrent.total_pieces().div_ceil(8) as usize;
+
+        let bitfield_msg = Message::new(MessageId::Bitfield, vec![0; bytes_count]);
+        self.send(stream, bitfield_msg)?;
+        Ok(())
+    }
+
     /// Sends a request message to the peer.
     pub fn send_request(
         &self,
@@ -120,6 +159,36 @@ impl MessageHandler {
         Ok(())
     }
 
+    /// Sends a hash request message to the peer, for the hashes of some blocks of a v2 torrent.
+    pub fn send_hash_request(
+        &self,
+        request: &HashRequest,
+        stream: &mut TcpStream,
+    ) -> Result<(), MessageHandlerError> {
+        let hash_request_msg = Message::new(MessageId::HashRequest, request.as_bytes());
+        self.send(stream, hash_request_msg)
+    }
+
+    /// Sends a hashes message to the peer, answering one of its hash requests.
+    pub fn send_hashes(
+        &self,
+        hashes: &Hashes,
+        stream: &mut TcpStream,
+    ) -> Result<(), MessageHandlerError> {
+        let hashes_msg = Message::new(MessageId::Hashes, hashes.as_bytes());
+        self.send(stream, hashes_msg)
+    }
+
+    /// Sends a hash reject message to the peer, for one of its hash requests that will not be answered.
+    pub fn send_hash_reject(
+        &self,
+        request: &HashRequest,
+        stream: &mut TcpStream,
+    ) -> Result<(), MessageHandlerError> {
+        let hash_reject_msg = Message::new(MessageId::HashReject, request.as_bytes());
+        self.send(stream, hash_reject_msg)
+    }
+
     /// Sends an interested message to the peer.
     pub fn send_interested(&mut self, stream: &mut TcpStream) -> Result<(), MessageHandlerError> {
         let interested_msg = Message::new(MessageId::Interested, vec![]);
@@ -127,6 +196,16 @@ impl MessageHandler {
         Ok(())
     }
 
+    /// Sends a not interested message to the peer.
+    pub fn send_not_interested(
+        &mut self,
+        stream: &mut TcpStream,
+    ) -> Result<(), MessageHandlerError> {
+        let not_interested_msg = Message::new(MessageId::NotInterested, vec![]);
+        self.send(stream, not_interested_msg)?;
+        Ok(())
+    }
+
     /// Sends a cancel message to the peer.
     pub fn send_cancel(
         &mut self,
@@ -143,7 +222,7 @@ impl MessageHandler {
         let cancel_msg = Message::new(MessageId::Cancel, payload);
         self.send(stream, cancel_msg)?;
 
-        info!("Cancel piece: {} / Offset: {}", index, begin);
+        info!(piece = index, offset = begin, "Cancelled block request");
 
         Ok(())
     }
@@ -166,7 +245,9 @@ impl MessageHandler {
     fn send(&self, stream: &mut TcpStream, message: Message) -> Result<(), MessageHandlerError> {
         stream
             .write_all(&message.as_bytes())
-            .map_err(|_| MessageHandlerError::MessageError(message.id))?;
+            .map_err(|_| MessageHandlerError::MessageError(message.id.clone()))?;
+        self.torrent_status
+            .add_overhead_uploaded_bytes(message.overhead_bytes());
         Ok(())
     }
 
@@ -177,15 +258,15 @@ impl MessageHandler {
     ///
     /// It returns an error if the handshake could not be sent or the handshake was not successful.
     pub fn send_handshake(&mut self, stream: &mut TcpStream) -> Result<(), MessageHandlerError> {
-        let info_hash = self
-            .torrent
-            .get_info_hash_as_bytes()
-            .map_err(|_| MessageHandlerError::HandshakeError)?;
-
-        let handshake = Handshake::new(info_hash, self.client_peer_id.as_bytes().to_vec());
+        let handshake = Handshake::new(
+            self.torrent.info_hash,
+            self.client_peer_id.as_bytes().to_vec(),
+        );
         stream
             .write_all(&handshake.as_bytes())
             .map_err(|_| MessageHandlerError::HandshakeError)?;
+        self.torrent_status
+            .add_overhead_uploaded_bytes(HANDSHAKE_LENGTH as u64);
         Ok(())
     }
 }
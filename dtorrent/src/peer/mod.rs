@@ -1,5 +1,11 @@
+pub mod block;
+pub mod block_hashes;
+pub mod block_scheduler;
 pub mod bt_peer;
-mod handshake;
+pub mod client_id;
+pub mod connection_direction;
+pub mod features;
+pub(crate) mod handshake;
 mod message_handler;
 pub mod peer_message;
 pub mod peer_session;
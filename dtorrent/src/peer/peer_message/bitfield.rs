@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 use crate::torrent_handler::status::PieceStatus;
 
 /// Represents a Bitfield.
@@ -33,14 +31,19 @@ impl Bitfield {
         self.bitfield.iter().all(|byte| *byte == 0b1111_1111)
     }
 
-    /// Creates a bitfield from pieces status
-    pub fn from(pieces_status: &HashMap<u32, PieceStatus>) -> Bitfield {
+    /// Returns the number of pieces in the bitfield.
+    pub fn pieces_count(&self) -> u32 {
+        self.bitfield.iter().map(|byte| byte.count_ones()).sum()
+    }
+
+    /// Creates a bitfield from the status of each piece, indexed by piece.
+    pub fn from(pieces_status: &[PieceStatus]) -> Bitfield {
         let bytes_count = (pieces_status.len() + 7) / 8;
         let mut bitfield = vec![0; bytes_count];
 
-        for (piece_index, status) in pieces_status {
+        for (piece_index, status) in pieces_status.iter().enumerate() {
             if status == &PieceStatus::Finished {
-                let byte_index = (piece_index / 8) as usize;
+                let byte_index = piece_index / 8;
                 let byte = bitfield[byte_index];
 
                 let bit_index = 7 - (piece_index % 8); // Gets the bit index in the byte (from the right)
@@ -119,13 +122,16 @@ mod tests {
     }
 
     #[test]
-    fn test_bitfield_from_one_piece_finished() {
-        let mut pieces_status = HashMap::new();
-        for i in 0..8 {
-            pieces_status.insert(i, PieceStatus::Free);
-        }
+    fn test_pieces_count() {
+        let bitfield = Bitfield::new(vec![0b10100000, 0b11111111, 0b00000001]);
+
+        assert_eq!(bitfield.pieces_count(), 11);
+    }
 
-        pieces_status.insert(0, PieceStatus::Finished);
+    #[test]
+    fn test_bitfield_from_one_piece_finished() {
+        let mut pieces_status = vec![PieceStatus::Free; 8];
+        pieces_status[0] = PieceStatus::Finished;
 
         let bitfield = Bitfield::from(&pieces_status);
 
@@ -134,12 +140,8 @@ mod tests {
 
     #[test]
     fn test_bitfield_from_one_piece_finished_in_the_middle() {
-        let mut pieces_status = HashMap::new();
-        for i in 0..8 {
-            pieces_status.insert(i, PieceStatus::Free);
-        }
-
-        pieces_status.insert(3, PieceStatus::Finished);
+        let mut pieces_status = vec![PieceStatus::Free; 8];
+        pieces_status[3] = PieceStatus::Finished;
 
         let bitfield = Bitfield::from(&pieces_status);
 
@@ -148,10 +150,7 @@ mod tests {
 
     #[test]
     fn test_bitfield_from_all_pieces_finished() {
-        let mut pieces_status = HashMap::new();
-        for i in 0..8 {
-            pieces_status.insert(i, PieceStatus::Finished);
-        }
+        let pieces_status = vec![PieceStatus::Finished; 8];
 
         let bitfield = Bitfield::from(&pieces_status);
 
@@ -160,10 +159,7 @@ mod tests {
 
     #[test]
     fn test_from_two_bytes() {
-        let mut pieces_status = HashMap::new();
-        for i in 0..9 {
-            pieces_status.insert(i, PieceStatus::Finished);
-        }
+        let pieces_status = vec![PieceStatus::Finished; 9];
 
         let bitfield = Bitfield::from(&pieces_status);
 
@@ -172,10 +168,7 @@ mod tests {
 
     #[test]
     fn test_from_two_bytes_complete() {
-        let mut pieces_status = HashMap::new();
-        for i in 0..16 {
-            pieces_status.insert(i, PieceStatus::Finished);
-        }
+        let pieces_status = vec![PieceStatus::Finished; 16];
 
         let bitfield = Bitfield::from(&pieces_status);
 
@@ -0,0 +1,237 @@
+use std::io::{self, Read};
+
+use super::{Message, MessageError, MessageId, HASH_REQUEST_LENGTH, LENGTH_PREFIX_BYTES};
+use crate::torrent_parser::sha256::SHA256_LENGTH;
+
+/// Max length of the block sent in a Piece message. Blocks are usually 16 KiB, but some clients send up to 128 KiB.
+const MAX_PIECE_BLOCK_LENGTH: u32 = 131072;
+
+/// Max number of hashes of a Hashes message: the 512 hashes a request can ask for and their uncle hashes.
+const MAX_HASHES: u32 = 512 + 32;
+
+/// Max length of a message with an ID not supported by the client, for example extension messages.
+const MAX_UNKNOWN_MESSAGE_LENGTH: u32 = 1048576;
+
+/// A length-prefixed frame read from a peer.
+#[derive(Debug)]
+pub enum Frame {
+    KeepAlive,
+    Message(Message),
+    /// A message with an ID not supported by the client. Its payload is discarded.
+    Unknown {
+        id: u8,
+        length: u32,
+    },
+}
+
+impl Frame {
+    /// Returns the bytes the frame took on the wire that are not block data, see `Message::overhead_bytes`.
+    pub fn overhead_bytes(&self) -> u64 {
+        match self {
+            Frame::KeepAlive => LENGTH_PREFIX_BYTES as u64,
+            Frame::Message(message) => message.overhead_bytes(),
+            Frame::Unknown { length, .. } => LENGTH_PREFIX_BYTES as u64 + *length as u64,
+        }
+    }
+}
+
+/// Posible errors reading a frame.
+///
+/// Unknown message IDs are not an error, they are returned as `Frame::Unknown`.
+#[derive(Debug)]
+pub enum FrameError {
+    Io(io::Error),
+    InvalidLength { id: u8, length: u32 },
+}
+
+/// Reads the next frame from the stream.
+///
+/// The length of each message is checked against the limits of its type before reading the payload,
+/// so a peer can not make us allocate a huge buffer.
+///
+/// # Errors
+/// - `Io` if the frame could not be read from the stream.
+/// - `InvalidLength` if the length of the message is not valid for its type.
+pub fn read_frame<R: Read>(stream: &mut R, total_pieces: u32) -> Result<Frame, FrameError> {
+    let mut length = [0; 4];
+    stream.read_exact(&mut length).map_err(FrameError::Io)?;
+    let length = u32::from_be_bytes(length);
+
+    if length == 0 {
+        return Ok(Frame::KeepAlive);
+    }
+
+    let mut id = [0; 1];
+    stream.read_exact(&mut id).map_err(FrameError::Io)?;
+    let id = id[0];
+
+    let (min_length, max_length) = length_limits(id, total_pieces);
+    if length < min_length || length > max_length {
+        return Err(FrameError::InvalidLength { id, length });
+    }
+
+    if MessageId::from_id(id).is_none() {
+        // The payload is discarded as it is read, without buffering it.
+        let payload_length = u64::from(length - 1);
+        let discarded = io::copy(&mut stream.by_ref().take(payload_length), &mut io::sink())
+            .map_err(FrameError::Io)?;
+        if discarded < payload_length {
+            return Err(FrameError::Io(io::Error::from(
+                io::ErrorKind::UnexpectedEof,
+            )));
+        }
+        return Ok(Frame::Unknown { id, length });
+    }
+
+    let mut bytes = vec![0; length as usize];
+    bytes[0] = id;
+    stream.read_exact(&mut bytes[1..]).map_err(FrameError::Io)?;
+
+    match Message::from_bytes(&bytes) {
+        Ok(message) => Ok(Frame::Message(message)),
+        Err(MessageError::UnknownMessage(_)) | Err(MessageError::InvalidMessage) => {
+            Err(FrameError::InvalidLength { id, length })
+        }
+    }
+}
+
+/// Returns the minimum and maximum length, including the ID, of a message with the given ID.
+fn length_limits(id: u8, total_pieces: u32) -> (u32, u32) {
+    match id {
+        id if id == MessageId::Choke as u8
+            || id == MessageId::Unchoke as u8
+            || id == MessageId::Interested as u8
+            || id == MessageId::NotInterested as u8 =>
+        {
+            (1, 1)
+        }
+        id if id == MessageId::Have as u8 => (5, 5),
+        id if id == MessageId::Bitfield as u8 => {
+            let bitfield_length = 1 + total_pieces.div_ceil(8);
+            (bitfield_length, bitfield_length)
+        }
+        id if id == MessageId::Request as u8 || id == MessageId::Cancel as u8 => (13, 13),
+        id if id == MessageId::Piece as u8 => (9, 9 + MAX_PIECE_BLOCK_LENGTH),
+        id if id == MessageId::Port as u8 => (3, 3),
+        id if id == MessageId::HashRequest as u8 || id == MessageId::HashReject as u8 => {
+            let length = 1 + HASH_REQUEST_LENGTH as u32;
+            (length, length)
+        }
+        id if id == MessageId::Hashes as u8 => {
+            let length = 1 + HASH_REQUEST_LENGTH as u32;
+            (length, length + MAX_HASHES * SHA256_LENGTH as u32)
+        }
+        _ => (1, MAX_UNKNOWN_MESSAGE_LENGTH),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_read_keep_alive() {
+        let mut stream = Cursor::new(0u32.to_be_bytes().to_vec());
+
+        assert!(matches!(
+            read_frame(&mut stream, 10).unwrap(),
+            Frame::KeepAlive
+        ));
+    }
+
+    #[test]
+    fn test_read_message() {
+        let bytes = Message::new(MessageId::Have, 3u32.to_be_bytes().to_vec()).as_bytes();
+        let mut stream = Cursor::new(bytes);
+
+        match read_frame(&mut stream, 10).unwrap() {
+            Frame::Message(message) => {
+                assert_eq!(message.id, MessageId::Have);
+                assert_eq!(message.payload, 3u32.to_be_bytes().to_vec());
+            }
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn test_unknown_message_is_skipped() {
+        // Extension message (ID 20) followed by an Unchoke.
+        let mut bytes = 4u32.to_be_bytes().to_vec();
+        bytes.extend([20, 0, 1, 2]);
+        bytes.extend(Message::new(MessageId::Unchoke, vec![]).as_bytes());
+        let mut stream = Cursor::new(bytes);
+
+        assert!(matches!(
+            read_frame(&mut stream, 10).unwrap(),
+            Frame::Unknown { id: 20, length: 4 }
+        ));
+        match read_frame(&mut stream, 10).unwrap() {
+            Frame::Message(message) => assert_eq!(message.id, MessageId::Unchoke),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn test_truncated_unknown_message_is_an_error() {
+        let mut bytes = 100u32.to_be_bytes().to_vec();
+        bytes.extend([20, 0, 1, 2]);
+        let mut stream = Cursor::new(bytes);
+
+        assert!(matches!(
+            read_frame(&mut stream, 10),
+            Err(FrameError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
+
+    #[test]
+    fn test_piece_message_with_big_block() {
+        let mut payload = vec![0; 8];
+        payload.extend(vec![1; 32768]);
+        let bytes = Message::new(MessageId::Piece, payload).as_bytes();
+        let mut stream = Cursor::new(bytes);
+
+        assert!(matches!(
+            read_frame(&mut stream, 10).unwrap(),
+            Frame::Message(_)
+        ));
+    }
+
+    #[test]
+    fn test_message_too_long_for_its_type() {
+        let bytes = Message::new(MessageId::Have, vec![0; 100]).as_bytes();
+        let mut stream = Cursor::new(bytes);
+
+        assert!(matches!(
+            read_frame(&mut stream, 10),
+            Err(FrameError::InvalidLength { id: 4, length: 101 })
+        ));
+    }
+
+    #[test]
+    fn test_bitfield_length_depends_on_total_pieces() {
+        let bytes = Message::new(MessageId::Bitfield, vec![0; 2]).as_bytes();
+
+        assert!(matches!(
+            read_frame(&mut Cursor::new(bytes.clone()), 10).unwrap(),
+            Frame::Message(_)
+        ));
+        assert!(matches!(
+            read_frame(&mut Cursor::new(bytes), 100),
+            Err(FrameError::InvalidLength { .. })
+        ));
+    }
+
+    #[test]
+    fn test_huge_unknown_message_is_rejected() {
+        let mut bytes = (MAX_UNKNOWN_MESSAGE_LENGTH + 1).to_be_bytes().to_vec();
+        bytes.push(20);
+        let mut stream = Cursor::new(bytes);
+
+        assert!(matches!(
+            read_frame(&mut stream, 10),
+            Err(FrameError::InvalidLength { id: 20, .. })
+        ));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::torrent_parser::{merkle::MerkleHash, sha256::SHA256_LENGTH};
+
+/// Length in bytes of the payload of a Hash Request or Hash Reject message, and of the header of a Hashes message.
+pub const HASH_REQUEST_LENGTH: usize = SHA256_LENGTH + 16;
+
+/// Represents the payload of a Hash Request message (BEP 52), and of the Hash Reject sent back when it is not
+/// served.
+///
+/// It asks for `length` hashes of the `base_layer` of the merkle tree of the file with `pieces_root`, starting at
+/// `index`, and for the uncle hashes of the `proof_layers` layers above them to check them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashRequest {
+    pub pieces_root: MerkleHash,
+    pub base_layer: u32,
+    pub index: u32,
+    pub length: u32,
+    pub proof_layers: u32,
+}
+
+/// Represents the payload of a Hashes message (BEP 52): the request it answers, the requested hashes and then the
+/// uncle hashes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hashes {
+    pub request: HashRequest,
+    pub hashes: Vec<MerkleHash>,
+}
+
+/// Posible errors of a Hash Request, Hash Reject or Hashes message received from a peer.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HashRequestError {
+    InvalidPayload,
+}
+
+impl HashRequest {
+    /// Converts a `HashRequest` to a byte array.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HASH_REQUEST_LENGTH);
+        bytes.extend_from_slice(&self.pieces_root);
+        bytes.extend_from_slice(&self.base_layer.to_be_bytes());
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        bytes.extend_from_slice(&self.length.to_be_bytes());
+        bytes.extend_from_slice(&self.proof_layers.to_be_bytes());
+        bytes
+    }
+
+    /// Parses the payload of a Hash Request or Hash Reject message.
+    ///
+    /// It returns `InvalidPayload` if the payload is not `HASH_REQUEST_LENGTH` bytes long.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HashRequestError> {
+        if bytes.len() != HASH_REQUEST_LENGTH {
+            return Err(HashRequestError::InvalidPayload);
+        }
+        let read_u32 = |start: usize| {
+            let mut value = [0; 4];
+            value.copy_from_slice(&bytes[start..start + 4]);
+            u32::from_be_bytes(value)
+        };
+        let mut pieces_root = [0; SHA256_LENGTH];
+        pieces_root.copy_from_slice(&bytes[..SHA256_LENGTH]);
+
+        Ok(Self {
+            pieces_root,
+            base_layer: read_u32(SHA256_LENGTH),
+            index: read_u32(SHA256_LENGTH + 4),
+            length: read_u32(SHA256_LENGTH + 8),
+            proof_layers: read_u32(SHA256_LENGTH + 12),
+        })
+    }
+}
+
+impl Hashes {
+    /// Converts a `Hashes` message to a byte array.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.request.as_bytes();
+        for hash in &self.hashes {
+            bytes.extend_from_slice(hash);
+        }
+        bytes
+    }
+
+    /// Parses the payload of a Hashes message.
+    ///
+    /// It returns `InvalidPayload` if the payload has no request or its hashes are not 32 bytes each.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HashRequestError> {
+        if bytes.len() < HASH_REQUEST_LENGTH
+            || !(bytes.len() - HASH_REQUEST_LENGTH).is_multiple_of(SHA256_LENGTH)
+        {
+            return Err(HashRequestError::InvalidPayload);
+        }
+        let (request, hashes) = bytes.split_at(HASH_REQUEST_LENGTH);
+        let hashes = hashes
+            .chunks_exact(SHA256_LENGTH)
+            .map(|hash| {
+                let mut value = [0; SHA256_LENGTH];
+                value.copy_from_slice(hash);
+                value
+            })
+            .collect();
+
+        Ok(Self {
+            request: HashRequest::from_bytes(request)?,
+            hashes,
+        })
+    }
+
+    /// Returns the requested hashes and the uncle hashes, or `None` if there are fewer hashes than requested.
+    pub fn split(&self) -> Option<(&[MerkleHash], &[MerkleHash])> {
+        let length = self.request.length as usize;
+        (self.hashes.len() >= length).then(|| self.hashes.split_at(length))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> HashRequest {
+        HashRequest {
+            pieces_root: [1; 32],
+            base_layer: 0,
+            index: 4,
+            length: 2,
+            proof_layers: 1,
+        }
+    }
+
+    #[test]
+    fn test_hash_request_from_bytes() {
+        let bytes = request().as_bytes();
+
+        assert_eq!(bytes.len(), HASH_REQUEST_LENGTH);
+        assert_eq!(
+            &bytes[32..],
+            [0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 2, 0, 0, 0, 1]
+        );
+        assert_eq!(HashRequest::from_bytes(&bytes).unwrap(), request());
+        assert_eq!(
+            HashRequest::from_bytes(&bytes[1..]),
+            Err(HashRequestError::InvalidPayload)
+        );
+    }
+
+    #[test]
+    fn test_hashes_from_bytes() {
+        let hashes = Hashes {
+            request: request(),
+            hashes: vec![[2; 32], [3; 32], [4; 32]],
+        };
+        let bytes = hashes.as_bytes();
+
+        let parsed = Hashes::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, hashes);
+        assert_eq!(
+            parsed.split(),
+            Some((&[[2; 32], [3; 32]][..], &[[4; 32]][..]))
+        );
+        assert_eq!(
+            Hashes::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(HashRequestError::InvalidPayload)
+        );
+    }
+}
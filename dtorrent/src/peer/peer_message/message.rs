@@ -1,6 +1,8 @@
 // IDs of the messages defined in the protocol.
 #[derive(PartialEq, Debug, Clone)]
 pub enum MessageId {
+    /// Message with an ID not supported by the client, it is ignored.
+    Unknown = -2,
     KeepAlive = -1,
     Choke = 0,
     Unchoke = 1,
@@ -12,8 +14,37 @@ pub enum MessageId {
     Piece = 7,
     Cancel = 8,
     Port = 9,
+    /// Hash messages of v2 torrents (BEP 52).
+    HashRequest = 21,
+    Hashes = 22,
+    HashReject = 23,
 }
 
+impl MessageId {
+    /// Returns the message ID of the byte that identifies it on the wire, or `None` if the client does not support it.
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(MessageId::Choke),
+            1 => Some(MessageId::Unchoke),
+            2 => Some(MessageId::Interested),
+            3 => Some(MessageId::NotInterested),
+            4 => Some(MessageId::Have),
+            5 => Some(MessageId::Bitfield),
+            6 => Some(MessageId::Request),
+            7 => Some(MessageId::Piece),
+            8 => Some(MessageId::Cancel),
+            9 => Some(MessageId::Port),
+            21 => Some(MessageId::HashRequest),
+            22 => Some(MessageId::Hashes),
+            23 => Some(MessageId::HashReject),
+            _ => None,
+        }
+    }
+}
+
+/// Length in bytes of the length prefix of every message.
+pub const LENGTH_PREFIX_BYTES: usize = 4;
+
 /// The message that is sent to the peer.
 ///
 /// It contains the message ID and the payload.
@@ -26,6 +57,7 @@ pub struct Message {
 #[derive(Debug)]
 pub enum MessageError {
     InvalidMessage,
+    UnknownMessage(u8),
 }
 
 impl Message {
@@ -35,20 +67,12 @@ impl Message {
     }
 
     /// Parses a byte array into a `Message`.
+    ///
+    /// It returns `UnknownMessage` if the ID is not supported by the client, and `InvalidMessage` if the byte array is empty.
     pub fn from_bytes(payload: &[u8]) -> Result<Self, MessageError> {
-        let id = match payload[0] {
-            0 => MessageId::Choke,
-            1 => MessageId::Unchoke,
-            2 => MessageId::Interested,
-            3 => MessageId::NotInterested,
-            4 => MessageId::Have,
-            5 => MessageId::Bitfield,
-            6 => MessageId::Request,
-            7 => MessageId::Piece,
-            8 => MessageId::Cancel,
-            9 => MessageId::Port,
-            _ => return Err(MessageError::InvalidMessage),
-        };
+        let first_byte = payload.first().ok_or(MessageError::InvalidMessage)?;
+        let id =
+            MessageId::from_id(*first_byte).ok_or(MessageError::UnknownMessage(*first_byte))?;
 
         let msg_payload = if payload.len() > 1 {
             payload[1..].to_vec()
@@ -62,6 +86,16 @@ impl Message {
         })
     }
 
+    /// Returns the bytes the message takes on the wire that are not block data: all of them, except the block of a
+    /// `Piece`, of which only the length prefix, the ID, the index and the begin are counted.
+    pub fn overhead_bytes(&self) -> u64 {
+        let block_length = match self.id {
+            MessageId::Piece => self.payload.len().saturating_sub(8),
+            _ => 0,
+        };
+        (LENGTH_PREFIX_BYTES + 1 + self.payload.len() - block_length) as u64
+    }
+
     /// Converts a `Message` to a byte array.
     pub fn as_bytes(&self) -> Vec<u8> {
         let len = self.payload.len() + 1;
@@ -96,6 +130,25 @@ mod tests {
         assert_eq!(msg.payload, vec![]);
     }
 
+    #[test]
+    fn test_message_unknown_from_bytes() {
+        let payload = [20u8, 0, 1];
+
+        assert!(matches!(
+            Message::from_bytes(&payload),
+            Err(MessageError::UnknownMessage(20))
+        ));
+        assert_eq!(Message::from_bytes(&[22u8]).unwrap().id, MessageId::Hashes);
+    }
+
+    #[test]
+    fn test_message_empty_from_bytes() {
+        assert!(matches!(
+            Message::from_bytes(&[]),
+            Err(MessageError::InvalidMessage)
+        ));
+    }
+
     #[test]
     fn test_message_request_as_bytes() {
         let index = 0u32.to_be_bytes();
@@ -130,4 +183,15 @@ mod tests {
 
         assert_eq!(bytes, expected);
     }
+
+    #[test]
+    fn test_overhead_bytes_leave_out_the_block_of_a_piece() {
+        let have = Message::new(MessageId::Have, 3u32.to_be_bytes().to_vec());
+        let mut payload = vec![0; 8];
+        payload.extend([1; 16]);
+        let piece = Message::new(MessageId::Piece, payload);
+
+        assert_eq!(have.overhead_bytes(), have.as_bytes().len() as u64);
+        assert_eq!(piece.overhead_bytes(), 13);
+    }
 }
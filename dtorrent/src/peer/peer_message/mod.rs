@@ -1,7 +1,11 @@
 mod bitfield;
+mod frame;
+mod hash_request;
 mod message;
 mod request;
 
 pub use self::bitfield::*;
+pub use self::frame::*;
+pub use self::hash_request::*;
 pub use self::message::*;
 pub use self::request::*;
@@ -1,5 +1,7 @@
+use crate::torrent_parser::torrent::Torrent;
+
 /// Represents the payload of a Request message.
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct Request {
     index: u32,
     begin: u32,
@@ -24,11 +26,71 @@ impl Request {
         bytes[8..12].copy_from_slice(&self.length.to_be_bytes());
         bytes
     }
+
+    /// Parses the payload of a Request message.
+    ///
+    /// It returns `InvalidPayload` if the payload is not 12 bytes long.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RequestError> {
+        if bytes.len() != 12 {
+            return Err(RequestError::InvalidPayload);
+        }
+        let read_u32 = |start: usize| {
+            let mut value = [0; 4];
+            value.copy_from_slice(&bytes[start..start + 4]);
+            u32::from_be_bytes(value)
+        };
+        Ok(Self::new(read_u32(0), read_u32(4), read_u32(8)))
+    }
+
+    /// Checks that the requested block is inside the torrent.
+    ///
+    /// It returns an error if:
+    /// - `InvalidPieceIndex` if the piece does not exist in the torrent.
+    /// - `InvalidLength` if the length is 0 or bigger than `max_length`.
+    /// - `OutOfBounds` if the block ends after the end of the piece.
+    pub fn validate(&self, torrent: &Torrent, max_length: u32) -> Result<(), RequestError> {
+        if self.index >= torrent.total_pieces() {
+            return Err(RequestError::InvalidPieceIndex);
+        }
+        if self.length == 0 || self.length > max_length {
+            return Err(RequestError::InvalidLength);
+        }
+        if self.begin as u64 + self.length as u64 > torrent.piece_size(self.index) as u64 {
+            return Err(RequestError::OutOfBounds);
+        }
+        Ok(())
+    }
+
+    /// Returns the index of the requested piece.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Returns the offset of the requested block inside the piece.
+    pub fn begin(&self) -> u32 {
+        self.begin
+    }
+
+    /// Returns the length of the requested block.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+}
+
+/// Posible errors of a Request message received from a peer.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RequestError {
+    InvalidPayload,
+    InvalidPieceIndex,
+    InvalidLength,
+    OutOfBounds,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::torrent_parser::info::Info;
+    use url_encoder::info_hash::InfoHash;
 
     #[test]
     fn test_request_as_bytes() {
@@ -46,4 +108,90 @@ mod tests {
 
         assert_eq!(bytes, expected);
     }
+
+    #[test]
+    fn test_request_from_bytes() {
+        let request = Request::new(1, 2, 3);
+
+        assert_eq!(Request::from_bytes(&request.as_bytes()).unwrap(), request);
+    }
+
+    #[test]
+    fn test_request_from_bytes_with_invalid_payload() {
+        assert_eq!(
+            Request::from_bytes(&[0; 8]),
+            Err(RequestError::InvalidPayload)
+        );
+    }
+
+    #[test]
+    fn test_valid_request() {
+        let torrent = create_test_torrent();
+
+        assert!(Request::new(0, 0, 16384).validate(&torrent, 16384).is_ok());
+        assert!(Request::new(2, 0, 100).validate(&torrent, 16384).is_ok());
+    }
+
+    #[test]
+    fn test_request_with_invalid_piece_index() {
+        let torrent = create_test_torrent();
+
+        assert_eq!(
+            Request::new(3, 0, 100).validate(&torrent, 16384),
+            Err(RequestError::InvalidPieceIndex)
+        );
+    }
+
+    #[test]
+    fn test_request_with_invalid_length() {
+        let torrent = create_test_torrent();
+
+        assert_eq!(
+            Request::new(0, 0, 32768).validate(&torrent, 16384),
+            Err(RequestError::InvalidLength)
+        );
+        assert_eq!(
+            Request::new(0, 0, 0).validate(&torrent, 16384),
+            Err(RequestError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn test_request_past_the_end_of_the_piece() {
+        let torrent = create_test_torrent();
+
+        assert_eq!(
+            Request::new(0, 16384, 16384).validate(&torrent, 16384),
+            Err(RequestError::OutOfBounds)
+        );
+        assert_eq!(
+            Request::new(2, 0, 101).validate(&torrent, 16384),
+            Err(RequestError::OutOfBounds)
+        );
+        assert_eq!(
+            Request::new(0, u32::MAX, 16384).validate(&torrent, 16384),
+            Err(RequestError::OutOfBounds)
+        );
+    }
+
+    /// Torrent with two pieces of 16 KiB and a last piece of 100 bytes.
+    fn create_test_torrent() -> Torrent {
+        Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![],
+            http_seeds: vec![],
+            info_hash_v2: None,
+            piece_layer: vec![],
+            info: Info {
+                length: 2 * 16384 + 100,
+                name: "test".to_string(),
+                piece_length: 16384,
+                pieces: vec![],
+                private: None,
+                meta_version: None,
+                file_tree: Default::default(),
+            },
+            info_hash: InfoHash::new([0; 20]),
+        }
+    }
 }
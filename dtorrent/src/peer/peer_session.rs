@@ -1,68 +1,127 @@
 use std::{
-    fmt::Write,
-    io::{self, Read, Write as IOWrite},
-    net::TcpStream,
-    sync::Arc,
-    time::Duration,
+    io::{self, Read},
+    net::{Shutdown, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
-use chrono::{DateTime, Local};
-use sha1::{Digest, Sha1};
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
 use crate::{
     config::cfg::Cfg,
-    torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError},
+    torrent_handler::{
+        cancellation_token::CancellationToken,
+        rate_tracker::RateTracker,
+        status::{AtomicTorrentStatus, AtomicTorrentStatusError, ConnectionId, HashedPiece},
+    },
     torrent_parser::torrent::Torrent,
 };
 
 use super::{
+    block_hashes::{BlockCheck, BlockHashes},
+    block_scheduler::BlockScheduler,
     bt_peer::{BtPeer, BtPeerError},
+    connection_direction::ConnectionDirection,
+    features::Features,
+    handshake::HANDSHAKE_LENGTH,
     message_handler::{MessageHandler, MessageHandlerError},
-    peer_message::{Bitfield, Message, MessageError, MessageId},
+    peer_message::{
+        read_frame, Bitfield, Frame, FrameError, HashRequest, HashRequestError, Hashes, Message,
+        MessageId, Request, RequestError,
+    },
     session_status::SessionStatus,
 };
 
-const BLOCK_SIZE: u32 = 16384;
-
 #[derive(Debug)]
 pub enum PeerSessionError {
     ErrorReadingMessage(io::Error),
-    MessageDoesNotExist(MessageError),
     CouldNotConnectToPeer,
     ErrorDisconnectingFromPeer(AtomicTorrentStatusError),
     ErrorAbortingPiece(AtomicTorrentStatusError),
     ErrorSelectingPiece(AtomicTorrentStatusError),
     ErrorNotifyingPieceDownloaded(AtomicTorrentStatusError),
+    ErrorHashingPiece(AtomicTorrentStatusError),
     ErrorConnectingToPeer(AtomicTorrentStatusError),
-    PieceHashDoesNotMatch,
-    NoPiecesLeftToDownloadInThisPeer,
     ErrorGettingBitfield(AtomicTorrentStatusError),
     ErrorGettingPiece(AtomicTorrentStatusError),
     ErrorGettingSessionsStatus(AtomicTorrentStatusError),
-    PeerNotInterested,
     MessageHandlerError(MessageHandlerError),
     MessageError(MessageId),
-    MessageLengthTooLong,
+    ErrorReadingFrame(FrameError),
     ErrorSettingStreamTimeout,
     BtPeerError(BtPeerError),
     PeerIsOurself,
+    PeerAlreadyConnected,
+    ErrorSuperSeeding(AtomicTorrentStatusError),
+    ErrorChokingPeer(AtomicTorrentStatusError),
+    ErrorCheckingDisconnectRequest(AtomicTorrentStatusError),
+    DisconnectRequested,
+    Cancelled,
+    ErrorGettingCancellationToken(AtomicTorrentStatusError),
+    ErrorBanningPeer(AtomicTorrentStatusError),
+    PeerBanned,
+    ErrorUpdatingStats(AtomicTorrentStatusError),
+    InvalidRequest(RequestError),
+    InvalidHashMessage(HashRequestError),
+}
+
+/// Time to wait for a message before serving the session again, for example to unchoke the peer when an
+/// upload slot is freed or to announce the pieces downloaded from other peers.
+const POLL_MILLIS: u64 = 100;
+
+/// Time to wait for the peer to close the connection once both peers have all the pieces.
+const CLOSE_MILLIS: u64 = 2000;
+
+/// Max bytes of the last messages of the peer read while closing the connection.
+const MAX_CLOSE_BYTES: u64 = 64 * 1024;
+
+/// Stage of the connection with a peer.
+///
+/// After the handshake and the bitfields, the session is `Negotiating` while no blocks can flow in either direction,
+/// and in `Transfer` while we download from the peer, upload to it, or both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    Handshaking,
+    /// Our bitfield was sent and the first message of the peer, usually its bitfield, was not received yet.
+    ExchangingBitfields,
+    Negotiating,
+    Transfer,
 }
 
 /// A PeerSession represents a connection to a peer.
 ///
-/// It is used to send and receive messages from a peer.
+/// It is used to send and receive messages from a peer. Both directions are served by a single event loop,
+/// so pieces are downloaded from and uploaded to the same peer at the same time.
 pub struct PeerSession {
     torrent: Torrent,
     peer: BtPeer,
     bitfield: Bitfield,
     status: SessionStatus,
-    piece: Vec<u8>,
     torrent_status: Arc<AtomicTorrentStatus>,
-    current_piece: u32,
+    scheduler: BlockScheduler,
     config: Cfg,
     message_handler: MessageHandler,
     client_peer_id: String,
+    download_rate: RateTracker,
+    upload_rate: RateTracker,
+    state: SessionState,
+    last_message: Instant,
+    /// Number of downloaded pieces when our bitfield was last refreshed.
+    known_pieces: usize,
+    /// Id of the connection in the torrent status, `None` until the peer is connected.
+    connection: Option<ConnectionId>,
+    /// Cancelled by the torrent status to stop the session, for example when the torrent is paused.
+    cancellation: CancellationToken,
+    /// Outcome of the pieces sent to be hashed, see `AtomicTorrentStatus::hash_piece`.
+    hashed_sender: Sender<HashedPiece>,
+    hashed_receiver: Receiver<HashedPiece>,
+    /// Pieces sent to be hashed whose outcome was not handled yet.
+    hashing_pieces: u32,
+    /// Hashes of the blocks of the pieces downloaded from the peer, for hybrid torrents.
+    block_hashes: Option<BlockHashes>,
 }
 
 impl PeerSession {
@@ -87,138 +146,403 @@ impl PeerSession {
         );
 
         let pieces_count = torrent.total_pieces();
+        let cancellation = torrent_status
+            .cancellation_token()
+            .map_err(PeerSessionError::ErrorGettingCancellationToken)?;
+        let (hashed_sender, hashed_receiver) = mpsc::channel();
+        let block_hashes = BlockHashes::new(&torrent);
 
         Ok(PeerSession {
             torrent,
             peer,
-            bitfield: Bitfield::new(vec![0; (pieces_count / 8) as usize]),
+            bitfield: Bitfield::new(vec![0; pieces_count.div_ceil(8) as usize]),
             status: SessionStatus::new(our_bitfield),
-            piece: vec![],
             torrent_status,
-            current_piece: 0,
+            scheduler: BlockScheduler::with_block_size(config.pipelining_size, config.block_size),
             config,
             message_handler,
             client_peer_id,
+            download_rate: RateTracker::new(),
+            upload_rate: RateTracker::new(),
+            state: SessionState::Handshaking,
+            last_message: Instant::now(),
+            known_pieces: 0,
+            connection: None,
+            cancellation,
+            hashed_sender,
+            hashed_receiver,
+            hashing_pieces: 0,
+            block_hashes,
         })
     }
 
+    /// Returns the stage of the connection.
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
     // ------------------------------------------------------------------------------------------------
-    // Uploading
+    // Connection
 
-    /// Handshakes with an incoming leecher.
-    pub fn handshake_incoming_leecher(
-        &mut self,
-        stream: &mut TcpStream,
-    ) -> Result<(), PeerSessionError> {
+    /// Handshakes with a peer that connected to us, after its handshake was received, and sends our bitfield.
+    pub fn handshake_incoming(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
         self.message_handler
             .send_handshake(stream)
             .map_err(PeerSessionError::MessageHandlerError)?;
 
         info!(
-            "IP: {}:{} Handshake successful",
-            self.peer.ip, self.peer.port
+            client = self.peer.client.as_deref().unwrap_or("unknown"),
+            "IP: {}:{} Handshake successful", self.peer.ip, self.peer.port
         );
 
-        self.message_handler
-            .send_bitfield(stream)
-            .map_err(PeerSessionError::MessageHandlerError)?;
+        self.send_bitfield(stream)
+    }
+
+    /// Serves a peer that connected to us until the connection ends, see `handshake_incoming`.
+    pub fn run_incoming(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+        self.connected(ConnectionDirection::Incoming)?;
+
+        let result = self.run(stream);
+        self.disconnected()?;
+        result
+    }
+
+    /// Connects to the peer, handshakes and serves it until the connection ends.
+    ///
+    /// It returns an error if:
+    /// - The connection could not be established
+    /// - The handshake was not successful
+    pub fn start_outgoing(&mut self) -> Result<(), PeerSessionError> {
+        let mut stream = match self.set_up_peer_session() {
+            Ok(stream) => stream,
+            Err(e) => {
+                self.torrent_status.peer_connecting_failed();
+                return Err(e);
+            }
+        };
+
+        self.connected(ConnectionDirection::Outgoing)?;
+
+        let result = self
+            .send_bitfield(&mut stream)
+            .and_then(|_| self.run(&mut stream));
+        self.disconnected()?;
+        result
+    }
+
+    /// Sends our bitfield, empty when super-seeding so the pieces can be revealed one at a time.
+    fn send_bitfield(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+        if self.torrent_status.is_super_seeding() {
+            self.message_handler
+                .send_empty_bitfield(stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+        } else {
+            self.message_handler
+                .send_bitfield(stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+        }
+        self.state = SessionState::ExchangingBitfields;
 
         info!("IP: {}:{} Bitfield sent", self.peer.ip, self.peer.port);
+        Ok(())
+    }
 
+    /// Adds the peer to the torrent status, keeping the id of the connection.
+    fn connected(&mut self, direction: ConnectionDirection) -> Result<(), PeerSessionError> {
+        let connection = self
+            .torrent_status
+            .peer_connected_once(&self.peer, direction, self.client_peer_id.as_bytes())
+            .map_err(|err| {
+                self.torrent_status.peer_connecting_failed();
+                match err {
+                    AtomicTorrentStatusError::DuplicateConnection => {
+                        PeerSessionError::PeerAlreadyConnected
+                    }
+                    err => PeerSessionError::ErrorConnectingToPeer(err),
+                }
+            })?;
+        self.connection = Some(connection);
         Ok(())
     }
 
-    pub fn unchoke_incoming_leecher(
-        &mut self,
-        stream: &mut TcpStream,
-    ) -> Result<(), PeerSessionError> {
-        self.torrent_status
-            .peer_connected(&self.peer)
-            .map_err(PeerSessionError::ErrorConnectingToPeer)?;
-        match self.unchoke_incoming_leecher_wrap(stream) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                self.torrent_status
-                    .peer_disconnected(&self.peer)
-                    .map_err(PeerSessionError::ErrorDisconnectingFromPeer)?;
-                Err(e)
+    /// Frees the pieces in progress and removes the peer from the torrent status.
+    fn disconnected(&mut self) -> Result<(), PeerSessionError> {
+        self.abort_pieces_in_progress()?;
+        match self.connection.take() {
+            Some(connection) => self
+                .torrent_status
+                .peer_disconnected(connection)
+                .map_err(PeerSessionError::ErrorDisconnectingFromPeer),
+            None => Ok(()),
+        }
+    }
+
+    /// Event loop of the session, serving the upload and the download on every message of the peer.
+    ///
+    /// It ends without error once both we and the peer have every piece.
+    fn run(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+        self.last_message = Instant::now();
+        loop {
+            self.check_disconnect_request()?;
+            self.handle_hashed_pieces()?;
+
+            self.upload(stream)?;
+            self.download(stream)?;
+            self.update_state();
+
+            if self.announced_all_pieces() && self.peer_has_all_pieces() {
+                info!(
+                    "IP: {}:{} Both peers have all the pieces",
+                    self.peer.ip, self.peer.port
+                );
+                self.close(stream);
+                return Ok(());
             }
+
+            self.wait_for_message(stream)?;
         }
     }
 
-    /// Sends an unchoke message to the peer to start sending pieces.
-    pub fn unchoke_incoming_leecher_wrap(
-        &mut self,
-        stream: &mut TcpStream,
-    ) -> Result<(), PeerSessionError> {
-        let mut id = self.read_message_from_stream(stream)?;
-        while id != MessageId::Interested {
-            // if we receive a `not interested` message, we close the connection.
-            if id == MessageId::NotInterested {
-                self.status.peer_interested = false;
-                // peer disconnected
-                return Err(PeerSessionError::PeerNotInterested);
+    /// Closes our side of the connection and reads what the peer sent meanwhile until it closes its own.
+    ///
+    /// Closing with messages of the peer still unread resets the connection, so the peer could fail to read the
+    /// last messages we sent.
+    fn close(&self, stream: &mut TcpStream) {
+        if stream.shutdown(Shutdown::Write).is_err()
+            || stream
+                .set_read_timeout(Some(Duration::from_millis(CLOSE_MILLIS)))
+                .is_err()
+        {
+            return;
+        }
+        let _ = io::copy(&mut stream.take(MAX_CLOSE_BYTES), &mut io::sink());
+    }
+
+    /// Moves between `Negotiating` and `Transfer` as the choke and interest of both sides change.
+    fn update_state(&mut self) {
+        if !matches!(
+            self.state,
+            SessionState::Negotiating | SessionState::Transfer
+        ) {
+            return;
+        }
+        let downloading = self.status.interested && !self.status.choked;
+        let uploading = self.status.peer_interested && !self.status.peer_choked;
+        self.state = if downloading || uploading {
+            SessionState::Transfer
+        } else {
+            SessionState::Negotiating
+        };
+    }
+
+    /// Waits a short time for the next message of the peer and handles it.
+    ///
+    /// A peer that sends nothing for `read_write_seconds_timeout` is disconnected. If a requested block is not
+    /// delivered in time the peer is marked as snubbed, and a snubbed peer that times out again is disconnected.
+    fn wait_for_message(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+        let poll = Duration::from_millis(POLL_MILLIS);
+        let request_timeout = Duration::from_secs(self.config.request_timeout_seconds);
+        let wait = match self.scheduler.oldest_request_age() {
+            Some(age) => request_timeout.saturating_sub(age).min(poll),
+            None => poll,
+        };
+        stream
+            .set_read_timeout(Some(wait.max(Duration::from_millis(1))))
+            .map_err(|_| PeerSessionError::ErrorSettingStreamTimeout)?;
+
+        // Only peeking, so a timeout never leaves a message half read.
+        let peeked = stream.peek(&mut [0; 1]);
+        self.set_stream_timeouts(stream, self.config.read_write_seconds_timeout)?;
+
+        match peeked {
+            Ok(0) => Err(PeerSessionError::ErrorReadingMessage(io::Error::from(
+                io::ErrorKind::UnexpectedEof,
+            ))),
+            Ok(_) => {
+                self.read_message_from_stream(stream)?;
+                self.last_message = Instant::now();
+                Ok(())
+            }
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                if self.scheduler.has_timed_out(request_timeout) {
+                    if self.status.snubbed {
+                        return Err(PeerSessionError::ErrorReadingMessage(err));
+                    }
+                    return self.peer_snubbed();
+                }
+                let idle_timeout = Duration::from_secs(self.config.read_write_seconds_timeout);
+                if self.last_message.elapsed() >= idle_timeout {
+                    return Err(PeerSessionError::ErrorReadingMessage(err));
+                }
+                Ok(())
             }
-            // wait for the peer to send an interested message
-            id = self.read_message_from_stream(stream)?;
+            Err(err) => Err(PeerSessionError::ErrorReadingMessage(err)),
         }
+    }
 
-        // Peer is interested
-        self.status.peer_interested = true;
+    /// Returns true once the torrent is finished and the peer was told about every piece we have.
+    ///
+    /// The hashing pool can save a piece at any time, so the torrent being finished is not enough: leaving before
+    /// announcing the last piece would leave the peer waiting for it.
+    fn announced_all_pieces(&self) -> bool {
+        self.torrent_status.is_finished()
+            && (self.torrent_status.is_super_seeding()
+                || self.known_pieces == self.torrent_status.downloaded_pieces())
+    }
 
-        self.message_handler
-            .send_unchoked(stream)
-            .map_err(PeerSessionError::MessageHandlerError)?;
+    /// Returns true if the peer announced every piece of the torrent.
+    fn peer_has_all_pieces(&self) -> bool {
+        (0..self.torrent.total_pieces()).all(|index| self.bitfield.has_piece(index))
+    }
 
-        self.status.peer_choked = false;
+    // ------------------------------------------------------------------------------------------------
+    // Uploading
 
-        loop {
-            self.update_bitfield(stream)?;
+    /// Unchokes the peer while it is interested and has an upload slot, and announces our new pieces.
+    fn upload(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+        if self.status.peer_interested && self.status.peer_choked {
+            if self
+                .torrent_status
+                .try_unchoke_peer(&self.peer)
+                .map_err(PeerSessionError::ErrorChokingPeer)?
+            {
+                self.message_handler
+                    .send_unchoked(stream)
+                    .map_err(PeerSessionError::MessageHandlerError)?;
+                self.status.peer_choked = false;
+                self.update_peer_status()?;
+            }
+        } else if !self.status.peer_interested && !self.status.peer_choked {
+            // The upload slot is given to other peers while this one does not want our pieces.
+            self.torrent_status
+                .peer_choked(&self.peer)
+                .map_err(PeerSessionError::ErrorChokingPeer)?;
+            self.message_handler
+                .send_choked(stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+            self.status.peer_choked = true;
+            self.update_peer_status()?;
+        }
 
-            // TODO: Handle max connections.
-            self.read_message_from_stream(stream)?;
+        if self.torrent_status.is_super_seeding() {
+            if !self.status.peer_choked {
+                self.reveal_next_piece(stream)?;
+            }
+        } else {
+            self.announce_new_pieces(stream)?;
         }
+        Ok(())
+    }
+
+    /// Sends a `Have` message with the next piece to reveal to the peer when super-seeding.
+    fn reveal_next_piece(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+        let next_piece = self
+            .torrent_status
+            .super_seed_next_piece(&self.peer, &self.bitfield)
+            .map_err(PeerSessionError::ErrorSuperSeeding)?;
+
+        if let Some(index) = next_piece {
+            self.message_handler
+                .send_have(index, stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+        }
+        Ok(())
+    }
+
+    /// Sends a `Have` message for each piece downloaded since the last announce, from this or other peers.
+    fn announce_new_pieces(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+        // Read before the bitfield, so `known_pieces` never counts a piece that was not announced.
+        let downloaded_pieces = self.torrent_status.downloaded_pieces();
+        if downloaded_pieces == self.known_pieces {
+            return Ok(());
+        }
+
+        let updated_bitfield = self
+            .torrent_status
+            .get_bitfield()
+            .map_err(PeerSessionError::ErrorGettingBitfield)?;
+
+        for index in updated_bitfield.diff(&self.status.bitfield) {
+            self.message_handler
+                .send_have(index as u32, stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+        }
+
+        self.status.bitfield = updated_bitfield;
+        self.known_pieces = downloaded_pieces;
+        Ok(())
     }
 
     /// ------------------------------------------------------------------------------------------------
     /// Downloading
 
-    /// Starts a connection to an outgoing seeder to start downloading pieces.
+    /// Tells the peer whether we want its pieces, and requests blocks while it does not choke us.
     ///
-    /// It returns an error if:
-    /// - The connection could not be established
-    /// - The handshake was not successful
-    pub fn start_outgoing_seeder(&mut self) -> Result<(), PeerSessionError> {
-        let mut stream = match self.set_up_peer_session() {
-            Ok(stream) => stream,
-            Err(e) => {
-                self.torrent_status.peer_connecting_failed();
-                return Err(e);
+    /// Block requests are pipelined across pieces: a new piece is selected as soon as all the blocks of the current
+    /// ones are requested, so the peer always has requests queued.
+    fn download(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+        let interested = self.wants_pieces_from_peer();
+        if interested != self.status.interested {
+            if interested {
+                self.message_handler
+                    .send_interested(stream)
+                    .map_err(PeerSessionError::MessageHandlerError)?;
+            } else {
+                self.message_handler
+                    .send_not_interested(stream)
+                    .map_err(PeerSessionError::MessageHandlerError)?;
+                self.cancel_finished_pieces(stream)?;
+                self.abort_pieces_in_progress()?;
             }
-        };
+            self.status.interested = interested;
+            self.update_peer_status()?;
+        }
 
-        self.torrent_status
-            .peer_connected(&self.peer)
-            .map_err(PeerSessionError::ErrorConnectingToPeer)?;
+        // While choked, the pieces in progress are kept and their blocks are requested again after the unchoke.
+        if !self.status.interested || self.status.choked {
+            return Ok(());
+        }
 
-        match self.start_outgoing_seeder_wrap(&mut stream) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                self.torrent_status
-                    .peer_disconnected(&self.peer)
-                    .map_err(PeerSessionError::ErrorDisconnectingFromPeer)?;
-                Err(e)
-            }
+        if self.is_endgame() {
+            self.cancel_finished_pieces(stream)?;
         }
+        self.select_pieces()?;
+        self.send_requests(stream)
     }
 
-    fn set_up_peer_session(&mut self) -> Result<TcpStream, PeerSessionError> {
-        let peer_socket = format!("{}:{}", self.peer.ip, self.peer.port);
+    /// Returns true if the torrent is not finished and the peer has a piece we do not have.
+    fn wants_pieces_from_peer(&self) -> bool {
+        !self.torrent_status.is_finished()
+            && self
+                .bitfield
+                .bitfield
+                .iter()
+                .zip(self.status.bitfield.bitfield.iter())
+                .any(|(theirs, ours)| theirs & !ours != 0)
+    }
 
-        let mut stream = TcpStream::connect(&peer_socket)
-            .map_err(|_| PeerSessionError::CouldNotConnectToPeer)?;
+    fn set_up_peer_session(&mut self) -> Result<TcpStream, PeerSessionError> {
+        if self.cancellation.is_cancelled() {
+            return Err(PeerSessionError::Cancelled);
+        }
+        let mut stream = match self.config.proxy() {
+            Some(proxy) if self.config.proxy_peer_connections => proxy
+                .connect(&self.peer.ip, self.peer.port as u16)
+                .map_err(|_| PeerSessionError::CouldNotConnectToPeer)?,
+            _ => {
+                let peer_socket = format!("{}:{}", self.peer.ip, self.peer.port);
+                TcpStream::connect(&peer_socket)
+                    .map_err(|_| PeerSessionError::CouldNotConnectToPeer)?
+            }
+        };
 
-        self.set_stream_timeouts(&mut stream)?;
+        // A peer that accepts the connection but never answers the handshake is dropped sooner.
+        self.set_stream_timeouts(&mut stream, self.config.handshake_seconds_timeout)?;
 
         self.message_handler
             .send_handshake(&mut stream)
@@ -227,256 +551,298 @@ impl PeerSession {
         self.peer
             .receive_handshake(&mut stream)
             .map_err(PeerSessionError::BtPeerError)?;
+        self.torrent_status
+            .add_overhead_downloaded_bytes(HANDSHAKE_LENGTH as u64);
 
-        info!("Handshake successful");
+        self.set_stream_timeouts(&mut stream, self.config.read_write_seconds_timeout)?;
+
+        info!(
+            client = self.peer.client.as_deref().unwrap_or("unknown"),
+            "Handshake successful"
+        );
 
         // Avoid connecting to ourself.
         match &self.peer.peer_id {
             Some(id) => {
-                if id == self.client_peer_id.to_string().as_bytes() {
+                if id == self.client_peer_id.as_bytes() {
                     return Err(PeerSessionError::PeerIsOurself);
                 }
+                // Avoid a second connection to a peer that connected to us from another port.
+                if !self
+                    .torrent_status
+                    .keeps_connection(
+                        id,
+                        ConnectionDirection::Outgoing,
+                        self.client_peer_id.as_bytes(),
+                    )
+                    .map_err(PeerSessionError::ErrorConnectingToPeer)?
+                {
+                    return Err(PeerSessionError::PeerAlreadyConnected);
+                }
             }
             None => (),
         }
         Ok(stream)
     }
 
-    fn start_outgoing_seeder_wrap(
-        &mut self,
-        stream: &mut TcpStream,
-    ) -> Result<(), PeerSessionError> {
-        loop {
-            self.read_message_from_stream(stream)?;
-
-            if self.status.choked && !self.status.interested {
-                self.message_handler
-                    .send_interested(stream)
-                    .map_err(PeerSessionError::MessageHandlerError)?;
-
-                self.status.interested = true;
-            }
+    /// Selects new pieces to download while the request queue has room for them.
+    fn select_pieces(&mut self) -> Result<(), PeerSessionError> {
+        let bytes_per_second = self.download_bytes_per_second();
 
-            if !self.status.choked && self.status.interested {
-                self.request_pieces(stream)?;
+        while self.scheduler.wants_more_pieces(bytes_per_second) {
+            // In the endgame all the pieces left are already being downloaded, so we download one at a time, and
+            // not while the last one is hashed since it may be selected again.
+            if self.is_endgame() && (!self.scheduler.is_empty() || self.hashing_pieces > 0) {
+                break;
             }
-        }
-    }
 
-    fn request_pieces(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
-        loop {
             let piece_index = self
                 .torrent_status
                 .select_piece(&self.bitfield)
                 .map_err(PeerSessionError::ErrorSelectingPiece)?;
 
             match piece_index {
-                Some(piece_index) => {
-                    self.current_piece = piece_index;
-                    match self.download_piece(stream, piece_index) {
-                        Ok(_) => {
-                            self.torrent_status
-                                .piece_downloaded(piece_index, &self.piece)
-                                .map_err(PeerSessionError::ErrorNotifyingPieceDownloaded)?;
-                        }
-                        Err(e) => {
-                            self.torrent_status
-                                .piece_aborted(piece_index)
-                                .map_err(PeerSessionError::ErrorAbortingPiece)?;
-
-                            return Err(e);
-                        }
+                Some(index) => {
+                    self.scheduler
+                        .add_piece(index, self.torrent.piece_size(index));
+                    if let Some(block_hashes) = self.peer_block_hashes() {
+                        block_hashes.piece_selected(index);
                     }
-                    if self.status.choked {
-                        // If we are choked, we need to wait for the peer to unchoke us.
-                        return Ok(());
-                    }
-                }
-                None => {
-                    return Err(PeerSessionError::NoPiecesLeftToDownloadInThisPeer);
                 }
-            };
+                None => break,
+            }
         }
+        Ok(())
     }
 
-    /// Downloads a piece from the peer given the piece index.
-    fn download_piece(
-        &mut self,
-        stream: &mut TcpStream,
-        piece_index: u32,
-    ) -> Result<(), PeerSessionError> {
-        self.piece = vec![]; // reset piece
+    /// Returns the hashes of the blocks of the torrent if the peer can send them.
+    fn peer_block_hashes(&mut self) -> Option<&mut BlockHashes> {
+        if !self.peer.features.contains(Features::BITTORRENT_V2) {
+            return None;
+        }
+        self.block_hashes.as_mut()
+    }
 
-        let entire_blocks_in_piece = self.download_with_pipeline(piece_index, stream)?;
+    /// Marks the peer as snubbed, and gives the pieces we were downloading from it to other peers.
+    fn peer_snubbed(&mut self) -> Result<(), PeerSessionError> {
+        warn!("IP: {}:{} Peer snubbed us", self.peer.ip, self.peer.port);
 
-        self.check_last_piece_block(piece_index, entire_blocks_in_piece, stream)?;
+        self.abort_pieces_in_progress()?;
+        self.set_snubbed(true)
+    }
 
-        self.validate_piece(&self.piece, piece_index)?;
+    fn set_snubbed(&mut self, snubbed: bool) -> Result<(), PeerSessionError> {
+        self.scheduler.set_snubbed(snubbed);
+        self.status.snubbed = snubbed;
+        self.torrent_status
+            .set_peer_snubbed(&self.peer, snubbed)
+            .map_err(PeerSessionError::ErrorChokingPeer)?;
+        self.update_peer_status()
+    }
 
-        info!("Piece {} downloaded!", piece_index);
+    /// Returns true if all the pieces left are already being downloaded by some peer.
+    fn is_endgame(&self) -> bool {
+        self.torrent_status.downloading_pieces() >= self.torrent_status.remaining_pieces()
+    }
 
-        let remaining_pieces = self.torrent_status.downloaded_pieces();
-        info!(
-            "*** Torrent: {} - Pieces downloaded: {} / {}",
-            self.torrent.name(),
-            remaining_pieces,
-            self.torrent.total_pieces()
-        );
+    /// Cancels the requests of the pieces in progress that were already downloaded from another peer.
+    fn cancel_finished_pieces(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+        let bitfield = self
+            .torrent_status
+            .get_bitfield()
+            .map_err(PeerSessionError::ErrorGettingBitfield)?;
 
+        for piece_index in self.scheduler.pieces_in_progress() {
+            if !bitfield.has_piece(piece_index) {
+                continue;
+            }
+            if let Some(block_hashes) = &mut self.block_hashes {
+                block_hashes.forget(piece_index);
+            }
+            for request in self.scheduler.cancel_piece(piece_index) {
+                self.message_handler
+                    .send_cancel(request.index, request.begin, request.length, stream)
+                    .map_err(PeerSessionError::MessageHandlerError)?;
+            }
+        }
         Ok(())
     }
 
-    /// Downloads a piece in 'chunks' of blocks.
-    ///
-    /// If the pipelinening size in the config is 5, then it will request 5 blocks and wait for those 5 blocks to be received.
-    ///
-    /// If there are less than 5 blocks left in the piece, it will request the remaining blocks and wait for those blocks to be received.
-    fn download_with_pipeline(
-        &mut self,
-        piece_index: u32,
-        stream: &mut TcpStream,
-    ) -> Result<u32, PeerSessionError> {
-        let entire_blocks_in_piece = self.complete_blocks_in_torrent_piece(piece_index);
-        let mut blocks_downloaded = 0;
-        while blocks_downloaded < entire_blocks_in_piece {
-            let remaining_blocks = entire_blocks_in_piece - blocks_downloaded;
-            let blocks_to_download = if remaining_blocks % self.config.pipelining_size == 0 {
-                self.config.pipelining_size
-            } else {
-                remaining_blocks
-            };
-
-            let download_start_time = Local::now();
-
-            // request blocks
-            for block in 0..blocks_to_download {
+    /// Sends the block requests needed to fill the request queue, after the hash requests of the new pieces.
+    fn send_requests(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+        if let Some(block_hashes) = &mut self.block_hashes {
+            for request in block_hashes.take_unsent() {
                 self.message_handler
-                    .send_request(
-                        piece_index,
-                        (block + blocks_downloaded) * BLOCK_SIZE,
-                        BLOCK_SIZE,
-                        stream,
-                    )
+                    .send_hash_request(&request, stream)
                     .map_err(PeerSessionError::MessageHandlerError)?;
             }
+        }
 
-            // If we are in the endgame phase, and we already downloaded all the blocks, we send a cancel message.
-            if self.torrent_status.is_finished() {
-                for block in 0..blocks_to_download {
-                    self.message_handler
-                        .send_cancel(
-                            piece_index,
-                            (block + blocks_downloaded) * BLOCK_SIZE,
-                            BLOCK_SIZE,
-                            stream,
-                        )
-                        .map_err(PeerSessionError::MessageHandlerError)?;
-                }
-            }
+        let requests = self
+            .scheduler
+            .next_requests(self.download_bytes_per_second());
 
-            // Check that we receive a piece message.
-            // If we receive another message we handle it accordingly.
-            let mut current_blocks_downloaded = 0;
-            while current_blocks_downloaded < blocks_to_download {
-                if self.read_message_from_stream(stream)? == MessageId::Piece {
-                    current_blocks_downloaded += 1;
-                    blocks_downloaded += 1;
-                }
-            }
-            // Calculate download speed
-            let download_speed = self.calculate_kilobits_per_second(
-                download_start_time,
-                (blocks_to_download * BLOCK_SIZE).into(),
-            );
-            self.status.download_speed = download_speed;
-            self.update_peer_status()?;
+        for request in requests {
+            self.message_handler
+                .send_request(request.index, request.begin, request.length, stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
         }
-        Ok(entire_blocks_in_piece)
+        Ok(())
     }
 
-    fn check_last_piece_block(
+    /// Sends a piece whose blocks were all received to be hashed and stored, without waiting for it, see
+    /// `handle_hashed_pieces`.
+    fn piece_completed(
         &mut self,
         piece_index: u32,
-        entire_blocks_in_piece: u32,
-        stream: &mut TcpStream,
+        piece: Vec<u8>,
     ) -> Result<(), PeerSessionError> {
-        let last_block_size = self.torrent.last_piece_size() % BLOCK_SIZE;
+        if let Some(block_hashes) = &mut self.block_hashes {
+            block_hashes.forget(piece_index);
+        }
+        self.torrent_status
+            .hash_piece(piece_index, piece, self.hashed_sender.clone())
+            .map_err(PeerSessionError::ErrorHashingPiece)?;
+        self.hashing_pieces += 1;
+        Ok(())
+    }
 
-        let last_piece_index = self.torrent.total_pieces() - 1;
+    /// Handles the pieces hashed since the last call: the stored ones are logged and the corrupted ones are
+    /// counted against the peer, see `corrupted_piece_received`.
+    fn handle_hashed_pieces(&mut self) -> Result<(), PeerSessionError> {
+        while let Ok(hashed) = self.hashed_receiver.try_recv() {
+            self.hashing_pieces -= 1;
+            match hashed.result {
+                Ok(true) => {
+                    self.torrent_status
+                        .peer_provided_data(&self.peer)
+                        .map_err(PeerSessionError::ErrorNotifyingPieceDownloaded)?;
+
+                    info!(
+                        piece = hashed.index,
+                        downloaded_pieces = self.torrent_status.downloaded_pieces(),
+                        total_pieces = self.torrent.total_pieces(),
+                        "Piece downloaded"
+                    );
+                }
+                Ok(false) => self.corrupted_piece_received(hashed.index, hashed.length)?,
+                // Saved meanwhile from another peer, or from this one during the endgame, so it was wasted.
+                Err(AtomicTorrentStatusError::PieceWasNotDownloading) => (),
+                Err(err) => return Err(PeerSessionError::ErrorNotifyingPieceDownloaded(err)),
+            }
+        }
+        Ok(())
+    }
 
-        if last_block_size != 0 && piece_index == last_piece_index {
-            self.message_handler
-                .send_request(
-                    piece_index,
-                    entire_blocks_in_piece * BLOCK_SIZE,
-                    last_block_size,
-                    stream,
-                )
-                .map_err(PeerSessionError::MessageHandlerError)?;
+    /// Frees the pieces that were being downloaded from the peer, so other peers can download them.
+    ///
+    /// Pieces already downloaded from another peer during the endgame are left as they are.
+    fn abort_pieces_in_progress(&mut self) -> Result<(), PeerSessionError> {
+        let bitfield = self
+            .torrent_status
+            .get_bitfield()
+            .map_err(PeerSessionError::ErrorGettingBitfield)?;
 
-            while self.read_message_from_stream(stream)? != MessageId::Piece {
+        for piece_index in self.scheduler.take_pieces() {
+            if let Some(block_hashes) = &mut self.block_hashes {
+                block_hashes.forget(piece_index);
+            }
+            if bitfield.has_piece(piece_index) {
                 continue;
             }
+            self.torrent_status
+                .piece_aborted(piece_index)
+                .map_err(PeerSessionError::ErrorAbortingPiece)?;
         }
         Ok(())
     }
 
-    fn complete_blocks_in_torrent_piece(&self, piece_index: u32) -> u32 {
-        let last_piece_index = self.torrent.total_pieces() - 1;
+    /// Returns the download rate of the peer in bytes per second.
+    fn download_bytes_per_second(&self) -> f64 {
+        self.download_rate.kilobits_per_second() * 1024.0 / 8.0
+    }
 
-        if piece_index != last_piece_index {
-            self.torrent.piece_length() / BLOCK_SIZE
-        } else {
-            let last_piece_size = self.torrent.last_piece_size();
+    /// Registers a piece that failed the hash check.
+    ///
+    /// The downloaded bytes are counted as wasted, and if the peer sent too many corrupted pieces it is banned.
+    fn corrupted_piece_received(
+        &mut self,
+        piece_index: u32,
+        piece_length: usize,
+    ) -> Result<(), PeerSessionError> {
+        warn!(
+            "IP: {}:{} Piece {} failed the hash check",
+            self.peer.ip, self.peer.port, piece_index
+        );
+        self.corrupted_data_received(piece_length)
+    }
 
-            // If the last piece is multiple of the piece length, then is the same as the other pieces.
-            if last_piece_size == 0 {
-                self.torrent.piece_length() / BLOCK_SIZE
-            } else {
-                (last_piece_size as f64 / BLOCK_SIZE as f64).floor() as u32
-            }
+    /// Registers a block that does not match the hashes of its piece, and requests it again.
+    ///
+    /// It is counted against the peer as a corrupted piece, since the piece would have failed the hash check.
+    fn corrupted_block_received(
+        &mut self,
+        piece_index: u32,
+        begin: u32,
+        block_length: usize,
+    ) -> Result<(), PeerSessionError> {
+        warn!(
+            "IP: {}:{} Block at {} of piece {} failed the merkle hash check",
+            self.peer.ip, self.peer.port, begin, piece_index
+        );
+        if !self
+            .scheduler
+            .block_rejected(piece_index, begin, block_length as u32)
+        {
+            // Not requested, so it is wasted like any unexpected block.
+            self.torrent_status.add_wasted_bytes(block_length as u64);
+            return Ok(());
         }
+        self.corrupted_data_received(block_length)
     }
 
-    /// ------------------------------------------------------------------------------------------------
-    /// Commons for download and upload
-
-    fn update_bitfield(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
-        let updated_bitfield = self
-            .torrent_status
-            .get_bitfield()
-            .map_err(PeerSessionError::ErrorGettingBitfield)?;
+    /// Counts corrupted data against the peer, and bans it if it sent too much.
+    fn corrupted_data_received(&mut self, length: usize) -> Result<(), PeerSessionError> {
+        self.torrent_status.add_wasted_bytes(length as u64);
+        self.torrent_status.add_hash_failure();
 
-        let indices = updated_bitfield.diff(&self.status.bitfield);
+        self.status.corrupted_pieces += 1;
+        self.update_peer_status()?;
 
-        for index in indices {
-            self.message_handler
-                .send_have(index as u32, stream)
-                .map_err(PeerSessionError::MessageHandlerError)?;
+        if self.status.corrupted_pieces >= self.config.runtime.max_corrupted_pieces() {
+            self.torrent_status
+                .ban_peer(&self.peer)
+                .map_err(PeerSessionError::ErrorBanningPeer)?;
+            return Err(PeerSessionError::PeerBanned);
         }
-
-        self.status.bitfield = updated_bitfield;
-
-        let bitfield_msg = Message::new(MessageId::Bitfield, self.status.bitfield.get_vec());
-        stream
-            .write_all(&bitfield_msg.as_bytes())
-            .map_err(|_| PeerSessionError::MessageError(MessageId::Bitfield))?;
         Ok(())
     }
 
-    fn calculate_kilobits_per_second(&self, start_time: DateTime<Local>, size: u64) -> f64 {
-        let elapsed_time = Local::now().signed_duration_since(start_time);
-        let elapsed_time_in_seconds = match elapsed_time.num_microseconds() {
-            Some(x) => x as f64 / 1_000_000.0,
-            None => return 0.0,
-        };
-        (size as f64 / elapsed_time_in_seconds) * 8.0 / 1024.0
+    /// ------------------------------------------------------------------------------------------------
+    /// Commons for download and upload
+
+    /// Returns an error if the torrent status asked this session to disconnect, for example to make room for other peers,
+    /// or cancelled every session of the torrent.
+    fn check_disconnect_request(&self) -> Result<(), PeerSessionError> {
+        if self.cancellation.is_cancelled() {
+            return Err(PeerSessionError::Cancelled);
+        }
+        if self
+            .torrent_status
+            .should_disconnect(&self.peer)
+            .map_err(PeerSessionError::ErrorCheckingDisconnectRequest)?
+        {
+            return Err(PeerSessionError::DisconnectRequested);
+        }
+        Ok(())
     }
 
     fn update_peer_status(&mut self) -> Result<(), PeerSessionError> {
-        self.torrent_status
-            .update_peer_session_status(&self.peer, &self.status)
-            .map_err(PeerSessionError::ErrorGettingSessionsStatus)?;
+        if let Some(connection) = self.connection {
+            self.torrent_status
+                .update_peer_session_status(connection, &self.peer, &self.status)
+                .map_err(PeerSessionError::ErrorGettingSessionsStatus)?;
+        }
         Ok(())
     }
 
@@ -488,34 +854,30 @@ impl PeerSession {
         &mut self,
         stream: &mut TcpStream,
     ) -> Result<MessageId, PeerSessionError> {
-        let mut length = [0; 4];
-
-        stream
-            .read_exact(&mut length)
-            .map_err(PeerSessionError::ErrorReadingMessage)?;
-        let len = u32::from_be_bytes(length);
-
-        // TODO: solucionar el problema de que el peer puede mandar un mensaje de mas de 16393 bytes. Cuando esta mandando cualquiera.
-        // Ahora que en el server la iniciacion esta dentro del Ok() esta fallando en el handshake, mirar ahi tambien.
-        if len > BLOCK_SIZE * 10 {
-            return Err(PeerSessionError::MessageLengthTooLong);
-        }
-
-        if len == 0 {
-            return Ok(MessageId::KeepAlive);
+        let frame = read_frame(stream, self.torrent.total_pieces());
+        if let Ok(frame) = &frame {
+            self.torrent_status
+                .add_overhead_downloaded_bytes(frame.overhead_bytes());
         }
-
-        let mut payload = vec![0; (len) as usize];
-
-        stream
-            .read_exact(&mut payload)
-            .map_err(PeerSessionError::ErrorReadingMessage)?;
-
-        let message =
-            Message::from_bytes(&payload).map_err(PeerSessionError::MessageDoesNotExist)?;
+        let message = match frame {
+            Ok(Frame::Message(message)) => message,
+            Ok(Frame::KeepAlive) => return Ok(MessageId::KeepAlive),
+            Ok(Frame::Unknown { id, .. }) => {
+                info!(
+                    "Ignoring unknown message with id {} from peer: {}:{}",
+                    id, self.peer.ip, self.peer.port
+                );
+                return Ok(MessageId::Unknown);
+            }
+            Err(FrameError::Io(err)) => return Err(PeerSessionError::ErrorReadingMessage(err)),
+            Err(err) => return Err(PeerSessionError::ErrorReadingFrame(err)),
+        };
         let id = message.id.clone();
 
         self.handle_message(message, stream)?;
+        if self.state == SessionState::ExchangingBitfields {
+            self.state = SessionState::Negotiating;
+        }
         Ok(id)
     }
 
@@ -531,18 +893,90 @@ impl PeerSession {
             }
             MessageId::Choke => {
                 self.status.choked = true;
+                // The peer discards our pending requests when it chokes us, so we stop waiting for them.
+                self.scheduler.requests_dropped();
+            }
+            MessageId::Interested => {
+                self.status.peer_interested = true;
+                self.update_peer_status()?;
+            }
+            MessageId::NotInterested => {
+                self.status.peer_interested = false;
+                self.update_peer_status()?;
             }
             MessageId::Bitfield => {
                 self.bitfield = self.message_handler.handle_bitfield(message);
+                self.status.peer_pieces = self.bitfield.pieces_count();
+                self.update_peer_status()?;
             }
             MessageId::Piece => {
-                let mut block = self.message_handler.handle_piece(message);
-                self.piece.append(&mut block);
+                let (index, begin, block) = self.message_handler.handle_piece(message);
+                self.torrent_status
+                    .add_downloaded_bytes(block.len() as u64)
+                    .map_err(PeerSessionError::ErrorUpdatingStats)?;
+                self.torrent_status.throttle_download(block.len());
+                self.download_rate.add(block.len() as u64);
+                self.status.download_speed = self.download_rate.kilobits_per_second();
+
+                let check = match &self.block_hashes {
+                    Some(block_hashes) => block_hashes.check_block(index, begin, &block),
+                    None => BlockCheck::Unknown,
+                };
+                if check == BlockCheck::Corrupted {
+                    self.corrupted_block_received(index, begin, block.len())?;
+                    return self.update_peer_status();
+                }
+
+                let received = self.scheduler.block_received(index, begin, &block);
+                if received.is_ok() && self.status.snubbed {
+                    self.set_snubbed(false)?;
+                }
+                match received {
+                    Ok(Some((index, piece))) => self.piece_completed(index, piece)?,
+                    Ok(None) => (),
+                    // Blocks we did not request can not be used.
+                    Err(_) => self.torrent_status.add_wasted_bytes(block.len() as u64),
+                }
+                self.update_peer_status()?;
             }
             MessageId::Request => self.handle_request(message, stream)?,
+            MessageId::HashRequest => self.handle_hash_request(message, stream)?,
+            MessageId::Hashes => {
+                let hashes = Hashes::from_bytes(&message.payload)
+                    .map_err(PeerSessionError::InvalidHashMessage)?;
+                if let Some(block_hashes) = &mut self.block_hashes {
+                    if let Err(err) = block_hashes.hashes_received(&hashes) {
+                        warn!(
+                            "Ignoring hashes {:?} from peer: {}:{}",
+                            err, self.peer.ip, self.peer.port
+                        );
+                    }
+                }
+            }
+            MessageId::HashReject => {
+                let request = HashRequest::from_bytes(&message.payload)
+                    .map_err(PeerSessionError::InvalidHashMessage)?;
+                if let Some(block_hashes) = &mut self.block_hashes {
+                    block_hashes.request_rejected(&request);
+                }
+            }
             MessageId::Have => {
                 let index = self.message_handler.handle_have(message);
-                self.bitfield.set_bit(index as u32, true);
+                if !self.bitfield.has_piece(index) {
+                    self.bitfield.set_bit(index, true);
+                    self.status.peer_pieces += 1;
+                    self.update_peer_status()?;
+                }
+                if self.torrent_status.is_super_seeding() {
+                    self.torrent_status
+                        .super_seed_piece_announced(&self.peer, index)
+                        .map_err(PeerSessionError::ErrorSuperSeeding)?;
+                }
+            }
+            // There is no DHT node yet, the port is kept on the peer, and on the peers of the torrent status, for discovery.
+            MessageId::Port => {
+                self.peer.dht_port = Some(self.message_handler.handle_port(message));
+                self.update_peer_status()?;
             }
             _ => {} // TODO: handle other messages,
         }
@@ -550,17 +984,17 @@ impl PeerSession {
     }
 
     /// Sets read and write timeouts for the stream.
-    fn set_stream_timeouts(&self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+    fn set_stream_timeouts(
+        &self,
+        stream: &mut TcpStream,
+        seconds: u64,
+    ) -> Result<(), PeerSessionError> {
         stream
-            .set_read_timeout(Some(Duration::from_secs(
-                self.config.read_write_seconds_timeout,
-            )))
+            .set_read_timeout(Some(Duration::from_secs(seconds)))
             .map_err(|_| PeerSessionError::ErrorSettingStreamTimeout)?;
 
         stream
-            .set_write_timeout(Some(Duration::from_secs(
-                self.config.read_write_seconds_timeout,
-            )))
+            .set_write_timeout(Some(Duration::from_secs(seconds)))
             .map_err(|_| PeerSessionError::ErrorSettingStreamTimeout)?;
         Ok(())
     }
@@ -571,66 +1005,328 @@ impl PeerSession {
         message: Message,
         stream: &mut TcpStream,
     ) -> Result<(), PeerSessionError> {
-        let mut index: [u8; 4] = [0; 4];
-        let mut begin: [u8; 4] = [0; 4];
-        let mut length: [u8; 4] = [0; 4];
-        index.copy_from_slice(&message.payload[0..4]);
-        begin.copy_from_slice(&message.payload[4..8]);
-        length.copy_from_slice(&message.payload[8..12]);
-
-        let index = u32::from_be_bytes(index);
-        let begin = u32::from_be_bytes(begin);
-        let length = u32::from_be_bytes(length);
-
-        let offset = index * self.torrent.piece_length() + begin;
-
-        let upload_start_time = Local::now();
+        // Requests from choked peers are ignored.
+        if self.status.peer_choked {
+            return Ok(());
+        }
 
-        let block = self
+        // Invalid requests disconnect the peer, so it can not read past the end of the file.
+        let request = Request::from_bytes(&message.payload)
+            .and_then(|request| {
+                request.validate(&self.torrent, self.config.max_request_length)?;
+                Ok(request)
+            })
+            .map_err(|err| {
+                warn!(
+                    "Invalid request {:?} from peer: {}:{}",
+                    err, self.peer.ip, self.peer.port
+                );
+                PeerSessionError::InvalidRequest(err)
+            })?;
+
+        let index = request.index();
+        let begin = request.begin();
+        let length = request.length();
+
+        let offset = self.torrent.piece_offset(index) + begin as u64;
+
+        // A well-behaved peer can request a piece before our `have` reaches it, so a piece we do not have yet is
+        // ignored instead of ending the session.
+        let block = match self
             .torrent_status
-            .get_piece(index, offset as u64, length as usize)
-            .map_err(PeerSessionError::ErrorGettingPiece)?;
+            .get_piece(index, offset, length as usize)
+        {
+            Ok(block) => block,
+            Err(AtomicTorrentStatusError::PieceWasNotFinished) => {
+                debug!(
+                    "Ignoring request of piece {} we do not have from peer: {}:{}",
+                    index, self.peer.ip, self.peer.port
+                );
+                return Ok(());
+            }
+            Err(err) => return Err(PeerSessionError::ErrorGettingPiece(err)),
+        };
 
         self.message_handler
             .send_piece(index, begin, &block, stream)
             .map_err(PeerSessionError::MessageHandlerError)?;
 
-        // Calculate upload speed
-        let upload_speed = self.calculate_kilobits_per_second(upload_start_time, (length).into());
-        self.status.upload_speed = upload_speed;
+        self.upload_rate.add(length.into());
+        self.status.upload_speed = self.upload_rate.kilobits_per_second();
         self.update_peer_status()?;
         Ok(())
     }
 
-    /// Validates the downloaded piece.
+    /// Handles a hash request received from the peer.
     ///
-    /// Checks the piece hash and compares it to the hash in the torrent file.
-    fn validate_piece(&self, piece: &[u8], piece_index: u32) -> Result<(), PeerSessionError> {
-        let start = (piece_index * 20) as usize;
-        let end = start + 20;
+    /// Only the hashes of the blocks of a whole piece we have are sent, other requests are rejected.
+    fn handle_hash_request(
+        &mut self,
+        message: Message,
+        stream: &mut TcpStream,
+    ) -> Result<(), PeerSessionError> {
+        let request = HashRequest::from_bytes(&message.payload)
+            .map_err(PeerSessionError::InvalidHashMessage)?;
+
+        let hashes = match self.served_hashes(&request)? {
+            Some(hashes) => hashes,
+            None => {
+                return self
+                    .message_handler
+                    .send_hash_reject(&request, stream)
+                    .map_err(PeerSessionError::MessageHandlerError)
+            }
+        };
+        self.message_handler
+            .send_hashes(&hashes, stream)
+            .map_err(PeerSessionError::MessageHandlerError)
+    }
 
-        let real_hash = &self.torrent.info.pieces[start..end];
-        let real_piece_hash = self.convert_to_hex_string(real_hash);
+    /// Returns the answer to a hash request, or `None` if it can not be served.
+    fn served_hashes(&self, request: &HashRequest) -> Result<Option<Hashes>, PeerSessionError> {
+        let block_hashes = match &self.block_hashes {
+            Some(block_hashes) if !self.status.peer_choked => block_hashes,
+            _ => return Ok(None),
+        };
+        let index = match block_hashes.requested_piece(request) {
+            Some(index) if index < self.torrent.total_pieces() => index,
+            _ => return Ok(None),
+        };
+        let bitfield = self
+            .torrent_status
+            .get_bitfield()
+            .map_err(PeerSessionError::ErrorGettingBitfield)?;
+        if !bitfield.has_piece(index) {
+            return Ok(None);
+        }
 
-        let hash = Sha1::digest(piece);
-        let res_piece_hash = self.convert_to_hex_string(hash.as_slice());
+        let piece = self
+            .torrent_status
+            .get_piece(
+                index,
+                self.torrent.piece_offset(index),
+                self.torrent.piece_size(index) as usize,
+            )
+            .map_err(PeerSessionError::ErrorGettingPiece)?;
+        Ok(block_hashes.answer(request, &piece))
+    }
+}
 
-        if real_piece_hash == res_piece_hash {
-            Ok(())
-        } else {
-            Err(PeerSessionError::PieceHashDoesNotMatch)
-        }
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+    use crate::{
+        storage_manager::memory_storage::MemoryStorage,
+        test_support::{
+            fixtures::test_config,
+            local_swarm::{
+                add_pieces, generated_hybrid_torrent, generated_torrent, LEECHER_PEER_ID,
+                SEEDER_PEER_ID,
+            },
+            temp_dir::TempDir,
+        },
+    };
+
+    fn status_with_pieces(
+        torrent: &Torrent,
+        data: &[u8],
+        config: &Cfg,
+        pieces: Bitfield,
+    ) -> Arc<AtomicTorrentStatus> {
+        let status = AtomicTorrentStatus::with_storage(
+            torrent,
+            config.clone(),
+            Box::new(MemoryStorage::new()),
+        );
+        add_pieces(&status, torrent, data, &pieces);
+        Arc::new(status)
     }
 
-    /// Converts a byte array to a hex string.
-    fn convert_to_hex_string(&self, bytes: &[u8]) -> String {
-        let mut res = String::with_capacity(bytes.len() * 2);
-        for b in bytes {
-            match write!(&mut res, "{:02x}", b) {
-                Ok(()) => (),
-                Err(_) => warn!("Error converting bytes to hex string!"),
-            }
+    /// Connects two sessions that have half of the pieces each and runs them until both are finished.
+    fn exchange_half_of_the_pieces(config: Cfg, outgoing_config: Cfg) {
+        let (torrent, data) =
+            generated_torrent("exchange", 8 * 16384, 16384, "http://127.0.0.1:1/announce");
+        exchange_half_of_the_pieces_of(torrent, data, config, outgoing_config);
+    }
+
+    /// Like `exchange_half_of_the_pieces` with a torrent of 8 pieces.
+    fn exchange_half_of_the_pieces_of(
+        torrent: Torrent,
+        data: Vec<u8>,
+        config: Cfg,
+        outgoing_config: Cfg,
+    ) {
+        let outgoing_status = status_with_pieces(
+            &torrent,
+            &data,
+            &outgoing_config,
+            Bitfield::new(vec![0b1111_0000]),
+        );
+        let incoming_status =
+            status_with_pieces(&torrent, &data, &config, Bitfield::new(vec![0b0000_1111]));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let incoming = {
+            let (torrent, config, status) =
+                (torrent.clone(), config.clone(), incoming_status.clone());
+            thread::spawn(move || {
+                let (mut stream, addr) = listener.accept().unwrap();
+                let mut peer = BtPeer::new(addr.ip().to_string(), addr.port() as i64);
+                peer.receive_handshake(&mut stream).unwrap();
+
+                status.peer_connecting();
+                let mut session =
+                    PeerSession::new(peer, torrent, status, config, SEEDER_PEER_ID.to_string())
+                        .unwrap();
+                session.handshake_incoming(&mut stream).unwrap();
+                session.run_incoming(&mut stream)
+            })
+        };
+
+        outgoing_status.peer_connecting();
+        let mut session = PeerSession::new(
+            BtPeer::new("127.0.0.1".to_string(), port as i64),
+            torrent,
+            outgoing_status.clone(),
+            outgoing_config,
+            LEECHER_PEER_ID.to_string(),
+        )
+        .unwrap();
+
+        assert!(session.start_outgoing().is_ok());
+        assert!(incoming.join().unwrap().is_ok());
+        assert!(outgoing_status.is_finished());
+        assert!(incoming_status.is_finished());
+    }
+
+    #[test]
+    fn test_peers_with_half_of_the_pieces_exchange_them() {
+        let dir = TempDir::new("peer_session_exchange");
+        let config = test_config(&dir);
+
+        exchange_half_of_the_pieces(config.clone(), config);
+    }
+
+    #[test]
+    fn test_peers_exchange_the_pieces_of_a_hybrid_torrent() {
+        let dir = TempDir::new("peer_session_hybrid");
+        let config = test_config(&dir);
+        let (torrent, data) = generated_hybrid_torrent(
+            "hybrid",
+            8 * 32768 - 100,
+            32768,
+            "http://127.0.0.1:1/announce",
+        );
+
+        exchange_half_of_the_pieces_of(torrent, data, config.clone(), config);
+    }
+
+    #[test]
+    fn test_peer_requesting_smaller_blocks_is_served() {
+        let dir = TempDir::new("peer_session_smaller_blocks");
+        let config = test_config(&dir);
+        // Blocks that do not divide the piece size, so the last block of every piece is shorter.
+        let outgoing_config = Cfg {
+            block_size: 5000,
+            ..config.clone()
+        };
+
+        exchange_half_of_the_pieces(config, outgoing_config);
+    }
+
+    #[test]
+    fn test_request_of_a_piece_we_do_not_have_is_ignored() {
+        let dir = TempDir::new("peer_session_request_missing_piece");
+        let config = test_config(&dir);
+        let (torrent, data) =
+            generated_torrent("missing", 8 * 16384, 16384, "http://127.0.0.1:1/announce");
+        let status = status_with_pieces(&torrent, &data, &config, Bitfield::new(vec![0b1000_0000]));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut peer_stream, _) = listener.accept().unwrap();
+        let mut session = PeerSession::new(
+            BtPeer::new("127.0.0.1".to_string(), 6881),
+            torrent,
+            status,
+            config,
+            SEEDER_PEER_ID.to_string(),
+        )
+        .unwrap();
+        session.status.peer_choked = false;
+
+        let missing = Message::new(MessageId::Request, Request::new(1, 0, 16384).as_bytes());
+        assert!(session.handle_request(missing, &mut stream).is_ok());
+
+        let owned = Message::new(MessageId::Request, Request::new(0, 0, 16384).as_bytes());
+        assert!(session.handle_request(owned, &mut stream).is_ok());
+        let mut header = [0; 5];
+        peer_stream.read_exact(&mut header).unwrap();
+        assert_eq!(header[4], MessageId::Piece as u8);
+        let mut index = [0; 4];
+        peer_stream.read_exact(&mut index).unwrap();
+        assert_eq!(u32::from_be_bytes(index), 0);
+    }
+
+    #[test]
+    fn test_cancelled_session_stops_before_the_read_timeout() {
+        let dir = TempDir::new("peer_session_cancelled");
+        let config = Cfg {
+            read_write_seconds_timeout: 60,
+            ..test_config(&dir)
+        };
+        let (torrent, data) =
+            generated_torrent("cancelled", 8 * 16384, 16384, "http://127.0.0.1:1/announce");
+        let seeder_status = status_with_pieces(&torrent, &data, &config, Bitfield::new(vec![0xff]));
+        let leecher_status = status_with_pieces(&torrent, &data, &config, Bitfield::new(vec![0]));
+
+        // The seeder answers the handshake and then never sends anything else.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (torrent_clone, config_clone) = (torrent.clone(), config.clone());
+        thread::spawn(move || {
+            let (mut stream, addr) = listener.accept().unwrap();
+            let mut peer = BtPeer::new(addr.ip().to_string(), addr.port() as i64);
+            peer.receive_handshake(&mut stream).unwrap();
+            let mut session = PeerSession::new(
+                peer,
+                torrent_clone,
+                seeder_status,
+                config_clone,
+                SEEDER_PEER_ID.to_string(),
+            )
+            .unwrap();
+            session.handshake_incoming(&mut stream).unwrap();
+            thread::sleep(Duration::from_secs(30));
+        });
+
+        {
+            let status = leecher_status.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(300));
+                status.cancel_sessions().unwrap();
+            });
         }
-        res
+        leecher_status.peer_connecting();
+        let mut session = PeerSession::new(
+            BtPeer::new("127.0.0.1".to_string(), port as i64),
+            torrent,
+            leecher_status.clone(),
+            config,
+            LEECHER_PEER_ID.to_string(),
+        )
+        .unwrap();
+        let started = Instant::now();
+
+        assert!(matches!(
+            session.start_outgoing(),
+            Err(PeerSessionError::Cancelled)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(leecher_status.current_peers(), 0);
     }
 }
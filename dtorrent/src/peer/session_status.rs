@@ -14,6 +14,12 @@ pub struct SessionStatus {
     pub bitfield: Bitfield,
     pub download_speed: f64,
     pub upload_speed: f64,
+    /// Number of pieces received from the peer that failed the hash check
+    pub corrupted_pieces: u32,
+    /// The peer did not send the blocks we requested in time
+    pub snubbed: bool,
+    /// Number of pieces the peer announced it has
+    pub peer_pieces: u32,
 }
 
 impl SessionStatus {
@@ -26,6 +32,9 @@ impl SessionStatus {
             bitfield,
             download_speed: 0.0,
             upload_speed: 0.0,
+            corrupted_pieces: 0,
+            snubbed: false,
+            peer_pieces: 0,
         }
     }
 }
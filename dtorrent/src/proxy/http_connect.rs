@@ -0,0 +1,150 @@
+use std::io::{Read, Write};
+
+use super::ProxyError;
+
+/// Max length of the response headers of the proxy.
+const MAX_RESPONSE_LENGTH: usize = 8192;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Asks the HTTP proxy at the other end of `stream` to open a tunnel to `host:port` with a `CONNECT` request.
+///
+/// Basic authentication is used if `credentials` are given.
+///
+/// # Errors
+/// - `Io` if there was a problem talking to the proxy.
+/// - `AuthenticationRequired` if the proxy answered 407.
+/// - `InvalidResponse` if the proxy sent an unexpected response.
+/// - `ConnectionRefused` if the proxy answered with any other error status.
+pub fn connect<S: Read + Write>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    credentials: &Option<(String, String)>,
+) -> Result<(), ProxyError> {
+    stream
+        .write_all(connect_request(host, port, credentials).as_bytes())
+        .map_err(ProxyError::Io)?;
+
+    let response = read_response_headers(stream)?;
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        Some("407") => Err(ProxyError::AuthenticationRequired),
+        Some(_) => Err(ProxyError::ConnectionRefused(status_line.to_string())),
+        None => Err(ProxyError::InvalidResponse),
+    }
+}
+
+fn connect_request(host: &str, port: u16, credentials: &Option<(String, String)>) -> String {
+    let mut request = format!(
+        "CONNECT {}:{} HTTP/1.1\r\nHost: {}:{}\r\n",
+        host, port, host, port
+    );
+    if let Some((username, password)) = credentials {
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            base64_encode(format!("{}:{}", username, password).as_bytes())
+        ));
+    }
+    request.push_str("\r\n");
+    request
+}
+
+/// Reads the response headers one byte at a time, so nothing sent by the remote host after them is consumed.
+fn read_response_headers<S: Read>(stream: &mut S) -> Result<String, ProxyError> {
+    let mut response = vec![];
+    let mut byte = [0; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_RESPONSE_LENGTH {
+            return Err(ProxyError::InvalidResponse);
+        }
+        stream.read_exact(&mut byte).map_err(ProxyError::Io)?;
+        response.push(byte[0]);
+    }
+    String::from_utf8(response).map_err(|_| ProxyError::InvalidResponse)
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b"user:pass"), "dXNlcjpwYXNz");
+        assert_eq!(base64_encode(b"ab"), "YWI=");
+        assert_eq!(base64_encode(b"a"), "YQ==");
+    }
+
+    #[test]
+    fn test_connect_request_with_credentials() {
+        let credentials = Some(("user".to_string(), "pass".to_string()));
+
+        assert_eq!(
+            connect_request("tracker.example.com", 80, &credentials),
+            "CONNECT tracker.example.com:80 HTTP/1.1\r\nHost: tracker.example.com:80\r\nProxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_read_response_headers_leaves_the_rest_of_the_stream() {
+        let mut stream =
+            Cursor::new(b"HTTP/1.1 200 Connection established\r\n\r\nd8:complete".to_vec());
+
+        read_response_headers(&mut stream).unwrap();
+
+        let mut rest = String::new();
+        stream.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "d8:complete");
+    }
+
+    #[test]
+    fn test_proxy_authentication_required() {
+        let mut stream = MockStream(Cursor::new(
+            b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n".to_vec(),
+        ));
+
+        assert!(matches!(
+            connect(&mut stream, "tracker.example.com", 80, &None),
+            Err(ProxyError::AuthenticationRequired)
+        ));
+    }
+
+    struct MockStream(Cursor<Vec<u8>>);
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
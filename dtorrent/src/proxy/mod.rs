@@ -0,0 +1,77 @@
+pub mod http_connect;
+pub mod socks5;
+
+use std::io;
+use std::net::TcpStream;
+use std::str::FromStr;
+
+/// Kind of proxy to connect through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyType {
+    None,
+    Socks5,
+    Http,
+}
+
+impl FromStr for ProxyType {
+    type Err = ProxyError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "none" => Ok(ProxyType::None),
+            "socks5" => Ok(ProxyType::Socks5),
+            "http" => Ok(ProxyType::Http),
+            _ => Err(ProxyError::UnknownProxyType(value.to_string())),
+        }
+    }
+}
+
+/// A SOCKS5 or HTTP proxy to open TCP connections through.
+///
+/// To create a new `Proxy` use `Cfg::proxy()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proxy {
+    pub proxy_type: ProxyType,
+    pub host: String,
+    pub port: u16,
+    /// Credentials to authenticate with the proxy, if any.
+    pub credentials: Option<(String, String)>,
+}
+
+/// Posible proxy errors.
+#[derive(Debug)]
+pub enum ProxyError {
+    Io(io::Error),
+    UnknownProxyType(String),
+    HostTooLong,
+    AuthenticationRequired,
+    AuthenticationFailed,
+    InvalidResponse,
+    ConnectionRefused(String),
+}
+
+impl Proxy {
+    /// Opens a TCP connection to `host:port` through the proxy.
+    ///
+    /// # Errors
+    /// - `Io` if the proxy could not be reached.
+    /// - `HostTooLong` if the host name can not be sent to a SOCKS5 proxy.
+    /// - `AuthenticationRequired` if the proxy requires credentials that were not configured.
+    /// - `AuthenticationFailed` if the proxy rejected the credentials.
+    /// - `InvalidResponse` if the proxy sent an unexpected response.
+    /// - `ConnectionRefused` if the proxy could not connect to `host:port`.
+    pub fn connect(&self, host: &str, port: u16) -> Result<TcpStream, ProxyError> {
+        if self.proxy_type == ProxyType::None {
+            return TcpStream::connect((host, port)).map_err(ProxyError::Io);
+        }
+
+        let mut stream =
+            TcpStream::connect((self.host.as_str(), self.port)).map_err(ProxyError::Io)?;
+        match self.proxy_type {
+            ProxyType::Socks5 => socks5::connect(&mut stream, host, port, &self.credentials)?,
+            ProxyType::Http => http_connect::connect(&mut stream, host, port, &self.credentials)?,
+            ProxyType::None => (),
+        }
+        Ok(stream)
+    }
+}
@@ -0,0 +1,225 @@
+use std::io::{Read, Write};
+use std::net::Ipv4Addr;
+
+use super::ProxyError;
+
+const VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const USERNAME_PASSWORD: u8 = 2;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const USERNAME_PASSWORD_VERSION: u8 = 1;
+const CONNECT: u8 = 1;
+const ADDRESS_IPV4: u8 = 1;
+const ADDRESS_DOMAIN: u8 = 3;
+const ADDRESS_IPV6: u8 = 4;
+const SUCCEEDED: u8 = 0;
+
+/// Asks the SOCKS5 proxy at the other end of `stream` to connect to `host:port` (RFC 1928).
+///
+/// Username and password authentication (RFC 1929) is offered if `credentials` are given.
+///
+/// # Errors
+/// - `Io` if there was a problem talking to the proxy.
+/// - `HostTooLong` if the host name is longer than 255 bytes.
+/// - `AuthenticationRequired` if the proxy does not accept any of the offered methods.
+/// - `AuthenticationFailed` if the proxy rejected the credentials.
+/// - `InvalidResponse` if the proxy sent an unexpected response.
+/// - `ConnectionRefused` if the proxy could not connect to `host:port`.
+pub fn connect<S: Read + Write>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    credentials: &Option<(String, String)>,
+) -> Result<(), ProxyError> {
+    let methods = match credentials {
+        Some(_) => vec![NO_AUTHENTICATION, USERNAME_PASSWORD],
+        None => vec![NO_AUTHENTICATION],
+    };
+    let mut greeting = vec![VERSION, methods.len() as u8];
+    greeting.extend(methods);
+    stream.write_all(&greeting).map_err(ProxyError::Io)?;
+
+    let mut method = [0; 2];
+    stream.read_exact(&mut method).map_err(ProxyError::Io)?;
+    match (method, credentials) {
+        ([VERSION, NO_AUTHENTICATION], _) => (),
+        ([VERSION, USERNAME_PASSWORD], Some((username, password))) => {
+            authenticate(stream, username, password)?
+        }
+        ([VERSION, NO_ACCEPTABLE_METHODS], _) => return Err(ProxyError::AuthenticationRequired),
+        _ => return Err(ProxyError::InvalidResponse),
+    }
+
+    stream
+        .write_all(&connect_request(host, port)?)
+        .map_err(ProxyError::Io)?;
+    read_connect_reply(stream)
+}
+
+fn authenticate<S: Read + Write>(
+    stream: &mut S,
+    username: &str,
+    password: &str,
+) -> Result<(), ProxyError> {
+    if username.len() > u8::MAX as usize || password.len() > u8::MAX as usize {
+        return Err(ProxyError::AuthenticationFailed);
+    }
+    let mut request = vec![USERNAME_PASSWORD_VERSION, username.len() as u8];
+    request.extend(username.as_bytes());
+    request.push(password.len() as u8);
+    request.extend(password.as_bytes());
+    stream.write_all(&request).map_err(ProxyError::Io)?;
+
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).map_err(ProxyError::Io)?;
+    match reply {
+        [USERNAME_PASSWORD_VERSION, SUCCEEDED] => Ok(()),
+        _ => Err(ProxyError::AuthenticationFailed),
+    }
+}
+
+fn connect_request(host: &str, port: u16) -> Result<Vec<u8>, ProxyError> {
+    let mut request = vec![VERSION, CONNECT, 0];
+    match host.parse::<Ipv4Addr>() {
+        Ok(ip) => {
+            request.push(ADDRESS_IPV4);
+            request.extend(ip.octets());
+        }
+        Err(_) => {
+            if host.len() > u8::MAX as usize {
+                return Err(ProxyError::HostTooLong);
+            }
+            request.push(ADDRESS_DOMAIN);
+            request.push(host.len() as u8);
+            request.extend(host.as_bytes());
+        }
+    }
+    request.extend(port.to_be_bytes());
+    Ok(request)
+}
+
+fn read_connect_reply<S: Read>(stream: &mut S) -> Result<(), ProxyError> {
+    let mut header = [0; 4];
+    stream.read_exact(&mut header).map_err(ProxyError::Io)?;
+    if header[0] != VERSION {
+        return Err(ProxyError::InvalidResponse);
+    }
+    if header[1] != SUCCEEDED {
+        return Err(ProxyError::ConnectionRefused(format!(
+            "SOCKS5 reply code {}",
+            header[1]
+        )));
+    }
+
+    // The bound address is not used, but it has to be consumed from the stream.
+    let address_length = match header[3] {
+        ADDRESS_IPV4 => 4,
+        ADDRESS_IPV6 => 16,
+        ADDRESS_DOMAIN => {
+            let mut length = [0; 1];
+            stream.read_exact(&mut length).map_err(ProxyError::Io)?;
+            length[0] as usize
+        }
+        _ => return Err(ProxyError::InvalidResponse),
+    };
+    let mut bound_address = vec![0; address_length + 2];
+    stream
+        .read_exact(&mut bound_address)
+        .map_err(ProxyError::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// A stream that returns the scripted proxy replies and records what is written to it.
+    struct MockProxy {
+        replies: Cursor<Vec<u8>>,
+        written: Vec<u8>,
+    }
+
+    impl MockProxy {
+        fn new(replies: Vec<u8>) -> Self {
+            Self {
+                replies: Cursor::new(replies),
+                written: vec![],
+            }
+        }
+    }
+
+    impl Read for MockProxy {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for MockProxy {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_connect_without_authentication() {
+        let mut proxy = MockProxy::new(vec![5, 0, 5, 0, 0, 1, 10, 0, 0, 1, 0x1a, 0xe1]);
+
+        connect(&mut proxy, "tracker.example.com", 80, &None).unwrap();
+
+        let mut expected = vec![5, 1, 0, 5, 1, 0, 3, 19];
+        expected.extend(b"tracker.example.com");
+        expected.extend([0, 80]);
+        assert_eq!(proxy.written, expected);
+    }
+
+    #[test]
+    fn test_connect_to_ipv4_with_authentication() {
+        let mut proxy = MockProxy::new(vec![5, 2, 1, 0, 5, 0, 0, 1, 10, 0, 0, 1, 0x1a, 0xe1]);
+        let credentials = Some(("user".to_string(), "pass".to_string()));
+
+        connect(&mut proxy, "192.168.0.2", 6881, &credentials).unwrap();
+
+        let mut expected = vec![5, 2, 0, 2, 1, 4];
+        expected.extend(b"user");
+        expected.push(4);
+        expected.extend(b"pass");
+        expected.extend([5, 1, 0, 1, 192, 168, 0, 2, 0x1a, 0xe1]);
+        assert_eq!(proxy.written, expected);
+    }
+
+    #[test]
+    fn test_authentication_failed() {
+        let mut proxy = MockProxy::new(vec![5, 2, 1, 1]);
+        let credentials = Some(("user".to_string(), "wrong".to_string()));
+
+        assert!(matches!(
+            connect(&mut proxy, "192.168.0.2", 6881, &credentials),
+            Err(ProxyError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn test_authentication_required() {
+        let mut proxy = MockProxy::new(vec![5, 0xff]);
+
+        assert!(matches!(
+            connect(&mut proxy, "192.168.0.2", 6881, &None),
+            Err(ProxyError::AuthenticationRequired)
+        ));
+    }
+
+    #[test]
+    fn test_connection_refused() {
+        let mut proxy = MockProxy::new(vec![5, 0, 5, 5, 0, 1, 0, 0, 0, 0, 0, 0]);
+
+        assert!(matches!(
+            connect(&mut proxy, "192.168.0.2", 6881, &None),
+            Err(ProxyError::ConnectionRefused(_))
+        ));
+    }
+}
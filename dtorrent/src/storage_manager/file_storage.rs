@@ -0,0 +1,210 @@
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    path::PathBuf,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use super::{
+    layout,
+    manager::{retrieve_block, save_piece},
+    output_file::OutputFile,
+    storage::Storage,
+};
+use crate::{config::cfg::Cfg, torrent_parser::torrent::Torrent};
+
+/// `Storage` that keeps the data of a torrent in a file.
+///
+/// The file is placed by the `download_layout` of the config, written to a `.part` file with `part_files`
+/// and moved into the `completed_directory` when the download completes, see `OutputFile`. With
+/// `copy_completed` it is copied there instead, and the torrent keeps being seeded from the original.
+///
+/// Pieces are written and read in parallel, only moving the file waits for them to finish.
+///
+/// To create a new `FileStorage` use `FileStorage::new`.
+#[derive(Debug)]
+pub struct FileStorage {
+    file: RwLock<OutputFile>,
+    completed_directory: Option<PathBuf>,
+    copy_completed: bool,
+}
+
+impl FileStorage {
+    /// Creates a new `FileStorage` for the torrent with the given config.
+    pub fn new(torrent: &Torrent, config: &Cfg) -> Self {
+        let completed_directory = Some(PathBuf::from(&config.completed_directory))
+            .filter(|directory| !directory.as_os_str().is_empty());
+        Self {
+            file: RwLock::new(OutputFile::new(
+                layout::output_path(config, torrent),
+                config.part_files,
+            )),
+            completed_directory,
+            copy_completed: config.copy_completed,
+        }
+    }
+
+    fn read_file(&self) -> io::Result<RwLockReadGuard<'_, OutputFile>> {
+        self.file
+            .read()
+            .map_err(|_| io::Error::other("Poisoned output file lock"))
+    }
+
+    fn write_file(&self) -> io::Result<RwLockWriteGuard<'_, OutputFile>> {
+        self.file
+            .write()
+            .map_err(|_| io::Error::other("Poisoned output file lock"))
+    }
+}
+
+impl Storage for FileStorage {
+    fn write_piece(&self, offset: u64, piece: &[u8]) -> io::Result<()> {
+        save_piece(&self.read_file()?.current_path(), piece, offset)
+    }
+
+    fn read_block(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        retrieve_block(&self.read_file()?.current_path(), offset, length)
+    }
+
+    /// Creates the file with the given length, the space is only used as it is written on most file systems.
+    fn allocate(&self, length: u64) -> io::Result<()> {
+        let path = self.read_file()?.current_path();
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if file.metadata()?.len() < length {
+            file.set_len(length)?;
+        }
+        Ok(())
+    }
+
+    fn complete(&self) -> io::Result<()> {
+        let mut file = self.write_file()?;
+        if !self.copy_completed {
+            return file.complete(self.completed_directory.as_deref());
+        }
+        if file.is_completed() {
+            return Ok(());
+        }
+
+        file.complete(None)?;
+        if let (Some(directory), Some(file_name)) =
+            (&self.completed_directory, file.path().file_name())
+        {
+            fs::create_dir_all(directory)?;
+            fs::copy(file.path(), directory.join(file_name))?;
+        }
+        Ok(())
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        self.read_file().ok().map(|file| file.path().to_path_buf())
+    }
+
+    fn set_path(&self, path: PathBuf) -> io::Result<()> {
+        self.write_file()?.set_path(path)
+    }
+
+    /// Deletes the file the torrent is seeded from, a copy in the `completed_directory` is kept.
+    fn delete(&self) -> io::Result<()> {
+        self.write_file()?.delete()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use sha1::Digest;
+
+    use super::*;
+    use crate::{
+        test_support::{fixtures::test_torrent, temp_dir::TempDir},
+        torrent_parser::info::Info,
+    };
+    use url_encoder::info_hash::InfoHash;
+
+    #[test]
+    fn test_allocate_and_verify() {
+        let dir = "./test_file_storage_allocate_and_verify";
+        let torrent = Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![],
+            http_seeds: vec![],
+            info_hash_v2: None,
+            piece_layer: vec![],
+            info: Info {
+                length: 8,
+                name: "data".to_string(),
+                piece_length: 4,
+                pieces: vec![],
+                private: None,
+                meta_version: None,
+                file_tree: Default::default(),
+            },
+            info_hash: InfoHash::new([0; 20]),
+        };
+        let config = Cfg {
+            download_directory: dir.to_string(),
+            ..Cfg::default()
+        };
+        let storage = FileStorage::new(&torrent, &config);
+
+        storage.allocate(8).unwrap();
+        storage.write_piece(4, b"abcd").unwrap();
+
+        assert_eq!(fs::metadata(Path::new(dir).join("data")).unwrap().len(), 8);
+        assert_eq!(storage.read_block(0, 8).unwrap(), b"\0\0\0\0abcd");
+        let hash = sha1::Sha1::digest(b"abcd");
+        assert!(storage.verify(4, 4, hash.as_slice()).unwrap());
+        assert!(!storage.verify(0, 4, hash.as_slice()).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_complete_copies_to_completed_directory() {
+        let dir = TempDir::new("test_file_storage_copy_completed");
+        let torrent = test_torrent("data", "announce");
+        let config = Cfg {
+            download_directory: dir.join("downloads").display().to_string(),
+            completed_directory: dir.join("completed").display().to_string(),
+            copy_completed: true,
+            ..Cfg::default()
+        };
+        let storage = FileStorage::new(&torrent, &config);
+
+        storage.allocate(10).unwrap();
+        storage.write_piece(0, b"0123456789").unwrap();
+        storage.complete().unwrap();
+
+        assert_eq!(storage.path(), Some(dir.join("downloads").join("data")));
+        assert_eq!(fs::read(dir.join("downloads/data")).unwrap(), b"0123456789");
+        assert_eq!(fs::read(dir.join("completed/data")).unwrap(), b"0123456789");
+        assert_eq!(storage.read_block(0, 4).unwrap(), b"0123");
+    }
+
+    #[test]
+    fn test_delete_removes_the_part_file() {
+        let dir = TempDir::new("test_file_storage_delete");
+        let torrent = test_torrent("data", "announce");
+        let config = Cfg {
+            download_directory: dir.join("downloads").display().to_string(),
+            part_files: true,
+            ..Cfg::default()
+        };
+        let storage = FileStorage::new(&torrent, &config);
+        storage.allocate(10).unwrap();
+        assert!(dir.join("downloads/data.part").exists());
+
+        storage.delete().unwrap();
+
+        assert!(!dir.join("downloads/data.part").exists());
+        // Deleting a file that is not there does nothing.
+        storage.delete().unwrap();
+    }
+}
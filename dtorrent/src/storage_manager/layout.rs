@@ -0,0 +1,172 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{config::cfg::Cfg, torrent_parser::torrent::Torrent};
+
+/// Number of characters of the info hash added to the folder name with the `NameAndHash` layout.
+const SHORT_HASH_LENGTH: usize = 8;
+
+/// Layout of the downloaded files inside the `download_directory`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadLayout {
+    /// Every torrent is saved directly in the `download_directory` with its name.
+    Flat,
+    /// Every torrent is saved in a folder named after its info hash.
+    InfoHash,
+    /// Every torrent is saved in a folder named after its name and the start of its info hash.
+    NameAndHash,
+}
+
+/// Posible `DownloadLayout` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DownloadLayoutError {
+    UnknownDownloadLayout(String),
+}
+
+impl DownloadLayout {
+    /// Returns the path of the downloaded file of the torrent, relative to the `download_directory`.
+    pub fn file_path(&self, torrent: &Torrent) -> PathBuf {
+        let name = torrent.name();
+        let info_hash = torrent.info_hash().to_hex();
+        match self {
+            DownloadLayout::Flat => PathBuf::from(name),
+            DownloadLayout::InfoHash => Path::new(&info_hash).join(name),
+            DownloadLayout::NameAndHash => {
+                Path::new(&format!("{}-{}", name, &info_hash[..SHORT_HASH_LENGTH])).join(name)
+            }
+        }
+    }
+}
+
+impl FromStr for DownloadLayout {
+    type Err = DownloadLayoutError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "flat" => Ok(DownloadLayout::Flat),
+            "info_hash" => Ok(DownloadLayout::InfoHash),
+            "name_and_hash" => Ok(DownloadLayout::NameAndHash),
+            _ => Err(DownloadLayoutError::UnknownDownloadLayout(
+                value.to_string(),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DownloadLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let layout = match self {
+            DownloadLayout::Flat => "flat",
+            DownloadLayout::InfoHash => "info_hash",
+            DownloadLayout::NameAndHash => "name_and_hash",
+        };
+        write!(f, "{}", layout)
+    }
+}
+
+/// Returns the path where the downloaded file of the torrent is saved.
+///
+/// It is the `output_path` overridden for the torrent if there is one, relative to the `download_directory`
+/// unless it is absolute, or the path given by the `download_layout` of the config.
+pub fn output_path(config: &Cfg, torrent: &Torrent) -> PathBuf {
+    let download_directory = Path::new(&config.download_directory);
+    match &config.overrides.output_path {
+        Some(output_path) => download_directory.join(output_path),
+        None => download_directory.join(config.download_layout.file_path(torrent)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url_encoder::info_hash::InfoHash;
+
+    use super::*;
+    use crate::{config::torrent_overrides::TorrentOverrides, torrent_parser::info::Info};
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("FLAT".parse(), Ok(DownloadLayout::Flat));
+        assert_eq!("name_and_hash".parse(), Ok(DownloadLayout::NameAndHash));
+        assert_eq!(
+            "nested".parse::<DownloadLayout>(),
+            Err(DownloadLayoutError::UnknownDownloadLayout(
+                "nested".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_output_path_with_layouts() {
+        let torrent = create_test_torrent();
+        let info_hash = torrent.info_hash().to_hex();
+        let mut config = Cfg {
+            download_directory: "./downloads".to_string(),
+            ..Cfg::default()
+        };
+
+        assert_eq!(
+            output_path(&config, &torrent),
+            Path::new("./downloads/movie.mp4")
+        );
+
+        config.download_layout = DownloadLayout::InfoHash;
+        assert_eq!(
+            output_path(&config, &torrent),
+            Path::new("./downloads").join(&info_hash).join("movie.mp4")
+        );
+
+        config.download_layout = DownloadLayout::NameAndHash;
+        assert_eq!(
+            output_path(&config, &torrent),
+            Path::new("./downloads")
+                .join(format!("movie.mp4-{}", &info_hash[..8]))
+                .join("movie.mp4")
+        );
+    }
+
+    #[test]
+    fn test_output_path_override() {
+        let torrent = create_test_torrent();
+        let mut config = Cfg {
+            download_directory: "./downloads".to_string(),
+            ..Cfg::default()
+        };
+        TorrentOverrides {
+            output_path: Some("movies/film.mp4".to_string()),
+            ..TorrentOverrides::default()
+        }
+        .apply(&mut config);
+
+        assert_eq!(
+            output_path(&config, &torrent),
+            Path::new("./downloads/movies/film.mp4")
+        );
+
+        config.overrides.output_path = Some("/tmp/film.mp4".to_string());
+        assert_eq!(output_path(&config, &torrent), Path::new("/tmp/film.mp4"));
+    }
+
+    fn create_test_torrent() -> Torrent {
+        let info = Info {
+            name: "movie.mp4".to_string(),
+            length: 10,
+            piece_length: 10,
+            pieces: vec![0; 20],
+            private: None,
+            meta_version: None,
+            file_tree: Default::default(),
+        };
+        Torrent {
+            announce_url: "http://tracker".to_string(),
+            announce_list: vec![],
+            http_seeds: vec![],
+            info_hash_v2: None,
+            piece_layer: vec![],
+            info,
+            info_hash: InfoHash::new([0xab; 20]),
+        }
+    }
+}
@@ -1,45 +1,86 @@
-use crate::config::cfg::Cfg;
 use std::fs::{self, File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
+#[cfg(windows)]
+use std::io;
 use std::path::Path;
 
+/// Writes at an offset of a file without moving a shared cursor, so several threads can write to it at once.
 trait WriteWithOffset {
-    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> Result<(), std::io::Error>;
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> Result<(), std::io::Error>;
 }
 
+/// Reads at an offset of a file without moving a shared cursor, so several threads can read from it at once.
+trait ReadWithOffset {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<(), std::io::Error>;
+}
+
+#[cfg(unix)]
 impl WriteWithOffset for File {
-    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> Result<(), std::io::Error> {
-        self.seek(SeekFrom::Start(offset))?;
-        self.write_all(buf)
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> Result<(), std::io::Error> {
+        std::os::unix::fs::FileExt::write_all_at(self, buf, offset)
     }
 }
 
-trait ReadWithOffset {
-    fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> Result<(), std::io::Error>;
+#[cfg(unix)]
+impl ReadWithOffset for File {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<(), std::io::Error> {
+        std::os::unix::fs::FileExt::read_exact_at(self, buf, offset)
+    }
 }
 
+#[cfg(windows)]
+impl WriteWithOffset for File {
+    fn write_all_at(&self, mut buf: &[u8], mut offset: u64) -> Result<(), std::io::Error> {
+        while !buf.is_empty() {
+            match std::os::windows::fs::FileExt::seek_write(self, buf, offset) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(written) => {
+                    buf = &buf[written..];
+                    offset += written as u64;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
 impl ReadWithOffset for File {
-    fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> Result<(), std::io::Error> {
-        self.seek(SeekFrom::Start(offset))?;
-        self.read_exact(buf)
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> Result<(), std::io::Error> {
+        while !buf.is_empty() {
+            match std::os::windows::fs::FileExt::seek_read(self, buf, offset) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                Ok(read) => {
+                    let remaining = buf;
+                    buf = &mut remaining[read..];
+                    offset += read as u64;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
     }
 }
 
-pub fn save_piece(
-    name: String,
-    piece: &[u8],
-    piece_offset: u64,
-    config: Cfg,
-) -> Result<(), std::io::Error> {
-    let save_directory = config.download_directory;
-    if !Path::new(&save_directory).exists() {
-        fs::create_dir_all(save_directory.clone())?;
+/// Writes a piece to a file at a given offset, creating the file and its directory if needed.
+///
+/// # Arguments
+/// * `path` - the path of the file to write the piece to, see `layout::output_path`.
+/// * `piece` - the bytes of the piece.
+/// * `piece_offset` - integer specifying the offset in bytes from the start of the file
+pub fn save_piece(path: &Path, piece: &[u8], piece_offset: u64) -> Result<(), std::io::Error> {
+    if let Some(save_directory) = path.parent() {
+        if !save_directory.exists() {
+            fs::create_dir_all(save_directory)?;
+        }
     }
-    let mut file = OpenOptions::new()
+    let file = OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
-        .open(save_directory + "/" + &name)?;
+        .open(path)?;
 
     file.write_all_at(piece, piece_offset)?;
 
@@ -49,21 +90,11 @@ pub fn save_piece(
 /// Retrieves a block of data from a file at a given offset.
 ///
 /// # Arguments
-/// * `filename` - the name of the file to retrieve the data from.
+/// * `path` - the path of the file to retrieve the data from.
 /// * `offset` - integer specifying the offset in bytes from the start of the file
 /// * `length` - integer specifying the requested length
-/// * `config` - the configuration of the application
-pub fn retrieve_block(
-    filename: String,
-    offset: u64,
-    length: usize,
-    config: Cfg,
-) -> Result<Vec<u8>, std::io::Error> {
-    let file_directory = config.download_directory;
-
-    let mut file = OpenOptions::new()
-        .read(true)
-        .open(file_directory + "/" + &filename)?;
+pub fn retrieve_block(path: &Path, offset: u64, length: usize) -> Result<Vec<u8>, std::io::Error> {
+    let file = OpenOptions::new().read(true).open(path)?;
 
     let mut buffer = vec![0; length];
     file.read_exact_at(&mut buffer, offset)?;
@@ -76,14 +107,17 @@ mod tests {
     use std::fs::File;
     use std::io::Write;
     use std::path::Path;
+    use std::sync::Arc;
+    use std::thread;
 
     use super::*;
-
-    const CONFIG_PATH: &str = "config.cfg";
+    use crate::config::cfg::Cfg;
+    use crate::test_support::{fixtures::test_config, temp_dir::TempDir};
 
     #[test]
     fn retrieve_block_with_offset_zero() {
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let dir = TempDir::new("retrieve_block_with_offset_zero");
+        let config = test_config(&dir);
 
         let filename = "test_retrieve_block_01.txt";
         let filepath = format!("{}/{}", config.download_directory, filename);
@@ -93,7 +127,7 @@ mod tests {
         let offset = 0;
         let length = 5;
 
-        let block = retrieve_block(String::from(filename), offset, length, config)
+        let block = retrieve_block(Path::new(&filepath), offset, length)
             .map_err(|err| {
                 fs::remove_file(&filepath).unwrap();
                 err
@@ -108,7 +142,8 @@ mod tests {
 
     #[test]
     fn retrieve_block_with_offset_in_the_middle() {
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let dir = TempDir::new("retrieve_block_with_offset_in_the_middle");
+        let config = test_config(&dir);
 
         let filename = "test_retrieve_block_02.txt";
         let filepath = format!("{}/{}", config.download_directory, filename);
@@ -118,7 +153,7 @@ mod tests {
         let offset = 4;
         let length = 7;
 
-        let block = retrieve_block(String::from(filename), offset, length, config)
+        let block = retrieve_block(Path::new(&filepath), offset, length)
             .map_err(|err| {
                 fs::remove_file(&filepath).unwrap();
                 err
@@ -133,7 +168,9 @@ mod tests {
 
     #[test]
     fn retrieve_block_with_offset_zero_and_length_equal_to_length_of_file() {
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let dir =
+            TempDir::new("retrieve_block_with_offset_zero_and_length_equal_to_length_of_file");
+        let config = test_config(&dir);
 
         let filename = "test_retrieve_block_03.txt";
         let filepath = format!("{}/{}", config.download_directory, filename);
@@ -143,7 +180,7 @@ mod tests {
         let offset = 0;
         let length = contents.len();
 
-        let block = retrieve_block(String::from(filename), offset, length, config)
+        let block = retrieve_block(Path::new(&filepath), offset, length)
             .map_err(|err| {
                 fs::remove_file(&filepath).unwrap();
                 err
@@ -158,7 +195,8 @@ mod tests {
 
     #[test]
     fn retrieve_block_with_offset_zero_and_length_more_than_file_length() {
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let dir = TempDir::new("retrieve_block_with_offset_zero_and_length_more_than_file_length");
+        let config = test_config(&dir);
 
         let filename = "test_retrieve_block_04.txt";
         let filepath = format!("{}/{}", config.download_directory, filename);
@@ -168,7 +206,7 @@ mod tests {
         let offset = 0;
         let length = contents.len() + 1;
 
-        let io_error = retrieve_block(String::from(filename), offset, length, config).unwrap_err();
+        let io_error = retrieve_block(Path::new(&filepath), offset, length).unwrap_err();
 
         fs::remove_file(filepath).unwrap();
 
@@ -177,7 +215,9 @@ mod tests {
 
     #[test]
     fn retrieve_block_with_offset_in_middle_and_length_more_than_file_length() {
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let dir =
+            TempDir::new("retrieve_block_with_offset_in_middle_and_length_more_than_file_length");
+        let config = test_config(&dir);
 
         let filename = "test_retrieve_block_05.txt";
         let filepath = format!("{}/{}", config.download_directory, filename);
@@ -187,7 +227,7 @@ mod tests {
         let offset = 0;
         let length = contents.len() + 1;
 
-        let io_error = retrieve_block(String::from(filename), offset, length, config).unwrap_err();
+        let io_error = retrieve_block(Path::new(&filepath), offset, length).unwrap_err();
 
         fs::remove_file(filepath).unwrap();
 
@@ -196,7 +236,8 @@ mod tests {
 
     #[test]
     fn retrieve_block_with_offset_zero_and_length_zero() {
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let dir = TempDir::new("retrieve_block_with_offset_zero_and_length_zero");
+        let config = test_config(&dir);
 
         let filename = "test_retrieve_block_06.txt";
         let filepath = format!("{}/{}", config.download_directory, filename);
@@ -206,7 +247,7 @@ mod tests {
         let offset = 0;
         let length = 0;
 
-        let block = retrieve_block(String::from(filename), offset, length, config)
+        let block = retrieve_block(Path::new(&filepath), offset, length)
             .map_err(|err| {
                 fs::remove_file(&filepath).unwrap();
                 err
@@ -221,21 +262,28 @@ mod tests {
 
     #[test]
     fn retrieve_block_and_directory_does_not_exist() {
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let dir = TempDir::new("retrieve_block_and_directory_does_not_exist");
+        let config = test_config(&dir);
 
         let filename = "test_retrieve_block_07.txt";
 
         let offset = 0;
         let length = 6;
 
-        let io_error = retrieve_block(String::from(filename), offset, length, config).unwrap_err();
+        let io_error = retrieve_block(
+            &Path::new(&config.download_directory).join(filename),
+            offset,
+            length,
+        )
+        .unwrap_err();
 
         assert_eq!(io_error.kind(), std::io::ErrorKind::NotFound);
     }
 
     #[test]
     fn retrieve_block_and_file_does_not_exist() {
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let dir = TempDir::new("retrieve_block_and_file_does_not_exist");
+        let config = test_config(&dir);
 
         let filename = "test_retrieve_block_08.txt";
         create_downloads_dir_if_necessary(config.download_directory.as_str());
@@ -243,7 +291,12 @@ mod tests {
         let offset = 0;
         let length = 5;
 
-        let io_error = retrieve_block(String::from(filename), offset, length, config).unwrap_err();
+        let io_error = retrieve_block(
+            &Path::new(&config.download_directory).join(filename),
+            offset,
+            length,
+        )
+        .unwrap_err();
 
         assert_eq!(io_error.kind(), std::io::ErrorKind::NotFound);
     }
@@ -260,15 +313,15 @@ mod tests {
     #[test]
     fn save_file_creates_file_if_it_does_not_exist() {
         let file_name = "test_file_01.txt".to_string();
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let dir = TempDir::new("save_file_creates_file_if_it_does_not_exist");
+        let config = test_config(&dir);
         let path = format!("{}/{}", config.download_directory, &file_name);
 
         assert!(!Path::new(&path).exists());
         assert!(save_piece(
-            file_name,
+            Path::new(&path),
             &[0x50u8, 0x65u8, 0x72u8, 0xF3u8, 0x6Eu8],
-            0,
-            config
+            0
         )
         .is_ok());
         assert!(Path::new(&path).exists());
@@ -278,7 +331,8 @@ mod tests {
     #[test]
     fn write_in_nonexistent_file() {
         let file_name = "test_file_02.txt".to_string();
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let dir = TempDir::new("write_in_nonexistent_file");
+        let config = test_config(&dir);
         let path = format!("{}/{}", config.download_directory, &file_name);
 
         create_downloads_dir_if_necessary(config.download_directory.as_str());
@@ -286,7 +340,7 @@ mod tests {
         assert!(!Path::new(&path).exists());
 
         let content_to_write = vec![0x50u8, 0x65u8, 0x72u8, 0xF3u8, 0x6Eu8];
-        assert!(save_piece(file_name, &content_to_write, 0, config).is_ok());
+        assert!(save_piece(Path::new(&path), &content_to_write, 0).is_ok());
         assert!(Path::new(&path).exists());
 
         read_file_and_assert_its_content_equals_expected_content(content_to_write, &path);
@@ -297,7 +351,8 @@ mod tests {
     #[test]
     fn write_in_existing_file() {
         let file_name = "test_file_03.txt".to_string();
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let dir = TempDir::new("write_in_existing_file");
+        let config = test_config(&dir);
         let path = format!("{}/{}", config.download_directory, &file_name);
 
         create_downloads_dir_if_necessary(config.download_directory.as_str());
@@ -305,7 +360,7 @@ mod tests {
         File::create(&path).unwrap();
 
         let content_to_write = vec![0x50u8, 0x65u8, 0x72u8, 0xF3u8, 0x6Eu8];
-        assert!(save_piece(file_name, &content_to_write, 0, config).is_ok());
+        assert!(save_piece(Path::new(&path), &content_to_write, 0).is_ok());
 
         read_file_and_assert_its_content_equals_expected_content(content_to_write, &path);
 
@@ -315,7 +370,8 @@ mod tests {
     #[test]
     fn write_at_the_end_of_existing_file_that_already_has_contents() {
         let file_name = "test_file_04.txt".to_string();
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let dir = TempDir::new("write_at_the_end_of_existing_file_that_already_has_contents");
+        let config = test_config(&dir);
         let path = format!("{}/{}", config.download_directory, &file_name);
 
         create_downloads_dir_if_necessary(config.download_directory.as_str());
@@ -325,7 +381,7 @@ mod tests {
         file.write_all(&previous_content).unwrap();
 
         let content_to_write = vec![0x50u8, 0x65u8, 0x72u8, 0xF3u8, 0x6Eu8];
-        assert!(save_piece(file_name, &content_to_write, 5, config).is_ok());
+        assert!(save_piece(Path::new(&path), &content_to_write, 5).is_ok());
 
         read_file_and_assert_its_content_equals_expected_content(
             vec![
@@ -340,7 +396,8 @@ mod tests {
     #[test]
     fn write_between_pieces_of_existing_file_that_already_has_contents() {
         let file_name = "test_file_05.txt".to_string();
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let dir = TempDir::new("write_between_pieces_of_existing_file_that_already_has_contents");
+        let config = test_config(&dir);
         let path = format!("{}/{}", config.download_directory, &file_name);
 
         create_downloads_dir_if_necessary(config.download_directory.as_str());
@@ -353,7 +410,7 @@ mod tests {
         file.write_all(&first_piece).unwrap();
         file.write_all_at(&third_piece, 7).unwrap();
 
-        assert!(save_piece(file_name, &second_piece, 4, config).is_ok());
+        assert!(save_piece(Path::new(&path), &second_piece, 4).is_ok());
 
         read_file_and_assert_its_content_equals_expected_content(
             vec![
@@ -365,6 +422,87 @@ mod tests {
         fs::remove_file(path).unwrap();
     }
 
+    #[test]
+    fn concurrent_writes_and_reads_to_shared_file() {
+        let dir = TempDir::new("concurrent_writes_and_reads_to_shared_file");
+        let config = test_config(&dir);
+        create_downloads_dir_if_necessary(config.download_directory.as_str());
+        let path = format!("{}/test_file_06.txt", config.download_directory);
+        let block_length = 4096;
+        let blocks = 16;
+
+        let file = Arc::new(
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&path)
+                .unwrap(),
+        );
+        let writers: Vec<_> = (0..blocks)
+            .map(|block| {
+                let file = file.clone();
+                thread::spawn(move || {
+                    let content = vec![block as u8; block_length];
+                    file.write_all_at(&content, (block * block_length) as u64)
+                        .unwrap();
+                })
+            })
+            .collect();
+        writers
+            .into_iter()
+            .for_each(|writer| writer.join().unwrap());
+
+        let readers: Vec<_> = (0..blocks)
+            .map(|block| {
+                let file = file.clone();
+                thread::spawn(move || {
+                    let mut content = vec![0; block_length];
+                    file.read_exact_at(&mut content, (block * block_length) as u64)
+                        .unwrap();
+                    assert!(content.iter().all(|byte| *byte == block as u8));
+                })
+            })
+            .collect();
+        readers
+            .into_iter()
+            .for_each(|reader| reader.join().unwrap());
+
+        assert_eq!(
+            fs::metadata(&path).unwrap().len(),
+            (blocks * block_length) as u64
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn concurrent_save_piece_to_same_file() {
+        let dir = TempDir::new("concurrent_save_piece_to_same_file");
+        let config = test_config(&dir);
+        let path = format!("{}/test_file_07.txt", config.download_directory);
+
+        let writers: Vec<_> = (0..8u8)
+            .map(|piece| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    save_piece(Path::new(&path), &[piece; 100], piece as u64 * 100).unwrap();
+                })
+            })
+            .collect();
+        writers
+            .into_iter()
+            .for_each(|writer| writer.join().unwrap());
+
+        let content = fs::read(&path).unwrap();
+        for piece in 0..8u8 {
+            let block = retrieve_block(Path::new(&path), piece as u64 * 100, 100).unwrap();
+            assert_eq!(block, vec![piece; 100]);
+        }
+        assert_eq!(content.len(), 800);
+        fs::remove_file(path).unwrap();
+    }
+
     fn read_file_and_assert_its_content_equals_expected_content(
         expected_content: Vec<u8>,
         file_name: &str,
@@ -0,0 +1,87 @@
+use std::{
+    io,
+    sync::{Mutex, MutexGuard},
+};
+
+use super::storage::Storage;
+
+/// `Storage` that keeps the data of a torrent in memory, for tests and torrents that are not saved.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    data: Mutex<Vec<u8>>,
+}
+
+impl MemoryStorage {
+    /// Creates a new empty `MemoryStorage`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of the data written so far.
+    pub fn data(&self) -> io::Result<Vec<u8>> {
+        Ok(self.lock_data()?.clone())
+    }
+
+    fn lock_data(&self) -> io::Result<MutexGuard<'_, Vec<u8>>> {
+        self.data
+            .lock()
+            .map_err(|_| io::Error::other("Poisoned memory storage lock"))
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn write_piece(&self, offset: u64, piece: &[u8]) -> io::Result<()> {
+        let mut data = self.lock_data()?;
+        let start = offset as usize;
+        let end = start + piece.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(piece);
+        Ok(())
+    }
+
+    fn read_block(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        let data = self.lock_data()?;
+        let start = offset as usize;
+        data.get(start..start + length)
+            .map(|block| block.to_vec())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))
+    }
+
+    fn allocate(&self, length: u64) -> io::Result<()> {
+        let mut data = self.lock_data()?;
+        if (data.len() as u64) < length {
+            data.resize(length as usize, 0);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read() {
+        let storage = MemoryStorage::new();
+        storage.write_piece(2, b"cd").unwrap();
+        storage.write_piece(0, b"ab").unwrap();
+
+        assert_eq!(storage.read_block(1, 2).unwrap(), b"bc");
+        assert_eq!(
+            storage.read_block(3, 2).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_allocate() {
+        let storage = MemoryStorage::new();
+        storage.allocate(4).unwrap();
+
+        assert_eq!(storage.data().unwrap(), vec![0; 4]);
+        assert!(storage.path().is_none());
+        assert!(storage.set_path("other".into()).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    fs::File,
+    io,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    ptr, slice,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use super::{file_storage::FileStorage, storage::Storage};
+use crate::{config::cfg::Cfg, torrent_parser::torrent::Torrent};
+
+/// `Storage` that writes the data of a torrent like a `FileStorage` and, once the download completes, maps
+/// the completed file read-only in memory to serve the blocks requested by other peers without a read call each time.
+///
+/// Blocks of a torrent that is not complete yet are read from the file.
+///
+/// To create a new `MmapStorage` use `MmapStorage::new`.
+#[derive(Debug)]
+pub struct MmapStorage {
+    file: FileStorage,
+    map: RwLock<Option<Mmap>>,
+}
+
+impl MmapStorage {
+    /// Creates a new `MmapStorage` for the torrent with the given config.
+    pub fn new(torrent: &Torrent, config: &Cfg) -> Self {
+        Self {
+            file: FileStorage::new(torrent, config),
+            map: RwLock::new(None),
+        }
+    }
+
+    /// Returns `true` if the blocks are being served from the mapped file.
+    pub fn is_mapped(&self) -> bool {
+        self.read_map().map(|map| map.is_some()).unwrap_or(false)
+    }
+
+    /// Maps the file at the final path of the download.
+    fn map_file(&self) -> io::Result<()> {
+        let path = self
+            .file
+            .path()
+            .ok_or_else(|| io::Error::other("The storage has no output path"))?;
+        *self.write_map()? = Some(Mmap::open(&path)?);
+        Ok(())
+    }
+
+    fn read_map(&self) -> io::Result<RwLockReadGuard<'_, Option<Mmap>>> {
+        self.map
+            .read()
+            .map_err(|_| io::Error::other("Poisoned file map lock"))
+    }
+
+    fn write_map(&self) -> io::Result<RwLockWriteGuard<'_, Option<Mmap>>> {
+        self.map
+            .write()
+            .map_err(|_| io::Error::other("Poisoned file map lock"))
+    }
+}
+
+impl Storage for MmapStorage {
+    fn write_piece(&self, offset: u64, piece: &[u8]) -> io::Result<()> {
+        self.file.write_piece(offset, piece)
+    }
+
+    fn read_block(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        if let Some(map) = self.read_map()?.as_ref() {
+            let start = usize::try_from(offset)
+                .map_err(|_| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+            return map
+                .as_slice()
+                .get(start..start.saturating_add(length))
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        self.file.read_block(offset, length)
+    }
+
+    fn allocate(&self, length: u64) -> io::Result<()> {
+        self.file.allocate(length)
+    }
+
+    /// Completes the file like a `FileStorage` and maps it.
+    fn complete(&self) -> io::Result<()> {
+        self.file.complete()?;
+        self.map_file()
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        self.file.path()
+    }
+
+    /// Moves the file like a `FileStorage`, a mapped file is unmapped before the move and mapped again at the new path.
+    fn set_path(&self, path: PathBuf) -> io::Result<()> {
+        let mut map = self.write_map()?;
+        let was_mapped = map.take().is_some();
+        self.file.set_path(path)?;
+        drop(map);
+        if was_mapped {
+            self.map_file()?;
+        }
+        Ok(())
+    }
+
+    /// Unmaps the file and deletes it like a `FileStorage`.
+    fn delete(&self) -> io::Result<()> {
+        let mut map = self.write_map()?;
+        map.take();
+        self.file.delete()
+    }
+}
+
+/// A file mapped read-only in memory, unmapped on drop.
+#[derive(Debug)]
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// SAFETY: the mapping is read-only and owned by the `Mmap`, so it can be read from any thread.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Maps the whole file at the given path.
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::other("The file is too big to be mapped"))?;
+        if len == 0 {
+            // Mapping zero bytes fails, an empty file is kept as an empty slice.
+            return Ok(Self {
+                ptr: ptr::null_mut(),
+                len,
+            });
+        }
+
+        // SAFETY: a new read-only mapping of an open file descriptor does not alias any Rust memory,
+        // the mapping stays valid after the file is closed.
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // SAFETY: `ptr` points to `len` readable bytes for as long as `self` is alive. The completed file
+        // is not written again by the client, but the slice relies on no other process modifying it either:
+        // a write from outside changes bytes behind a `&[u8]`, and a truncation makes reading past the new
+        // end raise SIGBUS, both undefined behavior. This is why `mmap_seeding` is opt-in.
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            // SAFETY: `ptr` and `len` are the ones returned by `mmap` and the map is not used after this.
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::torrent_parser::info::Info;
+    use url_encoder::info_hash::InfoHash;
+
+    fn build_torrent() -> Torrent {
+        Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![],
+            http_seeds: vec![],
+            info_hash_v2: None,
+            piece_layer: vec![],
+            info: Info {
+                length: 8,
+                name: "data".to_string(),
+                piece_length: 4,
+                pieces: vec![],
+                private: None,
+                meta_version: None,
+                file_tree: Default::default(),
+            },
+            info_hash: InfoHash::new([0; 20]),
+        }
+    }
+
+    #[test]
+    fn test_serves_blocks_from_map_once_complete() {
+        let dir = "./test_mmap_storage_serves_blocks";
+        let config = Cfg {
+            download_directory: dir.to_string(),
+            ..Cfg::default()
+        };
+        let storage = MmapStorage::new(&build_torrent(), &config);
+
+        storage.write_piece(0, b"abcd").unwrap();
+        storage.write_piece(4, b"efgh").unwrap();
+        assert!(!storage.is_mapped());
+        assert_eq!(storage.read_block(2, 4).unwrap(), b"cdef");
+
+        storage.complete().unwrap();
+
+        assert!(storage.is_mapped());
+        assert_eq!(storage.read_block(2, 4).unwrap(), b"cdef");
+        assert_eq!(storage.read_block(4, 4).unwrap(), b"efgh");
+        assert!(storage.read_block(6, 4).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_path_maps_the_moved_file() {
+        let dir = "./test_mmap_storage_set_path";
+        let config = Cfg {
+            download_directory: dir.to_string(),
+            ..Cfg::default()
+        };
+        let storage = MmapStorage::new(&build_torrent(), &config);
+        storage.write_piece(0, b"abcdefgh").unwrap();
+        storage.complete().unwrap();
+
+        let new_path = Path::new(dir).join("moved").join("data");
+        storage.set_path(new_path.clone()).unwrap();
+
+        assert!(storage.is_mapped());
+        assert_eq!(storage.path(), Some(new_path));
+        assert_eq!(storage.read_block(0, 8).unwrap(), b"abcdefgh");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1 +1,9 @@
+pub mod file_storage;
+pub mod layout;
 pub mod manager;
+pub mod memory_storage;
+#[cfg(unix)]
+pub mod mmap_storage;
+pub mod output_file;
+pub mod storage;
+pub mod verify;
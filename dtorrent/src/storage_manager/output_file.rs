@@ -0,0 +1,170 @@
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Extension added to the files of the downloads in progress.
+pub const PART_EXTENSION: &str = "part";
+
+/// The file a torrent is downloaded to.
+///
+/// With `part_files` the download in progress is written to `<path>.part`, which is renamed to `path` when
+/// the download completes, so the file at `path` is never half-written.
+#[derive(Debug)]
+pub struct OutputFile {
+    path: PathBuf,
+    in_progress: bool,
+    completed: bool,
+}
+
+impl OutputFile {
+    /// Creates a new `OutputFile` for a download that will be saved at `path`.
+    pub fn new(path: PathBuf, part_files: bool) -> Self {
+        Self {
+            path,
+            in_progress: part_files,
+            completed: false,
+        }
+    }
+
+    /// Returns the path where the file is saved once the download completes.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns `true` if the download completed and the file is at its final location.
+    pub fn is_completed(&self) -> bool {
+        self.completed
+    }
+
+    /// Returns the path where the file is currently written to and read from.
+    pub fn current_path(&self) -> PathBuf {
+        if self.in_progress {
+            part_path(&self.path)
+        } else {
+            self.path.clone()
+        }
+    }
+
+    /// Changes the path where the file is saved, moving the data already written.
+    ///
+    /// # Errors
+    ///
+    /// - `io::Error` if the file could not be moved to the new path.
+    pub fn set_path(&mut self, path: PathBuf) -> io::Result<()> {
+        let current_path = self.current_path();
+        self.path = path;
+        move_if_exists(&current_path, &self.current_path())
+    }
+
+    /// Moves the file to its final location once the download completes.
+    ///
+    /// The `.part` file is renamed to the path of the file, which is moved into the `completed_directory`
+    /// if there is one. Completing the file more than once does nothing.
+    ///
+    /// # Errors
+    ///
+    /// - `io::Error` if the file could not be renamed or moved.
+    pub fn complete(&mut self, completed_directory: Option<&Path>) -> io::Result<()> {
+        if self.completed {
+            return Ok(());
+        }
+        let current_path = self.current_path();
+        if let (Some(directory), Some(file_name)) = (completed_directory, self.path.file_name()) {
+            self.path = directory.join(file_name);
+        }
+        move_if_exists(&current_path, &self.path)?;
+        self.in_progress = false;
+        self.completed = true;
+        Ok(())
+    }
+
+    /// Deletes the file where it is currently written to, if it exists.
+    ///
+    /// # Errors
+    ///
+    /// - `io::Error` if the file exists and could not be deleted.
+    pub fn delete(&self) -> io::Result<()> {
+        match fs::remove_file(self.current_path()) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returns the path with the `.part` extension added.
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut part_path = OsString::from(path);
+    part_path.push(".");
+    part_path.push(PART_EXTENSION);
+    PathBuf::from(part_path)
+}
+
+/// Moves the file at `from` to `to` creating the directory of `to` if needed, if there is a file at `from`.
+fn move_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    if from == to || !from.exists() {
+        return Ok(());
+    }
+    if let Some(directory) = to.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::rename(from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_part_file_is_renamed_on_complete() {
+        let dir = "./test_part_file_is_renamed_on_complete";
+        let path = Path::new(dir).join("movie.mp4");
+        let mut file = OutputFile::new(path.clone(), true);
+        fs::create_dir_all(dir).unwrap();
+        fs::write(file.current_path(), b"data").unwrap();
+
+        assert_eq!(file.current_path(), Path::new(dir).join("movie.mp4.part"));
+        assert!(!path.exists());
+
+        file.complete(None).unwrap();
+
+        assert_eq!(file.current_path(), path);
+        assert_eq!(fs::read(&path).unwrap(), b"data");
+        assert!(!part_path(&path).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_complete_moves_to_completed_directory() {
+        let dir = "./test_complete_moves_to_completed_directory";
+        let completed = Path::new(dir).join("completed");
+        let mut file = OutputFile::new(Path::new(dir).join("movie.mp4"), false);
+        fs::create_dir_all(dir).unwrap();
+        fs::write(file.current_path(), b"data").unwrap();
+
+        file.complete(Some(&completed)).unwrap();
+        file.complete(Some(&completed.join("again"))).unwrap();
+
+        assert_eq!(file.path(), completed.join("movie.mp4"));
+        assert_eq!(fs::read(completed.join("movie.mp4")).unwrap(), b"data");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_path_moves_part_file() {
+        let dir = "./test_set_path_moves_part_file";
+        let mut file = OutputFile::new(Path::new(dir).join("movie.mp4"), true);
+        fs::create_dir_all(dir).unwrap();
+        fs::write(file.current_path(), b"data").unwrap();
+
+        file.set_path(Path::new(dir).join("films").join("film.mp4"))
+            .unwrap();
+
+        assert_eq!(
+            fs::read(Path::new(dir).join("films").join("film.mp4.part")).unwrap(),
+            b"data"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
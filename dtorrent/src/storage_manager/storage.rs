@@ -0,0 +1,47 @@
+use std::{fmt::Debug, io, path::PathBuf};
+
+use sha1::{Digest, Sha1};
+
+/// Where the downloaded data of a torrent is kept.
+///
+/// Offsets are relative to the start of the torrent data. `FileStorage` keeps it in a file of the
+/// `download_directory` and `MemoryStorage` in memory.
+pub trait Storage: Debug + Send + Sync {
+    /// Writes a verified piece at the given offset.
+    fn write_piece(&self, offset: u64, piece: &[u8]) -> io::Result<()>;
+
+    /// Reads `length` bytes at the given offset.
+    fn read_block(&self, offset: u64, length: usize) -> io::Result<Vec<u8>>;
+
+    /// Reserves space for `length` bytes of data.
+    fn allocate(&self, length: u64) -> io::Result<()>;
+
+    /// Returns `true` if the SHA-1 hash of the `length` bytes at the given offset is `hash`.
+    fn verify(&self, offset: u64, length: usize, hash: &[u8]) -> io::Result<bool> {
+        let data = self.read_block(offset, length)?;
+        Ok(Sha1::digest(data).as_slice() == hash)
+    }
+
+    /// Called once every piece was written.
+    fn complete(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Returns the path of the data once the download completes, if it is kept in a file.
+    fn path(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// Changes the path of the data, moving what was already written.
+    fn set_path(&self, _path: PathBuf) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "The storage is not kept in a file",
+        ))
+    }
+
+    /// Deletes the data written, when the torrent is removed. Storages that do not outlive the client keep it.
+    fn delete(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
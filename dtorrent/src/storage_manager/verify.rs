@@ -0,0 +1,139 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use sha1::{Digest, Sha1};
+
+use crate::torrent_parser::torrent::Torrent;
+
+/// Result of checking a piece of a file on disk against the hash in the torrent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PieceCheck {
+    /// The piece matches its hash.
+    Valid,
+    /// The piece does not match its hash.
+    Invalid,
+    /// The file ends before the end of the piece.
+    Missing,
+}
+
+/// Per-piece results of checking a file on disk against a torrent, see `verify`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Path of the checked file.
+    pub path: PathBuf,
+    /// Result of each piece, by piece index.
+    pub pieces: Vec<PieceCheck>,
+}
+
+impl VerifyReport {
+    /// Returns the number of pieces with the given result.
+    pub fn count(&self, check: PieceCheck) -> usize {
+        self.pieces.iter().filter(|piece| **piece == check).count()
+    }
+
+    /// Returns true if every piece matches its hash.
+    pub fn is_complete(&self) -> bool {
+        self.pieces.iter().all(|piece| *piece == PieceCheck::Valid)
+    }
+}
+
+/// Checks the data at `path` against the piece hashes of `torrent`, reading it a piece at a time.
+///
+/// `path` is either the file of the torrent or a directory holding it under the name of the torrent.
+///
+/// # Errors
+///
+/// - `io::Error` if the file could not be opened or read.
+pub fn verify(torrent: &Torrent, path: &Path) -> io::Result<VerifyReport> {
+    let path = if path.is_dir() {
+        path.join(torrent.name())
+    } else {
+        path.to_path_buf()
+    };
+    let mut file = File::open(&path)?;
+
+    let mut pieces = Vec::with_capacity(torrent.total_pieces() as usize);
+    let mut buffer = vec![0; torrent.piece_length() as usize];
+    for index in 0..torrent.total_pieces() {
+        let piece = &mut buffer[..torrent.piece_size(index) as usize];
+        let read = read_up_to(&mut file, piece)?;
+        let check = if read < piece.len() {
+            PieceCheck::Missing
+        } else if Sha1::digest(&piece[..]).as_slice() == piece_hash(torrent, index) {
+            PieceCheck::Valid
+        } else {
+            PieceCheck::Invalid
+        };
+        pieces.push(check);
+    }
+
+    Ok(VerifyReport { path, pieces })
+}
+
+fn piece_hash(torrent: &Torrent, index: u32) -> &[u8] {
+    let start = index as usize * 20;
+    &torrent.info.pieces[start..start + 20]
+}
+
+/// Fills `buffer` until the end of the file, returning the number of bytes read.
+fn read_up_to(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match file.read(&mut buffer[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_support::{local_swarm::generated_torrent, temp_dir::TempDir};
+
+    #[test]
+    fn test_verify_complete_file() {
+        let dir = TempDir::new("test_verify_complete_file");
+        let (torrent, data) = generated_torrent("verified.bin", 3 * 16384 + 100, 16384, "announce");
+        fs::write(dir.join("verified.bin"), &data).unwrap();
+
+        let report = verify(&torrent, &dir.join("")).unwrap();
+
+        assert_eq!(report.path, dir.join("verified.bin"));
+        assert_eq!(report.pieces.len(), torrent.total_pieces() as usize);
+        assert!(report.is_complete());
+    }
+
+    #[test]
+    fn test_verify_corrupted_and_truncated_file() {
+        let dir = TempDir::new("test_verify_corrupted_and_truncated_file");
+        let (torrent, mut data) =
+            generated_torrent("corrupted.bin", 3 * 16384 + 100, 16384, "announce");
+        data[0] ^= 0xff;
+        data.truncate(data.len() - 10);
+        let path = dir.join("corrupted.bin");
+        fs::write(&path, &data).unwrap();
+
+        let report = verify(&torrent, &path).unwrap();
+
+        assert_eq!(report.pieces.first(), Some(&PieceCheck::Invalid));
+        assert_eq!(report.pieces.last(), Some(&PieceCheck::Missing));
+        assert!(!report.is_complete());
+    }
+
+    #[test]
+    fn test_verify_missing_file() {
+        let dir = TempDir::new("test_verify_missing_file");
+        let (torrent, _) = generated_torrent("absent.bin", 100, 16384, "announce");
+
+        assert!(verify(&torrent, &dir.join("absent.bin")).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use url_encoder::info_hash::InfoHash;
+
+use super::temp_dir::TempDir;
+use crate::{
+    config::cfg::Cfg,
+    torrent_parser::{info::Info, torrent::Torrent},
+};
+
+/// Returns the default config with the downloads and the logs kept in `dir`.
+pub fn test_config(dir: &TempDir) -> Cfg {
+    Cfg {
+        download_directory: dir.join("downloads").display().to_string(),
+        log_directory: dir.join("logs").display().to_string(),
+        ..Cfg::default()
+    }
+}
+
+/// Returns a torrent of 10 pieces of 1 byte announced to `announce_url`, without piece hashes.
+pub fn test_torrent(name: &str, announce_url: &str) -> Torrent {
+    Torrent {
+        announce_url: announce_url.to_string(),
+        announce_list: vec![],
+        http_seeds: vec![],
+        info_hash_v2: None,
+        piece_layer: vec![],
+        info: Info {
+            length: 10,
+            name: name.to_string(),
+            piece_length: 1,
+            pieces: vec![],
+            private: None,
+            meta_version: None,
+            file_tree: Default::default(),
+        },
+        info_hash: InfoHash::new([0; 20]),
+    }
+}
@@ -0,0 +1,161 @@
+use std::{collections::HashMap, net::SocketAddrV4, sync::Arc, thread};
+
+use sha1::{Digest, Sha1};
+use url_encoder::info_hash::InfoHash;
+
+use crate::{
+    bt_server::server::BtServer,
+    config::cfg::Cfg,
+    peer::peer_message::Bitfield,
+    storage_manager::memory_storage::MemoryStorage,
+    torrent_handler::status::AtomicTorrentStatus,
+    torrent_parser::{
+        file_tree::{FileTree, FileTreeEntry},
+        info::{Info, META_VERSION_2},
+        merkle,
+        sha256::sha256,
+        torrent::Torrent,
+    },
+};
+
+pub const SEEDER_PEER_ID: &str = "-DT0001-seeder-00000";
+pub const LEECHER_PEER_ID: &str = "-DT0001-leecher-0000";
+
+/// Returns a torrent of `length` generated bytes with its piece hashes, and the bytes.
+pub fn generated_torrent(
+    name: &str,
+    length: usize,
+    piece_length: usize,
+    announce_url: &str,
+) -> (Torrent, Vec<u8>) {
+    let data: Vec<u8> = (0..length).map(|byte| (byte * 31 % 251) as u8).collect();
+    let pieces = data
+        .chunks(piece_length)
+        .flat_map(|piece| Sha1::digest(piece).to_vec())
+        .collect();
+    let info_hash: [u8; 20] = Sha1::digest(name.as_bytes()).into();
+
+    let torrent = Torrent {
+        announce_url: announce_url.to_string(),
+        announce_list: vec![],
+        http_seeds: vec![],
+        info_hash_v2: None,
+        piece_layer: vec![],
+        info: Info {
+            length: length as i64,
+            name: name.to_string(),
+            piece_length: piece_length as i64,
+            pieces,
+            private: None,
+            meta_version: None,
+            file_tree: Default::default(),
+        },
+        info_hash: InfoHash::new(info_hash),
+    };
+    (torrent, data)
+}
+
+/// Returns a hybrid torrent (BEP 52) of `length` generated bytes with its v1 piece hashes and the piece layer of its
+/// file, and the bytes.
+///
+/// `piece_length` must be a power of two of at least 16 KiB.
+pub fn generated_hybrid_torrent(
+    name: &str,
+    length: usize,
+    piece_length: usize,
+    announce_url: &str,
+) -> (Torrent, Vec<u8>) {
+    let (mut torrent, data) = generated_torrent(name, length, piece_length, announce_url);
+    let blocks_per_piece =
+        merkle::blocks_per_piece(piece_length as u64).expect("a valid v2 piece length") as usize;
+
+    let pieces_root = if length <= piece_length {
+        merkle::root(&merkle::block_hashes(&data), 1, 0)
+    } else {
+        torrent.piece_layer = data
+            .chunks(piece_length)
+            .map(|piece| merkle::root(&merkle::block_hashes(piece), blocks_per_piece, 0))
+            .collect();
+        merkle::root(&torrent.piece_layer, 1, blocks_per_piece.trailing_zeros())
+    };
+    if length <= piece_length {
+        torrent.piece_layer = vec![pieces_root];
+    }
+
+    torrent.info.meta_version = Some(META_VERSION_2);
+    torrent.info.file_tree = FileTree::new(vec![FileTreeEntry {
+        path: vec![name.to_string()],
+        length: length as u64,
+        pieces_root: Some(pieces_root),
+    }]);
+    torrent.info_hash_v2 = Some(sha256(
+        &bencoder::to_bytes(&torrent.info).expect("the info can be encoded"),
+    ));
+    (torrent, data)
+}
+
+/// A `BtServer` on a local port seeding a torrent kept in memory.
+///
+/// The server keeps listening until the tests finish.
+///
+/// To start a new `LocalSeeder` use `LocalSeeder::start`.
+#[derive(Debug)]
+pub struct LocalSeeder {
+    address: SocketAddrV4,
+    status: Arc<AtomicTorrentStatus>,
+}
+
+impl LocalSeeder {
+    /// Starts seeding `data` as the content of `torrent`.
+    pub fn start(torrent: &Torrent, data: &[u8], config: Cfg) -> Self {
+        let config = Cfg {
+            tcp_port: 0,
+            tcp_port_max: 0,
+            ..config
+        };
+        let status = AtomicTorrentStatus::with_storage(
+            torrent,
+            config.clone(),
+            Box::new(MemoryStorage::new()),
+        );
+        let all_pieces = Bitfield::new(vec![0xff; (torrent.total_pieces() as usize).div_ceil(8)]);
+        add_pieces(&status, torrent, data, &all_pieces);
+        let status = Arc::new(status);
+
+        let mut server = BtServer::new(
+            HashMap::from([(torrent.info_hash(), status.clone())]),
+            config,
+            SEEDER_PEER_ID.to_string(),
+        );
+        let port = server.bind().expect("the seeder can listen");
+        thread::spawn(move || server.init());
+
+        Self {
+            address: SocketAddrV4::new([127, 0, 0, 1].into(), port),
+            status,
+        }
+    }
+
+    /// Returns the address the leechers connect to.
+    pub fn address(&self) -> SocketAddrV4 {
+        self.address
+    }
+
+    pub fn status(&self) -> Arc<AtomicTorrentStatus> {
+        self.status.clone()
+    }
+}
+
+/// Marks the `pieces` as downloaded with their part of `data`.
+pub fn add_pieces(status: &AtomicTorrentStatus, torrent: &Torrent, data: &[u8], pieces: &Bitfield) {
+    while let Some(index) = status
+        .select_piece(pieces)
+        .expect("the status can select pieces")
+    {
+        let start = index as usize * torrent.info.piece_length as usize;
+        let end = (start + torrent.info.piece_length as usize).min(data.len());
+        status
+            .piece_downloaded(index, &data[start..end])
+            .expect("the pieces are kept in memory");
+    }
+}
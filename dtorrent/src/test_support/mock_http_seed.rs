@@ -0,0 +1,141 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// HTTP seed (BEP 17) listening on a local port that serves the pieces of `data`.
+///
+/// The request lines it receives are kept to check what was requested. While busy it answers every request with
+/// `503` and the seconds to wait. It stops when dropped.
+///
+/// To start a new `MockHttpSeed` use `MockHttpSeed::start`.
+#[derive(Debug)]
+pub struct MockHttpSeed {
+    address: SocketAddr,
+    requests: Arc<Mutex<Vec<String>>>,
+    busy: Arc<Mutex<Option<u64>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockHttpSeed {
+    /// Starts a seed of `data` split in pieces of `piece_length` bytes.
+    pub fn start(data: Vec<u8>, piece_length: usize) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("the mock seed can listen");
+        let address = listener.local_addr().expect("the mock seed has an address");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let busy = Arc::new(Mutex::new(None));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let (thread_requests, thread_busy, thread_stopped) =
+            (requests.clone(), busy.clone(), stopped.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let busy = *thread_busy.lock().unwrap();
+                    Self::answer(stream, &data, piece_length, busy, &thread_requests);
+                }
+            }
+        });
+
+        Self {
+            address,
+            requests,
+            busy,
+            stopped,
+        }
+    }
+
+    /// Returns the url of the seed.
+    pub fn url(&self) -> String {
+        format!("http://{}/seed", self.address)
+    }
+
+    /// Answers the next requests with `503` and the given seconds to wait, or serves them again with `None`.
+    pub fn set_busy(&self, retry_secs: Option<u64>) {
+        *self.busy.lock().unwrap() = retry_secs;
+    }
+
+    /// Returns the request lines received so far, like `GET /seed?info_hash=...&piece=0 HTTP/1.0`.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn answer(
+        mut stream: TcpStream,
+        data: &[u8],
+        piece_length: usize,
+        busy: Option<u64>,
+        requests: &Mutex<Vec<String>>,
+    ) {
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).is_err() {
+            return;
+        }
+        let mut header = String::new();
+        while reader.read_line(&mut header).is_ok_and(|read| read > 2) {
+            header.clear();
+        }
+        requests
+            .lock()
+            .unwrap()
+            .push(request_line.trim_end().to_string());
+
+        let (status, body) = match busy {
+            Some(retry_secs) => (
+                "503 Service Unavailable",
+                retry_secs.to_string().into_bytes(),
+            ),
+            None => match Self::body(&request_line, data, piece_length) {
+                Some(body) => ("200 OK", body),
+                None => ("400 Bad Request", vec![]),
+            },
+        };
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            body.len()
+        );
+        let _ = stream.write_all(head.as_bytes());
+        let _ = stream.write_all(&body);
+    }
+
+    /// Returns the bytes asked by the `piece` and `ranges` query params of a request line.
+    fn body(request_line: &str, data: &[u8], piece_length: usize) -> Option<Vec<u8>> {
+        let query = request_line.split_whitespace().nth(1)?.split_once('?')?.1;
+        let param = |name: &str| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+        };
+
+        let start = param("piece")?.parse::<usize>().ok()? * piece_length;
+        let piece = data.get(start..(start + piece_length).min(data.len()))?;
+        let ranges = match param("ranges") {
+            Some(ranges) => ranges,
+            None => return Some(piece.to_vec()),
+        };
+        let mut body = vec![];
+        for range in ranges.split(',') {
+            let (start, end) = range.split_once('-')?;
+            body.extend_from_slice(piece.get(start.parse().ok()?..=end.parse().ok()?)?);
+        }
+        Some(body)
+    }
+}
+
+impl Drop for MockHttpSeed {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        // Wakes up the listener so it sees the seed was stopped.
+        let _ = TcpStream::connect(self.address);
+    }
+}
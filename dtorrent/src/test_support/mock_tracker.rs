@@ -0,0 +1,134 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, SocketAddrV4, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// HTTP tracker listening on a local port that answers every announce with the same response.
+///
+/// The request lines it receives are kept to check what was announced. It stops when dropped.
+///
+/// To start a new `MockTracker` use `MockTracker::start`.
+#[derive(Debug)]
+pub struct MockTracker {
+    address: SocketAddr,
+    requests: Arc<Mutex<Vec<String>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockTracker {
+    /// Starts a tracker that answers with `response` as the body.
+    pub fn start(response: Vec<u8>) -> Self {
+        Self::start_with_headers(response, "")
+    }
+
+    /// Starts a tracker that answers with `response` as the body, adding `headers` to the response headers.
+    ///
+    /// Each header must end with `\r\n`, like `Content-Encoding: gzip\r\n`.
+    pub fn start_with_headers(response: Vec<u8>, headers: &str) -> Self {
+        let headers = headers.to_string();
+        let listener = TcpListener::bind("127.0.0.1:0").expect("the mock tracker can listen");
+        let address = listener
+            .local_addr()
+            .expect("the mock tracker has an address");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let thread_requests = requests.clone();
+        let thread_stopped = stopped.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    Self::answer(stream, &response, &headers, &thread_requests);
+                }
+            }
+        });
+
+        Self {
+            address,
+            requests,
+            stopped,
+        }
+    }
+
+    /// Returns the announce url of the tracker.
+    pub fn announce_url(&self) -> String {
+        format!("http://{}/announce", self.address)
+    }
+
+    /// Returns the request lines received so far, like `GET /announce?info_hash=... HTTP/1.1`.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn answer(
+        mut stream: TcpStream,
+        response: &[u8],
+        headers: &str,
+        requests: &Mutex<Vec<String>>,
+    ) {
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).is_err() {
+            return;
+        }
+        let mut header = String::new();
+        while reader.read_line(&mut header).is_ok_and(|read| read > 2) {
+            header.clear();
+        }
+        requests
+            .lock()
+            .unwrap()
+            .push(request_line.trim_end().to_string());
+
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n",
+            response.len(),
+            headers
+        );
+        let _ = stream.write_all(head.as_bytes());
+        let _ = stream.write_all(response);
+    }
+}
+
+impl Drop for MockTracker {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        // Wakes up the listener so it sees the tracker was stopped.
+        let _ = TcpStream::connect(self.address);
+    }
+}
+
+/// Returns a bencoded announce response with the given peers in the compact model.
+pub fn peers_response(interval: i64, peers: &[SocketAddrV4]) -> Vec<u8> {
+    let compact: Vec<u8> = peers
+        .iter()
+        .flat_map(|peer| {
+            let mut bytes = peer.ip().octets().to_vec();
+            bytes.extend_from_slice(&peer.port().to_be_bytes());
+            bytes
+        })
+        .collect();
+    let mut response = format!(
+        "d8:completei{}e10:incompletei0e8:intervali{}e5:peers{}:",
+        peers.len(),
+        interval,
+        compact.len()
+    )
+    .into_bytes();
+    response.extend(compact);
+    response.push(b'e');
+    response
+}
+
+/// Returns a bencoded announce response with a failure reason.
+pub fn failure_response(reason: &str) -> Vec<u8> {
+    format!("d14:failure reason{}:{}e", reason.len(), reason).into_bytes()
+}
@@ -0,0 +1,7 @@
+//! Helpers shared by the tests so they run offline and without files in the working directory.
+
+pub mod fixtures;
+pub mod local_swarm;
+pub mod mock_http_seed;
+pub mod mock_tracker;
+pub mod temp_dir;
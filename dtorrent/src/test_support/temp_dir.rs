@@ -0,0 +1,32 @@
+use std::{env, fs, path::PathBuf, process};
+
+/// Directory inside the temporary directory of the system, removed with everything in it on drop.
+///
+/// The name is suffixed with the id of the process so test runs in parallel do not share it.
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates an empty directory named after the test, removing what a failed run left there.
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("dtorrent_{}_{}", name, process::id()));
+        if path.exists() {
+            fs::remove_dir_all(&path).expect("the temporary directory can be removed");
+        }
+        fs::create_dir_all(&path).expect("the temporary directory can be created");
+        Self { path }
+    }
+
+    /// Returns the path of `name` inside the directory.
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Limits the bytes transferred per second with a token bucket.
+///
+/// The bucket holds up to one second of transfer, so a burst after an idle period is allowed. Each transfer takes
+/// its bytes from the bucket, and once it runs out the transfers wait until it is refilled. A limit of 0 disables it.
+///
+/// To create a new `BandwidthLimiter` use `BandwidthLimiter::new(kb_per_second)`.
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    bytes_per_second: f64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes that can be transferred without waiting, negative if the transfers already reserved more.
+    tokens: f64,
+    last_update: Instant,
+}
+
+impl BandwidthLimiter {
+    /// Creates a new `BandwidthLimiter` of `kb_per_second` kilobytes per second, 0 means unlimited.
+    pub fn new(kb_per_second: u32) -> Self {
+        let bytes_per_second = f64::from(kb_per_second) * 1024.0;
+        Self {
+            bytes_per_second,
+            bucket: Mutex::new(Bucket {
+                tokens: bytes_per_second,
+                last_update: Instant::now(),
+            }),
+        }
+    }
+
+    /// Returns true if the limiter does not limit the transfers.
+    pub fn is_unlimited(&self) -> bool {
+        self.bytes_per_second == 0.0
+    }
+
+    /// Takes `bytes` from the bucket, sleeping the current thread until the transfer fits in the limit.
+    pub fn throttle(&self, bytes: usize) {
+        let delay = self.reserve_at(bytes, Instant::now());
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+
+    /// Takes `bytes` from the bucket and returns how long to wait before transferring them.
+    fn reserve_at(&self, bytes: usize, now: Instant) -> Duration {
+        if self.is_unlimited() {
+            return Duration::ZERO;
+        }
+        // The bucket only holds numbers, so it is still valid if another thread panicked holding it.
+        let mut bucket = self
+            .bucket
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let elapsed = now
+            .saturating_duration_since(bucket.last_update)
+            .as_secs_f64();
+        bucket.tokens =
+            (bucket.tokens + elapsed * self.bytes_per_second).min(self.bytes_per_second);
+        bucket.last_update = now;
+        bucket.tokens -= bytes as f64;
+
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / self.bytes_per_second)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlimited_never_waits() {
+        let limiter = BandwidthLimiter::new(0);
+
+        assert!(limiter.is_unlimited());
+        assert_eq!(
+            limiter.reserve_at(usize::MAX, Instant::now()),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_waits_once_the_bucket_is_empty() {
+        let limiter = BandwidthLimiter::new(16);
+        let now = Instant::now();
+
+        // The bucket starts full with a second of transfer.
+        assert_eq!(limiter.reserve_at(16 * 1024, now), Duration::ZERO);
+        assert_eq!(
+            limiter.reserve_at(8 * 1024, now),
+            Duration::from_millis(500)
+        );
+        assert_eq!(limiter.reserve_at(8 * 1024, now), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_bucket_is_refilled_up_to_a_second() {
+        let limiter = BandwidthLimiter::new(16);
+        let now = Instant::now();
+        limiter.reserve_at(16 * 1024, now);
+
+        assert_eq!(
+            limiter.reserve_at(16 * 1024, now + Duration::from_secs(1)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            limiter.reserve_at(16 * 1024, now + Duration::from_secs(10)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            limiter.reserve_at(16 * 1024, now + Duration::from_secs(10)),
+            Duration::from_secs(1)
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+
+use crate::peer::bt_peer::BtPeer;
+
+/// Keeps track of the peers we are uploading to.
+///
+/// A peer can only be unchoked if there is a free upload slot, the amount of slots is given by the `max_upload_slots` setting.
+///
+/// Snubbed peers, which stopped sending us the blocks we requested, are deprioritized: they can not take the last free slot.
+#[derive(Debug)]
+pub struct Choker {
+    max_upload_slots: usize,
+    unchoked_peers: HashSet<BtPeer>,
+    snubbed_peers: HashSet<BtPeer>,
+}
+
+impl Choker {
+    /// Creates a new `Choker` with the given amount of upload slots.
+    pub fn new(max_upload_slots: usize) -> Self {
+        Self {
+            max_upload_slots,
+            unchoked_peers: HashSet::new(),
+            snubbed_peers: HashSet::new(),
+        }
+    }
+
+    /// Tries to take an upload slot for the peer.
+    ///
+    /// Returns true if the peer can be unchoked. If the peer was already unchoked it keeps its slot.
+    pub fn unchoke(&mut self, peer: &BtPeer) -> bool {
+        if self.unchoked_peers.contains(peer) {
+            return true;
+        }
+        let free_slots = self
+            .max_upload_slots
+            .saturating_sub(self.unchoked_peers.len());
+        if free_slots == 0 || (free_slots == 1 && self.snubbed_peers.contains(peer)) {
+            return false;
+        }
+        self.unchoked_peers.insert(peer.clone());
+        true
+    }
+
+    /// Frees the upload slot of the peer.
+    pub fn choke(&mut self, peer: &BtPeer) {
+        self.unchoked_peers.remove(peer);
+    }
+
+    /// Marks or unmarks a peer as snubbed.
+    pub fn set_snubbed(&mut self, peer: &BtPeer, snubbed: bool) {
+        if snubbed {
+            self.snubbed_peers.insert(peer.clone());
+        } else {
+            self.snubbed_peers.remove(peer);
+        }
+    }
+
+    /// Returns the number of peers currently unchoked.
+    pub fn unchoked_peers(&self) -> usize {
+        self.unchoked_peers.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unchoke_until_slots_are_full() {
+        let mut choker = Choker::new(2);
+
+        assert!(choker.unchoke(&BtPeer::new("127.0.0.1".to_string(), 1)));
+        assert!(choker.unchoke(&BtPeer::new("127.0.0.1".to_string(), 2)));
+        assert!(!choker.unchoke(&BtPeer::new("127.0.0.1".to_string(), 3)));
+        assert_eq!(choker.unchoked_peers(), 2);
+    }
+
+    #[test]
+    fn test_unchoked_peer_keeps_its_slot() {
+        let mut choker = Choker::new(1);
+        let peer = BtPeer::new("127.0.0.1".to_string(), 1);
+
+        assert!(choker.unchoke(&peer));
+        assert!(choker.unchoke(&peer));
+        assert_eq!(choker.unchoked_peers(), 1);
+    }
+
+    #[test]
+    fn test_snubbed_peer_can_not_take_the_last_slot() {
+        let mut choker = Choker::new(2);
+        let snubbed_peer = BtPeer::new("127.0.0.1".to_string(), 1);
+        choker.set_snubbed(&snubbed_peer, true);
+
+        assert!(choker.unchoke(&snubbed_peer));
+        choker.choke(&snubbed_peer);
+        assert!(choker.unchoke(&BtPeer::new("127.0.0.1".to_string(), 2)));
+        assert!(!choker.unchoke(&snubbed_peer));
+
+        choker.set_snubbed(&snubbed_peer, false);
+        assert!(choker.unchoke(&snubbed_peer));
+    }
+
+    #[test]
+    fn test_choke_frees_the_slot() {
+        let mut choker = Choker::new(1);
+        let peer = BtPeer::new("127.0.0.1".to_string(), 1);
+        let other_peer = BtPeer::new("127.0.0.1".to_string(), 2);
+
+        assert!(choker.unchoke(&peer));
+        choker.choke(&peer);
+        assert!(choker.unchoke(&other_peer));
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    io,
+    path::Path,
+    process::{Child, Command},
+};
+
+use crate::torrent_parser::torrent::Torrent;
+
+/// Environment variables with the torrent the `on_complete_command` runs for.
+pub const TORRENT_NAME_VAR: &str = "DTORRENT_TORRENT_NAME";
+pub const TORRENT_PATH_VAR: &str = "DTORRENT_TORRENT_PATH";
+pub const INFO_HASH_VAR: &str = "DTORRENT_INFO_HASH";
+
+/// Starts `command` in the shell of the system for the finished `torrent`, whose data is kept at `path`.
+///
+/// The name, path and hex info hash of the torrent are passed in the `DTORRENT_TORRENT_NAME`,
+/// `DTORRENT_TORRENT_PATH` and `DTORRENT_INFO_HASH` environment variables, the path is empty if the data is not
+/// kept in a file. The command is not waited for.
+///
+/// # Errors
+///
+/// - `io::Error` if the shell could not be started.
+pub fn spawn(command: &str, torrent: &Torrent, path: Option<&Path>) -> io::Result<Child> {
+    shell(command)
+        .env(TORRENT_NAME_VAR, torrent.name())
+        .env(
+            TORRENT_PATH_VAR,
+            path.map(|path| path.as_os_str()).unwrap_or_default(),
+        )
+        .env(INFO_HASH_VAR, torrent.info_hash().to_hex())
+        .spawn()
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_support::{fixtures::test_torrent, temp_dir::TempDir};
+
+    #[test]
+    fn test_command_gets_the_torrent_in_the_environment() {
+        let dir = TempDir::new("test_completion_command_environment");
+        let output = dir.join("output");
+        let command = format!(
+            "echo \"$DTORRENT_TORRENT_NAME $DTORRENT_TORRENT_PATH $DTORRENT_INFO_HASH\" > {}",
+            output.display()
+        );
+        let torrent = test_torrent("movie.mkv", "announce");
+
+        let status = spawn(&command, &torrent, Some(Path::new("/downloads/movie.mkv")))
+            .unwrap()
+            .wait()
+            .unwrap();
+
+        assert!(status.success());
+        assert_eq!(
+            fs::read_to_string(output).unwrap(),
+            format!(
+                "movie.mkv /downloads/movie.mkv {}\n",
+                torrent.info_hash().to_hex()
+            )
+        );
+    }
+}
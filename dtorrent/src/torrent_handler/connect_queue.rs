@@ -0,0 +1,81 @@
+use std::collections::VecDeque;
+
+use crate::peer::bt_peer::BtPeer;
+
+/// Queue of peers waiting for an outgoing connection.
+///
+/// Peers that previously provided data are put at the front of the queue, so they are connected first.
+#[derive(Debug, Default)]
+pub struct PeerConnectQueue {
+    queue: VecDeque<BtPeer>,
+}
+
+impl PeerConnectQueue {
+    /// Creates a new empty `PeerConnectQueue`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a peer to the queue if it was not already queued.
+    ///
+    /// `provided_data` tells if we already downloaded pieces from this peer.
+    pub fn push(&mut self, peer: BtPeer, provided_data: bool) {
+        if self.queue.contains(&peer) {
+            return;
+        }
+        if provided_data {
+            self.queue.push_front(peer);
+        } else {
+            self.queue.push_back(peer);
+        }
+    }
+
+    /// Removes the next peer to connect to from the queue.
+    pub fn pop(&mut self) -> Option<BtPeer> {
+        self.queue.pop_front()
+    }
+
+    /// Returns the number of queued peers.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns true if there are no queued peers.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peers_are_popped_in_order() {
+        let mut queue = PeerConnectQueue::new();
+        queue.push(BtPeer::new("127.0.0.1".to_string(), 1), false);
+        queue.push(BtPeer::new("127.0.0.1".to_string(), 2), false);
+
+        assert_eq!(queue.pop().unwrap().port, 1);
+        assert_eq!(queue.pop().unwrap().port, 2);
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn test_peers_that_provided_data_go_first() {
+        let mut queue = PeerConnectQueue::new();
+        queue.push(BtPeer::new("127.0.0.1".to_string(), 1), false);
+        queue.push(BtPeer::new("127.0.0.1".to_string(), 2), true);
+
+        assert_eq!(queue.pop().unwrap().port, 2);
+    }
+
+    #[test]
+    fn test_duplicated_peers_are_not_queued() {
+        let mut queue = PeerConnectQueue::new();
+        queue.push(BtPeer::new("127.0.0.1".to_string(), 1), false);
+        queue.push(BtPeer::new("127.0.0.1".to_string(), 1), true);
+
+        assert_eq!(queue.len(), 1);
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// Seconds an address is not dialed after a failed connection, doubled on each consecutive failure.
+const UNREACHABLE_COOLDOWN_SECS: u64 = 30;
+const MAX_UNREACHABLE_COOLDOWN_SECS: u64 = 30 * 60;
+
+/// Registry of the outgoing peer connections of every torrent, shared by their `TorrentHandler`s.
+///
+/// It caps the connections open to the same IP, and remembers the addresses that could not be reached so the
+/// other torrents do not dial them again until their cool-down ends.
+#[derive(Debug)]
+pub struct PeerConnectionRegistry {
+    max_connections_per_ip: usize,
+    state: Mutex<RegistryState>,
+}
+
+#[derive(Debug, Default)]
+struct RegistryState {
+    connections: HashMap<IpAddr, usize>,
+    unreachable: HashMap<SocketAddr, Unreachable>,
+}
+
+/// An address that could not be connected to.
+#[derive(Debug, Clone, Copy)]
+struct Unreachable {
+    failures: u32,
+    until: Instant,
+}
+
+/// Reasons a connection to a peer is not started.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PeerConnectionRegistryError {
+    Unreachable,
+    TooManyConnectionsToIp,
+    PoisonedRegistryLock,
+}
+
+impl PeerConnectionRegistry {
+    /// Creates an empty registry that allows `max_connections_per_ip` connections to the same IP, 0 for no limit.
+    pub fn new(max_connections_per_ip: u32) -> Self {
+        let max_connections_per_ip = match max_connections_per_ip {
+            0 => usize::MAX,
+            max => max as usize,
+        };
+        Self {
+            max_connections_per_ip,
+            state: Mutex::new(RegistryState::default()),
+        }
+    }
+
+    /// Reserves a connection to `address`, which must be released with `release` when it ends.
+    ///
+    /// # Errors
+    ///
+    /// - `Unreachable` if a previous connection to the address failed and its cool-down did not end.
+    /// - `TooManyConnectionsToIp` if there are already `max_connections_per_ip` connections to its IP.
+    /// - `PoisonedRegistryLock` if the lock of the registry is poisoned.
+    pub fn reserve(&self, address: SocketAddr) -> Result<(), PeerConnectionRegistryError> {
+        let mut state = self.lock()?;
+        if let Some(unreachable) = state.unreachable.get(&address) {
+            if Instant::now() < unreachable.until {
+                return Err(PeerConnectionRegistryError::Unreachable);
+            }
+        }
+
+        let connections = state.connections.entry(address.ip()).or_insert(0);
+        if *connections >= self.max_connections_per_ip {
+            return Err(PeerConnectionRegistryError::TooManyConnectionsToIp);
+        }
+        *connections += 1;
+        Ok(())
+    }
+
+    /// Releases the connection to `address` reserved with `reserve`.
+    ///
+    /// `reached` tells if the peer answered, otherwise it is not dialed again by any torrent for a cool-down that
+    /// grows with each consecutive failure.
+    ///
+    /// # Errors
+    ///
+    /// - `PoisonedRegistryLock` if the lock of the registry is poisoned.
+    pub fn release(
+        &self,
+        address: SocketAddr,
+        reached: bool,
+    ) -> Result<(), PeerConnectionRegistryError> {
+        let mut state = self.lock()?;
+        if let Some(connections) = state.connections.get_mut(&address.ip()) {
+            *connections = connections.saturating_sub(1);
+            if *connections == 0 {
+                state.connections.remove(&address.ip());
+            }
+        }
+
+        if reached {
+            state.unreachable.remove(&address);
+        } else {
+            let failures = state
+                .unreachable
+                .get(&address)
+                .map_or(0, |unreachable| unreachable.failures)
+                + 1;
+            state.unreachable.insert(
+                address,
+                Unreachable {
+                    failures,
+                    until: Instant::now() + unreachable_cooldown(failures),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the number of open connections to `ip` among every torrent.
+    pub fn connections_to(&self, ip: IpAddr) -> Result<usize, PeerConnectionRegistryError> {
+        Ok(self.lock()?.connections.get(&ip).copied().unwrap_or(0))
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, RegistryState>, PeerConnectionRegistryError> {
+        self.state
+            .lock()
+            .map_err(|_| PeerConnectionRegistryError::PoisonedRegistryLock)
+    }
+}
+
+/// Returns how long an address is not dialed after `failures` consecutive failed connections.
+fn unreachable_cooldown(failures: u32) -> Duration {
+    let secs = UNREACHABLE_COOLDOWN_SECS.saturating_mul(1 << failures.saturating_sub(1).min(16));
+    Duration::from_secs(secs.min(MAX_UNREACHABLE_COOLDOWN_SECS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connections_per_ip_are_capped() {
+        let registry = PeerConnectionRegistry::new(2);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        registry.reserve(SocketAddr::new(ip, 6881)).unwrap();
+        registry.reserve(SocketAddr::new(ip, 6882)).unwrap();
+
+        assert_eq!(
+            registry.reserve(SocketAddr::new(ip, 6883)),
+            Err(PeerConnectionRegistryError::TooManyConnectionsToIp)
+        );
+        assert!(registry.reserve("10.0.0.2:6881".parse().unwrap()).is_ok());
+
+        registry.release(SocketAddr::new(ip, 6881), true).unwrap();
+        assert_eq!(registry.connections_to(ip), Ok(1));
+        assert!(registry.reserve(SocketAddr::new(ip, 6883)).is_ok());
+    }
+
+    #[test]
+    fn test_no_limit_of_connections_per_ip() {
+        let registry = PeerConnectionRegistry::new(0);
+
+        for port in 0..10 {
+            registry
+                .reserve(SocketAddr::new("10.0.0.1".parse().unwrap(), port))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_unreachable_address_is_not_dialed_again() {
+        let registry = PeerConnectionRegistry::new(0);
+        let address = "10.0.0.1:6881".parse().unwrap();
+
+        registry.reserve(address).unwrap();
+        registry.release(address, false).unwrap();
+
+        assert_eq!(
+            registry.reserve(address),
+            Err(PeerConnectionRegistryError::Unreachable)
+        );
+        assert!(registry.reserve("10.0.0.1:6882".parse().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_unreachable_cooldown_grows_until_the_maximum() {
+        assert_eq!(unreachable_cooldown(1), Duration::from_secs(30));
+        assert_eq!(unreachable_cooldown(3), Duration::from_secs(120));
+        assert_eq!(
+            unreachable_cooldown(100),
+            Duration::from_secs(MAX_UNREACHABLE_COOLDOWN_SECS)
+        );
+    }
+}
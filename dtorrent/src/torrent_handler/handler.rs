@@ -1,6 +1,14 @@
-use super::status::{AtomicTorrentStatus, AtomicTorrentStatusError};
+use super::{
+    connect_queue::PeerConnectQueue,
+    connection_registry::{PeerConnectionRegistry, PeerConnectionRegistryError},
+    seed_limits::SeedLimits,
+    status::{AtomicTorrentStatus, AtomicTorrentStatusError},
+};
 use crate::{
+    bt_server::server::{global_current_peers, SharedTorrents},
     config::cfg::Cfg,
+    http_seed::{client::HttpSeed, downloader::HttpSeedDownloader},
+    nat::{self, SharedPortMapping},
     peer::{
         bt_peer::BtPeer,
         peer_session::{PeerSession, PeerSessionError},
@@ -12,14 +20,25 @@ use crate::{
     },
 };
 use std::{
-    sync::{
-        mpsc::{self, Receiver},
-        Arc,
-    },
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, RwLock},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
-use tracing::{error, info, warn};
+use tracing::{error, info, info_span, warn};
+
+/// Milliseconds between checks of the connection limits, which can be changed by a config reload without any peer
+/// connecting or disconnecting, and of a torrent finished by the connected peers.
+const SLOTS_POLL_MILLIS: u64 = 100;
+
+/// Milliseconds between checks of the seed limits of a finished torrent.
+const SEED_LIMITS_POLL_MILLIS: u64 = 1000;
+
+/// Seconds to wait before announcing again after the first announce no tracker answered, doubled on each
+/// consecutive failure.
+const TRACKER_FAILURE_BACKOFF_SECS: u64 = 15;
+const MAX_TRACKER_FAILURE_BACKOFF_SECS: u64 = 30 * 60;
 
 /// Struct for handling the torrent download.
 ///
@@ -29,8 +48,11 @@ pub struct TorrentHandler {
     torrent: Torrent,
     config: Cfg,
     torrent_status: Arc<AtomicTorrentStatus>,
-    torrent_status_receiver: Receiver<usize>,
     client_peer_id: String,
+    client_port: u16,
+    port_mapping: SharedPortMapping,
+    connection_registry: Arc<PeerConnectionRegistry>,
+    torrents: SharedTorrents,
 }
 
 /// Posible torrent handler errors.
@@ -39,88 +61,285 @@ pub enum TorrentHandlerError {
     TrackerError(TrackerHandlerError),
     TorrentStatusError(AtomicTorrentStatusError),
     PeerSessionError(PeerSessionError),
-    TorrentStatusRecvError(mpsc::RecvError),
+    ConnectionRegistryError(PeerConnectionRegistryError),
 }
 
 impl TorrentHandler {
     /// Creates a new `TorrentHandler` from a torrent, a config and a logger sender.
+    ///
+    /// The torrent uses the settings of its section of the config, if it has one.
     pub fn new(torrent: Torrent, config: Cfg, client_peer_id: String) -> Self {
-        let (torrent_status, torrent_status_receiver) =
-            AtomicTorrentStatus::new(&torrent, config.clone());
+        let config = config.for_torrent(&torrent.info_hash());
+        let torrent_status = Arc::new(AtomicTorrentStatus::new(&torrent, config.clone()));
+        let torrents = HashMap::from([(torrent.info_hash(), torrent_status.clone())]);
 
         Self {
-            torrent_status: Arc::new(torrent_status),
+            torrent_status,
+            torrents: Arc::new(RwLock::new(torrents)),
             torrent,
+            client_port: config.tcp_port,
+            port_mapping: Arc::default(),
+            connection_registry: Arc::new(PeerConnectionRegistry::new(
+                config.max_connections_per_ip,
+            )),
             config,
-            torrent_status_receiver,
             client_peer_id,
         }
     }
 
+    /// Sets the port announced to the tracker, it must be the port the `BtServer` is bound to.
+    pub fn set_client_port(&mut self, client_port: u16) {
+        self.client_port = client_port;
+    }
+
+    /// Announces the external IP and port of the port mapped in the gateway instead of the local ones, once there
+    /// is a mapping. The mapping is read on every announce, so the renewed ones are announced too.
+    pub fn set_port_mapping(&mut self, port_mapping: SharedPortMapping) {
+        self.port_mapping = port_mapping;
+    }
+
+    /// Shares the registry of outgoing peer connections with the other torrents, so they do not exceed the
+    /// connections per IP together nor dial the peers another torrent could not reach.
+    pub fn set_connection_registry(&mut self, connection_registry: Arc<PeerConnectionRegistry>) {
+        self.connection_registry = connection_registry;
+    }
+
+    /// Shares the torrents served by the `BtServer`, so the outgoing connections count the peers of all of them
+    /// against the `max_global_connections` setting. The status of this torrent must be one of them.
+    pub fn set_torrents(&mut self, torrents: SharedTorrents) {
+        self.torrents = torrents;
+    }
+
     /// Starts the torrent download.
     ///
-    /// First it connects to the tracker and gets the peers. Then it connects to each peer and starts the download.
+    /// First it connects to the tracker and gets the peers and reserves the space of the torrent in its storage.
+    /// Then it connects to each peer and starts the download.
+    ///
+    /// Peers are queued and connected a few at a time, given by the `max_half_open_connections` setting. No peer is
+    /// connected while the torrent is paused, see `AtomicTorrentStatus::pause`.
+    /// Peers that already provided data are connected first. Peers that are unreachable or whose IP has too many
+    /// connections in the connection registry are skipped until the next announce.
+    ///
+    /// The pieces are also downloaded from the HTTP seeds of the torrent, if it has any, see `start_http_seeds`.
+    ///
+    /// Each announce tries the trackers of the torrent until one answers. If none of them does, the announce is retried
+    /// after a delay that grows with each consecutive failure. The health of the trackers is kept in the status.
+    ///
+    /// A removed torrent, see `AtomicTorrentStatus::remove`, stops without announcing anything else.
+    ///
+    /// Once the download finishes, if the torrent has a `seed_ratio_limit` or a `seed_time_limit_seconds` it keeps
+    /// seeding until one of them is reached, see `seed_until_limit`. Otherwise it returns and keeps seeding forever.
     ///
     /// # Errors
     ///
-    /// - `TrackerErr` if there was a problem connecting to the tracker or getting the peers.
+    /// - `TrackerErr` if none of the tracker urls of the torrent is valid.
     /// - `TorrentStatusError` if there was a problem using the `Torrent Status`.
-    /// - `TorrentStatusRecvError` if there was a problem receiving from the receiver of `Torrent Status`.
     pub fn handle(&mut self) -> Result<(), TorrentHandlerError> {
-        let tracker_handler = TrackerHandler::new(
+        let span = info_span!("torrent", torrent = %self.torrent.info.name);
+        let _enter = span.enter();
+
+        let mut tracker_handler = TrackerHandler::new(
             self.torrent.clone(),
-            self.config.tcp_port.into(),
+            self.client_port.into(),
             self.client_peer_id.clone(),
         )
         .map_err(TorrentHandlerError::TrackerError)?;
+        tracker_handler.set_proxy(self.config.proxy());
+        tracker_handler.set_numwant(Some(self.config.numwant));
         info!("Connected to tracker.");
 
+        self.torrent_status
+            .allocate()
+            .map_err(TorrentHandlerError::TorrentStatusError)?;
+        self.start_http_seeds();
+
+        let mut connect_queue = PeerConnectQueue::new();
+        let mut tracker_failures = 0;
+
         while !self.torrent_status.is_finished() {
-            let peer_list = self.get_peers_list(&tracker_handler)?;
+            if self.torrent_status.is_removed() {
+                info!("Torrent removed.");
+                return Ok(());
+            }
+            let peer_list = match self.get_peers_list(&mut tracker_handler) {
+                Ok(peer_list) => peer_list,
+                Err(TorrentHandlerError::TrackerError(err)) => {
+                    tracker_failures += 1;
+                    let backoff = tracker_failure_backoff(tracker_failures);
+                    warn!(
+                        "No tracker answered, last error: {:?}, announcing again in {:?}.",
+                        err, backoff
+                    );
+                    self.wait_before_announcing(backoff);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            tracker_failures = 0;
             info!("Tracker peer list obtained.");
 
-            // Start connection with each peer
             for peer in peer_list {
-                let current_peers = self.torrent_status.all_current_peers();
-
-                // If we reached the maximum number of simultaneous peers, wait until the status tells us that one disconnected.
-                if current_peers >= self.config.max_peers_per_torrent as usize {
-                    // This while loop is done to prevent creating more peers than allowed when multiple peers are disconnected at the same time.
-                    self.torrent_status_receiver
-                        .recv()
-                        .map_err(TorrentHandlerError::TorrentStatusRecvError)?;
-                    while self
-                        .torrent_status_receiver
-                        .recv_timeout(Duration::from_nanos(1))
-                        .is_ok()
-                    {
-                        continue;
-                    }
-                }
-                if self.torrent_status.is_finished() {
-                    break;
+                if self
+                    .torrent_status
+                    .is_banned(&peer)
+                    .map_err(TorrentHandlerError::TorrentStatusError)?
+                {
+                    continue;
                 }
-
-                let connected_peers = self
+                let provided_data = self
                     .torrent_status
-                    .get_connected_peers()
+                    .has_provided_data(&peer)
                     .map_err(TorrentHandlerError::TorrentStatusError)?;
+                connect_queue.push(peer, provided_data);
+            }
+
+            // Start connection with each queued peer
+            while let Some(peer) = connect_queue.pop() {
+                self.wait_while_paused()?;
+                self.wait_for_connection_slot()?;
+
+                if self.torrent_status.is_finished() || self.torrent_status.is_removed() {
+                    break;
+                }
 
                 // Avoid connecting to the same peer twice.
-                if connected_peers.contains_key(&peer) {
+                if self
+                    .torrent_status
+                    .is_connected(&peer)
+                    .map_err(TorrentHandlerError::TorrentStatusError)?
+                {
                     continue;
                 }
 
-                let current_peers = self.torrent_status.all_current_peers();
-                if current_peers < self.config.max_peers_per_torrent as usize {
-                    self.connect_to_peer(peer)?;
+                let address = peer.socket_addr();
+                if let Some(address) = address {
+                    match self.connection_registry.reserve(address) {
+                        Ok(()) => (),
+                        Err(PeerConnectionRegistryError::PoisonedRegistryLock) => {
+                            return Err(TorrentHandlerError::ConnectionRegistryError(
+                                PeerConnectionRegistryError::PoisonedRegistryLock,
+                            ))
+                        }
+                        Err(err) => {
+                            info!("Skipping peer {}: {:?}", address, err);
+                            continue;
+                        }
+                    }
                 }
+
+                self.connect_to_peer(peer, address)?;
             }
         }
         info!("Torrent download finished.");
+        self.seed_until_limit(&mut tracker_handler)
+    }
+
+    /// Blocks until the finished torrent reaches one of its seed limits, if it has any.
+    ///
+    /// Then the torrent stops seeding, see `AtomicTorrentStatus::stop_seeding`, and the `stopped` event is announced
+    /// to the trackers.
+    fn seed_until_limit(
+        &self,
+        tracker_handler: &mut TrackerHandler,
+    ) -> Result<(), TorrentHandlerError> {
+        let limits = SeedLimits::from_config(&self.config);
+        if !limits.is_enabled() {
+            return Ok(());
+        }
+        info!("Seeding until a seed limit is reached.");
+
+        let seeding_since = Instant::now();
+        let limit = loop {
+            if self.torrent_status.is_removed() {
+                return Ok(());
+            }
+            let seeding_time = seeding_since.elapsed();
+            if let Some(limit) = limits.reached(self.torrent_status.seed_ratio(), seeding_time) {
+                break limit;
+            }
+            let poll = limits
+                .time_left(seeding_time)
+                .unwrap_or(Duration::MAX)
+                .min(Duration::from_millis(SEED_LIMITS_POLL_MILLIS));
+            thread::sleep(poll);
+        };
+
+        self.torrent_status
+            .stop_seeding()
+            .map_err(TorrentHandlerError::TorrentStatusError)?;
+        let stats = self
+            .torrent_status
+            .stats()
+            .map_err(TorrentHandlerError::TorrentStatusError)?;
+        tracker_handler.set_port_mapping(nat::current_mapping(&self.port_mapping));
+        tracker_handler.announce_stopped(&stats);
+        self.torrent_status
+            .update_trackers_health(tracker_handler.health())
+            .map_err(TorrentHandlerError::TorrentStatusError)?;
+        info!(
+            event = "TorrentSeedingComplete",
+            ?limit,
+            seed_ratio = self.torrent_status.seed_ratio(),
+            seeding_seconds = seeding_since.elapsed().as_secs(),
+            "Seed limit reached, the torrent stopped seeding."
+        );
+        Ok(())
+    }
+
+    /// Blocks until a new outgoing connection can be started, or the torrent is removed.
+    ///
+    /// A connection can be started if we did not reach the maximum number of simultaneous peers, neither of this
+    /// torrent nor between all the torrents, and the maximum number of half-open connections.
+    fn wait_for_connection_slot(&self) -> Result<(), TorrentHandlerError> {
+        // Woken when a peer connects or disconnects, so several disconnections at once are seen in a single check.
+        while !self
+            .torrent_status
+            .wait_for_peers(Some(Duration::from_millis(SLOTS_POLL_MILLIS)), || {
+                self.has_connection_slot() || self.torrent_status.is_removed()
+            })
+            .map_err(TorrentHandlerError::TorrentStatusError)?
+        {}
         Ok(())
     }
 
+    fn has_connection_slot(&self) -> bool {
+        self.torrent_status.all_current_peers() < self.config.max_peers() as usize
+            && self.torrent_status.half_open_peers()
+                < self.config.runtime.max_half_open_connections() as usize
+            && self.global_current_peers() < self.config.runtime.max_global_connections() as usize
+    }
+
+    /// Returns the number of peers connected and connecting between all the torrents.
+    fn global_current_peers(&self) -> usize {
+        match self.torrents.read() {
+            Ok(torrents) => global_current_peers(&torrents),
+            // The map is only written to insert or remove whole torrents, so it is still valid.
+            Err(poisoned) => global_current_peers(&poisoned.into_inner()),
+        }
+    }
+
+    /// Blocks until the torrent is resumed or removed, if it is paused.
+    fn wait_while_paused(&self) -> Result<(), TorrentHandlerError> {
+        self.torrent_status
+            .wait_for_peers(None, || {
+                !self.torrent_status.is_paused() || self.torrent_status.is_removed()
+            })
+            .map_err(TorrentHandlerError::TorrentStatusError)?;
+        Ok(())
+    }
+
+    /// Blocks for `delay`, or until the torrent is finished by the peers that are already connected or removed.
+    fn wait_before_announcing(&self, delay: Duration) {
+        let deadline = Instant::now() + delay;
+        while !self.torrent_status.is_finished() && !self.torrent_status.is_removed() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            thread::sleep(remaining.min(Duration::from_millis(SLOTS_POLL_MILLIS)));
+        }
+    }
+
     /// Gets the status of the torrent.
     pub fn status(&self) -> Arc<AtomicTorrentStatus> {
         self.torrent_status.clone()
@@ -128,11 +347,22 @@ impl TorrentHandler {
 
     fn get_peers_list(
         &self,
-        tracker_handler: &TrackerHandler,
+        tracker_handler: &mut TrackerHandler,
     ) -> Result<Vec<BtPeer>, TorrentHandlerError> {
-        let tracker_response = tracker_handler
-            .get_peers_list()
-            .map_err(TorrentHandlerError::TrackerError)?;
+        let stats = self
+            .torrent_status
+            .stats()
+            .map_err(TorrentHandlerError::TorrentStatusError)?;
+
+        tracker_handler.set_port_mapping(nat::current_mapping(&self.port_mapping));
+        let tracker_response = tracker_handler.get_peers_list(&stats);
+        self.torrent_status
+            .update_trackers_health(tracker_handler.health())
+            .map_err(TorrentHandlerError::TorrentStatusError)?;
+        let tracker_response = tracker_response.map_err(|err| {
+            self.torrent_status.add_tracker_error();
+            TorrentHandlerError::TrackerError(err)
+        })?;
 
         self.update_total_peers(&tracker_response);
 
@@ -154,7 +384,44 @@ impl TorrentHandler {
         }
     }
 
-    fn connect_to_peer(&mut self, peer: BtPeer) -> Result<(), TorrentHandlerError> {
+    /// Starts an `HttpSeedDownloader` in a new thread for each HTTP seed of the torrent.
+    ///
+    /// Seeds with an invalid url, or that fail too many times in a row, are only logged, the peers keep downloading.
+    fn start_http_seeds(&self) {
+        for url in &self.torrent.http_seeds {
+            let seed = match HttpSeed::new(url) {
+                Ok(seed) => seed.with_proxy(self.config.proxy()),
+                Err(err) => {
+                    warn!("Skipping HTTP seed: {:?}", err);
+                    continue;
+                }
+            };
+            let downloader =
+                HttpSeedDownloader::new(seed, self.torrent.clone(), self.torrent_status.clone());
+
+            let builder = thread::Builder::new().name(format!(
+                "Torrent: {} / HTTP seed: {}",
+                self.torrent.info.name, url
+            ));
+            let span = info_span!("http_seed", torrent = %self.torrent.info.name, seed = %url);
+            if let Err(err) = builder.spawn(move || {
+                let _enter = span.enter();
+                if let Err(err) = downloader.run() {
+                    warn!("HTTP seed stopped: {:?}", err);
+                }
+            }) {
+                error!("{:?}", err);
+            }
+        }
+    }
+
+    /// Starts a session with the peer in a new thread, which releases the connection reserved for its `address`
+    /// in the connection registry when it ends.
+    fn connect_to_peer(
+        &mut self,
+        peer: BtPeer,
+        address: Option<SocketAddr>,
+    ) -> Result<(), TorrentHandlerError> {
         self.torrent_status.peer_connecting();
         let peer_name = format!("{}:{}", peer.ip, peer.port);
 
@@ -172,21 +439,262 @@ impl TorrentHandler {
             self.torrent.info.name, peer_name
         ));
 
-        let join = builder.spawn(move || match peer_session.start_outgoing_seeder() {
-            Ok(_) => (),
-            Err(err) => {
+        let span = info_span!("peer", torrent = %self.torrent.info.name, peer = %peer_name);
+        let connection_registry = self.connection_registry.clone();
+        let join = builder.spawn(move || {
+            let _enter = span.enter();
+            let result = peer_session.start_outgoing();
+            if let Err(err) = &result {
                 warn!("{:?}", err);
             }
+            if let Some(address) = address {
+                let reached = !matches!(result, Err(PeerSessionError::CouldNotConnectToPeer));
+                if let Err(err) = connection_registry.release(address, reached) {
+                    error!("{:?}", err);
+                }
+            }
         });
         match join {
             Ok(_) => (),
             Err(err) => {
                 error!("{:?}", err);
-                self.torrent_status
-                    .peer_disconnected(&peer)
-                    .map_err(TorrentHandlerError::TorrentStatusError)?;
+                self.torrent_status.peer_connecting_failed();
+                if let Some(address) = address {
+                    self.connection_registry
+                        .release(address, true)
+                        .map_err(TorrentHandlerError::ConnectionRegistryError)?;
+                }
             }
         }
         Ok(())
     }
 }
+
+/// Returns the delay before announcing again after `failures` consecutive announces no tracker answered.
+fn tracker_failure_backoff(failures: u32) -> Duration {
+    let secs = TRACKER_FAILURE_BACKOFF_SECS.saturating_mul(1 << failures.saturating_sub(1).min(16));
+    Duration::from_secs(secs.min(MAX_TRACKER_FAILURE_BACKOFF_SECS))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, net::Ipv4Addr, sync::mpsc, time::Duration};
+
+    use sha1::{Digest, Sha1};
+
+    use super::*;
+    use crate::nat::PortMapping;
+    use crate::test_support::{
+        fixtures::test_config,
+        local_swarm::{generated_torrent, LocalSeeder, LEECHER_PEER_ID},
+        mock_http_seed::MockHttpSeed,
+        mock_tracker::{peers_response, MockTracker},
+        temp_dir::TempDir,
+    };
+
+    const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn test_downloads_torrent_from_local_seeder() {
+        let dir = TempDir::new("test_downloads_torrent_from_local_seeder");
+        let config = test_config(&dir);
+        // The last piece is shorter and spans more than one block.
+        let (torrent, data) = generated_torrent("local_swarm", 100_000, 32 * 1024, "");
+        download_from_local_seeder(torrent, data, config);
+    }
+
+    #[test]
+    fn test_downloads_torrent_shorter_than_a_block() {
+        let dir = TempDir::new("test_downloads_torrent_shorter_than_a_block");
+        let config = test_config(&dir);
+        // A single piece, shorter than the piece length and than one block.
+        let (torrent, data) = generated_torrent("tiny_swarm", 100, 32 * 1024, "");
+        download_from_local_seeder(torrent, data, config);
+    }
+
+    #[test]
+    fn test_tracker_failure_backoff_grows_until_the_maximum() {
+        assert_eq!(tracker_failure_backoff(1), Duration::from_secs(15));
+        assert_eq!(tracker_failure_backoff(3), Duration::from_secs(60));
+        assert_eq!(
+            tracker_failure_backoff(100),
+            Duration::from_secs(MAX_TRACKER_FAILURE_BACKOFF_SECS)
+        );
+    }
+
+    #[test]
+    fn test_stops_seeding_when_the_seed_time_limit_is_reached() {
+        let dir = TempDir::new("test_stops_seeding_when_the_seed_time_limit_is_reached");
+        let config = Cfg {
+            seed_time_limit_seconds: 1,
+            ..test_config(&dir)
+        };
+        let (torrent, data) = generated_torrent("seed_time_limit_swarm", 100, 32 * 1024, "");
+        let seeder = LocalSeeder::start(&torrent, &data, config.clone());
+        let tracker = MockTracker::start(peers_response(60, &[seeder.address()]));
+        let torrent = Torrent {
+            announce_url: tracker.announce_url(),
+            ..torrent
+        };
+
+        let mut leecher = TorrentHandler::new(torrent, config, LEECHER_PEER_ID.to_string());
+        let status = leecher.status();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || sender.send(leecher.handle().is_ok()));
+
+        assert_eq!(receiver.recv_timeout(DOWNLOAD_TIMEOUT), Ok(true));
+        assert!(status.is_finished());
+        assert!(status.is_seeding_stopped());
+        let requests = tracker.requests();
+        assert!(requests.last().unwrap().contains("&event=stopped"));
+    }
+
+    #[test]
+    fn test_removed_torrent_stops_the_handler() {
+        let dir = TempDir::new("test_removed_torrent_stops_the_handler");
+        let config = test_config(&dir);
+        let (torrent, _) = generated_torrent("removed_swarm", 100, 32 * 1024, "");
+        let tracker = MockTracker::start(peers_response(60, &[]));
+        let torrent = Torrent {
+            announce_url: tracker.announce_url(),
+            ..torrent
+        };
+
+        let mut leecher = TorrentHandler::new(torrent, config, LEECHER_PEER_ID.to_string());
+        let status = leecher.status();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || sender.send(leecher.handle().is_ok()));
+        status.remove().unwrap();
+
+        assert_eq!(receiver.recv_timeout(DOWNLOAD_TIMEOUT), Ok(true));
+        assert!(!status.is_finished());
+    }
+
+    #[test]
+    fn test_downloads_torrent_from_http_seed() {
+        let dir = TempDir::new("test_downloads_torrent_from_http_seed");
+        let config = test_config(&dir);
+        let (torrent, data) = generated_torrent("http_seed_swarm", 100_000, 32 * 1024, "");
+        let seed = MockHttpSeed::start(data.clone(), 32 * 1024);
+        let tracker = MockTracker::start(peers_response(60, &[]));
+        let torrent = Torrent {
+            announce_url: tracker.announce_url(),
+            http_seeds: vec![seed.url()],
+            info_hash_v2: None,
+            piece_layer: vec![],
+            ..torrent
+        };
+
+        let mut leecher = TorrentHandler::new(torrent, config, LEECHER_PEER_ID.to_string());
+        let status = leecher.status();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || sender.send(leecher.handle().is_ok()));
+
+        assert_eq!(receiver.recv_timeout(DOWNLOAD_TIMEOUT), Ok(true));
+        assert!(status.is_finished());
+        let downloaded = fs::read(status.output_path().unwrap()).unwrap();
+        assert_eq!(Sha1::digest(downloaded), Sha1::digest(&data));
+    }
+
+    #[test]
+    fn test_waits_for_a_global_connection_slot_before_connecting() {
+        let dir = TempDir::new("test_waits_for_a_global_connection_slot_before_connecting");
+        let config = Cfg {
+            max_global_connections: 1,
+            ..test_config(&dir)
+        };
+        config.runtime.update(&config);
+        let (torrent, data) = generated_torrent("global_slot_swarm", 100, 32 * 1024, "");
+        let seeder = LocalSeeder::start(&torrent, &data, config.clone());
+        let tracker = MockTracker::start(peers_response(60, &[seeder.address()]));
+        let torrent = Torrent {
+            announce_url: tracker.announce_url(),
+            ..torrent
+        };
+
+        // Another torrent already has the only connection allowed.
+        let (other_torrent, _) = generated_torrent("global_slot_other", 100, 32 * 1024, "");
+        let other_status = Arc::new(AtomicTorrentStatus::new(&other_torrent, config.clone()));
+        other_status.peer_connecting();
+        let connection = other_status
+            .peer_connected(&BtPeer::new("10.0.0.1".to_string(), 6881))
+            .unwrap();
+
+        let mut leecher = TorrentHandler::new(torrent, config, LEECHER_PEER_ID.to_string());
+        let status = leecher.status();
+        leecher.set_torrents(Arc::new(RwLock::new(HashMap::from([
+            (other_torrent.info_hash(), other_status.clone()),
+            (status.torrent.info_hash(), status.clone()),
+        ]))));
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || sender.send(leecher.handle().is_ok()));
+
+        thread::sleep(Duration::from_millis(500));
+        assert_eq!(status.all_current_peers(), 0);
+        assert!(!status.is_finished());
+
+        other_status.peer_disconnected(connection).unwrap();
+        assert_eq!(receiver.recv_timeout(DOWNLOAD_TIMEOUT), Ok(true));
+        assert!(status.is_finished());
+    }
+
+    #[test]
+    fn test_announces_the_mapped_port() {
+        let dir = TempDir::new("test_announces_the_mapped_port");
+        let config = test_config(&dir);
+        let (torrent, _) = generated_torrent("mapped_port_swarm", 100, 32 * 1024, "");
+        let tracker = MockTracker::start(peers_response(60, &[]));
+        let torrent = Torrent {
+            announce_url: tracker.announce_url(),
+            ..torrent
+        };
+
+        let mut leecher = TorrentHandler::new(torrent, config, LEECHER_PEER_ID.to_string());
+        leecher.set_client_port(6881);
+        leecher.set_port_mapping(Arc::new(RwLock::new(Some(PortMapping {
+            external_ip: Ipv4Addr::new(203, 0, 113, 7),
+            external_port: 40000,
+        }))));
+        let status = leecher.status();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || sender.send(leecher.handle().is_ok()));
+
+        while tracker.requests().is_empty() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        status.remove().unwrap();
+
+        assert_eq!(receiver.recv_timeout(DOWNLOAD_TIMEOUT), Ok(true));
+        let request = &tracker.requests()[0];
+        assert!(request.contains("&port=40000&"));
+        assert!(request.contains("&ip=203.0.113.7"));
+    }
+
+    fn download_from_local_seeder(torrent: Torrent, data: Vec<u8>, config: Cfg) {
+        let seeder = LocalSeeder::start(&torrent, &data, config.clone());
+        let tracker = MockTracker::start(peers_response(60, &[seeder.address()]));
+        let torrent = Torrent {
+            announce_url: tracker.announce_url(),
+            ..torrent
+        };
+
+        let mut leecher = TorrentHandler::new(torrent, config, LEECHER_PEER_ID.to_string());
+        let status = leecher.status();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || sender.send(leecher.handle().is_ok()));
+
+        assert_eq!(receiver.recv_timeout(DOWNLOAD_TIMEOUT), Ok(true));
+        assert!(status.is_finished());
+        let downloaded = fs::read(status.output_path().unwrap()).unwrap();
+        assert_eq!(Sha1::digest(downloaded), Sha1::digest(&data));
+        let stats = status.stats().unwrap();
+        assert_eq!(stats.left_bytes, 0);
+        // Handshakes, bitfields, requests and the announce are counted apart from the pieces.
+        assert!(stats.overhead_uploaded_bytes > 0);
+        assert!(stats.overhead_downloaded_bytes > 0);
+        assert!(seeder.status().stats().unwrap().uploaded_bytes > 0);
+        let trackers_health = status.trackers_health().unwrap();
+        assert_eq!(trackers_health.len(), 1);
+        assert!(trackers_health[0].last_response.is_some());
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use tracing::error;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Threads that check the hash of the downloaded pieces, so a peer thread keeps serving its socket meanwhile.
+///
+/// The jobs are run in the order they were sent by the first free thread. The threads are stopped once the pool
+/// is dropped and the jobs already sent are done.
+///
+/// `Sha1` picks the SHA instructions of the CPU at runtime when it has them, see the `sha-1` crate.
+#[derive(Debug)]
+pub struct HashPool {
+    sender: Sender<Job>,
+}
+
+/// Posible `HashPool` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HashPoolError {
+    /// Every thread of the pool stopped, so the job can not be run.
+    Stopped,
+}
+
+impl HashPool {
+    /// Starts a pool of `threads` threads, at least one, named after `name`.
+    pub fn new(threads: u32, name: &str) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for i in 0..threads.max(1) {
+            let receiver = receiver.clone();
+            let builder = thread::Builder::new().name(format!("{} / Hashing: {}", name, i));
+            if let Err(err) = builder.spawn(move || Self::work(&receiver)) {
+                error!("Could not start a hashing thread: {:?}", err);
+            }
+        }
+        Self { sender }
+    }
+
+    /// Runs `job` on a thread of the pool.
+    ///
+    /// # Errors
+    ///
+    /// - `Stopped` if no thread of the pool is running.
+    pub fn execute<F>(&self, job: F) -> Result<(), HashPoolError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.sender
+            .send(Box::new(job))
+            .map_err(|_| HashPoolError::Stopped)
+    }
+
+    /// Runs the jobs until the pool is dropped.
+    fn work(receiver: &Mutex<Receiver<Job>>) {
+        loop {
+            // The lock is released before running the job, so the other threads can take the next ones.
+            let job = match receiver.lock() {
+                Ok(receiver) => receiver.recv(),
+                Err(_) => return,
+            };
+            match job {
+                Ok(job) => job(),
+                Err(_) => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_jobs_run_in_parallel() {
+        let pool = HashPool::new(2, "test");
+        let (sender, receiver) = mpsc::channel();
+        let (first_done, wait_first) = mpsc::channel::<()>();
+
+        // The first job blocks its thread until the second one, run by the other thread, is done.
+        let first_sender = sender.clone();
+        pool.execute(move || {
+            let _ = wait_first.recv_timeout(Duration::from_secs(5));
+            first_sender.send(1).unwrap();
+        })
+        .unwrap();
+        pool.execute(move || {
+            sender.send(2).unwrap();
+            first_done.send(()).unwrap();
+        })
+        .unwrap();
+
+        let order: Vec<i32> = receiver.iter().take(2).collect();
+        assert_eq!(order, vec![2, 1]);
+    }
+
+    #[test]
+    fn test_at_least_one_thread() {
+        let pool = HashPool::new(0, "test");
+        let (sender, receiver) = mpsc::channel();
+
+        pool.execute(move || sender.send(()).unwrap()).unwrap();
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(()));
+    }
+}
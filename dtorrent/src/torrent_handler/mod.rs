@@ -1,2 +1,17 @@
+pub mod bandwidth_limiter;
+pub mod cancellation_token;
+pub mod choker;
+pub mod completion_command;
+pub mod connect_queue;
+pub mod connection_registry;
 pub mod handler;
+pub mod hash_pool;
+pub mod peer_slots;
+pub mod peer_snapshot;
+pub mod piece_states;
+pub mod rate_tracker;
+pub mod seed_limits;
 pub mod status;
+pub mod super_seed;
+pub mod torrent_stats;
+pub mod torrent_watcher;
@@ -0,0 +1,184 @@
+use std::{
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Wakes the threads waiting for a change in the peers of a torrent, like a connection slot being freed.
+///
+/// The waiters check their own condition with `wait_until`, so any number of changes is never lost nor queued:
+/// a waiter woken by several disconnections at once sees all of them in a single check. Checks run one at a time,
+/// so a condition that also claims what it waited for, like a slot, is never satisfied twice by the same change.
+#[derive(Debug, Default)]
+pub struct PeerSlots {
+    changes: Mutex<u64>,
+    changed: Condvar,
+}
+
+/// Posible `PeerSlots` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PeerSlotsError {
+    PoisonedChangesLock,
+}
+
+impl PeerSlots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wakes every waiter to check its condition again.
+    ///
+    /// # Errors
+    ///
+    /// - `PoisonedChangesLock` if the lock of the changes is poisoned.
+    pub fn notify(&self) -> Result<(), PeerSlotsError> {
+        let mut changes = self
+            .changes
+            .lock()
+            .map_err(|_| PeerSlotsError::PoisonedChangesLock)?;
+        *changes = changes.wrapping_add(1);
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Blocks until `condition` returns true, checking it on every `notify`, and returns true.
+    ///
+    /// If `timeout` is given, it returns false once it elapses without the condition being met.
+    ///
+    /// # Errors
+    ///
+    /// - `PoisonedChangesLock` if the lock of the changes is poisoned.
+    pub fn wait_until<F>(
+        &self,
+        timeout: Option<Duration>,
+        mut condition: F,
+    ) -> Result<bool, PeerSlotsError>
+    where
+        F: FnMut() -> bool,
+    {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut changes = self
+            .changes
+            .lock()
+            .map_err(|_| PeerSlotsError::PoisonedChangesLock)?;
+
+        // The condition is checked with the lock held, so a change notified after the check wakes the wait below.
+        while !condition() {
+            changes = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Ok(false);
+                    }
+                    self.changed
+                        .wait_timeout(changes, remaining)
+                        .map_err(|_| PeerSlotsError::PoisonedChangesLock)?
+                        .0
+                }
+                None => self
+                    .changed
+                    .wait(changes)
+                    .map_err(|_| PeerSlotsError::PoisonedChangesLock)?,
+            };
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_wait_returns_when_the_condition_is_met() {
+        let slots = PeerSlots::new();
+
+        assert_eq!(slots.wait_until(None, || true), Ok(true));
+    }
+
+    #[test]
+    fn test_wait_times_out() {
+        let slots = PeerSlots::new();
+
+        assert_eq!(
+            slots.wait_until(Some(Duration::from_millis(10)), || false),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn test_notify_wakes_the_waiter() {
+        let slots = Arc::new(PeerSlots::new());
+        let peers = Arc::new(AtomicUsize::new(1));
+
+        let waiter = {
+            let (slots, peers) = (slots.clone(), peers.clone());
+            thread::spawn(move || {
+                slots.wait_until(Some(Duration::from_secs(10)), || {
+                    peers.load(Ordering::SeqCst) == 0
+                })
+            })
+        };
+        thread::sleep(Duration::from_millis(20));
+        peers.store(0, Ordering::SeqCst);
+        slots.notify().unwrap();
+
+        assert_eq!(waiter.join().unwrap(), Ok(true));
+    }
+
+    #[test]
+    fn test_simultaneous_disconnections_free_exactly_their_slots() {
+        const MAX_PEERS: usize = 10;
+        const WAITERS: usize = 30;
+        let slots = Arc::new(PeerSlots::new());
+        let peers = Arc::new(AtomicUsize::new(MAX_PEERS));
+
+        // Every waiter claims a slot when one is free, like a handler starting a connection.
+        let waiters: Vec<_> = (0..WAITERS)
+            .map(|_| {
+                let (slots, peers) = (slots.clone(), peers.clone());
+                thread::spawn(move || {
+                    slots
+                        .wait_until(Some(Duration::from_millis(500)), || {
+                            if peers.load(Ordering::SeqCst) < MAX_PEERS {
+                                peers.fetch_add(1, Ordering::SeqCst);
+                                true
+                            } else {
+                                false
+                            }
+                        })
+                        .unwrap()
+                })
+            })
+            .collect();
+
+        // Half of the peers disconnect at the same time.
+        let disconnections: Vec<_> = (0..MAX_PEERS / 2)
+            .map(|_| {
+                let (slots, peers) = (slots.clone(), peers.clone());
+                thread::spawn(move || {
+                    peers.fetch_sub(1, Ordering::SeqCst);
+                    slots.notify().unwrap();
+                })
+            })
+            .collect();
+        for disconnection in disconnections {
+            disconnection.join().unwrap();
+        }
+
+        let started = waiters
+            .into_iter()
+            .map(|waiter| waiter.join().unwrap())
+            .filter(|started| *started)
+            .count();
+        assert_eq!(started, MAX_PEERS / 2);
+        assert_eq!(peers.load(Ordering::SeqCst), MAX_PEERS);
+    }
+}
@@ -0,0 +1,46 @@
+use crate::peer::{bt_peer::BtPeer, session_status::SessionStatus};
+
+/// Snapshot of a connected peer and its session.
+///
+/// To get the snapshots of the peers of a torrent use `AtomicTorrentStatus::peer_snapshots()`.
+///
+/// - `client`: name and version of the client of the peer, decoded from its peer id, see `client_id::client_name`,
+/// - `choked` / `interested`: the peer chokes us / we are interested in the peer,
+/// - `peer_choked` / `peer_interested`: we choke the peer / the peer is interested in us,
+/// - `download_speed` / `upload_speed`: in kilobits per second,
+/// - `pieces`: number of pieces the peer announced it has.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerSnapshot {
+    pub ip: String,
+    pub port: i64,
+    pub peer_id: Option<Vec<u8>>,
+    pub client: Option<String>,
+    pub choked: bool,
+    pub interested: bool,
+    pub peer_choked: bool,
+    pub peer_interested: bool,
+    pub snubbed: bool,
+    pub download_speed: f64,
+    pub upload_speed: f64,
+    pub pieces: u32,
+}
+
+impl PeerSnapshot {
+    /// Creates a snapshot of a peer and the status of its session.
+    pub fn new(peer: &BtPeer, status: &SessionStatus) -> Self {
+        Self {
+            ip: peer.ip.clone(),
+            port: peer.port,
+            peer_id: peer.peer_id.clone(),
+            client: peer.client.clone(),
+            choked: status.choked,
+            interested: status.interested,
+            peer_choked: status.peer_choked,
+            peer_interested: status.peer_interested,
+            snubbed: status.snubbed,
+            download_speed: status.download_speed,
+            upload_speed: status.upload_speed,
+            pieces: status.peer_pieces,
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use super::status::PieceStatus;
+
+/// Keeps the `PieceStatus` of every piece of a torrent, indexed by piece.
+///
+/// The free pieces are also kept in a list, so they can be selected without going through every piece of the
+/// torrent. A piece is added to and removed from the list in constant time.
+#[derive(Debug)]
+pub struct PieceStates {
+    states: Vec<PieceStatus>,
+    free: Vec<u32>,
+    /// Position of each piece in `free`, `None` if the piece is not free.
+    free_positions: Vec<Option<usize>>,
+}
+
+impl PieceStates {
+    /// Creates a new `PieceStates` with every piece free.
+    pub fn new(total_pieces: u32) -> Self {
+        Self {
+            states: vec![PieceStatus::Free; total_pieces as usize],
+            free: (0..total_pieces).collect(),
+            free_positions: (0..total_pieces as usize).map(Some).collect(),
+        }
+    }
+
+    /// Returns the status of a piece, `None` if the index is invalid.
+    pub fn get(&self, index: u32) -> Option<PieceStatus> {
+        self.states.get(index as usize).copied()
+    }
+
+    /// Changes the status of a piece, ignoring invalid indices.
+    pub fn set(&mut self, index: u32, status: PieceStatus) {
+        let Some(current) = self.states.get_mut(index as usize) else {
+            return;
+        };
+        *current = status;
+
+        match (status, self.free_positions[index as usize]) {
+            (PieceStatus::Free, None) => {
+                self.free_positions[index as usize] = Some(self.free.len());
+                self.free.push(index);
+            }
+            (PieceStatus::Free, Some(_)) | (_, None) => (),
+            (_, Some(position)) => {
+                self.free.swap_remove(position);
+                if let Some(moved) = self.free.get(position) {
+                    self.free_positions[*moved as usize] = Some(position);
+                }
+                self.free_positions[index as usize] = None;
+            }
+        }
+    }
+
+    /// Returns the indices of the free pieces, in no particular order.
+    pub fn free(&self) -> &[u32] {
+        &self.free
+    }
+
+    /// Returns the status of every piece, indexed by piece.
+    pub fn as_slice(&self) -> &[PieceStatus] {
+        &self.states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_pieces_are_free() {
+        let states = PieceStates::new(3);
+
+        assert_eq!(states.get(2), Some(PieceStatus::Free));
+        assert_eq!(states.get(3), None);
+        assert_eq!(states.free(), &[0, 1, 2]);
+    }
+
+    #[test]
+    fn test_set_keeps_the_free_pieces() {
+        let mut states = PieceStates::new(4);
+
+        states.set(1, PieceStatus::Downloading);
+        states.set(3, PieceStatus::Finished);
+        assert_eq!(states.get(1), Some(PieceStatus::Downloading));
+
+        let mut free = states.free().to_vec();
+        free.sort();
+        assert_eq!(free, vec![0, 2]);
+
+        states.set(1, PieceStatus::Free);
+        states.set(1, PieceStatus::Free);
+        let mut free = states.free().to_vec();
+        free.sort();
+        assert_eq!(free, vec![0, 1, 2]);
+    }
+}
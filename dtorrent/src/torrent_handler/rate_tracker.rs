@@ -0,0 +1,120 @@
+use std::time::Instant;
+
+/// Time constant in seconds of the moving average, older transfers weight less as time passes.
+const TIME_CONSTANT_SECONDS: f64 = 5.0;
+
+/// Tracks a transfer rate using an exponentially weighted moving average.
+///
+/// Every time bytes are transferred they are added with `add()`, and the rate decays when nothing is transferred,
+/// so the reported speed does not spike when a burst of blocks arrives at once.
+#[derive(Debug, Clone)]
+pub struct RateTracker {
+    bytes_per_second: f64,
+    last_update: Instant,
+}
+
+impl Default for RateTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateTracker {
+    /// Creates a new `RateTracker` with a rate of 0.
+    pub fn new() -> Self {
+        Self {
+            bytes_per_second: 0.0,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Adds transferred bytes to the rate.
+    pub fn add(&mut self, bytes: u64) {
+        self.add_at(bytes, Instant::now());
+    }
+
+    /// Returns the current rate in kilobits per second.
+    pub fn kilobits_per_second(&self) -> f64 {
+        self.kilobits_per_second_at(Instant::now())
+    }
+
+    fn add_at(&mut self, bytes: u64, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_update)
+            .as_secs_f64();
+        let decay = (-elapsed / TIME_CONSTANT_SECONDS).exp();
+
+        // Weight of the new bytes, it tends to 1 / TIME_CONSTANT_SECONDS when the elapsed time is close to 0.
+        let weight = if elapsed > 0.0 {
+            (1.0 - decay) / elapsed
+        } else {
+            1.0 / TIME_CONSTANT_SECONDS
+        };
+
+        self.bytes_per_second = self.bytes_per_second * decay + bytes as f64 * weight;
+        self.last_update = now;
+    }
+
+    fn kilobits_per_second_at(&self, now: Instant) -> f64 {
+        let elapsed = now
+            .saturating_duration_since(self.last_update)
+            .as_secs_f64();
+        let decay = (-elapsed / TIME_CONSTANT_SECONDS).exp();
+
+        self.bytes_per_second * decay * 8.0 / 1024.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_starts_at_zero() {
+        let rate = RateTracker::new();
+
+        assert_eq!(rate.kilobits_per_second(), 0.0);
+    }
+
+    #[test]
+    fn test_constant_transfer_converges_to_real_rate() {
+        let mut rate = RateTracker::new();
+        let mut now = Instant::now();
+
+        // 128 bytes every 1/8 of a second = 1024 bytes per second = 8 kilobits per second.
+        for _ in 0..1000 {
+            now += Duration::from_millis(125);
+            rate.add_at(128, now);
+        }
+
+        let speed = rate.kilobits_per_second_at(now);
+        assert!((speed - 8.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_burst_does_not_spike() {
+        let mut rate = RateTracker::new();
+        let now = Instant::now();
+
+        // 80 KiB received in the same instant are spread over the time constant.
+        rate.add_at(81920, now);
+
+        let speed = rate.kilobits_per_second_at(now);
+        let expected_speed = 81920.0 / TIME_CONSTANT_SECONDS * 8.0 / 1024.0;
+        assert!((speed - expected_speed).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_rate_decays_without_transfers() {
+        let mut rate = RateTracker::new();
+        let now = Instant::now();
+        rate.add_at(81920, now);
+
+        let speed = rate.kilobits_per_second_at(now);
+        let later_speed = rate.kilobits_per_second_at(now + Duration::from_secs(10));
+
+        assert!(later_speed < speed / 5.0);
+    }
+}
@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use crate::config::cfg::Cfg;
+
+/// Limits after which a finished torrent stops seeding, given by the `seed_ratio_limit` and
+/// `seed_time_limit_seconds` settings of its config.
+///
+/// To create a new `SeedLimits` use `SeedLimits::from_config`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeedLimits {
+    ratio: Option<f64>,
+    time: Option<Duration>,
+}
+
+/// The seed limit that was reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeedLimit {
+    Ratio,
+    Time,
+}
+
+impl SeedLimits {
+    /// Creates the limits of the config, a limit of 0 is not set.
+    pub fn from_config(config: &Cfg) -> Self {
+        Self {
+            ratio: Some(config.seed_ratio_limit).filter(|ratio| *ratio > 0.0),
+            time: Some(config.seed_time_limit_seconds)
+                .filter(|seconds| *seconds > 0)
+                .map(Duration::from_secs),
+        }
+    }
+
+    /// Returns `true` if any limit is set, otherwise the torrent is seeded forever.
+    pub fn is_enabled(&self) -> bool {
+        self.ratio.is_some() || self.time.is_some()
+    }
+
+    /// Returns the limit reached by a torrent with the given share `ratio` that was seeded for `seeding_time`.
+    pub fn reached(&self, ratio: f64, seeding_time: Duration) -> Option<SeedLimit> {
+        if self.ratio.is_some_and(|limit| ratio >= limit) {
+            return Some(SeedLimit::Ratio);
+        }
+        if self.time.is_some_and(|limit| seeding_time >= limit) {
+            return Some(SeedLimit::Time);
+        }
+        None
+    }
+
+    /// Returns how long a torrent seeded for `seeding_time` can keep seeding before it reaches the time limit.
+    pub fn time_left(&self, seeding_time: Duration) -> Option<Duration> {
+        self.time.map(|limit| limit.saturating_sub(seeding_time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(seed_ratio_limit: f64, seed_time_limit_seconds: u64) -> SeedLimits {
+        SeedLimits::from_config(&Cfg {
+            seed_ratio_limit,
+            seed_time_limit_seconds,
+            ..Cfg::default()
+        })
+    }
+
+    #[test]
+    fn test_no_limits() {
+        let limits = limits(0.0, 0);
+
+        assert!(!limits.is_enabled());
+        assert_eq!(limits.reached(100.0, Duration::from_secs(100_000)), None);
+    }
+
+    #[test]
+    fn test_ratio_limit() {
+        let limits = limits(1.5, 0);
+
+        assert!(limits.is_enabled());
+        assert_eq!(limits.reached(1.0, Duration::from_secs(100_000)), None);
+        assert_eq!(limits.reached(1.5, Duration::ZERO), Some(SeedLimit::Ratio));
+    }
+
+    #[test]
+    fn test_time_limit() {
+        let limits = limits(0.0, 60);
+
+        assert!(limits.is_enabled());
+        assert_eq!(limits.reached(100.0, Duration::from_secs(59)), None);
+        assert_eq!(
+            limits.reached(0.0, Duration::from_secs(60)),
+            Some(SeedLimit::Time)
+        );
+        assert_eq!(
+            limits.time_left(Duration::from_secs(45)),
+            Some(Duration::from_secs(15))
+        );
+    }
+}
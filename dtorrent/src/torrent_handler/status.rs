@@ -1,18 +1,36 @@
+use super::{
+    bandwidth_limiter::BandwidthLimiter, cancellation_token::CancellationToken, choker::Choker,
+    completion_command, hash_pool::HashPool, peer_slots::PeerSlots, peer_snapshot::PeerSnapshot,
+    piece_states::PieceStates, rate_tracker::RateTracker, super_seed::SuperSeedStatus,
+    torrent_stats::TorrentStats,
+};
+#[cfg(unix)]
+use crate::storage_manager::mmap_storage::MmapStorage;
 use crate::{
     config::cfg::Cfg,
-    peer::{bt_peer::BtPeer, peer_message::Bitfield, session_status::SessionStatus},
-    storage_manager::manager::{retrieve_block, save_piece},
+    peer::{
+        bt_peer::BtPeer, connection_direction::ConnectionDirection, peer_message::Bitfield,
+        session_status::SessionStatus,
+    },
+    storage_manager::{file_storage::FileStorage, storage::Storage},
     torrent_parser::torrent::Torrent,
+    tracker::tracker_health::TrackerHealth,
 };
 use rand::{self, prelude::IteratorRandom};
+use sha1::{Digest, Sha1};
 use std::{
-    collections::HashMap,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        mpsc::{sync_channel, Receiver, SyncSender},
-        {Mutex, MutexGuard},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::Sender,
+        Arc, OnceLock, {Mutex, MutexGuard},
     },
+    thread,
+    time::Duration,
 };
+use tracing::warn;
 
 /// A Struct that represents the current status of a torrent.
 ///
@@ -25,91 +43,242 @@ use std::{
 ///
 /// To create a new `AtomicTorrentStatus`, use the `new()` method.
 ///
-/// The `new()` method also returns a `Receiver` that can be used to know when a peer disconnects. This is useful if there is a limit for how many peers can be created,
-/// so the thread can be blocked until the status notifies that a peer has disconnected.
+/// A thread can block until a peer connects or disconnects with `wait_for_peers`. This is useful if there is a limit for
+/// how many peers can be created, so the thread waits until one of them disconnects.
 #[derive(Debug)]
 pub struct AtomicTorrentStatus {
     pub torrent: Torrent, //TODO: resolver encqapsulamiento en statistics.rs
-    pieces_status: Mutex<HashMap<u32, PieceStatus>>,
+    pieces_status: Mutex<PieceStates>,
+    pieces_priority: Mutex<Vec<PiecePriority>>,
     current_peers: AtomicUsize,
     config: Cfg,
-    torrent_status_sender: SyncSender<usize>,
-    sessions_status: Mutex<HashMap<BtPeer, SessionStatus>>,
+    peer_slots: PeerSlots,
+    cancellation: Mutex<CancellationToken>,
+    sessions_status: Mutex<HashMap<ConnectionId, ConnectedPeer>>,
+    next_connection_id: AtomicU64,
+    super_seed_status: Mutex<SuperSeedStatus>,
+    choker: Mutex<Choker>,
+    disconnect_requests: Mutex<HashSet<BtPeer>>,
+    peers_that_provided_data: Mutex<HashSet<BtPeer>>,
+    banned_ips: Mutex<HashSet<String>>,
+    wasted_bytes: AtomicU64,
+    hash_failures: AtomicU64,
+    tracker_errors: AtomicU64,
+    rejected_connections: AtomicU64,
+    trackers_health: Mutex<Vec<TrackerHealth>>,
+    uploaded_bytes: AtomicU64,
+    downloaded_bytes: AtomicU64,
+    overhead_uploaded_bytes: AtomicU64,
+    overhead_downloaded_bytes: AtomicU64,
+    upload_rate: Mutex<RateTracker>,
+    download_rate: Mutex<RateTracker>,
+    upload_limiter: BandwidthLimiter,
+    download_limiter: BandwidthLimiter,
     finished_pieces: AtomicUsize,
     downloading_pieces: AtomicUsize,
     free_pieces: AtomicUsize,
+    skipped_pieces: AtomicUsize,
+    completion_command_started: AtomicBool,
+    seeding_stopped: AtomicBool,
+    paused: AtomicBool,
+    removed: AtomicBool,
     total_seeders_count: AtomicUsize,
     total_leechers_count: AtomicUsize,
     all_current_peers: AtomicUsize,
+    storage: Box<dyn Storage>,
+    /// Started with the first piece to hash, see `hash_piece`.
+    hash_pool: OnceLock<HashPool>,
+}
+
+/// Identifies the connection with a peer while it is connected, see `AtomicTorrentStatus::peer_connected`.
+///
+/// Two connections never share an id, even if they come from the same address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionId(u64);
+
+/// A connected peer with the status of its session.
+#[derive(Debug)]
+struct ConnectedPeer {
+    peer: BtPeer,
+    status: SessionStatus,
+    /// Side that opened the connection, `None` if it was added with `peer_connected`.
+    direction: Option<ConnectionDirection>,
+}
+
+/// Connection kept when a peer is connected twice.
+#[derive(Debug)]
+enum DuplicateConnection {
+    KeepExisting,
+    /// The new connection is kept, the existing one with this peer is closed.
+    ReplaceExisting(BtPeer),
+}
+
+/// Finds a connection with `peer_id` and decides which one is kept if a new one in `direction` is added.
+///
+/// Only the opposite connection of a simultaneous connect can replace the existing one, any other duplicate is
+/// refused.
+fn duplicate_connection(
+    peer_status: &HashMap<ConnectionId, ConnectedPeer>,
+    peer_id: &[u8],
+    direction: ConnectionDirection,
+    client_peer_id: &[u8],
+) -> Option<DuplicateConnection> {
+    let existing = peer_status
+        .values()
+        .find(|connected| connected.peer.peer_id.as_deref() == Some(peer_id))?;
+
+    match existing.direction {
+        Some(existing_direction)
+            if existing_direction != direction
+                && ConnectionDirection::kept(client_peer_id, peer_id) == direction =>
+        {
+            Some(DuplicateConnection::ReplaceExisting(existing.peer.clone()))
+        }
+        _ => Some(DuplicateConnection::KeepExisting),
+    }
 }
 
 /// Possible states of a piece.
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum PieceStatus {
     Finished,
     Downloading,
     Free,
 }
 
+/// Download priority of a piece.
+///
+/// Pieces with a higher priority are selected first, `Skip` pieces are never selected.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum PiecePriority {
+    Skip,
+    Low,
+    Normal,
+    High,
+}
+
 /// Totrrent status possible errors.
 #[derive(Debug)]
 pub enum AtomicTorrentStatusError {
     PoisonedPiecesStatusLock,
     PoisonedCurrentPeersLock,
     PoisonedSessionsStatusLock,
+    PoisonedPiecesPriorityLock,
+    PoisonedSuperSeedStatusLock,
+    PoisonedChokerLock,
+    PoisonedDisconnectRequestsLock,
+    PoisonedPeersThatProvidedDataLock,
+    PoisonedBannedIpsLock,
+    PoisonedRateLock,
+    PoisonedTrackersHealthLock,
+    PoisonedPeerSlotsLock,
+    PoisonedCancellationLock,
     InvalidPieceIndex,
     NoPeersConnected,
+    UnknownConnection,
+    DuplicateConnection,
     PieceWasNotDownloading,
     SavePieceError(std::io::Error),
     RetrievingPieceError(std::io::Error),
     PieceWasNotFinished,
+    TorrentRemoved,
+    MoveOutputError(std::io::Error),
+    CompleteOutputError(std::io::Error),
+    AllocateError(std::io::Error),
+    VerifyPieceError(std::io::Error),
+    DeleteDataError(std::io::Error),
+    HashPoolStopped,
+}
+
+/// Outcome of a piece sent to `AtomicTorrentStatus::hash_piece`.
+#[derive(Debug)]
+pub struct HashedPiece {
+    pub index: u32,
+    pub length: usize,
+    /// True if the piece matched its hash and was saved, false if it did not and was aborted.
+    pub result: Result<bool, AtomicTorrentStatusError>,
 }
 
 impl AtomicTorrentStatus {
     /// Creates a new `AtomicTorrentStatus` from a `Torrent` and a `config`.
     ///
-    /// Returns a tuple with the `AtomicTorrentStatus` and a channel `Receiver` that can be used optionally to receive when a peer disconects from the torrent status.
-    /// The value sent on the channel is the current number of peers connected.
-    pub fn new(torrent: &Torrent, config: Cfg) -> (Self, Receiver<usize>) {
-        let mut pieces_status: HashMap<u32, PieceStatus> = HashMap::new();
-        let sessions_status: HashMap<BtPeer, SessionStatus> = HashMap::new();
+    /// The downloaded data is kept in a `FileStorage`, or in a `MmapStorage` with `mmap_seeding` on Unix.
+    pub fn new(torrent: &Torrent, config: Cfg) -> Self {
+        #[cfg(unix)]
+        if config.mmap_seeding {
+            let storage = MmapStorage::new(torrent, &config);
+            return Self::with_storage(torrent, config, Box::new(storage));
+        }
+        let storage = FileStorage::new(torrent, &config);
+        Self::with_storage(torrent, config, Box::new(storage))
+    }
 
-        let (torrent_status_sender, torrent_status_receiver): (SyncSender<usize>, Receiver<usize>) =
-            sync_channel((config.max_peers_per_torrent * 100) as usize);
+    /// Creates a new `AtomicTorrentStatus` that keeps the downloaded data in the given `Storage`.
+    pub fn with_storage(torrent: &Torrent, config: Cfg, storage: Box<dyn Storage>) -> Self {
+        let sessions_status: HashMap<ConnectionId, ConnectedPeer> = HashMap::new();
+        let choker = Choker::new(config.max_upload_slots as usize);
+        let upload_limiter = BandwidthLimiter::new(config.max_upload_kb_per_second);
+        let download_limiter = BandwidthLimiter::new(config.max_download_kb_per_second);
 
         let total_pieces = torrent.total_pieces();
 
-        for index in 0..total_pieces {
-            pieces_status.insert(index as u32, PieceStatus::Free);
-        }
-
-        (
-            Self {
-                torrent: torrent.clone(),
-                pieces_status: Mutex::new(pieces_status),
-                current_peers: AtomicUsize::new(0),
-                config,
-                torrent_status_sender,
-                sessions_status: Mutex::new(sessions_status),
-                finished_pieces: AtomicUsize::new(0),
-                downloading_pieces: AtomicUsize::new(0),
-                free_pieces: AtomicUsize::new(total_pieces as usize),
-                total_seeders_count: AtomicUsize::new(0),
-                total_leechers_count: AtomicUsize::new(0),
-                all_current_peers: AtomicUsize::new(0),
-            },
-            torrent_status_receiver,
-        )
+        Self {
+            torrent: torrent.clone(),
+            pieces_status: Mutex::new(PieceStates::new(total_pieces)),
+            pieces_priority: Mutex::new(vec![PiecePriority::Normal; total_pieces as usize]),
+            current_peers: AtomicUsize::new(0),
+            config,
+            peer_slots: PeerSlots::new(),
+            cancellation: Mutex::new(CancellationToken::new()),
+            sessions_status: Mutex::new(sessions_status),
+            next_connection_id: AtomicU64::new(0),
+            super_seed_status: Mutex::new(SuperSeedStatus::new()),
+            choker: Mutex::new(choker),
+            disconnect_requests: Mutex::new(HashSet::new()),
+            peers_that_provided_data: Mutex::new(HashSet::new()),
+            banned_ips: Mutex::new(HashSet::new()),
+            wasted_bytes: AtomicU64::new(0),
+            hash_failures: AtomicU64::new(0),
+            tracker_errors: AtomicU64::new(0),
+            rejected_connections: AtomicU64::new(0),
+            trackers_health: Mutex::new(vec![]),
+            uploaded_bytes: AtomicU64::new(0),
+            downloaded_bytes: AtomicU64::new(0),
+            overhead_uploaded_bytes: AtomicU64::new(0),
+            overhead_downloaded_bytes: AtomicU64::new(0),
+            upload_rate: Mutex::new(RateTracker::new()),
+            download_rate: Mutex::new(RateTracker::new()),
+            upload_limiter,
+            download_limiter,
+            finished_pieces: AtomicUsize::new(0),
+            downloading_pieces: AtomicUsize::new(0),
+            free_pieces: AtomicUsize::new(total_pieces as usize),
+            skipped_pieces: AtomicUsize::new(0),
+            completion_command_started: AtomicBool::new(false),
+            seeding_stopped: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            removed: AtomicBool::new(false),
+            total_seeders_count: AtomicUsize::new(0),
+            total_leechers_count: AtomicUsize::new(0),
+            all_current_peers: AtomicUsize::new(0),
+            storage,
+            hash_pool: OnceLock::new(),
+        }
     }
 
     /// Returns true if the torrent download finished.
+    ///
+    /// Pieces with `Skip` priority that were not downloaded are not taken into account.
     pub fn is_finished(&self) -> bool {
-        self.finished_pieces.load(Ordering::Relaxed) == self.torrent.total_pieces() as usize
+        self.finished_pieces.load(Ordering::Relaxed) + self.skipped_pieces.load(Ordering::Relaxed)
+            == self.torrent.total_pieces() as usize
     }
 
     /// Returns the number of ramaining pieces to download.
     pub fn remaining_pieces(&self) -> usize {
-        self.torrent.total_pieces() as usize - self.finished_pieces.load(Ordering::Relaxed)
+        self.torrent.total_pieces() as usize
+            - self.finished_pieces.load(Ordering::Relaxed)
+            - self.skipped_pieces.load(Ordering::Relaxed)
     }
 
     /// Returns the number of pieces that are currently downloading.
@@ -124,13 +293,84 @@ impl AtomicTorrentStatus {
 
     /// Adds a new peer to the current number of peers.
     ///
+    /// Returns the id of the connection, used to update its session status and to disconnect it.
+    ///
     /// # Errors
     /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
-    pub fn peer_connected(&self, peer: &BtPeer) -> Result<(), AtomicTorrentStatusError> {
-        self.current_peers.fetch_add(1, Ordering::Relaxed);
+    pub fn peer_connected(&self, peer: &BtPeer) -> Result<ConnectionId, AtomicTorrentStatusError> {
         let mut peer_status = self.lock_session_status()?;
-        peer_status.insert(peer.clone(), SessionStatus::new(Bitfield::new(vec![])));
-        Ok(())
+        Ok(self.add_connected_peer(&mut peer_status, peer, None))
+    }
+
+    /// Adds a new peer to the current number of peers, unless there is already a connection with its peer id.
+    ///
+    /// When we and the peer connected to each other at the same time, the connection opened by the lower peer id is
+    /// kept, see `ConnectionDirection::kept`: if it is the new one, the session of the other one is asked to
+    /// disconnect.
+    ///
+    /// # Errors
+    /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
+    /// - `PoisonedDisconnectRequestsLock` if the lock on the `disconnect_requests` field is poisoned.
+    /// - `DuplicateConnection` if the existing connection with the peer is kept instead.
+    pub fn peer_connected_once(
+        &self,
+        peer: &BtPeer,
+        direction: ConnectionDirection,
+        client_peer_id: &[u8],
+    ) -> Result<ConnectionId, AtomicTorrentStatusError> {
+        let mut peer_status = self.lock_session_status()?;
+        if let Some(peer_id) = &peer.peer_id {
+            match duplicate_connection(&peer_status, peer_id, direction, client_peer_id) {
+                Some(DuplicateConnection::KeepExisting) => {
+                    return Err(AtomicTorrentStatusError::DuplicateConnection)
+                }
+                Some(DuplicateConnection::ReplaceExisting(existing)) => {
+                    self.request_peer_disconnect(&existing)?
+                }
+                None => (),
+            }
+        }
+        Ok(self.add_connected_peer(&mut peer_status, peer, Some(direction)))
+    }
+
+    /// Returns true if a new connection with the peer id would be kept by `peer_connected_once`.
+    ///
+    /// It lets a duplicate connection be dropped right after the handshake.
+    ///
+    /// # Errors
+    /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
+    pub fn keeps_connection(
+        &self,
+        peer_id: &[u8],
+        direction: ConnectionDirection,
+        client_peer_id: &[u8],
+    ) -> Result<bool, AtomicTorrentStatusError> {
+        let peer_status = self.lock_session_status()?;
+        Ok(!matches!(
+            duplicate_connection(&peer_status, peer_id, direction, client_peer_id),
+            Some(DuplicateConnection::KeepExisting)
+        ))
+    }
+
+    fn add_connected_peer(
+        &self,
+        peer_status: &mut HashMap<ConnectionId, ConnectedPeer>,
+        peer: &BtPeer,
+        direction: Option<ConnectionDirection>,
+    ) -> ConnectionId {
+        let connection = ConnectionId(self.next_connection_id.fetch_add(1, Ordering::Relaxed));
+        peer_status.insert(
+            connection,
+            ConnectedPeer {
+                peer: peer.clone(),
+                status: SessionStatus::new(Bitfield::new(vec![])),
+                direction,
+            },
+        );
+        self.current_peers.fetch_add(1, Ordering::Relaxed);
+        // The connection is no longer half-open.
+        self.notify_peers_changed();
+        connection
     }
 
     /// Adds a new peer to the current number of peers.
@@ -143,34 +383,68 @@ impl AtomicTorrentStatus {
     /// # Errors
     /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
     /// - `NoPeersConnected` if there are no peers connected.
-    pub fn peer_disconnected(&self, peer: &BtPeer) -> Result<(), AtomicTorrentStatusError> {
+    /// - `UnknownConnection` if the connection was already disconnected.
+    pub fn peer_disconnected(
+        &self,
+        connection: ConnectionId,
+    ) -> Result<(), AtomicTorrentStatusError> {
         let mut peer_status = self.lock_session_status()?;
         if self.current_peers.load(Ordering::Relaxed) == 0 {
             return Err(AtomicTorrentStatusError::NoPeersConnected);
         }
+        let peer = match peer_status.remove(&connection) {
+            Some(connected) => connected.peer,
+            None => return Err(AtomicTorrentStatusError::UnknownConnection),
+        };
+        drop(peer_status);
         self.current_peers.fetch_sub(1, Ordering::Relaxed);
         self.all_current_peers.fetch_sub(1, Ordering::Relaxed);
 
-        peer_status.remove(peer);
+        let peer = &peer;
+        self.lock_super_seed_status()?.peer_disconnected(peer);
+        let mut choker = self.lock_choker()?;
+        choker.choke(peer);
+        choker.set_snubbed(peer, false);
+        drop(choker);
+        self.lock_disconnect_requests()?.remove(peer);
 
-        self.notify_peer_disconnected();
+        self.notify_peers_changed();
         Ok(())
     }
 
     /// Removes a peer from the current number of connecting peers.
     pub fn peer_connecting_failed(&self) {
         self.all_current_peers.fetch_sub(1, Ordering::Relaxed);
-        self.notify_peer_disconnected();
+        self.notify_peers_changed();
+    }
+
+    /// Wakes the threads blocked in `wait_for_peers`.
+    fn notify_peers_changed(&self) {
+        if let Err(err) = self.peer_slots.notify() {
+            warn!("Could not notify a change in the peers: {:?}", err);
+        }
     }
 
-    /// Notifies the torrent status receiver that a peer has disconnected.
-    fn notify_peer_disconnected(&self) {
-        // If the value couldn't be sent, it means the channel was closed.
-        if self
-            .torrent_status_sender
-            .send(self.all_current_peers.load(Ordering::Relaxed))
-            .is_ok()
-        {}
+    /// Blocks until `condition` returns true, checking it every time a peer connects or disconnects, the torrent is
+    /// resumed or removed, or `timeout` elapses. Returns false if the timeout elapsed with the condition unmet.
+    ///
+    /// The condition is checked by a single waiter at a time, so it can claim a connection slot without another
+    /// waiter taking the same one. It is checked with a lock held, so it should only read counters like
+    /// `all_current_peers`, not take the other locks of the status. See `PeerSlots`.
+    ///
+    /// # Errors
+    /// - `PoisonedPeerSlotsLock` if the lock of the peer slots is poisoned.
+    pub fn wait_for_peers<F>(
+        &self,
+        timeout: Option<Duration>,
+        condition: F,
+    ) -> Result<bool, AtomicTorrentStatusError>
+    where
+        F: FnMut() -> bool,
+    {
+        self.peer_slots
+            .wait_until(timeout, condition)
+            .map_err(|_| AtomicTorrentStatusError::PoisonedPeerSlotsLock)
     }
 
     /// Returns the current number of peers.
@@ -178,23 +452,57 @@ impl AtomicTorrentStatus {
         self.current_peers.load(Ordering::Relaxed)
     }
 
+    /// Returns the config of the torrent.
+    pub fn config(&self) -> &Cfg {
+        &self.config
+    }
+
     /// Returns the number of peers connected and connecting to the torrent.
     pub fn all_current_peers(&self) -> usize {
         self.all_current_peers.load(Ordering::Relaxed)
     }
 
-    /// Updates the peer session status of a peer.
+    /// Returns the number of peers that are connecting to the torrent but did not finish the handshake yet.
+    pub fn half_open_peers(&self) -> usize {
+        self.all_current_peers
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.current_peers.load(Ordering::Relaxed))
+    }
+
+    /// Registers that a piece was downloaded from a peer.
+    ///
+    /// # Errors
+    /// - `PoisonedPeersThatProvidedDataLock` if the lock on the `peers_that_provided_data` field is poisoned.
+    pub fn peer_provided_data(&self, peer: &BtPeer) -> Result<(), AtomicTorrentStatusError> {
+        self.lock_peers_that_provided_data()?.insert(peer.clone());
+        Ok(())
+    }
+
+    /// Returns true if a piece was already downloaded from the peer.
+    ///
+    /// # Errors
+    /// - `PoisonedPeersThatProvidedDataLock` if the lock on the `peers_that_provided_data` field is poisoned.
+    pub fn has_provided_data(&self, peer: &BtPeer) -> Result<bool, AtomicTorrentStatusError> {
+        Ok(self.lock_peers_that_provided_data()?.contains(peer))
+    }
+
+    /// Updates the peer session status of a connection, along with what is known of its peer.
     ///
     /// # Errors
     /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
+    /// - `UnknownConnection` if the connection was already disconnected.
     pub fn update_peer_session_status(
         &self,
+        connection: ConnectionId,
         peer: &BtPeer,
         status: &SessionStatus,
     ) -> Result<(), AtomicTorrentStatusError> {
         let mut peer_status = self.lock_session_status()?;
-        peer_status.remove(peer);
-        peer_status.insert(peer.clone(), status.clone());
+        let connected = peer_status
+            .get_mut(&connection)
+            .ok_or(AtomicTorrentStatusError::UnknownConnection)?;
+        connected.peer.clone_from(peer);
+        connected.status.clone_from(status);
         Ok(())
     }
 
@@ -215,305 +523,1469 @@ impl AtomicTorrentStatus {
         (seeders, leechers)
     }
 
-    /// Returns the connected peers.
+    /// Calls `f` with each connected peer and the status of its session.
+    ///
+    /// The sessions can not update their status until it returns, so `f` should be short.
     ///
     /// # Errors
     /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
-    pub fn get_connected_peers(
-        &self,
-    ) -> Result<HashMap<BtPeer, SessionStatus>, AtomicTorrentStatusError> {
-        Ok(self.lock_session_status()?.clone())
+    pub fn for_each_connected_peer<F>(&self, mut f: F) -> Result<(), AtomicTorrentStatusError>
+    where
+        F: FnMut(&BtPeer, &SessionStatus),
+    {
+        for connected in self.lock_session_status()?.values() {
+            f(&connected.peer, &connected.status);
+        }
+        Ok(())
     }
 
-    /// Returns the current download speed of the torrent in kilobits per second.
+    /// Returns a snapshot of each connected peer, for the UIs and the RPC layer.
     ///
     /// # Errors
     /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
-    pub fn torrent_download_speed(&self) -> Result<f64, AtomicTorrentStatusError> {
+    pub fn peer_snapshots(&self) -> Result<Vec<PeerSnapshot>, AtomicTorrentStatusError> {
         Ok(self
             .lock_session_status()?
             .values()
-            .map(|peer_session| peer_session.download_speed)
-            .sum())
+            .map(|connected| PeerSnapshot::new(&connected.peer, &connected.status))
+            .collect())
     }
 
-    /// Returns the current upload speed of the torrent in kilobits per second.
+    /// Returns true if there is a connection with the address of the peer.
     ///
     /// # Errors
     /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
-    pub fn torrent_upload_speed(&self) -> Result<f64, AtomicTorrentStatusError> {
+    pub fn is_connected(&self, peer: &BtPeer) -> Result<bool, AtomicTorrentStatusError> {
         Ok(self
             .lock_session_status()?
             .values()
-            .map(|peer_session| peer_session.upload_speed)
-            .sum())
+            .any(|connected| connected.peer == *peer))
     }
 
-    /// Returns the index of a piece that can be downloaded from a peer `Bitfield` passed by parameter.
+    /// Returns true if a peer with the given peer id is already connected.
     ///
-    /// If none of the pieces can be downloaded, returns `None`.
+    /// A single connection is used to download from and upload to a peer, so a second one is redundant.
     ///
     /// # Errors
-    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
-    pub fn select_piece(
-        &self,
-        bitfield: &Bitfield,
-    ) -> Result<Option<u32>, AtomicTorrentStatusError> {
-        let mut pieces_status = self.lock_pieces_status()?;
-
-        // If there are no free pieces do the 'EndGame' strategy, otherwise do the normal piece selection.
-        let index = if pieces_status
+    /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
+    pub fn is_peer_id_connected(&self, peer_id: &[u8]) -> Result<bool, AtomicTorrentStatusError> {
+        Ok(self
+            .lock_session_status()?
             .values()
-            .filter(|status| **status == PieceStatus::Free)
-            .count()
-            == 0
-        {
-            pieces_status
-                .clone()
-                .iter()
-                .filter(|(_, status)| **status == PieceStatus::Downloading)
-                .choose(&mut rand::thread_rng())
-                .map(|(index, _)| *index)
-        } else {
-            pieces_status
-                .clone()
-                .iter()
-                .filter(|(_, status)| **status == PieceStatus::Free)
-                .find(|(index, _)| bitfield.has_piece(**index))
-                .map(|(index, _)| *index)
-        };
+            .any(|connected| connected.peer.peer_id.as_deref() == Some(peer_id)))
+    }
 
-        Ok(match index {
-            Some(index) => {
-                pieces_status.insert(index, PieceStatus::Downloading);
-                self.downloading_pieces.fetch_add(1, Ordering::Relaxed);
-                self.free_pieces.fetch_sub(1, Ordering::Relaxed);
-                Some(index)
-            }
-            None => None,
-        })
+    /// Returns the current download speed of the torrent in kilobits per second.
+    ///
+    /// The speed is a moving average of the blocks received from all the peers.
+    ///
+    /// # Errors
+    /// - `PoisonedRateLock` if the lock on the `download_rate` field is poisoned.
+    pub fn torrent_download_speed(&self) -> Result<f64, AtomicTorrentStatusError> {
+        Ok(self
+            .download_rate
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedRateLock)?
+            .kilobits_per_second())
     }
 
-    /// Saves a downlaoded piece to the disk.
+    /// Returns the current upload speed of the torrent in kilobits per second.
+    ///
+    /// The speed is a moving average of the blocks sent to all the peers.
     ///
     /// # Errors
-    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
-    /// - `InvalidPieceIndex` if the piece index is invalid.
-    /// - `PieceWasNotDownloading` if the piece was not downloading.
-    /// - `SavePieceError` if the piece could not be saved.
-    pub fn piece_downloaded(
-        &self,
-        index: u32,
-        piece: &[u8],
-    ) -> Result<(), AtomicTorrentStatusError> {
-        let mut piece_status = self.lock_pieces_status()?;
-        match piece_status.get(&index) {
-            Some(value) => {
-                if *value != PieceStatus::Downloading {
-                    return Err(AtomicTorrentStatusError::PieceWasNotDownloading);
-                }
-            }
-            None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
-        }
-        save_piece(
-            self.torrent.info.name.clone(),
-            piece,
-            (index * self.torrent.info.piece_length as u32) as u64,
-            self.config.clone(),
-        )
-        .map_err(AtomicTorrentStatusError::SavePieceError)?;
+    /// - `PoisonedRateLock` if the lock on the `upload_rate` field is poisoned.
+    pub fn torrent_upload_speed(&self) -> Result<f64, AtomicTorrentStatusError> {
+        Ok(self
+            .upload_rate
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedRateLock)?
+            .kilobits_per_second())
+    }
 
-        piece_status.insert(index, PieceStatus::Finished);
-        self.downloading_pieces.fetch_sub(1, Ordering::Relaxed);
-        self.finished_pieces.fetch_add(1, Ordering::Relaxed);
+    /// Returns the connected peer with the lowest transfer speed, along with its download plus upload speed in kilobits per second.
+    ///
+    /// Peers already asked to disconnect are skipped. Returns `None` if there are no other peers connected.
+    ///
+    /// # Errors
+    /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
+    /// - `PoisonedDisconnectRequestsLock` if the lock on the `disconnect_requests` field is poisoned.
+    pub fn slowest_peer(&self) -> Result<Option<(BtPeer, f64)>, AtomicTorrentStatusError> {
+        let session_status = self.lock_session_status()?;
+        let disconnect_requests = self.lock_disconnect_requests()?;
+        Ok(session_status
+            .values()
+            .filter(|connected| !disconnect_requests.contains(&connected.peer))
+            .map(|connected| {
+                (
+                    connected.peer.clone(),
+                    connected.status.download_speed + connected.status.upload_speed,
+                )
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b)))
+    }
+
+    /// Asks the session of a peer to disconnect.
+    ///
+    /// The session checks it with `should_disconnect` and closes the connection by itself.
+    ///
+    /// # Errors
+    /// - `PoisonedDisconnectRequestsLock` if the lock on the `disconnect_requests` field is poisoned.
+    pub fn request_peer_disconnect(&self, peer: &BtPeer) -> Result<(), AtomicTorrentStatusError> {
+        self.lock_disconnect_requests()?.insert(peer.clone());
         Ok(())
     }
 
-    /// Gets a piece already downloaded from the disk.
+    /// Returns the number of peers asked to disconnect whose sessions did not close yet.
     ///
     /// # Errors
-    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
-    /// - `InvalidPieceIndex` if the piece index is invalid.
-    /// - `PieceWasNotFinished` if the piece was not donwloaded.
-    pub fn get_piece(
-        &self,
-        index: u32,
-        offset: u64,
-        length: usize,
-    ) -> Result<Vec<u8>, AtomicTorrentStatusError> {
-        let pieces_status = self.lock_pieces_status()?;
+    /// - `PoisonedDisconnectRequestsLock` if the lock on the `disconnect_requests` field is poisoned.
+    pub fn pending_disconnects(&self) -> Result<usize, AtomicTorrentStatusError> {
+        Ok(self.lock_disconnect_requests()?.len())
+    }
 
-        match pieces_status.get(&index) {
-            Some(value) => {
-                if *value != PieceStatus::Finished {
-                    return Err(AtomicTorrentStatusError::PieceWasNotFinished);
-                }
-            }
-            None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
-        }
+    /// Returns true if the session of the peer was asked to disconnect.
+    ///
+    /// # Errors
+    /// - `PoisonedDisconnectRequestsLock` if the lock on the `disconnect_requests` field is poisoned.
+    pub fn should_disconnect(&self, peer: &BtPeer) -> Result<bool, AtomicTorrentStatusError> {
+        Ok(self.lock_disconnect_requests()?.contains(peer))
+    }
 
-        retrieve_block(
-            self.torrent.info.name.clone(),
-            offset,
-            length,
-            self.config.clone(),
-        )
-        .map_err(AtomicTorrentStatusError::RetrievingPieceError)
+    /// Tries to take an upload slot for a peer.
+    ///
+    /// Returns true if the peer can be unchoked.
+    ///
+    /// # Errors
+    /// - `PoisonedChokerLock` if the lock on the `choker` field is poisoned.
+    pub fn try_unchoke_peer(&self, peer: &BtPeer) -> Result<bool, AtomicTorrentStatusError> {
+        Ok(self.lock_choker()?.unchoke(peer))
     }
 
-    /// Aborts a piece download.
+    /// Frees the upload slot of a peer.
     ///
-    /// This must be called when a piece obteined from `select_piece` can not longer be downloaded.
+    /// # Errors
+    /// - `PoisonedChokerLock` if the lock on the `choker` field is poisoned.
+    pub fn peer_choked(&self, peer: &BtPeer) -> Result<(), AtomicTorrentStatusError> {
+        self.lock_choker()?.choke(peer);
+        Ok(())
+    }
+
+    /// Marks or unmarks a peer as snubbed, snubbed peers are deprioritized when taking upload slots.
     ///
     /// # Errors
-    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
-    /// - `InvalidPieceIndex` if the piece index is invalid.
-    /// - `PieceWasNotDownloading` if the piece was not downloading.
-    pub fn piece_aborted(&self, index: u32) -> Result<(), AtomicTorrentStatusError> {
-        let mut piece_status = self.lock_pieces_status()?;
-        match piece_status.get(&index) {
-            Some(value) => {
-                if *value != PieceStatus::Downloading {
-                    return Err(AtomicTorrentStatusError::PieceWasNotDownloading);
-                }
-            }
-            None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
-        }
-        piece_status.insert(index, PieceStatus::Free);
-        self.downloading_pieces.fetch_sub(1, Ordering::Relaxed);
-        self.free_pieces.fetch_add(1, Ordering::Relaxed);
+    /// - `PoisonedChokerLock` if the lock on the `choker` field is poisoned.
+    pub fn set_peer_snubbed(
+        &self,
+        peer: &BtPeer,
+        snubbed: bool,
+    ) -> Result<(), AtomicTorrentStatusError> {
+        self.lock_choker()?.set_snubbed(peer, snubbed);
         Ok(())
     }
 
-    /// Returns the current bitfield of the torrent.
+    /// Returns the number of peers that are currently unchoked.
     ///
     /// # Errors
-    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
-    pub fn get_bitfield(&self) -> Result<Bitfield, AtomicTorrentStatusError> {
-        let pieces_status = self.lock_pieces_status()?;
-        Ok(Bitfield::from(&pieces_status))
+    /// - `PoisonedChokerLock` if the lock on the `choker` field is poisoned.
+    pub fn unchoked_peers(&self) -> Result<usize, AtomicTorrentStatusError> {
+        Ok(self.lock_choker()?.unchoked_peers())
     }
 
-    fn lock_pieces_status(
-        &self,
-    ) -> Result<MutexGuard<HashMap<u32, PieceStatus>>, AtomicTorrentStatusError> {
-        self.pieces_status
+    /// Adds bytes of a block sent to a peer.
+    ///
+    /// # Errors
+    /// - `PoisonedRateLock` if the lock on the `upload_rate` field is poisoned.
+    pub fn add_uploaded_bytes(&self, bytes: u64) -> Result<(), AtomicTorrentStatusError> {
+        self.uploaded_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.upload_rate
             .lock()
-            .map_err(|_| AtomicTorrentStatusError::PoisonedPiecesStatusLock)
+            .map_err(|_| AtomicTorrentStatusError::PoisonedRateLock)?
+            .add(bytes);
+        Ok(())
     }
 
-    fn lock_session_status(
-        &self,
-    ) -> Result<MutexGuard<HashMap<BtPeer, SessionStatus>>, AtomicTorrentStatusError> {
-        self.sessions_status
+    /// Adds bytes of a block received from a peer.
+    ///
+    /// # Errors
+    /// - `PoisonedRateLock` if the lock on the `download_rate` field is poisoned.
+    pub fn add_downloaded_bytes(&self, bytes: u64) -> Result<(), AtomicTorrentStatusError> {
+        self.downloaded_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.download_rate
             .lock()
-            .map_err(|_| AtomicTorrentStatusError::PoisonedSessionsStatusLock)
+            .map_err(|_| AtomicTorrentStatusError::PoisonedRateLock)?
+            .add(bytes);
+        Ok(())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use std::{fs, sync::Arc, thread};
 
-    use crate::torrent_parser::info::Info;
+    /// Waits until `bytes` of block data can be sent without exceeding the `max_upload_kb_per_second` of the torrent.
+    pub fn throttle_upload(&self, bytes: usize) {
+        self.upload_limiter.throttle(bytes);
+    }
 
-    use super::*;
+    /// Waits until `bytes` of block data received fit in the `max_download_kb_per_second` of the torrent, delaying
+    /// the next read from the peer.
+    pub fn throttle_download(&self, bytes: usize) {
+        self.download_limiter.throttle(bytes);
+    }
 
-    const CONFIG_PATH: &str = "config.cfg";
+    /// Adds bytes sent to a peer that are not block data, like handshakes, haves, bitfields and message headers.
+    pub fn add_overhead_uploaded_bytes(&self, bytes: u64) {
+        self.overhead_uploaded_bytes
+            .fetch_add(bytes, Ordering::Relaxed);
+    }
 
-    #[test]
-    fn test_is_not_finished() {
-        let torrent = create_test_torrent("test_is_not_finished");
-        let config = Cfg::new(CONFIG_PATH).unwrap();
-        let status = create_status_whitout_receiver(&torrent, config.clone());
-        assert!(!status.is_finished());
+    /// Adds bytes received from a peer that are not block data, see `add_overhead_uploaded_bytes`.
+    pub fn add_overhead_downloaded_bytes(&self, bytes: u64) {
+        self.overhead_downloaded_bytes
+            .fetch_add(bytes, Ordering::Relaxed);
     }
 
-    #[test]
-    fn test_is_finished() {
-        let torrent = create_test_torrent("test_is_finished");
+    /// Returns a snapshot of the transfer statistics of the torrent.
+    ///
+    /// The overhead includes the bytes of the announces, taken from the health of the trackers.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `PoisonedTrackersHealthLock` if the lock on the `trackers_health` field is poisoned.
+    pub fn stats(&self) -> Result<TorrentStats, AtomicTorrentStatusError> {
+        let left_bytes = self.left_bytes()?;
+        let (tracker_sent_bytes, tracker_received_bytes) = self
+            .lock_trackers_health()?
+            .iter()
+            .fold((0, 0), |(sent, received), health| {
+                (sent + health.sent_bytes, received + health.received_bytes)
+            });
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
-        let status = create_status_whitout_receiver(&torrent, config.clone());
-        for _ in 0..(torrent.info.length / torrent.info.piece_length) {
-            let index = status
-                .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
-                .unwrap()
-                .unwrap();
-            status.piece_downloaded(index as u32, &[]).unwrap();
-        }
-        assert!(status.is_finished());
-        fs::remove_file(format!(
-            "{}/{}",
-            config.download_directory, torrent.info.name
-        ))
-        .unwrap();
+        Ok(TorrentStats {
+            uploaded_bytes: self.uploaded_bytes.load(Ordering::Relaxed),
+            downloaded_bytes: self.downloaded_bytes.load(Ordering::Relaxed),
+            overhead_uploaded_bytes: self.overhead_uploaded_bytes.load(Ordering::Relaxed)
+                + tracker_sent_bytes,
+            overhead_downloaded_bytes: self.overhead_downloaded_bytes.load(Ordering::Relaxed)
+                + tracker_received_bytes,
+            wasted_bytes: self.wasted_bytes.load(Ordering::Relaxed),
+            left_bytes,
+            downloaded_pieces: self.finished_pieces.load(Ordering::Relaxed),
+            total_pieces: self.torrent.total_pieces() as usize,
+        })
+    }
+
+    /// Returns the download progress of the torrent, from 0.0 to 1.0.
+    ///
+    /// It is calculated with the bytes of the downloaded pieces, taking into account that the last piece may be shorter.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    pub fn progress(&self) -> Result<f64, AtomicTorrentStatusError> {
+        let length = self.torrent.length();
+        if length == 0 {
+            return Ok(1.0);
+        }
+        let left_bytes = self.left_bytes()?;
+        Ok((length - left_bytes) as f64 / length as f64)
+    }
+
+    /// Returns the seed ratio of the torrent: the uploaded bytes divided by the size of the torrent.
+    ///
+    /// It is the ratio compared with the `seed_ratio_limit`, unlike the share ratio of `TorrentStats` it does not
+    /// depend on how much of the torrent was downloaded from other peers.
+    pub fn seed_ratio(&self) -> f64 {
+        let length = self.torrent.length();
+        if length == 0 {
+            return 0.0;
+        }
+        self.uploaded_bytes.load(Ordering::Relaxed) as f64 / length as f64
+    }
+
+    /// Stops seeding the torrent, stopping every peer session, see `cancel_sessions`.
+    ///
+    /// The `BtServer` refuses the incoming connections of a torrent that stopped seeding.
+    ///
+    /// # Errors
+    /// - `PoisonedCancellationLock` if the lock on the `cancellation` field is poisoned.
+    pub fn stop_seeding(&self) -> Result<(), AtomicTorrentStatusError> {
+        self.seeding_stopped.store(true, Ordering::Relaxed);
+        self.cancel_sessions()
+    }
+
+    /// Returns true if the torrent stopped seeding, see `stop_seeding`.
+    pub fn is_seeding_stopped(&self) -> bool {
+        self.seeding_stopped.load(Ordering::Relaxed)
+    }
+
+    /// Pauses the torrent until `resume` is called, stopping every peer session, see `cancel_sessions`.
+    ///
+    /// The `TorrentHandler` does not connect to new peers and the `BtServer` refuses the incoming connections
+    /// while the torrent is paused.
+    ///
+    /// # Errors
+    /// - `PoisonedCancellationLock` if the lock on the `cancellation` field is poisoned.
+    pub fn pause(&self) -> Result<(), AtomicTorrentStatusError> {
+        self.paused.store(true, Ordering::Relaxed);
+        self.cancel_sessions()
+    }
+
+    /// Resumes a torrent paused with `pause`.
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+        self.notify_peers_changed();
+    }
+
+    /// Returns true if the torrent is paused, see `pause`.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Removes the torrent, stopping every peer session, see `cancel_sessions`.
+    ///
+    /// The `TorrentHandler` stops connecting to peers and announcing, and no more pieces are saved.
+    /// The torrent is not removed from the torrents served by the `BtServer`, that is up to the caller.
+    ///
+    /// # Errors
+    /// - `PoisonedCancellationLock` if the lock on the `cancellation` field is poisoned.
+    pub fn remove(&self) -> Result<(), AtomicTorrentStatusError> {
+        self.removed.store(true, Ordering::Relaxed);
+        self.notify_peers_changed();
+        self.cancel_sessions()
+    }
+
+    /// Returns true if the torrent was removed, see `remove`.
+    pub fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::Relaxed)
+    }
+
+    /// Deletes the downloaded data of the torrent from its storage.
+    ///
+    /// # Errors
+    /// - `DeleteDataError` if the data could not be deleted.
+    pub fn delete_data(&self) -> Result<(), AtomicTorrentStatusError> {
+        self.storage
+            .delete()
+            .map_err(AtomicTorrentStatusError::DeleteDataError)
+    }
+
+    /// Returns the token the peer sessions check to know they must stop, see `cancel_sessions`.
+    ///
+    /// # Errors
+    /// - `PoisonedCancellationLock` if the lock on the `cancellation` field is poisoned.
+    pub fn cancellation_token(&self) -> Result<CancellationToken, AtomicTorrentStatusError> {
+        Ok(self.lock_cancellation()?.clone())
+    }
+
+    /// Stops every peer session of the torrent, the connected ones and the ones still connecting.
+    ///
+    /// The sessions see it before handling their next message, and the ones started afterwards get a new token
+    /// that is not cancelled.
+    ///
+    /// # Errors
+    /// - `PoisonedCancellationLock` if the lock on the `cancellation` field is poisoned.
+    pub fn cancel_sessions(&self) -> Result<(), AtomicTorrentStatusError> {
+        let mut cancellation = self.lock_cancellation()?;
+        cancellation.cancel();
+        *cancellation = CancellationToken::new();
+        Ok(())
+    }
+
+    fn lock_cancellation(
+        &self,
+    ) -> Result<MutexGuard<'_, CancellationToken>, AtomicTorrentStatusError> {
+        self.cancellation
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedCancellationLock)
+    }
+
+    /// Returns the estimated time left to finish the download, based on the current download speed.
+    ///
+    /// Returns `None` if nothing is being downloaded, so the time can not be estimated.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `PoisonedRateLock` if the lock on the `download_rate` field is poisoned.
+    pub fn eta(&self) -> Result<Option<Duration>, AtomicTorrentStatusError> {
+        let left_bytes = self.left_bytes()?;
+        if left_bytes == 0 {
+            return Ok(Some(Duration::ZERO));
+        }
+
+        let bytes_per_second = self.torrent_download_speed()? * 1024.0 / 8.0;
+        if bytes_per_second <= 0.0 {
+            return Ok(None);
+        }
+        Ok(Some(Duration::from_secs_f64(
+            left_bytes as f64 / bytes_per_second,
+        )))
+    }
+
+    /// Returns the bytes of the pieces that are not downloaded yet.
+    fn left_bytes(&self) -> Result<u64, AtomicTorrentStatusError> {
+        let pieces_status = self.lock_pieces_status()?;
+
+        // A torrent with no data has no last piece.
+        if self.torrent.total_pieces() == 0 {
+            return Ok(0);
+        }
+        let last_piece_index = self.torrent.total_pieces() - 1;
+        let piece_length = self.torrent.info.piece_length as u64;
+
+        Ok(pieces_status
+            .as_slice()
+            .iter()
+            .enumerate()
+            .filter(|(_, status)| **status != PieceStatus::Finished)
+            .map(|(index, _)| {
+                if index as u32 == last_piece_index {
+                    self.torrent.length() - self.torrent.piece_offset(last_piece_index)
+                } else {
+                    piece_length
+                }
+            })
+            .sum())
+    }
+
+    /// Adds bytes that were downloaded but could not be used, for example because the piece failed the hash check.
+    pub fn add_wasted_bytes(&self, bytes: u64) {
+        self.wasted_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Returns the number of bytes that were downloaded but could not be used.
+    pub fn wasted_bytes(&self) -> u64 {
+        self.wasted_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Counts a piece that failed the hash check.
+    pub fn add_hash_failure(&self) {
+        self.hash_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of pieces that failed the hash check.
+    pub fn hash_failures(&self) -> u64 {
+        self.hash_failures.load(Ordering::Relaxed)
+    }
+
+    /// Counts a failed request to the tracker.
+    pub fn add_tracker_error(&self) {
+        self.tracker_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of failed requests to the tracker.
+    pub fn tracker_errors(&self) -> u64 {
+        self.tracker_errors.load(Ordering::Relaxed)
+    }
+
+    /// Counts an incoming connection refused because the torrent or the client had too many peers.
+    pub fn add_rejected_connection(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of incoming connections refused because the torrent or the client had too many peers.
+    pub fn rejected_connections(&self) -> u64 {
+        self.rejected_connections.load(Ordering::Relaxed)
+    }
+
+    /// Replaces the health of the trackers of the torrent, after an announce.
+    ///
+    /// # Errors
+    /// - `PoisonedTrackersHealthLock` if the lock on the `trackers_health` field is poisoned.
+    pub fn update_trackers_health(
+        &self,
+        health: Vec<TrackerHealth>,
+    ) -> Result<(), AtomicTorrentStatusError> {
+        *self.lock_trackers_health()? = health;
+        Ok(())
+    }
+
+    /// Returns the health of each tracker of the torrent, for the UIs. It is empty until the first announce.
+    ///
+    /// # Errors
+    /// - `PoisonedTrackersHealthLock` if the lock on the `trackers_health` field is poisoned.
+    pub fn trackers_health(&self) -> Result<Vec<TrackerHealth>, AtomicTorrentStatusError> {
+        Ok(self.lock_trackers_health()?.clone())
+    }
+
+    /// Bans the IP of a peer, so no more connections are made with it.
+    ///
+    /// # Errors
+    /// - `PoisonedBannedIpsLock` if the lock on the `banned_ips` field is poisoned.
+    pub fn ban_peer(&self, peer: &BtPeer) -> Result<(), AtomicTorrentStatusError> {
+        self.lock_banned_ips()?.insert(peer.ip.clone());
+        Ok(())
+    }
+
+    /// Returns true if the IP of the peer was banned.
+    ///
+    /// # Errors
+    /// - `PoisonedBannedIpsLock` if the lock on the `banned_ips` field is poisoned.
+    pub fn is_banned(&self, peer: &BtPeer) -> Result<bool, AtomicTorrentStatusError> {
+        Ok(self.lock_banned_ips()?.contains(&peer.ip))
+    }
+
+    /// Returns true if pieces should be revealed one at a time to the peers.
+    ///
+    /// Super-seeding is only done if it is enabled in the config and the whole torrent is already downloaded.
+    /// A torrent finished with skipped pieces is not super-seeded, since it could reveal a piece it does not have.
+    pub fn is_super_seeding(&self) -> bool {
+        self.config.super_seeding
+            && self.downloaded_pieces() == self.torrent.total_pieces() as usize
+    }
+
+    /// Returns the next piece to reveal to a peer when super-seeding, given the peer `Bitfield`.
+    ///
+    /// Returns `None` if the last piece revealed to the peer was not announced by another peer yet.
+    ///
+    /// # Errors
+    /// - `PoisonedSuperSeedStatusLock` if the lock on the `super_seed_status` field is poisoned.
+    pub fn super_seed_next_piece(
+        &self,
+        peer: &BtPeer,
+        peer_bitfield: &Bitfield,
+    ) -> Result<Option<u32>, AtomicTorrentStatusError> {
+        Ok(self.lock_super_seed_status()?.next_piece(
+            peer,
+            peer_bitfield,
+            self.torrent.total_pieces(),
+        ))
+    }
+
+    /// Registers that a peer announced having a piece when super-seeding.
+    ///
+    /// # Errors
+    /// - `PoisonedSuperSeedStatusLock` if the lock on the `super_seed_status` field is poisoned.
+    pub fn super_seed_piece_announced(
+        &self,
+        peer: &BtPeer,
+        index: u32,
+    ) -> Result<(), AtomicTorrentStatusError> {
+        self.lock_super_seed_status()?.piece_announced(peer, index);
+        Ok(())
+    }
+
+    /// Returns the index of a piece that can be downloaded from a peer `Bitfield` passed by parameter.
+    ///
+    /// Pieces with a higher `PiecePriority` are selected first and pieces with `Skip` priority are never selected.
+    /// With `sequential_download` the pieces of the same priority are selected in order.
+    ///
+    /// If none of the pieces can be downloaded, returns `None`.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `PoisonedPiecesPriorityLock` if the lock on the `pieces_priority` field is poisoned.
+    pub fn select_piece(
+        &self,
+        bitfield: &Bitfield,
+    ) -> Result<Option<u32>, AtomicTorrentStatusError> {
+        let mut pieces_status = self.lock_pieces_status()?;
+        let pieces_priority = self.lock_pieces_priority()?;
+
+        let wanted = |index: u32| pieces_priority[index as usize] != PiecePriority::Skip;
+
+        // If there are no free pieces do the 'EndGame' strategy, otherwise do the normal piece selection.
+        if !pieces_status.free().iter().any(|index| wanted(*index)) {
+            // The piece is already downloading from another peer, so its status does not change.
+            return Ok(pieces_status
+                .as_slice()
+                .iter()
+                .enumerate()
+                .map(|(index, status)| (index as u32, status))
+                .filter(|(index, status)| {
+                    **status == PieceStatus::Downloading
+                        && wanted(*index)
+                        && bitfield.has_piece(*index)
+                })
+                .choose(&mut rand::thread_rng())
+                .map(|(index, _)| index));
+        }
+
+        let candidates = pieces_status
+            .free()
+            .iter()
+            .copied()
+            .filter(|index| wanted(*index) && bitfield.has_piece(*index));
+        let index = if self.config.sequential_download {
+            candidates.max_by_key(|index| (pieces_priority[*index as usize], Reverse(*index)))
+        } else {
+            candidates.max_by_key(|index| pieces_priority[*index as usize])
+        };
+
+        Ok(match index {
+            Some(index) => {
+                pieces_status.set(index, PieceStatus::Downloading);
+                self.downloading_pieces.fetch_add(1, Ordering::Relaxed);
+                self.free_pieces.fetch_sub(1, Ordering::Relaxed);
+                Some(index)
+            }
+            None => None,
+        })
+    }
+
+    /// Sets the download priority of a piece.
+    ///
+    /// Pieces set to `Skip` that are not already downloaded are no longer needed for the torrent to be finished.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `PoisonedPiecesPriorityLock` if the lock on the `pieces_priority` field is poisoned.
+    /// - `InvalidPieceIndex` if the piece index is invalid.
+    pub fn set_piece_priority(
+        &self,
+        index: u32,
+        priority: PiecePriority,
+    ) -> Result<(), AtomicTorrentStatusError> {
+        let pieces_status = self.lock_pieces_status()?;
+        let mut pieces_priority = self.lock_pieces_priority()?;
+
+        let previous = match pieces_priority.get(index as usize) {
+            Some(previous) => *previous,
+            None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
+        };
+        if pieces_status.get(index) != Some(PieceStatus::Finished) {
+            if previous != PiecePriority::Skip && priority == PiecePriority::Skip {
+                self.skipped_pieces.fetch_add(1, Ordering::Relaxed);
+            } else if previous == PiecePriority::Skip && priority != PiecePriority::Skip {
+                self.skipped_pieces.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+        pieces_priority[index as usize] = priority;
+        Ok(())
+    }
+
+    /// Returns the download priority of a piece.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesPriorityLock` if the lock on the `pieces_priority` field is poisoned.
+    /// - `InvalidPieceIndex` if the piece index is invalid.
+    pub fn piece_priority(&self, index: u32) -> Result<PiecePriority, AtomicTorrentStatusError> {
+        self.lock_pieces_priority()?
+            .get(index as usize)
+            .copied()
+            .ok_or(AtomicTorrentStatusError::InvalidPieceIndex)
+    }
+
+    /// Checks the hash of a downloaded piece on a thread of the hashing pool, and saves it if it matches, see
+    /// `piece_downloaded`, or aborts it otherwise, see `piece_aborted`.
+    ///
+    /// The calling thread does not wait for it, the outcome is sent to `results`. The pool is started with the first
+    /// piece, with the `hashing_threads` of the config.
+    ///
+    /// # Errors
+    /// - `HashPoolStopped` if no thread of the hashing pool is running.
+    pub fn hash_piece(
+        self: &Arc<Self>,
+        index: u32,
+        piece: Vec<u8>,
+        results: Sender<HashedPiece>,
+    ) -> Result<(), AtomicTorrentStatusError> {
+        let status = self.clone();
+        self.hash_pool()
+            .execute(move || {
+                let result = if status.piece_matches_hash(index, &piece) {
+                    status.piece_downloaded(index, &piece).map(|_| true)
+                } else {
+                    match status.piece_aborted(index) {
+                        // Saved meanwhile from another peer, the piece is still corrupted.
+                        Ok(()) | Err(AtomicTorrentStatusError::PieceWasNotDownloading) => Ok(false),
+                        Err(err) => Err(err),
+                    }
+                };
+                // The piece is saved even if the session that downloaded it already ended.
+                let _ = results.send(HashedPiece {
+                    index,
+                    length: piece.len(),
+                    result,
+                });
+            })
+            .map_err(|_| AtomicTorrentStatusError::HashPoolStopped)
+    }
+
+    fn hash_pool(&self) -> &HashPool {
+        self.hash_pool.get_or_init(|| {
+            HashPool::new(
+                self.config.hashing_threads,
+                &format!("Torrent: {}", self.torrent.info.name),
+            )
+        })
+    }
+
+    /// Returns true if the SHA-1 of `piece` is the hash of the piece at `index` in the torrent.
+    pub fn piece_matches_hash(&self, index: u32, piece: &[u8]) -> bool {
+        let start = index as usize * 20;
+        self.torrent.info.pieces.get(start..start + 20) == Some(Sha1::digest(piece).as_slice())
+    }
+
+    /// Saves a downlaoded piece to the disk.
+    ///
+    /// The lock on the pieces status is not held while the piece is written, so pieces are saved in parallel and
+    /// other peers can keep selecting pieces. The piece is only marked as finished once it is written.
+    ///
+    /// When the last piece is saved the storage is completed, see `Storage::complete`, and the `on_complete_command`
+    /// of the config is started once, see `completion_command::spawn`.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `InvalidPieceIndex` if the piece index is invalid.
+    /// - `PieceWasNotDownloading` if the piece was not downloading, or was saved by another peer while writing it.
+    /// - `TorrentRemoved` if the torrent was removed, see `remove`.
+    /// - `SavePieceError` if the piece could not be saved.
+    /// - `CompleteOutputError` if the downloaded file could not be moved to its final location.
+    pub fn piece_downloaded(
+        &self,
+        index: u32,
+        piece: &[u8],
+    ) -> Result<(), AtomicTorrentStatusError> {
+        if self.is_removed() {
+            return Err(AtomicTorrentStatusError::TorrentRemoved);
+        }
+        self.check_piece_downloading(&*self.lock_pieces_status()?, index, piece)?;
+
+        self.storage
+            .write_piece(self.torrent.piece_offset(index), piece)
+            .map_err(AtomicTorrentStatusError::SavePieceError)?;
+
+        let finished = {
+            let mut piece_status = self.lock_pieces_status()?;
+            // During the endgame the same piece can be written by two peers at the same time.
+            self.check_piece_downloading(&piece_status, index, piece)?;
+
+            if self.piece_priority(index)? == PiecePriority::Skip {
+                self.skipped_pieces.fetch_sub(1, Ordering::Relaxed);
+            }
+            piece_status.set(index, PieceStatus::Finished);
+            self.downloading_pieces.fetch_sub(1, Ordering::Relaxed);
+            self.finished_pieces.fetch_add(1, Ordering::Relaxed);
+            self.is_finished()
+        };
+
+        if finished {
+            self.storage
+                .complete()
+                .map_err(AtomicTorrentStatusError::CompleteOutputError)?;
+            self.run_completion_command();
+        }
+        Ok(())
+    }
+
+    /// Starts the `on_complete_command` of the config the first time the download finishes.
+    ///
+    /// A command that can not be started is only logged, as the download itself succeeded.
+    fn run_completion_command(&self) {
+        let command = &self.config.on_complete_command;
+        if command.is_empty()
+            || self
+                .completion_command_started
+                .swap(true, Ordering::Relaxed)
+        {
+            return;
+        }
+
+        let path = self.storage.path();
+        match completion_command::spawn(command, &self.torrent, path.as_deref()) {
+            // Waits for the command so it does not remain as a zombie process.
+            Ok(mut child) => {
+                thread::spawn(move || child.wait());
+            }
+            Err(err) => warn!("Could not run the completion command: {:?}", err),
+        }
+    }
+
+    /// Returns an error if the piece is not downloading, counting the bytes of the piece as wasted if it was
+    /// already downloaded.
+    fn check_piece_downloading(
+        &self,
+        piece_status: &PieceStates,
+        index: u32,
+        piece: &[u8],
+    ) -> Result<(), AtomicTorrentStatusError> {
+        match piece_status.get(index) {
+            Some(value) => {
+                // The piece was already downloaded by another peer, so the received bytes were wasted.
+                if value == PieceStatus::Finished {
+                    self.add_wasted_bytes(piece.len() as u64);
+                }
+                if value != PieceStatus::Downloading {
+                    return Err(AtomicTorrentStatusError::PieceWasNotDownloading);
+                }
+            }
+            None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
+        }
+        Ok(())
+    }
+
+    /// Gets a piece already downloaded from the disk.
+    ///
+    /// The lock on the pieces status is released before reading, so blocks are read in parallel.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `InvalidPieceIndex` if the piece index is invalid.
+    /// - `PieceWasNotFinished` if the piece was not donwloaded.
+    pub fn get_piece(
+        &self,
+        index: u32,
+        offset: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, AtomicTorrentStatusError> {
+        match self.lock_pieces_status()?.get(index) {
+            Some(PieceStatus::Finished) => (),
+            Some(_) => return Err(AtomicTorrentStatusError::PieceWasNotFinished),
+            None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
+        }
+
+        self.storage
+            .read_block(offset, length)
+            .map_err(AtomicTorrentStatusError::RetrievingPieceError)
+    }
+
+    /// Returns the path where the downloaded file of the torrent is saved once the download finishes,
+    /// or `None` if its storage does not keep it in a file.
+    pub fn output_path(&self) -> Option<PathBuf> {
+        self.storage.path()
+    }
+
+    /// Changes the path where the downloaded file of the torrent is saved.
+    ///
+    /// If part of the file was already downloaded it is moved to the new path, creating its directory if needed.
+    ///
+    /// # Errors
+    /// - `MoveOutputError` if the downloaded file could not be moved to the new path, or the storage does not keep it in a file.
+    pub fn set_output_path(&self, path: PathBuf) -> Result<(), AtomicTorrentStatusError> {
+        self.storage
+            .set_path(path)
+            .map_err(AtomicTorrentStatusError::MoveOutputError)
+    }
+
+    /// Reserves the space of the whole torrent in its storage.
+    ///
+    /// # Errors
+    /// - `AllocateError` if the space could not be reserved.
+    pub fn allocate(&self) -> Result<(), AtomicTorrentStatusError> {
+        self.storage
+            .allocate(self.torrent.length())
+            .map_err(AtomicTorrentStatusError::AllocateError)
+    }
+
+    /// Checks the hash of a piece kept in the storage against the hash in the torrent.
+    ///
+    /// # Errors
+    /// - `InvalidPieceIndex` if the piece index is invalid.
+    /// - `VerifyPieceError` if the piece could not be read from the storage.
+    pub fn verify_piece(&self, index: u32) -> Result<bool, AtomicTorrentStatusError> {
+        let start = index as usize * 20;
+        let hash = self
+            .torrent
+            .info
+            .pieces
+            .get(start..start + 20)
+            .ok_or(AtomicTorrentStatusError::InvalidPieceIndex)?;
+        self.storage
+            .verify(
+                self.torrent.piece_offset(index),
+                self.torrent.piece_size(index) as usize,
+                hash,
+            )
+            .map_err(AtomicTorrentStatusError::VerifyPieceError)
+    }
+
+    /// Aborts a piece download.
+    ///
+    /// This must be called when a piece obteined from `select_piece` can not longer be downloaded.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `InvalidPieceIndex` if the piece index is invalid.
+    /// - `PieceWasNotDownloading` if the piece was not downloading.
+    pub fn piece_aborted(&self, index: u32) -> Result<(), AtomicTorrentStatusError> {
+        let mut piece_status = self.lock_pieces_status()?;
+        match piece_status.get(index) {
+            Some(value) => {
+                if value != PieceStatus::Downloading {
+                    return Err(AtomicTorrentStatusError::PieceWasNotDownloading);
+                }
+            }
+            None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
+        }
+        piece_status.set(index, PieceStatus::Free);
+        self.downloading_pieces.fetch_sub(1, Ordering::Relaxed);
+        self.free_pieces.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the current bitfield of the torrent.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    pub fn get_bitfield(&self) -> Result<Bitfield, AtomicTorrentStatusError> {
+        let pieces_status = self.lock_pieces_status()?;
+        Ok(Bitfield::from(pieces_status.as_slice()))
+    }
+
+    fn lock_pieces_status(&self) -> Result<MutexGuard<'_, PieceStates>, AtomicTorrentStatusError> {
+        self.pieces_status
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedPiecesStatusLock)
+    }
+
+    fn lock_pieces_priority(
+        &self,
+    ) -> Result<MutexGuard<'_, Vec<PiecePriority>>, AtomicTorrentStatusError> {
+        self.pieces_priority
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedPiecesPriorityLock)
+    }
+
+    fn lock_super_seed_status(
+        &self,
+    ) -> Result<MutexGuard<'_, SuperSeedStatus>, AtomicTorrentStatusError> {
+        self.super_seed_status
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedSuperSeedStatusLock)
+    }
+
+    fn lock_choker(&self) -> Result<MutexGuard<'_, Choker>, AtomicTorrentStatusError> {
+        self.choker
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedChokerLock)
+    }
+
+    fn lock_disconnect_requests(
+        &self,
+    ) -> Result<MutexGuard<'_, HashSet<BtPeer>>, AtomicTorrentStatusError> {
+        self.disconnect_requests
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedDisconnectRequestsLock)
+    }
+
+    fn lock_peers_that_provided_data(
+        &self,
+    ) -> Result<MutexGuard<'_, HashSet<BtPeer>>, AtomicTorrentStatusError> {
+        self.peers_that_provided_data
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedPeersThatProvidedDataLock)
+    }
+
+    fn lock_trackers_health(
+        &self,
+    ) -> Result<MutexGuard<'_, Vec<TrackerHealth>>, AtomicTorrentStatusError> {
+        self.trackers_health
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedTrackersHealthLock)
+    }
+
+    fn lock_banned_ips(&self) -> Result<MutexGuard<'_, HashSet<String>>, AtomicTorrentStatusError> {
+        self.banned_ips
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedBannedIpsLock)
+    }
+
+    fn lock_session_status(
+        &self,
+    ) -> Result<MutexGuard<'_, HashMap<ConnectionId, ConnectedPeer>>, AtomicTorrentStatusError>
+    {
+        self.sessions_status
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedSessionsStatusLock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, sync::Arc, thread, time::Duration};
+
+    use crate::{
+        peer::features::Features,
+        storage_manager::{memory_storage::MemoryStorage, storage::Storage},
+        test_support::{fixtures::test_config, temp_dir::TempDir},
+        torrent_parser::info::Info,
+    };
+    use sha1::{Digest, Sha1};
+    use url_encoder::info_hash::InfoHash;
+
+    use super::*;
+
+    #[test]
+    fn test_is_not_finished() {
+        let torrent = create_test_torrent("test_is_not_finished");
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        assert!(!status.is_finished());
+    }
+
+    #[test]
+    fn test_is_finished() {
+        let torrent = create_test_torrent("test_is_finished");
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        for _ in 0..(torrent.info.length / torrent.info.piece_length) {
+            let index = status
+                .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+                .unwrap()
+                .unwrap();
+            status.piece_downloaded(index as u32, &[]).unwrap();
+        }
+        assert!(status.is_finished());
+    }
+
+    /// `Storage` that takes a while to write, counting the most pieces written at the same time.
+    #[derive(Debug, Default)]
+    struct SlowStorage {
+        writing: AtomicUsize,
+        max_writing: Arc<AtomicUsize>,
+    }
+
+    impl Storage for SlowStorage {
+        fn write_piece(&self, _offset: u64, _piece: &[u8]) -> io::Result<()> {
+            let writing = self.writing.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_writing.fetch_max(writing, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(50));
+            self.writing.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn read_block(&self, _offset: u64, length: usize) -> io::Result<Vec<u8>> {
+            Ok(vec![0; length])
+        }
+
+        fn allocate(&self, _length: u64) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_pieces_are_written_in_parallel() {
+        let torrent = create_test_torrent("test_pieces_are_written_in_parallel");
+        let storage = SlowStorage::default();
+        let max_writing = storage.max_writing.clone();
+        let status = AtomicTorrentStatus::with_storage(&torrent, Cfg::default(), Box::new(storage));
+
+        let pieces: Vec<u32> = (0..torrent.total_pieces())
+            .map(|_| {
+                status
+                    .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+                    .unwrap()
+                    .unwrap()
+            })
+            .collect();
+
+        thread::scope(|scope| {
+            for index in pieces {
+                let status = &status;
+                scope.spawn(move || status.piece_downloaded(index, &[0]).unwrap());
+            }
+            // Other peers can use the status while the pieces are written.
+            thread::sleep(Duration::from_millis(10));
+            assert!(status.get_bitfield().is_ok());
+            assert!(status.get_piece(0, 0, 1).is_err());
+        });
+
+        assert!(status.is_finished());
+        assert!(max_writing.load(Ordering::SeqCst) > 1);
+    }
+
+    #[test]
+    fn test_endgame_selects_downloading_pieces_of_the_peer() {
+        let torrent = create_test_torrent("test_endgame_selects_downloading_pieces_of_the_peer");
+        let status = create_status_whitout_receiver(&torrent, Cfg::default());
+        for _ in 0..torrent.total_pieces() {
+            status
+                .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+                .unwrap()
+                .unwrap();
+        }
+
+        let endgame_piece = status
+            .select_piece(&Bitfield::new(vec![0b00000000, 0b01000000]))
+            .unwrap();
+
+        assert_eq!(endgame_piece, Some(9));
+        assert_eq!(status.downloading_pieces(), 10);
+        assert_eq!(
+            status
+                .select_piece(&Bitfield::new(vec![0b00000000, 0b00100000]))
+                .unwrap(),
+            None
+        );
     }
 
     #[test]
     fn test_starting_current_peers() {
         let torrent = create_test_torrent("test_starting_current_peers");
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
-        let status = create_status_whitout_receiver(&torrent, config.clone());
-        assert_eq!(0, status.current_peers());
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        assert_eq!(0, status.current_peers());
+    }
+
+    #[test]
+    fn test_peer_connected() {
+        let torrent = create_test_torrent("test_peer_connected");
+        let peer = create_test_peer("192.0".to_string());
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        status.peer_connected(&peer).unwrap();
+        assert_eq!(1, status.current_peers());
+    }
+
+    #[test]
+    fn test_peer_disconnected() {
+        let torrent = create_test_torrent("test_peer_disconnected");
+        let peer = create_test_peer("192.0".to_string());
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        let connection = status.peer_connected(&peer).unwrap();
+        status.peer_connected(&peer).unwrap();
+        status.peer_disconnected(connection).unwrap();
+        assert_eq!(1, status.current_peers());
+        assert!(status.is_connected(&peer).unwrap());
+    }
+
+    #[test]
+    fn test_peer_id_connected() {
+        let torrent = create_test_torrent("test_peer_id_connected");
+        let mut peer = create_test_peer("192.0".to_string());
+        peer.peer_id = Some(b"-DT0001-connected-00".to_vec());
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        assert!(!status
+            .is_peer_id_connected(b"-DT0001-connected-00")
+            .unwrap());
+
+        status.peer_connected(&peer).unwrap();
+        assert!(status
+            .is_peer_id_connected(b"-DT0001-connected-00")
+            .unwrap());
+        assert!(!status
+            .is_peer_id_connected(b"-DT0001-other-000000")
+            .unwrap());
+    }
+
+    #[test]
+    fn test_peer_connected_once_refuses_duplicates() {
+        let torrent = create_test_torrent("test_peer_connected_once_refuses_duplicates");
+        let mut peer = create_test_peer("192.0".to_string());
+        peer.peer_id = Some(b"-DT0001-bbbbbbbbbbbb".to_vec());
+        let mut other_port = peer.clone();
+        other_port.port = 6881;
+
+        let status = create_status_whitout_receiver(&torrent, Cfg::default());
+        status
+            .peer_connected_once(&peer, ConnectionDirection::Incoming, CLIENT_PEER_ID)
+            .unwrap();
+
+        assert!(matches!(
+            status.peer_connected_once(&other_port, ConnectionDirection::Incoming, CLIENT_PEER_ID),
+            Err(AtomicTorrentStatusError::DuplicateConnection)
+        ));
+        assert_eq!(1, status.current_peers());
+    }
+
+    #[test]
+    fn test_simultaneous_connections_keep_the_one_of_the_lower_peer_id() {
+        let torrent = create_test_torrent("test_simultaneous_connections_keep_lower_peer_id");
+        let mut incoming = create_test_peer("192.0".to_string());
+        incoming.peer_id = Some(b"-DT0001-bbbbbbbbbbbb".to_vec());
+        let mut outgoing = incoming.clone();
+        outgoing.port = 6881;
+
+        // Our peer id is lower, so our outgoing connection replaces the incoming one.
+        let status = create_status_whitout_receiver(&torrent, Cfg::default());
+        status
+            .peer_connected_once(&incoming, ConnectionDirection::Incoming, CLIENT_PEER_ID)
+            .unwrap();
+        assert!(status
+            .keeps_connection(
+                b"-DT0001-bbbbbbbbbbbb",
+                ConnectionDirection::Outgoing,
+                CLIENT_PEER_ID
+            )
+            .unwrap());
+        status
+            .peer_connected_once(&outgoing, ConnectionDirection::Outgoing, CLIENT_PEER_ID)
+            .unwrap();
+        assert!(status.should_disconnect(&incoming).unwrap());
+        assert!(!status.should_disconnect(&outgoing).unwrap());
+
+        // The peer opened the incoming one, and its peer id is lower.
+        let status = create_status_whitout_receiver(&torrent, Cfg::default());
+        status
+            .peer_connected_once(
+                &incoming,
+                ConnectionDirection::Incoming,
+                b"-DT0001-cccccccccccc",
+            )
+            .unwrap();
+        assert!(!status
+            .keeps_connection(
+                b"-DT0001-bbbbbbbbbbbb",
+                ConnectionDirection::Outgoing,
+                b"-DT0001-cccccccccccc"
+            )
+            .unwrap());
+        assert!(matches!(
+            status.peer_connected_once(
+                &outgoing,
+                ConnectionDirection::Outgoing,
+                b"-DT0001-cccccccccccc"
+            ),
+            Err(AtomicTorrentStatusError::DuplicateConnection)
+        ));
+        assert!(!status.should_disconnect(&incoming).unwrap());
+    }
+
+    #[test]
+    fn test_peer_disconnected_error() {
+        let torrent = create_test_torrent("test_peer_disconnected_error");
+        let peer = create_test_peer("192.0".to_string());
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        assert!(status.peer_disconnected(ConnectionId(0)).is_err());
+
+        let connection = status.peer_connected(&peer).unwrap();
+        status.peer_connected(&peer).unwrap();
+        status.peer_disconnected(connection).unwrap();
+        assert!(matches!(
+            status.peer_disconnected(connection),
+            Err(AtomicTorrentStatusError::UnknownConnection)
+        ));
+    }
+
+    #[test]
+    fn test_select_piece() {
+        let torrent = create_test_torrent("test_piece_downloaded");
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            status.pieces_status.lock().unwrap().get(index).unwrap(),
+            PieceStatus::Downloading
+        );
+    }
+
+    #[test]
+    fn test_set_output_path_moves_downloaded_file() {
+        let torrent = create_test_torrent("test_set_output_path_moves_downloaded_file");
+
+        let dir = TempDir::new("test_set_output_path_moves_downloaded_file");
+        let config = test_config(&dir);
+        let status = AtomicTorrentStatus::new(&torrent, config.clone());
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap()
+            .unwrap();
+        status.piece_downloaded(index as u32, &[7]).unwrap();
+
+        let new_path = PathBuf::from(format!(
+            "{}/test_set_output_path/moved",
+            config.download_directory
+        ));
+        status.set_output_path(new_path.clone()).unwrap();
+
+        assert_eq!(status.output_path(), Some(new_path));
+        assert_eq!(status.get_piece(index, index as u64, 1).unwrap(), vec![7]);
+        assert!(!PathBuf::from(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .exists());
+    }
+
+    #[test]
+    fn test_part_file_renamed_when_finished() {
+        let torrent = Torrent {
+            info: Info {
+                length: 2,
+                ..create_test_torrent("test_part_file_renamed_when_finished").info
+            },
+            ..create_test_torrent("test_part_file_renamed_when_finished")
+        };
+        let dir = TempDir::new("test_part_file_renamed_when_finished");
+        let config = Cfg {
+            part_files: true,
+            ..test_config(&dir)
+        };
+        let status = AtomicTorrentStatus::new(&torrent, config.clone());
+        let path = status.output_path().unwrap();
+        let part_path = PathBuf::from(format!("{}.part", path.display()));
+
+        let bitfield = Bitfield::new(vec![0b11000000]);
+        let first = status.select_piece(&bitfield).unwrap().unwrap();
+        status.piece_downloaded(first, &[first as u8]).unwrap();
+        assert!(part_path.exists());
+        assert!(!path.exists());
+
+        let second = status.select_piece(&bitfield).unwrap().unwrap();
+        status.piece_downloaded(second, &[second as u8]).unwrap();
+        assert!(!part_path.exists());
+        assert_eq!(fs::read(&path).unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_seed_ratio() {
+        let torrent = create_test_torrent("test_seed_ratio");
+        let status = create_status_whitout_receiver(&torrent, Cfg::default());
+
+        assert_eq!(status.seed_ratio(), 0.0);
+        status.add_uploaded_bytes(torrent.length() * 3 / 2).unwrap();
+        assert_eq!(status.seed_ratio(), 1.5);
+    }
+
+    #[test]
+    fn test_stop_seeding_cancels_the_sessions() {
+        let torrent = create_test_torrent("test_stop_seeding_cancels_the_sessions");
+        let status = create_status_whitout_receiver(&torrent, Cfg::default());
+        let session_token = status.cancellation_token().unwrap();
+
+        assert!(!status.is_seeding_stopped());
+        status.stop_seeding().unwrap();
+
+        assert!(status.is_seeding_stopped());
+        assert!(session_token.is_cancelled());
     }
 
     #[test]
-    fn test_peer_connected() {
-        let torrent = create_test_torrent("test_peer_connected");
-        let peer = create_test_peer("192.0".to_string());
-
-        let config = Cfg::new(CONFIG_PATH).unwrap();
-        let status = create_status_whitout_receiver(&torrent, config.clone());
-        status.peer_connected(&peer).unwrap();
-        assert_eq!(1, status.current_peers());
+    fn test_pause_and_resume() {
+        let torrent = create_test_torrent("test_pause_and_resume");
+        let status = create_status_whitout_receiver(&torrent, Cfg::default());
+        let session_token = status.cancellation_token().unwrap();
+
+        status.pause().unwrap();
+        assert!(status.is_paused());
+        assert!(session_token.is_cancelled());
+
+        status.resume();
+        assert!(!status.is_paused());
+        assert!(!status.cancellation_token().unwrap().is_cancelled());
     }
 
     #[test]
-    fn test_peer_disconnected() {
-        let torrent = create_test_torrent("test_peer_disconnected");
-        let peer = create_test_peer("192.0".to_string());
+    fn test_removed_torrent_cancels_the_sessions_and_saves_no_pieces() {
+        let torrent = create_test_torrent("test_removed_torrent");
+        let status = create_status_whitout_receiver(&torrent, Cfg::default());
+        let session_token = status.cancellation_token().unwrap();
+
+        status.remove().unwrap();
+
+        assert!(status.is_removed());
+        assert!(session_token.is_cancelled());
+        assert!(matches!(
+            status.piece_downloaded(0, &[0]),
+            Err(AtomicTorrentStatusError::TorrentRemoved)
+        ));
+    }
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
-        let status = create_status_whitout_receiver(&torrent, config.clone());
-        status.peer_connected(&peer).unwrap();
-        status.peer_connected(&peer).unwrap();
-        status.peer_disconnected(&peer).unwrap();
-        assert_eq!(1, status.current_peers());
+    #[cfg(unix)]
+    #[test]
+    fn test_completion_command_runs_once_when_finished() {
+        let torrent = Torrent {
+            info: Info {
+                length: 2,
+                ..create_test_torrent("test_completion_command_runs_once").info
+            },
+            ..create_test_torrent("test_completion_command_runs_once")
+        };
+        let dir = TempDir::new("test_completion_command_runs_once");
+        let output = dir.join("completed");
+        let config = Cfg {
+            on_complete_command: format!("echo \"$DTORRENT_TORRENT_PATH\" >> {}", output.display()),
+            ..test_config(&dir)
+        };
+        let status = AtomicTorrentStatus::new(&torrent, config);
+        status.allocate().unwrap();
+
+        let bitfield = Bitfield::new(vec![0b11000000]);
+        let first = status.select_piece(&bitfield).unwrap().unwrap();
+        status.piece_downloaded(first, &[first as u8]).unwrap();
+        assert!(!status.completion_command_started.load(Ordering::Relaxed));
+
+        let second = status.select_piece(&bitfield).unwrap().unwrap();
+        status.piece_downloaded(second, &[second as u8]).unwrap();
+        status.run_completion_command();
+
+        let expected = format!("{}\n", status.output_path().unwrap().display());
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while fs::read_to_string(&output).ok().as_deref() != Some(expected.as_str()) {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "the command did not run"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
     }
 
     #[test]
-    fn test_peer_disconnected_error() {
-        let torrent = create_test_torrent("test_peer_disconnected_error");
-        let peer = create_test_peer("192.0".to_string());
+    fn test_hash_piece_saves_or_aborts_it() {
+        let torrent = Torrent {
+            info: Info {
+                length: 2,
+                pieces: [Sha1::digest([5]).as_slice(), Sha1::digest([6]).as_slice()].concat(),
+                ..create_test_torrent("test_hash_piece").info
+            },
+            ..create_test_torrent("test_hash_piece")
+        };
+        let status = Arc::new(AtomicTorrentStatus::with_storage(
+            &torrent,
+            Cfg::default(),
+            Box::new(MemoryStorage::new()),
+        ));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let bitfield = Bitfield::new(vec![0b11000000]);
+        let first = status.select_piece(&bitfield).unwrap().unwrap();
+        let second = status.select_piece(&bitfield).unwrap().unwrap();
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
-        let status = create_status_whitout_receiver(&torrent, config.clone());
-        assert!(status.peer_disconnected(&peer).is_err());
+        status
+            .hash_piece(first, vec![first as u8 + 5], sender.clone())
+            .unwrap();
+        status.hash_piece(second, vec![0], sender).unwrap();
+
+        let mut hashed: Vec<_> = receiver
+            .iter()
+            .map(|hashed| (hashed.index, hashed.length, hashed.result.unwrap()))
+            .collect();
+        hashed.sort();
+        let mut expected = vec![(first, 1, true), (second, 1, false)];
+        expected.sort();
+        assert_eq!(hashed, expected);
+        assert_eq!(status.downloaded_pieces(), 1);
+        assert_eq!(status.downloading_pieces(), 0);
+        assert_eq!(status.remaining_pieces(), 1);
     }
 
     #[test]
-    fn test_select_piece() {
-        let torrent = create_test_torrent("test_piece_downloaded");
+    fn test_allocate_and_verify_piece() {
+        let torrent = Torrent {
+            info: Info {
+                length: 2,
+                pieces: [Sha1::digest([5]).as_slice(), Sha1::digest([6]).as_slice()].concat(),
+                ..create_test_torrent("test_allocate_and_verify_piece").info
+            },
+            ..create_test_torrent("test_allocate_and_verify_piece")
+        };
+        let status = create_status_whitout_receiver(&torrent, Cfg::default());
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
-        let status = create_status_whitout_receiver(&torrent, config.clone());
-        let index = status
-            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
-            .unwrap()
-            .unwrap();
-        assert_eq!(
-            *status.pieces_status.lock().unwrap().get(&index).unwrap(),
-            PieceStatus::Downloading
-        );
+        status.allocate().unwrap();
+        assert!(!status.verify_piece(0).unwrap());
+
+        let bitfield = Bitfield::new(vec![0b10000000]);
+        let index = status.select_piece(&bitfield).unwrap().unwrap();
+        status.piece_downloaded(index, &[5]).unwrap();
+
+        assert!(status.verify_piece(0).unwrap());
+        assert!(!status.verify_piece(1).unwrap());
+        assert!(status.output_path().is_none());
     }
 
     #[test]
     fn test_no_pieces_to_select() {
         let torrent = create_test_torrent("test_no_pieces_to_select");
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let config = Cfg::default();
         let status = create_status_whitout_receiver(&torrent, config.clone());
         let index = status
             .select_piece(&Bitfield::new(vec![0b00000000, 0b00000000]))
@@ -525,7 +1997,7 @@ mod tests {
     fn test_piece_downloaded() {
         let torrent = create_test_torrent("test_piece_downloaded");
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let config = Cfg::default();
         let status = create_status_whitout_receiver(&torrent, config.clone());
         let index = status
             .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
@@ -533,21 +2005,16 @@ mod tests {
             .unwrap();
         status.piece_downloaded(index as u32, &[]).unwrap();
         assert_eq!(
-            *status.pieces_status.lock().unwrap().get(&index).unwrap(),
+            status.pieces_status.lock().unwrap().get(index).unwrap(),
             PieceStatus::Finished
         );
-        fs::remove_file(format!(
-            "{}/{}",
-            config.download_directory, torrent.info.name
-        ))
-        .unwrap();
     }
 
     #[test]
     fn test_piece_aborted() {
         let torrent = create_test_torrent("test_piece_aborted");
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let config = Cfg::default();
         let status = create_status_whitout_receiver(&torrent, config.clone());
         let index = status
             .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
@@ -555,7 +2022,7 @@ mod tests {
             .unwrap();
         status.piece_aborted(index).unwrap();
         assert_eq!(
-            *status.pieces_status.lock().unwrap().get(&index).unwrap(),
+            status.pieces_status.lock().unwrap().get(index).unwrap(),
             PieceStatus::Free
         );
     }
@@ -564,7 +2031,7 @@ mod tests {
     fn test_bad_index() {
         let torrent = create_test_torrent("test_bad_index");
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let config = Cfg::default();
         let status = create_status_whitout_receiver(&torrent, config.clone());
         let index = 1000;
         assert!(status.piece_downloaded(index, &[]).is_err());
@@ -574,7 +2041,7 @@ mod tests {
     fn test_multiple_threads_current_peers() {
         let torrent = create_test_torrent("test_multiple_threads");
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let config = Cfg::default();
         let status = Arc::new(create_status_whitout_receiver(&torrent, config.clone()));
         let mut joins = Vec::new();
 
@@ -594,7 +2061,7 @@ mod tests {
     fn test_multiple_threads_piece_status() {
         let torrent = create_test_torrent("test_multiple_threads_piece_status");
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let config = Cfg::default();
         let status = Arc::new(create_status_whitout_receiver(&torrent, config.clone()));
         let mut joins = Vec::new();
 
@@ -613,18 +2080,13 @@ mod tests {
             join.join().unwrap();
         }
         assert!(status.is_finished());
-        fs::remove_file(format!(
-            "{}/{}",
-            config.download_directory, torrent.info.name
-        ))
-        .unwrap();
     }
 
     #[test]
     fn test_bad_downloaded() {
         let torrent = create_test_torrent("test_bad_downloaded");
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let config = Cfg::default();
         let status = create_status_whitout_receiver(&torrent, config.clone());
         let index = 0;
         assert!(status.piece_downloaded(index, &[]).is_err());
@@ -634,7 +2096,7 @@ mod tests {
     fn test_bad_abort() {
         let torrent = create_test_torrent("test_bad_abort");
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let config = Cfg::default();
         let status = create_status_whitout_receiver(&torrent, config.clone());
         let index = 0;
         assert!(status.piece_aborted(index).is_err());
@@ -644,7 +2106,7 @@ mod tests {
     fn test_remaining_pieces() {
         let torrent = create_test_torrent("test_remaining_pieces");
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let config = Cfg::default();
         let status = create_status_whitout_receiver(&torrent, config.clone());
 
         let total_pieces = (torrent.info.length / torrent.info.piece_length) as usize;
@@ -659,17 +2121,12 @@ mod tests {
 
         assert_eq!(remaining_starting_pieces, total_pieces);
         assert_eq!(status.remaining_pieces(), total_pieces - 1);
-        fs::remove_file(format!(
-            "{}/{}",
-            config.download_directory, torrent.info.name
-        ))
-        .unwrap();
     }
 
     #[test]
     fn test_downloading_pieces() {
         let torrent = create_test_torrent("test_downloading_pieces");
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let config = Cfg::default();
         let status = create_status_whitout_receiver(&torrent, config.clone());
 
         let _ = status
@@ -683,7 +2140,7 @@ mod tests {
     #[test]
     fn test_downloaded_pieces() {
         let torrent = create_test_torrent("test_downloaded_pieces");
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let config = Cfg::default();
         let status = create_status_whitout_receiver(&torrent, config.clone());
 
         let index = status
@@ -693,30 +2150,177 @@ mod tests {
         status.piece_downloaded(index, &[]).unwrap();
 
         assert_eq!(status.downloaded_pieces(), 1);
-        fs::remove_file(format!(
-            "{}/{}",
-            config.download_directory, torrent.info.name
-        ))
-        .unwrap();
     }
 
     #[test]
-    fn test_torrent_status_channel() {
-        let torrent = create_test_torrent("test_torrent_status_channel");
+    fn test_wait_for_peers_wakes_on_disconnection() {
+        let torrent = create_test_torrent("test_wait_for_peers_wakes_on_disconnection");
         let peer = create_test_peer("192.0".to_string());
 
-        let (status, receiver) = AtomicTorrentStatus::new(&torrent, Cfg::new(CONFIG_PATH).unwrap());
+        let dir = TempDir::new("test_wait_for_peers_wakes_on_disconnection");
+        let status = Arc::new(AtomicTorrentStatus::new(&torrent, test_config(&dir)));
         status.peer_connecting();
         status.peer_connecting();
+        let connection = status.peer_connected(&peer).unwrap();
         status.peer_connected(&peer).unwrap();
-        status.peer_connected(&peer).unwrap();
-        status.peer_disconnected(&peer).unwrap();
-        assert_eq!(receiver.recv().unwrap(), 1);
+
+        let waiter = {
+            let status = status.clone();
+            thread::spawn(move || {
+                status.wait_for_peers(Some(Duration::from_secs(10)), || {
+                    status.all_current_peers() < 2
+                })
+            })
+        };
+        status.peer_disconnected(connection).unwrap();
+
+        assert!(waiter.join().unwrap().unwrap());
+        assert_eq!(status.all_current_peers(), 1);
     }
 
     #[test]
     fn test_torrent_download_speed() {
         let torrent = create_test_torrent("test_torrent_download_speed");
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        assert_eq!(status.torrent_download_speed().unwrap(), 0.0);
+
+        status.add_downloaded_bytes(16384).unwrap();
+        assert!(status.torrent_download_speed().unwrap() > 0.0);
+        assert_eq!(status.torrent_upload_speed().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_torrent_upload_speed() {
+        let torrent = create_test_torrent("test_torrent_upload_speed");
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        assert_eq!(status.torrent_upload_speed().unwrap(), 0.0);
+
+        status.add_uploaded_bytes(16384).unwrap();
+        assert!(status.torrent_upload_speed().unwrap() > 0.0);
+        assert_eq!(status.torrent_download_speed().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_select_piece_with_higher_priority() {
+        let torrent = create_test_torrent("test_select_piece_with_higher_priority");
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        status.set_piece_priority(7, PiecePriority::High).unwrap();
+        status.set_piece_priority(2, PiecePriority::Low).unwrap();
+
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(index, 7);
+    }
+
+    #[test]
+    fn test_sequential_download_selects_pieces_in_order() {
+        let torrent = create_test_torrent("test_sequential_download_selects_pieces_in_order");
+
+        let config = Cfg {
+            sequential_download: true,
+            ..Cfg::default()
+        };
+        let status = create_status_whitout_receiver(&torrent, config);
+        status.set_piece_priority(7, PiecePriority::High).unwrap();
+        status.set_piece_priority(2, PiecePriority::Skip).unwrap();
+
+        let mut selected = vec![];
+        while let Some(index) = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap()
+        {
+            status.piece_downloaded(index, &[]).unwrap();
+            selected.push(index);
+        }
+
+        assert_eq!(selected, vec![7, 0, 1, 3, 4, 5, 6, 8, 9]);
+    }
+
+    #[test]
+    fn test_skipped_pieces_are_not_selected() {
+        let torrent = create_test_torrent("test_skipped_pieces_are_not_selected");
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        for index in 0..10 {
+            status
+                .set_piece_priority(index, PiecePriority::Skip)
+                .unwrap();
+        }
+
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap();
+        assert!(index.is_none());
+    }
+
+    #[test]
+    fn test_is_finished_with_skipped_pieces() {
+        let torrent = create_test_torrent("test_is_finished_with_skipped_pieces");
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        for index in 1..10 {
+            status
+                .set_piece_priority(index, PiecePriority::Skip)
+                .unwrap();
+        }
+        assert_eq!(status.remaining_pieces(), 1);
+
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(index, 0);
+        status.piece_downloaded(index, &[]).unwrap();
+
+        assert!(status.is_finished());
+    }
+
+    #[test]
+    fn test_no_super_seeding_with_skipped_pieces() {
+        let torrent = create_test_torrent("test_no_super_seeding_with_skipped_pieces");
+
+        let config = Cfg {
+            super_seeding: true,
+            ..Cfg::default()
+        };
+        let status = create_status_whitout_receiver(&torrent, config);
+        status.set_piece_priority(1, PiecePriority::Skip).unwrap();
+        while let Some(index) = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap()
+        {
+            status.piece_downloaded(index, &[]).unwrap();
+        }
+
+        assert!(status.is_finished());
+        assert!(!status.is_super_seeding());
+    }
+
+    #[test]
+    fn test_set_piece_priority_bad_index() {
+        let torrent = create_test_torrent("test_set_piece_priority_bad_index");
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        assert!(status
+            .set_piece_priority(1000, PiecePriority::High)
+            .is_err());
+        assert_eq!(status.piece_priority(0).unwrap(), PiecePriority::Normal);
+    }
+
+    #[test]
+    fn test_slowest_peer() {
+        let torrent = create_test_torrent("test_slowest_peer");
         let peer1 = create_test_peer("192.0".to_string());
         let peer2 = create_test_peer("932.0".to_string());
 
@@ -724,44 +2328,264 @@ mod tests {
         peer_session1.download_speed = 1500.0;
 
         let mut peer_session2 = create_test_peer_session_status();
-        peer_session2.download_speed = 2500.0;
+        peer_session2.upload_speed = 200.0;
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let config = Cfg::default();
         let status = create_status_whitout_receiver(&torrent, config.clone());
-        status.peer_connected(&peer1).unwrap();
-        status.peer_connected(&peer2).unwrap();
+        let connection1 = status.peer_connected(&peer1).unwrap();
+        let connection2 = status.peer_connected(&peer2).unwrap();
         status
-            .update_peer_session_status(&peer1, &peer_session1)
+            .update_peer_session_status(connection1, &peer1, &peer_session1)
             .unwrap();
         status
-            .update_peer_session_status(&peer2, &peer_session2)
+            .update_peer_session_status(connection2, &peer2, &peer_session2)
             .unwrap();
-        assert_eq!(status.torrent_download_speed().unwrap(), 4000.0);
+        assert_eq!(status.slowest_peer().unwrap(), Some((peer2, 200.0)));
     }
 
     #[test]
-    fn test_torrent_upload_speed() {
-        let torrent = create_test_torrent("test_torrent_upload_speed");
+    fn test_slowest_peer_skips_peers_asked_to_disconnect() {
+        let torrent = create_test_torrent("test_slowest_peer_skips_peers_asked_to_disconnect");
         let peer1 = create_test_peer("192.0".to_string());
         let peer2 = create_test_peer("932.0".to_string());
 
         let mut peer_session1 = create_test_peer_session_status();
-        peer_session1.upload_speed = 100.0;
-
-        let mut peer_session2 = create_test_peer_session_status();
-        peer_session2.upload_speed = 200.0;
+        peer_session1.download_speed = 1500.0;
 
-        let config = Cfg::new(CONFIG_PATH).unwrap();
-        let status = create_status_whitout_receiver(&torrent, config.clone());
-        status.peer_connected(&peer1).unwrap();
+        let status = create_status_whitout_receiver(&torrent, Cfg::default());
+        let connection1 = status.peer_connected(&peer1).unwrap();
         status.peer_connected(&peer2).unwrap();
         status
-            .update_peer_session_status(&peer1, &peer_session1)
+            .update_peer_session_status(connection1, &peer1, &peer_session1)
+            .unwrap();
+
+        status.request_peer_disconnect(&peer2).unwrap();
+        assert_eq!(status.pending_disconnects().unwrap(), 1);
+        assert_eq!(
+            status.slowest_peer().unwrap(),
+            Some((peer1.clone(), 1500.0))
+        );
+
+        status.request_peer_disconnect(&peer1).unwrap();
+        assert_eq!(status.slowest_peer().unwrap(), None);
+    }
+
+    #[test]
+    fn test_update_peer_session_status_keeps_dht_port() {
+        let torrent = create_test_torrent("test_update_peer_session_status_keeps_dht_port");
+        let mut peer = create_test_peer("192.0".to_string());
+
+        let status = create_status_whitout_receiver(&torrent, Cfg::default());
+        let connection = status.peer_connected(&peer).unwrap();
+        peer.dht_port = Some(6881);
+        status
+            .update_peer_session_status(connection, &peer, &create_test_peer_session_status())
+            .unwrap();
+
+        let mut dht_ports = vec![];
+        status
+            .for_each_connected_peer(|connected_peer, _| dht_ports.push(connected_peer.dht_port))
             .unwrap();
+        assert_eq!(dht_ports, vec![Some(6881)]);
+    }
+
+    #[test]
+    fn test_peer_snapshots() {
+        let torrent = create_test_torrent("test_peer_snapshots");
+        let mut peer = create_test_peer("192.0".to_string());
+        peer.peer_id = Some(b"-DT0001-snapshot-000".to_vec());
+        peer.client = Some("dtorrent 0.0.0.1".to_string());
+
+        let status = create_status_whitout_receiver(&torrent, Cfg::default());
+        let connection = status.peer_connected(&peer).unwrap();
+        let mut session = create_test_peer_session_status();
+        session.peer_interested = true;
+        session.upload_speed = 800.0;
+        session.peer_pieces = 3;
         status
-            .update_peer_session_status(&peer2, &peer_session2)
+            .update_peer_session_status(connection, &peer, &session)
+            .unwrap();
+
+        let snapshots = status.peer_snapshots().unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].ip, "192.0");
+        assert_eq!(snapshots[0].client, Some("dtorrent 0.0.0.1".to_string()));
+        assert!(snapshots[0].peer_interested);
+        assert_eq!(snapshots[0].upload_speed, 800.0);
+        assert_eq!(snapshots[0].pieces, 3);
+    }
+
+    #[test]
+    fn test_request_peer_disconnect() {
+        let torrent = create_test_torrent("test_request_peer_disconnect");
+        let peer = create_test_peer("192.0".to_string());
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        let connection = status.peer_connected(&peer).unwrap();
+        assert!(!status.should_disconnect(&peer).unwrap());
+
+        status.request_peer_disconnect(&peer).unwrap();
+        assert!(status.should_disconnect(&peer).unwrap());
+
+        status.peer_disconnected(connection).unwrap();
+        assert!(!status.should_disconnect(&peer).unwrap());
+    }
+
+    #[test]
+    fn test_half_open_peers() {
+        let torrent = create_test_torrent("test_half_open_peers");
+        let peer = create_test_peer("192.0".to_string());
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        status.peer_connecting();
+        status.peer_connecting();
+        assert_eq!(status.half_open_peers(), 2);
+
+        status.peer_connected(&peer).unwrap();
+        assert_eq!(status.half_open_peers(), 1);
+    }
+
+    #[test]
+    fn test_peer_provided_data() {
+        let torrent = create_test_torrent("test_peer_provided_data");
+        let peer = create_test_peer("192.0".to_string());
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        assert!(!status.has_provided_data(&peer).unwrap());
+
+        status.peer_provided_data(&peer).unwrap();
+        assert!(status.has_provided_data(&peer).unwrap());
+    }
+
+    #[test]
+    fn test_ban_peer() {
+        let torrent = create_test_torrent("test_ban_peer");
+        let peer = create_test_peer("192.0".to_string());
+        let mut same_ip_peer = create_test_peer("192.0".to_string());
+        same_ip_peer.port = 1234;
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        assert!(!status.is_banned(&peer).unwrap());
+
+        status.ban_peer(&peer).unwrap();
+        assert!(status.is_banned(&peer).unwrap());
+        assert!(status.is_banned(&same_ip_peer).unwrap());
+    }
+
+    #[test]
+    fn test_wasted_bytes() {
+        let torrent = create_test_torrent("test_wasted_bytes");
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        status.add_wasted_bytes(10);
+        status.add_wasted_bytes(5);
+        assert_eq!(status.wasted_bytes(), 15);
+    }
+
+    #[test]
+    fn test_stats() {
+        let torrent = create_test_torrent("test_stats");
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        status.add_downloaded_bytes(20).unwrap();
+        status.add_uploaded_bytes(10).unwrap();
+
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap()
+            .unwrap();
+        status.piece_downloaded(index, &[0]).unwrap();
+
+        let stats = status.stats().unwrap();
+        assert_eq!(stats.downloaded_bytes, 20);
+        assert_eq!(stats.uploaded_bytes, 10);
+        assert_eq!(stats.left_bytes, 9);
+        assert_eq!(stats.downloaded_pieces, 1);
+        assert_eq!(stats.total_pieces, 10);
+    }
+
+    #[test]
+    fn test_stats_of_an_empty_torrent() {
+        let mut torrent = create_test_torrent("test_stats_of_an_empty_torrent");
+        torrent.info.length = 0;
+
+        let status = create_status_whitout_receiver(&torrent, Cfg::default());
+
+        let stats = status.stats().unwrap();
+        assert_eq!(stats.left_bytes, 0);
+        assert_eq!(stats.total_pieces, 0);
+    }
+
+    #[test]
+    fn test_duplicated_piece_is_wasted() {
+        let torrent = create_test_torrent("test_duplicated_piece_is_wasted");
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap()
+            .unwrap();
+        status.piece_downloaded(index, &[0]).unwrap();
+        assert!(status.piece_downloaded(index, &[0]).is_err());
+
+        assert_eq!(status.stats().unwrap().wasted_bytes, 1);
+    }
+
+    #[test]
+    fn test_progress_with_short_last_piece() {
+        let mut torrent = create_test_torrent("test_progress_with_short_last_piece");
+        torrent.info.length = 10;
+        torrent.info.piece_length = 4;
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        assert_eq!(status.progress().unwrap(), 0.0);
+
+        // Only the last piece, of 2 bytes, has the bit set.
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b00100000]))
+            .unwrap()
             .unwrap();
-        assert_eq!(status.torrent_upload_speed().unwrap(), 300.0);
+        assert_eq!(index, 2);
+        status.piece_downloaded(index, &[0, 0]).unwrap();
+
+        assert_eq!(status.progress().unwrap(), 0.2);
+    }
+
+    #[test]
+    fn test_eta() {
+        let torrent = create_test_torrent("test_eta");
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        assert!(status.eta().unwrap().is_none());
+
+        status.add_downloaded_bytes(16384).unwrap();
+        assert!(status.eta().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_eta_when_finished() {
+        let torrent = create_test_torrent("test_eta_when_finished");
+
+        let config = Cfg::default();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        for _ in 0..10 {
+            let index = status
+                .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+                .unwrap()
+                .unwrap();
+            status.piece_downloaded(index, &[]).unwrap();
+        }
+        assert_eq!(status.eta().unwrap(), Some(Duration::ZERO));
+        assert_eq!(status.progress().unwrap(), 1.0);
     }
 
     // Auxiliary functions
@@ -772,21 +2596,33 @@ mod tests {
             name: name.to_string(),
             piece_length: 1,
             pieces: vec![],
+            private: None,
+            meta_version: None,
+            file_tree: Default::default(),
         };
 
         Torrent {
             announce_url: "announce".to_string(),
+            announce_list: vec![],
+            http_seeds: vec![],
+            info_hash_v2: None,
+            piece_layer: vec![],
             info,
-            info_hash: "info_hash".to_string(),
+            info_hash: InfoHash::new([0; 20]),
         }
     }
 
+    const CLIENT_PEER_ID: &[u8] = b"-DT0001-aaaaaaaaaaaa";
+
     fn create_test_peer(ip: String) -> BtPeer {
         BtPeer {
             peer_id: Some(vec![0x00]),
+            client: None,
             ip: ip,
             port: 0,
             info_hash: None,
+            features: Features::empty(),
+            dht_port: None,
         }
     }
 
@@ -795,7 +2631,6 @@ mod tests {
     }
 
     fn create_status_whitout_receiver(torrent: &Torrent, config: Cfg) -> AtomicTorrentStatus {
-        let (status, _) = AtomicTorrentStatus::new(&torrent, config);
-        status
+        AtomicTorrentStatus::with_storage(&torrent, config, Box::new(MemoryStorage::new()))
     }
 }
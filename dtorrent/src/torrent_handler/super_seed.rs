@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use crate::peer::{bt_peer::BtPeer, peer_message::Bitfield};
+
+/// Keeps track of the pieces revealed to each peer while super-seeding.
+///
+/// Each peer is offered a single piece at a time. The next piece is only revealed to that peer
+/// once the previous one was announced by a different peer, meaning it was shared with the swarm.
+///
+/// The pieces revealed the least amount of times are revealed first.
+#[derive(Debug, Default)]
+pub struct SuperSeedStatus {
+    revealed_pieces: HashMap<BtPeer, RevealedPiece>,
+    reveal_count: HashMap<u32, usize>,
+}
+
+/// The piece revealed to a peer and whether another peer already announced it.
+#[derive(Debug, Clone)]
+struct RevealedPiece {
+    index: u32,
+    seen: bool,
+}
+
+impl SuperSeedStatus {
+    /// Creates a new empty `SuperSeedStatus`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the next piece that should be revealed to a peer.
+    ///
+    /// Returns `None` if the last piece revealed to the peer was not seen in another peer yet, or if the peer already has all the pieces.
+    pub fn next_piece(
+        &mut self,
+        peer: &BtPeer,
+        peer_bitfield: &Bitfield,
+        total_pieces: u32,
+    ) -> Option<u32> {
+        if let Some(revealed) = self.revealed_pieces.get(peer) {
+            if !revealed.seen {
+                return None;
+            }
+        }
+
+        let index = (0..total_pieces)
+            .filter(|index| !Self::has_piece(peer_bitfield, *index))
+            .min_by_key(|index| self.reveal_count.get(index).copied().unwrap_or(0))?;
+
+        *self.reveal_count.entry(index).or_insert(0) += 1;
+        self.revealed_pieces
+            .insert(peer.clone(), RevealedPiece { index, seen: false });
+        Some(index)
+    }
+
+    /// Registers that a peer announced a piece, unlocking the next piece for every other peer that piece was revealed to.
+    pub fn piece_announced(&mut self, peer: &BtPeer, index: u32) {
+        for (other_peer, revealed) in self.revealed_pieces.iter_mut() {
+            if other_peer != peer && revealed.index == index {
+                revealed.seen = true;
+            }
+        }
+    }
+
+    /// Removes a peer from the super-seeding status.
+    pub fn peer_disconnected(&mut self, peer: &BtPeer) {
+        self.revealed_pieces.remove(peer);
+    }
+
+    fn has_piece(bitfield: &Bitfield, index: u32) -> bool {
+        (index / 8) < bitfield.bitfield.len() as u32 && bitfield.has_piece(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reveals_one_piece_at_a_time() {
+        let mut status = SuperSeedStatus::new();
+        let peer = BtPeer::new("127.0.0.1".to_string(), 6881);
+        let bitfield = Bitfield::new(vec![0b0000_0000]);
+
+        assert!(status.next_piece(&peer, &bitfield, 8).is_some());
+        assert!(status.next_piece(&peer, &bitfield, 8).is_none());
+    }
+
+    #[test]
+    fn test_next_piece_unlocked_when_announced_by_another_peer() {
+        let mut status = SuperSeedStatus::new();
+        let peer = BtPeer::new("127.0.0.1".to_string(), 6881);
+        let other_peer = BtPeer::new("127.0.0.2".to_string(), 6881);
+        let bitfield = Bitfield::new(vec![0b0000_0000]);
+
+        let index = status.next_piece(&peer, &bitfield, 8).unwrap();
+
+        status.piece_announced(&peer, index);
+        assert!(status.next_piece(&peer, &bitfield, 8).is_none());
+
+        status.piece_announced(&other_peer, index);
+        assert!(status.next_piece(&peer, &bitfield, 8).is_some());
+    }
+
+    #[test]
+    fn test_least_revealed_pieces_first() {
+        let mut status = SuperSeedStatus::new();
+        let peer = BtPeer::new("127.0.0.1".to_string(), 6881);
+        let other_peer = BtPeer::new("127.0.0.2".to_string(), 6881);
+        let bitfield = Bitfield::new(vec![0b0000_0000]);
+
+        let first = status.next_piece(&peer, &bitfield, 2).unwrap();
+        let second = status.next_piece(&other_peer, &bitfield, 2).unwrap();
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_pieces_the_peer_has_are_not_revealed() {
+        let mut status = SuperSeedStatus::new();
+        let peer = BtPeer::new("127.0.0.1".to_string(), 6881);
+        let bitfield = Bitfield::new(vec![0b1110_0000]);
+
+        assert_eq!(status.next_piece(&peer, &bitfield, 4), Some(3));
+    }
+}
@@ -0,0 +1,87 @@
+/// Snapshot of the transfer statistics of a torrent.
+///
+/// To get the current statistics of a torrent use `AtomicTorrentStatus::stats()`.
+///
+/// - `uploaded_bytes`: bytes of pieces sent to other peers,
+/// - `downloaded_bytes`: bytes of pieces received from other peers, including the wasted ones,
+/// - `overhead_uploaded_bytes`: bytes sent that are not piece data: handshakes, the other messages, the headers of
+///   the pieces and the announces to the trackers,
+/// - `overhead_downloaded_bytes`: bytes received that are not piece data, like `overhead_uploaded_bytes`,
+/// - `wasted_bytes`: bytes received that could not be used, because they failed the hash check or were duplicated,
+/// - `left_bytes`: bytes still needed to finish the download,
+/// - `downloaded_pieces`: number of pieces already downloaded and verified,
+/// - `total_pieces`: number of pieces of the torrent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TorrentStats {
+    pub uploaded_bytes: u64,
+    pub downloaded_bytes: u64,
+    pub overhead_uploaded_bytes: u64,
+    pub overhead_downloaded_bytes: u64,
+    pub wasted_bytes: u64,
+    pub left_bytes: u64,
+    pub downloaded_pieces: usize,
+    pub total_pieces: usize,
+}
+
+impl TorrentStats {
+    /// Returns the share ratio of the torrent: the uploaded bytes divided by the downloaded bytes.
+    ///
+    /// If nothing was downloaded yet the ratio is 0.
+    pub fn share_ratio(&self) -> f64 {
+        if self.downloaded_bytes == 0 {
+            return 0.0;
+        }
+        self.uploaded_bytes as f64 / self.downloaded_bytes as f64
+    }
+
+    /// Returns all the bytes sent, the piece data and the overhead.
+    pub fn total_uploaded_bytes(&self) -> u64 {
+        self.uploaded_bytes + self.overhead_uploaded_bytes
+    }
+
+    /// Returns all the bytes received, the piece data and the overhead.
+    pub fn total_downloaded_bytes(&self) -> u64 {
+        self.downloaded_bytes + self.overhead_downloaded_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_share_ratio() {
+        let stats = TorrentStats {
+            uploaded_bytes: 300,
+            downloaded_bytes: 200,
+            ..Default::default()
+        };
+
+        assert_eq!(stats.share_ratio(), 1.5);
+    }
+
+    #[test]
+    fn test_share_ratio_without_downloaded_bytes() {
+        let stats = TorrentStats {
+            uploaded_bytes: 300,
+            ..Default::default()
+        };
+
+        assert_eq!(stats.share_ratio(), 0.0);
+    }
+
+    #[test]
+    fn test_overhead_is_left_out_of_the_share_ratio() {
+        let stats = TorrentStats {
+            uploaded_bytes: 300,
+            downloaded_bytes: 200,
+            overhead_uploaded_bytes: 100,
+            overhead_downloaded_bytes: 50,
+            ..Default::default()
+        };
+
+        assert_eq!(stats.share_ratio(), 1.5);
+        assert_eq!(stats.total_uploaded_bytes(), 400);
+        assert_eq!(stats.total_downloaded_bytes(), 250);
+    }
+}
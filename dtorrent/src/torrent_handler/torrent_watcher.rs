@@ -0,0 +1,318 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use tracing::{error, info, warn};
+use url_encoder::info_hash::InfoHash;
+
+use super::{connection_registry::PeerConnectionRegistry, handler::TorrentHandler};
+use crate::{
+    bt_server::server::SharedTorrents,
+    config::cfg::Cfg,
+    nat::SharedPortMapping,
+    torrent_parser::{parser::TorrentParser, torrent::Torrent},
+};
+
+/// Subdirectory of the watch directory the loaded `.torrent` files are moved to.
+pub const LOADED_DIRECTORY: &str = "loaded";
+
+/// Checks the `watch_directory` of the config for new `.torrent` files and starts a `TorrentHandler` for each one.
+///
+/// Loaded files are moved to the `loaded` subdirectory if the `move_loaded_torrents` setting is enabled, otherwise
+/// they are remembered and left in place. Files that can not be parsed are retried when they are modified, so a file
+/// still being copied is loaded once it is complete. A torrent already started is not started again.
+///
+/// The started torrents are added to the torrents served by the `BtServer`, so the peers connecting to them are
+/// accepted.
+///
+/// To create a new `TorrentWatcher` use `TorrentWatcher::new`.
+#[derive(Debug)]
+pub struct TorrentWatcher {
+    directory: PathBuf,
+    config: Cfg,
+    client_peer_id: String,
+    torrents: SharedTorrents,
+    client_port: u16,
+    port_mapping: SharedPortMapping,
+    connection_registry: Arc<PeerConnectionRegistry>,
+    checked: HashMap<PathBuf, Option<SystemTime>>,
+    started: HashSet<InfoHash>,
+}
+
+impl TorrentWatcher {
+    /// Creates a new `TorrentWatcher` of the `watch_directory` of `config`, that adds the started torrents to
+    /// `torrents`.
+    pub fn new(config: Cfg, client_peer_id: String, torrents: SharedTorrents) -> Self {
+        Self {
+            directory: PathBuf::from(&config.watch_directory),
+            client_port: config.tcp_port,
+            port_mapping: Arc::default(),
+            connection_registry: Arc::new(PeerConnectionRegistry::new(
+                config.max_connections_per_ip,
+            )),
+            config,
+            client_peer_id,
+            torrents,
+            checked: HashMap::new(),
+            started: HashSet::new(),
+        }
+    }
+
+    /// Sets the port announced to the tracker by the started torrents, it must be the port the `BtServer` is bound to.
+    pub fn set_client_port(&mut self, client_port: u16) {
+        self.client_port = client_port;
+    }
+
+    /// Sets the port mapping announced by the started torrents, see `TorrentHandler::set_port_mapping`.
+    pub fn set_port_mapping(&mut self, port_mapping: SharedPortMapping) {
+        self.port_mapping = port_mapping;
+    }
+
+    /// Shares the registry of outgoing peer connections with the started torrents.
+    pub fn set_connection_registry(&mut self, connection_registry: Arc<PeerConnectionRegistry>) {
+        self.connection_registry = connection_registry;
+    }
+
+    /// Spawns a thread that checks the watch directory every `interval` and starts the new torrents.
+    pub fn spawn(mut self, interval: Duration) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            match self.check() {
+                Ok(torrents) => {
+                    for torrent in torrents {
+                        self.start(torrent);
+                    }
+                }
+                Err(err) => warn!("Could not check the watch directory: {:?}", err),
+            }
+            thread::sleep(interval);
+        })
+    }
+
+    /// Loads the `.torrent` files added to the watch directory since the last check.
+    ///
+    /// Returns the torrents that were not started yet, which must be started with `start`.
+    ///
+    /// # Errors
+    ///
+    /// - `io::Error` if the watch directory could not be read, or a loaded file could not be moved to the `loaded`
+    ///   subdirectory.
+    pub fn check(&mut self) -> io::Result<Vec<Torrent>> {
+        let mut torrents = Vec::new();
+
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            if !is_torrent_file(&path) {
+                continue;
+            }
+            let modified = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            if self.checked.get(&path) == Some(&modified) {
+                continue;
+            }
+            self.checked.insert(path.clone(), modified);
+
+            let torrent = match TorrentParser::parse(&path) {
+                Ok(torrent) => torrent,
+                Err(err) => {
+                    warn!("Could not load {}: {:?}", path.display(), err);
+                    continue;
+                }
+            };
+            if self.config.move_loaded_torrents {
+                self.move_to_loaded(&path)?;
+            }
+            if self.started.insert(torrent.info_hash()) {
+                info!("Loaded {} from the watch directory", torrent.name());
+                torrents.push(torrent);
+            }
+        }
+        Ok(torrents)
+    }
+
+    /// Starts the download of `torrent` in a new thread.
+    ///
+    /// Returns `None` if the torrent is already served, e.g. because it was added through the control socket.
+    pub fn start(&self, torrent: Torrent) -> Option<JoinHandle<()>> {
+        let info_hash = torrent.info_hash();
+        let mut torrent_handler =
+            TorrentHandler::new(torrent, self.config.clone(), self.client_peer_id.clone());
+        torrent_handler.set_client_port(self.client_port);
+        torrent_handler.set_port_mapping(self.port_mapping.clone());
+        torrent_handler.set_connection_registry(self.connection_registry.clone());
+        torrent_handler.set_torrents(self.torrents.clone());
+
+        match self.torrents.write() {
+            Ok(mut torrents) => {
+                if torrents.contains_key(&info_hash) {
+                    warn!("Torrent {} is already being served", info_hash);
+                    return None;
+                }
+                torrents.insert(info_hash, torrent_handler.status());
+            }
+            Err(_) => {
+                error!(
+                    "Could not start torrent {}: poisoned torrents lock",
+                    info_hash
+                );
+                return None;
+            }
+        }
+
+        Some(thread::spawn(move || {
+            if let Err(err) = torrent_handler.handle() {
+                error!("Torrent loaded from the watch directory failed: {:?}", err);
+            }
+        }))
+    }
+
+    fn move_to_loaded(&mut self, path: &Path) -> io::Result<()> {
+        let loaded_directory = self.directory.join(LOADED_DIRECTORY);
+        fs::create_dir_all(&loaded_directory)?;
+        if let Some(file_name) = path.file_name() {
+            fs::rename(path, loaded_directory.join(file_name))?;
+            self.checked.remove(path);
+        }
+        Ok(())
+    }
+}
+
+fn is_torrent_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("torrent"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bt_server::server::BtServer,
+        peer::{bt_peer::BtPeer, peer_message::Bitfield, peer_session::PeerSession},
+        storage_manager::memory_storage::MemoryStorage,
+        test_support::{
+            fixtures::test_config,
+            local_swarm::{add_pieces, generated_torrent, LEECHER_PEER_ID, SEEDER_PEER_ID},
+            temp_dir::TempDir,
+        },
+        torrent_handler::status::AtomicTorrentStatus,
+    };
+
+    const TORRENT_FILE: &str = "../torrents/file1.torrent";
+
+    fn watcher(dir: &TempDir, move_loaded_torrents: bool) -> TorrentWatcher {
+        let watch_directory = dir.join("watch");
+        fs::create_dir_all(&watch_directory).unwrap();
+        let config = Cfg {
+            watch_directory: watch_directory.display().to_string(),
+            move_loaded_torrents,
+            ..test_config(dir)
+        };
+        TorrentWatcher::new(config, "client_peer_id".to_string(), Arc::default())
+    }
+
+    #[test]
+    fn test_new_torrent_file_is_loaded_and_moved() {
+        let dir = TempDir::new("torrent_watcher_moved");
+        let mut watcher = watcher(&dir, true);
+        fs::copy(TORRENT_FILE, dir.join("watch/file1.torrent")).unwrap();
+
+        let torrents = watcher.check().unwrap();
+
+        assert_eq!(torrents.len(), 1);
+        assert_eq!(
+            torrents[0].info_hash(),
+            TorrentParser::parse(Path::new(TORRENT_FILE))
+                .unwrap()
+                .info_hash()
+        );
+        assert!(!dir.join("watch/file1.torrent").exists());
+        assert!(dir.join("watch/loaded/file1.torrent").exists());
+        assert!(watcher.check().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_loaded_torrent_file_is_left_in_place() {
+        let dir = TempDir::new("torrent_watcher_left");
+        let mut watcher = watcher(&dir, false);
+        fs::copy(TORRENT_FILE, dir.join("watch/file1.torrent")).unwrap();
+
+        assert_eq!(watcher.check().unwrap().len(), 1);
+        assert!(dir.join("watch/file1.torrent").exists());
+        assert!(watcher.check().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_same_torrent_is_not_started_twice() {
+        let dir = TempDir::new("torrent_watcher_duplicated");
+        let mut watcher = watcher(&dir, true);
+        fs::copy(TORRENT_FILE, dir.join("watch/file1.torrent")).unwrap();
+        fs::copy(TORRENT_FILE, dir.join("watch/copy.torrent")).unwrap();
+
+        assert_eq!(watcher.check().unwrap().len(), 1);
+        assert!(dir.join("watch/loaded/copy.torrent").exists());
+        assert!(dir.join("watch/loaded/file1.torrent").exists());
+    }
+
+    #[test]
+    fn test_other_and_invalid_files_are_not_loaded() {
+        let dir = TempDir::new("torrent_watcher_invalid");
+        let mut watcher = watcher(&dir, true);
+        fs::write(dir.join("watch/notes.txt"), b"not a torrent").unwrap();
+        fs::write(dir.join("watch/broken.torrent"), b"d8:announce").unwrap();
+
+        assert!(watcher.check().unwrap().is_empty());
+        assert!(dir.join("watch/notes.txt").exists());
+        assert!(dir.join("watch/broken.torrent").exists());
+    }
+
+    #[test]
+    fn test_peers_of_a_started_torrent_are_accepted() {
+        let dir = TempDir::new("torrent_watcher_accepts_peers");
+        let config = Cfg {
+            tcp_port: 0,
+            tcp_port_max: 0,
+            ..test_config(&dir)
+        };
+        let (torrent, data) =
+            generated_torrent("watched", 4 * 16384, 16384, "http://127.0.0.1:1/announce");
+        let mut server = BtServer::new(HashMap::new(), config.clone(), LEECHER_PEER_ID.to_string());
+        let port = server.bind().unwrap();
+        let watcher = TorrentWatcher::new(
+            config.clone(),
+            LEECHER_PEER_ID.to_string(),
+            server.torrents(),
+        );
+        let torrents = server.torrents();
+        thread::spawn(move || server.init());
+
+        assert!(watcher.start(torrent.clone()).is_some());
+        assert!(watcher.start(torrent.clone()).is_none());
+
+        let peer_status = AtomicTorrentStatus::with_storage(
+            &torrent,
+            config.clone(),
+            Box::new(MemoryStorage::new()),
+        );
+        add_pieces(&peer_status, &torrent, &data, &Bitfield::new(vec![0xff]));
+        let peer_status = Arc::new(peer_status);
+        peer_status.peer_connecting();
+        let mut peer_session = PeerSession::new(
+            BtPeer::new("127.0.0.1".to_string(), port as i64),
+            torrent.clone(),
+            peer_status,
+            config,
+            SEEDER_PEER_ID.to_string(),
+        )
+        .unwrap();
+
+        assert!(peer_session.start_outgoing().is_ok());
+        assert!(torrents.read().unwrap()[&torrent.info_hash()].is_finished());
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+
+use bencoder::bencode::Bencode;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::sha256::SHA256_LENGTH;
+
+/// A file of the `file tree` of a v2 torrent.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileTreeEntry {
+    /// Names of the directories and of the file, from the root of the torrent.
+    pub path: Vec<String>,
+    pub length: u64,
+    /// Root of the merkle tree of the 16 KiB blocks of the file, `None` for empty files.
+    pub pieces_root: Option<[u8; SHA256_LENGTH]>,
+}
+
+/// The `file tree` dictionary of a v2 torrent (BEP 52), flattened into its files sorted by path.
+///
+/// Directories are dictionaries keyed by the names of their entries, and a file is a dictionary with its
+/// `length` and `pieces root` under the empty key. It is encoded back to the same dictionary, so the info hash
+/// of a torrent built from its decoded bencode does not change.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileTree {
+    files: Vec<FileTreeEntry>,
+}
+
+/// Posible `FileTree` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FileTreeError {
+    NotADict,
+    /// A file or directory name is empty, not UTF-8 or a relative path component.
+    InvalidName,
+    /// A file has no valid `length`, or no 32 bytes `pieces root` while not empty.
+    InvalidFile,
+}
+
+impl FileTree {
+    /// Builds a `FileTree` with `files`.
+    pub fn new(mut files: Vec<FileTreeEntry>) -> Self {
+        files.sort();
+        FileTree { files }
+    }
+
+    /// Builds the `FileTree` from the bencoded `file tree` dictionary.
+    ///
+    /// # Errors
+    ///
+    /// - `NotADict` if the bencode, or a directory in it, is not a dictionary.
+    /// - `InvalidName` if a name is empty, not UTF-8, `.` or `..`.
+    /// - `InvalidFile` if a file has no valid `length`, or no valid `pieces root` while not empty.
+    pub fn from(bencode: &Bencode) -> Result<FileTree, FileTreeError> {
+        let mut files = vec![];
+        Self::parse_directory(bencode, &mut vec![], &mut files)?;
+        Ok(FileTree::new(files))
+    }
+
+    fn parse_directory(
+        bencode: &Bencode,
+        path: &mut Vec<String>,
+        files: &mut Vec<FileTreeEntry>,
+    ) -> Result<(), FileTreeError> {
+        let entries = bencode.as_dict().ok_or(FileTreeError::NotADict)?;
+        for (name, entry) in entries {
+            if name.is_empty() && !path.is_empty() {
+                files.push(Self::parse_file(entry, path.clone())?);
+                continue;
+            }
+            let name = String::from_utf8(name.clone()).map_err(|_| FileTreeError::InvalidName)?;
+            if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+                return Err(FileTreeError::InvalidName);
+            }
+            path.push(name);
+            Self::parse_directory(entry, path, files)?;
+            path.pop();
+        }
+        Ok(())
+    }
+
+    fn parse_file(bencode: &Bencode, path: Vec<String>) -> Result<FileTreeEntry, FileTreeError> {
+        let length = bencode
+            .get_int("length")
+            .and_then(|length| u64::try_from(length).ok())
+            .ok_or(FileTreeError::InvalidFile)?;
+        let pieces_root = match bencode.get_bytes("pieces root") {
+            Some(root) => Some(root.try_into().map_err(|_| FileTreeError::InvalidFile)?),
+            None if length == 0 => None,
+            None => return Err(FileTreeError::InvalidFile),
+        };
+
+        Ok(FileTreeEntry {
+            path,
+            length,
+            pieces_root,
+        })
+    }
+
+    /// Returns the files of the tree sorted by path.
+    pub fn files(&self) -> &[FileTreeEntry] {
+        &self.files
+    }
+
+    /// Returns true if the tree has no files, as in v1 torrents.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Returns the sum of the lengths of the files.
+    pub fn total_length(&self) -> u64 {
+        self.files.iter().map(|file| file.length).sum()
+    }
+
+    /// Returns the tree as its bencoded dictionary.
+    pub fn to_bencode(&self) -> Bencode {
+        let mut root = BTreeMap::new();
+        for file in &self.files {
+            let mut directory = &mut root;
+            for name in &file.path {
+                let entry = directory
+                    .entry(name.as_bytes().to_vec())
+                    .or_insert_with(|| Bencode::BDict(BTreeMap::new()));
+                directory = match entry {
+                    Bencode::BDict(entries) => entries,
+                    // Every entry inserted in the tree is a dictionary.
+                    _ => unreachable!("the entries of the tree are dictionaries"),
+                };
+            }
+
+            let mut attributes = BTreeMap::new();
+            attributes.insert(b"length".to_vec(), Bencode::BNumber(file.length as i64));
+            if let Some(root) = file.pieces_root {
+                attributes.insert(b"pieces root".to_vec(), Bencode::BString(root.to_vec()));
+            }
+            directory.insert(vec![], Bencode::BDict(attributes));
+        }
+        Bencode::BDict(root)
+    }
+}
+
+impl Serialize for FileTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_bencode().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FileTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bencode = Bencode::deserialize(deserializer)?;
+        FileTree::from(&bencode)
+            .map_err(|err| de::Error::custom(format!("invalid file tree: {:?}", err)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TREE: &[u8] = b"d1:ad0:d6:lengthi3e11:pieces root32:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaee\
+3:dird1:bd0:d6:lengthi0eee1:cd0:d6:lengthi5e11:pieces root32:cccccccccccccccccccccccccccccccceeee";
+
+    #[test]
+    fn test_parse_file_tree() {
+        let tree = FileTree::from(&Bencode::decode(TREE).unwrap()).unwrap();
+
+        assert_eq!(
+            tree.files(),
+            [
+                FileTreeEntry {
+                    path: vec!["a".to_string()],
+                    length: 3,
+                    pieces_root: Some([b'a'; 32]),
+                },
+                FileTreeEntry {
+                    path: vec!["dir".to_string(), "b".to_string()],
+                    length: 0,
+                    pieces_root: None,
+                },
+                FileTreeEntry {
+                    path: vec!["dir".to_string(), "c".to_string()],
+                    length: 5,
+                    pieces_root: Some([b'c'; 32]),
+                },
+            ]
+        );
+        assert_eq!(tree.total_length(), 8);
+        assert_eq!(Bencode::encode(&tree.to_bencode()), TREE);
+    }
+
+    #[test]
+    fn test_invalid_file_tree() {
+        let tree = |data: &[u8]| FileTree::from(&Bencode::decode(data).unwrap());
+
+        // Not empty and without pieces root.
+        assert_eq!(
+            tree(b"d1:ad0:d6:lengthi3eeee"),
+            Err(FileTreeError::InvalidFile)
+        );
+        assert_eq!(
+            tree(b"d2:..d0:d6:lengthi0eeee"),
+            Err(FileTreeError::InvalidName)
+        );
+        assert_eq!(tree(b"d1:ai3ee"), Err(FileTreeError::NotADict));
+    }
+}
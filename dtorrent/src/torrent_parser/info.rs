@@ -1,111 +1,90 @@
-use std::collections::BTreeMap;
+use bencoder::{bencode::Bencode, SerdeError};
+use serde::{Deserialize, Serialize};
 
-use bencoder::bencode::{Bencode, ToBencode};
+use super::file_tree::FileTree;
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Value of the `meta version` key of v2 torrents (BEP 52).
+pub const META_VERSION_2: i64 = 2;
+
+/// The `info` dictionary of a single file torrent.
+///
+/// Missing keys are left with their default values. Hybrid torrents, that have both the v1 keys and the v2 ones
+/// (BEP 52), are downloaded as v1 torrents.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Info {
+    /// Files of a v2 torrent with the merkle roots of their blocks, empty for v1 torrents.
+    #[serde(rename = "file tree", skip_serializing_if = "FileTree::is_empty")]
+    pub file_tree: FileTree,
     pub length: i64,
+    /// `2` if the torrent is a v2 or hybrid torrent.
+    #[serde(rename = "meta version")]
+    pub meta_version: Option<i64>,
     pub name: String,
+    #[serde(rename = "piece length")]
     pub piece_length: i64,
+    #[serde(with = "bencoder::bytes")]
     pub pieces: Vec<u8>,
+    /// `1` if the torrent is private, so its peers must only come from its trackers.
+    pub private: Option<i64>,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum FromInfoError {
-    MissingLength,
-    MissingName,
-    MissingPieceLength,
-    MissingPieces,
+    InvalidInfo(SerdeError),
     NotADict,
     MultipleFilesNotSupported,
+    /// The torrent only has the v2 keys, so it can not be downloaded as a v1 torrent.
+    V2OnlyNotSupported,
+    /// The v1 and v2 keys of a hybrid torrent describe different files.
+    HybridMismatch,
 }
 
 impl Info {
+    /// Builds the `Info` from the bencoded `info` dictionary of a torrent.
+    ///
+    /// # Errors
+    ///
+    /// - `NotADict` if the bencode is not a dictionary.
+    /// - `MultipleFilesNotSupported` if the torrent has a `files` key.
+    /// - `InvalidInfo` if a key has a value of the wrong type, or the `file tree` is not valid.
+    /// - `V2OnlyNotSupported` if it is a v2 torrent without `pieces`.
+    /// - `HybridMismatch` if it is a hybrid torrent whose `file tree` is not the single file named and sized as
+    ///   the v1 keys say.
     pub fn from(bencode: &Bencode) -> Result<Info, FromInfoError> {
-        let mut name = String::new();
-        let mut length = 0;
-        let mut piece_length = 0;
-        let mut pieces = Vec::new();
-
-        let d = match bencode {
-            Bencode::BDict(s) => s,
-            _ => return Err(FromInfoError::NotADict),
-        };
-
-        for (k, v) in d.iter() {
-            if k == b"name" {
-                name = Info::create_name(v)?;
-            } else if k == b"length" {
-                length = Info::create_length(v)?;
-            } else if k == b"piece length" {
-                piece_length = Info::create_piece_length(v)?;
-            } else if k == b"pieces" {
-                pieces = Info::create_pieces(v)?;
-            } else if k == b"files" {
-                return Err(FromInfoError::MultipleFilesNotSupported);
-            }
+        if bencode.as_dict().is_none() {
+            return Err(FromInfoError::NotADict);
         }
 
-        Ok(Info {
-            length,
-            name,
-            piece_length,
-            pieces,
-        })
-    }
-
-    fn create_name(bencode: &Bencode) -> Result<String, FromInfoError> {
-        let c = match bencode {
-            &Bencode::BString(ref s) => s,
-            _ => return Err(FromInfoError::MissingName),
-        };
-
-        let name = match String::from_utf8(c.to_vec()) {
-            Ok(s) => s,
-            Err(_) => return Err(FromInfoError::MissingName),
-        };
-
-        Ok(name)
-    }
-
-    fn create_length(bencode: &Bencode) -> Result<i64, FromInfoError> {
-        let c = match bencode {
-            &Bencode::BNumber(ref s) => s,
-            _ => return Err(FromInfoError::MissingLength),
-        };
-        Ok(*c)
-    }
-
-    fn create_piece_length(bencode: &Bencode) -> Result<i64, FromInfoError> {
-        let c = match bencode {
-            &Bencode::BNumber(ref s) => s,
-            _ => return Err(FromInfoError::MissingPieceLength),
-        };
-        Ok(*c)
-    }
+        if bencode.get("files").is_some() {
+            return Err(FromInfoError::MultipleFilesNotSupported);
+        }
 
-    fn create_pieces(bencode: &Bencode) -> Result<Vec<u8>, FromInfoError> {
-        let c = match bencode {
-            &Bencode::BString(ref s) => s,
-            _ => return Err(FromInfoError::MissingPieces),
-        };
-        Ok(c.to_vec())
+        let info: Info =
+            bencoder::from_bencode(bencode.clone()).map_err(FromInfoError::InvalidInfo)?;
+        if info.is_v2() {
+            if info.pieces.is_empty() {
+                return Err(FromInfoError::V2OnlyNotSupported);
+            }
+            match info.file_tree.files() {
+                [file] if file.path == [info.name.clone()] && file.length == info.length as u64 => {
+                }
+                _ => return Err(FromInfoError::HybridMismatch),
+            }
+        }
+        Ok(info)
     }
-}
 
-impl ToBencode for Info {
-    fn to_bencode(&self) -> Bencode {
-        let mut info = BTreeMap::new();
-        info.insert(b"length".to_vec(), self.length.to_bencode());
-        info.insert(b"name".to_vec(), self.name.to_bencode());
-        info.insert(b"piece length".to_vec(), self.piece_length.to_bencode());
-        info.insert(b"pieces".to_vec(), self.pieces.to_bencode());
-        Bencode::BDict(info)
+    /// Returns true if it is the info of a v2 or hybrid torrent.
+    pub fn is_v2(&self) -> bool {
+        self.meta_version == Some(META_VERSION_2)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
     use super::*;
 
     #[test]
@@ -134,6 +113,51 @@ mod tests {
         assert_eq!(response.pieces, b"test2");
     }
 
+    #[test]
+    fn test_from_info_with_invalid_length() {
+        let mut info = BTreeMap::new();
+        info.insert(b"length".to_vec(), Bencode::BString(b"ten".to_vec()));
+        let bencode = Bencode::BDict(info);
+
+        let response = Info::from(&bencode).unwrap_err();
+        assert!(matches!(response, FromInfoError::InvalidInfo(_)));
+    }
+
+    #[test]
+    fn test_encode_info() {
+        let info = Info {
+            file_tree: FileTree::default(),
+            length: 1,
+            meta_version: None,
+            name: String::from("a"),
+            piece_length: 2,
+            pieces: vec![0xff],
+            private: None,
+        };
+
+        let encoded = bencoder::to_bytes(&info).unwrap();
+        assert_eq!(
+            encoded,
+            b"d6:lengthi1e4:name1:a12:piece lengthi2e6:pieces1:\xffe"
+        );
+        assert_eq!(
+            Info::from(&Bencode::decode(&encoded).unwrap()).unwrap(),
+            info
+        );
+    }
+
+    #[test]
+    fn test_private_flag() {
+        let bencode = Bencode::decode(b"d4:name1:a7:privatei1ee").unwrap();
+        let info = Info::from(&bencode).unwrap();
+
+        assert_eq!(info.private, Some(1));
+        assert_eq!(
+            bencoder::to_bytes(&info).unwrap(),
+            b"d6:lengthi0e4:name1:a12:piece lengthi0e6:pieces0:7:privatei1ee"
+        );
+    }
+
     #[test]
     fn test_from_info_with_multiple_files() {
         let mut info = BTreeMap::new();
@@ -146,4 +170,50 @@ mod tests {
         let response = Info::from(&bencode).unwrap_err();
         assert_eq!(response, FromInfoError::MultipleFilesNotSupported);
     }
+
+    #[test]
+    fn test_from_hybrid_info() {
+        let bencode = Bencode::decode(
+            b"d9:file treed1:ad0:d6:lengthi3e11:pieces root32:rrrrrrrrrrrrrrrrrrrrrrrrrrrrrrrreee\
+6:lengthi3e12:meta versioni2e4:name1:a12:piece lengthi16384e6:pieces20:hhhhhhhhhhhhhhhhhhhhe",
+        )
+        .unwrap();
+
+        let info = Info::from(&bencode).unwrap();
+
+        assert!(info.is_v2());
+        assert_eq!(info.file_tree.files()[0].pieces_root, Some([b'r'; 32]));
+        assert_eq!(
+            Bencode::decode(&bencoder::to_bytes(&info).unwrap()).unwrap(),
+            bencode
+        );
+    }
+
+    #[test]
+    fn test_from_v2_only_info() {
+        let bencode = Bencode::decode(
+            b"d9:file treed1:ad0:d6:lengthi3e11:pieces root32:rrrrrrrrrrrrrrrrrrrrrrrrrrrrrrrreee\
+12:meta versioni2e4:name1:a12:piece lengthi16384ee",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Info::from(&bencode).unwrap_err(),
+            FromInfoError::V2OnlyNotSupported
+        );
+    }
+
+    #[test]
+    fn test_from_hybrid_info_with_another_file() {
+        let bencode = Bencode::decode(
+            b"d9:file treed1:bd0:d6:lengthi3e11:pieces root32:rrrrrrrrrrrrrrrrrrrrrrrrrrrrrrrreee\
+6:lengthi3e12:meta versioni2e4:name1:a12:piece lengthi16384e6:pieces20:hhhhhhhhhhhhhhhhhhhhe",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Info::from(&bencode).unwrap_err(),
+            FromInfoError::HybridMismatch
+        );
+    }
 }
@@ -0,0 +1,159 @@
+//! Merkle trees of the files of v2 torrents (BEP 52).
+//!
+//! The leaves of the tree of a file are the SHA-256 hashes of its blocks of 16 KiB, layer 0, and every node above
+//! is the hash of its two children. The layer where each node covers a piece is the piece layer. The layers are
+//! padded up to a power of two with the hashes of a subtree of zero leaves.
+
+use super::sha256::{sha256, SHA256_LENGTH};
+
+/// Size in bytes of the blocks hashed into the leaves of the tree.
+pub const MERKLE_BLOCK_SIZE: u32 = 16384;
+
+/// Hash of a node of the tree.
+pub type MerkleHash = [u8; SHA256_LENGTH];
+
+/// Returns the hashes of the blocks of `data`, the last one may be shorter.
+pub fn block_hashes(data: &[u8]) -> Vec<MerkleHash> {
+    data.chunks(MERKLE_BLOCK_SIZE as usize)
+        .map(sha256)
+        .collect()
+}
+
+/// Returns the number of leaves under each node of the piece layer, or `None` if `piece_length` is not a power of
+/// two of at least `MERKLE_BLOCK_SIZE`, as v2 torrents require.
+pub fn blocks_per_piece(piece_length: u64) -> Option<u32> {
+    if piece_length < MERKLE_BLOCK_SIZE as u64 || !piece_length.is_power_of_two() {
+        return None;
+    }
+    u32::try_from(piece_length / MERKLE_BLOCK_SIZE as u64).ok()
+}
+
+/// Returns the hash of the node whose children are `left` and `right`.
+pub fn hash_pair(left: &MerkleHash, right: &MerkleHash) -> MerkleHash {
+    let mut pair = [0; 2 * SHA256_LENGTH];
+    pair[..SHA256_LENGTH].copy_from_slice(left);
+    pair[SHA256_LENGTH..].copy_from_slice(right);
+    sha256(&pair)
+}
+
+/// Returns the hash that pads `layer`: zeros for the leaves, and the hash of two pads of the layer below above.
+pub fn pad_hash(layer: u32) -> MerkleHash {
+    (0..layer).fold([0; SHA256_LENGTH], |pad, _| hash_pair(&pad, &pad))
+}
+
+/// Returns the root of the subtree whose nodes at `layer` are `hashes`, padded up to `width`, or to the next power
+/// of two if there are more hashes.
+pub fn root(hashes: &[MerkleHash], width: usize, layer: u32) -> MerkleHash {
+    let mut nodes = padded(hashes, width, layer);
+    while nodes.len() > 1 {
+        nodes = parents(&nodes);
+    }
+    nodes[0]
+}
+
+/// Returns the uncle hashes of the node at `position` of a layer whose hashes are `hashes`, from the layer of the
+/// node up, at most `proof_layers` of them.
+///
+/// They are the hashes needed to check the node against the root, see `verify_proof`.
+pub fn proof(
+    hashes: &[MerkleHash],
+    layer: u32,
+    mut position: usize,
+    proof_layers: u32,
+) -> Vec<MerkleHash> {
+    let mut nodes = padded(hashes, 1, layer);
+    let mut uncles = vec![];
+    while nodes.len() > 1 && (uncles.len() as u32) < proof_layers {
+        uncles.push(nodes[position ^ 1]);
+        nodes = parents(&nodes);
+        position /= 2;
+    }
+    uncles
+}
+
+/// Returns true if the node at `position` of its layer hashes up to `expected` with the uncle hashes of `proof`.
+pub fn verify_proof(
+    node: MerkleHash,
+    mut position: usize,
+    proof: &[MerkleHash],
+    expected: &MerkleHash,
+) -> bool {
+    let mut node = node;
+    for uncle in proof {
+        node = if position.is_multiple_of(2) {
+            hash_pair(&node, uncle)
+        } else {
+            hash_pair(uncle, &node)
+        };
+        position /= 2;
+    }
+    &node == expected
+}
+
+/// Returns the hashes padded with the pad of `layer` up to `width`, or to the next power of two.
+fn padded(hashes: &[MerkleHash], width: usize, layer: u32) -> Vec<MerkleHash> {
+    let mut nodes = hashes.to_vec();
+    nodes.resize(width.max(hashes.len()).next_power_of_two(), pad_hash(layer));
+    nodes
+}
+
+/// Returns the layer above `nodes`, that has an even number of them.
+fn parents(nodes: &[MerkleHash]) -> Vec<MerkleHash> {
+    nodes
+        .chunks_exact(2)
+        .map(|pair| hash_pair(&pair[0], &pair[1]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_of_a_file() {
+        let data = vec![7; 3 * MERKLE_BLOCK_SIZE as usize + 10];
+        let leaves = block_hashes(&data);
+        assert_eq!(leaves.len(), 4);
+
+        let left = hash_pair(&leaves[0], &leaves[1]);
+        let right = hash_pair(&leaves[2], &leaves[3]);
+        assert_eq!(root(&leaves, 1, 0), hash_pair(&left, &right));
+
+        // Three leaves are padded with a zero leaf, and the piece layer of two leaves per piece with its pad.
+        assert_eq!(
+            root(&leaves[..3], 1, 0),
+            hash_pair(&left, &hash_pair(&leaves[2], &[0; 32]))
+        );
+        assert_eq!(
+            root(&[left], 2, 1),
+            hash_pair(&left, &hash_pair(&[0; 32], &[0; 32]))
+        );
+    }
+
+    #[test]
+    fn test_proof() {
+        let leaves: Vec<MerkleHash> = (0..5).map(|leaf| sha256(&[leaf])).collect();
+        let expected = root(&leaves, 1, 0);
+
+        for position in 0..leaves.len() {
+            let uncles = proof(&leaves, 0, position, 8);
+            assert_eq!(uncles.len(), 3);
+            assert!(verify_proof(leaves[position], position, &uncles, &expected));
+            assert!(!verify_proof(
+                leaves[position],
+                position ^ 1,
+                &uncles,
+                &expected
+            ));
+        }
+        assert_eq!(proof(&leaves, 0, 2, 1), vec![leaves[3]]);
+    }
+
+    #[test]
+    fn test_blocks_per_piece() {
+        assert_eq!(blocks_per_piece(16384), Some(1));
+        assert_eq!(blocks_per_piece(262144), Some(16));
+        assert_eq!(blocks_per_piece(8192), None);
+        assert_eq!(blocks_per_piece(3 * 16384), None);
+    }
+}
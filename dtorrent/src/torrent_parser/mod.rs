@@ -1,3 +1,6 @@
+pub mod file_tree;
 pub mod info;
+pub mod merkle;
 pub mod parser;
+pub mod sha256;
 pub mod torrent;
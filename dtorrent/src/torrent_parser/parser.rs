@@ -5,12 +5,10 @@ use std::{
 };
 
 use super::torrent::{FromTorrentError, Torrent};
-use bencoder::bencode::{Bencode, BencodeError};
 
 #[derive(Debug)]
 pub enum ParseError {
     IoError(Error),
-    BencodeError(BencodeError),
     FromTorrentError(FromTorrentError),
 }
 
@@ -22,20 +20,14 @@ impl TorrentParser {
     /// # Errors
     ///
     /// * `ParseError::IoError` - An error occurred while reading the file
-    /// * `ParseError::BencodeError` - An error occurred while parsing the bencode
-    /// * `ParseError::FromTorrentError` - An error occurred while creating the Torrent struct
+    /// * `ParseError::FromTorrentError` - An error occurred while parsing the bencode or creating the Torrent struct
     pub fn parse(filepath: &Path) -> Result<Torrent, ParseError> {
         let buffer = match TorrentParser::read_file(filepath) {
             Ok(buffer) => buffer,
             Err(e) => return Err(ParseError::IoError(e)),
         };
 
-        let bencode = match Bencode::decode(&buffer) {
-            Ok(bencode) => bencode,
-            Err(e) => return Err(ParseError::BencodeError(e)),
-        };
-
-        let torrent = match Torrent::from(bencode) {
+        let torrent = match Torrent::from_bytes(&buffer) {
             Ok(torrent) => torrent,
             Err(e) => return Err(ParseError::FromTorrentError(e)),
         };
@@ -79,7 +71,7 @@ mod tests {
         assert_eq!(torrent.info.name, "ubuntu-22.04-desktop-amd64.iso");
         assert_eq!(torrent.info.piece_length, 262144);
         assert_eq!(
-            torrent.info_hash,
+            torrent.info_hash.to_hex(),
             "48442ddee1900ed8c8101bb8b2bd955060f1eabc"
         );
         remove_file(filepath);
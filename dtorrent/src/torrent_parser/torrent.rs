@@ -1,17 +1,33 @@
-use std::fmt::Write;
-use std::{collections::BTreeMap, num::ParseIntError};
-
+use serde::Deserialize;
 use sha1::{Digest, Sha1};
+use tracing::warn;
 
-use bencoder::bencode::{Bencode, ToBencode};
+use bencoder::{
+    bencode::{Bencode, BencodeError},
+    SerdeError,
+};
+use url_encoder::{info_hash::InfoHash, url_encoder::encode_bytes};
 
-use super::info::{FromInfoError, Info};
+use super::{
+    info::{FromInfoError, Info},
+    merkle::{self, MerkleHash},
+    sha256::{sha256, SHA256_LENGTH},
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Torrent {
     pub announce_url: String,
+    /// Tiers of trackers of the `announce-list` key (BEP 12), empty if the torrent only has `announce_url`.
+    pub announce_list: Vec<Vec<String>>,
+    /// Urls of the HTTP seeds of the `httpseeds` key (BEP 17), that serve the pieces of the torrent.
+    pub http_seeds: Vec<String>,
     pub info: Info,
-    pub info_hash: String,
+    pub info_hash: InfoHash,
+    /// SHA-256 hash of the `info` dictionary of a hybrid torrent (BEP 52), that identifies it to v2 peers.
+    pub info_hash_v2: Option<[u8; SHA256_LENGTH]>,
+    /// Hashes of the pieces in the merkle tree of the file of a hybrid torrent, from the `piece layers` key, to
+    /// check the hashes of its blocks. Empty if unknown.
+    pub piece_layer: Vec<MerkleHash>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -19,95 +35,225 @@ pub enum FromTorrentError {
     MissingAnnounce,
     MissingInfo,
     FromInfoError(FromInfoError),
+    InvalidTorrent(SerdeError),
+    DecodeError(BencodeError),
     InfoHashError,
     NotADict,
+    /// The piece layer of a hybrid torrent does not hash up to the root of its file, or the piece length is not a
+    /// power of two of at least 16 KiB.
+    InvalidPieceLayers,
+}
+
+/// The keys of a metainfo file used by the client.
+///
+/// The info dictionary is kept as bencode so `Info` can reject multiple file torrents.
+#[derive(Deserialize)]
+struct MetaInfo {
+    announce: Option<String>,
+    #[serde(rename = "announce-list")]
+    announce_list: Option<Bencode>,
+    httpseeds: Option<Bencode>,
+    info: Option<Bencode>,
+    #[serde(rename = "piece layers")]
+    piece_layers: Option<Bencode>,
 }
 
 impl Torrent {
-    pub fn from(bencode: Bencode) -> Result<Torrent, FromTorrentError> {
-        let mut announce_url = String::new();
-        let mut info: Option<Info> = None;
+    /// Builds a `Torrent` from the contents of a torrent file.
+    ///
+    /// The info hash is the hash of the `info` dictionary exactly as it appears in the file,
+    /// so keys unknown to `Info` and non-canonical orderings are taken into account.
+    ///
+    /// # Errors
+    ///
+    /// - `DecodeError` if the data is not valid bencode.
+    /// - Any error returned by `from`.
+    pub fn from_bytes(data: &[u8]) -> Result<Torrent, FromTorrentError> {
+        let bencode = Bencode::decode(data).map_err(FromTorrentError::DecodeError)?;
+        let mut torrent = Torrent::from(bencode)?;
+
+        let raw_info = Bencode::raw_value(data, b"info")
+            .map_err(FromTorrentError::DecodeError)?
+            .ok_or(FromTorrentError::MissingInfo)?;
+        if let Err(err) = Bencode::decode_strict(raw_info) {
+            // Other clients may compute a different info hash for the same torrent.
+            warn!("Torrent has a non-canonical info dictionary: {:?}", err);
+        }
+        torrent.info_hash = Torrent::hash_info_bytes(raw_info)?;
+        if torrent.info.is_v2() {
+            torrent.info_hash_v2 = Some(sha256(raw_info));
+        }
 
-        let d = match bencode {
-            Bencode::BDict(s) => s,
-            _ => return Err(FromTorrentError::NotADict),
-        };
+        Ok(torrent)
+    }
 
-        for (k, v) in d.iter() {
-            if k == b"announce" {
-                announce_url = Torrent::create_announce(v)?;
-            } else if k == b"info" {
-                info = Some(Torrent::create_info(v)?);
-            }
+    /// Builds a `Torrent` from its decoded bencode.
+    ///
+    /// The original bytes of the `info` dictionary are not available, so the info hash is computed by encoding `Info` again.
+    /// Use `from_bytes` for torrent files.
+    pub fn from(bencode: Bencode) -> Result<Torrent, FromTorrentError> {
+        if bencode.as_dict().is_none() {
+            return Err(FromTorrentError::NotADict);
         }
 
-        if announce_url.is_empty() {
-            return Err(FromTorrentError::MissingAnnounce);
-        }
+        let meta_info: MetaInfo =
+            bencoder::from_bencode(bencode).map_err(FromTorrentError::InvalidTorrent)?;
+
+        let announce_list = meta_info
+            .announce_list
+            .as_ref()
+            .map(Torrent::parse_announce_list)
+            .unwrap_or_default();
+
+        // With an announce list the announce key is optional.
+        let announce_url = match meta_info.announce {
+            Some(announce_url) if !announce_url.is_empty() => announce_url,
+            _ => match announce_list.first().and_then(|tier| tier.first()) {
+                Some(announce_url) => announce_url.clone(),
+                None => return Err(FromTorrentError::MissingAnnounce),
+            },
+        };
+
+        let http_seeds = meta_info
+            .httpseeds
+            .as_ref()
+            .map(Torrent::parse_urls)
+            .unwrap_or_default();
 
-        let info = match info {
-            Some(x) => x,
+        let info = match meta_info.info {
+            Some(info) => Info::from(&info).map_err(FromTorrentError::FromInfoError)?,
             None => return Err(FromTorrentError::MissingInfo),
         };
 
         let info_hash = Torrent::create_info_hash(&info)?;
+        let (info_hash_v2, piece_layer) = if info.is_v2() {
+            let bencoded_info =
+                bencoder::to_bytes(&info).map_err(|_| FromTorrentError::InfoHashError)?;
+            let piece_layer = Torrent::parse_piece_layer(&info, meta_info.piece_layers.as_ref())?;
+            (Some(sha256(&bencoded_info)), piece_layer)
+        } else {
+            (None, vec![])
+        };
 
         Ok(Torrent {
             announce_url,
+            announce_list,
+            http_seeds,
             info,
             info_hash,
+            info_hash_v2,
+            piece_layer,
         })
     }
 
-    fn create_announce(bencode: &Bencode) -> Result<String, FromTorrentError> {
-        let announce_url = match bencode {
-            Bencode::BString(s) => s,
-            _ => return Err(FromTorrentError::MissingAnnounce),
+    /// Returns the piece layer of the single file of a hybrid torrent, checked against its pieces root.
+    ///
+    /// A file of a single piece has no entry in `piece layers`, its piece hash is the pieces root. Without the
+    /// entry the layer is left empty, so the blocks are only checked with the v1 hash of their piece.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidPieceLayers` if the piece length is not valid for v2 torrents, or the layer is not the hashes of
+    ///   every piece of the file under its pieces root.
+    fn parse_piece_layer(
+        info: &Info,
+        piece_layers: Option<&Bencode>,
+    ) -> Result<Vec<MerkleHash>, FromTorrentError> {
+        let piece_length = info.piece_length as u64;
+        let blocks_per_piece =
+            merkle::blocks_per_piece(piece_length).ok_or(FromTorrentError::InvalidPieceLayers)?;
+        let pieces_root = match info
+            .file_tree
+            .files()
+            .first()
+            .and_then(|file| file.pieces_root)
+        {
+            Some(pieces_root) => pieces_root,
+            None => return Ok(vec![]),
         };
+        if info.length as u64 <= piece_length {
+            return Ok(vec![pieces_root]);
+        }
 
-        let announce_url = match String::from_utf8(announce_url.to_vec()) {
-            Ok(s) => s,
-            Err(_) => return Err(FromTorrentError::MissingAnnounce),
+        let layer = match piece_layers
+            .and_then(|piece_layers| piece_layers.as_dict())
+            .and_then(|piece_layers| piece_layers.get(pieces_root.as_slice()))
+        {
+            Some(layer) => layer
+                .as_bytes()
+                .ok_or(FromTorrentError::InvalidPieceLayers)?,
+            None => {
+                warn!("Hybrid torrent without the piece layer of its file");
+                return Ok(vec![]);
+            }
         };
-
-        Ok(announce_url)
+        let layer: Vec<MerkleHash> = layer
+            .chunks(SHA256_LENGTH)
+            .map(|hash| hash.try_into())
+            .collect::<Result<_, _>>()
+            .map_err(|_| FromTorrentError::InvalidPieceLayers)?;
+
+        let total_pieces = (info.length as u64).div_ceil(piece_length);
+        let piece_layer_index = blocks_per_piece.trailing_zeros();
+        if layer.len() as u64 != total_pieces
+            || merkle::root(&layer, 1, piece_layer_index) != pieces_root
+        {
+            return Err(FromTorrentError::InvalidPieceLayers);
+        }
+        Ok(layer)
     }
 
-    fn create_info(bencode: &Bencode) -> Result<Info, FromTorrentError> {
-        let info = match Info::from(bencode) {
-            Ok(x) => x,
-            Err(err) => return Err(FromTorrentError::FromInfoError(err)),
-        };
-
-        Ok(info)
+    /// Parses the tiers of the `announce-list` key, skipping the urls that are not strings and the empty tiers.
+    fn parse_announce_list(announce_list: &Bencode) -> Vec<Vec<String>> {
+        announce_list
+            .as_list()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|tier| {
+                let urls = Torrent::parse_urls(tier);
+                (!urls.is_empty()).then_some(urls)
+            })
+            .collect()
     }
 
-    pub fn create_info_hash(info: &Info) -> Result<String, FromTorrentError> {
-        let bencoded_info = Bencode::encode(info);
-        let hash = Sha1::digest(bencoded_info);
-
-        let mut hex_string = String::with_capacity(hash.len() * 2);
+    /// Parses a list of urls, skipping the ones that are not strings or are empty.
+    fn parse_urls(urls: &Bencode) -> Vec<String> {
+        urls.as_list()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|url| url.as_str())
+            .filter(|url| !url.is_empty())
+            .map(String::from)
+            .collect()
+    }
 
-        for b in hash {
-            match write!(&mut hex_string, "{:02x}", b) {
-                Ok(_) => (),
-                Err(_) => return Err(FromTorrentError::InfoHashError),
+    /// Returns the urls of the trackers of the torrent, in the order they should be tried.
+    ///
+    /// They are the trackers of the announce list tier by tier, without repetitions, or the announce url if the
+    /// torrent has no announce list.
+    pub fn trackers(&self) -> Vec<String> {
+        if self.announce_list.is_empty() {
+            return vec![self.announce_url.clone()];
+        }
+        let mut trackers: Vec<String> = vec![];
+        for url in self.announce_list.iter().flatten() {
+            if !trackers.contains(url) {
+                trackers.push(url.clone());
             }
         }
-
-        Ok(hex_string)
+        trackers
     }
 
-    /// Returns the info hash of the torrent as a byte array.
-    pub fn get_info_hash_as_bytes(&self) -> Result<Vec<u8>, ParseIntError> {
-        Self::decode_hex(self.info_hash.as_str())
+    pub fn create_info_hash(info: &Info) -> Result<InfoHash, FromTorrentError> {
+        let bencoded_info =
+            bencoder::to_bytes(info).map_err(|_| FromTorrentError::InfoHashError)?;
+        Torrent::hash_info_bytes(&bencoded_info)
     }
 
-    fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
-        (0..s.len())
-            .step_by(2)
-            .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
-            .collect()
+    fn hash_info_bytes(bencoded_info: &[u8]) -> Result<InfoHash, FromTorrentError> {
+        let hash = Sha1::digest(bencoded_info);
+
+        InfoHash::from_bytes(&hash).map_err(|_| FromTorrentError::InfoHashError)
     }
 
     /// Returns the name of the torrent.
@@ -121,37 +267,97 @@ impl Torrent {
     }
 
     /// Returns the length in bytes of the torrent.
-    pub fn length(&self) -> u32 {
-        self.info.length as u32
+    pub fn length(&self) -> u64 {
+        self.info.length as u64
     }
 
     /// Returns the number of pieces of the torrent.
     pub fn total_pieces(&self) -> u32 {
-        (self.info.length as f64 / self.info.piece_length as f64).ceil() as u32
+        self.length().div_ceil(self.info.piece_length as u64) as u32
     }
 
     /// Returns the size of the last piece of the torrent.
+    ///
+    /// If the length of the torrent is a multiple of the piece length, it returns 0.
     pub fn last_piece_size(&self) -> u32 {
-        self.info.length as u32 % self.info.piece_length as u32
+        (self.length() % self.info.piece_length as u64) as u32
     }
 
-    pub fn info_hash(&self) -> String {
-        self.info_hash.clone()
+    /// Returns the size in bytes of the piece, taking into account that the last piece may be shorter.
+    pub fn piece_size(&self, index: u32) -> u32 {
+        let last_piece_size = self.last_piece_size();
+        if index == self.total_pieces() - 1 && last_piece_size != 0 {
+            last_piece_size
+        } else {
+            self.piece_length()
+        }
     }
-}
 
-impl ToBencode for Torrent {
-    fn to_bencode(&self) -> Bencode {
-        let mut m = BTreeMap::new();
-        m.insert(b"announce_url".to_vec(), self.announce_url.to_bencode());
-        m.insert(b"info".to_vec(), self.info.to_bencode());
-        Bencode::BDict(m)
+    /// Returns the offset in bytes of the piece from the start of the file.
+    pub fn piece_offset(&self, index: u32) -> u64 {
+        index as u64 * self.info.piece_length as u64
+    }
+
+    /// Returns the info hash of the torrent.
+    pub fn info_hash(&self) -> InfoHash {
+        self.info_hash
+    }
+
+    /// Returns true if the `info` dictionary has the private flag set.
+    pub fn is_private(&self) -> bool {
+        self.info.private == Some(1)
+    }
+
+    /// Returns true if the torrent is a hybrid torrent, that can also be downloaded from v2 peers.
+    pub fn is_hybrid(&self) -> bool {
+        self.info_hash_v2.is_some()
+    }
+
+    /// Returns the root of the merkle tree of the file of a hybrid torrent.
+    pub fn pieces_root(&self) -> Option<MerkleHash> {
+        self.info
+            .file_tree
+            .files()
+            .first()
+            .and_then(|file| file.pieces_root)
+    }
+
+    /// Returns the hash of the piece in the merkle tree of the file of a hybrid torrent, if its piece layer is known.
+    pub fn piece_hash_v2(&self, index: u32) -> Option<MerkleHash> {
+        self.piece_layer.get(index as usize).copied()
+    }
+
+    /// Returns the v2 info hash as a lowercase hexadecimal string, if it is a hybrid torrent.
+    pub fn info_hash_v2_hex(&self) -> Option<String> {
+        self.info_hash_v2
+            .map(|hash| hash.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    /// Returns the magnet link of the torrent, with its info hash, name and all its trackers, to share it.
+    ///
+    /// Hybrid torrents also have their v2 info hash as a SHA-256 multihash.
+    pub fn to_magnet(&self) -> String {
+        let mut link = format!("magnet:?xt=urn:btih:{}", self.info_hash.to_hex());
+        if let Some(hash) = self.info_hash_v2_hex() {
+            link.push_str("&xt=urn:btmh:1220");
+            link.push_str(&hash);
+        }
+        link.push_str("&dn=");
+        link.push_str(&encode_bytes(self.info.name.as_bytes()));
+        for tracker in self.trackers() {
+            link.push_str("&tr=");
+            link.push_str(&encode_bytes(tracker.as_bytes()));
+        }
+        link
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
     use super::*;
+    use crate::test_support::local_swarm::generated_hybrid_torrent;
 
     #[test]
     fn test_from_torrent_full() {
@@ -176,6 +382,9 @@ mod tests {
         let torrent = Torrent::from(torrent_bencode).unwrap();
 
         assert_eq!(torrent.announce_url, announce);
+        assert!(torrent.announce_list.is_empty());
+        assert!(torrent.http_seeds.is_empty());
+        assert_eq!(torrent.trackers(), vec![announce]);
         assert_eq!(torrent.info.length, info_len);
         assert_eq!(torrent.info.name, info_name);
         assert_eq!(torrent.info.piece_length, info_piece_len);
@@ -183,6 +392,51 @@ mod tests {
         assert_eq!(torrent.info_hash, info_hash);
     }
 
+    #[test]
+    fn test_from_torrent_with_announce_list() {
+        let data = b"d8:announce17:http://a/announce13:announce-listll17:http://a/announce17:http://b/announceeli3eel17:http://c/announceee4:infod6:lengthi10e4:name7:example12:piece lengthi20e6:pieces4:testee";
+
+        let torrent = Torrent::from_bytes(data).unwrap();
+
+        assert_eq!(
+            torrent.announce_list,
+            vec![
+                vec![
+                    "http://a/announce".to_string(),
+                    "http://b/announce".to_string()
+                ],
+                vec!["http://c/announce".to_string()]
+            ]
+        );
+        assert_eq!(
+            torrent.trackers(),
+            vec![
+                "http://a/announce",
+                "http://b/announce",
+                "http://c/announce"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_torrent_with_http_seeds() {
+        let data = b"d8:announce17:http://a/announce9:httpseedsl14:http://s/seed1i3e0:e4:infod6:lengthi10e4:name7:example12:piece lengthi20e6:pieces4:testee";
+
+        let torrent = Torrent::from_bytes(data).unwrap();
+
+        assert_eq!(torrent.http_seeds, vec!["http://s/seed1"]);
+    }
+
+    #[test]
+    fn test_from_torrent_with_announce_list_only() {
+        let data = b"d13:announce-listll17:http://b/announceee4:infod6:lengthi10e4:name7:example12:piece lengthi20e6:pieces4:testee";
+
+        let torrent = Torrent::from_bytes(data).unwrap();
+
+        assert_eq!(torrent.announce_url, "http://b/announce");
+        assert_eq!(torrent.trackers(), vec!["http://b/announce"]);
+    }
+
     #[test]
     fn test_from_torrent_empty() {
         let torrent_bencode = Bencode::BDict(BTreeMap::new());
@@ -229,24 +483,56 @@ mod tests {
     }
 
     #[test]
-    fn test_get_info_hash_as_bytes() {
-        let info_hash = String::from("2c6b6858d61da9543d4231a71db4b1c9264b0685");
+    fn test_from_bytes_hashes_raw_info() {
+        let raw_info =
+            b"d6:lengthi10e4:name7:example12:piece lengthi20e6:pieces4:test6:source4:teste";
+        let mut data = b"d8:announce27:http://example.com/announce4:info".to_vec();
+        data.extend(raw_info);
+        data.push(b'e');
+
+        let torrent = Torrent::from_bytes(&data).unwrap();
+
+        let expected = InfoHash::from_bytes(&Sha1::digest(raw_info)).unwrap();
+        assert_eq!(torrent.info_hash, expected);
+        assert_ne!(
+            torrent.info_hash,
+            Torrent::create_info_hash(&torrent.info).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_invalid_bencode() {
+        let actual_err = Torrent::from_bytes(b"d8:announce").unwrap_err();
+
+        assert!(matches!(actual_err, FromTorrentError::DecodeError(_)));
+    }
+
+    #[test]
+    fn test_info_hash() {
+        let info_hash = InfoHash::from_hex("2c6b6858d61da9543d4231a71db4b1c9264b0685").unwrap();
         let info_hash_bytes = [
             44, 107, 104, 88, 214, 29, 169, 84, 61, 66, 49, 167, 29, 180, 177, 201, 38, 75, 6, 133,
         ];
 
         let torrent = Torrent {
             announce_url: String::from("http://example.com/announce"),
+            announce_list: vec![],
+            http_seeds: vec![],
+            info_hash_v2: None,
+            piece_layer: vec![],
             info: Info {
                 length: 10,
                 name: String::from("example"),
                 piece_length: 20,
                 pieces: String::from("test").into_bytes(),
+                private: None,
+                meta_version: None,
+                file_tree: Default::default(),
             },
             info_hash,
         };
 
-        assert_eq!(torrent.get_info_hash_as_bytes().unwrap(), info_hash_bytes);
+        assert_eq!(torrent.info_hash().as_bytes(), &info_hash_bytes);
     }
 
     #[test]
@@ -279,6 +565,131 @@ mod tests {
         assert_eq!(torrent.last_piece_size(), 5);
     }
 
+    #[test]
+    fn test_piece_size() {
+        let torrent = build_test_torrent();
+        assert_eq!(torrent.piece_size(0), 10);
+        assert_eq!(torrent.piece_size(10), 5);
+    }
+
+    #[test]
+    fn test_torrent_shorter_than_a_piece() {
+        let mut torrent = build_test_torrent();
+        torrent.info.length = 7;
+
+        assert_eq!(torrent.total_pieces(), 1);
+        assert_eq!(torrent.piece_size(0), 7);
+    }
+
+    #[test]
+    fn test_piece_offset() {
+        let torrent = build_test_torrent();
+        assert_eq!(torrent.piece_offset(0), 0);
+        assert_eq!(torrent.piece_offset(10), 100);
+    }
+
+    #[test]
+    fn test_torrent_larger_than_4_gib() {
+        let mut torrent = build_test_torrent();
+        // 5 GiB + 100 bytes, with pieces of 256 KiB.
+        torrent.info.length = 5 * 1024 * 1024 * 1024 + 100;
+        torrent.info.piece_length = 256 * 1024;
+
+        assert_eq!(torrent.length(), 5_368_709_220);
+        assert_eq!(torrent.total_pieces(), 20481);
+        assert_eq!(torrent.last_piece_size(), 100);
+        assert_eq!(torrent.piece_offset(20480), 5_368_709_120);
+    }
+
+    #[test]
+    fn test_to_magnet() {
+        let mut torrent = build_test_torrent();
+        torrent.info.name = String::from("two words");
+        torrent.announce_list = vec![
+            vec![String::from("http://a.com/announce")],
+            vec![String::from("udp://b.com:80")],
+        ];
+
+        assert_eq!(
+            torrent.to_magnet(),
+            "magnet:?xt=urn:btih:0000000000000000000000000000000000000000&dn=two%20words\
+             &tr=http%3A%2F%2Fa.com%2Fannounce&tr=udp%3A%2F%2Fb.com%3A80"
+        );
+        assert!(!torrent.is_private());
+    }
+
+    #[test]
+    fn test_from_hybrid_torrent() {
+        let raw_info: &[u8] =
+            b"d9:file treed1:ad0:d6:lengthi3e11:pieces root32:rrrrrrrrrrrrrrrrrrrrrrrrrrrrrrrreee\
+6:lengthi3e12:meta versioni2e4:name1:a12:piece lengthi16384e6:pieces20:hhhhhhhhhhhhhhhhhhhhe";
+        let data = [b"d8:announce17:http://a/announce4:info", raw_info, b"e"].concat();
+
+        let torrent = Torrent::from_bytes(&data).unwrap();
+
+        assert!(torrent.is_hybrid());
+        assert_eq!(torrent.info_hash_v2, Some(sha256(raw_info)));
+        assert_eq!(
+            torrent.info_hash,
+            InfoHash::from_bytes(&Sha1::digest(raw_info)).unwrap()
+        );
+        assert_eq!(
+            Torrent::from(Bencode::decode(&data).unwrap()).unwrap(),
+            torrent
+        );
+        assert!(torrent.to_magnet().contains(&format!(
+            "&xt=urn:btmh:1220{}&dn=a",
+            torrent.info_hash_v2_hex().unwrap()
+        )));
+    }
+
+    #[test]
+    fn test_from_v2_only_torrent() {
+        let data = b"d8:announce17:http://a/announce4:infod9:file treed1:ad0:d6:lengthi3e11:pieces root32:\
+rrrrrrrrrrrrrrrrrrrrrrrrrrrrrrrreee12:meta versioni2e4:name1:a12:piece lengthi16384eee";
+
+        assert_eq!(
+            Torrent::from_bytes(data).unwrap_err(),
+            FromTorrentError::FromInfoError(FromInfoError::V2OnlyNotSupported)
+        );
+    }
+
+    #[test]
+    fn test_from_hybrid_torrent_with_piece_layers() {
+        let (generated, _) =
+            generated_hybrid_torrent("hybrid", 3 * 32768 - 10, 32768, "http://a/announce");
+        let pieces_root = generated.pieces_root().unwrap();
+        let torrent_bencode = |layer: Option<Vec<u8>>| {
+            let info = bencoder::to_bytes(&generated.info).unwrap();
+            let mut dict = BTreeMap::new();
+            dict.insert(
+                b"announce".to_vec(),
+                Bencode::BString(b"http://a/announce".to_vec()),
+            );
+            dict.insert(b"info".to_vec(), Bencode::decode(&info).unwrap());
+            if let Some(layer) = layer {
+                let mut piece_layers = BTreeMap::new();
+                piece_layers.insert(pieces_root.to_vec(), Bencode::BString(layer));
+                dict.insert(b"piece layers".to_vec(), Bencode::BDict(piece_layers));
+            }
+            Bencode::BDict(dict)
+        };
+        let mut layer = generated.piece_layer.concat();
+
+        let torrent = Torrent::from(torrent_bencode(Some(layer.clone()))).unwrap();
+        assert_eq!(torrent.piece_layer, generated.piece_layer);
+        assert_eq!(torrent.piece_hash_v2(2), Some(generated.piece_layer[2]));
+
+        let torrent = Torrent::from(torrent_bencode(None)).unwrap();
+        assert!(torrent.piece_layer.is_empty());
+
+        layer[0] ^= 1;
+        assert_eq!(
+            Torrent::from(torrent_bencode(Some(layer))).unwrap_err(),
+            FromTorrentError::InvalidPieceLayers
+        );
+    }
+
     fn build_info_bencode(
         length: i64,
         name: Vec<u8>,
@@ -306,13 +717,20 @@ mod tests {
     fn build_test_torrent() -> Torrent {
         Torrent {
             announce_url: String::from("http://example.com/announce"),
+            announce_list: vec![],
+            http_seeds: vec![],
+            info_hash_v2: None,
+            piece_layer: vec![],
             info: Info {
                 length: 105,
                 name: String::from("example"),
                 piece_length: 10,
                 pieces: String::from("test").into_bytes(),
+                private: None,
+                meta_version: None,
+                file_tree: Default::default(),
             },
-            info_hash: "info_hash".to_string(),
+            info_hash: InfoHash::new([0; 20]),
         }
     }
 }
@@ -0,0 +1,460 @@
+/// Flags of the gzip header.
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+/// Compression method of the gzip header for deflate, the only one defined.
+const DEFLATE_METHOD: u8 = 8;
+
+const MAX_CODE_LENGTH: usize = 15;
+
+/// Base lengths and extra bits of the length symbols 257 to 285.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Base distances and extra bits of the distance symbols 0 to 29.
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Order in which the code lengths of the code length alphabet are stored in a dynamic block.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Posible errors decompressing gzip data.
+#[derive(Debug, PartialEq, Eq)]
+pub enum GzipError {
+    /// The data does not start with a gzip header.
+    InvalidHeader,
+    /// The compression method is not deflate.
+    UnsupportedMethod(u8),
+    /// The deflate stream is corrupt.
+    InvalidData,
+    /// The data ends before the end of the stream.
+    UnexpectedEnd,
+    /// The CRC or size in the trailer do not match the decompressed data.
+    ChecksumMismatch,
+    /// The decompressed data is bigger than the allowed size.
+    TooLarge,
+}
+
+/// Decompresses gzip data (RFC 1952), as sent with `Content-Encoding: gzip`.
+///
+/// Only the first member is decompressed. The decompressed data can be at most `max_size` bytes long, so a small
+/// response can not expand to an arbitrary amount of memory.
+///
+/// It returns an error if:
+/// - `InvalidHeader` or `UnsupportedMethod` if the data is not a gzip stream compressed with deflate.
+/// - `InvalidData` or `UnexpectedEnd` if the deflate stream is corrupt or truncated.
+/// - `ChecksumMismatch` if the trailer does not match the decompressed data.
+/// - `TooLarge` if the decompressed data is bigger than `max_size`.
+pub fn decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>, GzipError> {
+    if data.len() < 10 || data[0] != 0x1f || data[1] != 0x8b {
+        return Err(GzipError::InvalidHeader);
+    }
+    if data[2] != DEFLATE_METHOD {
+        return Err(GzipError::UnsupportedMethod(data[2]));
+    }
+    let flags = data[3];
+
+    let mut position = 10;
+    if flags & FEXTRA != 0 {
+        let length = data
+            .get(position..position + 2)
+            .ok_or(GzipError::UnexpectedEnd)?;
+        position += 2 + u16::from_le_bytes([length[0], length[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = data
+                .get(position..)
+                .and_then(|rest| rest.iter().position(|byte| *byte == 0))
+                .ok_or(GzipError::UnexpectedEnd)?;
+            position += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        position += 2;
+    }
+    let compressed = data.get(position..).ok_or(GzipError::UnexpectedEnd)?;
+
+    let (decompressed, consumed) = inflate(compressed, max_size)?;
+
+    let trailer = compressed
+        .get(consumed..consumed + 8)
+        .ok_or(GzipError::UnexpectedEnd)?;
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if crc != crc32(&decompressed) || size != decompressed.len() as u32 {
+        return Err(GzipError::ChecksumMismatch);
+    }
+    Ok(decompressed)
+}
+
+/// Decompresses a raw deflate stream (RFC 1951), returning the data and the number of bytes of the stream.
+fn inflate(data: &[u8], max_size: usize) -> Result<(Vec<u8>, usize), GzipError> {
+    let mut reader = BitReader::new(data);
+    let mut output = vec![];
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => stored_block(&mut reader, &mut output, max_size)?,
+            1 => {
+                let (lengths, distances) = fixed_codes()?;
+                compressed_block(&mut reader, &mut output, max_size, &lengths, &distances)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_codes(&mut reader)?;
+                compressed_block(&mut reader, &mut output, max_size, &lengths, &distances)?;
+            }
+            _ => return Err(GzipError::InvalidData),
+        }
+        if last {
+            break;
+        }
+    }
+
+    reader.align();
+    Ok((output, reader.consumed()))
+}
+
+fn stored_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_size: usize,
+) -> Result<(), GzipError> {
+    reader.align();
+    let length = reader.bits(16)?;
+    let complement = reader.bits(16)?;
+    if length != !complement & 0xffff {
+        return Err(GzipError::InvalidData);
+    }
+    if output.len() + length as usize > max_size {
+        return Err(GzipError::TooLarge);
+    }
+    for _ in 0..length {
+        output.push(reader.bits(8)? as u8);
+    }
+    Ok(())
+}
+
+fn compressed_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_size: usize,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> Result<(), GzipError> {
+    loop {
+        let symbol = lengths.decode(reader)? as usize;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let symbol = symbol - 257;
+                if symbol >= LENGTH_BASE.len() {
+                    return Err(GzipError::InvalidData);
+                }
+                let length = LENGTH_BASE[symbol] as usize
+                    + reader.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+
+                let symbol = distances.decode(reader)? as usize;
+                if symbol >= DISTANCE_BASE.len() {
+                    return Err(GzipError::InvalidData);
+                }
+                let distance = DISTANCE_BASE[symbol] as usize
+                    + reader.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+                if distance > output.len() {
+                    return Err(GzipError::InvalidData);
+                }
+
+                // The copy can overlap the bytes it writes, so it goes byte by byte.
+                for _ in 0..length {
+                    output.push(output[output.len() - distance]);
+                }
+            }
+        }
+        if output.len() > max_size {
+            return Err(GzipError::TooLarge);
+        }
+    }
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), GzipError> {
+    let mut lengths = [8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), GzipError> {
+    let length_codes = reader.bits(5)? as usize + 257;
+    let distance_codes = reader.bits(5)? as usize + 1;
+    let code_length_codes = reader.bits(4)? as usize + 4;
+    if length_codes > 286 || distance_codes > 30 {
+        return Err(GzipError::InvalidData);
+    }
+
+    let mut code_lengths = [0; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_codes) {
+        code_lengths[*index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![];
+    while lengths.len() < length_codes + distance_codes {
+        let (length, repeat) = match code_lengths.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (
+                *lengths.last().ok_or(GzipError::InvalidData)?,
+                3 + reader.bits(2)?,
+            ),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        if lengths.len() + repeat as usize > length_codes + distance_codes {
+            return Err(GzipError::InvalidData);
+        }
+        lengths.extend((0..repeat).map(|_| length));
+    }
+
+    // The block must have an end of block symbol.
+    if lengths[256] == 0 {
+        return Err(GzipError::InvalidData);
+    }
+    Ok((
+        Huffman::new(&lengths[..length_codes])?,
+        Huffman::new(&lengths[length_codes..])?,
+    ))
+}
+
+/// Reads the bits of a deflate stream, starting from the least significant bit of each byte.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    /// Reads `count` bits, at most 16.
+    fn bits(&mut self, count: u32) -> Result<u32, GzipError> {
+        while self.count < count {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(GzipError::UnexpectedEnd)?;
+            self.position += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << count) - 1);
+        self.buffer >>= count;
+        self.count -= count;
+        Ok(value)
+    }
+
+    /// Discards the bits left of the current byte.
+    fn align(&mut self) {
+        let partial = self.count % 8;
+        self.buffer >>= partial;
+        self.count -= partial;
+    }
+
+    /// Returns the number of bytes read, not counting the whole bytes left in the buffer.
+    fn consumed(&self) -> usize {
+        self.position - (self.count / 8) as usize
+    }
+}
+
+/// Canonical Huffman code, decoded one bit at a time.
+struct Huffman {
+    /// Number of symbols of each code length.
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    /// Symbols ordered by code.
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// Builds the code from the code length of each symbol, 0 for unused symbols.
+    fn new(lengths: &[u8]) -> Result<Self, GzipError> {
+        let mut counts = [0; MAX_CODE_LENGTH + 1];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // More codes of a length than there is room for means the lengths are invalid.
+        let mut left: i32 = 1;
+        for count in &counts[1..] {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return Err(GzipError::InvalidData);
+            }
+        }
+
+        let mut offsets = [0; MAX_CODE_LENGTH + 2];
+        for length in 1..=MAX_CODE_LENGTH {
+            offsets[length + 1] = offsets[length] + counts[length] as usize;
+        }
+        let mut symbols = vec![0; offsets[MAX_CODE_LENGTH + 1]];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize]] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, GzipError> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = *count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(GzipError::InvalidData)
+    }
+}
+
+/// CRC-32 of the gzip trailer, with the reflected polynomial 0xEDB88320.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE: &[u8] = b"d8:intervali1800e5:peers0:e";
+
+    /// `RESPONSE` compressed with fixed Huffman codes.
+    const FIXED: [u8; 47] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4b, 0xb1, 0xb0, 0xca, 0xcc,
+        0x2b, 0x49, 0x2d, 0x2a, 0x4b, 0xcc, 0xc9, 0x34, 0xb4, 0x30, 0x30, 0x48, 0x35, 0xb5, 0x2a,
+        0x48, 0x4d, 0x2d, 0x2a, 0x36, 0xb0, 0x4a, 0x05, 0x00, 0xab, 0xd9, 0x98, 0x92, 0x1b, 0x00,
+        0x00, 0x00,
+    ];
+
+    /// `RESPONSE` in a stored block, without compression.
+    const STORED: [u8; 50] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x1b, 0x00, 0xe4, 0xff,
+        0x64, 0x38, 0x3a, 0x69, 0x6e, 0x74, 0x65, 0x72, 0x76, 0x61, 0x6c, 0x69, 0x31, 0x38, 0x30,
+        0x30, 0x65, 0x35, 0x3a, 0x70, 0x65, 0x65, 0x72, 0x73, 0x30, 0x3a, 0x65, 0xab, 0xd9, 0x98,
+        0x92, 0x1b, 0x00, 0x00, 0x00,
+    ];
+
+    /// `peers_list()` compressed with dynamic Huffman codes.
+    const DYNAMIC: [u8; 137] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xed, 0xd0, 0x31, 0x0e, 0xc3,
+        0x20, 0x10, 0x44, 0xd1, 0x1b, 0x45, 0x0b, 0x06, 0xbc, 0xec, 0x6d, 0xa2, 0x78, 0x0a, 0x24,
+        0x62, 0x23, 0x8c, 0x72, 0xfe, 0x44, 0x29, 0xb2, 0xf2, 0x96, 0xa9, 0xd1, 0x54, 0xa3, 0xd7,
+        0xfd, 0x8d, 0xe5, 0x71, 0x3c, 0x5b, 0xc5, 0x40, 0xf1, 0x70, 0x24, 0x65, 0xff, 0x7d, 0x02,
+        0x7f, 0xee, 0x40, 0x7f, 0xdd, 0x6b, 0xc9, 0x44, 0x88, 0xd2, 0x80, 0x7e, 0xd6, 0xcd, 0x4b,
+        0x69, 0x59, 0x1c, 0xdd, 0xbe, 0x0b, 0xd2, 0x8e, 0x3e, 0x4a, 0x62, 0x26, 0xe0, 0x6a, 0x4e,
+        0xcd, 0x59, 0xf3, 0x6a, 0xde, 0xda, 0xa2, 0xb6, 0x58, 0x0b, 0x6a, 0xc1, 0x5a, 0x54, 0x8b,
+        0xd6, 0x92, 0x5a, 0xb2, 0xb6, 0xaa, 0xad, 0xd6, 0x58, 0x8d, 0xad, 0x65, 0xb5, 0x6c, 0x6d,
+        0x76, 0x99, 0x5d, 0x66, 0x97, 0x7f, 0xba, 0x00, 0x6f, 0x05, 0x15, 0xf5, 0xc0, 0x97, 0x04,
+        0x00, 0x00,
+    ];
+
+    fn peers_list() -> Vec<u8> {
+        let mut response = b"d8:completei2e10:incompletei0e8:intervali900e5:peersl".to_vec();
+        for i in 0..40 {
+            response.extend(format!("d2:ip9:10.0.0.{}4:porti688{}ee", i % 10, i % 10).bytes());
+        }
+        response.extend(b"ee");
+        response
+    }
+
+    #[test]
+    fn test_decompress_fixed_codes() {
+        assert_eq!(decompress(&FIXED, 1024), Ok(RESPONSE.to_vec()));
+    }
+
+    #[test]
+    fn test_decompress_stored_block() {
+        assert_eq!(decompress(&STORED, 1024), Ok(RESPONSE.to_vec()));
+    }
+
+    #[test]
+    fn test_decompress_dynamic_codes() {
+        assert_eq!(decompress(&DYNAMIC, 4096), Ok(peers_list()));
+    }
+
+    #[test]
+    fn test_decompress_with_file_name() {
+        let mut data = FIXED[..10].to_vec();
+        data[3] = FNAME;
+        data.extend(b"announce\0");
+        data.extend(&FIXED[10..]);
+
+        assert_eq!(decompress(&data, 1024), Ok(RESPONSE.to_vec()));
+    }
+
+    #[test]
+    fn test_decompress_bigger_than_max_size() {
+        assert_eq!(decompress(&DYNAMIC, 1000), Err(GzipError::TooLarge));
+        assert_eq!(decompress(&STORED, 10), Err(GzipError::TooLarge));
+    }
+
+    #[test]
+    fn test_decompress_invalid_data() {
+        assert_eq!(decompress(RESPONSE, 1024), Err(GzipError::InvalidHeader));
+        assert_eq!(
+            decompress(&FIXED[..FIXED.len() - 4], 1024),
+            Err(GzipError::UnexpectedEnd)
+        );
+
+        let mut corrupt = FIXED;
+        corrupt[FIXED.len() - 8] ^= 1;
+        assert_eq!(decompress(&corrupt, 1024), Err(GzipError::ChecksumMismatch));
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+}
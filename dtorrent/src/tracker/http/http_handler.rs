@@ -1,12 +1,18 @@
 use native_tls::Error;
 use native_tls::HandshakeError;
 use native_tls::TlsConnector;
+use std::cell::Cell;
 use std::io::Error as IOError;
 use std::io::{Read, Write};
 use std::net::TcpStream;
 
+use super::gzip::{self, GzipError};
 use super::query_params::QueryParams;
 use super::url_parser::TrackerUrl;
+use crate::proxy::{Proxy, ProxyError};
+
+/// Max size in bytes of a tracker response, both as received and decompressed.
+const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
 
 /// `HttpHandler` struct to make **HTTP** requests.
 ///
@@ -15,10 +21,14 @@ use super::url_parser::TrackerUrl;
 /// To make a **HTTPS** request use the method `https_request()`.
 ///
 /// To make a **HTTP** request use the method `http_request()`.
+///
+/// The bytes sent and received by the request are kept, see `traffic()`.
 #[derive(Debug)]
 pub struct HttpHandler {
     tracker_url: TrackerUrl,
     query_params: QueryParams,
+    proxy: Option<Proxy>,
+    traffic: Cell<(u64, u64)>,
 }
 
 /// Posible `HttpHandler` errors
@@ -29,6 +39,9 @@ pub enum HttpHandlerError {
     TlsStreamConnectError(TlsStreamConnectError),
     ErrorWritingStream(IOError),
     ErrorReadingStream(IOError),
+    ProxyConnectError(ProxyError),
+    ResponseTooLarge,
+    DecompressError(GzipError),
 }
 
 /// Posible `TlsStreamConnect` errors.
@@ -49,18 +62,27 @@ impl HttpHandler {
         Self {
             tracker_url,
             query_params,
+            proxy: None,
+            traffic: Cell::new((0, 0)),
         }
     }
 
+    /// Makes the requests through `proxy`, if any.
+    pub fn with_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
     /// Makes a **HTTPS** request to the tracker url.
     ///
     /// On success it returns a `Vec<u8>` cointaining the tracker's response.
     ///
     /// It returns an `HttpHandlerError` if:
     /// - There was a problem creating a TlsConnector.
-    /// - There was a problem connecting to the tracker_url.
+    /// - There was a problem connecting to the tracker_url, or to the proxy if one is set.
     /// - There was a problem writing to the tracker stream.
     /// - There was a problem reading the tracker stream.
+    /// - The response is bigger than `MAX_RESPONSE_SIZE`, or it is gzipped and can not be decompressed.
     pub fn https_request(&self) -> Result<Vec<u8>, HttpHandlerError> {
         let connector = match TlsConnector::new() {
             Ok(connector) => connector,
@@ -90,14 +112,27 @@ impl HttpHandler {
     /// On success it returns a `Vec<u8>` cointaining the tracker's response.
     ///
     /// It returns an `HttpHandlerError` if:
-    /// - There was a problem connecting to the tracker_url.
+    /// - There was a problem connecting to the tracker_url, or to the proxy if one is set.
     /// - There was a problem writing to the tracker stream.
     /// - There was a problem reading the tracker stream.
+    /// - The response is bigger than `MAX_RESPONSE_SIZE`, or it is gzipped and can not be decompressed.
     pub fn http_request(&self) -> Result<Vec<u8>, HttpHandlerError> {
         self.request_and_decode(&self.connect_tcp_stream()?)
     }
 
+    /// Returns the bytes of the request sent and of the response received, before decompressing it.
+    ///
+    /// It does not include the bytes of the TLS handshake nor of the proxy negotiation.
+    pub fn traffic(&self) -> (u64, u64) {
+        self.traffic.get()
+    }
+
     fn connect_tcp_stream(&self) -> Result<TcpStream, HttpHandlerError> {
+        if let Some(proxy) = &self.proxy {
+            return proxy
+                .connect(&self.tracker_url.host, self.tracker_url.port as u16)
+                .map_err(HttpHandlerError::ProxyConnectError);
+        }
         let connect_url = format!("{}:{}", self.tracker_url.host, self.tracker_url.port);
         match TcpStream::connect(connect_url) {
             Ok(stream) => Ok(stream),
@@ -105,6 +140,9 @@ impl HttpHandler {
         }
     }
 
+    /// Sends the announce and returns the body of the response.
+    ///
+    /// Responses are accepted gzipped, since some trackers compress them, and are decompressed here.
     fn request_and_decode<A>(&self, mut stream: A) -> Result<Vec<u8>, HttpHandlerError>
     where
         A: Write + Read,
@@ -120,113 +158,205 @@ impl HttpHandler {
         request.push_str("\r\n");
         request.push_str("User-Agent: LDTorrent/0.1");
         request.push_str("\r\n");
+        request.push_str("Accept-Encoding: gzip");
+        request.push_str("\r\n");
+        request.push_str("Connection: close");
+        request.push_str("\r\n");
         request.push_str("\r\n");
 
         match stream.write_all(request.as_bytes()) {
             Ok(_) => (),
             Err(err) => return Err(HttpHandlerError::ErrorWritingStream(err)),
         }
+        let sent_bytes = request.len() as u64;
+        self.traffic.set((sent_bytes, 0));
         let mut res = vec![];
-        match stream.read_to_end(&mut res) {
-            Ok(_) => (),
-            Err(err) => return Err(HttpHandlerError::ErrorReadingStream(err)),
-        };
+        let read = (&mut stream)
+            .take(MAX_RESPONSE_SIZE as u64 + 1)
+            .read_to_end(&mut res);
+        self.traffic.set((sent_bytes, res.len() as u64));
+        if let Err(err) = read {
+            return Err(HttpHandlerError::ErrorReadingStream(err));
+        }
+        if res.len() > MAX_RESPONSE_SIZE {
+            return Err(HttpHandlerError::ResponseTooLarge);
+        }
 
-        Ok(Self::parse_http_response(&res).to_vec())
+        let (head, body) = Self::parse_http_response(&res);
+        if Self::is_gzipped(head) {
+            return gzip::decompress(body, MAX_RESPONSE_SIZE).map_err(|err| match err {
+                GzipError::TooLarge => HttpHandlerError::ResponseTooLarge,
+                err => HttpHandlerError::DecompressError(err),
+            });
+        }
+        Ok(body.to_vec())
     }
 
-    fn parse_http_response(res: &[u8]) -> &[u8] {
-        for (i, b) in res.iter().enumerate() {
-            if i + 3 > res.len() {
-                break;
-            }
-
-            if *b == b"\r"[0]
-                && res[i + 1] == b"\n"[0]
-                && res[i + 2] == b"\r"[0]
-                && res[i + 3] == b"\n"[0]
-            {
-                return &res[(i + 4)..];
-            }
+    /// Splits the response in its status line and headers, and its body.
+    ///
+    /// If the response has no headers, all of it is the body.
+    fn parse_http_response(res: &[u8]) -> (&[u8], &[u8]) {
+        match res.windows(4).position(|window| window == b"\r\n\r\n") {
+            Some(end) => (&res[..end], &res[end + 4..]),
+            None => (&[], res),
         }
-        res
+    }
+
+    /// Returns true if the headers have `Content-Encoding: gzip`.
+    fn is_gzipped(head: &[u8]) -> bool {
+        String::from_utf8_lossy(head).split("\r\n").any(|line| {
+            line.split_once(':').is_some_and(|(name, value)| {
+                name.trim().eq_ignore_ascii_case("content-encoding")
+                    && value.trim().eq_ignore_ascii_case("gzip")
+            })
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::net::{Ipv4Addr, SocketAddrV4};
+
+    use crate::test_support::mock_tracker::{failure_response, peers_response, MockTracker};
     use crate::tracker::http::url_parser;
+    use url_encoder::info_hash::InfoHash;
 
     use super::*;
 
     #[test]
-    fn test_http_handler_https_request() {
+    fn test_https_request_to_a_plain_http_tracker_fails_the_handshake() {
+        let tracker = MockTracker::start(failure_response("unregistered torrent"));
+        let http_handler = HttpHandler::new(
+            url_parser::TrackerUrl::parse(
+                &tracker.announce_url().replacen("http://", "https://", 1),
+            )
+            .unwrap(),
+            QueryParams::new(
+                InfoHash::new([0; 20]),
+                6969,
+                0,
+                0,
+                100,
+                "test_peer_id".to_string(),
+            ),
+        );
+
+        assert!(matches!(
+            http_handler.https_request(),
+            Err(HttpHandlerError::TlsStreamConnectError(
+                TlsStreamConnectError::FatalError
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_http_handler_http_request() {
+        let peers = [SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 6881)];
+        let tracker = MockTracker::start(peers_response(1800, &peers));
+        let info_hash = InfoHash::from_hex("f834824904be1854c89ba007c01678ff797f8dc7").unwrap();
         let http_handler = HttpHandler::new(
-            url_parser::TrackerUrl::parse("https://torrent.ubuntu.com/announce").unwrap(),
+            url_parser::TrackerUrl::parse(&tracker.announce_url()).unwrap(),
             QueryParams::new(
-                "e82753b6692c4f3f3646b055f70ee390309020e6".to_string(),
+                info_hash,
                 6969,
+                0,
+                0,
                 100,
                 "-qB4500-k51bMCWVA(~!".to_string(),
             ),
         );
-        let response = http_handler.https_request().unwrap();
+        let response = http_handler.http_request().unwrap();
 
-        // d8:complete
-        assert!(response.starts_with(&[100, 56, 58, 99, 111, 109, 112, 108, 101, 116, 101]));
+        assert_eq!(response, peers_response(1800, &peers));
+        let requests = tracker.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with(&format!(
+            "GET /announce?info_hash={}&",
+            info_hash.url_encoded()
+        )));
     }
 
     #[test]
-    fn test_bad_http_handler_https_request() {
+    fn test_bad_http_handler_http_request() {
+        let tracker = MockTracker::start(failure_response("unregistered torrent"));
         let http_handler = HttpHandler::new(
-            url_parser::TrackerUrl::parse("https://torrent.ubuntu.com:443/announce").unwrap(),
+            url_parser::TrackerUrl::parse(&tracker.announce_url()).unwrap(),
             QueryParams::new(
-                "info_hash_test_info_hash_test_info_hash_test".to_string(),
+                InfoHash::new([0; 20]),
                 6969,
+                0,
+                0,
                 100,
                 "test_peer_id".to_string(),
             ),
         );
-        let response = http_handler.https_request().unwrap();
+        let response = http_handler.http_request().unwrap();
 
         // d14:failure
         assert!(response.starts_with(&[100, 49, 52, 58, 102, 97, 105, 108, 117, 114, 101]));
     }
 
     #[test]
-    fn test_http_handler_http_request() {
+    fn test_http_request_with_gzipped_response() {
+        // peers_response(1800, &[127.0.0.1:6881]) compressed with gzip.
+        let gzipped = vec![
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4b, 0xb1, 0xb0, 0x4a,
+            0xce, 0xcf, 0x2d, 0xc8, 0x49, 0x2d, 0x49, 0xcd, 0x34, 0x4c, 0x35, 0x34, 0xb0, 0xca,
+            0xcc, 0x83, 0xf3, 0x0d, 0x52, 0x2d, 0x80, 0xdc, 0x92, 0xd4, 0xa2, 0xb2, 0xc4, 0x9c,
+            0x4c, 0x43, 0x0b, 0x03, 0x83, 0x54, 0x53, 0xab, 0x82, 0xd4, 0xd4, 0xa2, 0x62, 0x33,
+            0xab, 0x7a, 0x06, 0x06, 0x46, 0xa9, 0x87, 0xa9, 0x00, 0x64, 0xe2, 0x86, 0xd7, 0x3e,
+            0x00, 0x00, 0x00,
+        ];
+        let tracker = MockTracker::start_with_headers(gzipped, "Content-Encoding: gzip\r\n");
         let http_handler = HttpHandler::new(
-            url_parser::TrackerUrl::parse("http://vps02.net.orel.ru/announce").unwrap(),
+            url_parser::TrackerUrl::parse(&tracker.announce_url()).unwrap(),
             QueryParams::new(
-                "f834824904be1854c89ba007c01678ff797f8dc7".to_string(),
+                InfoHash::new([0; 20]),
                 6969,
+                0,
+                0,
                 100,
-                "-qB4500-k51bMCWVA(~!".to_string(),
+                "test_peer_id".to_string(),
             ),
         );
+
         let response = http_handler.http_request().unwrap();
 
-        // d8:complete
-        assert!(response.starts_with(&[100, 56, 58, 99, 111, 109, 112, 108, 101, 116, 101]));
+        let peers = [SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 6881)];
+        assert_eq!(response, peers_response(1800, &peers));
     }
 
     #[test]
-    fn test_bad_http_handler_http_request() {
+    fn test_http_request_with_too_large_response() {
+        let tracker = MockTracker::start(vec![b'0'; MAX_RESPONSE_SIZE + 1]);
         let http_handler = HttpHandler::new(
-            url_parser::TrackerUrl::parse("http://vps02.net.orel.ru/announce").unwrap(),
+            url_parser::TrackerUrl::parse(&tracker.announce_url()).unwrap(),
             QueryParams::new(
-                "info_hash_test_info_hash_test_info_hash_test".to_string(),
+                InfoHash::new([0; 20]),
                 6969,
+                0,
+                0,
                 100,
                 "test_peer_id".to_string(),
             ),
         );
-        let response = http_handler.http_request().unwrap();
 
-        // <title>Invalid Request</title>
-        assert!(response.starts_with(&[
-            60, 116, 105, 116, 108, 101, 62, 73, 110, 118, 97, 108, 105, 100, 32, 82, 101, 113,
-            117, 101, 115, 116, 60, 47, 116, 105, 116, 108, 101, 62, 10
-        ]));
+        assert!(matches!(
+            http_handler.http_request(),
+            Err(HttpHandlerError::ResponseTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_parse_http_response_headers() {
+        let (head, body) = HttpHandler::parse_http_response(
+            b"HTTP/1.1 200 OK\r\ncontent-encoding:  GZIP \r\n\r\nbody",
+        );
+
+        assert_eq!(body, b"body");
+        assert!(HttpHandler::is_gzipped(head));
+        assert!(!HttpHandler::is_gzipped(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4"
+        ));
     }
 }
@@ -1,3 +1,4 @@
+pub mod gzip;
 pub mod http_handler;
 pub mod query_params;
 pub mod url_parser;
@@ -1,4 +1,6 @@
-use url_encoder::url_encoder::encode;
+use std::net::Ipv4Addr;
+
+use url_encoder::{info_hash::InfoHash, url_encoder::encode_bytes};
 
 /// `QueryParams` struct containing the query parameters information.
 ///
@@ -7,37 +9,107 @@ use url_encoder::url_encoder::encode;
 /// To build the Query params string use the method 'build()'.
 #[derive(Debug)]
 pub struct QueryParams {
-    info_hash: String,
+    info_hash: InfoHash,
     client_port: u32,
-    info_length: i64,
+    uploaded: u64,
+    downloaded: u64,
+    left: u64,
     client_peer_id: String,
+    client_ip: Option<Ipv4Addr>,
+    numwant: Option<u32>,
+    tracker_id: Option<Vec<u8>>,
+    event: AnnounceEvent,
+}
+
+/// Event sent to the tracker with an announce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnounceEvent {
+    /// The regular announces asking for peers.
+    Started,
+    /// The torrent is no longer shared, sent when it stops seeding.
+    Stopped,
+}
+
+impl AnnounceEvent {
+    /// Returns the value of the `event` query param.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnnounceEvent::Started => "started",
+            AnnounceEvent::Stopped => "stopped",
+        }
+    }
 }
 
 impl QueryParams {
-    /// Creates a new `QueryParams` from an **info_hash**, **client_port** and **info_lenght** passed by parameters.
+    /// Creates a new `QueryParams` from an **info_hash**, **client_port**, the **uploaded**, **downloaded** and **left** bytes and the **client_peer_id** passed by parameters.
     pub fn new(
-        info_hash: String,
+        info_hash: InfoHash,
         client_port: u32,
-        info_length: i64,
+        uploaded: u64,
+        downloaded: u64,
+        left: u64,
         client_peer_id: String,
     ) -> QueryParams {
         QueryParams {
             info_hash,
             client_port,
-            info_length,
+            uploaded,
+            downloaded,
+            left,
             client_peer_id,
+            client_ip: None,
+            numwant: None,
+            tracker_id: None,
+            event: AnnounceEvent::Started,
         }
     }
 
+    /// Sets the IP announced to the tracker, used when the client is behind a NAT with a mapped port.
+    pub fn with_client_ip(mut self, client_ip: Option<Ipv4Addr>) -> QueryParams {
+        self.client_ip = client_ip;
+        self
+    }
+
+    /// Sets the number of peers asked to the tracker, if not set the tracker uses its default.
+    pub fn with_numwant(mut self, numwant: Option<u32>) -> QueryParams {
+        self.numwant = numwant;
+        self
+    }
+
+    /// Sets the tracker id received in a previous announce, which must be sent back to the tracker.
+    pub fn with_tracker_id(mut self, tracker_id: Option<Vec<u8>>) -> QueryParams {
+        self.tracker_id = tracker_id;
+        self
+    }
+
+    /// Sets the event of the announce, `started` if not set.
+    pub fn with_event(mut self, event: AnnounceEvent) -> QueryParams {
+        self.event = event;
+        self
+    }
+
     /// Builds the QueryParams string and returns it.
     pub fn build(&self) -> String {
-        format!(
-            "?info_hash={}&peer_id={}&port={}&uploaded=0&downloaded=0&left={}&event=started",
-            encode(self.info_hash.as_str()),
-            self.client_peer_id,
+        let mut query = format!(
+            "?info_hash={}&peer_id={}&port={}&uploaded={}&downloaded={}&left={}&event={}",
+            self.info_hash.url_encoded(),
+            encode_bytes(self.client_peer_id.as_bytes()),
             self.client_port,
-            self.info_length
-        )
+            self.uploaded,
+            self.downloaded,
+            self.left,
+            self.event.as_str()
+        );
+        if let Some(client_ip) = self.client_ip {
+            query.push_str(&format!("&ip={}", client_ip));
+        }
+        if let Some(numwant) = self.numwant {
+            query.push_str(&format!("&numwant={}", numwant));
+        }
+        if let Some(tracker_id) = &self.tracker_id {
+            query.push_str(&format!("&trackerid={}", encode_bytes(tracker_id)));
+        }
+        query
     }
 }
 
@@ -45,20 +117,78 @@ impl QueryParams {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_query_params_build_with_transfer_stats() {
+        let info_hash = InfoHash::from_hex("2c6b6858d61da9543d4231a71db4b1c9264b0685").unwrap();
+        let peer_id = "test_peer_id".to_string();
+        let query_params = QueryParams::new(info_hash, 6969, 300, 200, 100, peer_id.clone());
+
+        assert_eq!(
+            query_params.build(),
+            format!(
+                "?info_hash={}&peer_id={}&port=6969&uploaded=300&downloaded=200&left=100&event=started",
+                info_hash.url_encoded(),
+                peer_id,
+            )
+        );
+    }
+
+    #[test]
+    fn test_query_params_build_with_client_ip() {
+        let info_hash = InfoHash::from_hex("2c6b6858d61da9543d4231a71db4b1c9264b0685").unwrap();
+        let query_params = QueryParams::new(info_hash, 6969, 0, 0, 100, "test_peer_id".to_string())
+            .with_client_ip(Some(Ipv4Addr::new(203, 0, 113, 7)));
+
+        assert!(query_params
+            .build()
+            .ends_with("&event=started&ip=203.0.113.7"));
+    }
+
+    #[test]
+    fn test_query_params_build_with_numwant_and_tracker_id() {
+        let info_hash = InfoHash::from_hex("2c6b6858d61da9543d4231a71db4b1c9264b0685").unwrap();
+        let query_params = QueryParams::new(info_hash, 6969, 0, 0, 100, "test_peer_id".to_string())
+            .with_numwant(Some(20))
+            .with_tracker_id(Some(b"id".to_vec()));
+
+        assert!(query_params
+            .build()
+            .ends_with("&event=started&numwant=20&trackerid=id"));
+    }
+
+    #[test]
+    fn test_query_params_build_with_stopped_event() {
+        let info_hash = InfoHash::from_hex("2c6b6858d61da9543d4231a71db4b1c9264b0685").unwrap();
+        let query_params = QueryParams::new(info_hash, 6969, 0, 0, 0, "test_peer_id".to_string())
+            .with_event(AnnounceEvent::Stopped);
+
+        assert!(query_params.build().ends_with("&left=0&event=stopped"));
+    }
+
+    #[test]
+    fn test_query_params_build_encodes_the_peer_id() {
+        let info_hash = InfoHash::from_hex("2c6b6858d61da9543d4231a71db4b1c9264b0685").unwrap();
+        let query_params =
+            QueryParams::new(info_hash, 6969, 0, 0, 100, "-qB4500-k5 &=?%(~!".to_string());
+
+        assert!(query_params
+            .build()
+            .contains("&peer_id=-qB4500-k5%20%26%3D%3F%25%28~%21&"));
+    }
+
     #[test]
     fn test_query_params_build() {
-        let info_hash = "2c6b6858d61da9543d4231a71db4b1c9264b0685".to_string();
+        let info_hash = InfoHash::from_hex("2c6b6858d61da9543d4231a71db4b1c9264b0685").unwrap();
         let client_port = 6969;
         let length = 100;
         let peer_id = "test_peer_id".to_string();
-        let query_params =
-            QueryParams::new(info_hash.clone(), client_port, length, peer_id.clone());
+        let query_params = QueryParams::new(info_hash, client_port, 0, 0, length, peer_id.clone());
 
         assert_eq!(
             query_params.build(),
             format!(
                 "?info_hash={}&peer_id={}&port={}&uploaded=0&downloaded=0&left={}&event=started",
-                encode(info_hash.as_str()),
+                info_hash.url_encoded(),
                 peer_id,
                 client_port,
                 length
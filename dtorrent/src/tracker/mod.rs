@@ -1,3 +1,4 @@
 pub mod http;
 pub mod tracker_handler;
+pub mod tracker_health;
 pub mod tracker_response;
@@ -1,11 +1,19 @@
+use std::net::Ipv4Addr;
+use std::time::SystemTime;
+
 use super::http::http_handler::{HttpHandler, HttpHandlerError};
-use super::http::query_params::QueryParams;
+use super::http::query_params::{AnnounceEvent, QueryParams};
 use super::http::url_parser::{ConnectionProtocol, TrackerUrl, TrackerUrlError};
+use super::tracker_health::TrackerHealth;
 use super::tracker_response::FromTrackerResponseError;
+use crate::nat::PortMapping;
+use crate::proxy::Proxy;
+use crate::torrent_handler::torrent_stats::TorrentStats;
 use crate::torrent_parser::torrent::Torrent;
 use crate::tracker::tracker_response::TrackerResponse;
+use tracing::warn;
 
-/// `TrackerHandler` struct for communicating to a bt tracker.
+/// `TrackerHandler` struct for communicating to the bt trackers of a torrent.
 ///
 /// To create a new `TrackerHandler` use the method builder `new()`.
 ///
@@ -13,10 +21,23 @@ use crate::tracker::tracker_response::TrackerResponse;
 #[derive(Debug)]
 pub struct TrackerHandler {
     pub torrent: Torrent,
-    pub tracker_url: TrackerUrl,
+    trackers: Vec<Tracker>,
     pub client_port: u32,
     client_peer_id: String,
+    client_ip: Option<Ipv4Addr>,
+    proxy: Option<Proxy>,
+    numwant: Option<u32>,
+}
+
+/// A tracker of the torrent.
+#[derive(Debug)]
+struct Tracker {
+    url: TrackerUrl,
+    health: TrackerHealth,
+    /// Id received from the tracker, sent back on the next announces.
+    tracker_id: Option<Vec<u8>>,
 }
+
 /// Posible `TrackerHandler` errors.
 #[derive(Debug)]
 pub enum TrackerHandlerError {
@@ -28,110 +49,436 @@ pub enum TrackerHandlerError {
 impl TrackerHandler {
     /// Builds a new `TrackerHandler` from a **Torrent** and a **client_port** passed by paramaters.
     ///
+    /// The trackers are the ones of `Torrent::trackers`, the ones with an invalid url are skipped.
+    ///
     /// It returns an `TrackerHandlerError` if:
-    /// - There was an error parsing the torrent's announce_url.
+    /// - None of the torrent's tracker urls can be parsed.
     pub fn new(
         torrent: Torrent,
         client_port: u32,
         client_peer_id: String,
     ) -> Result<Self, TrackerHandlerError> {
-        let tracker_url = match TrackerUrl::parse(torrent.announce_url.as_str()) {
-            Ok(url) => url,
-            Err(err) => return Err(TrackerHandlerError::UrlParseError(err)),
-        };
+        let mut trackers = vec![];
+        let mut parse_error = None;
+        for url in torrent.trackers() {
+            match TrackerUrl::parse(&url) {
+                Ok(tracker_url) => trackers.push(Tracker {
+                    url: tracker_url,
+                    health: TrackerHealth::new(url),
+                    tracker_id: None,
+                }),
+                Err(err) => {
+                    warn!("Skipping tracker {}: {:?}", url, err);
+                    parse_error.get_or_insert(err);
+                }
+            }
+        }
+        if trackers.is_empty() {
+            return Err(TrackerHandlerError::UrlParseError(
+                parse_error.unwrap_or(TrackerUrlError::InvalidTrackerURL),
+            ));
+        }
 
         Ok(Self {
             torrent,
-            tracker_url,
+            trackers,
             client_port,
             client_peer_id,
+            client_ip: None,
+            proxy: None,
+            numwant: None,
         })
     }
 
-    /// Gets the tracker's peers list.
+    /// Sets the IP announced to the tracker instead of the one the tracker sees the request coming from.
+    pub fn set_client_ip(&mut self, client_ip: Option<Ipv4Addr>) {
+        self.client_ip = client_ip;
+    }
+
+    /// Announces the external IP and port of `mapping` instead of the local ones, if there is a mapping.
+    pub fn set_port_mapping(&mut self, mapping: Option<PortMapping>) {
+        if let Some(mapping) = mapping {
+            self.client_port = mapping.external_port.into();
+            self.client_ip = Some(mapping.external_ip);
+        }
+    }
+
+    /// Sets the proxy the announces are sent through.
+    pub fn set_proxy(&mut self, proxy: Option<Proxy>) {
+        self.proxy = proxy;
+    }
+
+    /// Sets the number of peers asked to the tracker on each announce.
+    pub fn set_numwant(&mut self, numwant: Option<u32>) {
+        self.numwant = numwant;
+    }
+
+    /// Returns the health of each tracker of the torrent.
+    pub fn health(&self) -> Vec<TrackerHealth> {
+        self.trackers
+            .iter()
+            .map(|tracker| tracker.health.clone())
+            .collect()
+    }
+
+    /// Restores the health of the trackers kept elsewhere, like in the status of the torrent, matched by url.
+    ///
+    /// It lets a new `TrackerHandler` announce `stopped` to the trackers a previous one announced to.
+    pub fn set_health(&mut self, health: Vec<TrackerHealth>) {
+        for health in health {
+            if let Some(tracker) = self
+                .trackers
+                .iter_mut()
+                .find(|tracker| tracker.health.url == health.url)
+            {
+                tracker.health = health;
+            }
+        }
+    }
+
+    /// Gets the tracker's peers list, announcing the transfer statistics of the torrent.
+    ///
+    /// The trackers are tried in order until one of them answers, skipping the dead ones, see `TrackerHealth`.
+    /// If every tracker is dead, they are all tried anyway.
     ///
     /// On success it returns a `TrackerResponse` struct cointaining the tracker's response.
     ///
-    /// It returns an `TrackerHandlerError` if:
+    /// It returns the `TrackerHandlerError` of the last tracker tried if none of them answered, because:
     /// - There was a problem writing to the tracker.
     /// - There was a problem reading the tracker's response.
     /// - There was a problem decoding the parser response.
-    pub fn get_peers_list(&self) -> Result<TrackerResponse, TrackerHandlerError> {
+    /// - The tracker answered with a failure reason, see `FromTrackerResponseError::TrackerFailure`.
+    ///
+    /// A warning message in the response is logged. If the response has a tracker id, it is sent on the next
+    /// announces to that tracker.
+    pub fn get_peers_list(
+        &mut self,
+        stats: &TorrentStats,
+    ) -> Result<TrackerResponse, TrackerHandlerError> {
+        let now = SystemTime::now();
+        let mut order: Vec<usize> = (0..self.trackers.len())
+            .filter(|index| !self.trackers[*index].health.is_dead(now))
+            .collect();
+        if order.is_empty() {
+            order = (0..self.trackers.len()).collect();
+        }
+
+        let mut last_error = None;
+        for index in order {
+            match self.announce(index, stats) {
+                Ok(tracker_response) => {
+                    let tracker = &mut self.trackers[index];
+                    tracker.health.response_received(SystemTime::now());
+                    if tracker_response.tracker_id.is_some() {
+                        tracker.tracker_id.clone_from(&tracker_response.tracker_id);
+                    }
+                    return Ok(tracker_response);
+                }
+                Err(err) => {
+                    let tracker = &mut self.trackers[index];
+                    warn!("Announce to {} failed: {:?}", tracker.health.url, err);
+                    tracker
+                        .health
+                        .announce_failed(format!("{:?}", err), SystemTime::now());
+                    last_error = Some(err);
+                }
+            }
+        }
+        // There is always at least one tracker, so one of them failed.
+        Err(last_error.unwrap_or(TrackerHandlerError::UrlParseError(
+            TrackerUrlError::InvalidTrackerURL,
+        )))
+    }
+
+    /// Announces to the trackers that answered before that the torrent stopped, so they remove the client from
+    /// its swarm.
+    ///
+    /// The responses are not needed, so the trackers that do not answer are only logged.
+    pub fn announce_stopped(&mut self, stats: &TorrentStats) {
+        for index in 0..self.trackers.len() {
+            if self.trackers[index].health.last_response.is_none() {
+                continue;
+            }
+            if let Err(err) = self.send_announce(index, stats, AnnounceEvent::Stopped) {
+                warn!(
+                    "Stopped announce to {} failed: {:?}",
+                    self.trackers[index].health.url, err
+                );
+            }
+        }
+    }
+
+    /// Announces to a single tracker.
+    fn announce(
+        &mut self,
+        index: usize,
+        stats: &TorrentStats,
+    ) -> Result<TrackerResponse, TrackerHandlerError> {
+        let response = self.send_announce(index, stats, AnnounceEvent::Started)?;
+        let tracker_response = TrackerResponse::from(response)
+            .map_err(TrackerHandlerError::FromTrackerResponseError)?;
+        if let Some(warning) = &tracker_response.warning_message {
+            warn!("Tracker warning: {}", warning);
+        }
+        Ok(tracker_response)
+    }
+
+    /// Sends an announce with `event` to a single tracker and returns the body of its response.
+    ///
+    /// The bytes of the announce are added to the health of the tracker, whether it answered or not.
+    fn send_announce(
+        &mut self,
+        index: usize,
+        stats: &TorrentStats,
+        event: AnnounceEvent,
+    ) -> Result<Vec<u8>, TrackerHandlerError> {
+        let tracker = &self.trackers[index];
         let query_params = QueryParams::new(
-            self.torrent.info_hash.clone(),
+            self.torrent.info_hash,
             self.client_port,
-            self.torrent.info.length,
+            stats.uploaded_bytes,
+            stats.downloaded_bytes,
+            stats.left_bytes,
             self.client_peer_id.clone(),
-        );
+        )
+        .with_client_ip(self.client_ip)
+        .with_numwant(self.numwant)
+        .with_tracker_id(tracker.tracker_id.clone())
+        .with_event(event);
 
-        let http_handler = HttpHandler::new(self.tracker_url.clone(), query_params);
+        let http_handler =
+            HttpHandler::new(tracker.url.clone(), query_params).with_proxy(self.proxy.clone());
 
-        let response = if self.tracker_url.protocol == ConnectionProtocol::Https {
-            match http_handler.https_request() {
-                Ok(response) => response,
-                Err(err) => return Err(TrackerHandlerError::HttpHandlerError(err)),
-            }
+        let response = if tracker.url.protocol == ConnectionProtocol::Https {
+            http_handler.https_request()
         } else {
-            match http_handler.http_request() {
-                Ok(response) => response,
-                Err(err) => return Err(TrackerHandlerError::HttpHandlerError(err)),
-            }
+            http_handler.http_request()
         };
-        match TrackerResponse::from(response) {
-            Ok(tracker_response) => Ok(tracker_response),
-            Err(err) => Err(TrackerHandlerError::FromTrackerResponseError(err)),
-        }
+        let (sent_bytes, received_bytes) = http_handler.traffic();
+        self.trackers[index]
+            .health
+            .add_traffic(sent_bytes, received_bytes);
+        response.map_err(TrackerHandlerError::HttpHandlerError)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::torrent_parser::info::Info;
+    use std::net::{SocketAddrV4, TcpListener};
+
+    use crate::test_support::{
+        fixtures::test_torrent,
+        mock_tracker::{failure_response, peers_response, MockTracker},
+    };
 
     use super::*;
 
     #[test]
     fn test_get_peers_list() {
-        let torrent = create_test_torrent(
-            "https://torrent.ubuntu.com:443/announce",
-            "e82753b6692c4f3f3646b055f70ee390309020e6",
-        );
+        let info_hash =
+            url_encoder::info_hash::InfoHash::from_hex("e82753b6692c4f3f3646b055f70ee390309020e6")
+                .unwrap();
+        let peers = [SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 6881)];
+        let tracker = MockTracker::start(peers_response(900, &peers));
+        let torrent = Torrent {
+            info_hash,
+            ..test_torrent("test", &tracker.announce_url())
+        };
         let test_port = 6969;
         let test_peer_id = "-qB4500-k51bMCWVA(~!".to_string();
 
-        let tracker_handler = TrackerHandler::new(torrent, test_port, test_peer_id).unwrap();
+        let mut tracker_handler = TrackerHandler::new(torrent, test_port, test_peer_id).unwrap();
 
-        assert!(!tracker_handler.get_peers_list().unwrap().peers.is_empty());
+        assert!(!tracker_handler
+            .get_peers_list(&create_test_stats())
+            .unwrap()
+            .peers
+            .is_empty());
+        assert!(tracker.requests()[0].starts_with(&format!(
+            "GET /announce?info_hash={}&",
+            info_hash.url_encoded()
+        )));
     }
 
     #[test]
     fn test_http_request() {
-        let torrent = create_test_torrent(
-            "http://vps02.net.orel.ru/announce",
-            "f834824904be1854c89ba007c01678ff797f8dc7",
-        );
+        let peers = [
+            SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 6881),
+            SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 51413),
+        ];
+        let tracker = MockTracker::start(peers_response(900, &peers));
+        let torrent = test_torrent("test", &tracker.announce_url());
         let test_port = 6969;
         let test_peer_id = "-qB4500-k51bMCWVA(~!".to_string();
 
-        let tracker_handler = TrackerHandler::new(torrent, test_port, test_peer_id).unwrap();
+        let mut tracker_handler = TrackerHandler::new(torrent, test_port, test_peer_id).unwrap();
+        let response = tracker_handler
+            .get_peers_list(&create_test_stats())
+            .unwrap();
 
-        assert!(!tracker_handler.get_peers_list().unwrap().peers.is_empty());
+        assert_eq!(response.interval, 900);
+        assert_eq!(response.complete, 2);
+        let peers: Vec<(String, i64)> = response
+            .peers
+            .into_iter()
+            .map(|peer| (peer.ip, peer.port))
+            .collect();
+        assert_eq!(
+            peers,
+            vec![
+                ("10.0.0.1".to_string(), 6881),
+                ("10.0.0.2".to_string(), 51413)
+            ]
+        );
+        assert!(tracker.requests()[0].contains("&port=6969&uploaded=0&downloaded=0&left=100"));
     }
 
-    // Auxiliar
+    #[test]
+    fn test_failure_response_is_an_error() {
+        let tracker = MockTracker::start(failure_response("unregistered torrent"));
+        let torrent = test_torrent("test", &tracker.announce_url());
+
+        let mut tracker_handler =
+            TrackerHandler::new(torrent, 6969, "test_peer_id".to_string()).unwrap();
+
+        match tracker_handler.get_peers_list(&create_test_stats()) {
+            Err(TrackerHandlerError::FromTrackerResponseError(
+                FromTrackerResponseError::TrackerFailure(reason),
+            )) => assert_eq!(reason, "unregistered torrent"),
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tracker_id_is_sent_back() {
+        let tracker = MockTracker::start(b"d8:intervali900e5:peers0:10:tracker id2:ide".to_vec());
+        let torrent = test_torrent("test", &tracker.announce_url());
+
+        let mut tracker_handler =
+            TrackerHandler::new(torrent, 6969, "test_peer_id".to_string()).unwrap();
+        tracker_handler.set_numwant(Some(20));
+        tracker_handler
+            .get_peers_list(&create_test_stats())
+            .unwrap();
+        tracker_handler
+            .get_peers_list(&create_test_stats())
+            .unwrap();
+
+        let requests = tracker.requests();
+        assert!(requests[0].contains("&numwant=20"));
+        assert!(!requests[0].contains("trackerid"));
+        assert!(requests[1].contains("&numwant=20&trackerid=id"));
+    }
+
+    #[test]
+    fn test_fails_over_to_the_next_tracker() {
+        let closed_port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let dead_url = format!("http://127.0.0.1:{}/announce", closed_port);
+        let peers = [SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 6881)];
+        let tracker = MockTracker::start(peers_response(900, &peers));
+        let torrent = Torrent {
+            announce_list: vec![vec![dead_url.clone()], vec![tracker.announce_url()]],
+            ..test_torrent("test", &dead_url)
+        };
+
+        let mut tracker_handler =
+            TrackerHandler::new(torrent, 6969, "test_peer_id".to_string()).unwrap();
+        for _ in 0..4 {
+            let response = tracker_handler
+                .get_peers_list(&create_test_stats())
+                .unwrap();
+            assert_eq!(response.peers.len(), 1);
+        }
+
+        let health = tracker_handler.health();
+        // The first tracker died after 3 errors and was skipped on the last announce.
+        assert_eq!(health[0].url, dead_url);
+        assert_eq!(health[0].error_streak, 3);
+        assert!(health[0].is_dead(SystemTime::now()));
+        assert_eq!(health[1].error_streak, 0);
+        assert!(health[1].last_response.is_some());
+        assert_eq!(tracker.requests().len(), 4);
+    }
+
+    #[test]
+    fn test_all_trackers_failing_is_an_error() {
+        let tracker = MockTracker::start(failure_response("unregistered torrent"));
+        let torrent = Torrent {
+            announce_list: vec![vec![tracker.announce_url(), "udp://tracker:80".to_string()]],
+            ..test_torrent("test", &tracker.announce_url())
+        };
+
+        let mut tracker_handler =
+            TrackerHandler::new(torrent, 6969, "test_peer_id".to_string()).unwrap();
+
+        assert!(tracker_handler
+            .get_peers_list(&create_test_stats())
+            .is_err());
+        assert_eq!(tracker_handler.health().len(), 1);
+        assert_eq!(tracker_handler.health()[0].error_streak, 1);
+    }
 
-    fn create_test_torrent(announce: &str, info_hash: &str) -> Torrent {
-        let info = Info {
-            length: 100,
-            name: "test".to_string(),
-            piece_length: 100,
-            pieces: vec![],
+    #[test]
+    fn test_stopped_is_announced_to_the_trackers_that_answered() {
+        let peers = [SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 6881)];
+        let tracker = MockTracker::start(peers_response(900, &peers));
+        let backup_tracker = MockTracker::start(peers_response(900, &peers));
+        let torrent = Torrent {
+            announce_list: vec![
+                vec![tracker.announce_url()],
+                vec![backup_tracker.announce_url()],
+            ],
+            ..test_torrent("test", &tracker.announce_url())
         };
 
-        Torrent {
-            announce_url: announce.to_string(),
-            info,
-            info_hash: info_hash.to_string(),
+        let mut tracker_handler =
+            TrackerHandler::new(torrent, 6969, "test_peer_id".to_string()).unwrap();
+        tracker_handler
+            .get_peers_list(&create_test_stats())
+            .unwrap();
+        tracker_handler.announce_stopped(&create_test_stats());
+
+        let requests = tracker.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains("&event=started"));
+        assert!(requests[1].contains("&event=stopped"));
+        assert!(backup_tracker.requests().is_empty());
+    }
+
+    #[test]
+    fn test_announce_bytes_are_added_to_the_health() {
+        let response = peers_response(900, &[]);
+        let tracker = MockTracker::start(response.clone());
+        let mut tracker_handler = TrackerHandler::new(
+            test_torrent("test", &tracker.announce_url()),
+            6969,
+            "test_peer_id".to_string(),
+        )
+        .unwrap();
+
+        tracker_handler
+            .get_peers_list(&create_test_stats())
+            .unwrap();
+        let health = tracker_handler.health();
+        assert!(health[0].sent_bytes > tracker.requests()[0].len() as u64);
+        // The response includes its status line and headers.
+        assert!(health[0].received_bytes > response.len() as u64);
+
+        tracker_handler.announce_stopped(&create_test_stats());
+        assert!(tracker_handler.health()[0].sent_bytes > health[0].sent_bytes);
+    }
+
+    // Auxiliar
+
+    fn create_test_stats() -> TorrentStats {
+        TorrentStats {
+            left_bytes: 100,
+            total_pieces: 1,
+            ..Default::default()
         }
     }
 }
@@ -0,0 +1,119 @@
+use std::time::{Duration, SystemTime};
+
+/// Consecutive failed announces after which a tracker is considered dead.
+const DEAD_TRACKER_ERRORS: u32 = 3;
+
+/// Cool-down of a tracker that just died, doubled on each failed announce after that.
+const DEAD_TRACKER_COOLDOWN_SECS: u64 = 60;
+const MAX_DEAD_TRACKER_COOLDOWN_SECS: u64 = 60 * 60;
+
+/// Health of a tracker of a torrent, given by the announces sent to it.
+///
+/// - `error_streak`: number of consecutive failed announces,
+/// - `last_response`: when the tracker last answered an announce with peers,
+/// - `last_error`: error of the last announce, if it failed,
+/// - `dead_until`: a dead tracker is not announced to until its cool-down ends,
+/// - `sent_bytes`: bytes of the requests of every announce sent to the tracker,
+/// - `received_bytes`: bytes of the responses of the tracker, as received.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackerHealth {
+    pub url: String,
+    pub error_streak: u32,
+    pub last_response: Option<SystemTime>,
+    pub last_error: Option<String>,
+    pub dead_until: Option<SystemTime>,
+    pub sent_bytes: u64,
+    pub received_bytes: u64,
+}
+
+impl TrackerHealth {
+    /// Creates the health of a tracker that was not announced to yet.
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            error_streak: 0,
+            last_response: None,
+            last_error: None,
+            dead_until: None,
+            sent_bytes: 0,
+            received_bytes: 0,
+        }
+    }
+
+    /// Returns true if the tracker is in its cool-down at `now`.
+    pub fn is_dead(&self, now: SystemTime) -> bool {
+        self.dead_until.is_some_and(|dead_until| now < dead_until)
+    }
+
+    /// Records an announce answered by the tracker, which makes it healthy again.
+    pub fn response_received(&mut self, now: SystemTime) {
+        self.error_streak = 0;
+        self.last_response = Some(now);
+        self.last_error = None;
+        self.dead_until = None;
+    }
+
+    /// Adds the bytes of an announce, answered or not.
+    pub fn add_traffic(&mut self, sent_bytes: u64, received_bytes: u64) {
+        self.sent_bytes += sent_bytes;
+        self.received_bytes += received_bytes;
+    }
+
+    /// Records a failed announce.
+    ///
+    /// After `DEAD_TRACKER_ERRORS` consecutive failures the tracker is dead for a cool-down that grows with each
+    /// further failure.
+    pub fn announce_failed(&mut self, error: String, now: SystemTime) {
+        self.error_streak += 1;
+        self.last_error = Some(error);
+        if self.error_streak >= DEAD_TRACKER_ERRORS {
+            let doublings = (self.error_streak - DEAD_TRACKER_ERRORS).min(16);
+            let cooldown =
+                (DEAD_TRACKER_COOLDOWN_SECS << doublings).min(MAX_DEAD_TRACKER_COOLDOWN_SECS);
+            self.dead_until = Some(now + Duration::from_secs(cooldown));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker_dies_after_consecutive_errors() {
+        let now = SystemTime::now();
+        let mut health = TrackerHealth::new("http://tracker/announce".to_string());
+
+        health.announce_failed("timeout".to_string(), now);
+        health.announce_failed("timeout".to_string(), now);
+        assert!(!health.is_dead(now));
+
+        health.announce_failed("refused".to_string(), now);
+        assert!(health.is_dead(now));
+        assert!(!health.is_dead(now + Duration::from_secs(DEAD_TRACKER_COOLDOWN_SECS)));
+        assert_eq!(health.error_streak, 3);
+        assert_eq!(health.last_error, Some("refused".to_string()));
+
+        health.announce_failed("refused".to_string(), now);
+        assert_eq!(
+            health.dead_until,
+            Some(now + Duration::from_secs(2 * DEAD_TRACKER_COOLDOWN_SECS))
+        );
+    }
+
+    #[test]
+    fn test_response_makes_the_tracker_healthy() {
+        let now = SystemTime::now();
+        let mut health = TrackerHealth::new("http://tracker/announce".to_string());
+        for _ in 0..5 {
+            health.announce_failed("timeout".to_string(), now);
+        }
+
+        health.response_received(now);
+
+        assert!(!health.is_dead(now));
+        assert_eq!(health.error_streak, 0);
+        assert_eq!(health.last_response, Some(now));
+        assert_eq!(health.last_error, None);
+    }
+}
@@ -1,133 +1,122 @@
-use bencoder::bencode::{Bencode, BencodeError};
+use bencoder::{bencode::Bencode, SerdeError};
+use serde::{de, Deserialize, Deserializer};
 
 use crate::peer::bt_peer::{BtPeer, BtPeerError};
 
 /// `TrackerResponse` struct containing a tracker response.
 ///
 /// To create a new `TrackerResponse` use the method builder `from()`.
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
 pub struct TrackerResponse {
+    #[serde(default)]
     pub interval: i64,
+    #[serde(default)]
     pub complete: i64,
+    #[serde(default)]
     pub incomplete: i64,
+    #[serde(default, deserialize_with = "deserialize_peers")]
     pub peers: Vec<BtPeer>,
+    #[serde(
+        default,
+        rename = "failure reason",
+        deserialize_with = "deserialize_message"
+    )]
+    failure_reason: Option<String>,
+    #[serde(
+        default,
+        rename = "warning message",
+        deserialize_with = "deserialize_message"
+    )]
+    pub warning_message: Option<String>,
+    /// Id the tracker wants to receive back on the next announces.
+    #[serde(
+        default,
+        rename = "tracker id",
+        deserialize_with = "deserialize_tracker_id"
+    )]
+    pub tracker_id: Option<Vec<u8>>,
 }
 
 /// Posible `TrackerResponse` errors.
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum FromTrackerResponseError {
-    DecodeResponseError(BencodeError),
-    InvalidInterval,
-    InvalidComplete,
-    InvalidIncomplete,
-    InvalidPeers(BtPeerError),
-    NotADict,
-    NotAList,
+    DecodeResponseError(SerdeError),
+    /// The tracker rejected the announce, with the reason it gave.
+    TrackerFailure(String),
 }
 
 impl TrackerResponse {
     /// Builds a new `TrackerResponse` decoding a bencoded Vec<u8> cointaining the tracker's response.
     ///
-    /// It returns an `FromTrackerResponseError` if:
-    /// - There was a problem decoding the parser response.
-    /// - The bencoded response is not a dict.
-    /// - The bencoded peers are not a list.
-    /// - The tracker response interval is invalid.
-    /// - The tracker response complete is invalid.
-    /// - The tracker response incomplete is invalid.
-    /// - The tracker response peers are invalid.
+    /// Missing keys are left with their default values.
+    ///
+    /// It returns a `DecodeResponseError` if:
+    /// - The response is not valid bencode or not a dict.
+    /// - The interval, complete or incomplete are not numbers.
+    /// - The peers are neither a list of dicts nor a compact string.
+    ///
+    /// It returns a `TrackerFailure` if the response has a `failure reason`, in which case the rest of the keys are
+    /// ignored.
     pub fn from(response: Vec<u8>) -> Result<TrackerResponse, FromTrackerResponseError> {
-        let mut interval = 0;
-        let mut complete = 0;
-        let mut incomplete = 0;
-        let mut peers = Vec::new();
-
-        let decoded_res = match Bencode::decode(&response) {
-            Ok(decoded_res) => decoded_res,
-            Err(err) => return Err(FromTrackerResponseError::DecodeResponseError(err)),
-        };
-
-        let d = match decoded_res {
-            Bencode::BDict(d) => d,
-            _ => return Err(FromTrackerResponseError::NotADict),
-        };
-
-        for (k, v) in d.iter() {
-            if k == b"interval" {
-                interval = Self::create_interval(v)?;
-            } else if k == b"complete" {
-                complete = Self::create_complete(v)?;
-            } else if k == b"incomplete" {
-                incomplete = Self::create_incomplete(v)?;
-            } else if k == b"peers" {
-                peers = Self::create_peers(v)?;
-            }
-        }
-
-        Ok(TrackerResponse {
-            interval,
-            complete,
-            incomplete,
-            peers,
-        })
-    }
-
-    fn create_interval(bencode: &Bencode) -> Result<i64, FromTrackerResponseError> {
-        let interval = match bencode {
-            Bencode::BNumber(n) => *n,
-            _ => return Err(FromTrackerResponseError::InvalidInterval),
-        };
-
-        Ok(interval)
-    }
-
-    fn create_complete(bencode: &Bencode) -> Result<i64, FromTrackerResponseError> {
-        let complete = match bencode {
-            Bencode::BNumber(n) => *n,
-            _ => return Err(FromTrackerResponseError::InvalidComplete),
-        };
-
-        Ok(complete)
-    }
-
-    fn create_incomplete(bencode: &Bencode) -> Result<i64, FromTrackerResponseError> {
-        let incomplete = match bencode {
-            Bencode::BNumber(n) => *n,
-            _ => return Err(FromTrackerResponseError::InvalidIncomplete),
-        };
-
-        Ok(incomplete)
-    }
+        let response: TrackerResponse = bencoder::from_bytes(&response)
+            .map_err(FromTrackerResponseError::DecodeResponseError)?;
 
-    fn create_peers(bencode: &Bencode) -> Result<Vec<BtPeer>, FromTrackerResponseError> {
-        match bencode {
-            Bencode::BList(list) => Self::create_peers_from_dict(list),
-            Bencode::BString(str) => Self::create_peers_from_bstring(str),
-            _ => Err(FromTrackerResponseError::NotAList),
+        match response.failure_reason {
+            Some(reason) => Err(FromTrackerResponseError::TrackerFailure(reason)),
+            None => Ok(response),
         }
     }
 
-    fn create_peers_from_dict(list: &[Bencode]) -> Result<Vec<BtPeer>, FromTrackerResponseError> {
-        let mut peers = Vec::new();
-
-        for p in list {
-            let peer = BtPeer::from(p.clone()).map_err(FromTrackerResponseError::InvalidPeers)?;
-            peers.push(peer);
-        }
-
-        Ok(peers)
+    fn create_peers_from_dict(list: &[Bencode]) -> Result<Vec<BtPeer>, BtPeerError> {
+        list.iter().cloned().map(BtPeer::from).collect()
     }
 
-    fn create_peers_from_bstring(bstring: &[u8]) -> Result<Vec<BtPeer>, FromTrackerResponseError> {
-        Ok(bstring
-            .chunks(6)
+    /// Peers in the compact model are 6 bytes each, 4 for the IP and 2 for the port.
+    fn create_peers_from_bstring(bstring: &[u8]) -> Vec<BtPeer> {
+        bstring
+            .chunks_exact(6)
             .map(|chunk| {
                 let ip = format!("{}.{}.{}.{}", chunk[0], chunk[1], chunk[2], chunk[3]);
                 let port = u16::from_be_bytes([chunk[4], chunk[5]]) as i64;
 
                 BtPeer::new(ip, port)
             })
-            .collect())
+            .collect()
+    }
+}
+
+/// Deserializes the peers from either the dictionary or the compact model.
+fn deserialize_peers<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<BtPeer>, D::Error> {
+    let peers = Bencode::deserialize(deserializer)?;
+    if let Some(list) = peers.as_list() {
+        TrackerResponse::create_peers_from_dict(list)
+            .map_err(|err| de::Error::custom(format!("invalid peer: {:?}", err)))
+    } else if let Some(bstring) = peers.as_bytes() {
+        Ok(TrackerResponse::create_peers_from_bstring(bstring))
+    } else {
+        Err(de::Error::custom("peers must be a list or a string"))
+    }
+}
+
+/// Deserializes a message of the tracker, replacing the bytes that are not valid UTF-8.
+fn deserialize_message<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let message = Bencode::deserialize(deserializer)?;
+    match message.as_bytes() {
+        Some(bytes) => Ok(Some(String::from_utf8_lossy(bytes).into_owned())),
+        None => Err(de::Error::custom("tracker messages must be a string")),
+    }
+}
+
+/// Deserializes the tracker id, which can be arbitrary bytes.
+fn deserialize_tracker_id<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<u8>>, D::Error> {
+    let tracker_id = Bencode::deserialize(deserializer)?;
+    match tracker_id.as_bytes() {
+        Some(bytes) => Ok(Some(bytes.to_vec())),
+        None => Err(de::Error::custom("tracker id must be a string")),
     }
 }
 
@@ -158,6 +147,65 @@ mod tests {
         assert_eq!(response_decoded.peers.len(), 2);
     }
 
+    #[test]
+    fn test_from_tracker_response_with_compact_peers() {
+        let mut response = b"d8:intervali1800e5:peers12:".to_vec();
+        response.extend([127, 0, 0, 1, 0x1a, 0xe1, 10, 0, 0, 2, 0x1a, 0xe2]);
+        response.push(b'e');
+
+        let response_decoded = TrackerResponse::from(response).unwrap();
+
+        assert_eq!(response_decoded.interval, 1800);
+        assert_eq!(response_decoded.complete, 0);
+        assert_eq!(response_decoded.tracker_id, None);
+        assert_eq!(
+            response_decoded.peers,
+            vec![
+                BtPeer::new("127.0.0.1".to_string(), 6881),
+                BtPeer::new("10.0.0.2".to_string(), 6882)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_tracker_response_with_invalid_peers() {
+        let response = b"d8:intervali1800e5:peersi3ee".to_vec();
+
+        assert!(TrackerResponse::from(response).is_err());
+    }
+
+    #[test]
+    fn test_from_tracker_response_with_failure_reason() {
+        let response = b"d14:failure reason20:unregistered torrent8:intervali1800ee".to_vec();
+
+        assert_eq!(
+            TrackerResponse::from(response).unwrap_err(),
+            FromTrackerResponseError::TrackerFailure("unregistered torrent".to_string())
+        );
+    }
+
+    #[test]
+    fn test_from_tracker_response_with_warning_message() {
+        let response = b"d8:intervali1800e5:peers0:15:warning message10:slow down\xffe".to_vec();
+
+        let response_decoded = TrackerResponse::from(response).unwrap();
+
+        assert_eq!(response_decoded.interval, 1800);
+        assert_eq!(
+            response_decoded.warning_message,
+            Some("slow down\u{fffd}".to_string())
+        );
+    }
+
+    #[test]
+    fn test_from_tracker_response_with_tracker_id() {
+        let response = b"d8:intervali1800e5:peers0:10:tracker id3:\x01abe".to_vec();
+
+        let response_decoded = TrackerResponse::from(response).unwrap();
+
+        assert_eq!(response_decoded.tracker_id, Some(b"\x01ab".to_vec()));
+    }
+
     fn build_peer_dict(peer_id: Vec<u8>, ip: Vec<u8>, port: i64) -> BTreeMap<Vec<u8>, Bencode> {
         let mut peer_dict = BTreeMap::new();
         peer_dict.insert(b"peer id".to_vec(), Bencode::BString(peer_id));
@@ -0,0 +1,84 @@
+//! Measures the announces per second the tracker status handles from several threads at once, with a single
+//! lock over every swarm and with the swarms split into the default amount of shards.
+//!
+//! Run it with `cargo bench --package dtracker`.
+
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::{Duration as ChronoDuration, Local};
+use dtracker::{
+    tracker_peer::{peer::Peer, peer_status::PeerStatus},
+    tracker_status::atomic_tracker_status::{AtomicTrackerStatus, TrackerTimeouts, DEFAULT_SHARDS},
+};
+use url_encoder::info_hash::InfoHash;
+
+const THREADS: usize = 8;
+const ANNOUNCES_PER_THREAD: usize = 50_000;
+const TORRENTS: usize = 1_000;
+const PEERS_PER_TORRENT: usize = 20;
+
+fn main() {
+    let single_lock = announces_per_second(1);
+    let sharded = announces_per_second(DEFAULT_SHARDS);
+
+    println!("1 shard: {:.0} announces/s", single_lock);
+    println!("{} shards: {:.0} announces/s", DEFAULT_SHARDS, sharded);
+    println!("Speedup: {:.2}x", sharded / single_lock);
+}
+
+fn announces_per_second(shards: usize) -> f64 {
+    let timeouts = TrackerTimeouts {
+        min_announce_interval: ChronoDuration::zero(),
+        ..TrackerTimeouts::default()
+    };
+    let status = Arc::new(AtomicTrackerStatus::with_shards(timeouts, shards));
+
+    let start = Instant::now();
+    let handles: Vec<_> = (0..THREADS)
+        .map(|thread| {
+            let status = status.clone();
+            thread::spawn(move || {
+                for announce in 0..ANNOUNCES_PER_THREAD {
+                    let n = thread * ANNOUNCES_PER_THREAD + announce;
+                    let peer_id = (n % PEERS_PER_TORRENT) as u8;
+                    status
+                        .incoming_peer(info_hash(n % TORRENTS), peer(peer_id), 50)
+                        .expect("announces without min interval are always accepted");
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().expect("announce thread panicked");
+    }
+
+    let elapsed: Duration = start.elapsed();
+    (THREADS * ANNOUNCES_PER_THREAD) as f64 / elapsed.as_secs_f64()
+}
+
+/// Spreads the torrents like SHA-1 hashes would, the first bytes decide the shard.
+fn info_hash(torrent: usize) -> InfoHash {
+    let mut bytes = [0; 20];
+    bytes[..8].copy_from_slice(
+        &(torrent as u64)
+            .wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            .to_be_bytes(),
+    );
+    InfoHash::new(bytes)
+}
+
+fn peer(id: u8) -> Peer {
+    let status = PeerStatus {
+        uploaded: 0,
+        downloaded: 0,
+        left: 1,
+        corrupt: 0,
+        event: None,
+        last_seen: Local::now(),
+    };
+    Peer::new([id; 20], "127.0.0.1".to_string(), 6881, None, status)
+}
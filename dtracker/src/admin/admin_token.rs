@@ -0,0 +1,67 @@
+/// The token required by the admin endpoints of the tracker.
+///
+/// ## Fields
+/// * `token`: The expected token. If `None`, the admin endpoints are disabled.
+#[derive(Debug, Default, Clone)]
+pub struct AdminToken {
+    token: Option<String>,
+}
+
+impl AdminToken {
+    /// Creates an admin token that disables the admin endpoints.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Creates an admin token that allows the admin endpoints to requests sending `token`.
+    pub fn new(token: String) -> Self {
+        Self { token: Some(token) }
+    }
+
+    /// Returns `true` if `token` allows using the admin endpoints.
+    ///
+    /// The comparison takes the same time wherever the tokens differ, so the token can not be guessed byte by byte.
+    pub fn is_valid(&self, token: &str) -> bool {
+        match &self.token {
+            Some(admin_token) => constant_time_eq(admin_token.as_bytes(), token.as_bytes()),
+            None => false,
+        }
+    }
+
+    /// Returns `true` if the value of an `Authorization` header carries the token as `Bearer <token>`.
+    pub fn is_authorized(&self, authorization: Option<&str>) -> bool {
+        authorization
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+            .is_some_and(|token| self.is_valid(token.trim()))
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admin_token() {
+        let admin_token = AdminToken::new("secret".to_string());
+
+        assert!(admin_token.is_valid("secret"));
+        assert!(!admin_token.is_valid("guess"));
+        assert!(!admin_token.is_valid("secre"));
+        assert!(!admin_token.is_valid("secret2"));
+        assert!(!AdminToken::disabled().is_valid(""));
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let admin_token = AdminToken::new("secret".to_string());
+
+        assert!(admin_token.is_authorized(Some("Bearer secret")));
+        assert!(!admin_token.is_authorized(Some("secret")));
+        assert!(!admin_token.is_authorized(Some("Bearer guess")));
+        assert!(!admin_token.is_authorized(None));
+    }
+}
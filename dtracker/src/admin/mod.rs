@@ -0,0 +1,2 @@
+pub mod admin_token;
+pub mod swarm_peers_response;
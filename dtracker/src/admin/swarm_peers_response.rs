@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use url_encoder::{info_hash::InfoHash, url_encoder::encode_bytes};
+
+use crate::{
+    http::query_params::QueryParams,
+    tracker_peer::{ip_policy::IpSource, peer::Peer},
+    tracker_status::atomic_tracker_status::AtomicTrackerStatus,
+};
+
+/// Struct that represents the response of the admin request for the peers of a swarm.
+///
+/// ## Fields
+/// * `info_hash`: The hex encoded info hash of the torrent.
+/// * `peers`: Every peer of the swarm, active or not.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SwarmPeersResponse {
+    pub info_hash: String,
+    pub peers: Vec<SwarmPeer>,
+}
+
+/// A peer of a swarm, as shown to the admins.
+///
+/// ## Fields
+/// * `peer_id`: The percent-encoded id of the peer, as expected by the other admin endpoints.
+/// * `ip`: The ip of the peer.
+/// * `port`: The port of the peer.
+/// * `uploaded`: The bytes uploaded by the peer, as of its last announce.
+/// * `downloaded`: The bytes downloaded by the peer, as of its last announce.
+/// * `left`: The bytes the peer has left to download, as of its last announce.
+/// * `corrupt`: The bytes the peer discarded for failing the hash check, as of its last announce.
+/// * `last_seen`: The RFC 3339 time of the last announce of the peer.
+/// * `announced_ip`: `true` if the ip was taken from the `ip` parameter of the announce.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SwarmPeer {
+    pub peer_id: String,
+    pub ip: String,
+    pub port: u16,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub left: u64,
+    pub corrupt: u64,
+    pub last_seen: String,
+    pub announced_ip: bool,
+}
+
+/// Posible swarm peers request errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SwarmPeersResponseError {
+    InvalidInfoHash,
+    UnknownTorrent,
+}
+
+impl SwarmPeersResponse {
+    /// Creates a new `SwarmPeersResponse` from the query parameters, expecting the hex encoded `info_hash` of the torrent.
+    pub fn from(
+        query_params: &QueryParams,
+        tracker_status: Arc<AtomicTrackerStatus>,
+    ) -> Result<Self, SwarmPeersResponseError> {
+        let info_hash = query_params
+            .get("info_hash")
+            .and_then(|info_hash| InfoHash::from_hex(info_hash).ok())
+            .ok_or(SwarmPeersResponseError::InvalidInfoHash)?;
+        let peers = tracker_status
+            .swarm_peers(&info_hash)
+            .ok_or(SwarmPeersResponseError::UnknownTorrent)?;
+
+        Ok(Self {
+            info_hash: info_hash.to_hex(),
+            peers: peers.iter().map(SwarmPeer::from).collect(),
+        })
+    }
+}
+
+impl From<&Peer> for SwarmPeer {
+    fn from(peer: &Peer) -> Self {
+        Self {
+            peer_id: encode_bytes(&peer.id),
+            ip: peer.ip.clone(),
+            port: peer.port,
+            uploaded: peer.status.uploaded,
+            downloaded: peer.status.downloaded,
+            left: peer.status.left,
+            corrupt: peer.status.corrupt,
+            last_seen: peer.get_last_seen().to_rfc3339(),
+            announced_ip: peer.ip_source == IpSource::Announced,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tracker_peer::peer_status::PeerStatus;
+
+    use super::*;
+
+    #[test]
+    fn test_swarm_peers() {
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+        let info_hash = InfoHash::new([0; 20]);
+        let status = PeerStatus::new(10, 20, 30, None);
+        let peer = Peer::new(
+            *b"-DT0001-a b/00000000",
+            "10.0.0.1".into(),
+            6881,
+            None,
+            status,
+        );
+        tracker_status.incoming_peer(info_hash, peer, 50).unwrap();
+
+        let mut params = QueryParams::default();
+        params.insert("info_hash", info_hash.to_hex().as_bytes());
+        let response = SwarmPeersResponse::from(&params, tracker_status.clone()).unwrap();
+
+        assert_eq!(response.peers.len(), 1);
+        assert_eq!(response.peers[0].peer_id, "-DT0001-a%20b%2F00000000");
+        assert_eq!(response.peers[0].ip, "10.0.0.1");
+        assert_eq!(response.peers[0].left, 30);
+
+        params.insert("info_hash", InfoHash::new([1; 20]).to_hex().as_bytes());
+        assert_eq!(
+            SwarmPeersResponse::from(&params, tracker_status).unwrap_err(),
+            SwarmPeersResponseError::UnknownTorrent
+        );
+    }
+}
@@ -1,18 +1,19 @@
-use std::{collections::HashMap, num::ParseIntError, str::FromStr};
+use std::str::FromStr;
 
 use super::announce_request_error::AnnounceRequestError;
-use crate::tracker_peer::event::PeerEvent;
-use url_encoder::url_encoder::decode;
+use crate::{http::query_params::QueryParams, tracker_peer::event::PeerEvent};
+use url_encoder::info_hash::InfoHash;
 
 /// Struct representing the announce request to a tracker.
 ///
 /// # Fields
-/// * `info_hash`: urlencoded 20-byte SHA1 hash of the value of the info key from the Metainfo file.
-/// * `peer_id`: urlencoded 20-byte string used as a unique ID for the client, generated by the client at startup.
+/// * `info_hash`: 20-byte SHA1 hash of the value of the info key from the Metainfo file.
+/// * `peer_id`: 20-byte string used as a unique ID for the client, generated by the client at startup.
 /// * `port`: The port number the client is listening on.
 /// * `uploaded`: The total amount of data uploaded by the client, in bytes.
 /// * `downloaded`: The total amount of data downloaded by the client, in bytes.
 /// * `left`: The total size of the file, in bytes, that the client still has to download.
+/// * `corrupt`: The amount of data, in bytes, the client discarded because it failed the hash check. Zero if absent.
 /// * `compact`: If true, the response will be in compact format.
 /// * `no_peer_id`: If true, the response will not include the peer id.
 /// * `event`: *(Optional)* The event that the client is reporting. Possible values are:
@@ -20,49 +21,49 @@ use url_encoder::url_encoder::decode;
 ///     * `stopped`: The client has just stopped.
 ///     * `completed`: The client has just successfully downloaded the file.
 /// * `ip`: *(Optional)* The IP address of the client. If not present, the IP address of the client will be determined automatically.
-/// * `numwant`: The number of peers that the client would like to receive in the response. If absent, the client requests a default number of peers.
-/// * `key`: *(Optional)* The key used to identify the client. If absent, the client will be identified by its peer id.
-/// * `trackerid`: *(Optional)* The id of the tracker. If absent, the tracker will be identified by its IP address.
+/// * `numwant`: *(Optional)* The number of peers that the client would like to receive in the response. If absent, the tracker sends its default number of peers. If zero, the response has no peers. Values over `u32::MAX` are saturated, the tracker clamps them to its maximum.
+/// * `key`: *(Optional)* A value only known by the client, that proves its identity if its IP changes or other clients share it. If absent, the client will be identified by its peer id.
+/// * `tracker_id`: *(Optional)* The `trackerid` parameter, the tracker id a previous announce response sent.
 #[derive(Debug, Clone)]
 pub struct AnnounceRequest {
-    pub info_hash: [u8; 20],
+    pub info_hash: InfoHash,
     pub peer_id: [u8; 20],
     pub port: u16,
     pub uploaded: u64,
     pub downloaded: u64,
     pub left: u64,
+    pub corrupt: u64,
     pub compact: bool,
     pub no_peer_id: bool,
     pub event: Option<PeerEvent>,
     pub ip: Option<String>,
-    pub numwant: u32,
+    pub numwant: Option<u32>,
     pub key: Option<String>,
     pub tracker_id: Option<String>,
 }
 
-const DEFAULT_NUMWANT: &str = "50";
-
 impl AnnounceRequest {
-    /// Creates a new AnnounceRequest from a HashMap containing the query parameters of the announce request.
+    /// Creates a new AnnounceRequest from the decoded query parameters of the announce request.
     ///
     /// If the request is invalid, an error is returned.
-    pub fn new_from(query_params: HashMap<String, String>) -> Result<Self, AnnounceRequestError> {
+    pub fn new_from(query_params: &QueryParams) -> Result<Self, AnnounceRequestError> {
         // Obligatory params:
-        let info_hash = Self::get_info_hash(&query_params)?;
-        let peer_id = Self::get_peer_id(&query_params)?;
-        let port = Self::get_port(&query_params)?;
-        let uploaded = Self::get_uploaded(&query_params)?;
-        let downloaded = Self::get_downloaded(&query_params)?;
-        let left = Self::get_left(&query_params)?;
+        let info_hash = Self::get_info_hash(query_params)?;
+        let peer_id = Self::get_peer_id(query_params)?;
+        let port = Self::get_port(query_params)?;
+        let uploaded = Self::get_uploaded(query_params)?;
+        let downloaded = Self::get_downloaded(query_params)?;
+        let left = Self::get_left(query_params)?;
 
         // Optional and default params:
-        let compact = Self::get_compact(&query_params);
-        let no_peer_id = Self::get_no_peer_id(&query_params);
-        let event = Self::get_event(&query_params)?;
-        let ip = Self::get_ip(&query_params);
-        let numwant = Self::get_numwant(&query_params)?;
-        let key = Self::get_key(&query_params);
-        let tracker_id = Self::get_tracker_id(&query_params);
+        let corrupt = Self::get_corrupt(query_params)?;
+        let compact = Self::get_compact(query_params);
+        let no_peer_id = Self::get_no_peer_id(query_params);
+        let event = Self::get_event(query_params)?;
+        let ip = Self::get_ip(query_params);
+        let numwant = Self::get_numwant(query_params)?;
+        let key = Self::get_key(query_params);
+        let tracker_id = Self::get_tracker_id(query_params);
 
         Ok(Self {
             info_hash,
@@ -71,6 +72,7 @@ impl AnnounceRequest {
             uploaded,
             downloaded,
             left,
+            corrupt,
             compact,
             no_peer_id,
             event,
@@ -81,45 +83,22 @@ impl AnnounceRequest {
         })
     }
 
-    fn get_info_hash(
-        query_params_map: &HashMap<String, String>,
-    ) -> Result<[u8; 20], AnnounceRequestError> {
-        let info_hash = query_params_map.get("info_hash").map_or_else(
-            || Err(AnnounceRequestError::InvalidInfoHash),
-            |i| {
-                Self::decode_hex(&decode(i))
-                    .map_err(|_| AnnounceRequestError::InvalidInfoHash)?
-                    .try_into()
-                    .map_err(|_| AnnounceRequestError::InvalidInfoHash)
-            },
-        )?;
-        Ok(info_hash)
-    }
-
-    fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
-        (0..s.len())
-            .step_by(2)
-            .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
-            .collect()
-    }
-
-    fn get_peer_id(
-        query_params_map: &HashMap<String, String>,
-    ) -> Result<[u8; 20], AnnounceRequestError> {
-        let peer_id = query_params_map.get("peer_id").map_or_else(
-            || Err(AnnounceRequestError::InvalidPeerId),
-            |i| {
-                Self::decode_hex(&decode(i))
-                    .map_err(|_| AnnounceRequestError::InvalidPeerId)?
-                    .try_into()
-                    .map_err(|_| AnnounceRequestError::InvalidPeerId)
-            },
-        )?;
-        Ok(peer_id)
+    fn get_info_hash(query_params: &QueryParams) -> Result<InfoHash, AnnounceRequestError> {
+        query_params
+            .get_bytes("info_hash")
+            .and_then(|info_hash| InfoHash::from_bytes(info_hash).ok())
+            .ok_or(AnnounceRequestError::InvalidInfoHash)
     }
 
-    fn get_port(query_params_map: &HashMap<String, String>) -> Result<u16, AnnounceRequestError> {
-        let port = query_params_map.get("port").map_or_else(
+    fn get_peer_id(query_params: &QueryParams) -> Result<[u8; 20], AnnounceRequestError> {
+        query_params
+            .get_bytes("peer_id")
+            .and_then(|peer_id| peer_id.try_into().ok())
+            .ok_or(AnnounceRequestError::InvalidPeerId)
+    }
+
+    fn get_port(query_params: &QueryParams) -> Result<u16, AnnounceRequestError> {
+        let port = query_params.get("port").map_or_else(
             || Err(AnnounceRequestError::InvalidPort),
             |p| {
                 p.parse::<u16>()
@@ -129,10 +108,8 @@ impl AnnounceRequest {
         Ok(port)
     }
 
-    fn get_uploaded(
-        query_params_map: &HashMap<String, String>,
-    ) -> Result<u64, AnnounceRequestError> {
-        let uploaded = query_params_map.get("uploaded").map_or_else(
+    fn get_uploaded(query_params: &QueryParams) -> Result<u64, AnnounceRequestError> {
+        let uploaded = query_params.get("uploaded").map_or_else(
             || Err(AnnounceRequestError::InvalidUploaded),
             |u| {
                 u.parse::<u64>()
@@ -142,10 +119,8 @@ impl AnnounceRequest {
         Ok(uploaded)
     }
 
-    fn get_downloaded(
-        query_params_map: &HashMap<String, String>,
-    ) -> Result<u64, AnnounceRequestError> {
-        let downloaded = query_params_map.get("downloaded").map_or_else(
+    fn get_downloaded(query_params: &QueryParams) -> Result<u64, AnnounceRequestError> {
+        let downloaded = query_params.get("downloaded").map_or_else(
             || Err(AnnounceRequestError::InvalidDownloaded),
             |d| {
                 d.parse::<u64>()
@@ -155,8 +130,8 @@ impl AnnounceRequest {
         Ok(downloaded)
     }
 
-    fn get_left(query_params_map: &HashMap<String, String>) -> Result<u64, AnnounceRequestError> {
-        let left = query_params_map.get("left").map_or_else(
+    fn get_left(query_params: &QueryParams) -> Result<u64, AnnounceRequestError> {
+        let left = query_params.get("left").map_or_else(
             || Err(AnnounceRequestError::InvalidLeft),
             |l| {
                 l.parse::<u64>()
@@ -166,21 +141,23 @@ impl AnnounceRequest {
         Ok(left)
     }
 
-    fn get_compact(query_params_map: &HashMap<String, String>) -> bool {
-        query_params_map.get("compact").unwrap_or(&"0".to_string()) == "1"
+    fn get_corrupt(query_params: &QueryParams) -> Result<u64, AnnounceRequestError> {
+        query_params.get("corrupt").map_or(Ok(0), |c| {
+            c.parse::<u64>()
+                .map_err(|_| AnnounceRequestError::InvalidCorrupt)
+        })
     }
 
-    fn get_no_peer_id(query_params_map: &HashMap<String, String>) -> bool {
-        query_params_map
-            .get("no_peer_id")
-            .unwrap_or(&"0".to_string())
-            == "1"
+    fn get_compact(query_params: &QueryParams) -> bool {
+        query_params.get("compact") == Some("1")
     }
 
-    fn get_event(
-        query_params_map: &HashMap<String, String>,
-    ) -> Result<Option<PeerEvent>, AnnounceRequestError> {
-        match query_params_map
+    fn get_no_peer_id(query_params: &QueryParams) -> bool {
+        query_params.get("no_peer_id") == Some("1")
+    }
+
+    fn get_event(query_params: &QueryParams) -> Result<Option<PeerEvent>, AnnounceRequestError> {
+        match query_params
             .get("event")
             .map(|e| PeerEvent::from_str(e).ok())
         {
@@ -192,25 +169,126 @@ impl AnnounceRequest {
         }
     }
 
-    fn get_ip(query_params_map: &HashMap<String, String>) -> Option<String> {
-        query_params_map.get("ip").map(|ip| ip.to_string())
+    fn get_ip(query_params: &QueryParams) -> Option<String> {
+        query_params.get("ip").map(|ip| ip.to_string())
     }
 
-    fn get_numwant(
-        query_params_map: &HashMap<String, String>,
-    ) -> Result<u32, AnnounceRequestError> {
-        query_params_map
+    fn get_numwant(query_params: &QueryParams) -> Result<Option<u32>, AnnounceRequestError> {
+        query_params
             .get("numwant")
-            .unwrap_or(&DEFAULT_NUMWANT.to_string())
-            .parse::<u32>()
-            .map_err(|_| AnnounceRequestError::InvalidNumwant)
+            .map(|numwant| {
+                numwant
+                    .parse::<u64>()
+                    .map(|numwant| numwant.min(u32::MAX as u64) as u32)
+                    .map_err(|_| AnnounceRequestError::InvalidNumwant)
+            })
+            .transpose()
+    }
+
+    fn get_key(query_params: &QueryParams) -> Option<String> {
+        query_params.get("key").map(|s| s.to_string())
+    }
+
+    fn get_tracker_id(query_params: &QueryParams) -> Option<String> {
+        query_params.get("trackerid").map(|s| s.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_optional_params() {
+        let request = AnnounceRequest::new_from(&create_test_params()).unwrap();
+
+        assert_eq!(request.numwant, None);
+        assert_eq!(request.corrupt, 0);
+        assert!(!request.compact);
+        assert!(!request.no_peer_id);
+        assert_eq!(request.event, None);
+        assert_eq!(request.ip, None);
+        assert_eq!(request.key, None);
+        assert_eq!(request.tracker_id, None);
+    }
+
+    #[test]
+    fn test_numwant() {
+        assert_eq!(parse_with("numwant", "0").unwrap().numwant, Some(0));
+        assert_eq!(parse_with("numwant", "200").unwrap().numwant, Some(200));
+        assert_eq!(
+            parse_with("numwant", "99999999999").unwrap().numwant,
+            Some(u32::MAX)
+        );
+        assert!(matches!(
+            parse_with("numwant", "-1"),
+            Err(AnnounceRequestError::InvalidNumwant)
+        ));
+    }
+
+    #[test]
+    fn test_corrupt() {
+        assert_eq!(parse_with("corrupt", "16384").unwrap().corrupt, 16384);
+        assert!(matches!(
+            parse_with("corrupt", "lots"),
+            Err(AnnounceRequestError::InvalidCorrupt)
+        ));
+    }
+
+    #[test]
+    fn test_compact() {
+        assert!(parse_with("compact", "1").unwrap().compact);
+        assert!(!parse_with("compact", "0").unwrap().compact);
+    }
+
+    #[test]
+    fn test_no_peer_id() {
+        assert!(parse_with("no_peer_id", "1").unwrap().no_peer_id);
+        assert!(!parse_with("no_peer_id", "0").unwrap().no_peer_id);
+    }
+
+    #[test]
+    fn test_event() {
+        assert_eq!(
+            parse_with("event", "completed").unwrap().event,
+            Some(PeerEvent::Completed)
+        );
+        assert!(matches!(
+            parse_with("event", "paused"),
+            Err(AnnounceRequestError::InvalidEvent)
+        ));
+    }
+
+    #[test]
+    fn test_key() {
+        assert_eq!(
+            parse_with("key", "8f3a2b").unwrap().key,
+            Some("8f3a2b".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tracker_id() {
+        assert_eq!(
+            parse_with("trackerid", "dtracker").unwrap().tracker_id,
+            Some("dtracker".to_string())
+        );
     }
 
-    fn get_key(query_params_map: &HashMap<String, String>) -> Option<String> {
-        query_params_map.get("key").map(|s| s.to_string())
+    fn parse_with(key: &str, value: &str) -> Result<AnnounceRequest, AnnounceRequestError> {
+        let mut params = create_test_params();
+        params.insert(key, value.as_bytes());
+        AnnounceRequest::new_from(&params)
     }
 
-    fn get_tracker_id(query_params_map: &HashMap<String, String>) -> Option<String> {
-        query_params_map.get("tracker_id").map(|s| s.to_string())
+    fn create_test_params() -> QueryParams {
+        let mut params = QueryParams::default();
+        params.insert("info_hash", &[0; 20]);
+        params.insert("peer_id", &[1; 20]);
+        params.insert("port", b"6881");
+        params.insert("uploaded", b"0");
+        params.insert("downloaded", b"0");
+        params.insert("left", b"100");
+        params
     }
 }
@@ -7,6 +7,7 @@ pub enum AnnounceRequestError {
     InvalidUploaded,
     InvalidDownloaded,
     InvalidLeft,
+    InvalidCorrupt,
     InvalidIp,
     InvalidNumwant,
     InvalidKey,
@@ -23,6 +24,7 @@ impl ToString for AnnounceRequestError {
             AnnounceRequestError::InvalidUploaded => "Invalid uploaded".to_string(),
             AnnounceRequestError::InvalidDownloaded => "Invalid downloaded".to_string(),
             AnnounceRequestError::InvalidLeft => "Invalid left".to_string(),
+            AnnounceRequestError::InvalidCorrupt => "Invalid corrupt".to_string(),
             AnnounceRequestError::InvalidIp => "Invalid ip".to_string(),
             AnnounceRequestError::InvalidNumwant => "Invalid numwant".to_string(),
             AnnounceRequestError::InvalidKey => "Invalid key".to_string(),
@@ -1,11 +1,21 @@
-use std::{
-    collections::{BTreeMap, HashMap},
-    sync::Arc,
-};
+use std::sync::Arc;
 
-use bencoder::bencode::ToBencode;
+use bencoder::{
+    bencode::{Bencode, ToBencode},
+    dict_builder::BencodeDictBuilder,
+};
+use tracing::warn;
 
-use crate::{tracker_peer::peer::Peer, tracker_status::atomic_tracker_status::AtomicTrackerStatus};
+use crate::{
+    http::query_params::QueryParams,
+    passkeys::passkey_registry::PasskeyRegistry,
+    torrent_whitelist::whitelist::TorrentWhitelist,
+    tracker_peer::{
+        ip_policy::{IpPolicy, PeerAddress},
+        peer::Peer,
+    },
+    tracker_status::atomic_tracker_status::AtomicTrackerStatus,
+};
 
 use super::announce_request::AnnounceRequest;
 
@@ -24,6 +34,8 @@ use super::announce_request::AnnounceRequest;
 ///    - **ip**: peer's IP address either IPv6 (hexed) or IPv4 (dotted quad) or DNS name (string)
 ///    - **port**: peer's port number (integer)
 /// * `peers_binary`: peers: (binary model) Instead of using the dictionary model described above, the peers value may be a string consisting of multiples of 6 bytes. First 4 bytes are the IP address and last 2 bytes are the port number. All in network (big endian) notation.
+/// * `compact`: If true, the peers are encoded with the binary model. Peers without an IPv4 address are left out.
+/// * `no_peer_id`: If true, the **peer_id** key is left out of the dictionary model.
 #[derive(Debug)]
 pub struct AnnounceResponse {
     pub failure_reason: Option<String>,
@@ -34,40 +46,78 @@ pub struct AnnounceResponse {
     pub complete: u32,
     pub incomplete: u32,
     pub peers: Vec<Peer>,
+    pub compact: bool,
+    pub no_peer_id: bool,
 }
 
 impl AnnounceResponse {
-    /// Creates a new AnnounceResponse from a HashMap containing the query parameters of the announce request.
+    /// Creates a new AnnounceResponse from the decoded query parameters of the announce request.
+    ///
+    /// If whitelist mode is on, announces for torrents not registered in the `whitelist` fail.
+    /// If passkeys are required, announces without a `passkey` registered in `passkeys` fail, and the rest are added to
+    /// the stats of its user.
+    /// The peer is registered with the `remote_address`, or the `ip` it sent if the `ip_policy` allows it.
     pub fn from(
-        query_params: HashMap<String, String>,
+        query_params: QueryParams,
         tracker_status: Arc<AtomicTrackerStatus>,
-        peer_ip: String,
+        whitelist: &TorrentWhitelist,
+        passkeys: &PasskeyRegistry,
+        passkey: Option<&str>,
+        remote_address: PeerAddress,
+        ip_policy: &IpPolicy,
     ) -> Self {
-        let announce_request = match AnnounceRequest::new_from(query_params) {
+        let announce_request = match AnnounceRequest::new_from(&query_params) {
             Ok(announce_request) => announce_request,
             Err(announce_request_error) => {
                 return Self::create_error_response(announce_request_error.to_string())
             }
         };
 
-        let peer = Peer::from_request(announce_request.clone(), peer_ip);
+        if !passkeys.is_allowed(passkey) {
+            return Self::create_error_response("unknown passkey".to_string());
+        }
+
+        if !whitelist.is_allowed(&announce_request.info_hash) {
+            return Self::create_error_response("torrent not registered".to_string());
+        }
 
-        let active_peers = tracker_status.incoming_peer(
+        let peer = Peer::from_request(announce_request.clone(), remote_address, ip_policy);
+
+        let active_peers = match tracker_status.incoming_peer(
             announce_request.info_hash,
             peer,
-            announce_request.numwant,
-        );
+            tracker_status.wanted_peers(announce_request.numwant),
+        ) {
+            Ok(active_peers) => active_peers,
+            Err(swarm_error) => {
+                warn!(
+                    "Rejected announce from {}: {:?}",
+                    remote_address.ip, swarm_error
+                );
+                return Self::create_error_response(swarm_error.to_string());
+            }
+        };
 
-        // TODO: Handle announce_request.compact == true case.
+        if let Some(passkey) = passkey.filter(|_| passkeys.is_enabled()) {
+            if let Err(err) = passkeys.record_announce(passkey, &announce_request) {
+                warn!("Could not record the announce of {}: {:?}", passkey, err);
+            }
+        }
 
-        Self::create_success_response(
+        let mut response = Self::create_success_response(
             active_peers.peers,
             active_peers.seeders,
             active_peers.leechers,
-        )
+            tracker_status.announce_interval().num_seconds() as u32,
+            tracker_status.min_announce_interval().num_seconds() as u32,
+        );
+        response.compact = announce_request.compact;
+        response.no_peer_id = announce_request.no_peer_id;
+        response
     }
 
-    fn create_error_response(failure_reason: String) -> Self {
+    /// Creates an AnnounceResponse that only contains a failure reason.
+    pub fn create_error_response(failure_reason: String) -> Self {
         Self {
             failure_reason: Some(failure_reason),
             warning_message: None,
@@ -77,42 +127,114 @@ impl AnnounceResponse {
             complete: 0,
             incomplete: 0,
             peers: Vec::new(),
+            compact: false,
+            no_peer_id: false,
         }
     }
 
-    fn create_success_response(peers_list: Vec<Peer>, complete: u32, incomplete: u32) -> Self {
+    fn create_success_response(
+        peers_list: Vec<Peer>,
+        complete: u32,
+        incomplete: u32,
+        interval: u32,
+        min_interval: u32,
+    ) -> Self {
         Self {
             failure_reason: None,
             warning_message: None,
-            interval: 0,
-            min_interval: None,
+            interval,
+            min_interval: Some(min_interval),
             tracker_id: None,
             complete,
             incomplete,
             peers: peers_list,
+            compact: false,
+            no_peer_id: false,
         }
     }
 }
 
 impl ToBencode for AnnounceResponse {
-    fn to_bencode(&self) -> bencoder::bencode::Bencode {
-        let mut announce_response = BTreeMap::new();
-        if let Some(failure_reason) = &self.failure_reason {
-            announce_response.insert(b"failure reason".to_vec(), failure_reason.to_bencode());
-        }
-        if let Some(warning_message) = &self.warning_message {
-            announce_response.insert(b"warning message".to_vec(), warning_message.to_bencode());
-        }
-        announce_response.insert(b"interval".to_vec(), self.interval.to_bencode());
-        if let Some(min_interval) = &self.min_interval {
-            announce_response.insert(b"min interval".to_vec(), min_interval.to_bencode());
-        }
-        if let Some(tracker_id) = &self.tracker_id {
-            announce_response.insert(b"tracker id".to_vec(), tracker_id.to_bencode());
-        }
-        announce_response.insert(b"complete".to_vec(), self.complete.to_bencode());
-        announce_response.insert(b"incomplete".to_vec(), self.incomplete.to_bencode());
-        announce_response.insert(b"peers".to_vec(), self.peers.to_bencode());
-        announce_response.to_bencode()
+    fn to_bencode(&self) -> Bencode {
+        let peers = if self.compact {
+            let peers: Vec<u8> = self
+                .peers
+                .iter()
+                .filter_map(Peer::compact)
+                .flatten()
+                .collect();
+            peers.to_bencode()
+        } else if self.no_peer_id {
+            Bencode::BList(self.peers.iter().map(Peer::to_bencode_without_id).collect())
+        } else {
+            self.peers.to_bencode()
+        };
+        BencodeDictBuilder::new()
+            .insert_optional(b"failure reason", self.failure_reason.as_ref())
+            .insert_optional(b"warning message", self.warning_message.as_ref())
+            .insert(b"interval", &self.interval)
+            .insert_optional(b"min interval", self.min_interval.as_ref())
+            .insert_optional(b"tracker id", self.tracker_id.as_ref())
+            .insert(b"complete", &self.complete)
+            .insert(b"incomplete", &self.incomplete)
+            .insert(b"peers", &peers)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tracker_peer::peer_status::PeerStatus;
+
+    use super::*;
+
+    #[test]
+    fn test_compact_peers() {
+        let mut response = create_test_response();
+        response.compact = true;
+
+        let encoded = Bencode::encode(&response);
+
+        let mut expected_peers = b"5:peers12:".to_vec();
+        expected_peers.extend([192, 168, 0, 1, 0x1a, 0xe1, 10, 0, 0, 2, 0x1a, 0xe2]);
+        assert!(contains(&encoded, &expected_peers));
+    }
+
+    #[test]
+    fn test_no_peer_id() {
+        let mut response = create_test_response();
+        response.no_peer_id = true;
+
+        let encoded = Bencode::encode(&response);
+
+        assert!(!contains(&encoded, b"peer_id"));
+        assert!(contains(&encoded, b"d2:ip11:192.168.0.14:porti6881ee"));
+    }
+
+    #[test]
+    fn test_dictionary_peers_include_peer_id() {
+        let encoded = Bencode::encode(&create_test_response());
+
+        assert!(contains(&encoded, b"7:peer_id"));
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    fn create_test_response() -> AnnounceResponse {
+        let peers = vec![
+            create_test_peer([1; 20], "192.168.0.1", 6881),
+            create_test_peer([2; 20], "10.0.0.2", 6882),
+            create_test_peer([3; 20], "::1", 6883),
+        ];
+        AnnounceResponse::create_success_response(peers, 1, 2, 1800, 900)
+    }
+
+    fn create_test_peer(id: [u8; 20], ip: &str, port: u16) -> Peer {
+        let status = PeerStatus::new(0, 0, 0, None);
+        Peer::new(id, ip.to_string(), port, None, status)
     }
 }
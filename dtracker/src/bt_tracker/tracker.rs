@@ -5,8 +5,18 @@ use chrono::Duration;
 use tracing::info;
 
 use crate::{
-    http_server::server::Server, stats::stats_updater::StatsUpdater,
-    tracker_status::atomic_tracker_status::AtomicTrackerStatus,
+    admin::admin_token::AdminToken,
+    http_server::{
+        rate_limiter::RateLimiter,
+        server::{Server, ServerConfig},
+        thread_pool::pool::ThreadPoolConfig,
+        tls::{TlsConfig, TlsError},
+    },
+    passkeys::passkey_registry::PasskeyRegistry,
+    stats::stats_updater::StatsUpdater,
+    torrent_whitelist::whitelist::TorrentWhitelist,
+    tracker_peer::ip_policy::IpPolicy,
+    tracker_status::atomic_tracker_status::{AtomicTrackerStatus, SwarmLimits, TrackerTimeouts},
 };
 
 /// Struct that represents the Tracker itself.
@@ -20,25 +30,93 @@ pub struct BtTracker {
 pub enum BtTrackerError {
     CreatingServerError(io::Error),
     StartingServerError(io::Error),
+    LoadingTlsError(TlsError),
+}
+
+/// Who is allowed to announce on the tracker.
+///
+/// ## Fields
+/// * `whitelist`: The torrents allowed on the tracker, every torrent is allowed if whitelist mode is off.
+/// * `passkeys`: The users allowed to announce on the tracker, every announce is allowed if passkeys are not required.
+/// * `admin_token`: The token required by the admin endpoints, which manage the torrents and peers at runtime.
+#[derive(Debug, Default)]
+pub struct TrackerAccess {
+    pub whitelist: TorrentWhitelist,
+    pub passkeys: PasskeyRegistry,
+    pub admin_token: AdminToken,
+}
+
+/// The limits that protect the tracker from being overloaded.
+///
+/// ## Fields
+/// * `max_announces_per_minute`: The amount of announces each IP can make per minute, the rest get rejected.
+/// * `swarms`: The maximum amount of torrents and of peers per torrent tracked.
+#[derive(Debug, Clone, Copy)]
+pub struct TrackerLimits {
+    pub max_announces_per_minute: u32,
+    pub swarms: SwarmLimits,
 }
 
 const STATS_UPDATER_MINUTES_TIMEOUT: i64 = 1;
 
 impl BtTracker {
     /// Creates a new BtTracker
-    pub fn init(port: u16) -> Result<Self, BtTrackerError> {
-        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+    ///
+    /// ## Arguments
+    /// * `port`: The port to listen for announces on.
+    /// * `timeouts`: The announce intervals, the peer timeout and the interval between removals of inactive peers.
+    /// * `access`: The torrents and users allowed on the tracker, and who administers it.
+    /// * `limits`: The announces each IP can make per minute and the maximum amount of torrents and peers tracked.
+    /// * `tls`: The certificate and key to serve over HTTPS, plain HTTP is used if absent.
+    /// * `pool_config`: The sizing of the pool of threads that handle the connections.
+    /// * `ip_policy`: Decides the IP each announcer is registered with, honoring trusted proxies and the `ip` parameter.
+    pub fn init(
+        port: u16,
+        timeouts: TrackerTimeouts,
+        access: TrackerAccess,
+        limits: TrackerLimits,
+        tls: Option<TlsConfig>,
+        pool_config: ThreadPoolConfig,
+        ip_policy: IpPolicy,
+    ) -> Result<Self, BtTrackerError> {
+        let tls_acceptor = tls
+            .map(|tls| tls.acceptor())
+            .transpose()
+            .map_err(BtTrackerError::LoadingTlsError)?;
+
+        let tracker_status =
+            Arc::new(AtomicTrackerStatus::new(timeouts).with_limits(limits.swarms));
 
+        Self::spawn_reaper(tracker_status.clone());
         let stats_updater = Self::spawn_stats_updater(tracker_status.clone());
 
-        let server = Server::init(tracker_status, stats_updater, port)
-            .map_err(BtTrackerError::CreatingServerError)?;
+        let server = Server::init(
+            tracker_status,
+            stats_updater,
+            Arc::new(access.whitelist),
+            Arc::new(access.passkeys),
+            Arc::new(RateLimiter::new(limits.max_announces_per_minute)),
+            Arc::new(ip_policy),
+            ServerConfig {
+                port,
+                tls: tls_acceptor,
+                pool_config,
+                admin_token: access.admin_token,
+            },
+        )
+        .map_err(BtTrackerError::CreatingServerError)?;
 
         info!("Tracker started");
 
         Ok(Self { server })
     }
 
+    /// Writes every request to the access log if `access_log` is true, with the `dtracker::access` target.
+    pub fn with_access_log(mut self, access_log: bool) -> Self {
+        self.server = self.server.with_access_log(access_log);
+        self
+    }
+
     /// Starts the server for handling requests.
     pub fn run(&self) -> Result<(), BtTrackerError> {
         self.server
@@ -46,6 +124,12 @@ impl BtTracker {
             .map_err(BtTrackerError::StartingServerError)
     }
 
+    fn spawn_reaper(tracker_status: Arc<AtomicTrackerStatus>) {
+        spawn(move || {
+            tracker_status.run_reaper();
+        });
+    }
+
     fn spawn_stats_updater(tracker_status: Arc<AtomicTrackerStatus>) -> Arc<StatsUpdater> {
         let stats_updater = Arc::new(StatsUpdater::new(
             tracker_status,
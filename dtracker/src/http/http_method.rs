@@ -3,6 +3,7 @@ use std::str::FromStr;
 #[derive(Debug, PartialEq)]
 pub enum HttpMethod {
     Get,
+    Post,
 }
 
 impl FromStr for HttpMethod {
@@ -11,6 +12,7 @@ impl FromStr for HttpMethod {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "GET" => Ok(HttpMethod::Get),
+            "POST" => Ok(HttpMethod::Post),
             _ => Err(()),
         }
     }
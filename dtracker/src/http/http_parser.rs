@@ -1,17 +1,19 @@
 use std::{collections::HashMap, str::FromStr};
 
-use super::http_method::HttpMethod;
+use super::{http_method::HttpMethod, query_params::QueryParams};
 
 /// A struct that represents a HTTP request.
 ///
 /// # Fields
 /// * `method`: The HTTP method of the request.
 /// * `endpoint`: The endpoint of the request.
-/// * `params`: The parameters of the request.
+/// * `params`: The percent-decoded query parameters of the request.
+/// * `headers`: The headers of the request, with their names in lowercase.
 pub struct Http {
     pub method: HttpMethod,
     pub endpoint: String,
-    pub params: HashMap<String, String>,
+    pub params: QueryParams,
+    pub headers: HashMap<String, String>,
 }
 
 #[derive(Debug)]
@@ -40,30 +42,32 @@ impl Http {
             .to_string();
 
         let query_params = endpoint_split.next().ok_or(HttpError::ParseError)?;
-        let params = parse_params(query_params).map_err(|_| HttpError::ParseError)?;
+        let params = QueryParams::parse(query_params).map_err(|_| HttpError::ParseError)?;
+
+        let mut headers = HashMap::new();
+        for line in lines {
+            let line = String::from_utf8_lossy(line);
+            let line = line.trim_start_matches('\n');
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+            }
+        }
 
         Ok(Http {
             method,
             endpoint,
             params,
+            headers,
         })
     }
-}
 
-fn parse_params(query_params: &[u8]) -> Result<HashMap<String, String>, HttpError> {
-    let mut params = HashMap::new();
-    let query_params = query_params.split(|&b| b == b'&');
-
-    for param in query_params {
-        let mut param_split = param.split(|&b| b == b'=');
-        let key =
-            String::from_utf8_lossy(param_split.next().ok_or(HttpError::ParseError)?).to_string();
-        let value =
-            String::from_utf8_lossy(param_split.next().ok_or(HttpError::ParseError)?).to_string();
-        params.insert(key, value);
+    /// Returns the value of a header, the name is case insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
     }
-
-    Ok(params)
 }
 
 #[cfg(test)]
@@ -74,21 +78,39 @@ mod tests {
     fn test_parse_valid_request() {
         let buffer = "GET /announce?info_hash=%b1%11%81%3c%e6%0f%42%91%97%34%82%3d%f5%ec%20%bd%1e%04%e7%f7&peer_id=DTorrent:02284204893&port=6969&uploaded=0&downloaded=0&left=396361728&event=started HTTP/1.1\r\nHost: bttracker.debian.org\r\nUser-Agent: LDTorrent/0.1\r\n\r\n".as_bytes();
         let http = Http::parse(buffer).unwrap();
-        let mut params = HashMap::new();
+        let mut params = QueryParams::default();
         params.insert(
-            "info_hash".to_string(),
-            "%b1%11%81%3c%e6%0f%42%91%97%34%82%3d%f5%ec%20%bd%1e%04%e7%f7".to_string(),
+            "info_hash",
+            &[
+                0xb1, 0x11, 0x81, 0x3c, 0xe6, 0x0f, 0x42, 0x91, 0x97, 0x34, 0x82, 0x3d, 0xf5, 0xec,
+                0x20, 0xbd, 0x1e, 0x04, 0xe7, 0xf7,
+            ],
         );
-        params.insert("peer_id".to_string(), "DTorrent:02284204893".to_string());
-        params.insert("port".to_string(), "6969".to_string());
-        params.insert("uploaded".to_string(), "0".to_string());
-        params.insert("downloaded".to_string(), "0".to_string());
-        params.insert("left".to_string(), "396361728".to_string());
-        params.insert("event".to_string(), "started".to_string());
+        params.insert("peer_id", b"DTorrent:02284204893");
+        params.insert("port", b"6969");
+        params.insert("uploaded", b"0");
+        params.insert("downloaded", b"0");
+        params.insert("left", b"396361728");
+        params.insert("event", b"started");
 
         assert_eq!(http.method, HttpMethod::from_str("GET").unwrap());
         assert_eq!(http.endpoint, "/announce");
         assert_eq!(http.params, params);
+        assert_eq!(http.header("host"), Some("bttracker.debian.org"));
+        assert_eq!(http.header("User-Agent"), Some("LDTorrent/0.1"));
+    }
+
+    #[test]
+    fn test_parse_post_request() {
+        let buffer =
+            "POST /admin/ban?action=ban&ip=10.0.0.1 HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n"
+                .as_bytes();
+        let http = Http::parse(buffer).unwrap();
+
+        assert_eq!(http.method, HttpMethod::Post);
+        assert_eq!(http.endpoint, "/admin/ban");
+        assert_eq!(http.params.get("ip"), Some("10.0.0.1"));
+        assert_eq!(http.header("authorization"), Some("Bearer secret"));
     }
 
     #[test]
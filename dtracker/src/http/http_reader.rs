@@ -0,0 +1,172 @@
+use std::io::{self, Read};
+
+/// Maximum length of the request line, enough for a scrape of about 200 percent-encoded info hashes.
+pub const MAX_REQUEST_LINE_LENGTH: usize = 16 * 1024;
+/// Maximum length of the headers that follow the request line.
+pub const MAX_HEADERS_LENGTH: usize = 8 * 1024;
+
+const READ_CHUNK_SIZE: usize = 1024;
+const HEADERS_END: &[u8] = b"\r\n\r\n";
+
+#[derive(Debug)]
+pub enum HttpReadError {
+    IoError(io::Error),
+    ConnectionClosed,
+    RequestLineTooLong,
+    HeadersTooLarge,
+}
+
+/// Reads a HTTP request from `reader` up to the empty line that ends its headers.
+///
+/// Returns the request line and headers, a body sent after them is not read. If the connection is closed before
+/// the empty line, returns what was read so the parser can decide if it is a valid request.
+///
+/// # Errors
+/// - `IoError` if the reader fails.
+/// - `ConnectionClosed` if the connection is closed before sending anything.
+/// - `RequestLineTooLong` if the request line is longer than `MAX_REQUEST_LINE_LENGTH`.
+/// - `HeadersTooLarge` if the headers are longer than `MAX_HEADERS_LENGTH`.
+pub fn read_request<R: Read>(reader: &mut R) -> Result<Vec<u8>, HttpReadError> {
+    let mut request = Vec::with_capacity(READ_CHUNK_SIZE);
+    let mut chunk = [0; READ_CHUNK_SIZE];
+    loop {
+        let bytes_read = reader.read(&mut chunk).map_err(HttpReadError::IoError)?;
+        if bytes_read == 0 {
+            if request.is_empty() {
+                return Err(HttpReadError::ConnectionClosed);
+            }
+            return Ok(request);
+        }
+        // The end can be split between two reads, so the search starts before the new bytes.
+        let search_start = request.len().saturating_sub(HEADERS_END.len() - 1);
+        request.extend_from_slice(&chunk[..bytes_read]);
+
+        if let Some(position) = find(&request[search_start..], HEADERS_END) {
+            request.truncate(search_start + position + HEADERS_END.len());
+            check_length(&request)?;
+            return Ok(request);
+        }
+        check_length(&request)?;
+    }
+}
+
+/// Checks that the request line and the headers read so far are within their maximum length.
+fn check_length(request: &[u8]) -> Result<(), HttpReadError> {
+    match find(request, b"\r\n") {
+        Some(line_end) if line_end > MAX_REQUEST_LINE_LENGTH => {
+            Err(HttpReadError::RequestLineTooLong)
+        }
+        Some(line_end) if request.len() - line_end > MAX_HEADERS_LENGTH => {
+            Err(HttpReadError::HeadersTooLarge)
+        }
+        None if request.len() > MAX_REQUEST_LINE_LENGTH => Err(HttpReadError::RequestLineTooLong),
+        _ => Ok(()),
+    }
+}
+
+fn find(bytes: &[u8], pattern: &[u8]) -> Option<usize> {
+    bytes
+        .windows(pattern.len())
+        .position(|window| window == pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the bytes in chunks of the given size, like a client sending the request in several packets.
+    struct ChunkedReader {
+        bytes: Vec<u8>,
+        chunk_size: usize,
+    }
+
+    impl Read for ChunkedReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let length = self.chunk_size.min(buf.len()).min(self.bytes.len());
+            buf[..length].copy_from_slice(&self.bytes[..length]);
+            self.bytes.drain(..length);
+            Ok(length)
+        }
+    }
+
+    fn chunked(request: &str, chunk_size: usize) -> ChunkedReader {
+        ChunkedReader {
+            bytes: request.as_bytes().to_vec(),
+            chunk_size,
+        }
+    }
+
+    #[test]
+    fn test_reads_request_longer_than_a_chunk() {
+        let info_hashes =
+            "info_hash=%b1%11%81%3c%e6%0f%42%91%97%34%82%3d%f5%ec%20%bd%1e%04%e7%f7&".repeat(30);
+        let request = format!(
+            "GET /scrape?{} HTTP/1.1\r\nHost: tracker\r\nX-Forwarded-For: 10.0.0.1\r\n\r\n",
+            info_hashes
+        );
+
+        let read = read_request(&mut chunked(&request, 7)).unwrap();
+
+        assert_eq!(read, request.as_bytes());
+    }
+
+    #[test]
+    fn test_stops_at_the_end_of_the_headers() {
+        let mut reader = chunked("GET /stats HTTP/1.1\r\nHost: tracker\r\n\r\nbody", 1024);
+
+        let read = read_request(&mut reader).unwrap();
+
+        assert_eq!(read, b"GET /stats HTTP/1.1\r\nHost: tracker\r\n\r\n");
+    }
+
+    #[test]
+    fn test_returns_request_without_end_when_closed() {
+        let read = read_request(&mut chunked("GET /stats HTTP/1.1\r\n", 5)).unwrap();
+
+        assert_eq!(read, b"GET /stats HTTP/1.1\r\n");
+    }
+
+    #[test]
+    fn test_closed_connection() {
+        assert!(matches!(
+            read_request(&mut chunked("", 1024)),
+            Err(HttpReadError::ConnectionClosed)
+        ));
+    }
+
+    #[test]
+    fn test_request_line_too_long() {
+        let request = format!(
+            "GET /scrape?{} HTTP/1.1\r\n\r\n",
+            "a".repeat(MAX_REQUEST_LINE_LENGTH)
+        );
+
+        assert!(matches!(
+            read_request(&mut chunked(&request, 1024)),
+            Err(HttpReadError::RequestLineTooLong)
+        ));
+    }
+
+    #[test]
+    fn test_request_line_too_long_without_line_end() {
+        let request = "a".repeat(MAX_REQUEST_LINE_LENGTH + 1);
+
+        assert!(matches!(
+            read_request(&mut chunked(&request, 1024)),
+            Err(HttpReadError::RequestLineTooLong)
+        ));
+    }
+
+    #[test]
+    fn test_headers_too_large() {
+        let request = format!(
+            "GET /stats HTTP/1.1\r\n{}\r\n",
+            "X-Padding: a\r\n".repeat(MAX_HEADERS_LENGTH / 10)
+        );
+
+        assert!(matches!(
+            read_request(&mut chunked(&request, 1024)),
+            Err(HttpReadError::HeadersTooLarge)
+        ));
+    }
+}
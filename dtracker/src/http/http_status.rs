@@ -5,6 +5,27 @@ pub enum HttpStatus {
     Ok,
     NotFound,
     BadRequest,
+    Unauthorized,
+    MethodNotAllowed,
+    URITooLong,
+    TooManyRequests,
+    RequestHeaderFieldsTooLarge,
+}
+
+impl HttpStatus {
+    /// Returns the numeric status code.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::Ok => 200,
+            Self::NotFound => 404,
+            Self::BadRequest => 400,
+            Self::Unauthorized => 401,
+            Self::MethodNotAllowed => 405,
+            Self::URITooLong => 414,
+            Self::TooManyRequests => 429,
+            Self::RequestHeaderFieldsTooLarge => 431,
+        }
+    }
 }
 
 impl FromStr for HttpStatus {
@@ -15,6 +36,11 @@ impl FromStr for HttpStatus {
             "200 OK" => Ok(HttpStatus::Ok),
             "404 NOT FOUND" => Ok(HttpStatus::NotFound),
             "400 BAD REQUEST" => Ok(HttpStatus::BadRequest),
+            "401 UNAUTHORIZED" => Ok(HttpStatus::Unauthorized),
+            "405 METHOD NOT ALLOWED" => Ok(HttpStatus::MethodNotAllowed),
+            "414 URI TOO LONG" => Ok(HttpStatus::URITooLong),
+            "429 TOO MANY REQUESTS" => Ok(HttpStatus::TooManyRequests),
+            "431 REQUEST HEADER FIELDS TOO LARGE" => Ok(HttpStatus::RequestHeaderFieldsTooLarge),
             _ => Err(()),
         }
     }
@@ -26,6 +52,11 @@ impl ToString for HttpStatus {
             Self::Ok => "200 OK".to_string(),
             Self::NotFound => "404 NOT FOUND".to_string(),
             Self::BadRequest => "400 BAD REQUEST".to_string(),
+            Self::Unauthorized => "401 UNAUTHORIZED".to_string(),
+            Self::MethodNotAllowed => "405 METHOD NOT ALLOWED".to_string(),
+            Self::URITooLong => "414 URI TOO LONG".to_string(),
+            Self::TooManyRequests => "429 TOO MANY REQUESTS".to_string(),
+            Self::RequestHeaderFieldsTooLarge => "431 REQUEST HEADER FIELDS TOO LARGE".to_string(),
         }
     }
 }
@@ -1,3 +1,5 @@
 pub mod http_method;
 pub mod http_parser;
+pub mod http_reader;
 pub mod http_status;
+pub mod query_params;
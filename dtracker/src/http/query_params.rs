@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use url_encoder::url_encoder::{decode, DecodeError};
+
+/// The percent-decoded query parameters of a HTTP request.
+///
+/// Values are stored as bytes, since parameters like `info_hash` and `peer_id` can contain arbitrary binary data.
+/// Use `get()` for text parameters and `get_bytes()` for binary ones. Every value of a repeated parameter is kept,
+/// like the `info_hash` of each torrent of a scrape, see `get_all_bytes()`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct QueryParams {
+    params: HashMap<String, Vec<Vec<u8>>>,
+}
+
+impl QueryParams {
+    /// Parses and decodes a query string, like `info_hash=%12%34&port=6881`.
+    ///
+    /// If a parameter is repeated, all its values are kept in order. Parameters without a value have an empty one.
+    ///
+    /// It returns an error if a key or value has an invalid percent-encoding.
+    pub fn parse(query: &[u8]) -> Result<Self, DecodeError> {
+        let mut params = Self::default();
+        let query = String::from_utf8_lossy(query);
+
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let key = String::from_utf8_lossy(&decode(key)?).to_string();
+            params.params.entry(key).or_default().push(decode(value)?);
+        }
+
+        Ok(params)
+    }
+
+    /// Returns the value of a text parameter, or `None` if it is missing or not valid UTF-8.
+    ///
+    /// If the parameter is repeated, the last value is returned.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_bytes(key)
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    /// Returns the raw bytes of a parameter.
+    ///
+    /// If the parameter is repeated, the last value is returned.
+    pub fn get_bytes(&self, key: &str) -> Option<&[u8]> {
+        self.params
+            .get(key)
+            .and_then(|values| values.last())
+            .map(Vec::as_slice)
+    }
+
+    /// Returns the raw bytes of every value of a parameter, in the order of the query, empty if it is missing.
+    pub fn get_all_bytes(&self, key: &str) -> Vec<&[u8]> {
+        self.params
+            .get(key)
+            .map(|values| values.iter().map(Vec::as_slice).collect())
+            .unwrap_or_default()
+    }
+
+    /// Sets the decoded value of a parameter, replacing the values it had.
+    pub fn insert(&mut self, key: &str, value: &[u8]) {
+        self.params.insert(key.to_string(), vec![value.to_vec()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_binary_and_text_params() {
+        let params = QueryParams::parse(b"info_hash=%124Vx%9A&port=6881&key=a+b").unwrap();
+
+        assert_eq!(
+            params.get_bytes("info_hash"),
+            Some([0x12, b'4', b'V', b'x', 0x9a].as_slice())
+        );
+        assert_eq!(params.get("info_hash"), None);
+        assert_eq!(params.get("port"), Some("6881"));
+        assert_eq!(params.get("key"), Some("a b"));
+    }
+
+    #[test]
+    fn test_parse_param_without_value() {
+        let params = QueryParams::parse(b"compact&port=6881").unwrap();
+
+        assert_eq!(params.get("compact"), Some(""));
+        assert_eq!(params.get("port"), Some("6881"));
+    }
+
+    #[test]
+    fn test_parse_repeated_param() {
+        let params = QueryParams::parse(b"info_hash=aaa&info_hash=%62bb&port=6881").unwrap();
+
+        assert_eq!(
+            params.get_all_bytes("info_hash"),
+            vec![b"aaa".as_slice(), b"bbb".as_slice()]
+        );
+        assert_eq!(params.get_bytes("info_hash"), Some(b"bbb".as_slice()));
+        assert_eq!(params.get_all_bytes("port"), vec![b"6881".as_slice()]);
+        assert!(params.get_all_bytes("peer_id").is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid_encoding() {
+        assert!(QueryParams::parse(b"info_hash=%1").is_err());
+    }
+}
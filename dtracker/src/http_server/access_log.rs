@@ -0,0 +1,142 @@
+use std::{fmt, net::IpAddr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use tracing::info;
+use url_encoder::info_hash::InfoHash;
+
+/// Target of the access log events, so they can be filtered apart from the rest of the logs with `RUST_LOG`.
+pub const ACCESS_LOG_TARGET: &str = "dtracker::access";
+
+/// A request served by the tracker, written to the access log once its response is sent.
+///
+/// It is formatted like a line of the combined log format, followed by the info hash, the event of the announce and
+/// the time taken to respond:
+///
+/// `203.0.113.7 - - [15/Oct/2026:10:00:00 +0000] "GET /announce HTTP/1.1" 200 62 "-" "qBittorrent/4.6" info_hash=<hex> event=started 1.250ms`
+///
+/// The query is left out of the request line, so passkeys and admin tokens are never logged.
+///
+/// ## Fields
+/// * `ip`: The IP of the client, as registered by the `IpPolicy`.
+/// * `time`: When the request was received.
+/// * `endpoint`: The requested endpoint, `None` if the request could not be parsed.
+/// * `info_hash`: The `info_hash` parameter of the request, if valid.
+/// * `event`: The `event` parameter of an announce.
+/// * `user_agent`: The `User-Agent` header of the request.
+/// * `status`: The status code of the response.
+/// * `bytes`: The length of the body of the response.
+/// * `latency`: The time between receiving the request and sending the response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessLogEntry {
+    pub ip: IpAddr,
+    pub time: DateTime<Utc>,
+    pub endpoint: Option<String>,
+    pub info_hash: Option<InfoHash>,
+    pub event: Option<String>,
+    pub user_agent: Option<String>,
+    pub status: u16,
+    pub bytes: usize,
+    pub latency: Duration,
+}
+
+impl AccessLogEntry {
+    /// Writes the entry to the access log.
+    pub fn log(&self) {
+        info!(target: ACCESS_LOG_TARGET, "{}", self);
+    }
+}
+
+impl fmt::Display for AccessLogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let request = match &self.endpoint {
+            Some(endpoint) => format!("GET {} HTTP/1.1", escape(endpoint)),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "{} - - [{}] \"{}\" {} {} \"-\" \"{}\"",
+            self.ip,
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            request,
+            self.status,
+            self.bytes,
+            self.user_agent.as_deref().map(escape).unwrap_or_default(),
+        )?;
+        if let Some(info_hash) = &self.info_hash {
+            write!(f, " info_hash={}", info_hash.to_hex())?;
+        }
+        if let Some(event) = &self.event {
+            write!(f, " event={}", escape(event))?;
+        }
+        write!(f, " {:.3}ms", self.latency.as_secs_f64() * 1000.0)
+    }
+}
+
+/// Escapes the quotes, backslashes and control characters sent by the client, so every entry is a single
+/// parseable line.
+fn escape(value: &str) -> String {
+    value.chars().flat_map(char::escape_default).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn entry() -> AccessLogEntry {
+        AccessLogEntry {
+            ip: "203.0.113.7".parse().unwrap(),
+            time: Utc.with_ymd_and_hms(2026, 10, 15, 10, 0, 0).unwrap(),
+            endpoint: Some("/announce".to_string()),
+            info_hash: Some(InfoHash::new([0xab; 20])),
+            event: Some("started".to_string()),
+            user_agent: Some("qBittorrent/4.6".to_string()),
+            status: 200,
+            bytes: 62,
+            latency: Duration::from_micros(1250),
+        }
+    }
+
+    #[test]
+    fn test_announce_entry() {
+        assert_eq!(
+            entry().to_string(),
+            format!(
+                "203.0.113.7 - - [15/Oct/2026:10:00:00 +0000] \"GET /announce HTTP/1.1\" 200 62 \"-\" \
+                 \"qBittorrent/4.6\" info_hash={} event=started 1.250ms",
+                "ab".repeat(20)
+            )
+        );
+    }
+
+    #[test]
+    fn test_unparsed_request_entry() {
+        let entry = AccessLogEntry {
+            endpoint: None,
+            info_hash: None,
+            event: None,
+            user_agent: None,
+            status: 400,
+            bytes: 0,
+            ..entry()
+        };
+
+        assert_eq!(
+            entry.to_string(),
+            "203.0.113.7 - - [15/Oct/2026:10:00:00 +0000] \"-\" 400 0 \"-\" \"\" 1.250ms"
+        );
+    }
+
+    #[test]
+    fn test_client_values_are_escaped() {
+        let entry = AccessLogEntry {
+            user_agent: Some("evil\" 200\nclient".to_string()),
+            ..entry()
+        };
+
+        assert!(entry
+            .to_string()
+            .contains("\"evil\\\" 200\\nclient\" info_hash="));
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::{
+    http::http_status::HttpStatus,
+    http_server::{rate_limiter::RateLimiter, thread_pool::pool::PoolMonitor},
+    tracker_status::atomic_tracker_status::AtomicTrackerStatus,
+};
+
+/// Collects the metrics of the server and renders them in the Prometheus text format.
+///
+/// ## Fields
+/// * `responses`: The amount of responses sent, by HTTP status code.
+/// * `pool`: The state of the thread pool that handles the connections.
+#[derive(Debug)]
+pub struct Metrics {
+    responses: Mutex<BTreeMap<u16, u64>>,
+    pool: PoolMonitor,
+}
+
+impl Metrics {
+    /// Creates a new `Metrics` for the server with the given thread pool.
+    pub fn new(pool: PoolMonitor) -> Self {
+        Self {
+            responses: Mutex::new(BTreeMap::new()),
+            pool,
+        }
+    }
+
+    /// Counts a response sent with the given status.
+    pub fn record_response(&self, status: &HttpStatus) {
+        *self.lock_responses().entry(status.code()).or_insert(0) += 1;
+    }
+
+    /// Renders every metric in the Prometheus text format.
+    ///
+    /// ## Arguments
+    /// * `tracker_status`: The status of the tracker, for the torrent and announce metrics.
+    /// * `rate_limiter`: The rate limiter, for the rejected announces.
+    pub fn render(
+        &self,
+        tracker_status: &AtomicTrackerStatus,
+        rate_limiter: &RateLimiter,
+    ) -> String {
+        let stats = tracker_status.get_global_statistics();
+        let metrics = [
+            (
+                "dtracker_torrents",
+                "gauge",
+                "Torrents known by the tracker.",
+                stats.torrents as u64,
+            ),
+            (
+                "dtracker_seeders",
+                "gauge",
+                "Peers with the whole torrent.",
+                stats.seeders as u64,
+            ),
+            (
+                "dtracker_leechers",
+                "gauge",
+                "Peers still downloading the torrent.",
+                stats.leechers as u64,
+            ),
+            (
+                "dtracker_snatches_total",
+                "counter",
+                "Completed downloads reported.",
+                stats.snatches as u64,
+            ),
+            (
+                "dtracker_announces_total",
+                "counter",
+                "Announces accepted.",
+                tracker_status.total_announces(),
+            ),
+            (
+                "dtracker_rate_limited_announces_total",
+                "counter",
+                "Announces rejected by the rate limiter.",
+                rate_limiter.get_stats().rejected_announces,
+            ),
+            (
+                "dtracker_thread_pool_workers",
+                "gauge",
+                "Threads handling connections.",
+                self.pool.workers() as u64,
+            ),
+            (
+                "dtracker_thread_pool_idle_workers",
+                "gauge",
+                "Threads waiting for a connection.",
+                self.pool.idle_workers() as u64,
+            ),
+            (
+                "dtracker_thread_pool_queued_jobs",
+                "gauge",
+                "Connections waiting for a thread.",
+                self.pool.queued_jobs() as u64,
+            ),
+        ];
+
+        let mut output = String::new();
+        for (name, metric_type, help, value) in metrics {
+            write_header(&mut output, name, metric_type, help);
+            // Writing to a String can not fail.
+            let _ = writeln!(output, "{} {}", name, value);
+        }
+
+        write_header(
+            &mut output,
+            "dtracker_http_responses_total",
+            "counter",
+            "HTTP responses sent, by status code.",
+        );
+        for (code, count) in self.lock_responses().iter() {
+            // Writing to a String can not fail.
+            let _ = writeln!(
+                output,
+                "dtracker_http_responses_total{{code=\"{}\"}} {}",
+                code, count
+            );
+        }
+        output
+    }
+
+    fn lock_responses(&self) -> MutexGuard<'_, BTreeMap<u16, u64>> {
+        self.responses.lock().unwrap() // unwrap is safe because no thread panics while holding the lock
+    }
+}
+
+fn write_header(output: &mut String, name: &str, metric_type: &str, help: &str) {
+    // Writing to a String can not fail.
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, metric_type);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http_server::thread_pool::pool::{ThreadPool, ThreadPoolConfig};
+
+    use super::*;
+
+    #[test]
+    fn test_render_in_prometheus_format() {
+        let pool = ThreadPool::new(ThreadPoolConfig::default());
+        let metrics = Metrics::new(pool.monitor());
+        metrics.record_response(&HttpStatus::Ok);
+        metrics.record_response(&HttpStatus::Ok);
+        metrics.record_response(&HttpStatus::BadRequest);
+
+        let output = metrics.render(&AtomicTrackerStatus::default(), &RateLimiter::new(1));
+
+        assert!(output.contains("# TYPE dtracker_torrents gauge\ndtracker_torrents 0\n"));
+        assert!(output.contains("dtracker_announces_total 0\n"));
+        assert!(output.contains("dtracker_thread_pool_queued_jobs 0\n"));
+        assert!(output.contains("dtracker_http_responses_total{code=\"200\"} 2\n"));
+        assert!(output.contains("dtracker_http_responses_total{code=\"400\"} 1\n"));
+    }
+}
@@ -1,3 +1,8 @@
+pub mod access_log;
+pub mod connection;
+pub mod metrics;
+pub mod rate_limiter;
 pub mod request_handler;
 pub mod server;
 pub mod thread_pool;
+pub mod tls;
@@ -0,0 +1,168 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+
+/// Default amount of announces a single IP can make per minute.
+pub const DEFAULT_MAX_ANNOUNCES_PER_MINUTE: u32 = 30;
+
+/// Limits the amount of announces each IP can make per minute, to protect the tracker from misbehaving clients.
+///
+/// Each IP gets a one minute window starting on its first announce, further announces in that window are
+/// rejected once the limit is reached.
+///
+/// ## Fields
+/// * `max_announces_per_minute`: The amount of announces allowed per IP in a window.
+/// * `windows`: The current window of every IP that announced in the last minute.
+/// * `last_cleanup`: When the expired windows were last removed.
+/// * `rejected_announces`: The total amount of announces rejected since the tracker started.
+#[derive(Debug)]
+pub struct RateLimiter {
+    max_announces_per_minute: u32,
+    windows: Mutex<HashMap<IpAddr, AnnounceWindow>>,
+    last_cleanup: Mutex<DateTime<Local>>,
+    rejected_announces: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AnnounceWindow {
+    start: DateTime<Local>,
+    announces: u32,
+}
+
+/// Struct containing the rate limiting counters of the tracker.
+///
+/// ## Fields
+/// * `limited_ips`: The amount of IPs currently over the limit.
+/// * `rejected_announces`: The total amount of announces rejected since the tracker started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimiterStats {
+    pub limited_ips: u32,
+    pub rejected_announces: u64,
+}
+
+impl RateLimiter {
+    /// Creates a new `RateLimiter`.
+    ///
+    /// ## Arguments
+    /// * `max_announces_per_minute`: The amount of announces allowed per IP per minute.
+    pub fn new(max_announces_per_minute: u32) -> Self {
+        Self {
+            max_announces_per_minute,
+            windows: Mutex::new(HashMap::new()),
+            last_cleanup: Mutex::new(Local::now()),
+            rejected_announces: AtomicU64::new(0),
+        }
+    }
+
+    /// Registers an announce from `ip` and returns whether it is allowed.
+    pub fn allow(&self, ip: IpAddr) -> bool {
+        self.allow_at(ip, Local::now())
+    }
+
+    fn allow_at(&self, ip: IpAddr, now: DateTime<Local>) -> bool {
+        self.remove_expired_windows(now);
+
+        let mut windows = self.lock_windows();
+        let window = windows.entry(ip).or_insert(AnnounceWindow {
+            start: now,
+            announces: 0,
+        });
+        if now.signed_duration_since(window.start) >= Duration::minutes(1) {
+            window.start = now;
+            window.announces = 0;
+        }
+
+        if window.announces >= self.max_announces_per_minute {
+            self.rejected_announces.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        window.announces += 1;
+        true
+    }
+
+    /// Returns the current rate limiting counters.
+    pub fn get_stats(&self) -> RateLimiterStats {
+        let now = Local::now();
+        let limited_ips = self
+            .lock_windows()
+            .values()
+            .filter(|window| {
+                window.announces >= self.max_announces_per_minute
+                    && now.signed_duration_since(window.start) < Duration::minutes(1)
+            })
+            .count() as u32;
+
+        RateLimiterStats {
+            limited_ips,
+            rejected_announces: self.rejected_announces.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Forgets the IPs whose window already ended, at most once per minute.
+    fn remove_expired_windows(&self, now: DateTime<Local>) {
+        let mut last_cleanup = self.last_cleanup.lock().unwrap(); // unwrap is safe because no thread panics while holding the lock
+        if now.signed_duration_since(*last_cleanup) < Duration::minutes(1) {
+            return;
+        }
+        *last_cleanup = now;
+        self.lock_windows()
+            .retain(|_, window| now.signed_duration_since(window.start) < Duration::minutes(1));
+    }
+
+    fn lock_windows(&self) -> MutexGuard<'_, HashMap<IpAddr, AnnounceWindow>> {
+        self.windows.lock().unwrap() // unwrap is safe because no thread panics while holding the lock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+    #[test]
+    fn test_announces_over_the_limit_are_rejected() {
+        let rate_limiter = RateLimiter::new(2);
+        let now = Local::now();
+
+        assert!(rate_limiter.allow_at(IP, now));
+        assert!(rate_limiter.allow_at(IP, now));
+        assert!(!rate_limiter.allow_at(IP, now));
+        assert_eq!(
+            rate_limiter.get_stats(),
+            RateLimiterStats {
+                limited_ips: 1,
+                rejected_announces: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_limit_is_per_ip() {
+        let rate_limiter = RateLimiter::new(1);
+        let now = Local::now();
+
+        assert!(rate_limiter.allow_at(IP, now));
+        assert!(rate_limiter.allow_at(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), now));
+    }
+
+    #[test]
+    fn test_limit_resets_after_a_minute() {
+        let rate_limiter = RateLimiter::new(1);
+        let now = Local::now();
+
+        assert!(rate_limiter.allow_at(IP, now));
+        assert!(!rate_limiter.allow_at(IP, now + Duration::seconds(30)));
+        assert!(rate_limiter.allow_at(IP, now + Duration::minutes(1)));
+    }
+}
@@ -1,21 +1,64 @@
 use std::{
-    io::{Read, Write},
-    net::TcpStream,
+    io::Write,
+    net::IpAddr,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
-use bencoder::bencode::Bencode;
+use bencoder::bencode::{Bencode, ToBencode};
+use chrono::Utc;
 
 use crate::{
+    admin::{admin_token::AdminToken, swarm_peers_response::SwarmPeersResponse},
     announce::announce_response::AnnounceResponse,
-    http::{http_method::HttpMethod, http_parser::Http, http_status::HttpStatus},
-    stats::{stats_response::StatsResponse, stats_updater::StatsUpdater},
+    http::{
+        http_method::HttpMethod,
+        http_parser::Http,
+        http_reader::{read_request, HttpReadError},
+        http_status::HttpStatus,
+        query_params::QueryParams,
+    },
+    http_server::{
+        access_log::AccessLogEntry, connection::Connection, metrics::Metrics,
+        rate_limiter::RateLimiter,
+    },
+    passkeys::passkey_registry::PasskeyRegistry,
+    scrape::scrape_response::ScrapeResponse,
+    stats::{
+        stats_response::StatsResponse, stats_updater::StatsUpdater,
+        torrent_stats_response::TorrentStatsResponse,
+    },
+    torrent_whitelist::whitelist::{TorrentWhitelist, WhitelistError},
+    tracker_peer::ip_policy::{IpPolicy, PeerAddress},
     tracker_status::atomic_tracker_status::AtomicTrackerStatus,
 };
+use url_encoder::info_hash::InfoHash;
+
+/// Initial capacity of the buffer an announce response is encoded into, enough for a compact response with 50 peers.
+const ANNOUNCE_RESPONSE_CAPACITY: usize = 512;
+
+/// Admin endpoints that change the state of the tracker, they only accept `POST` requests.
+const ADMIN_ACTIONS: [&str; 4] = [
+    "/whitelist",
+    "/admin/whitelist",
+    "/admin/ban",
+    "/admin/expire",
+];
 
 /// Struct that represents a connection capable of listening to requests and returning an answer.
 pub struct RequestHandler {
-    pub stream: TcpStream,
+    pub stream: Connection,
+    metrics: Arc<Metrics>,
+    admin_token: Arc<AdminToken>,
+    access_log: bool,
+    /// The access log entry of the request being handled, filled in as it is parsed and answered.
+    access_entry: Option<AccessLogEntry>,
+    /// Whether the request being handled is an announce.
+    announce: bool,
+    /// Whether the request being handled is for an admin endpoint, whose responses can not be read cross-origin.
+    admin: bool,
+    /// The status code of the response sent, if any.
+    status: Option<u16>,
 }
 
 #[derive(Debug)]
@@ -28,15 +71,40 @@ pub enum RequestHandlerError {
     WritingResponseError,
     InvalidQueryParamError,
     InvalidStatsError,
+    InvalidScrapeError,
+    InvalidWhitelistRequest,
+    InvalidAdminRequest,
+    Unauthorized,
+    MethodNotAllowed,
+    TooManyRequests,
+    RequestTooLarge,
+    WhitelistError(WhitelistError),
 }
 
 impl RequestHandler {
     /// Returns a new RequestHandler.
     ///
     /// ## Arguments
-    /// * `stream`: a TcpStream responsible of reading HTTP requests and sending a response.
-    pub fn new(stream: TcpStream) -> RequestHandler {
-        RequestHandler { stream }
+    /// * `stream`: a plain or TLS connection responsible of reading HTTP requests and sending a response.
+    /// * `metrics`: The server metrics, every response sent is counted there.
+    /// * `admin_token`: The token required by the `/whitelist` and `/admin/*` endpoints.
+    /// * `access_log`: Whether the request is written to the access log, see `AccessLogEntry`.
+    pub fn new(
+        stream: Connection,
+        metrics: Arc<Metrics>,
+        admin_token: Arc<AdminToken>,
+        access_log: bool,
+    ) -> RequestHandler {
+        RequestHandler {
+            stream,
+            metrics,
+            admin_token,
+            access_log,
+            access_entry: None,
+            announce: false,
+            admin: false,
+            status: None,
+        }
     }
 
     /// Handles a HTTP request and sends back a response in a successful scenario.
@@ -44,24 +112,85 @@ impl RequestHandler {
     ///
     /// ## Arguments
     /// * `tracker_status`: The status of the tracker at the moment of handling the request.
+    /// * `stats_updater`: The history of the tracker stats.
+    /// * `whitelist`: The torrents allowed on the tracker, editable through the `/whitelist` admin endpoint.
+    /// * `passkeys`: The users allowed to announce, with the passkey in `/announce/<passkey>` or the `passkey` parameter.
+    /// * `rate_limiter`: Limits the announces per IP, announces over the limit get a `429` response.
+    /// * `ip_policy`: Decides the IP each announcer is registered with.
     pub fn handle(
         &mut self,
         tracker_status: Arc<AtomicTrackerStatus>,
         stats_updater: Arc<StatsUpdater>,
+        whitelist: Arc<TorrentWhitelist>,
+        passkeys: Arc<PasskeyRegistry>,
+        rate_limiter: Arc<RateLimiter>,
+        ip_policy: Arc<IpPolicy>,
     ) -> Result<(), RequestHandlerError> {
-        // TODO: read HTTP message length correctly
-        let mut buf = [0; 1024];
-        let bytes_read = match self.stream.read(&mut buf) {
-            Ok(bytes_read) => bytes_read,
+        let received = Instant::now();
+        if self.access_log {
+            self.access_entry = self.get_peer_addr().ok().map(|ip| AccessLogEntry {
+                ip,
+                time: Utc::now(),
+                endpoint: None,
+                info_hash: None,
+                event: None,
+                user_agent: None,
+                status: 0,
+                bytes: 0,
+                latency: Duration::ZERO,
+            });
+        }
+
+        let result = self.handle_request(
+            tracker_status,
+            stats_updater.clone(),
+            whitelist,
+            passkeys,
+            rate_limiter,
+            ip_policy,
+        );
+
+        // Requests whose response could not be sent are not counted, their errors are logged.
+        let status = match self.status {
+            Some(status) => status,
+            None => return result,
+        };
+        let latency = received.elapsed();
+        stats_updater.record_request(self.announce, latency, status >= 400);
+        if let Some(mut entry) = self.access_entry.take() {
+            entry.status = status;
+            entry.latency = latency;
+            entry.log();
+        }
+        result
+    }
+
+    fn handle_request(
+        &mut self,
+        tracker_status: Arc<AtomicTrackerStatus>,
+        stats_updater: Arc<StatsUpdater>,
+        whitelist: Arc<TorrentWhitelist>,
+        passkeys: Arc<PasskeyRegistry>,
+        rate_limiter: Arc<RateLimiter>,
+        ip_policy: Arc<IpPolicy>,
+    ) -> Result<(), RequestHandlerError> {
+        let buf = match read_request(&mut self.stream) {
+            Ok(buf) => buf,
+            Err(HttpReadError::RequestLineTooLong) => {
+                self.send_response(vec![], HttpStatus::URITooLong)
+                    .map_err(|_| RequestHandlerError::WritingResponseError)?;
+                return Err(RequestHandlerError::RequestTooLarge);
+            }
+            Err(HttpReadError::HeadersTooLarge) => {
+                self.send_response(vec![], HttpStatus::RequestHeaderFieldsTooLarge)
+                    .map_err(|_| RequestHandlerError::WritingResponseError)?;
+                return Err(RequestHandlerError::RequestTooLarge);
+            }
             Err(_) => {
                 self.send_bad_request()?;
                 return Err(RequestHandlerError::BadRequest);
             }
         };
-        if bytes_read == 0 {
-            self.send_bad_request()?;
-            return Err(RequestHandlerError::BadRequest);
-        }
 
         let http_request = match Http::parse(&buf).map_err(|_| RequestHandlerError::ParseHttpError)
         {
@@ -72,29 +201,103 @@ impl RequestHandler {
             }
         };
 
-        let (status_line, response) = if http_request.method.eq(&HttpMethod::Get) {
-            let response = match http_request.endpoint.as_str() {
-                "/announce" => {
-                    self.handle_announce(http_request, tracker_status, self.get_peer_ip()?)
+        let remote_address = ip_policy.remote_address(self.get_peer_addr()?, &http_request);
+
+        let (endpoint, passkey) = match http_request.endpoint.strip_prefix("/announce/") {
+            Some(passkey) => ("/announce".to_string(), Some(passkey.to_string())),
+            None => (
+                http_request.endpoint.clone(),
+                http_request.params.get("passkey").map(str::to_string),
+            ),
+        };
+        self.announce = endpoint == "/announce";
+        self.admin = endpoint == "/whitelist" || endpoint.starts_with("/admin/");
+        if let Some(entry) = &mut self.access_entry {
+            entry.ip = remote_address.ip;
+            entry.endpoint = Some(endpoint.clone());
+            entry.info_hash = http_request
+                .params
+                .get_bytes("info_hash")
+                .and_then(|info_hash| InfoHash::from_bytes(info_hash).ok());
+            entry.event = http_request.params.get("event").map(str::to_string);
+            entry.user_agent = http_request.header("user-agent").map(str::to_string);
+        }
+
+        let method = if ADMIN_ACTIONS.contains(&endpoint.as_str()) {
+            HttpMethod::Post
+        } else {
+            HttpMethod::Get
+        };
+        if http_request.method != method {
+            self.send_response(vec![], HttpStatus::MethodNotAllowed)
+                .map_err(|_| RequestHandlerError::WritingResponseError)?;
+            return Err(RequestHandlerError::MethodNotAllowed);
+        }
+
+        let response = match endpoint.as_str() {
+            "/announce" if !rate_limiter.allow(remote_address.ip) => {
+                let response = AnnounceResponse::create_error_response(
+                    "Too many announces, slow down".to_string(),
+                );
+                self.send_response(Bencode::encode(&response), HttpStatus::TooManyRequests)
+                    .map_err(|_| RequestHandlerError::WritingResponseError)?;
+                return Err(RequestHandlerError::TooManyRequests);
+            }
+            "/announce" => Self::handle_announce(
+                http_request,
+                tracker_status,
+                &whitelist,
+                &passkeys,
+                passkey.as_deref(),
+                remote_address,
+                &ip_policy,
+            ),
+            "/scrape" => match Self::handle_scrape(http_request, tracker_status) {
+                Ok(response) => response,
+                Err(err) => {
+                    self.send_bad_request()?;
+                    return Err(err);
+                }
+            },
+            "/stats" => match self.handle_stats(http_request, stats_updater, &rate_limiter) {
+                Ok(response) => response,
+                Err(_) => {
+                    self.send_bad_request()?;
+                    return Err(RequestHandlerError::BadRequest);
+                }
+            },
+            "/stats/torrent" => match Self::handle_torrent_stats(http_request, stats_updater) {
+                Ok(response) => response,
+                Err(_) => {
+                    self.send_bad_request()?;
+                    return Err(RequestHandlerError::BadRequest);
                 }
-                "/stats" => match self.handle_stats(http_request, stats_updater) {
+            },
+            "/metrics" => self
+                .metrics
+                .render(&tracker_status, &rate_limiter)
+                .into_bytes(),
+            admin_endpoint if self.admin => {
+                match self.handle_admin(admin_endpoint, http_request, tracker_status, &whitelist) {
                     Ok(response) => response,
-                    Err(_) => {
+                    Err(RequestHandlerError::Unauthorized) => {
+                        self.send_response(vec![], HttpStatus::Unauthorized)
+                            .map_err(|_| RequestHandlerError::WritingResponseError)?;
+                        return Err(RequestHandlerError::Unauthorized);
+                    }
+                    Err(err) => {
                         self.send_bad_request()?;
-                        return Err(RequestHandlerError::BadRequest);
+                        return Err(err);
                     }
-                },
-                _ => {
-                    self.send_bad_request()?;
-                    return Err(RequestHandlerError::InvalidEndpointError);
                 }
-            };
-            (HttpStatus::Ok, response)
-        } else {
-            (HttpStatus::NotFound, "".as_bytes().to_vec())
+            }
+            _ => {
+                self.send_bad_request()?;
+                return Err(RequestHandlerError::InvalidEndpointError);
+            }
         };
 
-        self.send_response(response, status_line)
+        self.send_response(response, HttpStatus::Ok)
             .map_err(|_| RequestHandlerError::WritingResponseError)?;
 
         Ok(())
@@ -107,24 +310,169 @@ impl RequestHandler {
     }
 
     fn handle_announce(
-        &self,
         http_request: Http,
         tracker_status: Arc<AtomicTrackerStatus>,
-        peer_ip: String,
+        whitelist: &TorrentWhitelist,
+        passkeys: &PasskeyRegistry,
+        passkey: Option<&str>,
+        remote_address: PeerAddress,
+        ip_policy: &IpPolicy,
     ) -> Vec<u8> {
-        let response = AnnounceResponse::from(http_request.params, tracker_status, peer_ip);
+        let response = AnnounceResponse::from(
+            http_request.params,
+            tracker_status,
+            whitelist,
+            passkeys,
+            passkey,
+            remote_address,
+            ip_policy,
+        );
+        // Encoded straight into a single buffer, since every announce goes through here.
+        let mut encoded = Vec::with_capacity(ANNOUNCE_RESPONSE_CAPACITY);
         match response.failure_reason {
-            Some(failure) => Bencode::encode(&failure),
-            None => Bencode::encode(&response),
+            Some(failure) => failure.to_bencode().encode_into(&mut encoded),
+            None => response.to_bencode().encode_into(&mut encoded),
         }
+        encoded
+    }
+
+    fn handle_scrape(
+        http_request: Http,
+        tracker_status: Arc<AtomicTrackerStatus>,
+    ) -> Result<Vec<u8>, RequestHandlerError> {
+        let response = ScrapeResponse::from(http_request.params, tracker_status)
+            .map_err(|_| RequestHandlerError::InvalidScrapeError)?;
+        Ok(Bencode::encode(&response))
+    }
+
+    /// Handles the endpoints that operate on the tracker at runtime, which require the admin token in an
+    /// `Authorization: Bearer <token>` header, so it is not part of the URL:
+    /// * `/whitelist` or `/admin/whitelist`: Adds or removes a torrent from the whitelist, with a `POST` request.
+    /// * `/admin/ban`: Bans or unbans a peer id or an IP, with a `POST` request.
+    /// * `/admin/peers`: Lists the peers of a swarm.
+    /// * `/admin/expire`: Removes one or every peer of a swarm before they time out, with a `POST` request.
+    fn handle_admin(
+        &self,
+        endpoint: &str,
+        http_request: Http,
+        tracker_status: Arc<AtomicTrackerStatus>,
+        whitelist: &TorrentWhitelist,
+    ) -> Result<Vec<u8>, RequestHandlerError> {
+        if !self
+            .admin_token
+            .is_authorized(http_request.header("authorization"))
+        {
+            return Err(RequestHandlerError::Unauthorized);
+        }
+        let params = http_request.params;
+
+        match endpoint {
+            "/whitelist" | "/admin/whitelist" => Self::handle_whitelist(&params, whitelist),
+            "/admin/ban" => Self::handle_ban(&params, &tracker_status),
+            "/admin/peers" => {
+                let response = SwarmPeersResponse::from(&params, tracker_status)
+                    .map_err(|_| RequestHandlerError::InvalidAdminRequest)?;
+                Ok(serde_json::to_string(&response)
+                    .map_err(|_| RequestHandlerError::InvalidAdminRequest)?
+                    .into_bytes())
+            }
+            "/admin/expire" => Self::handle_expire(&params, &tracker_status),
+            _ => Err(RequestHandlerError::InvalidEndpointError),
+        }
+    }
+
+    /// Adds or removes a torrent from the whitelist.
+    ///
+    /// Expects the `action` (`add` or `remove`) and the hex encoded `info_hash` as query parameters.
+    fn handle_whitelist(
+        params: &QueryParams,
+        whitelist: &TorrentWhitelist,
+    ) -> Result<Vec<u8>, RequestHandlerError> {
+        let info_hash = params
+            .get("info_hash")
+            .and_then(|info_hash| InfoHash::from_hex(info_hash).ok())
+            .ok_or(RequestHandlerError::InvalidWhitelistRequest)?;
+
+        let changed = match params.get("action") {
+            Some("add") => whitelist.add(info_hash),
+            Some("remove") => whitelist.remove(&info_hash),
+            _ => return Err(RequestHandlerError::InvalidWhitelistRequest),
+        }
+        .map_err(RequestHandlerError::WhitelistError)?;
+
+        Ok(changed.to_string().into_bytes())
+    }
+
+    /// Bans or unbans a peer from the tracker, banning removes it from every swarm.
+    ///
+    /// Expects the `action` (`ban` or `unban`) and either an `ip` or a percent-encoded `peer_id` as query parameters.
+    fn handle_ban(
+        params: &QueryParams,
+        tracker_status: &AtomicTrackerStatus,
+    ) -> Result<Vec<u8>, RequestHandlerError> {
+        let ban = match params.get("action") {
+            Some("ban") => true,
+            Some("unban") => false,
+            _ => return Err(RequestHandlerError::InvalidAdminRequest),
+        };
+
+        let changed = match (params.get("ip"), Self::get_peer_id(params)?) {
+            (Some(ip), None) => {
+                let ip = ip
+                    .parse::<IpAddr>()
+                    .map_err(|_| RequestHandlerError::InvalidAdminRequest)?;
+                if ban {
+                    tracker_status.ban_ip(ip)
+                } else {
+                    tracker_status.unban_ip(&ip)
+                }
+            }
+            (None, Some(peer_id)) if ban => tracker_status.ban_peer(peer_id),
+            (None, Some(peer_id)) => tracker_status.unban_peer(&peer_id),
+            _ => return Err(RequestHandlerError::InvalidAdminRequest),
+        };
+
+        Ok(changed.to_string().into_bytes())
+    }
+
+    /// Removes peers from a swarm, they are added back if they announce again.
+    ///
+    /// Expects the hex encoded `info_hash` and optionally the percent-encoded `peer_id` to remove as query parameters,
+    /// every peer of the swarm is removed if it is absent. Responds with the amount of peers removed.
+    fn handle_expire(
+        params: &QueryParams,
+        tracker_status: &AtomicTrackerStatus,
+    ) -> Result<Vec<u8>, RequestHandlerError> {
+        let info_hash = params
+            .get("info_hash")
+            .and_then(|info_hash| InfoHash::from_hex(info_hash).ok())
+            .ok_or(RequestHandlerError::InvalidAdminRequest)?;
+        let peer_id = Self::get_peer_id(params)?;
+
+        Ok(tracker_status
+            .expire_peers(&info_hash, peer_id)
+            .to_string()
+            .into_bytes())
+    }
+
+    fn get_peer_id(params: &QueryParams) -> Result<Option<[u8; 20]>, RequestHandlerError> {
+        params
+            .get_bytes("peer_id")
+            .map(|peer_id| {
+                peer_id
+                    .try_into()
+                    .map_err(|_| RequestHandlerError::InvalidAdminRequest)
+            })
+            .transpose()
     }
 
     fn handle_stats(
         &self,
         http_request: Http,
         stats_updater: Arc<StatsUpdater>,
+        rate_limiter: &RateLimiter,
     ) -> Result<Vec<u8>, RequestHandlerError> {
-        let response = StatsResponse::from(http_request.params, stats_updater)
+        let response = StatsResponse::from(http_request.params, stats_updater, rate_limiter)
             .map_err(|_| RequestHandlerError::InvalidStatsError)?;
         Ok(serde_json::to_string(&response)
             .map_err(|_| RequestHandlerError::InvalidStatsError)?
@@ -132,10 +480,28 @@ impl RequestHandler {
             .to_vec())
     }
 
-    fn create_response(mut contents: Vec<u8>, status_line: HttpStatus) -> Vec<u8> {
+    fn handle_torrent_stats(
+        http_request: Http,
+        stats_updater: Arc<StatsUpdater>,
+    ) -> Result<Vec<u8>, RequestHandlerError> {
+        let response = TorrentStatsResponse::from(http_request.params, stats_updater)
+            .map_err(|_| RequestHandlerError::InvalidStatsError)?;
+        Ok(serde_json::to_string(&response)
+            .map_err(|_| RequestHandlerError::InvalidStatsError)?
+            .into_bytes())
+    }
+
+    /// Creates the response, the admin responses are not allowed to be read by the pages of other origins.
+    fn create_response(mut contents: Vec<u8>, status_line: HttpStatus, admin: bool) -> Vec<u8> {
+        let cors = if admin {
+            ""
+        } else {
+            "Access-Control-Allow-Origin: *\r\n"
+        };
         let response = format!(
-            "HTTP/1.1 {}\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n",
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\n\r\n",
             status_line.to_string(),
+            cors,
             contents.len(),
         );
         let mut response = response.as_bytes().to_vec();
@@ -144,20 +510,25 @@ impl RequestHandler {
     }
 
     fn send_response(&mut self, contents: Vec<u8>, status_line: HttpStatus) -> std::io::Result<()> {
-        let response = Self::create_response(contents, status_line);
+        self.metrics.record_response(&status_line);
+        if let Some(entry) = &mut self.access_entry {
+            entry.bytes = contents.len();
+        }
+        let status = status_line.code();
+        let response = Self::create_response(contents, status_line, self.admin);
 
         self.stream.write_all(&response)?;
         self.stream.flush()?;
+        self.status = Some(status);
 
         Ok(())
     }
 
-    fn get_peer_ip(&self) -> Result<String, RequestHandlerError> {
+    fn get_peer_addr(&self) -> Result<IpAddr, RequestHandlerError> {
         Ok(self
             .stream
             .peer_addr()
             .map_err(|_| RequestHandlerError::GettingPeerIpError)?
-            .ip()
-            .to_string())
+            .ip())
     }
 }
@@ -1,62 +1,161 @@
-use std::{net::TcpListener, sync::Arc};
+use std::{net::TcpListener, sync::Arc, time::Duration};
 
-use crate::http_server::request_handler::RequestHandler;
+use native_tls::TlsAcceptor;
+
+use crate::admin::admin_token::AdminToken;
+use crate::http_server::{
+    connection::Connection, metrics::Metrics, rate_limiter::RateLimiter,
+    request_handler::RequestHandler,
+};
+use crate::passkeys::passkey_registry::PasskeyRegistry;
 use crate::stats::stats_updater::StatsUpdater;
+use crate::torrent_whitelist::whitelist::TorrentWhitelist;
+use crate::tracker_peer::ip_policy::IpPolicy;
 use crate::{
-    http_server::thread_pool::pool::ThreadPool,
+    http_server::thread_pool::pool::{ThreadPool, ThreadPoolConfig},
     tracker_status::atomic_tracker_status::AtomicTrackerStatus,
 };
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+/// Seconds a client can take to send each part of its request before the connection is dropped.
+const READ_TIMEOUT_SECS: u64 = 10;
 
 /// Struct that represents the HTTP Server that will listen to connections to the Tracker.
 ///
 /// ## Fields
 /// * `listener`: The TCP server binded to the socket, responsible of listening for connections.
-/// * `pool`: A thread pool that provides worker threads, in order to favor parallel execution.
+/// * `pool`: A thread pool that provides worker threads, in order to favor parallel execution. Connections are dropped when it is saturated.
 /// * `status`: Current status of the tracker.
+/// * `whitelist`: The torrents allowed on the tracker.
+/// * `passkeys`: The users allowed to announce on the tracker.
+/// * `rate_limiter`: Limits the announces each IP can make per minute.
+/// * `ip_policy`: Decides the IP each announcer is registered with.
+/// * `tls`: If present, connections are served over HTTPS using this acceptor.
+/// * `metrics`: The metrics of the server, served on `/metrics`.
+/// * `admin_token`: The token required by the admin endpoints.
+/// * `access_log`: Whether every request is written to the access log.
 /// * `logger_sender`: To log using the Logger.
 pub struct Server {
     listener: TcpListener,
     pool: ThreadPool,
     status: Arc<AtomicTrackerStatus>,
     stats_updater: Arc<StatsUpdater>,
+    whitelist: Arc<TorrentWhitelist>,
+    passkeys: Arc<PasskeyRegistry>,
+    rate_limiter: Arc<RateLimiter>,
+    ip_policy: Arc<IpPolicy>,
+    tls: Option<TlsAcceptor>,
+    metrics: Arc<Metrics>,
+    admin_token: Arc<AdminToken>,
+    access_log: bool,
     port: u16,
 }
 
+/// Settings of the `Server`.
+///
+/// ## Fields
+/// * `port`: The port to listen on.
+/// * `tls`: If present, connections are served over HTTPS using this acceptor.
+/// * `pool_config`: The sizing of the pool of threads that handle the connections.
+/// * `admin_token`: The token required by the admin endpoints.
+pub struct ServerConfig {
+    pub port: u16,
+    pub tls: Option<TlsAcceptor>,
+    pub pool_config: ThreadPoolConfig,
+    pub admin_token: AdminToken,
+}
+
 impl Server {
     /// Creates a new `Server`.
     pub fn init(
         status: Arc<AtomicTrackerStatus>,
         stats_updater: Arc<StatsUpdater>,
-        port: u16,
+        whitelist: Arc<TorrentWhitelist>,
+        passkeys: Arc<PasskeyRegistry>,
+        rate_limiter: Arc<RateLimiter>,
+        ip_policy: Arc<IpPolicy>,
+        config: ServerConfig,
     ) -> std::io::Result<Server> {
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", port))?;
+        let listener = TcpListener::bind(format!("0.0.0.0:{}", config.port))?;
+        let pool = ThreadPool::new(config.pool_config);
+        let metrics = Arc::new(Metrics::new(pool.monitor()));
         Ok(Server {
             listener,
-            pool: ThreadPool::new(1000),
+            pool,
             status,
             stats_updater,
-            port,
+            whitelist,
+            passkeys,
+            rate_limiter,
+            ip_policy,
+            tls: config.tls,
+            metrics,
+            admin_token: Arc::new(config.admin_token),
+            access_log: false,
+            port: config.port,
         })
     }
 
+    /// Writes every request to the access log if `access_log` is true, see `AccessLogEntry`.
+    pub fn with_access_log(mut self, access_log: bool) -> Self {
+        self.access_log = access_log;
+        self
+    }
+
     /// Handles new connections to the server
     pub fn serve(&self) -> std::io::Result<()> {
-        info!("Serving on http://0.0.0.0:{}", self.port);
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        info!("Serving on {}://0.0.0.0:{}", scheme, self.port);
 
         for stream in self.listener.incoming() {
             let stream = stream?;
-            let mut request_handler = RequestHandler::new(stream);
+            // Requests are read until the end of their headers, so a client that stops sending must not keep a worker.
+            if let Err(error) =
+                stream.set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT_SECS)))
+            {
+                warn!(
+                    "Could not set the read timeout of a connection: {:?}",
+                    error
+                );
+            }
+            let tls = self.tls.clone();
             let status_clone = self.status.clone();
             let stats_updater = self.stats_updater.clone();
-            let _ = self.pool.execute(move || {
-                if let Err(error) = request_handler.handle(status_clone, stats_updater) {
+            let whitelist = self.whitelist.clone();
+            let passkeys = self.passkeys.clone();
+            let rate_limiter = self.rate_limiter.clone();
+            let ip_policy = self.ip_policy.clone();
+            let metrics = self.metrics.clone();
+            let admin_token = self.admin_token.clone();
+            let access_log = self.access_log;
+            let result = self.pool.execute(move || {
+                // The handshake is done by the worker so a slow client does not block new connections.
+                let connection = match tls {
+                    Some(acceptor) => match acceptor.accept(stream) {
+                        Ok(stream) => Connection::Tls(Box::new(stream)),
+                        Err(error) => return error!("TLS handshake failed: {:?}", error),
+                    },
+                    None => Connection::Plain(stream),
+                };
+                let mut request_handler =
+                    RequestHandler::new(connection, metrics, admin_token, access_log);
+                if let Err(error) = request_handler.handle(
+                    status_clone,
+                    stats_updater,
+                    whitelist,
+                    passkeys,
+                    rate_limiter,
+                    ip_policy,
+                ) {
                     error!(
                         "An error occurred while attempting to handle a request: {:?}",
                         error
                     );
                 }
             });
+            if let Err(error) = result {
+                warn!("Dropping connection, no worker available: {:?}", error);
+            }
         }
         Ok(())
     }
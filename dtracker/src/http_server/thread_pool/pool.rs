@@ -1,77 +1,255 @@
-use std::sync::{
-    mpsc::{self, channel, Sender},
-    Arc, Mutex,
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
-use crate::http_server::thread_pool::worker::{Message, Worker};
+use crate::http_server::thread_pool::worker::{SharedState, Worker};
 use tracing::{error, info};
 
+/// Default maximum amount of worker threads.
+pub const DEFAULT_MAX_WORKERS: usize = 256;
+/// Default maximum amount of jobs waiting for a worker.
+pub const DEFAULT_MAX_QUEUED_JOBS: usize = 1024;
+/// Default time an idle worker waits for a job before exiting.
+pub const DEFAULT_IDLE_TIMEOUT_SECONDS: u64 = 60;
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum ThreadPoolError {
-    MessageSendError(mpsc::SendError<Message>),
+    QueueFull,
+    Terminated,
 }
 
-/// Struct that represents a thread pool that spawns a specified number of worker threads and allows to process connections concurrently.
-/// Each idle thread in the pool is available to handle a task.
-/// When a thread is done processing its task, it is returned to the pool of idle threads, ready to handle a new task.
-pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: Sender<Message>,
+/// Sizing of a `ThreadPool`.
+///
+/// ## Fields
+/// * `max_workers`: The maximum amount of worker threads alive at the same time.
+/// * `max_queued_jobs`: The maximum amount of jobs waiting for a worker, further jobs are rejected.
+/// * `idle_timeout`: The time an idle worker waits for a job before exiting.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadPoolConfig {
+    pub max_workers: usize,
+    pub max_queued_jobs: usize,
+    pub idle_timeout: Duration,
 }
 
-impl ThreadPool {
-    /// Creates a new ThreadPool with a given size.
-    /// The size is the number of threads in the pool.
-    /// If the size is zero or a negative number, the `new` function will panic.
-    pub fn new(size: usize) -> ThreadPool {
-        assert!(size > 0);
+impl Default for ThreadPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_workers: DEFAULT_MAX_WORKERS,
+            max_queued_jobs: DEFAULT_MAX_QUEUED_JOBS,
+            idle_timeout: Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECONDS),
+        }
+    }
+}
 
-        let (sender, receiver) = channel();
+/// Read only view of the state of a `ThreadPool`, to monitor it from other threads.
+#[derive(Clone)]
+pub struct PoolMonitor {
+    shared: Arc<SharedState>,
+}
 
-        let receiver = Arc::new(Mutex::new(receiver));
+impl PoolMonitor {
+    /// Returns the amount of workers currently alive.
+    pub fn workers(&self) -> usize {
+        self.shared.lock().workers
+    }
 
-        let mut workers = Vec::with_capacity(size);
+    /// Returns the amount of workers waiting for a job.
+    pub fn idle_workers(&self) -> usize {
+        self.shared.lock().idle_workers
+    }
 
-        for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
-        }
+    /// Returns the amount of jobs waiting for a worker.
+    pub fn queued_jobs(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+}
 
-        ThreadPool { workers, sender }
+impl fmt::Debug for PoolMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolMonitor")
+            .field("workers", &self.workers())
+            .field("queued_jobs", &self.queued_jobs())
+            .finish()
+    }
+}
+
+/// Struct that represents a thread pool that allows to process connections concurrently.
+///
+/// Workers are spawned lazily when a job arrives and every worker is busy, up to `max_workers`.
+/// Workers that stay idle for `idle_timeout` exit, so the pool shrinks back when the load goes down.
+/// When every worker is busy jobs wait in a queue of at most `max_queued_jobs`, further jobs are rejected.
+pub struct ThreadPool {
+    config: ThreadPoolConfig,
+    shared: Arc<SharedState>,
+    workers: Mutex<Vec<Worker>>,
+    next_worker_id: Mutex<usize>,
+}
+
+impl ThreadPool {
+    /// Creates a new ThreadPool without any worker.
+    ///
+    /// If `max_workers` is zero, the `new` function will panic.
+    pub fn new(config: ThreadPoolConfig) -> ThreadPool {
+        assert!(config.max_workers > 0);
+
+        ThreadPool {
+            config,
+            shared: Arc::new(SharedState::default()),
+            workers: Mutex::new(Vec::new()),
+            next_worker_id: Mutex::new(0),
+        }
     }
 
     /// Receives a closure and assigns it to a thread in the pool to run.
+    ///
+    /// Returns `QueueFull` if every worker is busy and the queue is full, the closure is dropped without running.
     pub fn execute<F>(&self, closure: F) -> Result<(), ThreadPoolError>
     where
         F: FnOnce() + Send + 'static,
     {
-        let job = Box::new(closure);
+        let mut state = self.shared.lock();
+        if state.terminating {
+            return Err(ThreadPoolError::Terminated);
+        }
 
-        self.sender
-            .send(Message::NewJob(job))
-            .map_err(ThreadPoolError::MessageSendError)?;
+        // Idle workers already woken up for the queued jobs can not take this one.
+        let must_spawn =
+            state.queue.len() >= state.idle_workers && state.workers < self.config.max_workers;
+        if !must_spawn && state.queue.len() >= self.config.max_queued_jobs {
+            return Err(ThreadPoolError::QueueFull);
+        }
 
+        state.queue.push_back(Box::new(closure));
+        if must_spawn {
+            state.workers += 1;
+            drop(state);
+            self.spawn_worker();
+        } else {
+            drop(state);
+            self.shared.job_available.notify_one();
+        }
         Ok(())
     }
+
+    /// Returns the amount of workers currently alive.
+    pub fn workers(&self) -> usize {
+        self.shared.lock().workers
+    }
+
+    /// Returns a monitor of the state of the pool.
+    pub fn monitor(&self) -> PoolMonitor {
+        PoolMonitor {
+            shared: self.shared.clone(),
+        }
+    }
+
+    fn spawn_worker(&self) {
+        let mut next_worker_id = self.next_worker_id.lock().unwrap(); // unwrap is safe because no thread panics while holding the lock.
+        let id = *next_worker_id;
+        *next_worker_id += 1;
+
+        let mut workers = self.workers.lock().unwrap(); // unwrap is safe because no thread panics while holding the lock.
+        workers.retain(|worker| !worker.thread.is_finished());
+        workers.push(Worker::spawn(
+            id,
+            self.shared.clone(),
+            self.config.idle_timeout,
+        ));
+    }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
         info!("Sending terminate message to all workers.");
+        self.shared.lock().terminating = true;
+        self.shared.job_available.notify_all();
 
-        for _ in &self.workers {
-            if self.sender.send(Message::Terminate).is_err() {
-                error!("An error occurred while attempting to drop the thread pool.");
+        info!("Shutting down all workers.");
+        let workers = match self.workers.get_mut() {
+            Ok(workers) => workers,
+            Err(_) => return error!("An error occurred while attempting to drop the thread pool."),
+        };
+        for worker in workers.drain(..) {
+            info!("Shutting down worker {}", worker.id);
+            if worker.thread.join().is_err() {
+                error!("An error occurred while attempting to join a thread pool thread.");
             };
         }
+    }
+}
 
-        info!("Shutting down all workers.");
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{mpsc::channel, Barrier},
+        thread::sleep,
+    };
 
-        for worker in &mut self.workers {
-            info!("Shutting down worker {}", worker.id);
-            if let Some(thread) = worker.thread.take() {
-                if thread.join().is_err() {
-                    error!("An error occurred while attempting to join a thread pool thread.");
-                };
-            }
+    use super::*;
+
+    #[test]
+    fn test_workers_are_spawned_lazily() {
+        let pool = ThreadPool::new(ThreadPoolConfig::default());
+
+        assert_eq!(pool.workers(), 0);
+    }
+
+    #[test]
+    fn test_runs_jobs_concurrently() {
+        let pool = ThreadPool::new(ThreadPoolConfig::default());
+        let barrier = Arc::new(Barrier::new(3));
+        let (sender, receiver) = channel();
+
+        for _ in 0..2 {
+            let barrier = barrier.clone();
+            let sender = sender.clone();
+            pool.execute(move || {
+                barrier.wait();
+                sender.send(()).unwrap();
+            })
+            .unwrap();
         }
+        barrier.wait();
+
+        assert_eq!(receiver.iter().take(2).count(), 2);
+        assert_eq!(pool.workers(), 2);
+    }
+
+    #[test]
+    fn test_rejects_jobs_when_the_queue_is_full() {
+        let pool = ThreadPool::new(ThreadPoolConfig {
+            max_workers: 1,
+            max_queued_jobs: 1,
+            idle_timeout: Duration::from_secs(60),
+        });
+        let (started_sender, started_receiver) = channel();
+        let (sender, receiver) = channel::<()>();
+
+        pool.execute(move || {
+            started_sender.send(()).unwrap();
+            let _ = receiver.recv();
+        })
+        .unwrap();
+        started_receiver.recv().unwrap();
+        pool.execute(|| ()).unwrap();
+
+        assert_eq!(pool.execute(|| ()), Err(ThreadPoolError::QueueFull));
+        drop(sender);
+    }
+
+    #[test]
+    fn test_idle_workers_exit() {
+        let pool = ThreadPool::new(ThreadPoolConfig {
+            max_workers: 4,
+            max_queued_jobs: 4,
+            idle_timeout: Duration::from_millis(10),
+        });
+
+        pool.execute(|| ()).unwrap();
+        sleep(Duration::from_millis(200));
+
+        assert_eq!(pool.workers(), 0);
     }
 }
@@ -1,45 +1,88 @@
 use std::{
-    sync::{mpsc::Receiver, Arc, Mutex},
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
     thread,
+    time::Duration,
 };
 
 use tracing::info;
 
-type Job = Box<dyn FnOnce() + Send + 'static>;
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
 
-pub enum Message {
-    NewJob(Job),
-    Terminate,
+/// State shared by the `ThreadPool` and its workers.
+///
+/// ## Fields
+/// * `queue`: The jobs waiting for a worker.
+/// * `workers`: The amount of workers currently alive.
+/// * `idle_workers`: The amount of workers waiting for a job.
+/// * `terminating`: Set when the pool is dropped, workers finish the queued jobs and exit.
+#[derive(Default)]
+pub struct PoolState {
+    pub queue: VecDeque<Job>,
+    pub workers: usize,
+    pub idle_workers: usize,
+    pub terminating: bool,
 }
-/// Struct responsible for sending code from the ThreadPool to a Thread.
+
+/// The `PoolState` with the condition variable used to wake up idle workers.
+#[derive(Default)]
+pub struct SharedState {
+    pub state: Mutex<PoolState>,
+    pub job_available: Condvar,
+}
+
+impl SharedState {
+    pub fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap() // unwrap is safe because jobs run without holding the lock.
+    }
+}
+
+/// Struct responsible for running the jobs queued in the ThreadPool on its own thread.
 pub struct Worker {
-    // TODO: solve public attributes
     pub id: usize,
-    pub thread: Option<thread::JoinHandle<()>>,
+    pub thread: thread::JoinHandle<()>,
 }
 
 impl Worker {
-    /// Returns a new Worker instance that holds the `id` and a thread spawned with an empty closure.
-    pub fn new(id: usize, receiver: Arc<Mutex<Receiver<Message>>>) -> Worker {
-        let thread = thread::spawn(move || loop {
-            while let Ok(message) = receiver.lock().unwrap().recv() {
-                // unwrap is safe because we are the only one using the Receiver.
-                match message {
-                    Message::NewJob(job) => {
-                        info!("Worker {} got a job; executing.", id);
-                        job();
-                    }
-                    Message::Terminate => {
-                        info!("Worker {} was told to terminate.", id);
-                        break;
-                    }
-                }
+    /// Spawns a worker that runs queued jobs until the pool terminates or it stays idle for `idle_timeout`.
+    ///
+    /// The caller must have already counted the worker in `PoolState::workers`.
+    pub fn spawn(id: usize, shared: Arc<SharedState>, idle_timeout: Duration) -> Worker {
+        let thread = thread::spawn(move || {
+            while let Some(job) = Self::next_job(&shared, idle_timeout) {
+                info!("Worker {} got a job; executing.", id);
+                job();
             }
+            info!("Worker {} shutting down.", id);
         });
 
-        Worker {
-            id,
-            thread: Some(thread),
+        Worker { id, thread }
+    }
+
+    /// Waits for the next job, returns `None` when the worker should exit.
+    fn next_job(shared: &SharedState, idle_timeout: Duration) -> Option<Job> {
+        let mut state = shared.lock();
+        loop {
+            if let Some(job) = state.queue.pop_front() {
+                return Some(job);
+            }
+            if state.terminating {
+                state.workers -= 1;
+                return None;
+            }
+
+            state.idle_workers += 1;
+            let (new_state, timeout) = shared
+                .job_available
+                .wait_timeout(state, idle_timeout)
+                .unwrap(); // unwrap is safe because jobs run without holding the lock.
+            state = new_state;
+            state.idle_workers -= 1;
+
+            if timeout.timed_out() && state.queue.is_empty() && !state.terminating {
+                state.workers -= 1;
+                return None;
+            }
         }
     }
 }
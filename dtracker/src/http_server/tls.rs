@@ -0,0 +1,70 @@
+use std::{fs, io, path::PathBuf};
+
+use native_tls::{Identity, TlsAcceptor};
+
+/// Paths of the certificate and private key used to serve over HTTPS.
+///
+/// ## Fields
+/// * `cert_path`: PEM encoded certificate chain, starting with the server certificate.
+/// * `key_path`: PEM encoded PKCS #8 private key of the certificate.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+/// Posible errors loading the TLS configuration.
+#[derive(Debug)]
+pub enum TlsError {
+    Io(io::Error),
+    InvalidIdentity(native_tls::Error),
+}
+
+impl TlsConfig {
+    /// Creates a new `TlsConfig`.
+    pub fn new(cert_path: PathBuf, key_path: PathBuf) -> Self {
+        Self {
+            cert_path,
+            key_path,
+        }
+    }
+
+    /// Reads the certificate and key and builds the acceptor used to terminate TLS connections.
+    ///
+    /// Returns an error if any of the files can not be read or they are not a valid certificate and key pair.
+    pub fn acceptor(&self) -> Result<TlsAcceptor, TlsError> {
+        let cert = fs::read(&self.cert_path).map_err(TlsError::Io)?;
+        let key = fs::read(&self.key_path).map_err(TlsError::Io)?;
+        let identity = Identity::from_pkcs8(&cert, &key).map_err(TlsError::InvalidIdentity)?;
+        TlsAcceptor::new(identity).map_err(TlsError::InvalidIdentity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn test_missing_files_are_an_io_error() {
+        let config = TlsConfig::new(
+            PathBuf::from("missing_cert.pem"),
+            PathBuf::from("missing_key.pem"),
+        );
+
+        assert!(matches!(config.acceptor(), Err(TlsError::Io(_))));
+    }
+
+    #[test]
+    fn test_invalid_certificate_is_rejected() {
+        let path = env::temp_dir().join("dtracker_test_invalid_cert.pem");
+        fs::write(&path, "not a certificate").unwrap();
+        let config = TlsConfig::new(path.clone(), path.clone());
+
+        let result = config.acceptor();
+        fs::remove_file(path).unwrap();
+
+        assert!(matches!(result, Err(TlsError::InvalidIdentity(_))));
+    }
+}
@@ -1,8 +1,12 @@
+pub mod admin;
 pub mod announce;
 pub mod bt_tracker;
 pub mod http;
 pub mod http_server;
+pub mod passkeys;
+pub mod scrape;
 pub mod stats;
 pub mod torrent_swarm;
+pub mod torrent_whitelist;
 pub mod tracker_peer;
 pub mod tracker_status;
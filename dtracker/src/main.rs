@@ -1,21 +1,226 @@
-use dtracker::bt_tracker::tracker::BtTracker;
-use std::env;
-use tracing::error;
+use chrono::Duration;
+use dtracker::{
+    admin::admin_token::AdminToken,
+    bt_tracker::tracker::{BtTracker, TrackerAccess, TrackerLimits},
+    http_server::{
+        rate_limiter::DEFAULT_MAX_ANNOUNCES_PER_MINUTE, thread_pool::pool::ThreadPoolConfig,
+        tls::TlsConfig,
+    },
+    passkeys::passkey_registry::PasskeyRegistry,
+    torrent_whitelist::whitelist::TorrentWhitelist,
+    tracker_peer::ip_policy::{AnnouncedIpPolicy, IpPolicy},
+    tracker_status::atomic_tracker_status::{
+        SwarmLimits, TrackerTimeouts, DEFAULT_ANNOUNCE_INTERVAL_SECONDS,
+        DEFAULT_MIN_ANNOUNCE_INTERVAL_SECONDS, DEFAULT_PEER_TIMEOUT_SECONDS,
+        DEFAULT_REAPER_INTERVAL_SECONDS,
+    },
+};
+use std::{env, net::IpAddr, path::PathBuf};
+use tracing::{error, info};
+
+/// Environment variable with the path of the torrent whitelist, setting it turns private tracker mode on.
+const WHITELIST_ENV: &str = "DTRACKER_WHITELIST";
+/// Environment variable with the token required by the `/whitelist` and `/admin/*` endpoints, which are disabled if it is not set.
+const ADMIN_TOKEN_ENV: &str = "DTRACKER_ADMIN_TOKEN";
+/// Environment variable with the path of the passkeys and their users, setting it makes passkeys required to announce.
+const PASSKEYS_ENV: &str = "DTRACKER_PASSKEYS";
+/// Environment variable with the amount of announces each IP can make per minute.
+const MAX_ANNOUNCES_PER_MINUTE_ENV: &str = "DTRACKER_MAX_ANNOUNCES_PER_MINUTE";
+/// Environment variables with the maximum amount of torrents tracked and of peers of each torrent.
+const MAX_TORRENTS_ENV: &str = "DTRACKER_MAX_TORRENTS";
+const MAX_PEERS_PER_SWARM_ENV: &str = "DTRACKER_MAX_PEERS_PER_SWARM";
+/// Environment variables with the amount of peers sent to announces without `numwant` and the maximum sent to any announce.
+const DEFAULT_NUMWANT_ENV: &str = "DTRACKER_DEFAULT_NUMWANT";
+const MAX_NUMWANT_ENV: &str = "DTRACKER_MAX_NUMWANT";
+/// Environment variables with the maximum amount of threads handling connections and connections waiting for one.
+const MAX_WORKERS_ENV: &str = "DTRACKER_MAX_WORKERS";
+const MAX_QUEUED_CONNECTIONS_ENV: &str = "DTRACKER_MAX_QUEUED_CONNECTIONS";
+/// Environment variables with the seconds after which a silent peer is inactive and between removals of inactive peers.
+const PEER_TIMEOUT_ENV: &str = "DTRACKER_PEER_TIMEOUT_SECONDS";
+const REAPER_INTERVAL_ENV: &str = "DTRACKER_REAPER_INTERVAL_SECONDS";
+/// Environment variable with the comma separated IPs of the reverse proxies whose `X-Forwarded-For` and `X-Real-IP` headers are honored.
+const TRUSTED_PROXIES_ENV: &str = "DTRACKER_TRUSTED_PROXIES";
+/// Environment variable with when to honor the `ip` parameter of the announces: `ignore`, `private` or `always`.
+const ANNOUNCED_IP_POLICY_ENV: &str = "DTRACKER_ANNOUNCED_IP_POLICY";
+/// Environment variables with the PEM certificate and PKCS #8 key paths, setting both serves over HTTPS.
+const TLS_CERT_ENV: &str = "DTRACKER_TLS_CERT";
+const TLS_KEY_ENV: &str = "DTRACKER_TLS_KEY";
+/// Environment variable that turns the access log on when set to `1` or `true`.
+const ACCESS_LOG_ENV: &str = "DTRACKER_ACCESS_LOG";
 
 fn main() {
     // install global collector configured based on RUST_LOG env var.
     tracing_subscriber::fmt::init();
 
-    if env::args().count() != 2 {
-        return error!("Incorrect number of arguments. Only a port number should be passed");
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 4 {
+        return error!(
+            "Incorrect number of arguments. Usage: dtracker <port> [interval_seconds] [min_interval_seconds]"
+        );
+    };
+    let port = match args[1].parse::<u16>() {
+        Ok(port) => port,
+        Err(_) => return error!("Invalid port number"),
+    };
+    let announce_interval = match args.get(2).map(|s| s.parse::<i64>()) {
+        None => DEFAULT_ANNOUNCE_INTERVAL_SECONDS,
+        Some(Ok(interval)) if interval > 0 => interval,
+        Some(_) => return error!("Invalid announce interval"),
+    };
+    let min_announce_interval = match args.get(3).map(|s| s.parse::<i64>()) {
+        None => DEFAULT_MIN_ANNOUNCE_INTERVAL_SECONDS.min(announce_interval),
+        Some(Ok(interval)) if (0..=announce_interval).contains(&interval) => interval,
+        Some(_) => return error!("Invalid min announce interval"),
+    };
+
+    let peer_timeout = match env::var(PEER_TIMEOUT_ENV).map(|s| s.parse::<i64>()) {
+        Err(_) => DEFAULT_PEER_TIMEOUT_SECONDS,
+        Ok(Ok(timeout)) if timeout > 0 => timeout,
+        Ok(_) => return error!("Invalid peer timeout"),
+    };
+    let reaper_interval = match env::var(REAPER_INTERVAL_ENV).map(|s| s.parse::<i64>()) {
+        Err(_) => DEFAULT_REAPER_INTERVAL_SECONDS,
+        Ok(Ok(interval)) if interval > 0 => interval,
+        Ok(_) => return error!("Invalid reaper interval"),
+    };
+    let timeouts = TrackerTimeouts {
+        announce_interval: Duration::seconds(announce_interval),
+        min_announce_interval: Duration::seconds(min_announce_interval),
+        peer_timeout: Duration::seconds(peer_timeout),
+        reaper_interval: Duration::seconds(reaper_interval),
     };
-    let port = match env::args().last().unwrap() {
-        s if s.parse::<u16>().is_ok() => s.parse::<u16>().unwrap(),
-        _ => return error!("Invalid port number"),
+
+    let whitelist = match env::var(WHITELIST_ENV) {
+        Ok(path) => match TorrentWhitelist::load(PathBuf::from(&path)) {
+            Ok(whitelist) => {
+                info!("Private tracker mode on, whitelist loaded from {}", path);
+                whitelist
+            }
+            Err(err) => return error!("Could not load the whitelist: {:?}", err),
+        },
+        Err(_) => TorrentWhitelist::disabled(),
     };
 
-    match BtTracker::init(port) {
-        Ok(tracker) => match tracker.run() {
+    let passkeys = match env::var(PASSKEYS_ENV) {
+        Ok(path) => match PasskeyRegistry::load(PathBuf::from(&path)) {
+            Ok(passkeys) => {
+                info!("Passkeys required, users loaded from {}", path);
+                passkeys
+            }
+            Err(err) => return error!("Could not load the passkeys: {:?}", err),
+        },
+        Err(_) => PasskeyRegistry::disabled(),
+    };
+
+    let max_announces_per_minute = match env::var(MAX_ANNOUNCES_PER_MINUTE_ENV) {
+        Ok(max) => match max.parse::<u32>() {
+            Ok(max) if max > 0 => max,
+            _ => return error!("Invalid max announces per minute"),
+        },
+        Err(_) => DEFAULT_MAX_ANNOUNCES_PER_MINUTE,
+    };
+
+    let mut swarm_limits = SwarmLimits::default();
+    if let Ok(max_torrents) = env::var(MAX_TORRENTS_ENV) {
+        swarm_limits.max_torrents = match max_torrents.parse::<usize>() {
+            Ok(max_torrents) if max_torrents > 0 => max_torrents,
+            _ => return error!("Invalid max torrents"),
+        };
+    }
+    if let Ok(max_peers) = env::var(MAX_PEERS_PER_SWARM_ENV) {
+        swarm_limits.max_peers_per_swarm = match max_peers.parse::<usize>() {
+            Ok(max_peers) if max_peers > 0 => max_peers,
+            _ => return error!("Invalid max peers per swarm"),
+        };
+    }
+    if let Ok(default_numwant) = env::var(DEFAULT_NUMWANT_ENV) {
+        swarm_limits.default_numwant = match default_numwant.parse::<u32>() {
+            Ok(default_numwant) => default_numwant,
+            Err(_) => return error!("Invalid default numwant"),
+        };
+    }
+    if let Ok(max_numwant) = env::var(MAX_NUMWANT_ENV) {
+        swarm_limits.max_numwant = match max_numwant.parse::<u32>() {
+            Ok(max_numwant) if max_numwant > 0 => max_numwant,
+            _ => return error!("Invalid max numwant"),
+        };
+    }
+    if swarm_limits.default_numwant > swarm_limits.max_numwant {
+        return error!("The default numwant can not be larger than the max numwant");
+    }
+
+    let mut pool_config = ThreadPoolConfig::default();
+    if let Ok(max_workers) = env::var(MAX_WORKERS_ENV) {
+        pool_config.max_workers = match max_workers.parse::<usize>() {
+            Ok(max_workers) if max_workers > 0 => max_workers,
+            _ => return error!("Invalid max workers"),
+        };
+    }
+    if let Ok(max_queued) = env::var(MAX_QUEUED_CONNECTIONS_ENV) {
+        pool_config.max_queued_jobs = match max_queued.parse::<usize>() {
+            Ok(max_queued) => max_queued,
+            Err(_) => return error!("Invalid max queued connections"),
+        };
+    }
+
+    let trusted_proxies = match env::var(TRUSTED_PROXIES_ENV) {
+        Ok(proxies) => match proxies
+            .split(',')
+            .map(|ip| ip.trim().parse::<IpAddr>())
+            .collect::<Result<Vec<IpAddr>, _>>()
+        {
+            Ok(proxies) => proxies,
+            Err(_) => return error!("Invalid trusted proxies"),
+        },
+        Err(_) => Vec::new(),
+    };
+    let announced_ip_policy = match env::var(ANNOUNCED_IP_POLICY_ENV) {
+        Ok(policy) => match policy.parse::<AnnouncedIpPolicy>() {
+            Ok(policy) => policy,
+            Err(_) => return error!("Invalid announced ip policy"),
+        },
+        Err(_) => AnnouncedIpPolicy::PrivateOnly,
+    };
+
+    let tls = match (env::var(TLS_CERT_ENV), env::var(TLS_KEY_ENV)) {
+        (Ok(cert_path), Ok(key_path)) => Some(TlsConfig::new(
+            PathBuf::from(cert_path),
+            PathBuf::from(key_path),
+        )),
+        (Err(_), Err(_)) => None,
+        _ => {
+            return error!(
+                "Both {} and {} must be set to serve over HTTPS",
+                TLS_CERT_ENV, TLS_KEY_ENV
+            )
+        }
+    };
+
+    let access_log = match env::var(ACCESS_LOG_ENV).as_deref() {
+        Ok("1") | Ok("true") => true,
+        Ok("0") | Ok("false") | Err(_) => false,
+        Ok(_) => return error!("Invalid access log setting"),
+    };
+
+    match BtTracker::init(
+        port,
+        timeouts,
+        TrackerAccess {
+            whitelist,
+            passkeys,
+            admin_token: env::var(ADMIN_TOKEN_ENV)
+                .map(AdminToken::new)
+                .unwrap_or_default(),
+        },
+        TrackerLimits {
+            max_announces_per_minute,
+            swarms: swarm_limits,
+        },
+        tls,
+        pool_config,
+        IpPolicy::new(trusted_proxies, announced_ip_policy),
+    ) {
+        Ok(tracker) => match tracker.with_access_log(access_log).run() {
             Ok(_) => (),
             Err(e) => error!("Error: {:?}", e),
         },
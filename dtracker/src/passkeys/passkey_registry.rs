@@ -0,0 +1,279 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+
+use url_encoder::info_hash::InfoHash;
+
+use crate::{announce::announce_request::AnnounceRequest, tracker_peer::event::PeerEvent};
+
+/// Amount of data a user transferred, aggregated from the announces made with their passkey.
+///
+/// ## Fields
+/// * `uploaded`: The data uploaded by the user, in bytes.
+/// * `downloaded`: The data downloaded by the user, in bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UserStats {
+    pub uploaded: u64,
+    pub downloaded: u64,
+}
+
+/// Struct that represents the registry of users allowed to announce on a private tracker.
+///
+/// The registry is stored in a file with a passkey and the name of its user, separated by whitespace, per line.
+/// Empty lines and lines starting with `#` are ignored.
+///
+/// ## Fields
+/// * `users`: The user of each passkey. If `None`, passkeys are not required and every announce is allowed.
+/// * `transfers`: The stats of each user and the last counters announced by each of their peers.
+#[derive(Debug, Default)]
+pub struct PasskeyRegistry {
+    users: Option<HashMap<String, String>>,
+    transfers: Mutex<Transfers>,
+}
+
+/// The announced counters are totals of the current session of a peer, so the last ones are kept to add only
+/// what changed since the previous announce to the stats of the user.
+#[derive(Debug, Default)]
+struct Transfers {
+    stats: HashMap<String, UserStats>,
+    sessions: HashMap<(String, InfoHash, [u8; 20]), UserStats>,
+}
+
+/// Possible errors that can occur when using the passkey registry.
+#[derive(Debug)]
+pub enum PasskeyError {
+    Io(io::Error),
+    InvalidLine(String),
+    UnknownPasskey,
+}
+
+impl PasskeyRegistry {
+    /// Creates a registry that does not require passkeys, every announce is allowed.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Loads the registry from `path`, which makes passkeys required to announce.
+    ///
+    /// ## Arguments
+    /// * `path`: The file to load the passkeys and their users from.
+    pub fn load(path: PathBuf) -> Result<Self, PasskeyError> {
+        let contents = fs::read_to_string(path).map_err(PasskeyError::Io)?;
+        Ok(Self {
+            users: Some(Self::parse(&contents)?),
+            transfers: Mutex::new(Transfers::default()),
+        })
+    }
+
+    /// Returns `true` if passkeys are required to announce.
+    pub fn is_enabled(&self) -> bool {
+        self.users.is_some()
+    }
+
+    /// Returns `true` if an announce with `passkey` is allowed, that is if passkeys are not required or it is registered.
+    pub fn is_allowed(&self, passkey: Option<&str>) -> bool {
+        match &self.users {
+            Some(users) => passkey.is_some_and(|passkey| users.contains_key(passkey)),
+            None => true,
+        }
+    }
+
+    /// Returns the user of `passkey`, if registered.
+    pub fn user(&self, passkey: &str) -> Option<&str> {
+        self.users.as_ref()?.get(passkey).map(String::as_str)
+    }
+
+    /// Returns the stats of `user`, if they made any announce.
+    pub fn user_stats(&self, user: &str) -> Option<UserStats> {
+        self.lock().stats.get(user).copied()
+    }
+
+    /// Adds the data transferred since the previous announce of the peer to the stats of the user of `passkey`.
+    ///
+    /// A peer that stops ends its session, the counters of its next announce start over.
+    pub fn record_announce(
+        &self,
+        passkey: &str,
+        announce_request: &AnnounceRequest,
+    ) -> Result<(), PasskeyError> {
+        let user = self.user(passkey).ok_or(PasskeyError::UnknownPasskey)?;
+        let session = (
+            passkey.to_string(),
+            announce_request.info_hash,
+            announce_request.peer_id,
+        );
+        let announced = UserStats {
+            uploaded: announce_request.uploaded,
+            downloaded: announce_request.downloaded,
+        };
+
+        let mut transfers = self.lock();
+        let last = match announce_request.event {
+            Some(PeerEvent::Stopped) => transfers.sessions.remove(&session),
+            _ => transfers.sessions.insert(session, announced),
+        }
+        .unwrap_or_default();
+
+        let stats = transfers.stats.entry(user.to_string()).or_default();
+        stats.uploaded += Self::delta(last.uploaded, announced.uploaded);
+        stats.downloaded += Self::delta(last.downloaded, announced.downloaded);
+        Ok(())
+    }
+
+    /// Counters lower than the last ones mean the client restarted without stopping, so they are all new.
+    fn delta(last: u64, announced: u64) -> u64 {
+        if announced >= last {
+            announced - last
+        } else {
+            announced
+        }
+    }
+
+    fn parse(contents: &str) -> Result<HashMap<String, String>, PasskeyError> {
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let mut fields = line.split_whitespace();
+                match (fields.next(), fields.next(), fields.next()) {
+                    (Some(passkey), Some(user), None) => {
+                        Ok((passkey.to_string(), user.to_string()))
+                    }
+                    _ => Err(PasskeyError::InvalidLine(line.to_string())),
+                }
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Transfers> {
+        self.transfers.lock().unwrap() // Unwrap is safe here because the lock is never held while panicking.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::query_params::QueryParams;
+
+    use super::*;
+
+    const PASSKEY: &str = "0123456789abcdef";
+
+    #[test]
+    fn test_disabled_registry_allows_every_announce() {
+        let registry = PasskeyRegistry::disabled();
+
+        assert!(registry.is_allowed(None));
+        assert!(registry.is_allowed(Some("anything")));
+    }
+
+    #[test]
+    fn test_load_registry() {
+        let registry = load_test_registry("./test_load_registry.txt");
+
+        assert_eq!(registry.user(PASSKEY), Some("alice"));
+        assert!(registry.is_allowed(Some(PASSKEY)));
+        assert!(!registry.is_allowed(Some("unknown")));
+        assert!(!registry.is_allowed(None));
+    }
+
+    #[test]
+    fn test_load_registry_with_invalid_line() {
+        let path = PathBuf::from("./test_load_registry_with_invalid_line.txt");
+        fs::write(&path, "passkey-without-user\n").unwrap();
+
+        assert!(matches!(
+            PasskeyRegistry::load(path.clone()),
+            Err(PasskeyError::InvalidLine(_))
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_user_stats_are_aggregated_from_announces() {
+        let registry = load_test_registry("./test_user_stats_are_aggregated.txt");
+
+        registry
+            .record_announce(PASSKEY, &create_test_request([1; 20], 100, 50, None))
+            .unwrap();
+        registry
+            .record_announce(PASSKEY, &create_test_request([1; 20], 300, 80, None))
+            .unwrap();
+        registry
+            .record_announce(PASSKEY, &create_test_request([2; 20], 10, 0, None))
+            .unwrap();
+
+        assert_eq!(
+            registry.user_stats("alice"),
+            Some(UserStats {
+                uploaded: 310,
+                downloaded: 80
+            })
+        );
+        assert_eq!(registry.user_stats("bob"), None);
+    }
+
+    #[test]
+    fn test_stopped_peer_starts_a_new_session() {
+        let registry = load_test_registry("./test_stopped_peer_starts_a_new_session.txt");
+
+        registry
+            .record_announce(PASSKEY, &create_test_request([1; 20], 100, 0, None))
+            .unwrap();
+        registry
+            .record_announce(
+                PASSKEY,
+                &create_test_request([1; 20], 150, 0, Some("stopped")),
+            )
+            .unwrap();
+        registry
+            .record_announce(PASSKEY, &create_test_request([1; 20], 20, 0, None))
+            .unwrap();
+
+        assert_eq!(registry.user_stats("alice").unwrap().uploaded, 170);
+    }
+
+    #[test]
+    fn test_announce_with_unknown_passkey_is_not_recorded() {
+        let registry = load_test_registry("./test_announce_with_unknown_passkey.txt");
+
+        assert!(matches!(
+            registry.record_announce("unknown", &create_test_request([1; 20], 100, 0, None)),
+            Err(PasskeyError::UnknownPasskey)
+        ));
+    }
+
+    fn load_test_registry(path: &str) -> PasskeyRegistry {
+        let path = PathBuf::from(path);
+        fs::write(
+            &path,
+            format!("# users\n{} alice\n\nfedcba9876543210 bob\n", PASSKEY),
+        )
+        .unwrap();
+        let registry = PasskeyRegistry::load(path.clone()).unwrap();
+        fs::remove_file(path).unwrap();
+        registry
+    }
+
+    fn create_test_request(
+        peer_id: [u8; 20],
+        uploaded: u64,
+        downloaded: u64,
+        event: Option<&str>,
+    ) -> AnnounceRequest {
+        let mut params = QueryParams::default();
+        params.insert("info_hash", &[7; 20]);
+        params.insert("peer_id", &peer_id);
+        params.insert("port", b"6881");
+        params.insert("uploaded", uploaded.to_string().as_bytes());
+        params.insert("downloaded", downloaded.to_string().as_bytes());
+        params.insert("left", b"0");
+        if let Some(event) = event {
+            params.insert("event", event.as_bytes());
+        }
+        AnnounceRequest::new_from(&params).unwrap()
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use bencoder::{bencode::ToBencode, dict_builder::BencodeDictBuilder};
+use url_encoder::info_hash::InfoHash;
+
+use crate::{
+    http::query_params::QueryParams, torrent_swarm::swarm::SwarmStats,
+    tracker_status::atomic_tracker_status::AtomicTrackerStatus,
+};
+
+/// Struct representing the response of a tracker scrape request.
+///
+/// # Fields
+/// * `files`: The scrape information of each requested torrent, keyed by info hash:
+///    - **complete**: number of peers with the entire file, i.e. seeders.
+///    - **downloaded**: total number of times the tracker registered a completion.
+///    - **incomplete**: number of non-seeder peers, aka "leechers".
+#[derive(Debug)]
+pub struct ScrapeResponse {
+    pub files: Vec<(InfoHash, SwarmStats)>,
+}
+
+/// Posible scrape request errors.
+#[derive(Debug)]
+pub enum ScrapeResponseError {
+    InvalidInfoHash,
+}
+
+impl ScrapeResponse {
+    /// Creates a new ScrapeResponse from the decoded query parameters of the scrape request.
+    ///
+    /// Every `info_hash` of the request is scraped, a hash repeated is scraped once (BEP 48). If the request has no
+    /// `info_hash`, every torrent of the tracker is scraped.
+    pub fn from(
+        query_params: QueryParams,
+        tracker_status: Arc<AtomicTrackerStatus>,
+    ) -> Result<Self, ScrapeResponseError> {
+        let mut info_hashes = Vec::new();
+        for info_hash in query_params.get_all_bytes("info_hash") {
+            let info_hash = InfoHash::from_bytes(info_hash)
+                .map_err(|_| ScrapeResponseError::InvalidInfoHash)?;
+            if !info_hashes.contains(&info_hash) {
+                info_hashes.push(info_hash);
+            }
+        }
+
+        if info_hashes.is_empty() {
+            return Ok(Self {
+                files: tracker_status.scrape(None),
+            });
+        }
+        Ok(Self {
+            files: info_hashes
+                .into_iter()
+                .flat_map(|info_hash| tracker_status.scrape(Some(info_hash)))
+                .collect(),
+        })
+    }
+}
+
+impl ToBencode for ScrapeResponse {
+    fn to_bencode(&self) -> bencoder::bencode::Bencode {
+        let files =
+            self.files
+                .iter()
+                .fold(BencodeDictBuilder::new(), |files, (info_hash, stats)| {
+                    let file = BencodeDictBuilder::new()
+                        .insert(b"complete", &stats.seeders)
+                        .insert(b"downloaded", &stats.snatches)
+                        .insert(b"incomplete", &stats.leechers);
+                    files.insert(info_hash.as_bytes(), &file)
+                });
+
+        BencodeDictBuilder::new().insert(b"files", &files).build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bencoder::bencode::Bencode;
+    use chrono::Local;
+
+    use super::*;
+    use crate::tracker_peer::{peer::Peer, peer_status::PeerStatus};
+
+    #[test]
+    fn test_scrape_response_bencode() {
+        let response = ScrapeResponse {
+            files: vec![(
+                InfoHash::new([b'a'; 20]),
+                SwarmStats {
+                    seeders: 2,
+                    leechers: 3,
+                    snatches: 5,
+                },
+            )],
+        };
+
+        assert_eq!(
+            Bencode::encode(&response),
+            b"d5:filesd20:aaaaaaaaaaaaaaaaaaaad8:completei2e10:downloadedi5e10:incompletei3eeee"
+                .to_vec()
+        );
+    }
+
+    #[test]
+    fn test_scrape_unknown_torrent() {
+        let mut query_params = QueryParams::default();
+        query_params.insert("info_hash", &[0; 20]);
+
+        let response =
+            ScrapeResponse::from(query_params, Arc::new(AtomicTrackerStatus::default())).unwrap();
+
+        assert!(response.files.is_empty());
+    }
+
+    #[test]
+    fn test_scrape_every_requested_torrent() {
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+        for byte in [b'a', b'b', b'c'] {
+            tracker_status
+                .incoming_peer(InfoHash::new([byte; 20]), create_test_leecher(), 50)
+                .unwrap();
+        }
+        let query = format!("info_hash={}&info_hash={}", "a".repeat(20), "b".repeat(20));
+        let query_params = QueryParams::parse(query.as_bytes()).unwrap();
+
+        let response = ScrapeResponse::from(query_params, tracker_status).unwrap();
+
+        let info_hashes: Vec<InfoHash> = response
+            .files
+            .iter()
+            .map(|(info_hash, _)| *info_hash)
+            .collect();
+        assert_eq!(
+            info_hashes,
+            vec![InfoHash::new([b'a'; 20]), InfoHash::new([b'b'; 20])]
+        );
+        assert!(response.files.iter().all(|(_, stats)| stats.leechers == 1));
+    }
+
+    fn create_test_leecher() -> Peer {
+        let peer_status = PeerStatus {
+            uploaded: 0,
+            downloaded: 0,
+            left: 3000,
+            corrupt: 0,
+            event: None,
+            last_seen: Local::now(),
+        };
+
+        Peer::new([0; 20], "0".to_string(), 0, None, peer_status)
+    }
+}
@@ -1,2 +1,4 @@
+pub mod request_stats;
 pub mod stats_response;
 pub mod stats_updater;
+pub mod torrent_stats_response;
@@ -0,0 +1,173 @@
+use std::time::{Duration, Instant};
+
+/// Buckets of the latency histogram: four per power of two of microseconds, up to about 17 seconds.
+const LATENCY_BUCKETS: usize = 4 * 24;
+
+/// Histogram of the time taken to handle requests.
+///
+/// Each power of two of microseconds is split in four buckets, so a percentile is at most 25% above the real latency.
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    counts: [u64; LATENCY_BUCKETS],
+    total: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            counts: [0; LATENCY_BUCKETS],
+            total: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    /// Counts a latency, latencies over the last bucket are counted in it.
+    pub fn record(&mut self, latency: Duration) {
+        let micros = latency.as_micros().clamp(1, u64::MAX as u128) as u64;
+        let power = 63 - micros.leading_zeros() as usize;
+        // The two bits after the leading one pick the quarter of the power of two.
+        let quarter = ((micros << 2) >> power) as usize - 4;
+        let bucket = (power * 4 + quarter).min(LATENCY_BUCKETS - 1);
+
+        self.counts[bucket] += 1;
+        self.total += 1;
+    }
+
+    /// Returns the upper bound of the bucket of the given percentile, between 0 and 100, or zero if it is empty.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.total == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((percentile / 100.0) * self.total as f64).ceil().max(1.0) as u64;
+
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::upper_bound(bucket);
+            }
+        }
+        Self::upper_bound(LATENCY_BUCKETS - 1)
+    }
+
+    fn upper_bound(bucket: usize) -> Duration {
+        let (power, quarter) = (bucket / 4, bucket % 4);
+        Duration::from_micros(((4 + quarter as u64 + 1) << power) / 4)
+    }
+}
+
+/// The requests handled by the tracker since the stats were last taken.
+///
+/// ## Fields
+/// * `since`: When the interval started.
+/// * `announces`: The announces handled.
+/// * `errors`: The requests answered with an error status.
+/// * `announce_latency`: The time taken to handle each announce.
+#[derive(Debug, Clone)]
+pub struct RequestStats {
+    since: Instant,
+    announces: u32,
+    errors: u32,
+    announce_latency: LatencyHistogram,
+}
+
+/// Summary of the requests of an interval, see `RequestStats::summary`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RequestSummary {
+    pub announces_per_second: f64,
+    pub announce_latency_p50_ms: f64,
+    pub announce_latency_p95_ms: f64,
+    pub errors: u32,
+}
+
+impl Default for RequestStats {
+    fn default() -> Self {
+        Self {
+            since: Instant::now(),
+            announces: 0,
+            errors: 0,
+            announce_latency: LatencyHistogram::default(),
+        }
+    }
+}
+
+impl RequestStats {
+    /// Counts a handled request.
+    ///
+    /// ## Arguments
+    /// * `announce`: Whether the request was an announce, only their latency is measured.
+    /// * `latency`: The time between reading the request and sending its response.
+    /// * `error`: Whether the response had an error status.
+    pub fn record(&mut self, announce: bool, latency: Duration, error: bool) {
+        if announce {
+            self.announces += 1;
+            self.announce_latency.record(latency);
+        }
+        if error {
+            self.errors += 1;
+        }
+    }
+
+    /// Returns the announce rate, the median and 95th percentile announce latency and the errors of the interval.
+    pub fn summary(&self) -> RequestSummary {
+        let seconds = self.since.elapsed().as_secs_f64();
+        let announces_per_second = if seconds > 0.0 {
+            self.announces as f64 / seconds
+        } else {
+            0.0
+        };
+        let millis = |latency: Duration| latency.as_secs_f64() * 1000.0;
+
+        RequestSummary {
+            announces_per_second,
+            announce_latency_p50_ms: millis(self.announce_latency.percentile(50.0)),
+            announce_latency_p95_ms: millis(self.announce_latency.percentile(95.0)),
+            errors: self.errors,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let mut histogram = LatencyHistogram::default();
+        for millis in 1..=100 {
+            histogram.record(Duration::from_millis(millis));
+        }
+
+        let p50 = histogram.percentile(50.0);
+        let p95 = histogram.percentile(95.0);
+        assert!(p50 >= Duration::from_millis(50) && p50 <= Duration::from_micros(62_500));
+        assert!(p95 >= Duration::from_millis(95) && p95 <= Duration::from_micros(118_750));
+        assert_eq!(histogram.percentile(100.0), histogram.percentile(99.5));
+    }
+
+    #[test]
+    fn test_percentile_of_extreme_latencies() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.percentile(50.0), Duration::ZERO);
+
+        histogram.record(Duration::ZERO);
+        assert_eq!(histogram.percentile(50.0), Duration::from_micros(1));
+
+        histogram.record(Duration::from_secs(3600));
+        assert!(histogram.percentile(100.0) >= Duration::from_secs(16));
+    }
+
+    #[test]
+    fn test_request_summary() {
+        let mut stats = RequestStats::default();
+        stats.record(true, Duration::from_millis(2), false);
+        stats.record(true, Duration::from_millis(2), true);
+        stats.record(false, Duration::from_secs(1), true);
+
+        let summary = stats.summary();
+        assert!(summary.announces_per_second > 0.0);
+        assert!(summary.announce_latency_p95_ms >= 2.0 && summary.announce_latency_p95_ms < 2.5);
+        assert_eq!(summary.errors, 2);
+    }
+}
@@ -1,18 +1,24 @@
 use super::stats_updater::StatsUpdater;
-use crate::tracker_status::current_tracker_stats::CurrentTrackerStats;
+use crate::{
+    http::query_params::QueryParams,
+    http_server::rate_limiter::{RateLimiter, RateLimiterStats},
+    tracker_status::current_tracker_stats::CurrentTrackerStats,
+};
 use chrono::Duration;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Arc;
 
 /// Struct that represents the response of the stats request.
 ///
 /// ## Fields
 /// * `bucket_size_in_minutes`: The time interval in minutes of the bucket.
 /// * `content`: A `Vec<CurrentTrackerStats>` containing the history of the stats.
+/// * `rate_limiting`: The current rate limiting counters of the tracker.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StatsResponse {
     pub bucket_size_in_minutes: i64,
     pub content: Vec<CurrentTrackerStats>,
+    pub rate_limiting: RateLimiterStats,
 }
 
 /// Posible stats request errors.
@@ -21,13 +27,14 @@ pub enum StatsResponseError {
 }
 
 impl StatsResponse {
-    /// Creates a new `StatsResponse` from the query parameters and a StatsUpdater. If the query parameters are invalid, an `InvalidQueryParamError` is returned.
+    /// Creates a new `StatsResponse` from the query parameters, a StatsUpdater and the RateLimiter counters. If the query parameters are invalid, an `InvalidQueryParamError` is returned.
     ///
     /// ## Returns
     /// * `Result<StatsResponse, StatsResponseError>`: The response of the stats request.
     pub fn from(
-        query_params: HashMap<String, String>,
+        query_params: QueryParams,
         stats_updater: Arc<StatsUpdater>,
+        rate_limiter: &RateLimiter,
     ) -> Result<Self, StatsResponseError> {
         let since_in_hours = query_params
             .get("since")
@@ -40,6 +47,7 @@ impl StatsResponse {
         Ok(Self {
             bucket_size_in_minutes: stats_updater.get_timeout().num_minutes(),
             content: history,
+            rate_limiting: rate_limiter.get_stats(),
         })
     }
 }
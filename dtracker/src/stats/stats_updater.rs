@@ -1,18 +1,30 @@
 use chrono::Duration;
+use std::collections::HashMap;
 use std::sync::{Mutex, MutexGuard};
 use std::{sync::Arc, thread::sleep};
 
+use super::request_stats::RequestStats;
+
 use crate::tracker_status::atomic_tracker_status::AtomicTrackerStatus;
+use crate::tracker_status::current_torrent_stats::CurrentTorrentStats;
 use crate::tracker_status::current_tracker_stats::CurrentTrackerStats;
 use tracing::{info, warn};
+use url_encoder::info_hash::InfoHash;
 
 // for 1 month it takes 0.5 miliseconds to update the stats. And 0.5 Megabytes to store the stats.
 const MAX_DAYS_TO_KEEP_STATS: u64 = 30;
 
+type TorrentStatsBucket = HashMap<InfoHash, CurrentTorrentStats>;
+
 /// Struct that represents the current status of the stats.
+///
+/// The torrents history has one bucket for each bucket of the global history. Each bucket of the global history also
+/// has the requests handled since the previous one, recorded by the request handlers with `record_request`.
 #[derive(Debug)]
 pub struct StatsUpdater {
     stats_history: Mutex<Vec<CurrentTrackerStats>>,
+    torrent_stats_history: Mutex<Vec<TorrentStatsBucket>>,
+    requests: Mutex<RequestStats>,
     duration: chrono::Duration,
     tracker_status: Arc<AtomicTrackerStatus>,
 }
@@ -24,14 +36,16 @@ impl StatsUpdater {
             duration: timeout,
             tracker_status,
             stats_history: Mutex::new(Vec::new()),
+            torrent_stats_history: Mutex::new(Vec::new()),
+            requests: Mutex::new(RequestStats::default()),
         }
     }
 
     /// Starts the loop that updates the stats every `duration` seconds and saves them in the history.
     pub fn run(&self) {
         loop {
-            self.tracker_status.remove_inactive_peers();
             let mut stats_history = self.lock_stats_history();
+            let mut torrent_stats_history = self.lock_torrent_stats_history();
 
             // If we reached the maximum number of days to keep stats, remove the oldest one.
             let max_secs_to_keep_stats = MAX_DAYS_TO_KEEP_STATS * 24 * 60 * 60;
@@ -40,9 +54,17 @@ impl StatsUpdater {
             {
                 stats_history.rotate_left(1);
                 stats_history.pop();
+                torrent_stats_history.rotate_left(1);
+                torrent_stats_history.pop();
             }
 
-            stats_history.push(self.tracker_status.get_global_statistics());
+            let requests = std::mem::take(&mut *self.lock_requests()).summary();
+            stats_history.push(
+                self.tracker_status
+                    .get_global_statistics()
+                    .with_requests(requests),
+            );
+            torrent_stats_history.push(self.tracker_status.take_torrent_statistics());
             info!("Stats updated");
             let std_duration = match self.duration.to_std() {
                 Ok(std_duration) => std_duration,
@@ -53,25 +75,54 @@ impl StatsUpdater {
             };
             // Drop lock before sleeping.
             drop(stats_history);
+            drop(torrent_stats_history);
             sleep(std_duration);
         }
     }
 
+    /// Counts a request handled by the tracker in the current bucket.
+    ///
+    /// ## Arguments
+    /// * `announce`: Whether the request was an announce, only their rate and latency are measured.
+    /// * `latency`: The time between reading the request and sending its response.
+    /// * `error`: Whether the response had an error status.
+    pub fn record_request(&self, announce: bool, latency: std::time::Duration, error: bool) {
+        self.lock_requests().record(announce, latency, error);
+    }
+
     /// Gets the history of the stats since a given time. If the is less than `since` histories, all the histories are returned.
     ///
     /// ## Returns
     /// * `Vec<CurrentTrackerStats>`: The history of the stats. The total number of torrents, seeders and leechers at a given time.
     pub fn get_history(&self, since: chrono::Duration) -> Vec<CurrentTrackerStats> {
         let stats_history = self.lock_stats_history();
-        let since_secs = since.num_seconds();
-        let timeout_secs = self.duration.num_seconds();
+        stats_history[self.first_bucket_since(since, stats_history.len())..].to_vec()
+    }
+
+    /// Gets the history of the stats of a torrent since a given time, with the same buckets as `get_history`.
+    ///
+    /// ## Returns
+    /// * `Vec<CurrentTorrentStats>`: The history of the torrent stats. Buckets from before the torrent was announced have every stat in zero.
+    pub fn get_torrent_history(
+        &self,
+        info_hash: &InfoHash,
+        since: chrono::Duration,
+    ) -> Vec<CurrentTorrentStats> {
+        let torrent_stats_history = self.lock_torrent_stats_history();
+        torrent_stats_history[self.first_bucket_since(since, torrent_stats_history.len())..]
+            .iter()
+            .map(|bucket| bucket.get(info_hash).copied().unwrap_or_default())
+            .collect()
+    }
 
-        let number_of_histories_wanted = since_secs / timeout_secs;
+    /// Returns the index of the first bucket to include when asked for the stats since a given time.
+    fn first_bucket_since(&self, since: chrono::Duration, history_len: usize) -> usize {
+        let number_of_histories_wanted = since.num_seconds() / self.duration.num_seconds();
 
-        if number_of_histories_wanted > stats_history.len() as i64 {
-            return stats_history.clone();
+        if number_of_histories_wanted > history_len as i64 {
+            return 0;
         }
-        stats_history[stats_history.len() - number_of_histories_wanted as usize..].to_vec()
+        history_len - number_of_histories_wanted as usize
     }
 
     /// Gets the duration timeout of the stats.
@@ -82,4 +133,12 @@ impl StatsUpdater {
     fn lock_stats_history(&self) -> MutexGuard<Vec<CurrentTrackerStats>> {
         self.stats_history.lock().unwrap() // unwrap is safe because we are the only one who can modify the stats_history
     }
+
+    fn lock_requests(&self) -> MutexGuard<'_, RequestStats> {
+        self.requests.lock().unwrap() // unwrap is safe because the request stats are never left half updated
+    }
+
+    fn lock_torrent_stats_history(&self) -> MutexGuard<'_, Vec<TorrentStatsBucket>> {
+        self.torrent_stats_history.lock().unwrap() // unwrap is safe because we are the only one who can modify the torrent_stats_history
+    }
 }
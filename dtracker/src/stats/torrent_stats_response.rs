@@ -0,0 +1,59 @@
+use super::stats_updater::StatsUpdater;
+use crate::{
+    http::query_params::QueryParams, tracker_status::current_torrent_stats::CurrentTorrentStats,
+};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use url_encoder::info_hash::InfoHash;
+
+/// Struct that represents the response of the stats request of a single torrent.
+///
+/// ## Fields
+/// * `info_hash`: The hex encoded info hash of the torrent.
+/// * `bucket_size_in_minutes`: The time interval in minutes of the bucket.
+/// * `content`: A `Vec<CurrentTorrentStats>` containing the history of the torrent stats.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TorrentStatsResponse {
+    pub info_hash: String,
+    pub bucket_size_in_minutes: i64,
+    pub content: Vec<CurrentTorrentStats>,
+}
+
+/// Posible torrent stats request errors.
+pub enum TorrentStatsResponseError {
+    InvalidQueryParamError,
+    InvalidInfoHash,
+}
+
+impl TorrentStatsResponse {
+    /// Creates a new `TorrentStatsResponse` from the query parameters and a StatsUpdater.
+    ///
+    /// Expects the hex encoded `info_hash` of the torrent and the `since` hours to include.
+    ///
+    /// ## Returns
+    /// * `Result<TorrentStatsResponse, TorrentStatsResponseError>`: The response of the torrent stats request.
+    pub fn from(
+        query_params: QueryParams,
+        stats_updater: Arc<StatsUpdater>,
+    ) -> Result<Self, TorrentStatsResponseError> {
+        let info_hash = query_params
+            .get("info_hash")
+            .and_then(|info_hash| InfoHash::from_hex(info_hash).ok())
+            .ok_or(TorrentStatsResponseError::InvalidInfoHash)?;
+        let since_in_hours = query_params
+            .get("since")
+            .ok_or(TorrentStatsResponseError::InvalidQueryParamError)?
+            .parse::<u64>()
+            .map_err(|_| TorrentStatsResponseError::InvalidQueryParamError)?;
+
+        let history =
+            stats_updater.get_torrent_history(&info_hash, Duration::hours(since_in_hours as i64));
+
+        Ok(Self {
+            info_hash: info_hash.to_hex(),
+            bucket_size_in_minutes: stats_updater.get_timeout().num_minutes(),
+            content: history,
+        })
+    }
+}
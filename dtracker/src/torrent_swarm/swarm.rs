@@ -1,9 +1,12 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
-use chrono::{Duration, Local};
-use rand::{seq::IteratorRandom, thread_rng};
+use chrono::{DateTime, Duration, Local};
+use rand::{
+    seq::{IteratorRandom, SliceRandom},
+    thread_rng,
+};
 
-use crate::tracker_peer::peer::Peer;
+use crate::tracker_peer::{event::PeerEvent, peer::Peer};
 
 type PeerId = [u8; 20];
 
@@ -11,14 +14,37 @@ type PeerId = [u8; 20];
 ///
 /// ## Fields
 /// * `peer_timeout`: The time after which a peer is considered as inactive.
+/// * `min_announce_interval`: The minimum time a peer must wait between regular announces.
 /// * `seeders`: The current amount of seeders of the torrent.
 /// * `leechers`: The current amount of leechers of the torrent.
+/// * `snatches`: The amount of times the torrent was downloaded completely, it is kept after the peers leave.
+/// * `announces`: The amount of announces accepted since the counter was last taken.
+/// * `max_peers`: The maximum amount of peers in the swarm, new peers are rejected once it is reached.
+/// * `last_announce`: When the swarm was created or last accepted an announce.
 #[derive(Debug, Clone)]
 pub struct Swarm {
     peers: HashMap<PeerId, Peer>,
     peer_timeout: Duration,
+    min_announce_interval: Duration,
+    max_peers: usize,
+    last_announce: DateTime<Local>,
     seeders: u32,
     leechers: u32,
+    snatches: u32,
+    announces: u32,
+}
+
+/// Struct that represents the scrape information of a swarm.
+///
+/// ## Fields
+/// * `seeders`: The current amount of seeders of the swarm.
+/// * `leechers`: The current amount of leechers of the swarm.
+/// * `snatches`: The amount of times the torrent was downloaded completely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwarmStats {
+    pub seeders: u32,
+    pub leechers: u32,
+    pub snatches: u32,
 }
 
 /// Struct that represents the response to an active peers request.
@@ -34,49 +60,178 @@ pub struct ActivePeers {
     pub leechers: u32,
 }
 
+/// Posible errors announcing a peer to the swarm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwarmError {
+    /// The peer announced again before the minimum announce interval elapsed, it must wait `retry_in` more.
+    AnnouncedTooSoon { retry_in: Duration },
+    /// The id or the IP of the peer are banned from the tracker.
+    Banned,
+    /// The peer id belongs to a peer that announced with another key, so the announce comes from another client.
+    KeyMismatch,
+    /// The swarm already has as many active peers as allowed.
+    SwarmFull,
+    /// The tracker already has as many torrents as allowed, and none of them is idle.
+    TooManyTorrents,
+}
+
+impl fmt::Display for SwarmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwarmError::AnnouncedTooSoon { retry_in } => write!(
+                f,
+                "Announced too soon, retry in {} seconds",
+                retry_in.num_seconds().max(1)
+            ),
+            SwarmError::Banned => write!(f, "Banned from the tracker"),
+            SwarmError::KeyMismatch => write!(f, "Invalid key for this peer_id"),
+            SwarmError::SwarmFull => write!(f, "Too many peers for this torrent"),
+            SwarmError::TooManyTorrents => write!(f, "Too many torrents on the tracker"),
+        }
+    }
+}
+
 impl Swarm {
     /// Creates a new swarm.
     ///
     /// ## Arguments
     /// * `peer_timeout`: The timeout for a peer to be considered inactive.
-    pub fn new(peer_timeout: Duration) -> Self {
+    /// * `min_announce_interval`: The minimum time a peer must wait between regular announces.
+    pub fn new(peer_timeout: Duration, min_announce_interval: Duration) -> Self {
         Self {
             peers: HashMap::new(),
             peer_timeout,
+            min_announce_interval,
+            max_peers: usize::MAX,
+            last_announce: Local::now(),
             seeders: 0,
             leechers: 0,
+            snatches: 0,
+            announces: 0,
         }
     }
 
-    pub fn announce(&mut self, incoming_peer: Peer) {
+    /// Limits the swarm to `max_peers` peers.
+    pub fn with_max_peers(mut self, max_peers: usize) -> Self {
+        self.max_peers = max_peers;
+        self
+    }
+
+    /// Adds or updates a peer in the swarm.
+    ///
+    /// Announces with an event (started, stopped or completed) are always accepted, regular announces are
+    /// rejected if the peer already announced less than `min_announce_interval` ago.
+    ///
+    /// A `stopped` event removes the peer from the swarm. A `completed` event from a peer that was not already
+    /// seeding counts as a snatch, and so does a leecher of the swarm announcing with `left=0`, since not every
+    /// client sends the event.
+    ///
+    /// Once a peer announces with a `key`, only announces with the same key can update or remove it, even if they
+    /// come from its IP, so clients sharing an IP can not take over each other's peers.
+    ///
+    /// A new peer is rejected if the swarm has `max_peers` peers even after removing the inactive ones.
+    pub fn announce(&mut self, incoming_peer: Peer) -> Result<(), SwarmError> {
+        match self.peers.get(&incoming_peer.id) {
+            Some(old_peer) if old_peer.key.is_some() && old_peer.key != incoming_peer.key => {
+                return Err(SwarmError::KeyMismatch);
+            }
+            Some(_) => (),
+            None if incoming_peer.status.event == Some(PeerEvent::Stopped) => (),
+            None => {
+                if self.peers.len() >= self.max_peers {
+                    self.remove_inactive_peers();
+                }
+                if self.peers.len() >= self.max_peers {
+                    return Err(SwarmError::SwarmFull);
+                }
+            }
+        }
+
+        if incoming_peer.status.event.is_none() {
+            if let Some(old_peer) = self.peers.get(&incoming_peer.id) {
+                let elapsed = Local::now().signed_duration_since(old_peer.get_last_seen());
+                if elapsed < self.min_announce_interval {
+                    return Err(SwarmError::AnnouncedTooSoon {
+                        retry_in: self.min_announce_interval - elapsed,
+                    });
+                }
+            }
+        }
+
+        self.announces += 1;
+        self.last_announce = Local::now();
+
+        if incoming_peer.status.event == Some(PeerEvent::Stopped) {
+            if let Some(old_peer) = self.peers.remove(&incoming_peer.id) {
+                self.uncount(&old_peer);
+                if old_peer.is_leecher() && incoming_peer.status.left == 0 {
+                    self.snatches += 1;
+                }
+            }
+            return Ok(());
+        }
+
         let old_peer = self.peers.insert(incoming_peer.id, incoming_peer.clone());
         // If the peer was already in the swarm, we update it accordingly.
-
-        if let Some(old_peer) = old_peer {
-            if old_peer.is_leecher() {
-                self.leechers -= 1;
-            } else {
-                self.seeders -= 1;
+        let (was_seeder, was_leecher) = match old_peer {
+            Some(old_peer) => {
+                self.uncount(&old_peer);
+                (old_peer.is_seeder(), old_peer.is_leecher())
             }
+            None => (false, false),
         };
 
+        let completed = incoming_peer.status.event == Some(PeerEvent::Completed) && !was_seeder;
+        let finished = was_leecher && incoming_peer.is_seeder();
+        if completed || finished {
+            self.snatches += 1;
+        }
+
         if incoming_peer.is_leecher() {
             self.leechers += 1;
         } else {
             self.seeders += 1;
         }
+        Ok(())
     }
+
+    fn uncount(&mut self, peer: &Peer) {
+        if peer.is_leecher() {
+            self.leechers -= 1;
+        } else {
+            self.seeders -= 1;
+        }
+    }
+
     /// Returns an `ActivePeers` Struct containing a vector of active peers, the amount of seeders in the swarm and the amount of leechers in the swarm.
     ///
+    /// The requester is never included in the vector, and seeders only get leechers since they have nothing to download from other seeders.
+    /// The peers are picked at random and shuffled, so every peer is as likely to be sent, and to be sent first, on each announce.
+    ///
     /// ## Arguments
     /// * `wanted_peers`: The amount of active peers to include in the vector, unless the swarm does not contain as many active peers, in which case it equals the number of elements available.
-    pub fn get_active_peers(&self, wanted_peers: u32) -> ActivePeers {
-        let peers = self.peers.values().cloned();
+    /// * `requester`: The peer asking for the active peers.
+    pub fn get_active_peers(&self, wanted_peers: u32, requester: &Peer) -> ActivePeers {
+        if wanted_peers == 0 {
+            return ActivePeers {
+                peers: Vec::new(),
+                seeders: self.seeders,
+                leechers: self.leechers,
+            };
+        }
+
+        let requester_is_seeder = requester.is_seeder();
+        let peers = self
+            .peers
+            .values()
+            .filter(|peer| peer.id != requester.id)
+            .filter(|peer| !requester_is_seeder || peer.is_leecher())
+            .cloned();
 
         let mut rng = thread_rng();
-        let active_peers = peers
-            .into_iter()
-            .choose_multiple(&mut rng, wanted_peers as usize);
+        let mut active_peers = peers.choose_multiple(&mut rng, wanted_peers as usize);
+        // The sample keeps the order of the swarm, clients that try the first peers would all pick the same.
+        active_peers.shuffle(&mut rng);
 
         ActivePeers {
             peers: active_peers,
@@ -90,20 +245,422 @@ impl Swarm {
         (self.seeders, self.leechers)
     }
 
+    /// Returns the scrape information of the swarm.
+    pub fn get_stats(&self) -> SwarmStats {
+        SwarmStats {
+            seeders: self.seeders,
+            leechers: self.leechers,
+            snatches: self.snatches,
+        }
+    }
+
+    /// Returns the amount of announces accepted since the last call, and restarts the count.
+    pub fn take_announces(&mut self) -> u32 {
+        std::mem::take(&mut self.announces)
+    }
+
+    /// Returns when the swarm was created or last accepted an announce.
+    pub fn last_announce(&self) -> DateTime<Local> {
+        self.last_announce
+    }
+
+    /// Returns `true` if the swarm can be dropped without losing active peers, that is if it has no peers or
+    /// none of them announced within the peer timeout.
+    pub fn is_idle(&self) -> bool {
+        self.peers.is_empty()
+            || Local::now().signed_duration_since(self.last_announce) > self.peer_timeout
+    }
+
+    /// Returns every peer in the swarm, active or not.
+    pub fn peers(&self) -> Vec<Peer> {
+        self.peers.values().cloned().collect()
+    }
+
     /// Removes any inactive peers from the swarm.
     pub fn remove_inactive_peers(&mut self) {
+        let peer_timeout = self.peer_timeout;
+        self.remove_peers_where(|peer| {
+            Local::now().signed_duration_since(peer.get_last_seen()) > peer_timeout
+        });
+    }
+
+    /// Removes the peers for which `condition` is true and returns how many were removed.
+    pub fn remove_peers_where<F>(&mut self, condition: F) -> u32
+    where
+        F: Fn(&Peer) -> bool,
+    {
+        let mut removed = 0;
+        let (mut seeders, mut leechers) = (self.seeders, self.leechers);
         self.peers.retain(|_, peer| {
-            let last_seen = peer.get_last_seen();
-            if Local::now().signed_duration_since(last_seen) > self.peer_timeout {
-                if peer.is_leecher() {
-                    self.leechers -= 1;
-                } else {
-                    self.seeders -= 1;
-                }
-                false
+            if !condition(peer) {
+                return true;
+            }
+            if peer.is_leecher() {
+                leechers -= 1;
             } else {
-                true
+                seeders -= 1;
             }
+            removed += 1;
+            false
         });
+        self.seeders = seeders;
+        self.leechers = leechers;
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Sub;
+
+    use chrono::Local;
+
+    use crate::tracker_peer::peer_status::PeerStatus;
+
+    use super::*;
+
+    #[test]
+    fn test_regular_announce_too_soon_is_rejected() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::minutes(15));
+        swarm
+            .announce(create_test_peer(None, Local::now()))
+            .unwrap();
+
+        assert!(matches!(
+            swarm.announce(create_test_peer(None, Local::now())),
+            Err(SwarmError::AnnouncedTooSoon { .. })
+        ));
+        assert_eq!(swarm.get_current_seeders_and_leechers(), (0, 1));
+    }
+
+    #[test]
+    fn test_announce_with_event_is_always_accepted() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::minutes(15));
+        swarm
+            .announce(create_test_peer(Some(PeerEvent::Started), Local::now()))
+            .unwrap();
+
+        assert!(swarm
+            .announce(create_test_peer(Some(PeerEvent::Completed), Local::now()))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_regular_announce_after_min_interval_is_accepted() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::minutes(15));
+        let last_announce = Local::now().sub(Duration::minutes(20));
+        swarm
+            .announce(create_test_peer(None, last_announce))
+            .unwrap();
+
+        assert!(swarm.announce(create_test_peer(None, Local::now())).is_ok());
+    }
+
+    #[test]
+    fn test_stopped_peer_is_removed() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::minutes(15));
+        swarm
+            .announce(create_test_peer(Some(PeerEvent::Started), Local::now()))
+            .unwrap();
+
+        swarm
+            .announce(create_test_peer(Some(PeerEvent::Stopped), Local::now()))
+            .unwrap();
+
+        assert_eq!(swarm.get_current_seeders_and_leechers(), (0, 0));
+        assert!(swarm
+            .get_active_peers(50, &create_seeder(None))
+            .peers
+            .is_empty());
+    }
+
+    #[test]
+    fn test_completed_peer_is_a_snatch() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::minutes(15));
+        swarm
+            .announce(create_test_peer(Some(PeerEvent::Started), Local::now()))
+            .unwrap();
+
+        swarm
+            .announce(create_seeder(Some(PeerEvent::Completed)))
+            .unwrap();
+        swarm
+            .announce(create_seeder(Some(PeerEvent::Completed)))
+            .unwrap();
+        swarm
+            .announce(create_seeder(Some(PeerEvent::Stopped)))
+            .unwrap();
+
+        assert_eq!(
+            swarm.get_stats(),
+            SwarmStats {
+                seeders: 0,
+                leechers: 0,
+                snatches: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_seeder_announcing_started_is_not_a_leecher() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::minutes(15));
+
+        swarm
+            .announce(create_seeder(Some(PeerEvent::Started)))
+            .unwrap();
+
+        assert_eq!(swarm.get_current_seeders_and_leechers(), (1, 0));
+    }
+
+    #[test]
+    fn test_take_announces_counts_accepted_announces() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::minutes(15));
+        swarm
+            .announce(create_test_peer(Some(PeerEvent::Started), Local::now()))
+            .unwrap();
+        let _ = swarm.announce(create_test_peer(None, Local::now()));
+
+        assert_eq!(swarm.take_announces(), 1);
+        assert_eq!(swarm.take_announces(), 0);
+    }
+
+    #[test]
+    fn test_requester_is_not_in_its_own_peers() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::minutes(15));
+        let requester = create_test_peer(Some(PeerEvent::Started), Local::now());
+        swarm.announce(requester.clone()).unwrap();
+
+        assert!(swarm.get_active_peers(50, &requester).peers.is_empty());
+    }
+
+    #[test]
+    fn test_seeder_only_gets_leechers() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::minutes(15));
+        let mut other_seeder = create_seeder(None);
+        other_seeder.id = [1; 20];
+        let mut leecher = create_test_peer(None, Local::now());
+        leecher.id = [2; 20];
+        let requester = create_seeder(None);
+        swarm.announce(other_seeder).unwrap();
+        swarm.announce(leecher).unwrap();
+        swarm.announce(requester.clone()).unwrap();
+
+        let active_peers = swarm.get_active_peers(50, &requester);
+
+        assert_eq!(active_peers.peers.len(), 1);
+        assert_eq!(active_peers.peers[0].id, [2; 20]);
+        assert_eq!((active_peers.seeders, active_peers.leechers), (2, 1));
+    }
+
+    fn create_seeder(event: Option<PeerEvent>) -> Peer {
+        let mut peer = create_test_peer(event, Local::now());
+        peer.status.left = 0;
+        peer
+    }
+
+    #[test]
+    fn test_leecher_announcing_nothing_left_is_a_snatch() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::zero());
+        swarm
+            .announce(create_test_peer(None, Local::now()))
+            .unwrap();
+
+        swarm.announce(create_seeder(None)).unwrap();
+        swarm.announce(create_seeder(None)).unwrap();
+
+        assert_eq!(
+            swarm.get_stats(),
+            SwarmStats {
+                seeders: 1,
+                leechers: 0,
+                snatches: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_new_seeder_is_not_a_snatch() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::zero());
+
+        swarm
+            .announce(create_seeder(Some(PeerEvent::Started)))
+            .unwrap();
+
+        assert_eq!(swarm.get_stats().snatches, 0);
+    }
+
+    #[test]
+    fn test_leecher_stopping_with_nothing_left_is_a_snatch() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::zero());
+        swarm
+            .announce(create_test_peer(None, Local::now()))
+            .unwrap();
+
+        swarm
+            .announce(create_seeder(Some(PeerEvent::Stopped)))
+            .unwrap();
+
+        assert_eq!(swarm.get_stats().snatches, 1);
+    }
+
+    #[test]
+    fn test_snatches_are_kept_after_the_peers_leave() {
+        let mut swarm = Swarm::new(Duration::seconds(-1), Duration::zero());
+        swarm
+            .announce(create_test_peer(None, Local::now()))
+            .unwrap();
+        swarm
+            .announce(create_seeder(Some(PeerEvent::Completed)))
+            .unwrap();
+
+        swarm.remove_inactive_peers();
+
+        assert_eq!(
+            swarm.get_stats(),
+            SwarmStats {
+                seeders: 0,
+                leechers: 0,
+                snatches: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_full_swarm_rejects_new_peers() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::zero()).with_max_peers(1);
+        swarm
+            .announce(create_test_peer(None, Local::now()))
+            .unwrap();
+        let mut new_peer = create_test_peer(None, Local::now());
+        new_peer.id = [1; 20];
+
+        assert_eq!(swarm.announce(new_peer.clone()), Err(SwarmError::SwarmFull));
+        assert!(swarm.announce(create_test_peer(None, Local::now())).is_ok());
+
+        swarm.peer_timeout = Duration::seconds(-1);
+        assert!(swarm.announce(new_peer).is_ok());
+        assert_eq!(swarm.get_current_seeders_and_leechers(), (0, 1));
+    }
+
+    #[test]
+    fn test_idle_swarm() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::zero());
+        assert!(swarm.is_idle());
+
+        swarm
+            .announce(create_test_peer(None, Local::now()))
+            .unwrap();
+        assert!(!swarm.is_idle());
+
+        swarm.peer_timeout = Duration::seconds(-1);
+        assert!(swarm.is_idle());
+    }
+
+    #[test]
+    fn test_numwant_zero_gets_no_peers() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::minutes(15));
+        let mut peer = create_test_peer(None, Local::now());
+        peer.id = [1; 20];
+        swarm.announce(peer).unwrap();
+
+        let active_peers = swarm.get_active_peers(0, &create_test_peer(None, Local::now()));
+
+        assert!(active_peers.peers.is_empty());
+        assert_eq!((active_peers.seeders, active_peers.leechers), (0, 1));
+    }
+
+    #[test]
+    fn test_active_peers_are_picked_fairly() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::zero());
+        for id in 1..=10 {
+            let mut peer = create_test_peer(None, Local::now());
+            peer.id = [id; 20];
+            swarm.announce(peer).unwrap();
+        }
+        let requester = create_test_peer(None, Local::now());
+
+        let mut sent = [0; 11];
+        let mut sent_first = [0; 11];
+        for _ in 0..500 {
+            let active_peers = swarm.get_active_peers(2, &requester);
+            assert_eq!(active_peers.peers.len(), 2);
+            for peer in &active_peers.peers {
+                sent[peer.id[0] as usize] += 1;
+            }
+            sent_first[active_peers.peers[0].id[0] as usize] += 1;
+        }
+
+        assert!(sent[1..].iter().all(|&count| count > 0));
+        assert!(sent_first[1..].iter().all(|&count| count > 0));
+    }
+
+    #[test]
+    fn test_announce_with_another_key_is_rejected() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::zero());
+        swarm
+            .announce(create_test_peer_with_key(None, Some("a"), "10.0.0.1"))
+            .unwrap();
+
+        for key in [Some("b"), None] {
+            assert_eq!(
+                swarm.announce(create_test_peer_with_key(None, key, "10.0.0.1")),
+                Err(SwarmError::KeyMismatch)
+            );
+            assert_eq!(
+                swarm.announce(create_test_peer_with_key(
+                    Some(PeerEvent::Stopped),
+                    key,
+                    "10.0.0.1"
+                )),
+                Err(SwarmError::KeyMismatch)
+            );
+        }
+        assert_eq!(swarm.get_current_seeders_and_leechers(), (0, 1));
+    }
+
+    #[test]
+    fn test_peer_with_the_same_key_can_change_ip() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::zero());
+        swarm
+            .announce(create_test_peer_with_key(None, Some("a"), "10.0.0.1"))
+            .unwrap();
+
+        swarm
+            .announce(create_test_peer_with_key(None, Some("a"), "10.0.0.2"))
+            .unwrap();
+
+        assert_eq!(swarm.peers()[0].ip, "10.0.0.2");
+    }
+
+    #[test]
+    fn test_peer_without_key_is_identified_by_its_id() {
+        let mut swarm = Swarm::new(Duration::hours(1), Duration::zero());
+        swarm
+            .announce(create_test_peer_with_key(None, None, "10.0.0.1"))
+            .unwrap();
+
+        assert!(swarm
+            .announce(create_test_peer_with_key(None, Some("a"), "10.0.0.1"))
+            .is_ok());
+    }
+
+    fn create_test_peer_with_key(event: Option<PeerEvent>, key: Option<&str>, ip: &str) -> Peer {
+        let mut peer = create_test_peer(event, Local::now());
+        peer.key = key.map(str::to_string);
+        peer.ip = ip.to_string();
+        peer
+    }
+
+    fn create_test_peer(event: Option<PeerEvent>, last_seen: chrono::DateTime<Local>) -> Peer {
+        let peer_status = PeerStatus {
+            uploaded: 0,
+            downloaded: 0,
+            left: 3000,
+            corrupt: 0,
+            event,
+            last_seen,
+        };
+
+        Peer::new([0; 20], "0".to_string(), 0, None, peer_status)
     }
 }
@@ -0,0 +1,175 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+
+use url_encoder::info_hash::InfoHash;
+
+/// Struct that represents the registry of torrents allowed on a private tracker.
+///
+/// The whitelist is stored in a file with the hex encoded info hash of a torrent per line.
+/// Empty lines and lines starting with `#` are ignored.
+///
+/// ## Fields
+/// * `info_hashes`: The info hashes of the allowed torrents. If `None`, whitelist mode is off and every torrent is allowed.
+/// * `path`: The file the whitelist is loaded from and saved to.
+#[derive(Debug, Default)]
+pub struct TorrentWhitelist {
+    info_hashes: Option<Mutex<HashSet<InfoHash>>>,
+    path: Option<PathBuf>,
+}
+
+/// Possible errors that can occur when using the whitelist.
+#[derive(Debug)]
+pub enum WhitelistError {
+    Io(io::Error),
+    InvalidInfoHash(String),
+    WhitelistDisabled,
+}
+
+impl TorrentWhitelist {
+    /// Creates a whitelist with whitelist mode off, every torrent is allowed.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Loads the whitelist from `path`, turning whitelist mode on.
+    ///
+    /// If the file does not exist, the whitelist starts empty and the file is created on the first change.
+    ///
+    /// ## Arguments
+    /// * `path`: The file to load the whitelist from.
+    pub fn load(path: PathBuf) -> Result<Self, WhitelistError> {
+        let info_hashes = match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(err) => return Err(WhitelistError::Io(err)),
+        };
+
+        Ok(Self {
+            info_hashes: Some(Mutex::new(info_hashes)),
+            path: Some(path),
+        })
+    }
+
+    /// Returns `true` if whitelist mode is on.
+    pub fn is_enabled(&self) -> bool {
+        self.info_hashes.is_some()
+    }
+
+    /// Returns `true` if the torrent can be announced, that is if whitelist mode is off or the torrent is registered.
+    pub fn is_allowed(&self, info_hash: &InfoHash) -> bool {
+        match &self.info_hashes {
+            Some(info_hashes) => Self::lock(info_hashes).contains(info_hash),
+            None => true,
+        }
+    }
+
+    /// Registers a torrent and saves the whitelist. Returns `false` if it was already registered.
+    pub fn add(&self, info_hash: InfoHash) -> Result<bool, WhitelistError> {
+        self.edit(|info_hashes| info_hashes.insert(info_hash))
+    }
+
+    /// Unregisters a torrent and saves the whitelist. Returns `false` if it was not registered.
+    pub fn remove(&self, info_hash: &InfoHash) -> Result<bool, WhitelistError> {
+        self.edit(|info_hashes| info_hashes.remove(info_hash))
+    }
+
+    fn edit<F>(&self, change: F) -> Result<bool, WhitelistError>
+    where
+        F: FnOnce(&mut HashSet<InfoHash>) -> bool,
+    {
+        let info_hashes = self
+            .info_hashes
+            .as_ref()
+            .ok_or(WhitelistError::WhitelistDisabled)?;
+        let mut info_hashes = Self::lock(info_hashes);
+
+        let changed = change(&mut info_hashes);
+        if changed {
+            if let Some(path) = &self.path {
+                fs::write(path, Self::serialize(&info_hashes)).map_err(WhitelistError::Io)?;
+            }
+        }
+        Ok(changed)
+    }
+
+    fn parse(contents: &str) -> Result<HashSet<InfoHash>, WhitelistError> {
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                InfoHash::from_hex(line)
+                    .map_err(|_| WhitelistError::InvalidInfoHash(line.to_string()))
+            })
+            .collect()
+    }
+
+    fn serialize(info_hashes: &HashSet<InfoHash>) -> String {
+        let mut lines: Vec<String> = info_hashes.iter().map(InfoHash::to_hex).collect();
+        lines.sort();
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    fn lock(info_hashes: &Mutex<HashSet<InfoHash>>) -> MutexGuard<'_, HashSet<InfoHash>> {
+        info_hashes.lock().unwrap() // Unwrap is safe here because the lock is never held while panicking.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INFO_HASH: &str = "2c6b6858d61da9543d4231a71db4b1c9264b0685";
+
+    #[test]
+    fn test_disabled_whitelist_allows_every_torrent() {
+        let whitelist = TorrentWhitelist::disabled();
+
+        assert!(whitelist.is_allowed(&InfoHash::new([0; 20])));
+    }
+
+    #[test]
+    fn test_load_whitelist() {
+        let path = PathBuf::from("./test_load_whitelist.txt");
+        fs::write(&path, format!("# registered torrents\n{}\n\n", INFO_HASH)).unwrap();
+
+        let whitelist = TorrentWhitelist::load(path.clone()).unwrap();
+
+        assert!(whitelist.is_allowed(&InfoHash::from_hex(INFO_HASH).unwrap()));
+        assert!(!whitelist.is_allowed(&InfoHash::new([0; 20])));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_load_whitelist_with_invalid_info_hash() {
+        let path = PathBuf::from("./test_load_whitelist_with_invalid_info_hash.txt");
+        fs::write(&path, "not an info hash\n").unwrap();
+
+        assert!(matches!(
+            TorrentWhitelist::load(path.clone()),
+            Err(WhitelistError::InvalidInfoHash(_))
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_added_torrent_is_saved() {
+        let path = PathBuf::from("./test_added_torrent_is_saved.txt");
+        let whitelist = TorrentWhitelist::load(path.clone()).unwrap();
+        let info_hash = InfoHash::from_hex(INFO_HASH).unwrap();
+
+        assert!(whitelist.add(info_hash).unwrap());
+        assert!(!whitelist.add(info_hash).unwrap());
+
+        let reloaded = TorrentWhitelist::load(path.clone()).unwrap();
+        assert!(reloaded.is_allowed(&info_hash));
+
+        assert!(reloaded.remove(&info_hash).unwrap());
+        assert!(!reloaded.is_allowed(&info_hash));
+        fs::remove_file(path).unwrap();
+    }
+}
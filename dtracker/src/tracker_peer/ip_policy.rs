@@ -0,0 +1,236 @@
+use std::{net::IpAddr, str::FromStr};
+
+use crate::http::http_parser::Http;
+
+/// Where the IP of a peer was taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpSource {
+    /// The address of the TCP connection.
+    Connection,
+    /// The `X-Forwarded-For` or `X-Real-IP` header set by a trusted reverse proxy.
+    ProxyHeader,
+    /// The `ip` parameter sent by the client in the announce (BEP 7).
+    Announced,
+}
+
+/// The IP an announce comes from, as deduced by the `IpPolicy`.
+///
+/// ## Fields
+/// * `ip`: The IP of the announcer.
+/// * `source`: Where the IP was taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerAddress {
+    pub ip: IpAddr,
+    pub source: IpSource,
+}
+
+/// When to honor the `ip` parameter sent by the clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnouncedIpPolicy {
+    /// The `ip` parameter is always ignored.
+    Ignore,
+    /// The `ip` parameter is only honored for announces coming from private or loopback addresses,
+    /// e.g. clients on the same LAN as the tracker announcing their public IP.
+    PrivateOnly,
+    /// The `ip` parameter is always honored.
+    Always,
+}
+
+impl FromStr for AnnouncedIpPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(Self::Ignore),
+            "private" => Ok(Self::PrivateOnly),
+            "always" => Ok(Self::Always),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Decides the IP each announcer is registered with.
+///
+/// ## Fields
+/// * `trusted_proxies`: The reverse proxies whose `X-Forwarded-For` and `X-Real-IP` headers are honored.
+/// * `announced_ip`: When to honor the `ip` parameter of the announces.
+#[derive(Debug, Clone)]
+pub struct IpPolicy {
+    trusted_proxies: Vec<IpAddr>,
+    announced_ip: AnnouncedIpPolicy,
+}
+
+impl Default for IpPolicy {
+    /// No trusted proxies, and the `ip` parameter is only honored for private addresses.
+    fn default() -> Self {
+        Self::new(Vec::new(), AnnouncedIpPolicy::PrivateOnly)
+    }
+}
+
+impl IpPolicy {
+    /// Creates a new `IpPolicy`.
+    ///
+    /// ## Arguments
+    /// * `trusted_proxies`: The reverse proxies whose `X-Forwarded-For` and `X-Real-IP` headers are honored.
+    /// * `announced_ip`: When to honor the `ip` parameter of the announces.
+    pub fn new(trusted_proxies: Vec<IpAddr>, announced_ip: AnnouncedIpPolicy) -> Self {
+        Self {
+            trusted_proxies,
+            announced_ip,
+        }
+    }
+
+    /// Deduces the IP a request comes from.
+    ///
+    /// Proxy headers are only honored when the connection comes from a trusted proxy. `X-Forwarded-For` is read
+    /// from right to left skipping the trusted proxies, since the entries on its left can be forged by the client.
+    ///
+    /// ## Arguments
+    /// * `connection_ip`: The address of the TCP connection.
+    /// * `request`: The request, to read the proxy headers from.
+    pub fn remote_address(&self, connection_ip: IpAddr, request: &Http) -> PeerAddress {
+        let connection_address = PeerAddress {
+            ip: connection_ip,
+            source: IpSource::Connection,
+        };
+        if !self.trusted_proxies.contains(&connection_ip) {
+            return connection_address;
+        }
+
+        let forwarded_ip = request
+            .header("x-forwarded-for")
+            .and_then(|forwarded_for| {
+                forwarded_for
+                    .rsplit(',')
+                    .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+                    .find(|ip| !self.trusted_proxies.contains(ip))
+            })
+            .or_else(|| {
+                request
+                    .header("x-real-ip")
+                    .and_then(|ip| ip.trim().parse::<IpAddr>().ok())
+            });
+
+        match forwarded_ip {
+            Some(ip) => PeerAddress {
+                ip,
+                source: IpSource::ProxyHeader,
+            },
+            None => connection_address,
+        }
+    }
+
+    /// Returns the address a peer should be registered with, honoring its announced `ip` if the policy allows it.
+    ///
+    /// Announced values that are not an IP address are ignored.
+    pub fn peer_address(&self, remote: PeerAddress, announced_ip: Option<&str>) -> PeerAddress {
+        let announced_ip = match announced_ip.and_then(|ip| ip.parse::<IpAddr>().ok()) {
+            Some(announced_ip) => announced_ip,
+            None => return remote,
+        };
+        let allowed = match self.announced_ip {
+            AnnouncedIpPolicy::Ignore => false,
+            AnnouncedIpPolicy::PrivateOnly => is_private(remote.ip),
+            AnnouncedIpPolicy::Always => true,
+        };
+        if allowed {
+            PeerAddress {
+                ip: announced_ip,
+                source: IpSource::Announced,
+            }
+        } else {
+            remote
+        }
+    }
+}
+
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_loopback() || ip.is_link_local(),
+        IpAddr::V6(ip) => ip.is_loopback() || (ip.segments()[0] & 0xfe00) == 0xfc00,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const PROXY: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+
+    #[test]
+    fn test_proxy_headers_from_untrusted_connections_are_ignored() {
+        let policy = IpPolicy::default();
+        let request = request_with_header("X-Forwarded-For: 198.51.100.1");
+
+        assert_eq!(
+            policy.remote_address(PROXY, &request),
+            PeerAddress {
+                ip: PROXY,
+                source: IpSource::Connection
+            }
+        );
+    }
+
+    #[test]
+    fn test_forwarded_for_skips_trusted_proxies_from_the_right() {
+        let policy = IpPolicy::new(vec![PROXY], AnnouncedIpPolicy::PrivateOnly);
+        let request = request_with_header("X-Forwarded-For: 198.51.100.1, 203.0.113.7, 10.0.0.1");
+
+        assert_eq!(
+            policy.remote_address(PROXY, &request),
+            PeerAddress {
+                ip: CLIENT,
+                source: IpSource::ProxyHeader
+            }
+        );
+    }
+
+    #[test]
+    fn test_real_ip_from_trusted_proxy() {
+        let policy = IpPolicy::new(vec![PROXY], AnnouncedIpPolicy::PrivateOnly);
+        let request = request_with_header("X-Real-IP: 203.0.113.7");
+
+        assert_eq!(policy.remote_address(PROXY, &request).ip, CLIENT);
+    }
+
+    #[test]
+    fn test_announced_ip_only_honored_for_private_addresses() {
+        let policy = IpPolicy::default();
+        let public = PeerAddress {
+            ip: CLIENT,
+            source: IpSource::Connection,
+        };
+        let private = PeerAddress {
+            ip: PROXY,
+            source: IpSource::Connection,
+        };
+
+        assert_eq!(policy.peer_address(public, Some("198.51.100.1")), public);
+        assert_eq!(
+            policy.peer_address(private, Some("198.51.100.1")),
+            PeerAddress {
+                ip: IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1)),
+                source: IpSource::Announced
+            }
+        );
+    }
+
+    #[test]
+    fn test_announced_ip_that_is_not_an_ip_is_ignored() {
+        let policy = IpPolicy::new(Vec::new(), AnnouncedIpPolicy::Always);
+        let remote = PeerAddress {
+            ip: CLIENT,
+            source: IpSource::Connection,
+        };
+
+        assert_eq!(policy.peer_address(remote, Some("example.com")), remote);
+    }
+
+    fn request_with_header(header: &str) -> Http {
+        let request = format!("GET /announce?a=b HTTP/1.1\r\n{}\r\n\r\n", header);
+        Http::parse(request.as_bytes()).unwrap()
+    }
+}
@@ -1,3 +1,4 @@
 pub mod event;
+pub mod ip_policy;
 pub mod peer;
 pub mod peer_status;
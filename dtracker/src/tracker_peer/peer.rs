@@ -1,11 +1,18 @@
-use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
 
-use bencoder::bencode::ToBencode;
+use bencoder::{
+    bencode::{Bencode, ToBencode},
+    dict_builder::BencodeDictBuilder,
+};
 use chrono::{DateTime, Local};
 
 use crate::announce::announce_request::AnnounceRequest;
 
-use super::{event::PeerEvent, peer_status::PeerStatus};
+use super::{
+    event::PeerEvent,
+    ip_policy::{IpPolicy, IpSource, PeerAddress},
+    peer_status::PeerStatus,
+};
 
 /// Struct that represents a peer.
 ///
@@ -15,6 +22,7 @@ use super::{event::PeerEvent, peer_status::PeerStatus};
 /// * `port`: The port of the peer.
 /// * `status`: The current status of the peer.
 /// * `key`: The key to use to differentiate between other peers *(Optional)*.
+/// * `ip_source`: Where the ip of the peer was taken from.
 #[derive(Debug, Clone)]
 pub struct Peer {
     pub id: [u8; 20],
@@ -22,6 +30,7 @@ pub struct Peer {
     pub port: u16,
     pub status: PeerStatus,
     pub key: Option<String>, //link a wiki.theory.org:  https://bit.ly/3aTXQ3u
+    pub ip_source: IpSource,
 }
 impl Peer {
     /// Creates a new peer.
@@ -38,27 +47,34 @@ impl Peer {
             port,
             status,
             key,
+            ip_source: IpSource::Connection,
         }
     }
 
     /// Creates a new peer from an AnnounceRequest.
-    pub fn from_request(request: AnnounceRequest, peer_ip: String) -> Self {
+    ///
+    /// The `ip` sent in the request replaces the address the request came from only if the `ip_policy` allows it.
+    pub fn from_request(
+        request: AnnounceRequest,
+        remote_address: PeerAddress,
+        ip_policy: &IpPolicy,
+    ) -> Self {
         let id = request.peer_id;
-        let ip = match request.ip {
-            Some(ip) => ip,
-            None => peer_ip,
-        };
+        let address = ip_policy.peer_address(remote_address, request.ip.as_deref());
         let port = request.port;
         let key = request.key;
 
-        let status = PeerStatus::new(
+        let mut status = PeerStatus::new(
             request.uploaded,
             request.downloaded,
             request.left,
             request.event,
         );
+        status.corrupt = request.corrupt;
 
-        Self::new(id, ip, port, key, status)
+        let mut peer = Self::new(id, address.ip.to_string(), port, key, status);
+        peer.ip_source = address.source;
+        peer
     }
 
     pub fn get_last_seen(&self) -> DateTime<Local> {
@@ -67,9 +83,25 @@ impl Peer {
 
     /// Returns `true` if the given peer is acting as a leecher, `false` on the contrary.
     pub fn is_leecher(&self) -> bool {
-        self.status.left > 0
-            || (self.status.event != Some(PeerEvent::Completed) && self.status.event != None)
+        !self.is_seeder()
     }
+    /// Returns the peer in the compact format, the 4 bytes of the IP address followed by the 2 bytes of the port, in big endian.
+    ///
+    /// Returns `None` if the peer does not have an IPv4 address.
+    pub fn compact(&self) -> Option<[u8; 6]> {
+        let ip = self.ip.parse::<Ipv4Addr>().ok()?.octets();
+        let port = self.port.to_be_bytes();
+        Some([ip[0], ip[1], ip[2], ip[3], port[0], port[1]])
+    }
+
+    /// Returns the peer in the dictionary model without the **peer_id** key.
+    pub fn to_bencode_without_id(&self) -> Bencode {
+        BencodeDictBuilder::new()
+            .insert(b"ip", &self.ip)
+            .insert(b"port", &self.port)
+            .build()
+    }
+
     /// Returns `true` if the given peer is acting as a seeder, `false` on the contrary.
     pub fn is_seeder(&self) -> bool {
         self.status.left == 0 || self.status.event == Some(PeerEvent::Completed)
@@ -78,10 +110,10 @@ impl Peer {
 
 impl ToBencode for Peer {
     fn to_bencode(&self) -> bencoder::bencode::Bencode {
-        let mut peer = BTreeMap::new();
-        peer.insert(b"peer_id".to_vec(), self.id.to_vec().to_bencode());
-        peer.insert(b"ip".to_vec(), self.ip.to_bencode());
-        peer.insert(b"port".to_vec(), self.port.to_bencode());
-        peer.to_bencode()
+        BencodeDictBuilder::new()
+            .insert(b"peer_id", &self.id.to_vec())
+            .insert(b"ip", &self.ip)
+            .insert(b"port", &self.port)
+            .build()
     }
 }
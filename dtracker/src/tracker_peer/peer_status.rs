@@ -7,6 +7,7 @@ use chrono::{DateTime, Local};
 /// * `uploaded`: The number of bytes uploaded by the peer.
 /// * `downloaded`: The number of bytes downloaded by the peer.
 /// * `left`: The number of bytes left to download.
+/// * `corrupt`: The number of bytes discarded for failing the hash check.
 /// * `event`: The last event that the peer has sent *(Optional)*.
 /// * `last_seen`: The last time the peer status was updated.
 #[derive(Debug, Clone)]
@@ -14,6 +15,7 @@ pub struct PeerStatus {
     pub uploaded: u64,
     pub downloaded: u64,
     pub left: u64,
+    pub corrupt: u64,
     pub event: Option<PeerEvent>,
     pub last_seen: DateTime<Local>,
 }
@@ -25,6 +27,7 @@ impl PeerStatus {
             uploaded,
             downloaded,
             left,
+            corrupt: 0,
             event,
             last_seen: Local::now(),
         }
@@ -1,88 +1,439 @@
 use std::{
-    collections::HashMap,
-    sync::{Mutex, MutexGuard},
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
+    thread::sleep,
 };
 
-use chrono::Duration;
+use chrono::{DateTime, Duration, Local};
+use tracing::{info, warn};
+use url_encoder::info_hash::InfoHash;
 
 use crate::{
-    torrent_swarm::swarm::{ActivePeers, Swarm},
+    torrent_swarm::swarm::{ActivePeers, Swarm, SwarmError, SwarmStats},
     tracker_peer::peer::Peer,
 };
 
-use super::current_tracker_stats::CurrentTrackerStats;
+use super::{
+    current_torrent_stats::CurrentTorrentStats, current_tracker_stats::CurrentTrackerStats,
+};
+
+pub const DEFAULT_ANNOUNCE_INTERVAL_SECONDS: i64 = 1800;
+pub const DEFAULT_MIN_ANNOUNCE_INTERVAL_SECONDS: i64 = 900;
+pub const DEFAULT_PEER_TIMEOUT_SECONDS: i64 = 3600;
+pub const DEFAULT_REAPER_INTERVAL_SECONDS: i64 = 60;
+/// Default amount of shards the swarms are split into.
+pub const DEFAULT_SHARDS: usize = 64;
+pub const DEFAULT_MAX_TORRENTS: usize = 100_000;
+pub const DEFAULT_MAX_PEERS_PER_SWARM: usize = 10_000;
+pub const DEFAULT_NUMWANT: u32 = 50;
+pub const DEFAULT_MAX_NUMWANT: u32 = 200;
+
+type SwarmShard = HashMap<InfoHash, Swarm>;
+type PeerId = [u8; 20];
+
+/// The peers banned from the tracker, by IP or by peer id.
+#[derive(Debug, Default)]
+struct Bans {
+    ips: HashSet<IpAddr>,
+    peer_ids: HashSet<PeerId>,
+}
+
+/// The timing settings of a tracker.
+///
+/// ## Fields
+/// * `announce_interval`: The interval the clients should wait between regular announces.
+/// * `min_announce_interval`: The minimum interval the clients must wait between regular announces, faster announces are rejected.
+/// * `peer_timeout`: The time without announcing after which a peer is considered inactive.
+/// * `reaper_interval`: The time between removals of the inactive peers.
+#[derive(Debug, Clone, Copy)]
+pub struct TrackerTimeouts {
+    pub announce_interval: Duration,
+    pub min_announce_interval: Duration,
+    pub peer_timeout: Duration,
+    pub reaper_interval: Duration,
+}
+
+impl Default for TrackerTimeouts {
+    fn default() -> Self {
+        Self {
+            announce_interval: Duration::seconds(DEFAULT_ANNOUNCE_INTERVAL_SECONDS),
+            min_announce_interval: Duration::seconds(DEFAULT_MIN_ANNOUNCE_INTERVAL_SECONDS),
+            peer_timeout: Duration::seconds(DEFAULT_PEER_TIMEOUT_SECONDS),
+            reaper_interval: Duration::seconds(DEFAULT_REAPER_INTERVAL_SECONDS),
+        }
+    }
+}
 
-const PEER_HOURS_TIMEOUT: i64 = 1;
-type InfoHash = [u8; 20];
+/// The limits of the swarms of a tracker, so announces for random info hashes can not exhaust its memory and a
+/// single announce can not ask for every peer of a swarm.
+///
+/// ## Fields
+/// * `max_torrents`: The maximum amount of torrents tracked. Once reached, the least recently announced idle swarm is dropped to make room for a new torrent, or its announce is rejected if there is none.
+/// * `max_peers_per_swarm`: The maximum amount of peers of each torrent, new peers over it are rejected.
+/// * `default_numwant`: The amount of peers sent to announces without the `numwant` parameter.
+/// * `max_numwant`: The maximum amount of peers sent to an announce, larger `numwant` values are clamped to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwarmLimits {
+    pub max_torrents: usize,
+    pub max_peers_per_swarm: usize,
+    pub default_numwant: u32,
+    pub max_numwant: u32,
+}
+
+impl Default for SwarmLimits {
+    fn default() -> Self {
+        Self {
+            max_torrents: DEFAULT_MAX_TORRENTS,
+            max_peers_per_swarm: DEFAULT_MAX_PEERS_PER_SWARM,
+            default_numwant: DEFAULT_NUMWANT,
+            max_numwant: DEFAULT_MAX_NUMWANT,
+        }
+    }
+}
 
 /// Struct that represents the current status of the tracker.
 ///
+/// The swarms are split into shards by info hash, each one behind its own lock, so announces for torrents
+/// in different shards do not wait for each other.
+///
 /// ## Fields
-/// * `torrents`: The current torrents supported by the tracker. The key is the torrent `Info Hash`. The value is the `Torrent Status`.
+/// * `torrent_swarms`: The shards of the torrents supported by the tracker. The key is the torrent `Info Hash`. The value is its `Swarm`.
+/// * `timeouts`: The announce intervals, the peer timeout and the interval of the inactive peers reaper.
+/// * `announces`: The total amount of announces accepted.
+/// * `bans`: The IPs and peer ids whose announces are rejected.
+/// * `limits`: The maximum amount of torrents and of peers per torrent.
+/// * `torrents`: The amount of torrents tracked, kept apart so the limit is checked without locking every shard.
 #[derive(Debug)]
 pub struct AtomicTrackerStatus {
-    torrent_swarms: Mutex<HashMap<InfoHash, Swarm>>,
+    torrent_swarms: Vec<Mutex<SwarmShard>>,
+    timeouts: TrackerTimeouts,
+    limits: SwarmLimits,
+    torrents: AtomicUsize,
+    announces: AtomicU64,
+    bans: Mutex<Bans>,
 }
 
 impl Default for AtomicTrackerStatus {
-    /// Creates a new tracker status.
+    /// Creates a new tracker status with the default timeouts.
     fn default() -> Self {
-        AtomicTrackerStatus {
-            torrent_swarms: Mutex::new(HashMap::new()),
-        }
+        AtomicTrackerStatus::new(TrackerTimeouts::default())
     }
 }
 
 impl AtomicTrackerStatus {
+    /// Creates a new tracker status with the default amount of shards.
+    ///
+    /// ## Arguments
+    /// * `timeouts`: The announce intervals, the peer timeout and the interval of the inactive peers reaper.
+    pub fn new(timeouts: TrackerTimeouts) -> Self {
+        Self::with_shards(timeouts, DEFAULT_SHARDS)
+    }
+
+    /// Creates a new tracker status with the swarms split into `shards` shards.
+    ///
+    /// If `shards` is zero, the function will panic.
+    pub fn with_shards(timeouts: TrackerTimeouts, shards: usize) -> Self {
+        assert!(shards > 0);
+        AtomicTrackerStatus {
+            torrent_swarms: (0..shards).map(|_| Mutex::new(HashMap::new())).collect(),
+            timeouts,
+            limits: SwarmLimits::default(),
+            torrents: AtomicUsize::new(0),
+            announces: AtomicU64::new(0),
+            bans: Mutex::new(Bans::default()),
+        }
+    }
+
+    /// Sets the maximum amount of torrents, of peers per torrent and of peers sent to each announce.
+    pub fn with_limits(mut self, limits: SwarmLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Returns the interval the clients should wait between regular announces.
+    pub fn announce_interval(&self) -> Duration {
+        self.timeouts.announce_interval
+    }
+
+    /// Returns the minimum interval the clients must wait between regular announces.
+    pub fn min_announce_interval(&self) -> Duration {
+        self.timeouts.min_announce_interval
+    }
+
+    /// Returns the amount of peers to send to an announce with the given `numwant`: the default if absent, and at most
+    /// the maximum.
+    pub fn wanted_peers(&self, numwant: Option<u32>) -> u32 {
+        numwant
+            .unwrap_or(self.limits.default_numwant)
+            .min(self.limits.max_numwant)
+    }
+
     /// Adds or updates a peer for a torrent in the tracker status and returns an `ActivePeers` struct.
     ///
     /// ## Arguments
     /// * `info_hash`: The info hash of the torrent.
     /// * `peer`: The peer to add or update.
-    /// * `numwant`: The number of peers wanted by the client.
+    /// * `wanted_peers`: The number of peers wanted by the client, clamped to the maximum numwant.
     ///
     /// ## Returns
     /// * `ActivePeers`: Struct containing the peers of the torrent requested, the number of seeders and leechers.
-    pub fn incoming_peer(&self, info_hash: InfoHash, peer: Peer, wanted_peers: u32) -> ActivePeers {
-        let mut swarms = self.lock_swarms();
-        let torrent_swarm = swarms
-            .entry(info_hash)
-            .or_insert_with(|| Swarm::new(Duration::hours(PEER_HOURS_TIMEOUT)));
+    /// * `SwarmError`: If the peer is banned, announced again before the minimum announce interval, or the torrent or the tracker are full.
+    pub fn incoming_peer(
+        &self,
+        info_hash: InfoHash,
+        peer: Peer,
+        wanted_peers: u32,
+    ) -> Result<ActivePeers, SwarmError> {
+        if self.is_banned(&peer) {
+            return Err(SwarmError::Banned);
+        }
 
-        torrent_swarm.announce(peer);
+        let mut swarms = self.lock_shard(&info_hash);
+        if !swarms.contains_key(&info_hash)
+            && self.torrents.load(Ordering::Relaxed) >= self.limits.max_torrents
+        {
+            // Other shards are locked while evicting, so this one is released to avoid deadlocks.
+            drop(swarms);
+            if !self.evict_idle_swarm() {
+                return Err(SwarmError::TooManyTorrents);
+            }
+            swarms = self.lock_shard(&info_hash);
+        }
 
-        torrent_swarm.get_active_peers(wanted_peers)
+        let torrent_swarm = swarms.entry(info_hash).or_insert_with(|| {
+            self.torrents.fetch_add(1, Ordering::Relaxed);
+            Swarm::new(
+                self.timeouts.peer_timeout,
+                self.timeouts.min_announce_interval,
+            )
+            .with_max_peers(self.limits.max_peers_per_swarm)
+        });
+
+        torrent_swarm.announce(peer.clone())?;
+        self.announces.fetch_add(1, Ordering::Relaxed);
+
+        Ok(torrent_swarm.get_active_peers(wanted_peers.min(self.limits.max_numwant), &peer))
+    }
+
+    /// Drops the idle swarm that was announced to least recently, returns `false` if every swarm has active peers.
+    fn evict_idle_swarm(&self) -> bool {
+        let mut oldest: Option<(usize, InfoHash, DateTime<Local>)> = None;
+        for shard in 0..self.torrent_swarms.len() {
+            for (info_hash, swarm) in self.lock_shard_at(shard).iter() {
+                let last_announce = swarm.last_announce();
+                let is_older = match oldest {
+                    Some((_, _, oldest)) => last_announce < oldest,
+                    None => true,
+                };
+                if swarm.is_idle() && is_older {
+                    oldest = Some((shard, *info_hash, last_announce));
+                }
+            }
+        }
+
+        let Some((shard, info_hash, _)) = oldest else {
+            return false;
+        };
+        let mut swarms = self.lock_shard_at(shard);
+        // The swarm may have been announced to while no shard was locked.
+        if !swarms.get(&info_hash).is_some_and(Swarm::is_idle) {
+            return false;
+        }
+        swarms.remove(&info_hash);
+        self.torrents.fetch_sub(1, Ordering::Relaxed);
+        info!("Evicted idle swarm {} to make room", info_hash.to_hex());
+        true
+    }
+
+    /// Returns the total amount of announces accepted since the tracker started.
+    pub fn total_announces(&self) -> u64 {
+        self.announces.load(Ordering::Relaxed)
     }
 
     /// Gets the current statistics of the tracker.
     ///
     /// ## Returns
-    /// * `CurrentTrackerStats`: Struct containing the total number of torrents, seeders and leechers.
+    /// * `CurrentTrackerStats`: Struct containing the total number of torrents, seeders, leechers and snatches.
     pub fn get_global_statistics(&self) -> CurrentTrackerStats {
-        let swarms = self.lock_swarms();
-
-        let total_torrents = swarms.len() as u32;
+        let mut total_torrents = 0;
         let mut global_seeders = 0;
         let mut global_leechers = 0;
+        let mut global_snatches = 0;
+
+        for shard in 0..self.torrent_swarms.len() {
+            let swarms = self.lock_shard_at(shard);
+            total_torrents += swarms.len() as u32;
+            for swarm in swarms.values() {
+                let stats = swarm.get_stats();
+                global_seeders += stats.seeders;
+                global_leechers += stats.leechers;
+                global_snatches += stats.snatches;
+            }
+        }
+
+        CurrentTrackerStats::new(
+            total_torrents,
+            global_seeders,
+            global_leechers,
+            global_snatches,
+        )
+    }
+
+    /// Gets the current statistics of every torrent, restarting their announce counters.
+    ///
+    /// ## Returns
+    /// * `HashMap<InfoHash, CurrentTorrentStats>`: The peers, seeders, leechers, snatches and announces since the last call of each torrent.
+    pub fn take_torrent_statistics(&self) -> HashMap<InfoHash, CurrentTorrentStats> {
+        let mut torrent_stats = HashMap::new();
+        for shard in 0..self.torrent_swarms.len() {
+            for (info_hash, swarm) in self.lock_shard_at(shard).iter_mut() {
+                let stats = swarm.get_stats();
+                torrent_stats.insert(
+                    *info_hash,
+                    CurrentTorrentStats::new(
+                        stats.seeders,
+                        stats.leechers,
+                        stats.snatches,
+                        swarm.take_announces(),
+                    ),
+                );
+            }
+        }
+        torrent_stats
+    }
+
+    /// Gets the scrape information of the requested torrents.
+    ///
+    /// ## Arguments
+    /// * `info_hash`: The info hash of the torrent to scrape. If `None`, every torrent is scraped.
+    ///
+    /// ## Returns
+    /// * `Vec<(InfoHash, SwarmStats)>`: The seeders, leechers and snatches of each torrent known by the tracker.
+    pub fn scrape(&self, info_hash: Option<InfoHash>) -> Vec<(InfoHash, SwarmStats)> {
+        match info_hash {
+            Some(info_hash) => self
+                .lock_shard(&info_hash)
+                .get(&info_hash)
+                .map(|swarm| vec![(info_hash, swarm.get_stats())])
+                .unwrap_or_default(),
+            None => (0..self.torrent_swarms.len())
+                .flat_map(|shard| {
+                    self.lock_shard_at(shard)
+                        .iter()
+                        .map(|(info_hash, swarm)| (*info_hash, swarm.get_stats()))
+                        .collect::<Vec<_>>()
+                })
+                .collect(),
+        }
+    }
 
-        for swarm in swarms.values() {
-            let (seeders, leechers) = swarm.get_current_seeders_and_leechers();
-            global_seeders += seeders;
-            global_leechers += leechers;
+    /// Returns every peer of the swarm of `info_hash`, or `None` if the torrent is not known by the tracker.
+    pub fn swarm_peers(&self, info_hash: &InfoHash) -> Option<Vec<Peer>> {
+        self.lock_shard(info_hash).get(info_hash).map(Swarm::peers)
+    }
+
+    /// Removes peers from the swarm of `info_hash` before they time out and returns how many were removed.
+    ///
+    /// ## Arguments
+    /// * `info_hash`: The info hash of the torrent.
+    /// * `peer_id`: The id of the peer to remove. If `None`, every peer of the swarm is removed.
+    pub fn expire_peers(&self, info_hash: &InfoHash, peer_id: Option<PeerId>) -> u32 {
+        match self.lock_shard(info_hash).get_mut(info_hash) {
+            Some(swarm) => {
+                swarm.remove_peers_where(|peer| peer_id.is_none() || peer_id == Some(peer.id))
+            }
+            None => 0,
         }
+    }
 
-        CurrentTrackerStats::new(total_torrents, global_seeders, global_leechers)
+    /// Bans `ip` from the tracker, removing its peers from every swarm. Returns `false` if it was already banned.
+    pub fn ban_ip(&self, ip: IpAddr) -> bool {
+        let banned = self.lock_bans().ips.insert(ip);
+        self.remove_peers_where(|peer| peer.ip.parse::<IpAddr>() == Ok(ip));
+        banned
+    }
+
+    /// Lifts the ban of `ip`. Returns `false` if it was not banned.
+    pub fn unban_ip(&self, ip: &IpAddr) -> bool {
+        self.lock_bans().ips.remove(ip)
+    }
+
+    /// Bans the peer with `peer_id` from the tracker, removing it from every swarm. Returns `false` if it was already banned.
+    pub fn ban_peer(&self, peer_id: PeerId) -> bool {
+        let banned = self.lock_bans().peer_ids.insert(peer_id);
+        self.remove_peers_where(|peer| peer.id == peer_id);
+        banned
+    }
+
+    /// Lifts the ban of the peer with `peer_id`. Returns `false` if it was not banned.
+    pub fn unban_peer(&self, peer_id: &PeerId) -> bool {
+        self.lock_bans().peer_ids.remove(peer_id)
+    }
+
+    /// Returns `true` if the id or the IP of `peer` are banned.
+    pub fn is_banned(&self, peer: &Peer) -> bool {
+        let bans = self.lock_bans();
+        bans.peer_ids.contains(&peer.id)
+            || peer
+                .ip
+                .parse::<IpAddr>()
+                .is_ok_and(|ip| bans.ips.contains(&ip))
+    }
+
+    fn remove_peers_where<F>(&self, condition: F)
+    where
+        F: Fn(&Peer) -> bool,
+    {
+        for shard in 0..self.torrent_swarms.len() {
+            for swarm in self.lock_shard_at(shard).values_mut() {
+                swarm.remove_peers_where(&condition);
+            }
+        }
     }
 
     /// Removes any inactive peers from each swarm.
     pub fn remove_inactive_peers(&self) {
-        for swarm in self.lock_swarms().values_mut() {
-            swarm.remove_inactive_peers();
+        for shard in 0..self.torrent_swarms.len() {
+            for swarm in self.lock_shard_at(shard).values_mut() {
+                swarm.remove_inactive_peers();
+            }
+        }
+    }
+
+    /// Starts the loop that removes the inactive peers every `reaper_interval`.
+    ///
+    /// It runs independently of the stats updates, so inactive peers do not inflate the counts between them.
+    pub fn run_reaper(&self) {
+        let reaper_interval = match self.timeouts.reaper_interval.to_std() {
+            Ok(reaper_interval) => reaper_interval,
+            Err(_) => return warn!("Invalid reaper interval, inactive peers will not be removed"),
+        };
+        loop {
+            sleep(reaper_interval);
+            self.remove_inactive_peers();
+            info!("Inactive peers removed");
         }
     }
 
-    fn lock_swarms(&self) -> MutexGuard<HashMap<InfoHash, Swarm>> {
-        self.torrent_swarms.lock().unwrap() // Unwrap is safe here because we're the only ones who call this function.
+    /// Locks the shard that holds the swarm of `info_hash`.
+    ///
+    /// Info hashes are SHA-1 hashes, so their first bytes are evenly distributed among the shards.
+    fn lock_shard(&self, info_hash: &InfoHash) -> MutexGuard<'_, SwarmShard> {
+        let bytes = info_hash.as_bytes();
+        let key = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+        self.lock_shard_at(key % self.torrent_swarms.len())
+    }
+
+    fn lock_shard_at(&self, shard: usize) -> MutexGuard<'_, SwarmShard> {
+        self.torrent_swarms[shard].lock().unwrap() // Unwrap is safe here because we're the only ones who call this function.
+    }
+
+    fn lock_bans(&self) -> MutexGuard<'_, Bans> {
+        self.bans.lock().unwrap() // Unwrap is safe here because the lock is never held while panicking.
     }
 }
 
@@ -100,9 +451,11 @@ mod tests {
     fn test_incoming_seeder() {
         let tracker_status = AtomicTrackerStatus::default();
         let a_seeder = create_test_seeder([0; 20]);
-        let info_hash = [0; 20];
+        let info_hash = InfoHash::new([0; 20]);
 
-        tracker_status.incoming_peer(info_hash, a_seeder, 50);
+        tracker_status
+            .incoming_peer(info_hash, a_seeder, 50)
+            .unwrap();
 
         assert_there_is_only_one_seeder(&tracker_status, info_hash);
     }
@@ -111,9 +464,11 @@ mod tests {
     fn test_incoming_leecher() {
         let tracker_status = AtomicTrackerStatus::default();
         let a_leecher = create_test_leecher([0; 20]);
-        let info_hash = [0; 20];
+        let info_hash = InfoHash::new([0; 20]);
 
-        tracker_status.incoming_peer(info_hash, a_leecher, 50);
+        tracker_status
+            .incoming_peer(info_hash, a_leecher, 50)
+            .unwrap();
 
         assert_there_is_only_one_leecher(&tracker_status, info_hash);
     }
@@ -123,23 +478,27 @@ mod tests {
         let tracker_status = AtomicTrackerStatus::default();
         let a_peer = create_test_seeder([0; 20]);
         let another_peer = create_test_leecher([1; 20]);
-        let info_hash = [0; 20];
+        let info_hash = InfoHash::new([0; 20]);
 
-        tracker_status.incoming_peer(info_hash, a_peer, 50);
-        tracker_status.incoming_peer(info_hash, another_peer, 50);
+        tracker_status.incoming_peer(info_hash, a_peer, 50).unwrap();
+        tracker_status
+            .incoming_peer(info_hash, another_peer, 50)
+            .unwrap();
 
         assert_there_are_only_these_peers(&tracker_status, info_hash, 1, 1);
     }
 
     #[test]
     fn test_returning_peer() {
-        let tracker_status = AtomicTrackerStatus::default();
+        let tracker_status = create_test_status_without_min_interval();
         let peer_id = [0; 20];
         let a_peer = create_test_leecher(peer_id);
-        let info_hash = [0; 20];
+        let info_hash = InfoHash::new([0; 20]);
 
-        tracker_status.incoming_peer(info_hash, a_peer, 50);
-        tracker_status.incoming_peer(info_hash, create_test_seeder(peer_id), 50);
+        tracker_status.incoming_peer(info_hash, a_peer, 50).unwrap();
+        tracker_status
+            .incoming_peer(info_hash, create_test_seeder(peer_id), 50)
+            .unwrap();
 
         assert_there_is_only_one_seeder(&tracker_status, info_hash);
     }
@@ -149,11 +508,15 @@ mod tests {
         let tracker_status = AtomicTrackerStatus::default();
         let a_peer = create_test_leecher([0; 20]);
         let another_peer = create_test_leecher([1; 20]);
-        let an_info_hash = [0; 20];
-        let another_info_hash = [1; 20];
+        let an_info_hash = InfoHash::new([0; 20]);
+        let another_info_hash = InfoHash::new([1; 20]);
 
-        tracker_status.incoming_peer(an_info_hash, a_peer, 50);
-        tracker_status.incoming_peer(another_info_hash, another_peer, 50);
+        tracker_status
+            .incoming_peer(an_info_hash, a_peer, 50)
+            .unwrap();
+        tracker_status
+            .incoming_peer(another_info_hash, another_peer, 50)
+            .unwrap();
 
         assert_there_is_only_one_leecher(&tracker_status, an_info_hash);
         assert_there_is_only_one_leecher(&tracker_status, another_info_hash);
@@ -161,22 +524,253 @@ mod tests {
 
     #[test]
     fn test_peer_can_get_inactive() {
-        let tracker_status = AtomicTrackerStatus::default();
+        let tracker_status = create_test_status_without_min_interval();
         let peer_id = [0; 20];
         let a_peer = create_test_seeder(peer_id);
-        let an_info_hash = [0; 20];
-        tracker_status.incoming_peer(an_info_hash, a_peer, 50);
+        let an_info_hash = InfoHash::new([0; 20]);
+        tracker_status
+            .incoming_peer(an_info_hash, a_peer, 50)
+            .unwrap();
 
         let inactive_peer = create_inactive_peer(peer_id);
-        tracker_status.incoming_peer(an_info_hash, inactive_peer, 50);
+        tracker_status
+            .incoming_peer(an_info_hash, inactive_peer, 50)
+            .unwrap();
         tracker_status.remove_inactive_peers();
 
         assert_there_are_only_these_peers(&tracker_status, an_info_hash, 0, 0);
     }
 
+    #[test]
+    fn test_regular_announce_too_soon_is_rejected() {
+        let tracker_status = AtomicTrackerStatus::default();
+        let info_hash = InfoHash::new([0; 20]);
+
+        tracker_status
+            .incoming_peer(info_hash, create_test_leecher([0; 20]), 50)
+            .unwrap();
+
+        assert!(tracker_status
+            .incoming_peer(info_hash, create_test_leecher([0; 20]), 50)
+            .is_err());
+        assert_there_is_only_one_leecher(&tracker_status, info_hash);
+    }
+
+    #[test]
+    fn test_take_torrent_statistics() {
+        let tracker_status = AtomicTrackerStatus::default();
+        let info_hash = InfoHash::new([0; 20]);
+        tracker_status
+            .incoming_peer(info_hash, create_test_seeder([0; 20]), 50)
+            .unwrap();
+        tracker_status
+            .incoming_peer(info_hash, create_test_leecher([1; 20]), 50)
+            .unwrap();
+
+        let stats = tracker_status.take_torrent_statistics();
+
+        assert_eq!(stats[&info_hash], CurrentTorrentStats::new(1, 1, 0, 2));
+        assert_eq!(
+            tracker_status.take_torrent_statistics()[&info_hash].announces,
+            0
+        );
+    }
+
+    #[test]
+    fn test_statistics_are_aggregated_across_shards() {
+        let tracker_status = AtomicTrackerStatus::with_shards(TrackerTimeouts::default(), 4);
+        for byte in 0..8 {
+            tracker_status
+                .incoming_peer(InfoHash::new([byte; 20]), create_test_leecher([0; 20]), 50)
+                .unwrap();
+        }
+
+        let stats = tracker_status.get_global_statistics();
+
+        assert_eq!((stats.torrents, stats.leechers), (8, 8));
+        assert_eq!(tracker_status.scrape(None).len(), 8);
+        assert_eq!(tracker_status.take_torrent_statistics().len(), 8);
+    }
+
+    #[test]
+    fn test_banned_peer_is_removed_and_rejected() {
+        let tracker_status = AtomicTrackerStatus::default();
+        let info_hash = InfoHash::new([0; 20]);
+        tracker_status
+            .incoming_peer(info_hash, create_test_leecher([0; 20]), 50)
+            .unwrap();
+        tracker_status
+            .incoming_peer(info_hash, create_test_seeder([1; 20]), 50)
+            .unwrap();
+
+        assert!(tracker_status.ban_peer([0; 20]));
+        assert!(!tracker_status.ban_peer([0; 20]));
+
+        assert_there_is_only_one_seeder(&tracker_status, info_hash);
+        assert_eq!(
+            tracker_status
+                .incoming_peer(info_hash, create_test_leecher([0; 20]), 50)
+                .unwrap_err(),
+            SwarmError::Banned
+        );
+
+        assert!(tracker_status.unban_peer(&[0; 20]));
+        assert!(tracker_status
+            .incoming_peer(info_hash, create_test_leecher([0; 20]), 50)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_banned_ip_is_rejected() {
+        let tracker_status = AtomicTrackerStatus::default();
+        let info_hash = InfoHash::new([0; 20]);
+        let mut peer = create_test_leecher([0; 20]);
+        peer.ip = "10.0.0.1".to_string();
+        tracker_status
+            .incoming_peer(info_hash, peer.clone(), 50)
+            .unwrap();
+
+        tracker_status.ban_ip("10.0.0.1".parse().unwrap());
+
+        assert_there_are_only_these_peers(&tracker_status, info_hash, 0, 0);
+        assert!(tracker_status.incoming_peer(info_hash, peer, 50).is_err());
+    }
+
+    #[test]
+    fn test_expire_peers() {
+        let tracker_status = AtomicTrackerStatus::default();
+        let info_hash = InfoHash::new([0; 20]);
+        for peer_id in 0..3 {
+            tracker_status
+                .incoming_peer(info_hash, create_test_leecher([peer_id; 20]), 50)
+                .unwrap();
+        }
+
+        assert_eq!(tracker_status.expire_peers(&info_hash, Some([0; 20])), 1);
+        assert_eq!(tracker_status.swarm_peers(&info_hash).unwrap().len(), 2);
+
+        assert_eq!(tracker_status.expire_peers(&info_hash, None), 2);
+        assert_there_are_only_these_peers(&tracker_status, info_hash, 0, 0);
+        assert_eq!(
+            tracker_status.expire_peers(&InfoHash::new([1; 20]), None),
+            0
+        );
+        assert!(tracker_status
+            .swarm_peers(&InfoHash::new([1; 20]))
+            .is_none());
+    }
+
+    #[test]
+    fn test_numwant_is_clamped() {
+        let tracker_status = AtomicTrackerStatus::default().with_limits(SwarmLimits {
+            default_numwant: 2,
+            max_numwant: 3,
+            ..SwarmLimits::default()
+        });
+        let info_hash = InfoHash::new([0; 20]);
+        for peer_id in 1..=5 {
+            tracker_status
+                .incoming_peer(info_hash, create_test_leecher([peer_id; 20]), 0)
+                .unwrap();
+        }
+
+        assert_eq!(tracker_status.wanted_peers(None), 2);
+        assert_eq!(tracker_status.wanted_peers(Some(1)), 1);
+        assert_eq!(tracker_status.wanted_peers(Some(10_000)), 3);
+        let active_peers = tracker_status
+            .incoming_peer(info_hash, create_test_leecher([0; 20]), 10_000)
+            .unwrap();
+        assert_eq!(active_peers.peers.len(), 3);
+    }
+
+    #[test]
+    fn test_idle_swarm_is_evicted_for_a_new_torrent() {
+        let tracker_status = AtomicTrackerStatus::with_shards(TrackerTimeouts::default(), 4)
+            .with_limits(SwarmLimits {
+                max_torrents: 2,
+                ..SwarmLimits::default()
+            });
+        tracker_status
+            .incoming_peer(InfoHash::new([0; 20]), create_test_leecher([0; 20]), 50)
+            .unwrap();
+        tracker_status
+            .incoming_peer(InfoHash::new([1; 20]), create_test_leecher([0; 20]), 50)
+            .unwrap();
+
+        assert_eq!(
+            tracker_status
+                .incoming_peer(InfoHash::new([2; 20]), create_test_leecher([0; 20]), 50)
+                .unwrap_err(),
+            SwarmError::TooManyTorrents
+        );
+
+        tracker_status.expire_peers(&InfoHash::new([1; 20]), None);
+        tracker_status
+            .incoming_peer(InfoHash::new([2; 20]), create_test_leecher([0; 20]), 50)
+            .unwrap();
+
+        let torrents: Vec<InfoHash> = tracker_status
+            .scrape(None)
+            .into_iter()
+            .map(|(info_hash, _)| info_hash)
+            .collect();
+        assert_eq!(torrents.len(), 2);
+        assert!(!torrents.contains(&InfoHash::new([1; 20])));
+    }
+
+    #[test]
+    fn test_least_recently_announced_idle_swarm_is_evicted() {
+        let tracker_status = AtomicTrackerStatus::new(TrackerTimeouts {
+            peer_timeout: Duration::seconds(-1),
+            ..TrackerTimeouts::default()
+        })
+        .with_limits(SwarmLimits {
+            max_torrents: 2,
+            ..SwarmLimits::default()
+        });
+        for byte in 0..3 {
+            tracker_status
+                .incoming_peer(InfoHash::new([byte; 20]), create_test_leecher([0; 20]), 50)
+                .unwrap();
+        }
+
+        assert!(tracker_status
+            .swarm_peers(&InfoHash::new([0; 20]))
+            .is_none());
+        assert!(tracker_status
+            .swarm_peers(&InfoHash::new([1; 20]))
+            .is_some());
+    }
+
+    #[test]
+    fn test_peers_per_swarm_are_limited() {
+        let tracker_status = AtomicTrackerStatus::default().with_limits(SwarmLimits {
+            max_peers_per_swarm: 1,
+            ..SwarmLimits::default()
+        });
+        let info_hash = InfoHash::new([0; 20]);
+        tracker_status
+            .incoming_peer(info_hash, create_test_leecher([0; 20]), 50)
+            .unwrap();
+
+        assert_eq!(
+            tracker_status
+                .incoming_peer(info_hash, create_test_leecher([1; 20]), 50)
+                .unwrap_err(),
+            SwarmError::SwarmFull
+        );
+    }
+
+    fn create_test_status_without_min_interval() -> AtomicTrackerStatus {
+        AtomicTrackerStatus::new(TrackerTimeouts {
+            min_announce_interval: Duration::zero(),
+            ..TrackerTimeouts::default()
+        })
+    }
+
     fn assert_there_are_only_these_peers(
         status: &AtomicTrackerStatus,
-        info_hash: [u8; 20],
+        info_hash: InfoHash,
         expected_seeders: u32,
         expected_leechers: u32,
     ) {
@@ -190,13 +784,13 @@ mod tests {
         assert_eq!(leechers, expected_leechers);
     }
 
-    fn assert_there_is_only_one_seeder(status: &AtomicTrackerStatus, info_hash: [u8; 20]) {
+    fn assert_there_is_only_one_seeder(status: &AtomicTrackerStatus, info_hash: InfoHash) {
         assert_there_are_only_these_peers(status, info_hash, 1, 0);
         let (active_peers, _, _) = get_active_peers_for(status, info_hash, 50).unwrap();
         assert!(active_peers[0].is_seeder());
     }
 
-    fn assert_there_is_only_one_leecher(status: &AtomicTrackerStatus, info_hash: [u8; 20]) {
+    fn assert_there_is_only_one_leecher(status: &AtomicTrackerStatus, info_hash: InfoHash) {
         assert_there_are_only_these_peers(status, info_hash, 0, 1);
         let (active_peers, _, _) = get_active_peers_for(status, info_hash, 50).unwrap();
         assert!(active_peers[0].is_leecher());
@@ -204,13 +798,15 @@ mod tests {
 
     pub fn get_active_peers_for(
         status: &AtomicTrackerStatus,
-        info_hash: [u8; 20],
+        info_hash: InfoHash,
         wanted_peers: u32,
     ) -> Option<(Vec<Peer>, u32, u32)> {
-        let all_swarms = status.lock_swarms();
+        let all_swarms = status.lock_shard(&info_hash);
         let swarm = all_swarms.get(&info_hash)?;
 
-        let active_peers = swarm.get_active_peers(wanted_peers);
+        // A leecher that is not in the swarm gets every peer.
+        let active_peers =
+            swarm.get_active_peers(wanted_peers, &create_test_leecher([u8::MAX; 20]));
 
         Some((
             active_peers.peers,
@@ -224,6 +820,7 @@ mod tests {
             uploaded: 0,
             downloaded: 0,
             left: 0,
+            corrupt: 0,
             event: None,
             last_seen: Local::now(),
         };
@@ -236,6 +833,7 @@ mod tests {
             uploaded: 0,
             downloaded: 0,
             left: 3000,
+            corrupt: 0,
             event: None,
             last_seen: Local::now(),
         };
@@ -244,11 +842,12 @@ mod tests {
     }
 
     fn create_inactive_peer(peer_id: [u8; 20]) -> Peer {
-        let old_date = Local::now().sub(Duration::hours(PEER_HOURS_TIMEOUT) * 2);
+        let old_date = Local::now().sub(Duration::seconds(DEFAULT_PEER_TIMEOUT_SECONDS) * 2);
         let peer_status = PeerStatus {
             uploaded: 0,
             downloaded: 0,
             left: 0,
+            corrupt: 0,
             event: None,
             last_seen: old_date,
         };
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+/// Struct containing the stats of a single torrent during a stats bucket.
+///
+/// ## Fields
+/// * `peers`: The total number of peers of the torrent.
+/// * `seeders`: The number of seeders of the torrent.
+/// * `leechers`: The number of leechers of the torrent.
+/// * `snatches`: The number of times the torrent was downloaded completely since the tracker started.
+/// * `announces`: The number of announces received for the torrent during the bucket.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CurrentTorrentStats {
+    pub peers: u32,
+    pub seeders: u32,
+    pub leechers: u32,
+    pub snatches: u32,
+    pub announces: u32,
+}
+
+impl CurrentTorrentStats {
+    /// Creates a new `CurrentTorrentStats`.
+    pub fn new(seeders: u32, leechers: u32, snatches: u32, announces: u32) -> Self {
+        Self {
+            peers: seeders + leechers,
+            seeders,
+            leechers,
+            snatches,
+            announces,
+        }
+    }
+}
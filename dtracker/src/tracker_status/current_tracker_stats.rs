@@ -1,25 +1,57 @@
 use serde::{Deserialize, Serialize};
 
+use crate::stats::request_stats::RequestSummary;
+
 /// Struct containing the current stats of the tracker.
 ///
 /// ## Fields
 /// * `torrents`: The total number of torrents in the tracker.
 /// * `seeders`: The total number of seeders in the tracker.
 /// * `leechers`: The total number of leechers in the tracker.
+/// * `snatches`: The total number of completed downloads in the tracker.
+/// * `announces_per_second`: The announces handled per second since the previous stats.
+/// * `announce_latency_p50_ms`: The median time taken to handle an announce since the previous stats.
+/// * `announce_latency_p95_ms`: The 95th percentile of the time taken to handle an announce since the previous stats.
+/// * `errors`: The requests answered with an error status since the previous stats.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct CurrentTrackerStats {
     pub torrents: u32,
     pub seeders: u32,
     pub leechers: u32,
+    pub snatches: u32,
+    #[serde(default)]
+    pub announces_per_second: f64,
+    #[serde(default)]
+    pub announce_latency_p50_ms: f64,
+    #[serde(default)]
+    pub announce_latency_p95_ms: f64,
+    #[serde(default)]
+    pub errors: u32,
 }
 
 impl CurrentTrackerStats {
-    /// Creates a new `CurrentTrackerStats`.
-    pub fn new(torrents: u32, seeders: u32, leechers: u32) -> Self {
+    /// Creates a new `CurrentTrackerStats`, without requests.
+    pub fn new(torrents: u32, seeders: u32, leechers: u32, snatches: u32) -> Self {
         Self {
             torrents,
             seeders,
             leechers,
+            snatches,
+            announces_per_second: 0.0,
+            announce_latency_p50_ms: 0.0,
+            announce_latency_p95_ms: 0.0,
+            errors: 0,
+        }
+    }
+
+    /// Returns the stats with the requests of their interval.
+    pub fn with_requests(self, requests: RequestSummary) -> Self {
+        Self {
+            announces_per_second: requests.announces_per_second,
+            announce_latency_p50_ms: requests.announce_latency_p50_ms,
+            announce_latency_p95_ms: requests.announce_latency_p95_ms,
+            errors: requests.errors,
+            ..self
         }
     }
 }
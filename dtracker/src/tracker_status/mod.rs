@@ -1,2 +1,3 @@
 pub mod atomic_tracker_status;
+pub mod current_torrent_stats;
 pub mod current_tracker_stats;
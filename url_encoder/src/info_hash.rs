@@ -0,0 +1,153 @@
+use std::fmt::{self, Display, Write};
+
+use crate::url_encoder::{decode, encode_bytes};
+
+/// Length in bytes of a SHA-1 info hash.
+pub const INFO_HASH_LENGTH: usize = 20;
+
+/// Identifier of a torrent: the SHA-1 hash of its bencoded info dictionary.
+///
+/// It is stored as raw bytes, as sent in the handshake. Use `to_hex()` to get its hexadecimal representation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InfoHash([u8; INFO_HASH_LENGTH]);
+
+/// Posible `InfoHash` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum InfoHashError {
+    InvalidLength,
+    InvalidHex,
+    InvalidEncoding,
+}
+
+impl InfoHash {
+    /// Creates a new `InfoHash` from its bytes.
+    pub fn new(bytes: [u8; INFO_HASH_LENGTH]) -> Self {
+        Self(bytes)
+    }
+
+    /// Creates a new `InfoHash` from a slice of bytes.
+    ///
+    /// It returns `InvalidLength` if the slice is not 20 bytes long.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, InfoHashError> {
+        let bytes: [u8; INFO_HASH_LENGTH] =
+            bytes.try_into().map_err(|_| InfoHashError::InvalidLength)?;
+        Ok(Self(bytes))
+    }
+
+    /// Creates a new `InfoHash` from its hexadecimal representation.
+    ///
+    /// It returns an error if the string is not 40 hexadecimal characters long.
+    pub fn from_hex(hex: &str) -> Result<Self, InfoHashError> {
+        if hex.len() != INFO_HASH_LENGTH * 2 {
+            return Err(InfoHashError::InvalidLength);
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or(InfoHashError::InvalidHex)
+            })
+            .collect::<Result<Vec<u8>, InfoHashError>>()?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Returns the bytes of the info hash.
+    pub fn as_bytes(&self) -> &[u8; INFO_HASH_LENGTH] {
+        &self.0
+    }
+
+    /// Returns the info hash as a lowercase hexadecimal string.
+    pub fn to_hex(&self) -> String {
+        let mut hex = String::with_capacity(INFO_HASH_LENGTH * 2);
+        for byte in self.0 {
+            // Writing to a String can not fail.
+            let _ = write!(&mut hex, "{:02x}", byte);
+        }
+        hex
+    }
+
+    /// Returns the info hash percent-encoded, to be sent as a query parameter.
+    pub fn url_encoded(&self) -> String {
+        encode_bytes(&self.0)
+    }
+
+    /// Creates a new `InfoHash` from its percent-encoded representation, as received in a query parameter.
+    ///
+    /// The encoded bytes are the raw info hash, not its hexadecimal representation.
+    pub fn from_url_encoded(encoded: &str) -> Result<Self, InfoHashError> {
+        let bytes = decode(encoded).map_err(|_| InfoHashError::InvalidEncoding)?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+impl Display for InfoHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: &str = "2c6b6858d61da9543d4231a71db4b1c9264b0685";
+    const BYTES: [u8; 20] = [
+        44, 107, 104, 88, 214, 29, 169, 84, 61, 66, 49, 167, 29, 180, 177, 201, 38, 75, 6, 133,
+    ];
+
+    #[test]
+    fn test_from_hex() {
+        let info_hash = InfoHash::from_hex(HEX).unwrap();
+
+        assert_eq!(info_hash.as_bytes(), &BYTES);
+    }
+
+    #[test]
+    fn test_to_hex() {
+        let info_hash = InfoHash::new(BYTES);
+
+        assert_eq!(info_hash.to_hex(), HEX);
+        assert_eq!(info_hash.to_string(), HEX);
+    }
+
+    #[test]
+    fn test_from_bytes_with_invalid_length() {
+        assert_eq!(
+            InfoHash::from_bytes(&[0; 19]),
+            Err(InfoHashError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn test_from_invalid_hex() {
+        assert_eq!(
+            InfoHash::from_hex("zz6b6858d61da9543d4231a71db4b1c9264b0685"),
+            Err(InfoHashError::InvalidHex)
+        );
+    }
+
+    #[test]
+    fn test_url_encoded() {
+        let info_hash = InfoHash::new(BYTES);
+
+        assert_eq!(
+            info_hash.url_encoded(),
+            "%2CkhX%D6%1D%A9T%3DB1%A7%1D%B4%B1%C9%26K%06%85"
+        );
+    }
+
+    #[test]
+    fn test_from_url_encoded() {
+        let info_hash = InfoHash::from_url_encoded(
+            "%2c%6b%68%58%d6%1d%a9%54%3d%42%31%a7%1d%b4%b1%c9%26%4b%06%85",
+        )
+        .unwrap();
+
+        assert_eq!(info_hash, InfoHash::new(BYTES));
+        assert_eq!(
+            InfoHash::from_url_encoded(&InfoHash::new(BYTES).url_encoded()),
+            Ok(InfoHash::new(BYTES))
+        );
+    }
+}
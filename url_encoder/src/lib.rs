@@ -1 +1,2 @@
+pub mod info_hash;
 pub mod url_encoder;
@@ -1,4 +1,6 @@
 /// Takes an hex string and applies Percent-Encoding, returning an encoded version.
+///
+/// Every byte is escaped, to encode arbitrary bytes use `encode_bytes` instead.
 pub fn encode(hex_string: &str) -> String {
     if hex_string.is_empty() {
         return hex_string.to_string();
@@ -14,24 +16,63 @@ pub fn encode(hex_string: &str) -> String {
     encoded_hex_string
 }
 
-/// Takes an encoded string and decodes it.
-pub fn decode(hex_str: &str) -> String {
-    let mut out = Vec::new();
-    let mut iter = hex_str.chars();
-
-    while let Some(c) = iter.next() {
-        match c {
-            '%' => {
-                let c1 = iter.next().unwrap();
-                let c2 = iter.next().unwrap();
-                out.push(c1.to_string().to_lowercase());
-                out.push(c2.to_string().to_lowercase());
+/// Percent-encodes arbitrary bytes, as required for binary query parameters like the info hash or the peer id.
+///
+/// The unreserved characters of RFC 3986 (`A-Z`, `a-z`, `0-9`, `-`, `.`, `_` and `~`) are kept as they are and
+/// every other byte is escaped as `%XX` with uppercase hexadecimal digits.
+pub fn encode_bytes(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len() * 3);
+    for byte in bytes {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(*byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Posible decoding errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// A `%` not followed by two hexadecimal digits.
+    InvalidEscape,
+}
+
+/// Takes a percent-encoded string, as found in a query string, and returns the bytes it represents.
+///
+/// `%XX` escapes are decoded to the byte `XX`, so the result can contain arbitrary binary data,
+/// `+` is decoded to a space and every other character is kept as is.
+///
+/// It returns `InvalidEscape` if a `%` is not followed by two hexadecimal digits.
+pub fn decode(encoded: &str) -> Result<Vec<u8>, DecodeError> {
+    let encoded = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+
+    while i < encoded.len() {
+        match encoded[i] {
+            b'%' => {
+                let byte = encoded
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or(DecodeError::InvalidEscape)?;
+                decoded.push(byte);
+                i += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
             }
-            _ => out.push(format!("{:x}", c.to_string().as_bytes()[0])),
         }
     }
 
-    out.join("")
+    Ok(decoded)
 }
 
 #[cfg(test)]
@@ -52,9 +93,60 @@ mod tests {
     }
 
     #[test]
-    fn test_hex_decoder() {
+    fn test_encode_bytes_keeps_unreserved_characters() {
+        let unreserved = b"ABCXYZabcxyz0189-._~";
+
+        assert_eq!(encode_bytes(unreserved), "ABCXYZabcxyz0189-._~");
+    }
+
+    #[test]
+    fn test_encode_bytes_escapes_reserved_and_binary_bytes() {
+        assert_eq!(
+            encode_bytes(b"-qB4500-k51bMCWVA(~!"),
+            "-qB4500-k51bMCWVA%28~%21"
+        );
+        assert_eq!(encode_bytes(b" %&+/=?#"), "%20%25%26%2B%2F%3D%3F%23");
+        assert_eq!(encode_bytes(&[0x00, 0x7f, 0x80, 0xff]), "%00%7F%80%FF");
+        assert_eq!(encode_bytes(&[]), "");
+    }
+
+    #[test]
+    fn test_decode_encoded_bytes() {
+        let bytes: Vec<u8> = (0..=255).collect();
+
+        assert_eq!(decode(&encode_bytes(&bytes)).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_decode_binary_info_hash() {
         let infohash = "%124Vx%9A%BC%DE%F1%23Eg%89%AB%CD%EF%124Vx%9A";
-        let infohash_bytes = super::decode(infohash);
-        assert_eq!(infohash_bytes, "123456789abcdef123456789abcdef123456789a");
+        let infohash_bytes = decode(infohash).unwrap();
+        assert_eq!(
+            infohash_bytes,
+            vec![
+                0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf1, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd,
+                0xef, 0x12, 0x34, 0x56, 0x78, 0x9a
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_plus_and_lowercase_escapes() {
+        assert_eq!(decode("a+b%2fc").unwrap(), b"a b/c".to_vec());
+    }
+
+    #[test]
+    fn test_decode_invalid_escape() {
+        assert_eq!(decode("%1"), Err(DecodeError::InvalidEscape));
+        assert_eq!(decode("%zz"), Err(DecodeError::InvalidEscape));
+    }
+
+    #[test]
+    fn test_decode_encoded_info_hash() {
+        let info_hash = "2c6b6858d61da9543d4231a71db4b1c9264b0685";
+        let decoded = decode(&encode(info_hash)).unwrap();
+
+        assert_eq!(decoded.len(), 20);
+        assert_eq!(decoded[0], 0x2c);
     }
 }